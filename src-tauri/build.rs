@@ -1,3 +1,5 @@
 fn main() {
+  // Embedding the session engine alone doesn't need the Tauri codegen
+  #[cfg(feature = "desktop")]
   tauri_build::build()
 }
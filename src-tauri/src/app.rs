@@ -0,0 +1,424 @@
+use crate::config::AppConfig;
+use crate::credentials::{CredentialStore, SharedCredentialStore};
+use crate::discovery::DiscoveryStore;
+use crate::dotfiles::DotfilesStore;
+use crate::notifications::{NotificationCenter, NotificationEvent};
+use crate::output_stream::OutputStreamRegistry;
+use crate::plugins::PluginHost;
+use crate::scheduler::{JobContext, JobScheduler};
+use crate::scripting::ScriptHost;
+use crate::profiles::ProfileStore;
+use crate::recording::{RecordingConfig, RecordingManager};
+use crate::security::{self, SecurityConfig, SecurityManager, SharedSecurityManager};
+use crate::ssh::history::CommandHistory;
+use crate::ssh::known_hosts::{HostKeyPolicy, KnownHostsStore};
+use crate::ssh::SSHManager;
+use crate::transfer::{TransferManager, TransferProgress};
+use crate::types::CredentialUsage;
+use crate::workspaces::WorkspaceStore;
+use crate::{commands, logging, reporting, scheduler, types, webhooks, SharedSSHManager};
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager, WindowEvent};
+use tauri_plugin_notification::NotificationExt;
+use tokio::sync::{broadcast, RwLock};
+
+// Relay a backend broadcast channel to the frontend as a Tauri event
+fn forward_events<T: Serialize + Clone + Send + 'static>(
+  app_handle: AppHandle,
+  event: &'static str,
+  mut receiver: broadcast::Receiver<T>,
+) {
+  tokio::spawn(async move {
+    loop {
+      match receiver.recv().await {
+        Ok(payload) => {
+          if let Err(e) = app_handle.emit(event, payload) {
+            log::warn!("Failed to emit {} event: {}", event, e);
+          }
+        }
+        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+          log::warn!("Dropped {} {} events", skipped, event);
+        }
+        Err(broadcast::error::RecvError::Closed) => break,
+      }
+    }
+  });
+}
+
+// Emit notifications to the frontend and show the ones the user wants as native OS notifications
+fn relay_notifications(app_handle: AppHandle, mut receiver: broadcast::Receiver<NotificationEvent>) {
+  tokio::spawn(async move {
+    loop {
+      match receiver.recv().await {
+        Ok(notification) => {
+          if notification.native {
+            if let Err(e) = app_handle.notification().builder()
+              .title(notification.title.clone())
+              .body(notification.body.clone())
+              .show()
+            {
+              log::warn!("Failed to show native notification: {}", e);
+            }
+          }
+          if let Err(e) = app_handle.emit("notification", notification) {
+            log::warn!("Failed to emit notification event: {}", e);
+          }
+        }
+        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+          log::warn!("Dropped {} notifications", skipped);
+        }
+        Err(broadcast::error::RecvError::Closed) => break,
+      }
+    }
+  });
+}
+
+// Keep the audit trail of stored credentials offered to servers
+fn record_credential_uses(credential_store: SharedCredentialStore, mut receiver: broadcast::Receiver<CredentialUsage>) {
+  tokio::spawn(async move {
+    loop {
+      match receiver.recv().await {
+        Ok(usage) => {
+          if let Err(e) = credential_store.record_use(usage).await {
+            log::warn!("Failed to record credential use: {}", e);
+          }
+        }
+        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+          log::warn!("Dropped {} credential uses from the audit trail", skipped);
+        }
+        Err(broadcast::error::RecvError::Closed) => break,
+      }
+    }
+  });
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+  // Initialize SSH manager
+  let ssh_manager = SSHManager::new();
+  let port_forward_events = ssh_manager.port_forwards().subscribe();
+  let auth_prompt_events = ssh_manager.auth_prompts().subscribe();
+  let credential_uses = ssh_manager.credential_uses();
+  let approval_requests = ssh_manager.approvals().subscribe();
+  let approval_attempts = ssh_manager.approvals().attempts();
+  let ssh_manager: SharedSSHManager = Arc::new(RwLock::new(ssh_manager));
+  let security_manager = Arc::new(SecurityManager::new(SecurityConfig::default()));
+  let output_streams = Arc::new(OutputStreamRegistry::new());
+  let transfer_progress = Arc::new(TransferProgress::new());
+  let transfer_progress_events = transfer_progress.subscribe();
+  // Tracks the dialog transfers, so they can be paused and cancelled
+  let transfer_manager = TransferManager::with_progress(ssh_manager.clone(), transfer_progress.clone());
+  let window_output_streams = output_streams.clone();
+
+  tauri::Builder::default()
+    .plugin(tauri_plugin_clipboard_manager::init())
+    .plugin(tauri_plugin_dialog::init())
+    .plugin(tauri_plugin_notification::init())
+    .manage(ssh_manager.clone())
+    .manage(security_manager)
+    .manage(output_streams)
+    .manage(transfer_progress)
+    .manage(Arc::new(RwLock::new(transfer_manager)))
+    .on_window_event(move |_window, event| {
+      // No one is left to receive terminal output once the window is gone
+      if let WindowEvent::Destroyed = event {
+        window_output_streams.stop_all();
+      }
+    })
+    .setup(move |app| {
+      if cfg!(debug_assertions) {
+        app.handle().plugin(
+          tauri_plugin_log::Builder::default()
+            .level(log::LevelFilter::Trace)
+            .filter(logging::filter::enabled)
+            .build(),
+        )?;
+        // The plugin lets everything through; runtime filters decide what gets logged
+        logging::filter::set_default_level(log::LevelFilter::Info);
+      } else {
+        // Release builds keep rotating JSON log files under the app log directory
+        let log_config = logging::file::FileLogConfig::new(app.path().app_log_dir()?);
+        if let Err(e) = logging::file::init(log_config) {
+          eprintln!("Failed to initialize file logging: {}", e);
+        }
+      }
+
+      // Redaction patterns, opt-in error reporting and webhooks come from config.json
+      let config_path = AppConfig::path_from_env(&app.path().app_config_dir()?);
+      let app_config = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(AppConfig::load(&config_path))
+      })?;
+      if let Err(e) = logging::redact::configure(&app_config.redaction) {
+        log::warn!("Ignoring redaction patterns: {}", e);
+      }
+      if let Err(e) = reporting::init(&app_config.error_reporting) {
+        log::warn!("Error reporting disabled: {}", e);
+      }
+      if let Err(e) = webhooks::init(&app_config.webhooks) {
+        log::warn!("Webhooks disabled: {}", e);
+      }
+
+      let app_data_dir = app.path().app_data_dir()?;
+
+      // Keep recordings under the app data directory rather than the working directory
+      let recording_config = RecordingConfig {
+        storage_path: app_data_dir.join("recordings"),
+        ..RecordingConfig::default()
+      };
+      let recording_manager = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(RecordingManager::new(recording_config))
+      })?;
+      let recording_manager = Arc::new(recording_manager);
+      app.manage(recording_manager.clone());
+
+      let (credential_store, profile_store) = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async {
+          let credential_store = Arc::new(CredentialStore::load(app_data_dir.join("credentials.json")).await?);
+          let profile_store = ProfileStore::load(app_data_dir.join("profiles.json"), credential_store.clone()).await?;
+          Ok::<_, types::AppError>((credential_store, profile_store))
+        })
+      })?;
+      let profile_store = Arc::new(profile_store);
+      record_credential_uses(credential_store.clone(), credential_uses);
+      app.manage(credential_store);
+      app.manage(profile_store.clone());
+
+      let discovery_store = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(DiscoveryStore::load(app_data_dir.join("discovery.json"), profile_store.clone()))
+      })?;
+      app.manage(Arc::new(discovery_store));
+
+      let dotfiles_store = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(DotfilesStore::load(app_data_dir.join("dotfiles.json")))
+      })?;
+      app.manage(Arc::new(dotfiles_store));
+
+      let notification_center = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(NotificationCenter::load(app_data_dir.join("notifications.json")))
+      })?;
+      relay_notifications(app.handle().clone(), notification_center.subscribe());
+      let notification_center = Arc::new(notification_center);
+      app.manage(notification_center.clone());
+
+      let workspace_store = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(WorkspaceStore::load(app_data_dir.join("workspaces.json")))
+      })?;
+      app.manage(Arc::new(workspace_store));
+
+      let plugin_host = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(PluginHost::load(app_data_dir.join("plugins"), app_data_dir.join("plugin_grants.json")))
+      })?;
+      app.manage(Arc::new(plugin_host));
+
+      let script_host = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(ScriptHost::load(app_data_dir.join("scripts")))
+      })?;
+      app.manage(Arc::new(script_host));
+
+      // Scheduled jobs connect through the same manager and stores as interactive sessions
+      let job_scheduler = Arc::new(tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(JobScheduler::load(app_data_dir.join("jobs.json")))
+      })?);
+      scheduler::start(job_scheduler.clone(), JobContext {
+        ssh_manager: ssh_manager.clone(),
+        profiles: profile_store,
+        recordings: recording_manager,
+        notifications: notification_center,
+      });
+      app.manage(job_scheduler);
+
+      // Unknown host keys are confirmed by the user through a native trust dialog
+      let known_hosts = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async {
+          let known_hosts = Arc::new(
+            KnownHostsStore::load(app_data_dir.join("known_hosts.json"), HostKeyPolicy::Ask).await?,
+          );
+          ssh_manager.write().await.set_known_hosts(known_hosts.clone());
+          Ok::<_, types::AppError>(known_hosts)
+        })
+      })?;
+
+      // A history that can't be read starts over rather than keeping the app from starting
+      let loaded = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async {
+          let command_history = Arc::new(CommandHistory::load(app_data_dir.join("history.json")).await?);
+          ssh_manager.write().await.set_command_history(command_history);
+          Ok::<_, types::AppError>(())
+        })
+      });
+      if let Err(e) = loaded {
+        log::warn!("Command history won't be kept across restarts: {}", e);
+      }
+
+      forward_events(app.handle().clone(), "port-forward-status", port_forward_events);
+      forward_events(app.handle().clone(), "host-key-unknown", known_hosts.subscribe());
+      // Keyboard-interactive prompts, answered with ssh_auth_respond
+      forward_events(app.handle().clone(), "auth-prompt", auth_prompt_events);
+      // Connections to protected hosts, answered with ssh_approval_respond
+      forward_events(app.handle().clone(), "approval-request", approval_requests);
+      security::record_approval_attempts(app.state::<SharedSecurityManager>().inner().clone(), approval_attempts);
+      forward_events(app.handle().clone(), "transfer-progress", transfer_progress_events);
+
+      log::info!("WebTerminal Pro starting up...");
+      Ok(())
+    })
+    .invoke_handler(tauri::generate_handler![
+      commands::ssh_create_session,
+      commands::ssh_connect,
+      commands::ssh_disconnect,
+      commands::ssh_create_shell,
+      commands::ssh_write_to_shell,
+      commands::terminal_output_ack,
+      commands::ssh_resize_shell,
+      commands::ssh_list_sessions,
+      commands::ssh_get_session_info,
+      commands::ssh_collect_host_info,
+      commands::ssh_detect_environment,
+      commands::ssh_collect_variables,
+      commands::ssh_collect_accounts,
+      commands::ssh_get_charset,
+      commands::ssh_set_charset,
+      commands::ssh_set_session_tags,
+      commands::ssh_get_timeline,
+      commands::ssh_list_processes,
+      commands::ssh_signal_processes,
+      commands::ssh_inspect_network,
+      commands::ssh_send_signal,
+      commands::ssh_service_list,
+      commands::ssh_service_status,
+      commands::ssh_service_action,
+      commands::docker_list_containers,
+      commands::docker_list_images,
+      commands::docker_container_action,
+      commands::k8s_list_contexts,
+      commands::k8s_list_namespaces,
+      commands::k8s_list_pods,
+      commands::k8s_open_exec,
+      commands::ssh_list_multiplexer_sessions,
+      commands::ssh_attach_multiplexer,
+      commands::ssh_remove_session,
+      commands::ssh_exec_command,
+      commands::ssh_exec,
+      commands::sftp_create_session,
+      commands::sftp_list_directory,
+      commands::sftp_download_file,
+      commands::sftp_upload_file,
+      commands::sftp_append_file,
+      commands::sftp_write_at,
+      commands::sftp_rename,
+      commands::sftp_delete,
+      commands::sftp_mkdir,
+      commands::sftp_chmod,
+      commands::sftp_stat,
+      commands::sftp_symlink,
+      commands::sftp_readlink,
+      commands::sftp_recent_paths,
+      commands::ssh_command_history,
+      commands::ssh_clear_command_history,
+      commands::ssh_add_bookmark,
+      commands::ssh_list_bookmarks,
+      commands::ssh_remove_bookmark,
+      commands::ssh_output_since_bookmark,
+      commands::sftp_watch_directory,
+      commands::sftp_unwatch_directory,
+      commands::sftp_upload_with_dialog,
+      commands::sftp_download_with_dialog,
+      commands::get_autocomplete_suggestions,
+      commands::recording_start,
+      commands::recording_stop,
+      commands::recording_add_tag,
+      commands::recording_set_description,
+      commands::recording_search,
+      commands::recording_get_metadata,
+      commands::recording_load_events,
+      commands::security_get_stats,
+      commands::security_get_events,
+      commands::security_list_trusted_fingerprints,
+      commands::security_trust_fingerprint,
+      commands::security_revoke_fingerprint,
+      commands::credential_save,
+      commands::credential_get,
+      commands::credential_delete,
+      commands::credential_list,
+      commands::credential_usage,
+      commands::profile_create,
+      commands::profile_update,
+      commands::profile_delete,
+      commands::profile_list,
+      commands::profile_folders,
+      commands::profile_bookmark_list,
+      commands::profile_bookmark_add,
+      commands::profile_bookmark_update,
+      commands::profile_bookmark_delete,
+      commands::profile_bookmark_current_path,
+      commands::connect_with_profile,
+      commands::dotfiles_list,
+      commands::dotfiles_create,
+      commands::dotfiles_update,
+      commands::dotfiles_delete,
+      commands::dotfiles_sync,
+      commands::profile_exec_group,
+      commands::workspace_save,
+      commands::workspace_list,
+      commands::workspace_delete,
+      commands::workspace_restore,
+      commands::cloud_discover,
+      commands::cloud_list_instances,
+      commands::cloud_import_instances,
+      commands::lan_discover,
+      commands::diagnostics_run,
+      commands::diagnostics_ping,
+      commands::diagnostics_check_port,
+      commands::diagnostics_traceroute,
+      commands::diagnostics_scan_ports,
+      commands::known_hosts_list,
+      commands::known_hosts_trust,
+      commands::known_hosts_revoke,
+      commands::ssh_auth_respond,
+      commands::ssh_pending_approvals,
+      commands::ssh_approval_respond,
+      commands::ssh_discover_keys,
+      commands::port_forward_create,
+      commands::ssh_create_local_forward,
+      commands::ssh_create_remote_forward,
+      commands::port_forward_list,
+      commands::port_forward_close,
+      commands::clipboard_write_text,
+      commands::clipboard_prepare_paste,
+      commands::notification_get_preferences,
+      commands::notification_set_preferences,
+      commands::notification_get_rules,
+      commands::notification_set_rules,
+      commands::notification_get_do_not_disturb,
+      commands::notification_set_do_not_disturb,
+      commands::output_watch_add,
+      commands::output_watch_remove,
+      commands::output_watch_list,
+      commands::log_get_level,
+      commands::log_set_level,
+      commands::plugin_list,
+      commands::plugin_grant,
+      commands::plugin_run_command,
+      commands::script_list,
+      commands::script_reload,
+      commands::job_create,
+      commands::job_update,
+      commands::job_delete,
+      commands::job_list,
+      commands::job_run_now,
+      commands::i18n_get_language,
+      commands::i18n_set_language,
+      commands::get_system_proxy,
+      commands::get_background_tasks,
+      commands::jobs_list,
+      commands::jobs_cancel,
+      commands::transfer_pause,
+      commands::transfer_resume,
+      commands::transfer_cancel,
+      commands::get_transfer_limit,
+      commands::set_transfer_limit,
+    ])
+    .run(tauri::generate_context!())
+    .expect("error while running tauri application");
+}
@@ -0,0 +1,156 @@
+use crate::types::BackgroundTaskStatus;
+use chrono::Utc;
+use dashmap::DashMap;
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+// Periodic upkeep tasks of every manager while they run, by task ID
+static TASKS: OnceLock<DashMap<String, BackgroundTaskStatus>> = OnceLock::new();
+
+fn tasks() -> &'static DashMap<String, BackgroundTaskStatus> {
+    TASKS.get_or_init(DashMap::new)
+}
+
+// The periodic tasks one manager spawned. They stop at the manager's shutdown, or when it's
+// dropped, rather than running on for the life of the process.
+pub struct BackgroundTasks {
+    shutdown: CancellationToken,
+}
+
+impl Default for BackgroundTasks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BackgroundTasks {
+    pub fn new() -> Self {
+        Self {
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    // Run work now and then every period until shutdown. A run in progress is finished first.
+    pub fn spawn_periodic<F, Fut>(&self, name: &str, period: Duration, mut work: F) -> String
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let id = Uuid::new_v4().to_string();
+        tasks().insert(id.clone(), BackgroundTaskStatus {
+            id: id.clone(),
+            name: name.to_string(),
+            interval_seconds: period.as_secs(),
+            started_at: Utc::now(),
+            last_run: None,
+            runs: 0,
+            running: false,
+            healthy: true,
+        });
+
+        let shutdown = self.shutdown.clone();
+        let task_id = id.clone();
+        tokio::spawn(async move {
+            let mut ticks = interval(period);
+            // A slow run delays the next one instead of causing a burst of catch-up runs
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = shutdown.cancelled() => break,
+                    _ = ticks.tick() => {}
+                }
+                set_running(&task_id, true);
+                work().await;
+                set_running(&task_id, false);
+            }
+            if let Some((_, task)) = tasks().remove(&task_id) {
+                log::debug!("Background task {} stopped after {} runs", task.name, task.runs);
+            }
+        });
+        id
+    }
+
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+}
+
+impl Drop for BackgroundTasks {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+fn set_running(task_id: &str, running: bool) {
+    if let Some(mut task) = tasks().get_mut(task_id) {
+        task.running = running;
+        if !running {
+            task.last_run = Some(Utc::now());
+            task.runs += 1;
+        }
+    }
+}
+
+// Running tasks, oldest first. One that hasn't finished a run for two of its periods is stuck,
+// e.g. waiting on a lock, and reported unhealthy.
+pub fn statuses() -> Vec<BackgroundTaskStatus> {
+    let now = Utc::now();
+    let mut statuses: Vec<BackgroundTaskStatus> = tasks().iter()
+        .map(|entry| {
+            let mut task = entry.value().clone();
+            let since = task.last_run.unwrap_or(task.started_at);
+            task.healthy = (now - since).num_seconds() <= 2 * task.interval_seconds.max(1) as i64;
+            task
+        })
+        .collect();
+    statuses.sort_by_key(|task| task.started_at);
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tasks_stop_at_shutdown() {
+        let background = BackgroundTasks::new();
+        let (runs, mut ran) = tokio::sync::mpsc::unbounded_channel();
+        let id = background.spawn_periodic("test cleanup", Duration::from_secs(3600), move || {
+            let runs = runs.clone();
+            async move {
+                let _ = runs.send(());
+            }
+        });
+
+        // The first run is straight away
+        ran.recv().await.unwrap();
+        tokio::task::yield_now().await;
+        let task = statuses().into_iter().find(|task| task.id == id).unwrap();
+        assert_eq!((task.name.as_str(), task.runs, task.healthy), ("test cleanup", 1, true));
+
+        background.shutdown();
+        // The work closure is dropped with the task
+        assert!(ran.recv().await.is_none());
+        assert!(statuses().iter().all(|task| task.id != id));
+    }
+
+    #[tokio::test]
+    async fn test_tasks_stop_when_dropped() {
+        let background = BackgroundTasks::new();
+        let (runs, mut ran) = tokio::sync::mpsc::unbounded_channel();
+        background.spawn_periodic("dropped", Duration::from_secs(3600), move || {
+            let runs = runs.clone();
+            async move {
+                let _ = runs.send(());
+            }
+        });
+        ran.recv().await.unwrap();
+
+        drop(background);
+        assert!(ran.recv().await.is_none());
+    }
+}
@@ -0,0 +1,94 @@
+// Command-line tools that work without the GUI, e.g. over SSH on a server
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use webterminal_pro_lib::playback::{self, PlaybackOptions};
+
+const USAGE: &str = "\
+Usage: nebulashell play <recording-id|file> [options]
+
+Replays a session recording in this terminal.
+
+Options:
+  --speed <factor>        Playback speed, e.g. 2 for double speed (default 1)
+  --idle-limit <seconds>  Cap pauses at this many seconds (default 2)
+  --no-idle-limit         Keep the recorded pauses
+  --from <bookmark>       Start at a bookmark dropped while recording
+  --dir <path>            Recordings directory to look IDs up in
+                          (default $WEBTERMINAL_RECORDINGS or the app data directory)";
+
+struct PlayArgs {
+    target: String,
+    options: PlaybackOptions,
+    bookmark: Option<String>,
+    dir: Option<PathBuf>,
+}
+
+fn parse_play_args(args: &[String]) -> Result<PlayArgs, String> {
+    let mut target = None;
+    let mut options = PlaybackOptions::default();
+    let mut bookmark = None;
+    let mut dir = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().cloned().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "--speed" => {
+                options.speed = value("--speed")?.parse().map_err(|_| "Invalid --speed".to_string())?;
+            }
+            "--idle-limit" => {
+                let seconds: f64 = value("--idle-limit")?.parse().map_err(|_| "Invalid --idle-limit".to_string())?;
+                options.idle_limit = Some(Duration::try_from_secs_f64(seconds).map_err(|_| "Invalid --idle-limit".to_string())?);
+            }
+            "--no-idle-limit" => options.idle_limit = None,
+            "--from" => bookmark = Some(value("--from")?),
+            "--dir" => dir = Some(PathBuf::from(value("--dir")?)),
+            other if other.starts_with("--") => return Err(format!("Unknown option {}", other)),
+            other if target.is_none() => target = Some(other.to_string()),
+            other => return Err(format!("Unexpected argument {}", other)),
+        }
+    }
+
+    let target = target.ok_or_else(|| "Missing recording ID or file".to_string())?;
+    options.validate().map_err(|e| e.to_string())?;
+    Ok(PlayArgs { target, options, bookmark, dir })
+}
+
+async fn play(args: PlayArgs) -> Result<(), String> {
+    let dir = args.dir.or_else(playback::default_recordings_dir);
+    let path = playback::resolve_recording(&args.target, dir.as_deref()).map_err(|e| e.to_string())?;
+    let mut stdout = tokio::io::stdout();
+    playback::play(&path, &args.options, args.bookmark.as_deref(), &mut stdout).await.map_err(|e| e.to_string())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("play") => {}
+        Some("-h" | "--help" | "help") => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    }
+
+    let play_args = match parse_play_args(&args[1..]) {
+        Ok(play_args) => play_args,
+        Err(e) => {
+            eprintln!("nebulashell: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match play(play_args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("nebulashell: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,10 +1,34 @@
 // Test binary to run the HTTP server standalone for testing
+use webterminal_pro_lib::config::AppConfig;
+use webterminal_pro_lib::logging::file::{self, FileLogConfig};
+use webterminal_pro_lib::logging::redact;
+use webterminal_pro_lib::reporting;
 use webterminal_pro_lib::server::AppServer;
+use webterminal_pro_lib::webhooks;
+use webterminal_pro_lib::websocket;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging
-    env_logger::init();
+    // Initialize logging: rotating files under ./logs (or WEBTERMINAL_LOG_DIR), echoed to stderr
+    let mut log_config = FileLogConfig::from_env(std::path::PathBuf::from("logs"));
+    log_config.echo_stderr = true;
+    if let Err(e) = file::init(log_config) {
+        eprintln!("Failed to initialize file logging, falling back to stderr: {}", e);
+        env_logger::init();
+    }
+
+    // Redaction patterns, opt-in error reporting, webhooks and WebSocket resume, from ./config.json or WEBTERMINAL_CONFIG
+    let app_config = AppConfig::load(&AppConfig::path_from_env(std::path::Path::new("."))).await?;
+    if let Err(e) = redact::configure(&app_config.redaction) {
+        log::warn!("Ignoring redaction patterns: {}", e);
+    }
+    if let Err(e) = reporting::init(&app_config.error_reporting) {
+        log::warn!("Error reporting disabled: {}", e);
+    }
+    if let Err(e) = webhooks::init(&app_config.webhooks) {
+        log::warn!("Webhooks disabled: {}", e);
+    }
+    websocket::configure(&app_config.websocket);
     
     println!("Starting test HTTP server on port 3001...");
     
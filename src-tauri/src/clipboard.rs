@@ -0,0 +1,218 @@
+use crate::types::{AppError, AppResult};
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+
+// Largest text the backend will put on the system clipboard
+pub const MAX_CLIPBOARD_BYTES: usize = 1024 * 1024;
+// Base64 inflates by 4/3; anything longer cannot decode to an allowed payload
+const MAX_OSC52_PAYLOAD: usize = MAX_CLIPBOARD_BYTES / 3 * 4 + 4;
+
+const OSC52_START: &str = "\x1b]52;";
+
+pub fn validate_clipboard_text(text: &str) -> AppResult<()> {
+    if text.len() > MAX_CLIPBOARD_BYTES {
+        return Err(AppError::ValidationError(format!(
+            "Clipboard text is {} bytes, limit is {}", text.len(), MAX_CLIPBOARD_BYTES
+        )));
+    }
+    Ok(())
+}
+
+// Emitted when a remote program copies text through an OSC 52 escape sequence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteCopyEvent {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub selection: String,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Osc52Copy {
+    pub selection: String,
+    pub text: String,
+}
+
+// Finds OSC 52 clipboard writes in terminal output. Sequences may span several reads,
+// so an unterminated one is carried over to the next chunk.
+#[derive(Default)]
+pub struct Osc52Scanner {
+    pending: String,
+}
+
+impl Osc52Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &str) -> Vec<Osc52Copy> {
+        let mut copies = Vec::new();
+        let buffer = if self.pending.is_empty() {
+            chunk.to_string()
+        } else {
+            std::mem::take(&mut self.pending) + chunk
+        };
+
+        let mut rest = buffer.as_str();
+        while let Some(start) = rest.find(OSC52_START) {
+            let body = &rest[start + OSC52_START.len()..];
+            let Some((end, terminator_len)) = find_terminator(body) else {
+                // Keep the partial sequence unless it is already too long to be valid
+                if body.len() <= MAX_OSC52_PAYLOAD {
+                    self.pending = rest[start..].to_string();
+                }
+                return copies;
+            };
+
+            if let Some(copy) = parse_osc52(&body[..end]) {
+                copies.push(copy);
+            }
+            rest = &body[end + terminator_len..];
+        }
+
+        // The start marker itself may be cut off at the end of the chunk
+        if let Some(pos) = rest.rfind('\x1b') {
+            if OSC52_START.starts_with(&rest[pos..]) {
+                self.pending = rest[pos..].to_string();
+            }
+        }
+        copies
+    }
+}
+
+// OSC sequences end with BEL or ST (ESC \)
+fn find_terminator(body: &str) -> Option<(usize, usize)> {
+    let bel = body.find('\x07').map(|pos| (pos, 1));
+    let st = body.find("\x1b\\").map(|pos| (pos, 2));
+    match (bel, st) {
+        (Some(bel), Some(st)) => Some(if bel.0 < st.0 { bel } else { st }),
+        (bel, st) => bel.or(st),
+    }
+}
+
+// "<selection>;<base64>". A "?" payload is a clipboard read request, which is never honoured.
+fn parse_osc52(body: &str) -> Option<Osc52Copy> {
+    let (selection, payload) = body.split_once(';')?;
+    if payload == "?" || payload.len() > MAX_OSC52_PAYLOAD {
+        return None;
+    }
+
+    let bytes = general_purpose::STANDARD.decode(payload).ok()?;
+    let text = String::from_utf8(bytes).ok()?;
+    if text.len() > MAX_CLIPBOARD_BYTES {
+        return None;
+    }
+
+    Some(Osc52Copy {
+        selection: if selection.is_empty() { "c".to_string() } else { selection.to_string() },
+        text,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasteOptions {
+    #[serde(rename = "stripTrailingNewline", default = "default_true")]
+    pub strip_trailing_newline: bool,
+    #[serde(rename = "confirmSudo", default = "default_true")]
+    pub confirm_sudo: bool,
+    #[serde(rename = "confirmMultiline", default)]
+    pub confirm_multiline: bool,
+}
+
+impl Default for PasteOptions {
+    fn default() -> Self {
+        Self {
+            strip_trailing_newline: true,
+            confirm_sudo: true,
+            confirm_multiline: false,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasteTransform {
+    pub text: String,
+    #[serde(rename = "requiresConfirmation")]
+    pub requires_confirmation: bool,
+    pub warnings: Vec<String>,
+}
+
+// Prepare clipboard text for writing to a shell, flagging pastes the user should confirm first
+pub fn transform_paste(text: &str, options: &PasteOptions) -> PasteTransform {
+    let mut text = text.replace("\r\n", "\n");
+    if options.strip_trailing_newline {
+        let trimmed = text.trim_end_matches('\n').len();
+        text.truncate(trimmed);
+    }
+
+    let mut warnings = Vec::new();
+    if options.confirm_sudo && text.lines().any(runs_sudo) {
+        warnings.push("Pasted text runs commands with sudo".to_string());
+    }
+    if options.confirm_multiline && text.contains('\n') {
+        warnings.push(format!("Pasted text contains {} lines", text.lines().count()));
+    }
+
+    PasteTransform {
+        requires_confirmation: !warnings.is_empty(),
+        text,
+        warnings,
+    }
+}
+
+fn runs_sudo(line: &str) -> bool {
+    line.split(|c: char| c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | '`'))
+        .any(|word| word == "sudo" || word == "su" || word == "doas")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn osc52(text: &str) -> String {
+        format!("\x1b]52;c;{}\x07", general_purpose::STANDARD.encode(text))
+    }
+
+    #[test]
+    fn test_osc52_scanner_handles_split_sequences() {
+        let mut scanner = Osc52Scanner::new();
+        let sequence = osc52("hello world");
+        let (head, tail) = sequence.split_at(3);
+
+        assert!(scanner.feed(&format!("prompt$ {}", head)).is_empty());
+        let copies = scanner.feed(&format!("{}more output", tail));
+        assert_eq!(copies, vec![Osc52Copy { selection: "c".to_string(), text: "hello world".to_string() }]);
+
+        let st_terminated = format!("\x1b]52;p;{}\x1b\\", general_purpose::STANDARD.encode("x"));
+        assert_eq!(scanner.feed(&st_terminated)[0].selection, "p");
+    }
+
+    #[test]
+    fn test_osc52_ignores_read_requests() {
+        let mut scanner = Osc52Scanner::new();
+        assert!(scanner.feed("\x1b]52;c;?\x07").is_empty());
+        assert!(scanner.feed("\x1b]52;c;not base64!\x07").is_empty());
+    }
+
+    #[test]
+    fn test_transform_paste() {
+        let options = PasteOptions::default();
+
+        let plain = transform_paste("ls -la\r\n", &options);
+        assert_eq!(plain.text, "ls -la");
+        assert!(!plain.requires_confirmation);
+
+        let sudo = transform_paste("cd /tmp && sudo rm -rf build\n", &options);
+        assert!(sudo.requires_confirmation);
+        assert!(!transform_paste("echo pseudocode", &options).requires_confirmation);
+
+        let multiline = PasteOptions { confirm_multiline: true, ..PasteOptions::default() };
+        assert!(transform_paste("one\ntwo", &multiline).requires_confirmation);
+
+        assert!(validate_clipboard_text(&"a".repeat(MAX_CLIPBOARD_BYTES + 1)).is_err());
+    }
+}
@@ -1,10 +1,54 @@
 use crate::types::{
-    SSHConnectionConfig, SSHSession, SftpFileInfo,
-    AutocompleteSuggestion, TerminalOutputEvent
+    SSHConnectionConfig, SSHSession, SessionTags, AuthResponseData, SftpFileInfo, SftpStat, RemoteEnvironment,
+    AutocompleteSuggestion, TerminalOutputEvent, PortForward, PortForwardRequest, CommandExecResult, AppError, AppResult, HostInfo,
+    ProcessSignal, RemoteProcess, SignalResult, TerminalSignal, ServiceAction, ServiceStatus, ServiceUnit, SudoOptions,
+    ContainerAction, DockerContainer, DockerImage, KubeContext, KubeNamespace, KubePod, KubeScope,
+    MultiplexerKind, MultiplexerSession, MultiplexerSessionsEvent, SessionNoticesEvent, ShellClosedEvent, ShellVariables, RemoteAccounts, TerminalCharset, TimelineEvent,
+    CredentialUsage, DirectoryChangedEvent, DirectoryWatchClosedResponse, ApprovalRequest, ApprovalResponseData, TransferDirection, NetworkInfo, OutputBookmark, BackgroundTaskStatus,
+    Job, JobKind, TransferLimit, FileTransfer
 };
+use crate::background;
+use crate::jobs::{self, JobHandle};
+use crate::ssh::history::HistoryEntry;
+use crate::ssh::parse_mode;
+use crate::ssh::motd::LoginNoticeScanner;
+use crate::ssh::reader::ShellEvent;
+use crate::ssh::watch::WatchEvent;
+use crate::recording::{
+    PlaybackControl, RecordingMetadata, RecordingSearchCriteria, SharedRecordingManager,
+    TerminalEvent, TerminalEventType,
+};
+use crate::clipboard::{transform_paste, validate_clipboard_text, Osc52Copy, Osc52Scanner, PasteOptions, PasteTransform, RemoteCopyEvent};
+use crate::credentials::{CredentialReference, SharedCredentialStore, StoredCredential};
+use crate::diagnostics::{self, DiagnosticReport, PingResult, PortCheck, PortScanResult, TracerouteHop};
+use crate::dotfiles::{DotfileSet, DotfileSetRequest, DotfileSyncResult, SharedDotfilesStore};
+use crate::discovery::{DiscoveredInstance, DiscoveryRequest, ImportTemplate, SharedDiscoveryStore};
+use crate::discovery::lan::{self, LanDiscoveryRequest, LanHost};
+use crate::group_exec::{self, GroupExecRequest, GroupExecResult};
+use crate::i18n::{self, Language};
+use crate::logging::correlation;
+use crate::logging::filter::{self as log_filter, LogLevelConfig};
+use crate::notifications::{NotificationCenter, NotificationKind, NotificationPreferences, NotificationRule, OutputWatch, OutputWatchRequest, SharedNotificationCenter};
+use crate::output_stream::SharedOutputStreams;
+use crate::plugins::{PluginCapability, PluginInfo, SharedPluginHost};
+use crate::proxy::{detect_system_proxy, ProxySettings};
+use crate::scheduler::{self, JobContext, JobRequest, JobRun, ScheduledJob, SharedJobScheduler};
+use crate::scripting::{ScriptAction, ScriptInfo, SharedScriptHost};
+use crate::transfer::{self, SharedTransferManager, TrackedTransfer, TransferProgress};
+use crate::profiles::{BookmarkRequest, ConnectionProfile, DirectoryBookmark, ProfileOverrides, ProfileRequest, SharedProfileStore};
+use crate::webhooks::{self, WebhookEventKind};
+use crate::workspaces::{cd_command, SharedWorkspaceStore, Workspace, WorkspaceSession, WorkspaceSessionHint};
+use crate::ssh::SSHManager;
+use crate::ssh::keys::{default_key_dir, discover_keys, LocalSshKey};
+use crate::ssh::known_hosts::KnownHost;
+use crate::security::{SecurityEvent, SecurityStats, SharedSecurityManager, SshKeyFingerprint};
 use crate::SharedSSHManager;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Emitter, State};
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_dialog::DialogExt;
 
 // Command request/response types
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,8 +77,9 @@ pub struct ConnectResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateShellRequest {
     pub session_id: String,
-    pub cols: u16,
-    pub rows: u16,
+    // Left out, the profile's default size is used
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,6 +95,30 @@ pub struct ResizeShellRequest {
     pub rows: u16,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionInfoResponse {
+    pub success: bool,
+    pub session: Option<SSHSession>,
+    pub connected: bool,
+    pub shell_active: bool,
+    pub sftp_active: bool,
+    pub output_streaming: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecCommandRequest {
+    pub session_id: String,
+    pub command: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecCommandResponse {
+    pub success: bool,
+    pub result: Option<CommandExecResult>,
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SftpListRequest {
     pub session_id: String,
@@ -67,6 +136,47 @@ pub struct SftpUploadRequest {
     pub session_id: String,
     pub remote_path: String,
     pub contents: Vec<u8>,
+    // Write to a temporary file and rename it over remote_path once complete
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SftpWriteAtRequest {
+    pub session_id: String,
+    pub remote_path: String,
+    pub offset: u64,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SftpUploadDialogRequest {
+    pub session_id: String,
+    pub remote_dir: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SftpDownloadDialogRequest {
+    pub session_id: String,
+    pub remote_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DialogTransfer {
+    // Matches the transfer-progress events sent while it ran
+    pub transfer_id: String,
+    pub local_path: String,
+    pub remote_path: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DialogTransferResponse {
+    pub success: bool,
+    // The user closed the dialog without choosing anything
+    pub cancelled: bool,
+    pub transfers: Vec<DialogTransfer>,
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -76,6 +186,90 @@ pub struct AutocompleteRequest {
     pub cursor_position: usize,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartRecordingRequest {
+    pub session_id: String,
+    pub hostname: Option<String>,
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartRecordingResponse {
+    pub success: bool,
+    pub recording_id: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordingTagRequest {
+    pub session_id: String,
+    pub tag: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordingDescriptionRequest {
+    pub session_id: String,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordingEventsRequest {
+    pub recording_id: String,
+    pub control: Option<PlaybackControl>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustFingerprintRequest {
+    pub username: String,
+    pub fingerprint: SshKeyFingerprint,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveCredentialRequest {
+    pub key: String,
+    pub label: Option<String>,
+    pub credential: StoredCredential,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateProfileRequest {
+    pub profile_id: String,
+    pub profile: ProfileRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileResponse {
+    pub success: bool,
+    pub profile: Option<ConnectionProfile>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustHostKeyRequest {
+    pub hostname: String,
+    pub port: u16,
+    pub fingerprint: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeHostKeyRequest {
+    pub hostname: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PortForwardResponse {
+    pub success: bool,
+    pub forward: Option<PortForward>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeFingerprintRequest {
+    pub username: String,
+    pub fingerprint: String,
+}
+
 // SSH Commands
 #[tauri::command]
 pub async fn ssh_create_session(
@@ -93,7 +287,7 @@ pub async fn ssh_create_session(
         Err(e) => Ok(CreateSessionResponse {
             success: false,
             session: None,
-            error: Some(e.to_string()),
+            error: Some(localized(&e)),
         }),
     }
 }
@@ -104,100 +298,190 @@ pub async fn ssh_connect(
     ssh_manager: State<'_, SharedSSHManager>,
     request: ConnectRequest,
 ) -> Result<ConnectResponse, String> {
-    let manager = ssh_manager.read().await;
+    traced(async move {
+        let manager = ssh_manager.read().await;
     
-    match manager.connect(&request.session_id).await {
-        Ok(_) => {
-            // Emit connection success event
-            let _ = app_handle.emit("ssh-connected", &request.session_id);
+        match manager.connect(&request.session_id).await {
+            Ok(_) => {
+                // Emit connection success event
+                let _ = app_handle.emit("ssh-connected", &request.session_id);
 
-            Ok(ConnectResponse {
-                success: true,
-                error: None,
-            })
-        },
-        Err(e) => {
-            // Emit connection error event
-            let error_msg = e.to_string();
-            let _ = app_handle.emit("ssh-connection-error", &error_msg);
+                Ok(ConnectResponse {
+                    success: true,
+                    error: None,
+                })
+            },
+            Err(e) => {
+                // Emit connection error event
+                let error_msg = e.to_string();
+                let _ = app_handle.emit("ssh-connection-error", &error_msg);
             
-            Ok(ConnectResponse {
-                success: false,
-                error: Some(error_msg),
-            })
-        },
-    }
+                Ok(ConnectResponse {
+                    success: false,
+                    error: Some(error_msg),
+                })
+            },
+        }
+    }).await
 }
 
 #[tauri::command]
 pub async fn ssh_disconnect(
     app_handle: AppHandle,
     ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
+    output_streams: State<'_, SharedOutputStreams>,
     session_id: String,
 ) -> Result<ConnectResponse, String> {
-    let manager = ssh_manager.read().await;
+    traced(async move {
+        // Stop pushing output before the shell goes away
+        output_streams.stop(&session_id);
+
+        let manager = ssh_manager.read().await;
     
-    match manager.disconnect(&session_id).await {
-        Ok(_) => {
-            // Emit disconnection event
-            let _ = app_handle.emit("ssh-disconnected", &session_id);
+        match manager.disconnect(&session_id).await {
+            Ok(_) => {
+                // Finalize any recording still attached to the session
+                if let Err(e) = recording_manager.stop_recording(&session_id).await {
+                    log::warn!("Failed to stop recording for session {}: {}", session_id, e);
+                }
+
+                // Emit disconnection event
+                let _ = app_handle.emit("ssh-disconnected", &session_id);
             
-            Ok(ConnectResponse {
-                success: true,
-                error: None,
-            })
-        },
-        Err(e) => Ok(ConnectResponse {
-            success: false,
-            error: Some(e.to_string()),
-        }),
-    }
+                Ok(ConnectResponse {
+                    success: true,
+                    error: None,
+                })
+            },
+            Err(e) => Ok(ConnectResponse {
+                success: false,
+                error: Some(localized(&e)),
+            }),
+        }
+    }).await
 }
 
+#[allow(clippy::too_many_arguments)]
 #[tauri::command]
 pub async fn ssh_create_shell(
     app_handle: AppHandle,
     ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
+    output_streams: State<'_, SharedOutputStreams>,
+    notifications: State<'_, SharedNotificationCenter>,
+    plugins: State<'_, SharedPluginHost>,
+    scripts: State<'_, SharedScriptHost>,
     request: CreateShellRequest,
 ) -> Result<ConnectResponse, String> {
-    let manager = ssh_manager.read().await;
+    traced(async move {
+        let manager = ssh_manager.read().await;
     
-    match manager.create_shell(&request.session_id, request.cols, request.rows).await {
-        Ok(_) => {
-            // Start terminal output monitoring
-            start_terminal_output_monitoring(
-                app_handle,
-                ssh_manager.inner().clone(),
-                request.session_id.clone(),
-            ).await;
+        match manager.create_shell(&request.session_id, request.cols, request.rows).await {
+            Ok(_) => {
+                let app_handle_for_detect = app_handle.clone();
+                // Start terminal output monitoring
+                start_terminal_output_monitoring(
+                    app_handle,
+                    ssh_manager.inner().clone(),
+                    recording_manager.inner().clone(),
+                    output_streams.inner().clone(),
+                    notifications.inner().clone(),
+                    plugins.inner().clone(),
+                    scripts.inner().clone(),
+                    request.session_id.clone(),
+                ).await;
+                detect_multiplexers_in_background(app_handle_for_detect, ssh_manager.inner().clone(), request.session_id.clone());
             
-            Ok(ConnectResponse {
-                success: true,
-                error: None,
-            })
-        },
-        Err(e) => Ok(ConnectResponse {
-            success: false,
-            error: Some(e.to_string()),
-        }),
-    }
+                Ok(ConnectResponse {
+                    success: true,
+                    error: None,
+                })
+            },
+            Err(e) => Ok(ConnectResponse {
+                success: false,
+                error: Some(localized(&e)),
+            }),
+        }
+    }).await
+}
+
+// Offer to resume tmux/screen sessions left running on the host
+fn detect_multiplexers_in_background(app_handle: AppHandle, ssh_manager: SharedSSHManager, session_id: String) {
+    tokio::spawn(correlation::propagate(async move {
+        let manager = ssh_manager.read().await;
+        match manager.list_multiplexer_sessions(&session_id).await {
+            Ok(sessions) if !sessions.is_empty() => {
+                let _ = app_handle.emit("multiplexer-sessions-detected", &MultiplexerSessionsEvent { session_id, sessions });
+            }
+            Ok(_) => {}
+            Err(e) => log::debug!("Multiplexer detection failed for session {}: {}", session_id, e),
+        }
+    }));
+}
+
+#[tauri::command]
+pub async fn ssh_list_multiplexer_sessions(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+) -> Result<Vec<MultiplexerSession>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.list_multiplexer_sessions(&session_id).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn ssh_attach_multiplexer(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    kind: MultiplexerKind,
+    id: String,
+    detach_others: Option<bool>,
+) -> Result<(), String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.attach_multiplexer(&session_id, kind, &id, detach_others.unwrap_or(false))
+            .await
+            .map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn terminal_output_ack(
+    output_streams: State<'_, SharedOutputStreams>,
+    session_id: String,
+    bytes: usize,
+) -> Result<(), String> {
+    output_streams.acknowledge(&session_id, bytes);
+    Ok(())
 }
 
 #[tauri::command]
 pub async fn ssh_write_to_shell(
     ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
     request: WriteToShellRequest,
 ) -> Result<ConnectResponse, String> {
     let manager = ssh_manager.read().await;
     
     match manager.write_to_shell(&request.session_id, &request.input).await {
-        Ok(_) => Ok(ConnectResponse {
-            success: true,
-            error: None,
-        }),
+        Ok(_) => {
+            record_terminal_event(
+                &recording_manager,
+                &request.session_id,
+                TerminalEventType::Input,
+                request.input,
+            ).await;
+
+            Ok(ConnectResponse {
+                success: true,
+                error: None,
+            })
+        },
         Err(e) => Ok(ConnectResponse {
             success: false,
-            error: Some(e.to_string()),
+            error: Some(localized(&e)),
         }),
     }
 }
@@ -205,18 +489,29 @@ pub async fn ssh_write_to_shell(
 #[tauri::command]
 pub async fn ssh_resize_shell(
     ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
     request: ResizeShellRequest,
 ) -> Result<ConnectResponse, String> {
     let manager = ssh_manager.read().await;
     
     match manager.resize_shell(&request.session_id, request.cols, request.rows).await {
-        Ok(_) => Ok(ConnectResponse {
-            success: true,
-            error: None,
-        }),
+        Ok(_) => {
+            recording_manager.set_terminal_size(&request.session_id, request.cols, request.rows);
+            record_terminal_event(
+                &recording_manager,
+                &request.session_id,
+                TerminalEventType::Resize,
+                format!("{}x{}", request.cols, request.rows),
+            ).await;
+
+            Ok(ConnectResponse {
+                success: true,
+                error: None,
+            })
+        },
         Err(e) => Ok(ConnectResponse {
             success: false,
-            error: Some(e.to_string()),
+            error: Some(localized(&e)),
         }),
     }
 }
@@ -229,122 +524,2710 @@ pub async fn ssh_list_sessions(
     Ok(manager.list_sessions().await)
 }
 
-// SFTP Commands
 #[tauri::command]
-pub async fn sftp_create_session(
+pub async fn ssh_get_session_info(
     ssh_manager: State<'_, SharedSSHManager>,
+    output_streams: State<'_, SharedOutputStreams>,
     session_id: String,
-) -> Result<ConnectResponse, String> {
+) -> Result<SessionInfoResponse, String> {
     let manager = ssh_manager.read().await;
-    
-    match manager.create_sftp(&session_id).await {
-        Ok(_) => Ok(ConnectResponse {
+
+    let info = match manager.get_session(&session_id).await {
+        Ok(session) => manager.get_session_info(&session_id).await.map(|state| (session, state)),
+        Err(e) => Err(e),
+    };
+
+    match info {
+        Ok((session, (connected, shell_active, sftp_active))) => Ok(SessionInfoResponse {
             success: true,
+            session: Some(session),
+            connected,
+            shell_active,
+            sftp_active,
+            output_streaming: output_streams.is_active(&session_id),
             error: None,
         }),
-        Err(e) => Ok(ConnectResponse {
+        Err(e) => Ok(SessionInfoResponse {
             success: false,
-            error: Some(e.to_string()),
+            session: None,
+            connected: false,
+            shell_active: false,
+            sftp_active: false,
+            output_streaming: false,
+            error: Some(localized(&e)),
         }),
     }
 }
 
+// Re-read OS, load, memory and disk details for the host dashboard
 #[tauri::command]
-pub async fn sftp_list_directory(
+pub async fn ssh_collect_host_info(
     ssh_manager: State<'_, SharedSSHManager>,
-    request: SftpListRequest,
-) -> Result<Vec<SftpFileInfo>, String> {
-    let manager = ssh_manager.read().await;
-    
-    match manager.list_directory(&request.session_id, &request.path).await {
-        Ok(files) => Ok(files),
-        Err(e) => Err(e.to_string()),
-    }
+    session_id: String,
+) -> Result<HostInfo, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.collect_host_info(&session_id).await.map_err(|e| localized(&e))
+    }).await
 }
 
+// Re-detect the login shell, OS and locale, e.g. after changing the user's shell
 #[tauri::command]
-pub async fn sftp_download_file(
+pub async fn ssh_detect_environment(
     ssh_manager: State<'_, SharedSSHManager>,
-    request: SftpDownloadRequest,
-) -> Result<Vec<u8>, String> {
-    let manager = ssh_manager.read().await;
-    
-    match manager.download_file(&request.session_id, &request.remote_path).await {
-        Ok(contents) => Ok(contents),
-        Err(e) => Err(e.to_string()),
-    }
+    session_id: String,
+) -> Result<RemoteEnvironment, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.detect_environment(&session_id).await.map_err(|e| localized(&e))
+    }).await
 }
 
+// Collect the login shell's exported variables and aliases, again after editing rc files
 #[tauri::command]
-pub async fn sftp_upload_file(
+pub async fn ssh_collect_variables(
     ssh_manager: State<'_, SharedSSHManager>,
-    request: SftpUploadRequest,
-) -> Result<ConnectResponse, String> {
-    let manager = ssh_manager.read().await;
-    
-    match manager.upload_file(&request.session_id, &request.remote_path, &request.contents).await {
-        Ok(_) => Ok(ConnectResponse {
-            success: true,
-            error: None,
-        }),
-        Err(e) => Ok(ConnectResponse {
-            success: false,
-            error: Some(e.to_string()),
-        }),
-    }
+    session_id: String,
+) -> Result<ShellVariables, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.collect_variables(&session_id).await.map_err(|e| localized(&e))
+    }).await
 }
 
-// Autocomplete Commands
+// Read the host's users and groups again, e.g. after adding one, so owners and chown choices are current
 #[tauri::command]
-pub async fn get_autocomplete_suggestions(
+pub async fn ssh_collect_accounts(
     ssh_manager: State<'_, SharedSSHManager>,
-    request: AutocompleteRequest,
-) -> Result<Vec<AutocompleteSuggestion>, String> {
-    let manager = ssh_manager.read().await;
-    
-    match manager.get_autocomplete_suggestions(
-        &request.session_id,
-        &request.input,
-        request.cursor_position,
-    ).await {
-        Ok(suggestions) => Ok(suggestions),
-        Err(e) => Err(e.to_string()),
-    }
+    session_id: String,
+) -> Result<RemoteAccounts, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.collect_accounts(&session_id).await.map_err(|e| localized(&e))
+    }).await
 }
 
-// Helper function to start terminal output monitoring
-async fn start_terminal_output_monitoring(
-    app_handle: AppHandle,
-    ssh_manager: SharedSSHManager,
+#[tauri::command]
+pub async fn ssh_get_charset(
+    ssh_manager: State<'_, SharedSSHManager>,
     session_id: String,
-) {
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(50));
-        
-        loop {
-            interval.tick().await;
-            
-            let manager = ssh_manager.read().await;
-            match manager.read_from_shell(&session_id).await {
-                Ok(Some(output)) => {
-                    let event = TerminalOutputEvent {
-                        session_id: session_id.clone(),
-                        data: output,
-                    };
-                    
-                    if let Err(e) = app_handle.emit("terminal-output", &event) {
-                        log::error!("Failed to emit terminal output: {}", e);
-                        break;
-                    }
-                },
-                Ok(None) => {
-                    // No output available, continue
-                },
-                Err(e) => {
-                    log::error!("Error reading from shell: {}", e);
-                    break;
+) -> Result<TerminalCharset, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.charset(&session_id).await.map_err(|e| localized(&e))
+    }).await
+}
+
+// Change how the session's terminal is decoded, e.g. for a GBK host that reports a UTF-8 locale
+#[tauri::command]
+pub async fn ssh_set_charset(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    charset: Option<String>,
+) -> Result<TerminalCharset, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.set_charset(&session_id, charset).await.map_err(|e| localized(&e))
+    }).await
+}
+
+// Relabel a running session; profiles keep their own tags
+#[tauri::command]
+pub async fn ssh_set_session_tags(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    tags: SessionTags,
+) -> Result<SSHSession, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.set_tags(&session_id, tags).await.map_err(|e| localized(&e))
+    }).await
+}
+
+// How long each step of connecting took, and what happened to the session since
+#[tauri::command]
+pub async fn ssh_get_timeline(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+) -> Result<Vec<TimelineEvent>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.timeline(&session_id).map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn ssh_list_processes(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+) -> Result<Vec<RemoteProcess>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.list_processes(&session_id).await.map_err(|e| localized(&e))
+    }).await
+}
+
+// Interface addresses and listening sockets of the remote host
+#[tauri::command]
+pub async fn ssh_inspect_network(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+) -> Result<NetworkInfo, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.inspect_network(&session_id).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn ssh_signal_processes(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    pids: Vec<u32>,
+    signal: ProcessSignal,
+) -> Result<Vec<SignalResult>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.signal_processes(&session_id, &pids, signal).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn ssh_send_signal(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    signal: TerminalSignal,
+) -> Result<(), String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.signal_shell(&session_id, signal).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn ssh_service_list(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+) -> Result<Vec<ServiceUnit>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.list_services(&session_id).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn ssh_service_status(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    unit: String,
+) -> Result<ServiceStatus, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.service_status(&session_id, &unit).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn ssh_service_action(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    unit: String,
+    action: ServiceAction,
+    sudo: Option<SudoOptions>,
+) -> Result<ServiceStatus, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.service_action(&session_id, &unit, action, &sudo.unwrap_or_default())
+            .await
+            .map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn ssh_remove_session(
+    app_handle: AppHandle,
+    ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
+    output_streams: State<'_, SharedOutputStreams>,
+    session_id: String,
+) -> Result<ConnectResponse, String> {
+    let manager = ssh_manager.read().await;
+
+    if let Err(e) = manager.get_session(&session_id).await {
+        return Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        });
+    }
+
+    output_streams.stop(&session_id);
+
+    match manager.remove_session(&session_id).await {
+        Ok(_) => {
+            if let Err(e) = recording_manager.stop_recording(&session_id).await {
+                log::warn!("Failed to stop recording for session {}: {}", session_id, e);
+            }
+
+            let _ = app_handle.emit("ssh-session-removed", &session_id);
+
+            Ok(ConnectResponse {
+                success: true,
+                error: None,
+            })
+        },
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn ssh_exec_command(
+    ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
+    request: ExecCommandRequest,
+) -> Result<ExecCommandResponse, String> {
+    traced(run_exec(&ssh_manager, &recording_manager, request)).await
+}
+
+// The same as ssh_exec_command, under the name the exec API uses
+#[tauri::command]
+pub async fn ssh_exec(
+    ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
+    request: ExecCommandRequest,
+) -> Result<ExecCommandResponse, String> {
+    traced(run_exec(&ssh_manager, &recording_manager, request)).await
+}
+
+async fn run_exec(
+    ssh_manager: &SharedSSHManager,
+    recording_manager: &SharedRecordingManager,
+    request: ExecCommandRequest,
+) -> Result<ExecCommandResponse, String> {
+    let manager = ssh_manager.read().await;
+
+    match manager.exec_command(&request.session_id, &request.command).await {
+        Ok(result) => {
+            if let Err(e) = recording_manager.record_exec(&request.session_id, &request.command, &result, HashMap::new()).await {
+                log::warn!("Failed to record command for session {}: {}", request.session_id, e);
+            }
+            Ok(ExecCommandResponse {
+                success: true,
+                result: Some(result),
+                error: None,
+            })
+        }
+        Err(e) => Ok(ExecCommandResponse {
+            success: false,
+            result: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+// Docker Commands
+#[tauri::command]
+pub async fn docker_list_containers(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    sudo: Option<SudoOptions>,
+) -> Result<Vec<DockerContainer>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.list_containers(&session_id, &sudo.unwrap_or_default()).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn docker_list_images(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    sudo: Option<SudoOptions>,
+) -> Result<Vec<DockerImage>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.list_images(&session_id, &sudo.unwrap_or_default()).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn docker_container_action(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    container: String,
+    action: ContainerAction,
+    sudo: Option<SudoOptions>,
+) -> Result<(), String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.container_action(&session_id, &container, action, &sudo.unwrap_or_default())
+            .await
+            .map_err(|e| localized(&e))
+    }).await
+}
+
+// Kubernetes Commands
+#[tauri::command]
+pub async fn k8s_list_contexts(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    scope: Option<KubeScope>,
+) -> Result<Vec<KubeContext>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.kube_contexts(&session_id, &scope.unwrap_or_default()).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn k8s_list_namespaces(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    scope: Option<KubeScope>,
+) -> Result<Vec<KubeNamespace>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.kube_namespaces(&session_id, &scope.unwrap_or_default()).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn k8s_list_pods(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    scope: Option<KubeScope>,
+) -> Result<Vec<KubePod>, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.kube_pods(&session_id, &scope.unwrap_or_default()).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn k8s_open_exec(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    scope: Option<KubeScope>,
+    pod: String,
+    container: Option<String>,
+    command: Option<String>,
+) -> Result<(), String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.open_pod_exec(&session_id, &scope.unwrap_or_default(), &pod, container.as_deref(), command.as_deref())
+            .await
+            .map_err(|e| localized(&e))
+    }).await
+}
+
+// SFTP Commands
+#[tauri::command]
+pub async fn sftp_create_session(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+) -> Result<ConnectResponse, String> {
+    let manager = ssh_manager.read().await;
+    
+    match manager.create_sftp(&session_id).await {
+        Ok(_) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn sftp_list_directory(
+    ssh_manager: State<'_, SharedSSHManager>,
+    request: SftpListRequest,
+) -> Result<Vec<SftpFileInfo>, String> {
+    let manager = ssh_manager.read().await;
+    
+    match manager.list_directory(&request.session_id, &request.path).await {
+        Ok(files) => Ok(files),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn sftp_download_file(
+    ssh_manager: State<'_, SharedSSHManager>,
+    notifications: State<'_, SharedNotificationCenter>,
+    scripts: State<'_, SharedScriptHost>,
+    request: SftpDownloadRequest,
+) -> Result<Vec<u8>, String> {
+    let manager = ssh_manager.read().await;
+    run_before_transfer(&scripts, &manager, &notifications, &request.session_id, "download", &request.remote_path)
+        .await
+        .map_err(|e| localized(&e))?;
+    
+    match manager.download_file(&request.session_id, &request.remote_path).await {
+        Ok(contents) => Ok(contents),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn sftp_upload_file(
+    ssh_manager: State<'_, SharedSSHManager>,
+    notifications: State<'_, SharedNotificationCenter>,
+    scripts: State<'_, SharedScriptHost>,
+    request: SftpUploadRequest,
+) -> Result<ConnectResponse, String> {
+    let manager = ssh_manager.read().await;
+    let result = async {
+        run_before_transfer(&scripts, &manager, &notifications, &request.session_id, "upload", &request.remote_path).await?;
+        if request.atomic {
+            manager.upload_file_atomic(&request.session_id, &request.remote_path, &request.contents).await
+        } else {
+            manager.upload_file(&request.session_id, &request.remote_path, &request.contents).await
+        }
+    }.await;
+    
+    match result {
+        Ok(_) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+// Both return the file's new size. Transfer scripts see them as uploads.
+#[tauri::command]
+pub async fn sftp_append_file(
+    ssh_manager: State<'_, SharedSSHManager>,
+    notifications: State<'_, SharedNotificationCenter>,
+    scripts: State<'_, SharedScriptHost>,
+    request: SftpUploadRequest,
+) -> Result<u64, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        let result = async {
+            run_before_transfer(&scripts, &manager, &notifications, &request.session_id, "upload", &request.remote_path).await?;
+            manager.append_file(&request.session_id, &request.remote_path, &request.contents).await
+        }.await;
+        result.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn sftp_write_at(
+    ssh_manager: State<'_, SharedSSHManager>,
+    notifications: State<'_, SharedNotificationCenter>,
+    scripts: State<'_, SharedScriptHost>,
+    request: SftpWriteAtRequest,
+) -> Result<u64, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        let result = async {
+            run_before_transfer(&scripts, &manager, &notifications, &request.session_id, "upload", &request.remote_path).await?;
+            manager.write_file_at(&request.session_id, &request.remote_path, request.offset, &request.contents).await
+        }.await;
+        result.map_err(|e| localized(&e))
+    }).await
+}
+
+// File management for the file browser. Modes are octal strings, e.g. "755".
+#[tauri::command]
+pub async fn sftp_rename(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    from: String,
+    to: String,
+    overwrite: Option<bool>,
+) -> Result<(), String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.rename_path(&session_id, &from, &to, overwrite.unwrap_or(false)).await.map_err(|e| localized(&e))
+    }).await
+}
+
+// Returns how many entries were removed
+#[tauri::command]
+pub async fn sftp_delete(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    path: String,
+    recursive: Option<bool>,
+) -> Result<u64, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.delete_path(&session_id, &path, recursive.unwrap_or(false)).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn sftp_mkdir(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    path: String,
+    mode: Option<String>,
+) -> Result<(), String> {
+    traced(async move {
+        let result = async {
+            let mode = mode.as_deref().map(parse_mode).transpose()?;
+            ssh_manager.read().await.make_directory(&session_id, &path, mode).await
+        }.await;
+        result.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn sftp_chmod(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    path: String,
+    mode: String,
+) -> Result<(), String> {
+    traced(async move {
+        let result = async {
+            let mode = parse_mode(&mode)?;
+            ssh_manager.read().await.chmod(&session_id, &path, mode).await
+        }.await;
+        result.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn sftp_stat(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    path: String,
+) -> Result<SftpStat, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.stat_path(&session_id, &path).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn sftp_symlink(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    target: String,
+    link_path: String,
+) -> Result<(), String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.create_symlink(&session_id, &target, &link_path).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn sftp_readlink(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    path: String,
+) -> Result<String, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.read_link(&session_id, &path).await.map_err(|e| localized(&e))
+    }).await
+}
+
+// Let the user pick local files and upload them into remote_dir. File contents never cross IPC.
+#[tauri::command]
+pub async fn sftp_upload_with_dialog(
+    app_handle: AppHandle,
+    ssh_manager: State<'_, SharedSSHManager>,
+    transfer_manager: State<'_, SharedTransferManager>,
+    notifications: State<'_, SharedNotificationCenter>,
+    scripts: State<'_, SharedScriptHost>,
+    request: SftpUploadDialogRequest,
+) -> Result<DialogTransferResponse, String> {
+    traced(async move {
+        let dialog = app_handle.dialog().file().set_title("Upload files");
+        let picked = tokio::task::spawn_blocking(move || dialog.blocking_pick_files())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let Some(picked) = picked else {
+            return Ok(DialogTransferResponse {
+                success: false,
+                cancelled: true,
+                transfers: Vec::new(),
+                error: None,
+            });
+        };
+
+        let manager = ssh_manager.read().await;
+        let host = manager.get_session(&request.session_id).await.ok().map(|session| session.config.hostname);
+        let mut transfers = Vec::new();
+
+        for file in picked {
+            let result = async {
+                let local_path = file.into_path()
+                    .map_err(|e| AppError::FileOperationFailed(format!("Unsupported file selection: {}", e)))?;
+                let name = local_path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .ok_or_else(|| AppError::FileOperationFailed("Selected path has no file name".to_string()))?;
+                let remote_path = format!("{}/{}", request.remote_dir.trim_end_matches('/'), name);
+                run_before_transfer(&scripts, &manager, &notifications, &request.session_id, "upload", &remote_path).await?;
+
+                let mut record = TransferProgress::external(&request.session_id, &remote_path, &local_path.to_string_lossy(), TransferDirection::Upload);
+                record.size = std::fs::metadata(&local_path).map(|metadata| metadata.len()).unwrap_or(0);
+                record.max_bytes_per_second = transfer_cap(&manager, &request.session_id).await;
+                let size = record.size;
+                let tracked = transfer_manager.read().await.track(record);
+                let title = format!("Upload {}", name);
+                let (session_id, local, remote) = (&request.session_id, &local_path, &remote_path);
+                let result = jobs::run(JobKind::Transfer, title, Some(session_id.clone()), |job| {
+                    run_tracked(&tracked, job, Some(size), |report| manager.upload_from_path(session_id, local, remote, report))
+                }).await;
+                tracked.finish(&result);
+                Ok::<_, AppError>(DialogTransfer {
+                    transfer_id: tracked.id().to_string(),
+                    bytes: result?,
+                    local_path: local_path.to_string_lossy().to_string(),
+                    remote_path,
+                })
+            }.await;
+
+            match result {
+                Ok(transfer) => {
+                    notify_transfer(&notifications, &request.session_id, host.as_deref(), "Upload", &transfer.remote_path, None);
+                    transfers.push(transfer);
+                }
+                Err(e) => {
+                    notify_transfer(&notifications, &request.session_id, host.as_deref(), "Upload", &request.remote_dir, Some(&e));
+                    return Ok(DialogTransferResponse {
+                        success: false,
+                        cancelled: false,
+                        transfers,
+                        error: Some(localized(&e)),
+                    });
+                }
+            }
+        }
+
+        Ok(DialogTransferResponse {
+            success: true,
+            cancelled: false,
+            transfers,
+            error: None,
+        })
+    }).await
+}
+
+// Run a dialog transfer's copy as a job, until it completes, fails or is cancelled. A pause
+// stops the copy, which starts over once the transfer is resumed.
+async fn run_tracked<F, Fut>(tracked: &TrackedTransfer, job: JobHandle, size: Option<u64>, copy: F) -> AppResult<u64>
+where
+    F: Fn(Box<dyn Fn(u64) -> AppResult<()> + Send + Sync>) -> Fut,
+    Fut: std::future::Future<Output = AppResult<u64>>,
+{
+    loop {
+        if !tracked.proceed().await {
+            return Err(AppError::TransferError("Transfer cancelled".to_string()));
+        }
+        // Called from the blocking pool the copy runs on
+        let (tracked_copy, job) = (tracked.clone(), job.clone());
+        let report = move |done: u64| {
+            job.progress(done, size);
+            tracked_copy.report(done, || job.check())
+        };
+        match copy(Box::new(report)).await {
+            Err(_) if tracked.interrupted() => continue,
+            result => return result,
+        }
+    }
+}
+
+// The per-transfer bandwidth cap of the session's profile
+async fn transfer_cap(manager: &SSHManager, session_id: &str) -> Option<u64> {
+    manager.get_session(session_id).await.ok()
+        .and_then(|session| session.config.defaults.max_bytes_per_second)
+}
+
+// Ask where to save a remote file and stream it there
+#[tauri::command]
+pub async fn sftp_download_with_dialog(
+    app_handle: AppHandle,
+    ssh_manager: State<'_, SharedSSHManager>,
+    transfer_manager: State<'_, SharedTransferManager>,
+    notifications: State<'_, SharedNotificationCenter>,
+    scripts: State<'_, SharedScriptHost>,
+    request: SftpDownloadDialogRequest,
+) -> Result<DialogTransferResponse, String> {
+    traced(async move {
+        let file_name = request.remote_path.rsplit('/').next().unwrap_or("download").to_string();
+        let dialog = app_handle.dialog().file().set_title("Save file").set_file_name(file_name);
+        let picked = tokio::task::spawn_blocking(move || dialog.blocking_save_file())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let Some(picked) = picked else {
+            return Ok(DialogTransferResponse {
+                success: false,
+                cancelled: true,
+                transfers: Vec::new(),
+                error: None,
+            });
+        };
+
+        let host = ssh_manager.read().await.get_session(&request.session_id).await.ok().map(|session| session.config.hostname);
+        let result = async {
+            let local_path = picked.into_path()
+                .map_err(|e| AppError::FileOperationFailed(format!("Unsupported file selection: {}", e)))?;
+            let manager = ssh_manager.read().await;
+            run_before_transfer(&scripts, &manager, &notifications, &request.session_id, "download", &request.remote_path).await?;
+
+            let mut record = TransferProgress::external(&request.session_id, &request.remote_path, &local_path.to_string_lossy(), TransferDirection::Download);
+            record.size = manager.file_size(&request.session_id, &request.remote_path).await.ok().flatten().unwrap_or(0);
+            record.max_bytes_per_second = transfer_cap(&manager, &request.session_id).await;
+            let size = (record.size > 0).then_some(record.size);
+            let title = format!("Download {}", record.name);
+            let tracked = transfer_manager.read().await.track(record);
+            let (session_id, remote) = (&request.session_id, &request.remote_path);
+            let result = jobs::run(JobKind::Transfer, title, Some(session_id.clone()), |job| {
+                run_tracked(&tracked, job, size, |report| {
+                    let target = local_path.clone();
+                    let create = move |_| {
+                        std::fs::File::create(&target)
+                            .map_err(|e| AppError::FileOperationFailed(format!("Failed to create local file: {}", e)))
+                    };
+                    manager.download_to(session_id, remote, 0, create, report)
+                })
+            }).await;
+            tracked.finish(&result);
+            Ok::<_, AppError>(DialogTransfer {
+                transfer_id: tracked.id().to_string(),
+                bytes: result?,
+                local_path: local_path.to_string_lossy().to_string(),
+                remote_path: request.remote_path.clone(),
+            })
+        }.await;
+
+        match result {
+            Ok(transfer) => {
+                notify_transfer(&notifications, &request.session_id, host.as_deref(), "Download", &transfer.local_path, None);
+                Ok(DialogTransferResponse {
+                    success: true,
+                    cancelled: false,
+                    transfers: vec![transfer],
+                    error: None,
+                })
+            }
+            Err(e) => {
+                notify_transfer(&notifications, &request.session_id, host.as_deref(), "Download", &request.remote_path, Some(&e));
+                Ok(DialogTransferResponse {
+                    success: false,
+                    cancelled: false,
+                    transfers: Vec::new(),
+                    error: Some(localized(&e)),
+                })
+            }
+        }
+    }).await
+}
+
+fn notify_transfer(
+    notifications: &SharedNotificationCenter,
+    session_id: &str,
+    host: Option<&str>,
+    direction: &str,
+    path: &str,
+    error: Option<&AppError>,
+) {
+    let kind = if error.is_some() { WebhookEventKind::TransferFailed } else { WebhookEventKind::TransferCompleted };
+    webhooks::emit(kind, Some(session_id), serde_json::json!({
+        "direction": direction.to_lowercase(),
+        "path": path,
+        "error": error.map(|e| e.to_string()),
+    }));
+
+    match error {
+        None => notifications.notify_host(
+            NotificationKind::TransferCompleted,
+            Some(session_id),
+            host,
+            format!("{} complete", direction),
+            path.to_string(),
+        ),
+        Some(e) => notifications.notify_host(
+            NotificationKind::TransferFailed,
+            Some(session_id),
+            host,
+            format!("{} failed", direction),
+            format!("{}: {}", path, e),
+        ),
+    }
+}
+
+// Autocomplete Commands
+#[tauri::command]
+pub async fn get_autocomplete_suggestions(
+    ssh_manager: State<'_, SharedSSHManager>,
+    plugins: State<'_, SharedPluginHost>,
+    request: AutocompleteRequest,
+) -> Result<Vec<AutocompleteSuggestion>, String> {
+    let manager = ssh_manager.read().await;
+    
+    match manager.get_autocomplete_suggestions(
+        &request.session_id,
+        &request.input,
+        request.cursor_position,
+    ).await {
+        Ok(mut suggestions) => {
+            suggestions.extend(plugins.autocomplete(&request.input, request.cursor_position));
+            Ok(suggestions)
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Recording Commands
+#[tauri::command]
+pub async fn recording_start(
+    ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
+    request: StartRecordingRequest,
+) -> Result<StartRecordingResponse, String> {
+    // Fall back to the session's configured host when the caller doesn't name one
+    let hostname = match request.hostname {
+        Some(hostname) => hostname,
+        None => {
+            let manager = ssh_manager.read().await;
+            match manager.get_session(&request.session_id).await {
+                Ok(session) => session.config.hostname,
+                Err(e) => return Ok(StartRecordingResponse {
+                    success: false,
+                    recording_id: None,
+                    error: Some(localized(&e)),
+                }),
+            }
+        }
+    };
+
+    match recording_manager.start_recording(request.session_id, hostname, request.user_id).await {
+        Ok(recording_id) => Ok(StartRecordingResponse {
+            success: true,
+            recording_id: Some(recording_id),
+            error: None,
+        }),
+        Err(e) => Ok(StartRecordingResponse {
+            success: false,
+            recording_id: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn recording_stop(
+    recording_manager: State<'_, SharedRecordingManager>,
+    session_id: String,
+) -> Result<Option<RecordingMetadata>, String> {
+    recording_manager.stop_recording(&session_id).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn recording_add_tag(
+    recording_manager: State<'_, SharedRecordingManager>,
+    request: RecordingTagRequest,
+) -> Result<ConnectResponse, String> {
+    recording_manager.add_recording_tag(&request.session_id, request.tag);
+
+    Ok(ConnectResponse {
+        success: true,
+        error: None,
+    })
+}
+
+#[tauri::command]
+pub async fn recording_set_description(
+    recording_manager: State<'_, SharedRecordingManager>,
+    request: RecordingDescriptionRequest,
+) -> Result<ConnectResponse, String> {
+    recording_manager.set_recording_description(&request.session_id, request.description);
+
+    Ok(ConnectResponse {
+        success: true,
+        error: None,
+    })
+}
+
+#[tauri::command]
+pub async fn recording_search(
+    recording_manager: State<'_, SharedRecordingManager>,
+    criteria: RecordingSearchCriteria,
+) -> Result<Vec<RecordingMetadata>, String> {
+    recording_manager.search_recordings(criteria).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn recording_get_metadata(
+    recording_manager: State<'_, SharedRecordingManager>,
+    recording_id: String,
+) -> Result<Option<RecordingMetadata>, String> {
+    recording_manager.get_recording_metadata(&recording_id).await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn recording_load_events(
+    recording_manager: State<'_, SharedRecordingManager>,
+    request: RecordingEventsRequest,
+) -> Result<Vec<TerminalEvent>, String> {
+    recording_manager.load_recording_events(&request.recording_id, request.control).await
+        .map_err(|e| e.to_string())
+}
+
+// Security Commands
+#[tauri::command]
+pub async fn security_get_stats(
+    security_manager: State<'_, SharedSecurityManager>,
+) -> Result<SecurityStats, String> {
+    Ok(security_manager.get_security_stats().await)
+}
+
+#[tauri::command]
+pub async fn security_get_events(
+    security_manager: State<'_, SharedSecurityManager>,
+    limit: Option<usize>,
+) -> Result<Vec<SecurityEvent>, String> {
+    Ok(security_manager.get_recent_events(limit.unwrap_or(100)).await)
+}
+
+#[tauri::command]
+pub async fn security_list_trusted_fingerprints(
+    security_manager: State<'_, SharedSecurityManager>,
+    username: Option<String>,
+) -> Result<HashMap<String, Vec<SshKeyFingerprint>>, String> {
+    Ok(security_manager.list_trusted_fingerprints(username.as_deref()))
+}
+
+#[tauri::command]
+pub async fn security_trust_fingerprint(
+    security_manager: State<'_, SharedSecurityManager>,
+    request: TrustFingerprintRequest,
+) -> Result<ConnectResponse, String> {
+    security_manager.add_trusted_fingerprint(&request.username, request.fingerprint);
+
+    Ok(ConnectResponse {
+        success: true,
+        error: None,
+    })
+}
+
+#[tauri::command]
+pub async fn security_revoke_fingerprint(
+    security_manager: State<'_, SharedSecurityManager>,
+    request: RevokeFingerprintRequest,
+) -> Result<ConnectResponse, String> {
+    if security_manager.remove_trusted_fingerprint(&request.username, &request.fingerprint) {
+        Ok(ConnectResponse {
+            success: true,
+            error: None,
+        })
+    } else {
+        Ok(ConnectResponse {
+            success: false,
+            error: Some(format!("Fingerprint not trusted for user {}", request.username)),
+        })
+    }
+}
+
+// Credential Commands
+#[tauri::command]
+pub async fn credential_save(
+    credential_store: State<'_, SharedCredentialStore>,
+    request: SaveCredentialRequest,
+) -> Result<ConnectResponse, String> {
+    let label = request.label.unwrap_or_else(|| request.key.clone());
+
+    match credential_store.save(&request.key, &label, &request.credential).await {
+        Ok(_) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn credential_get(
+    credential_store: State<'_, SharedCredentialStore>,
+    key: String,
+) -> Result<Option<StoredCredential>, String> {
+    credential_store.get(&key).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn credential_delete(
+    credential_store: State<'_, SharedCredentialStore>,
+    key: String,
+) -> Result<ConnectResponse, String> {
+    match credential_store.delete(&key).await {
+        Ok(true) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Ok(false) => Ok(ConnectResponse {
+            success: false,
+            error: Some(format!("No stored credential for {}", key)),
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn credential_list(
+    credential_store: State<'_, SharedCredentialStore>,
+) -> Result<Vec<CredentialReference>, String> {
+    Ok(credential_store.list())
+}
+
+// Who used the credential, where and when, newest first
+#[tauri::command]
+pub async fn credential_usage(
+    credential_store: State<'_, SharedCredentialStore>,
+    key: String,
+) -> Result<Vec<CredentialUsage>, String> {
+    Ok(credential_store.usage(&key))
+}
+
+// Profile Commands
+#[tauri::command]
+pub async fn profile_create(
+    profile_store: State<'_, SharedProfileStore>,
+    request: ProfileRequest,
+) -> Result<ProfileResponse, String> {
+    match profile_store.create(request).await {
+        Ok(profile) => Ok(ProfileResponse {
+            success: true,
+            profile: Some(profile),
+            error: None,
+        }),
+        Err(e) => Ok(ProfileResponse {
+            success: false,
+            profile: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn profile_update(
+    profile_store: State<'_, SharedProfileStore>,
+    request: UpdateProfileRequest,
+) -> Result<ProfileResponse, String> {
+    match profile_store.update(&request.profile_id, request.profile).await {
+        Ok(profile) => Ok(ProfileResponse {
+            success: true,
+            profile: Some(profile),
+            error: None,
+        }),
+        Err(e) => Ok(ProfileResponse {
+            success: false,
+            profile: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn profile_delete(
+    profile_store: State<'_, SharedProfileStore>,
+    profile_id: String,
+) -> Result<ConnectResponse, String> {
+    match profile_store.delete(&profile_id).await {
+        Ok(true) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Ok(false) => Ok(ConnectResponse {
+            success: false,
+            error: Some(format!("Profile not found: {}", profile_id)),
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+// All profiles, or those filed in a folder and the folders below it
+#[tauri::command]
+pub async fn profile_list(
+    profile_store: State<'_, SharedProfileStore>,
+    folder: Option<String>,
+) -> Result<Vec<ConnectionProfile>, String> {
+    Ok(match folder {
+        Some(folder) => profile_store.list_folder(&folder),
+        None => profile_store.list(),
+    })
+}
+
+#[tauri::command]
+pub async fn profile_folders(
+    profile_store: State<'_, SharedProfileStore>,
+) -> Result<Vec<String>, String> {
+    Ok(profile_store.folders())
+}
+
+#[tauri::command]
+pub async fn profile_bookmark_list(
+    profile_store: State<'_, SharedProfileStore>,
+    profile_id: String,
+) -> Result<Vec<DirectoryBookmark>, String> {
+    profile_store.bookmarks(&profile_id).map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn profile_bookmark_add(
+    profile_store: State<'_, SharedProfileStore>,
+    profile_id: String,
+    request: BookmarkRequest,
+) -> Result<DirectoryBookmark, String> {
+    profile_store.add_bookmark(&profile_id, request).await.map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn profile_bookmark_update(
+    profile_store: State<'_, SharedProfileStore>,
+    profile_id: String,
+    bookmark_id: String,
+    request: BookmarkRequest,
+) -> Result<DirectoryBookmark, String> {
+    profile_store.update_bookmark(&profile_id, &bookmark_id, request).await.map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn profile_bookmark_delete(
+    profile_store: State<'_, SharedProfileStore>,
+    profile_id: String,
+    bookmark_id: String,
+) -> Result<bool, String> {
+    profile_store.delete_bookmark(&profile_id, &bookmark_id).await.map_err(|e| localized(&e))
+}
+
+// Bookmark the directory the session's SFTP browser is showing
+#[tauri::command]
+pub async fn profile_bookmark_current_path(
+    ssh_manager: State<'_, SharedSSHManager>,
+    profile_store: State<'_, SharedProfileStore>,
+    profile_id: String,
+    session_id: String,
+    name: Option<String>,
+) -> Result<DirectoryBookmark, String> {
+    let result = async {
+        let path = ssh_manager.read().await.sftp_path(&session_id).await?
+            .ok_or_else(|| AppError::ValidationError("No directory has been opened over SFTP in this session".to_string()))?;
+        profile_store.add_bookmark(&profile_id, BookmarkRequest { name, path }).await
+    }.await;
+    result.map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn sftp_recent_paths(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+) -> Result<Vec<String>, String> {
+    let manager = ssh_manager.read().await;
+    manager.recent_paths(&session_id).await.map_err(|e| localized(&e))
+}
+
+// Commands entered on the session's host, best ranked first
+#[tauri::command]
+pub async fn ssh_command_history(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    prefix: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<HistoryEntry>, String> {
+    let manager = ssh_manager.read().await;
+    manager.command_history(&session_id, prefix.as_deref().unwrap_or(""), limit.unwrap_or(100)).await
+        .map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn ssh_clear_command_history(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+) -> Result<(), String> {
+    let manager = ssh_manager.read().await;
+    manager.clear_command_history(&session_id).await.map_err(|e| localized(&e))
+}
+
+// Mark the shell's output here, and in the session's recording if one is running
+#[tauri::command]
+pub async fn ssh_add_bookmark(
+    ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
+    session_id: String,
+    name: String,
+) -> Result<OutputBookmark, String> {
+    let manager = ssh_manager.read().await;
+    let bookmark = manager.add_bookmark(&session_id, &name).await.map_err(|e| localized(&e))?;
+    if let Err(e) = recording_manager.record_bookmark(&session_id, &bookmark).await {
+        log::warn!("Failed to record bookmark for session {}: {}", session_id, e);
+    }
+    Ok(bookmark)
+}
+
+#[tauri::command]
+pub async fn ssh_list_bookmarks(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+) -> Result<Vec<OutputBookmark>, String> {
+    let manager = ssh_manager.read().await;
+    manager.bookmarks(&session_id).await.map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn ssh_remove_bookmark(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    bookmark_id: String,
+) -> Result<(), String> {
+    let manager = ssh_manager.read().await;
+    manager.remove_bookmark(&session_id, &bookmark_id).await.map_err(|e| localized(&e))
+}
+
+// The scrollback from a bookmark on, for jumping to it
+#[tauri::command]
+pub async fn ssh_output_since_bookmark(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    bookmark_id: String,
+) -> Result<String, String> {
+    let manager = ssh_manager.read().await;
+    manager.output_since_bookmark(&session_id, &bookmark_id).await.map_err(|e| localized(&e))
+}
+
+// Emits directory-changed while the listing changes, then directory-watch-closed
+#[tauri::command]
+pub async fn sftp_watch_directory(
+    app_handle: AppHandle,
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    path: String,
+    interval_secs: Option<u64>,
+) -> Result<String, String> {
+    traced(async move {
+        let (watch_id, mut events) = {
+            let manager = ssh_manager.read().await;
+            manager.watch_directory(&session_id, &path, interval_secs).await.map_err(|e| localized(&e))?
+        };
+
+        let id = watch_id.clone();
+        tokio::spawn(correlation::propagate(async move {
+            while let Some(event) = events.recv().await {
+                match event {
+                    WatchEvent::Changed(change) => {
+                        let event = DirectoryChangedEvent {
+                            watch_id: id.clone(),
+                            session_id: session_id.clone(),
+                            path: path.clone(),
+                            change,
+                            timestamp: Utc::now().timestamp_millis(),
+                        };
+                        if let Err(e) = app_handle.emit("directory-changed", &event) {
+                            log::error!("Failed to emit directory-changed event: {}", e);
+                        }
+                    }
+                    WatchEvent::Closed(error) => {
+                        let event = DirectoryWatchClosedResponse { watch_id: id.clone(), session_id: session_id.clone(), error };
+                        let _ = app_handle.emit("directory-watch-closed", &event);
+                        break;
+                    }
+                }
+            }
+        }));
+        Ok(watch_id)
+    }).await
+}
+
+#[tauri::command]
+pub async fn sftp_unwatch_directory(
+    ssh_manager: State<'_, SharedSSHManager>,
+    watch_id: String,
+) -> Result<(), String> {
+    let manager = ssh_manager.read().await;
+    manager.unwatch_directory(&watch_id).map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn connect_with_profile(
+    app_handle: AppHandle,
+    ssh_manager: State<'_, SharedSSHManager>,
+    profile_store: State<'_, SharedProfileStore>,
+    dotfiles: State<'_, SharedDotfilesStore>,
+    profile_id: String,
+    // Settings for this connect only, e.g. another user or a one-off password
+    overrides: Option<ProfileOverrides>,
+) -> Result<CreateSessionResponse, String> {
+    traced(async move {
+        let overrides = overrides.unwrap_or_default();
+        let result = {
+            let manager = ssh_manager.read().await;
+            profile_store.connect_with_profile(&manager, &profile_id, &overrides).await
+        };
+
+        match result {
+            Ok(mut session) => {
+                let _ = app_handle.emit("ssh-connected", &session.id);
+                sync_dotfiles_in_background(
+                    app_handle.clone(),
+                    ssh_manager.inner().clone(),
+                    dotfiles.inner().clone(),
+                    profile_id.clone(),
+                    session.id.clone(),
+                );
+
+                // Credentials came from the keyring, don't hand them back to the frontend
+                session.config.password = None;
+                session.config.private_key = None;
+                session.config.passphrase = None;
+
+                Ok(CreateSessionResponse {
+                    success: true,
+                    session: Some(session),
+                    error: None,
+                })
+            }
+            Err(e) => {
+                let error_msg = e.to_string();
+                let _ = app_handle.emit("ssh-connection-error", &error_msg);
+
+                Ok(CreateSessionResponse {
+                    success: false,
+                    session: None,
+                    error: Some(error_msg),
+                })
+            }
+        }
+    }).await
+}
+
+// Run one command on several stored hosts at once
+#[tauri::command]
+pub async fn profile_exec_group(
+    ssh_manager: State<'_, SharedSSHManager>,
+    profile_store: State<'_, SharedProfileStore>,
+    request: GroupExecRequest,
+) -> Result<GroupExecResult, String> {
+    traced(async move {
+        group_exec::run_on_group(&ssh_manager, &profile_store, request)
+            .await
+            .map_err(|e| localized(&e))
+    }).await
+}
+
+// Dotfiles Commands
+
+// Push the profile's pending dotfile sets without holding up the connect
+fn sync_dotfiles_in_background(
+    app_handle: AppHandle,
+    ssh_manager: SharedSSHManager,
+    dotfiles: SharedDotfilesStore,
+    profile_id: String,
+    session_id: String,
+) {
+    let Ok(home) = app_handle.path().home_dir() else {
+        return;
+    };
+    tokio::spawn(correlation::propagate(async move {
+        let manager = ssh_manager.read().await;
+        let results = dotfiles.sync_pending(&manager, &home, &profile_id, &session_id).await;
+        if !results.is_empty() {
+            let _ = app_handle.emit("dotfiles-synced", &results);
+        }
+    }));
+}
+
+#[tauri::command]
+pub async fn dotfiles_list(
+    dotfiles: State<'_, SharedDotfilesStore>,
+) -> Result<Vec<DotfileSet>, String> {
+    Ok(dotfiles.list())
+}
+
+#[tauri::command]
+pub async fn dotfiles_create(
+    dotfiles: State<'_, SharedDotfilesStore>,
+    request: DotfileSetRequest,
+) -> Result<DotfileSet, String> {
+    traced(async move {
+        dotfiles.create(request).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn dotfiles_update(
+    dotfiles: State<'_, SharedDotfilesStore>,
+    set_id: String,
+    request: DotfileSetRequest,
+) -> Result<DotfileSet, String> {
+    traced(async move {
+        dotfiles.update(&set_id, request).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn dotfiles_delete(
+    dotfiles: State<'_, SharedDotfilesStore>,
+    set_id: String,
+) -> Result<bool, String> {
+    traced(async move {
+        dotfiles.delete(&set_id).await.map_err(|e| localized(&e))
+    }).await
+}
+
+// Push a set to a connected session now, whether or not the host already has it
+#[tauri::command]
+pub async fn dotfiles_sync(
+    app_handle: AppHandle,
+    ssh_manager: State<'_, SharedSSHManager>,
+    dotfiles: State<'_, SharedDotfilesStore>,
+    session_id: String,
+    set_id: String,
+) -> Result<DotfileSyncResult, String> {
+    traced(async move {
+        let home = app_handle.path().home_dir().map_err(|e| e.to_string())?;
+        let set = dotfiles.get(&set_id)
+            .ok_or_else(|| localized(&AppError::NotFound(format!("Dotfile set {}", set_id))))?;
+        let manager = ssh_manager.read().await;
+        dotfiles.sync(&manager, &home, &session_id, &set).await.map_err(|e| localized(&e))
+    }).await
+}
+
+// Workspace Commands
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveWorkspaceRequest {
+    pub name: String,
+    pub layout: Option<serde_json::Value>,
+    #[serde(default)]
+    pub sessions: Vec<WorkspaceSessionHint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkspaceResponse {
+    pub success: bool,
+    pub workspace: Option<Workspace>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoredSession {
+    pub hostname: String,
+    pub session_id: Option<String>,
+    pub connected: bool,
+    pub shell_open: bool,
+    pub layout: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoreWorkspaceResponse {
+    pub success: bool,
+    pub sessions: Vec<RestoredSession>,
+    pub layout: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+#[tauri::command]
+pub async fn workspace_save(
+    ssh_manager: State<'_, SharedSSHManager>,
+    workspace_store: State<'_, SharedWorkspaceStore>,
+    request: SaveWorkspaceRequest,
+) -> Result<WorkspaceResponse, String> {
+    let manager = ssh_manager.read().await;
+    let mut sessions = Vec::new();
+
+    for session in manager.list_sessions().await {
+        let shell_open = manager.get_session_info(&session.id).await
+            .map(|(_, shell_active, _)| shell_active)
+            .unwrap_or(false);
+        let hint = request.sessions.iter().find(|hint| hint.session_id == session.id);
+
+        sessions.push(WorkspaceSession {
+            config: session.config,
+            shell_open,
+            working_directory: hint.and_then(|hint| hint.working_directory.clone()),
+            cols: hint.and_then(|hint| hint.cols),
+            rows: hint.and_then(|hint| hint.rows),
+            layout: hint.and_then(|hint| hint.layout.clone()),
+        });
+    }
+
+    match workspace_store.save_workspace(&request.name, sessions, request.layout).await {
+        Ok(workspace) => Ok(WorkspaceResponse {
+            success: true,
+            workspace: Some(workspace),
+            error: None,
+        }),
+        Err(e) => Ok(WorkspaceResponse {
+            success: false,
+            workspace: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn workspace_list(
+    workspace_store: State<'_, SharedWorkspaceStore>,
+) -> Result<Vec<Workspace>, String> {
+    Ok(workspace_store.list())
+}
+
+#[tauri::command]
+pub async fn workspace_delete(
+    workspace_store: State<'_, SharedWorkspaceStore>,
+    workspace_id: String,
+) -> Result<ConnectResponse, String> {
+    match workspace_store.delete(&workspace_id).await {
+        Ok(true) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Ok(false) => Ok(ConnectResponse {
+            success: false,
+            error: Some(format!("Workspace {} not found", workspace_id)),
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+// Reconnect every session in the workspace and reopen its shell. Secrets come from
+// the key file or a matching profile, since workspaces never store them.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn workspace_restore(
+    app_handle: AppHandle,
+    ssh_manager: State<'_, SharedSSHManager>,
+    recording_manager: State<'_, SharedRecordingManager>,
+    output_streams: State<'_, SharedOutputStreams>,
+    notifications: State<'_, SharedNotificationCenter>,
+    plugins: State<'_, SharedPluginHost>,
+    scripts: State<'_, SharedScriptHost>,
+    profile_store: State<'_, SharedProfileStore>,
+    workspace_store: State<'_, SharedWorkspaceStore>,
+    workspace_id: String,
+) -> Result<RestoreWorkspaceResponse, String> {
+    traced(async move {
+        let Some(workspace) = workspace_store.get(&workspace_id) else {
+            return Ok(RestoreWorkspaceResponse {
+                success: false,
+                sessions: Vec::new(),
+                layout: None,
+                error: Some(format!("Workspace {} not found", workspace_id)),
+            });
+        };
+
+        let manager = ssh_manager.read().await;
+        let mut restored = Vec::new();
+
+        for saved in workspace.sessions {
+            let mut result = RestoredSession {
+                hostname: saved.config.hostname.clone(),
+                session_id: None,
+                connected: false,
+                shell_open: false,
+                layout: saved.layout.clone(),
+                error: None,
+            };
+
+            let connected = async {
+                let mut config = saved.config.clone();
+                config.id = uuid::Uuid::new_v4().to_string();
+                if config.private_key_path.is_none() {
+                    let profile = profile_store.find_with_credentials(&config.hostname, config.port, &config.username)
+                        .ok_or_else(|| AppError::InvalidConfiguration(format!(
+                            "No stored credentials for {}@{}", config.username, config.hostname
+                        )))?;
+                    let credentials = profile_store.build_config(&profile.id).await?;
+                    config.password = credentials.password;
+                    config.private_key = credentials.private_key;
+                    config.passphrase = credentials.passphrase;
+                    config.credential = credentials.credential;
+                    config.approval = credentials.approval;
+                }
+
+                let session = manager.create_session(config).await?;
+                manager.connect(&session.id).await?;
+                Ok::<_, AppError>(session.id)
+            }.await;
+
+            let session_id = match connected {
+                Ok(session_id) => session_id,
+                Err(e) => {
+                    result.error = Some(localized(&e));
+                    restored.push(result);
+                    continue;
+                }
+            };
+            let _ = app_handle.emit("ssh-connected", &session_id);
+            result.session_id = Some(session_id.clone());
+            result.connected = true;
+
+            if saved.shell_open {
+                match manager.create_shell(&session_id, saved.cols, saved.rows).await {
+                    Ok(()) => {
+                        start_terminal_output_monitoring(
+                            app_handle.clone(),
+                            ssh_manager.inner().clone(),
+                            recording_manager.inner().clone(),
+                            output_streams.inner().clone(),
+                            notifications.inner().clone(),
+                            plugins.inner().clone(),
+                            scripts.inner().clone(),
+                            session_id.clone(),
+                        ).await;
+                        result.shell_open = true;
+                        detect_multiplexers_in_background(app_handle.clone(), ssh_manager.inner().clone(), session_id.clone());
+
+                        if let Some(directory) = &saved.working_directory {
+                            if let Err(e) = manager.write_to_shell(&session_id, &cd_command(directory)).await {
+                                log::warn!("Failed to restore working directory for session {}: {}", session_id, e);
+                            }
+                        }
+                    }
+                    Err(e) => result.error = Some(localized(&e)),
+                }
+            }
+
+            restored.push(result);
+        }
+
+        Ok(RestoreWorkspaceResponse {
+            success: restored.iter().all(|session| session.error.is_none()),
+            sessions: restored,
+            layout: workspace.layout,
+            error: None,
+        })
+    }).await
+}
+
+// Cloud Discovery Commands
+#[tauri::command]
+pub async fn cloud_discover(
+    app_handle: AppHandle,
+    discovery: State<'_, SharedDiscoveryStore>,
+    request: DiscoveryRequest,
+) -> Result<Vec<DiscoveredInstance>, String> {
+    traced(async move {
+        let home = app_handle.path().home_dir().map_err(|e| e.to_string())?;
+        discovery.refresh(&home, &request).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn cloud_list_instances(
+    discovery: State<'_, SharedDiscoveryStore>,
+    tags: Option<HashMap<String, String>>,
+) -> Result<Vec<DiscoveredInstance>, String> {
+    Ok(discovery.list(&tags.unwrap_or_default()))
+}
+
+// Turn discovered instances into connection profiles
+#[tauri::command]
+pub async fn cloud_import_instances(
+    discovery: State<'_, SharedDiscoveryStore>,
+    instance_ids: Vec<String>,
+    template: ImportTemplate,
+) -> Result<Vec<ConnectionProfile>, String> {
+    discovery.import(&instance_ids, &template).await.map_err(|e| localized(&e))
+}
+
+// Look for SSH hosts on the local network to offer for quick connect
+#[tauri::command]
+pub async fn lan_discover(
+    profile_store: State<'_, SharedProfileStore>,
+    request: LanDiscoveryRequest,
+) -> Result<Vec<LanHost>, String> {
+    traced(async move {
+        let mut hosts = lan::discover(&request).await.map_err(|e| localized(&e))?;
+        lan::match_profiles(&mut hosts, &profile_store.list());
+        Ok(hosts)
+    }).await
+}
+
+// Diagnostics Commands
+// A stored profile, or a host typed in directly
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiagnosticTarget {
+    pub profile_id: Option<String>,
+    pub hostname: Option<String>,
+    pub port: Option<u16>,
+}
+
+impl DiagnosticTarget {
+    fn resolve(&self, profile_store: &SharedProfileStore) -> AppResult<(String, u16)> {
+        match (&self.profile_id, &self.hostname) {
+            (Some(profile_id), _) => {
+                let profile = profile_store.get(profile_id)
+                    .ok_or_else(|| AppError::NotFound(format!("Profile {}", profile_id)))?;
+                Ok((profile.hostname, self.port.unwrap_or(profile.port)))
+            }
+            (None, Some(hostname)) => Ok((hostname.trim().to_string(), self.port.unwrap_or(22))),
+            (None, None) => Err(AppError::ValidationError("Either a profile or a host name is required".to_string())),
+        }
+    }
+}
+
+// DNS, ping, port and SSH banner checks, summed up as a verdict
+#[tauri::command]
+pub async fn diagnostics_run(
+    profile_store: State<'_, SharedProfileStore>,
+    target: DiagnosticTarget,
+) -> Result<DiagnosticReport, String> {
+    traced(async move {
+        let (hostname, port) = target.resolve(&profile_store).map_err(|e| localized(&e))?;
+        diagnostics::diagnose(&hostname, port).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn diagnostics_ping(
+    profile_store: State<'_, SharedProfileStore>,
+    target: DiagnosticTarget,
+) -> Result<PingResult, String> {
+    let (hostname, _) = target.resolve(&profile_store).map_err(|e| localized(&e))?;
+    diagnostics::ping(&hostname).await.map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn diagnostics_check_port(
+    profile_store: State<'_, SharedProfileStore>,
+    target: DiagnosticTarget,
+) -> Result<PortCheck, String> {
+    let (hostname, port) = target.resolve(&profile_store).map_err(|e| localized(&e))?;
+    diagnostics::validate_host(&hostname).map_err(|e| localized(&e))?;
+    Ok(diagnostics::check_port(&hostname, port, diagnostics::DEFAULT_CONNECT_TIMEOUT).await)
+}
+
+#[tauri::command]
+pub async fn diagnostics_traceroute(
+    profile_store: State<'_, SharedProfileStore>,
+    target: DiagnosticTarget,
+) -> Result<Vec<TracerouteHop>, String> {
+    let (hostname, _) = target.resolve(&profile_store).map_err(|e| localized(&e))?;
+    diagnostics::traceroute(&hostname).await.map_err(|e| localized(&e))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PortScanRequest {
+    pub host: String,
+    // e.g. "22,80,443,8000-8010"
+    pub ports: String,
+    // Scan from the server's side of this session instead of from here
+    pub session_id: Option<String>,
+    pub timeout_ms: Option<u64>,
+}
+
+#[tauri::command]
+pub async fn diagnostics_scan_ports(
+    ssh_manager: State<'_, SharedSSHManager>,
+    request: PortScanRequest,
+) -> Result<PortScanResult, String> {
+    traced(async move {
+        let ports = diagnostics::parse_port_list(&request.ports).map_err(|e| localized(&e))?;
+        let timeout = request.timeout_ms
+            .map_or(diagnostics::DEFAULT_CONNECT_TIMEOUT, std::time::Duration::from_millis);
+        let result = match &request.session_id {
+            Some(session_id) => {
+                let manager = ssh_manager.read().await;
+                diagnostics::scan_ports_remote(&manager, session_id, request.host.trim(), &ports, timeout).await
+            }
+            None => diagnostics::scan_ports(request.host.trim(), &ports, timeout).await,
+        };
+        result.map_err(|e| localized(&e))
+    }).await
+}
+
+// Known Hosts Commands
+#[tauri::command]
+pub async fn known_hosts_list(
+    ssh_manager: State<'_, SharedSSHManager>,
+) -> Result<Vec<KnownHost>, String> {
+    let manager = ssh_manager.read().await;
+    Ok(manager.known_hosts().list())
+}
+
+#[tauri::command]
+pub async fn known_hosts_trust(
+    ssh_manager: State<'_, SharedSSHManager>,
+    request: TrustHostKeyRequest,
+) -> Result<ConnectResponse, String> {
+    let known_hosts = ssh_manager.read().await.known_hosts();
+
+    match known_hosts.trust(&request.hostname, request.port, &request.fingerprint).await {
+        Ok(_) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+// Answer, or cancel, a keyboard-interactive prompt from an auth-prompt event
+#[tauri::command]
+pub async fn ssh_auth_respond(
+    ssh_manager: State<'_, SharedSSHManager>,
+    request: AuthResponseData,
+) -> Result<ConnectResponse, String> {
+    let auth_prompts = ssh_manager.read().await.auth_prompts();
+    let result = if request.cancel {
+        auth_prompts.cancel(&request.prompt_id)
+    } else {
+        auth_prompts.respond(&request.prompt_id, request.responses)
+    };
+
+    match result {
+        Ok(()) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+// Connections to protected hosts waiting for their second step
+#[tauri::command]
+pub async fn ssh_pending_approvals(
+    ssh_manager: State<'_, SharedSSHManager>,
+) -> Result<Vec<ApprovalRequest>, String> {
+    Ok(ssh_manager.read().await.approvals().pending())
+}
+
+// Answer an approval-request event: a TOTP code, or an admin's decision
+#[tauri::command]
+pub async fn ssh_approval_respond(
+    ssh_manager: State<'_, SharedSSHManager>,
+    request: ApprovalResponseData,
+) -> Result<ConnectResponse, String> {
+    let approvals = ssh_manager.read().await.approvals();
+    let approver = request.approver
+        .or_else(|| std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok())
+        .unwrap_or_else(|| "unknown".to_string());
+    let result = match &request.code {
+        Some(code) => approvals.submit_code(&request.request_id, code),
+        None if request.approve => approvals.approve(&request.request_id, &approver),
+        None => approvals.deny(&request.request_id, &approver),
+    };
+
+    match result {
+        Ok(()) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn known_hosts_revoke(
+    ssh_manager: State<'_, SharedSSHManager>,
+    request: RevokeHostKeyRequest,
+) -> Result<ConnectResponse, String> {
+    let known_hosts = ssh_manager.read().await.known_hosts();
+
+    match known_hosts.revoke(&request.hostname, request.port).await {
+        Ok(true) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Ok(false) => Ok(ConnectResponse {
+            success: false,
+            error: Some(format!("No known host key for {}:{}", request.hostname, request.port)),
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+// SSH Key Commands
+#[tauri::command]
+pub async fn ssh_discover_keys(app_handle: AppHandle) -> Result<Vec<LocalSshKey>, String> {
+    let home = app_handle.path().home_dir().map_err(|e| e.to_string())?;
+
+    tokio::task::spawn_blocking(move || discover_keys(&default_key_dir(&home)))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Port Forwarding Commands
+#[tauri::command]
+pub async fn port_forward_create(
+    ssh_manager: State<'_, SharedSSHManager>,
+    request: PortForwardRequest,
+) -> Result<PortForwardResponse, String> {
+    let manager = ssh_manager.read().await;
+
+    match manager.create_port_forward(request).await {
+        Ok(forward) => Ok(PortForwardResponse {
+            success: true,
+            forward: Some(forward),
+            error: None,
+        }),
+        Err(e) => Ok(PortForwardResponse {
+            success: false,
+            forward: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn ssh_create_local_forward(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    local_port: u16,
+    remote_host: String,
+    remote_port: u16,
+) -> Result<PortForward, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.create_local_forward(&session_id, local_port, &remote_host, remote_port).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn ssh_create_remote_forward(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: String,
+    remote_port: u16,
+    local_host: String,
+    local_port: u16,
+) -> Result<PortForward, String> {
+    traced(async move {
+        let manager = ssh_manager.read().await;
+        manager.create_remote_forward(&session_id, remote_port, &local_host, local_port).await.map_err(|e| localized(&e))
+    }).await
+}
+
+#[tauri::command]
+pub async fn port_forward_list(
+    ssh_manager: State<'_, SharedSSHManager>,
+    session_id: Option<String>,
+) -> Result<Vec<PortForward>, String> {
+    let manager = ssh_manager.read().await;
+    Ok(manager.list_port_forwards(session_id.as_deref()))
+}
+
+#[tauri::command]
+pub async fn port_forward_close(
+    ssh_manager: State<'_, SharedSSHManager>,
+    forward_id: String,
+) -> Result<ConnectResponse, String> {
+    let manager = ssh_manager.read().await;
+
+    match manager.close_port_forward(&forward_id) {
+        Ok(_) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+// Notification Commands
+#[tauri::command]
+pub async fn notification_get_preferences(
+    notifications: State<'_, SharedNotificationCenter>,
+) -> Result<NotificationPreferences, String> {
+    Ok(notifications.preferences())
+}
+
+#[tauri::command]
+pub async fn notification_set_preferences(
+    notifications: State<'_, SharedNotificationCenter>,
+    preferences: NotificationPreferences,
+) -> Result<ConnectResponse, String> {
+    match notifications.set_preferences(preferences).await {
+        Ok(()) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn notification_get_rules(
+    notifications: State<'_, SharedNotificationCenter>,
+) -> Result<Vec<NotificationRule>, String> {
+    Ok(notifications.rules())
+}
+
+// Rules are matched in the order given, so the list is replaced as a whole
+#[tauri::command]
+pub async fn notification_set_rules(
+    notifications: State<'_, SharedNotificationCenter>,
+    rules: Vec<NotificationRule>,
+) -> Result<Vec<NotificationRule>, String> {
+    notifications.set_rules(rules).await.map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn notification_get_do_not_disturb(
+    notifications: State<'_, SharedNotificationCenter>,
+) -> Result<Option<DateTime<Utc>>, String> {
+    Ok(notifications.do_not_disturb_until())
+}
+
+// Until the given time, or off when it's omitted
+#[tauri::command]
+pub async fn notification_set_do_not_disturb(
+    notifications: State<'_, SharedNotificationCenter>,
+    until: Option<DateTime<Utc>>,
+) -> Result<ConnectResponse, String> {
+    match notifications.set_do_not_disturb(until).await {
+        Ok(()) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutputWatchResponse {
+    pub success: bool,
+    pub watch: Option<OutputWatch>,
+    pub error: Option<String>,
+}
+
+#[tauri::command]
+pub async fn output_watch_add(
+    notifications: State<'_, SharedNotificationCenter>,
+    request: OutputWatchRequest,
+) -> Result<OutputWatchResponse, String> {
+    match notifications.add_watch(request).await {
+        Ok(watch) => Ok(OutputWatchResponse {
+            success: true,
+            watch: Some(watch),
+            error: None,
+        }),
+        Err(e) => Ok(OutputWatchResponse {
+            success: false,
+            watch: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn output_watch_remove(
+    notifications: State<'_, SharedNotificationCenter>,
+    watch_id: String,
+) -> Result<ConnectResponse, String> {
+    match notifications.remove_watch(&watch_id).await {
+        Ok(true) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Ok(false) => Ok(ConnectResponse {
+            success: false,
+            error: Some(format!("Output watch {} not found", watch_id)),
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn output_watch_list(
+    notifications: State<'_, SharedNotificationCenter>,
+) -> Result<Vec<OutputWatch>, String> {
+    Ok(notifications.list_watches())
+}
+
+// Clipboard Commands
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreparePasteRequest {
+    // Text to transform; the system clipboard is read when omitted
+    pub text: Option<String>,
+    #[serde(default)]
+    pub options: PasteOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreparePasteResponse {
+    pub success: bool,
+    pub paste: Option<PasteTransform>,
+    pub error: Option<String>,
+}
+
+#[tauri::command]
+pub async fn clipboard_write_text(
+    app_handle: AppHandle,
+    text: String,
+) -> Result<ConnectResponse, String> {
+    let result = validate_clipboard_text(&text)
+        .and_then(|_| app_handle.clipboard().write_text(text)
+            .map_err(|e| AppError::OperationFailed(format!("Clipboard write failed: {}", e))));
+
+    match result {
+        Ok(()) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn clipboard_prepare_paste(
+    app_handle: AppHandle,
+    request: PreparePasteRequest,
+) -> Result<PreparePasteResponse, String> {
+    let text = match request.text {
+        Some(text) => Ok(text),
+        None => app_handle.clipboard().read_text()
+            .map_err(|e| AppError::OperationFailed(format!("Clipboard read failed: {}", e))),
+    };
+
+    match text.and_then(|text| validate_clipboard_text(&text).map(|_| text)) {
+        Ok(text) => Ok(PreparePasteResponse {
+            success: true,
+            paste: Some(transform_paste(&text, &request.options)),
+            error: None,
+        }),
+        Err(e) => Ok(PreparePasteResponse {
+            success: false,
+            paste: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+// Logging Commands
+#[tauri::command]
+pub async fn log_get_level() -> Result<LogLevelConfig, String> {
+    Ok(log_filter::current())
+}
+
+#[tauri::command]
+pub async fn log_set_level(config: LogLevelConfig) -> Result<LogLevelConfig, String> {
+    log_filter::configure(&config).map_err(|e| e.to_string())
+}
+
+// Plugin Commands
+#[tauri::command]
+pub async fn plugin_list(plugins: State<'_, SharedPluginHost>) -> Result<Vec<PluginInfo>, String> {
+    Ok(plugins.list())
+}
+
+#[tauri::command]
+pub async fn plugin_grant(
+    plugins: State<'_, SharedPluginHost>,
+    name: String,
+    capabilities: Vec<PluginCapability>,
+) -> Result<PluginInfo, String> {
+    plugins.grant(&name, capabilities).await.map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn plugin_run_command(
+    plugins: State<'_, SharedPluginHost>,
+    name: String,
+    command: String,
+    args: Vec<String>,
+) -> Result<String, String> {
+    traced(async move {
+        let plugins = plugins.inner().clone();
+        // Plugin code runs to its fuel limit, so keep it off the async workers
+        tokio::task::spawn_blocking(move || plugins.run_command(&name, &command, &args))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| localized(&e))
+    }).await
+}
+
+// Scripting Commands
+#[tauri::command]
+pub async fn script_list(scripts: State<'_, SharedScriptHost>) -> Result<Vec<ScriptInfo>, String> {
+    Ok(scripts.list())
+}
+
+#[tauri::command]
+pub async fn script_reload(scripts: State<'_, SharedScriptHost>) -> Result<Vec<ScriptInfo>, String> {
+    scripts.reload().await.map_err(|e| localized(&e))
+}
+
+// Scheduler Commands
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateJobRequest {
+    pub job_id: String,
+    pub job: JobRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobResponse {
+    pub success: bool,
+    pub job: Option<ScheduledJob>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobRunResponse {
+    pub success: bool,
+    pub run: Option<JobRun>,
+    pub error: Option<String>,
+}
+
+#[tauri::command]
+pub async fn job_create(
+    scheduler: State<'_, SharedJobScheduler>,
+    request: JobRequest,
+) -> Result<JobResponse, String> {
+    match scheduler.create(request).await {
+        Ok(job) => Ok(JobResponse {
+            success: true,
+            job: Some(job),
+            error: None,
+        }),
+        Err(e) => Ok(JobResponse {
+            success: false,
+            job: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn job_update(
+    scheduler: State<'_, SharedJobScheduler>,
+    request: UpdateJobRequest,
+) -> Result<JobResponse, String> {
+    match scheduler.update(&request.job_id, request.job).await {
+        Ok(job) => Ok(JobResponse {
+            success: true,
+            job: Some(job),
+            error: None,
+        }),
+        Err(e) => Ok(JobResponse {
+            success: false,
+            job: None,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn job_delete(
+    scheduler: State<'_, SharedJobScheduler>,
+    job_id: String,
+) -> Result<ConnectResponse, String> {
+    match scheduler.delete(&job_id).await {
+        Ok(true) => Ok(ConnectResponse {
+            success: true,
+            error: None,
+        }),
+        Ok(false) => Ok(ConnectResponse {
+            success: false,
+            error: Some(format!("Job {} not found", job_id)),
+        }),
+        Err(e) => Ok(ConnectResponse {
+            success: false,
+            error: Some(localized(&e)),
+        }),
+    }
+}
+
+#[tauri::command]
+pub async fn job_list(
+    scheduler: State<'_, SharedJobScheduler>,
+) -> Result<Vec<ScheduledJob>, String> {
+    Ok(scheduler.list())
+}
+
+// Run a job now, outside its schedule. The result is also kept as the job's last run.
+#[tauri::command]
+pub async fn job_run_now(
+    ssh_manager: State<'_, SharedSSHManager>,
+    profile_store: State<'_, SharedProfileStore>,
+    recording_manager: State<'_, SharedRecordingManager>,
+    notifications: State<'_, SharedNotificationCenter>,
+    scheduler: State<'_, SharedJobScheduler>,
+    job_id: String,
+) -> Result<JobRunResponse, String> {
+    traced(async move {
+        let context = JobContext {
+            ssh_manager: ssh_manager.inner().clone(),
+            profiles: profile_store.inner().clone(),
+            recordings: recording_manager.inner().clone(),
+            notifications: notifications.inner().clone(),
+        };
+        match scheduler::run_now(&scheduler, &context, &job_id).await {
+            Ok(run) => Ok(JobRunResponse {
+                success: run.success,
+                error: run.error.clone(),
+                run: Some(run),
+            }),
+            Err(e) => Ok(JobRunResponse {
+                success: false,
+                run: None,
+                error: Some(localized(&e)),
+            }),
+        }
+    }).await
+}
+
+// Localization Commands
+#[tauri::command]
+pub async fn i18n_get_language() -> Result<Language, String> {
+    Ok(i18n::preferred())
+}
+
+// Accepts any tag the UI has, e.g. navigator.language; unsupported ones fall back to English
+#[tauri::command]
+pub async fn i18n_set_language(language: String) -> Result<Language, String> {
+    let language = Language::parse(&language).unwrap_or_default();
+    i18n::set_preferred(language);
+    Ok(language)
+}
+
+// System Commands
+#[tauri::command]
+pub async fn get_system_proxy() -> Result<ProxySettings, String> {
+    tokio::task::spawn_blocking(detect_system_proxy)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// The managers' periodic cleanup tasks, to check none of them is stuck
+#[tauri::command]
+pub async fn get_background_tasks() -> Result<Vec<BackgroundTaskStatus>, String> {
+    Ok(background::statuses())
+}
+
+// Stops a dialog transfer's copy within a chunk; transfer_resume starts the file over
+#[tauri::command]
+pub async fn transfer_pause(
+    transfer_manager: State<'_, SharedTransferManager>,
+    transfer_id: String,
+) -> Result<FileTransfer, String> {
+    transfer_manager.read().await.pause_transfer(&transfer_id).map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn transfer_resume(
+    transfer_manager: State<'_, SharedTransferManager>,
+    transfer_id: String,
+) -> Result<FileTransfer, String> {
+    transfer_manager.read().await.resume_transfer(&transfer_id).map_err(|e| localized(&e))
+}
+
+#[tauri::command]
+pub async fn transfer_cancel(
+    transfer_manager: State<'_, SharedTransferManager>,
+    transfer_id: String,
+) -> Result<FileTransfer, String> {
+    let mut manager = transfer_manager.write().await;
+    manager.cancel_transfer(&transfer_id).map_err(|e| localized(&e))?;
+    manager.get_transfer(&transfer_id)
+        .ok_or_else(|| localized(&AppError::NotFound(format!("Transfer {}", transfer_id))))
+}
+
+// The bandwidth cap shared by all transfers
+#[tauri::command]
+pub async fn get_transfer_limit() -> Result<TransferLimit, String> {
+    Ok(TransferLimit {
+        max_bytes_per_second: transfer::global_limit().get(),
+    })
+}
+
+#[tauri::command]
+pub async fn set_transfer_limit(request: TransferLimit) -> Result<TransferLimit, String> {
+    transfer::global_limit().set(request.max_bytes_per_second).map_err(|e| localized(&e))?;
+    Ok(request)
+}
+
+// Long-running work in progress or recently finished: dialog transfers, recordings, dotfile
+// syncs, scheduled runs and group commands
+#[tauri::command]
+pub async fn jobs_list(recording_manager: State<'_, SharedRecordingManager>) -> Result<Vec<Job>, String> {
+    Ok(jobs::list(&[], &recording_manager))
+}
+
+#[tauri::command]
+pub async fn jobs_cancel(
+    recording_manager: State<'_, SharedRecordingManager>,
+    job_id: String,
+) -> Result<Job, String> {
+    traced(async move {
+        jobs::cancel_or_stop(&job_id, &recording_manager).await.map_err(|e| localized(&e))
+    }).await
+}
+
+// Error text for command responses, in the language the UI asked for
+fn localized(error: &AppError) -> String {
+    error.localized(i18n::current())
+}
+
+// Run a command under a fresh correlation ID so everything one user action logs can be traced together
+async fn traced<F: std::future::Future>(future: F) -> F::Output {
+    correlation::scope(correlation::new_id(), future).await
+}
+
+// Carry out the input and notifications a script hook asked for
+async fn apply_script_actions(
+    manager: &SSHManager,
+    notifications: &NotificationCenter,
+    session_id: &str,
+    actions: Vec<ScriptAction>,
+) {
+    for action in actions {
+        match action {
+            ScriptAction::SendInput { session_id, input } => {
+                if let Err(e) = manager.write_to_shell(&session_id, &input).await {
+                    log::warn!("Script input for session {} failed: {}", session_id, e);
+                }
+            }
+            ScriptAction::Notify { title, body } => {
+                notifications.notify(NotificationKind::Script, Some(session_id), title, body);
+            }
+        }
+    }
+}
+
+// before_transfer hooks run their actions even when one of them cancels the transfer
+async fn run_before_transfer(
+    scripts: &SharedScriptHost,
+    manager: &SSHManager,
+    notifications: &NotificationCenter,
+    session_id: &str,
+    direction: &str,
+    remote_path: &str,
+) -> AppResult<()> {
+    let outcome = scripts.before_transfer(session_id, direction, remote_path);
+    let allowed = outcome.check_transfer(remote_path);
+    apply_script_actions(manager, notifications, session_id, outcome.actions).await;
+    allowed
+}
+
+// Helper to append an event to the session's active recording, if any
+async fn record_terminal_event(
+    recording_manager: &SharedRecordingManager,
+    session_id: &str,
+    event_type: TerminalEventType,
+    data: String,
+) {
+    let event = TerminalEvent {
+        timestamp: Utc::now(),
+        event_type,
+        data,
+        metadata: None,
+    };
+
+    if let Err(e) = recording_manager.record_event(session_id, event).await {
+        log::warn!("Failed to record terminal event for session {}: {}", session_id, e);
+    }
+}
+
+// Push terminal output to the frontend, pausing while too much of it is unacknowledged
+#[allow(clippy::too_many_arguments)]
+async fn start_terminal_output_monitoring(
+    app_handle: AppHandle,
+    ssh_manager: SharedSSHManager,
+    recording_manager: SharedRecordingManager,
+    output_streams: SharedOutputStreams,
+    notifications: SharedNotificationCenter,
+    plugins: SharedPluginHost,
+    scripts: SharedScriptHost,
+    session_id: String,
+) {
+    let stream = output_streams.register(&session_id);
+
+    // Shell errors are logged under the ID of the action that opened the shell
+    tokio::spawn(correlation::propagate(async move {
+        // The shell is open, so on_connect scripts can already type into it
+        {
+            let manager = ssh_manager.read().await;
+            let config = manager.get_session(&session_id).await.map(|session| session.config).ok();
+            let host = config.as_ref().map(|config| config.hostname.clone()).unwrap_or_default();
+            // The profile asks for every session to be recorded; a reopened shell keeps its recording
+            if config.is_some_and(|config| config.defaults.auto_record) && !recording_manager.is_recording(&session_id) {
+                if let Err(e) = recording_manager.start_recording(session_id.clone(), host.clone(), None).await {
+                    log::warn!("Failed to start recording session {}: {}", session_id, e);
+                }
+            }
+            let outcome = scripts.on_connect(&session_id, &host);
+            apply_script_actions(&manager, &notifications, &session_id, outcome.actions).await;
+        }
+
+        let mut osc52 = Osc52Scanner::new();
+        let mut login_notices = LoginNoticeScanner::new();
+        // Output arrives as it is read; the ticks only settle the login notices
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(250));
+
+        let subscription = ssh_manager.read().await.subscribe_shell(&session_id).await;
+        let mut output = match subscription {
+            Ok((_, output)) => output,
+            Err(e) => {
+                log::warn!("No shell output to stream for session {}: {}", session_id, e);
+                output_streams.unregister(&session_id, stream.id);
+                return;
+            }
+        };
+
+        loop {
+            let event = tokio::select! {
+                _ = stream.cancel.cancelled() => break,
+                event = output.recv() => match event {
+                    Some(event) => event,
+                    // The shell was closed or replaced
+                    None => break,
+                },
+                _ = interval.tick() => {
+                    if let Some(notices) = login_notices.poll().filter(|notices| !notices.is_empty()) {
+                        if let Err(e) = ssh_manager.read().await.set_notices(&session_id, notices.clone()).await {
+                            log::debug!("Failed to keep login notices for session {}: {}", session_id, e);
+                        }
+                        let _ = app_handle.emit("session-notices", &SessionNoticesEvent { session_id: session_id.clone(), notices });
+                    }
+                    continue;
+                }
+            };
+
+            let manager = ssh_manager.read().await;
+            match event {
+                ShellEvent::Output(output) => {
+                    login_notices.feed(&output);
+                    record_terminal_event(
+                        &recording_manager,
+                        &session_id,
+                        TerminalEventType::Output,
+                        output.clone(),
+                    ).await;
+
+                    for copy in osc52.feed(&output) {
+                        copy_to_clipboard(&app_handle, &session_id, copy);
+                    }
+                    notifications.check_output(&session_id, &output);
+                    let outcome = scripts.on_output(&session_id, &output);
+                    apply_script_actions(&manager, &notifications, &session_id, outcome.actions).await;
+                    drop(manager);
+
+                    // Plugins only change what is displayed, recordings and triggers see the raw output
+                    let output = plugins.filter_output(&session_id, output);
+                    let event = TerminalOutputEvent {
+                        session_id: session_id.clone(),
+                        bytes: output.len(),
+                        data: output,
+                    };
+                    stream.flow.record_sent(event.bytes);
+
+                    if let Err(e) = app_handle.emit("terminal-output", &event) {
+                        log::error!("Failed to emit terminal output: {}", e);
+                        break;
+                    }
+
+                    // Leaving the rest with the reader, and past it the server, until the frontend catches up
+                    tokio::select! {
+                        _ = stream.cancel.cancelled() => break,
+                        caught_up = stream.flow.wait_for_capacity() => {
+                            if !caught_up {
+                                log::warn!("Terminal output for session {} not acknowledged, resuming", session_id);
+                            }
+                        }
+                    }
+                },
+                ShellEvent::Closed => {
+                    match manager.close_exited_shell(&session_id).await {
+                        Ok(Some(exit)) => {
+                            record_terminal_event(
+                                &recording_manager,
+                                &session_id,
+                                TerminalEventType::Disconnect,
+                                format!("Shell exited with status {}", exit.exit_status.map_or("unknown".to_string(), |status| status.to_string())),
+                            ).await;
+                            let event = ShellClosedEvent { session_id: session_id.clone(), exit };
+                            if let Err(e) = app_handle.emit("shell-closed", &event) {
+                                log::error!("Failed to emit shell-closed event: {}", e);
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => log::debug!("Failed to close the shell of session {}: {}", session_id, e),
+                    }
+                    break;
+                },
+                ShellEvent::Failed(e) => {
+                    log::error!("Error reading from shell: {}", e);
+                    let host = manager.get_session(&session_id).await.ok().map(|session| session.config.hostname);
+                    notifications.notify_host(
+                        NotificationKind::SessionDropped,
+                        Some(&session_id),
+                        host.as_deref(),
+                        "Session disconnected",
+                        format!("Connection to {} was lost: {}", host.as_deref().unwrap_or(&session_id), e),
+                    );
+                    break;
                 }
             }
         }
-    });
+
+        output_streams.unregister(&session_id, stream.id);
+        let outcome = scripts.on_disconnect(&session_id);
+        apply_script_actions(&*ssh_manager.read().await, &notifications, &session_id, outcome.actions).await;
+        log::debug!("Terminal output stream stopped for session {}", session_id);
+    }));
+}
+
+// Remote programs (tmux, vim, ...) copy to the local clipboard with OSC 52
+fn copy_to_clipboard(app_handle: &AppHandle, session_id: &str, copy: Osc52Copy) {
+    let event = RemoteCopyEvent {
+        session_id: session_id.to_string(),
+        selection: copy.selection,
+        bytes: copy.text.len(),
+    };
+
+    if let Err(e) = app_handle.clipboard().write_text(copy.text) {
+        log::warn!("Failed to write OSC 52 payload to clipboard: {}", e);
+        return;
+    }
+    if let Err(e) = app_handle.emit("clipboard-remote-copy", &event) {
+        log::warn!("Failed to emit clipboard-remote-copy event: {}", e);
+    }
 }
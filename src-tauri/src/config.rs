@@ -0,0 +1,116 @@
+use crate::logging::redact::RedactionConfig;
+use crate::reporting::ErrorReportingConfig;
+use crate::types::{AppError, AppResult};
+use crate::webhooks::WebhookConfig;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE_NAME: &str = "config.json";
+
+// User-editable settings file. Every section is optional, a missing file means defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppConfig {
+    #[serde(rename = "errorReporting", default)]
+    pub error_reporting: ErrorReportingConfig,
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSocketConfig {
+    // How long the SSH sessions of a dropped WebSocket client wait for it to resume; 0 ends them at once
+    #[serde(rename = "resumeGraceSeconds", default = "default_resume_grace_seconds")]
+    pub resume_grace_seconds: u64,
+    // How often the server pings each client; 0 turns heartbeats off
+    #[serde(rename = "heartbeatIntervalSeconds", default = "default_heartbeat_interval_seconds")]
+    pub heartbeat_interval_seconds: u64,
+    // A client that hasn't answered a ping for this long is dropped
+    #[serde(rename = "heartbeatTimeoutSeconds", default = "default_heartbeat_timeout_seconds")]
+    pub heartbeat_timeout_seconds: u64,
+    // Terminal output larger than this is split across messages, for gateways with frame limits
+    #[serde(rename = "maxMessageBytes", default = "default_max_message_bytes")]
+    pub max_message_bytes: usize,
+    // Required on upgrades as ?token= or an Authorization: Bearer header; without it upgrades are refused
+    #[serde(rename = "authToken", default)]
+    pub auth_token: Option<String>,
+    // Browser origins allowed besides localhost and the desktop webview; "*" allows any
+    #[serde(rename = "allowedOrigins", default)]
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            resume_grace_seconds: default_resume_grace_seconds(),
+            heartbeat_interval_seconds: default_heartbeat_interval_seconds(),
+            heartbeat_timeout_seconds: default_heartbeat_timeout_seconds(),
+            max_message_bytes: default_max_message_bytes(),
+            auth_token: None,
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+fn default_resume_grace_seconds() -> u64 {
+    60
+}
+
+fn default_heartbeat_interval_seconds() -> u64 {
+    30
+}
+
+fn default_heartbeat_timeout_seconds() -> u64 {
+    90
+}
+
+fn default_max_message_bytes() -> usize {
+    32 * 1024
+}
+
+impl AppConfig {
+    pub async fn load(path: &Path) -> AppResult<Self> {
+        if !tokio::fs::try_exists(path).await? {
+            return Ok(Self::default());
+        }
+
+        let content = tokio::fs::read_to_string(path).await?;
+        serde_json::from_str(&content).map_err(|e| {
+            AppError::InvalidConfiguration(format!("Invalid config file {}: {}", path.display(), e))
+        })
+    }
+
+    // WEBTERMINAL_CONFIG overrides the default location, for the standalone server
+    pub fn path_from_env(default_dir: &Path) -> PathBuf {
+        std::env::var_os("WEBTERMINAL_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| default_dir.join(CONFIG_FILE_NAME))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+
+        let config = AppConfig::load(&path).await.unwrap();
+        assert!(!config.error_reporting.enabled);
+
+        tokio::fs::write(&path, r#"{ "errorReporting": { "enabled": true, "dsn": "https://key@errors.example.com/7" } }"#)
+            .await
+            .unwrap();
+        let config = AppConfig::load(&path).await.unwrap();
+        assert!(config.error_reporting.enabled);
+        assert_eq!(config.error_reporting.dsn.as_deref(), Some("https://key@errors.example.com/7"));
+
+        tokio::fs::write(&path, "{ not json").await.unwrap();
+        assert!(matches!(AppConfig::load(&path).await, Err(AppError::InvalidConfiguration(_))));
+    }
+}
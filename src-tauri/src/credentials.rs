@@ -0,0 +1,275 @@
+use crate::types::{AppError, AppResult, CredentialUsage};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+const KEYRING_SERVICE: &str = "webterminal-pro";
+// Next to the index; one JSON line per use, only ever appended to
+const USAGE_FILE: &str = "credential-usage.jsonl";
+
+pub type SharedCredentialStore = Arc<CredentialStore>;
+
+// Secrets kept in the OS keyring, serialized as a single JSON entry per key
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StoredCredential {
+    pub password: Option<String>,
+    #[serde(rename = "privateKey")]
+    pub private_key: Option<String>,
+    pub passphrase: Option<String>,
+}
+
+impl StoredCredential {
+    pub fn is_empty(&self) -> bool {
+        self.password.is_none() && self.private_key.is_none() && self.passphrase.is_none()
+    }
+}
+
+// What is stored under a key, without the secret itself. OS keyrings can't be enumerated,
+// so these references are kept in a separate index file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialReference {
+    pub key: String,
+    pub label: String,
+    #[serde(rename = "hasPassword")]
+    pub has_password: bool,
+    #[serde(rename = "hasPrivateKey")]
+    pub has_private_key: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+pub struct CredentialStore {
+    service: String,
+    references: DashMap<String, CredentialReference>,
+    index_path: Option<PathBuf>,
+    // Oldest first, kept after the credential itself is deleted
+    usage: DashMap<String, Vec<CredentialUsage>>,
+    usage_path: Option<PathBuf>,
+}
+
+impl CredentialStore {
+    pub fn new() -> Self {
+        Self {
+            service: KEYRING_SERVICE.to_string(),
+            references: DashMap::new(),
+            index_path: None,
+            usage: DashMap::new(),
+            usage_path: None,
+        }
+    }
+
+    pub async fn load(index_path: PathBuf) -> AppResult<Self> {
+        let mut store = Self::new();
+
+        if tokio::fs::try_exists(&index_path).await? {
+            let content = tokio::fs::read_to_string(&index_path).await?;
+            let references: Vec<CredentialReference> = serde_json::from_str(&content)?;
+            for reference in references {
+                store.references.insert(reference.key.clone(), reference);
+            }
+        }
+
+        let usage_path = index_path.with_file_name(USAGE_FILE);
+        if tokio::fs::try_exists(&usage_path).await? {
+            let content = tokio::fs::read_to_string(&usage_path).await?;
+            for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                // A line cut short by a crash shouldn't cost the rest of the trail
+                match serde_json::from_str::<CredentialUsage>(line) {
+                    Ok(usage) => store.usage.entry(usage.key.clone()).or_default().push(usage),
+                    Err(e) => log::warn!("Skipping unreadable credential usage entry: {}", e),
+                }
+            }
+            // Otherwise the next entry would be appended to the end of the cut line
+            if !content.is_empty() && !content.ends_with('\n') {
+                let mut file = tokio::fs::OpenOptions::new().append(true).open(&usage_path).await?;
+                file.write_all(b"\n").await?;
+                file.flush().await?;
+            }
+        }
+
+        store.index_path = Some(index_path);
+        store.usage_path = Some(usage_path);
+        Ok(store)
+    }
+
+    pub async fn save(&self, key: &str, label: &str, credential: &StoredCredential) -> AppResult<()> {
+        if key.trim().is_empty() {
+            return Err(AppError::ValidationError("Credential key cannot be empty".to_string()));
+        }
+        if credential.is_empty() {
+            return Err(AppError::ValidationError("Credential has no secrets to store".to_string()));
+        }
+
+        let secret = serde_json::to_string(credential)?;
+        let service = self.service.clone();
+        let entry_key = key.to_string();
+
+        // Keyring backends block on IPC with the platform secret service
+        tokio::task::spawn_blocking(move || {
+            keyring::Entry::new(&service, &entry_key)
+                .and_then(|entry| entry.set_password(&secret))
+                .map_err(keyring_error)
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Keyring task failed: {}", e)))??;
+
+        let now = Utc::now();
+        let created_at = self.references.get(key).map_or(now, |reference| reference.created_at);
+        self.references.insert(key.to_string(), CredentialReference {
+            key: key.to_string(),
+            label: label.to_string(),
+            has_password: credential.password.is_some(),
+            has_private_key: credential.private_key.is_some(),
+            created_at,
+            updated_at: now,
+        });
+        self.save_index().await
+    }
+
+    pub async fn get(&self, key: &str) -> AppResult<Option<StoredCredential>> {
+        let service = self.service.clone();
+        let key = key.to_string();
+
+        let secret = tokio::task::spawn_blocking(move || {
+            match keyring::Entry::new(&service, &key).and_then(|entry| entry.get_password()) {
+                Ok(secret) => Ok(Some(secret)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(e) => Err(keyring_error(e)),
+            }
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Keyring task failed: {}", e)))??;
+
+        secret.map(|secret| serde_json::from_str(&secret).map_err(AppError::from)).transpose()
+    }
+
+    pub async fn delete(&self, key: &str) -> AppResult<bool> {
+        let service = self.service.clone();
+        let entry_key = key.to_string();
+
+        let deleted = tokio::task::spawn_blocking(move || {
+            match keyring::Entry::new(&service, &entry_key).and_then(|entry| entry.delete_credential()) {
+                Ok(()) => Ok(true),
+                Err(keyring::Error::NoEntry) => Ok(false),
+                Err(e) => Err(keyring_error(e)),
+            }
+        })
+        .await
+        .map_err(|e| AppError::InternalError(format!("Keyring task failed: {}", e)))??;
+
+        if self.references.remove(key).is_some() {
+            self.save_index().await?;
+        }
+        Ok(deleted)
+    }
+
+    pub fn list(&self) -> Vec<CredentialReference> {
+        let mut references: Vec<CredentialReference> = self.references.iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        references.sort_by(|a, b| a.label.cmp(&b.label));
+        references
+    }
+
+    pub async fn record_use(&self, usage: CredentialUsage) -> AppResult<()> {
+        if let Some(path) = &self.usage_path {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let mut line = serde_json::to_string(&usage)?;
+            line.push('\n');
+            let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await?;
+        }
+
+        self.usage.entry(usage.key.clone()).or_default().push(usage);
+        Ok(())
+    }
+
+    // Newest first
+    pub fn usage(&self, key: &str) -> Vec<CredentialUsage> {
+        self.usage.get(key)
+            .map(|usage| usage.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    async fn save_index(&self) -> AppResult<()> {
+        let Some(path) = &self.index_path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let content = serde_json::to_string_pretty(&self.list())?;
+        tokio::fs::write(path, content).await?;
+        Ok(())
+    }
+}
+
+impl Default for CredentialStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn keyring_error(error: keyring::Error) -> AppError {
+    AppError::OperationFailed(format!("Keyring error: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(key: &str, hostname: &str, succeeded: bool) -> CredentialUsage {
+        CredentialUsage {
+            key: key.to_string(),
+            profile_id: Some("web".to_string()),
+            session_id: uuid::Uuid::new_v4().to_string(),
+            hostname: hostname.to_string(),
+            port: 22,
+            username: "deploy".to_string(),
+            local_user: Some("alice".to_string()),
+            method: "publickey".to_string(),
+            succeeded,
+            at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_credential_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_path = dir.path().join("credentials.json");
+
+        let store = CredentialStore::load(index_path.clone()).await.unwrap();
+        store.record_use(usage("profile:web", "web1", true)).await.unwrap();
+        store.record_use(usage("profile:db", "db1", true)).await.unwrap();
+        store.record_use(usage("profile:web", "web2", false)).await.unwrap();
+
+        let hosts = |usage: Vec<CredentialUsage>| usage.into_iter().map(|usage| usage.hostname).collect::<Vec<_>>();
+        assert_eq!(hosts(store.usage("profile:web")), ["web2", "web1"]);
+        assert!(store.usage("profile:missing").is_empty());
+
+        // Survives a restart, past a line cut short by a crash
+        let usage_path = dir.path().join(USAGE_FILE);
+        let mut content = std::fs::read_to_string(&usage_path).unwrap();
+        content.push_str("{\"key\":\"profile:web\",\"host");
+        std::fs::write(&usage_path, content).unwrap();
+
+        let store = CredentialStore::load(index_path).await.unwrap();
+        let web = store.usage("profile:web");
+        assert_eq!(hosts(web.clone()), ["web2", "web1"]);
+        assert!(!web[0].succeeded && web[1].succeeded);
+        assert_eq!(hosts(store.usage("profile:db")), ["db1"]);
+
+        store.record_use(usage("profile:web", "web3", true)).await.unwrap();
+        let store = CredentialStore::load(dir.path().join("credentials.json")).await.unwrap();
+        assert_eq!(hosts(store.usage("profile:web")), ["web3", "web2", "web1"]);
+    }
+}
@@ -0,0 +1,496 @@
+use crate::ssh::SSHManager;
+use crate::types::{AppError, AppResult};
+use futures_util::stream::{self, StreamExt};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const BANNER_TIMEOUT: Duration = Duration::from_secs(3);
+const PING_COUNT: u32 = 4;
+const PING_TIMEOUT: Duration = Duration::from_secs(15);
+const TRACEROUTE_MAX_HOPS: u32 = 30;
+const TRACEROUTE_TIMEOUT: Duration = Duration::from_secs(90);
+pub const MAX_SCAN_PORTS: usize = 1024;
+const SCAN_CONCURRENCY: usize = 64;
+// Exit status of coreutils timeout when the command ran out of time
+const TIMEOUT_EXIT_STATUS: &str = "124";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortState {
+    Open,
+    // Something answered with a reset
+    Closed,
+    // Nothing answered before the timeout, typically a firewall dropping packets
+    Filtered,
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsResult {
+    pub addresses: Vec<String>,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortCheck {
+    pub port: u16,
+    pub state: PortState,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PingResult {
+    pub transmitted: u32,
+    pub received: u32,
+    #[serde(rename = "lossPercent")]
+    pub loss_percent: f64,
+    #[serde(rename = "minMs")]
+    pub min_ms: Option<f64>,
+    #[serde(rename = "avgMs")]
+    pub avg_ms: Option<f64>,
+    #[serde(rename = "maxMs")]
+    pub max_ms: Option<f64>,
+    // Set when the ping tool itself couldn't run
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TracerouteHop {
+    pub hop: u32,
+    // None when the hop didn't answer
+    pub address: Option<String>,
+    #[serde(rename = "rttMs")]
+    pub rtt_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    // SSH answered with its banner
+    Reachable,
+    DnsFailed,
+    // No reply to ping and nothing listening
+    HostDown,
+    // The host is up but the SSH port is closed or filtered
+    SshBlocked,
+    // The port is open but doesn't speak SSH
+    NotSsh,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticReport {
+    pub hostname: String,
+    pub port: u16,
+    pub dns: DnsResult,
+    pub ping: Option<PingResult>,
+    pub tcp: Option<PortCheck>,
+    #[serde(rename = "sshBanner")]
+    pub ssh_banner: Option<String>,
+    pub verdict: Verdict,
+}
+
+// Hosts end up as arguments to ping and traceroute, so nothing that could pass for an option
+pub fn validate_host(host: &str) -> AppResult<()> {
+    let valid = !host.is_empty()
+        && host.len() <= 253
+        && !host.starts_with('-')
+        && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '_' | '%'));
+    if !valid {
+        return Err(AppError::ValidationError(format!("Invalid host name: {}", host)));
+    }
+    Ok(())
+}
+
+pub async fn resolve(host: &str, port: u16) -> DnsResult {
+    let started = Instant::now();
+    let result = tokio::net::lookup_host((host, port)).await;
+    let duration_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(addresses) => {
+            let mut addresses: Vec<String> = addresses.map(|address| address.ip().to_string()).collect();
+            addresses.dedup();
+            DnsResult { addresses, duration_ms, error: None }
+        }
+        Err(e) => DnsResult { addresses: Vec::new(), duration_ms, error: Some(e.to_string()) },
+    }
+}
+
+pub async fn check_port(host: &str, port: u16, timeout: Duration) -> PortCheck {
+    let started = Instant::now();
+    let result = tokio::time::timeout(timeout, TcpStream::connect((host, port))).await;
+    let (state, error) = match result {
+        Ok(Ok(_)) => (PortState::Open, None),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => (PortState::Closed, None),
+        Ok(Err(e)) => (PortState::Unreachable, Some(e.to_string())),
+        Err(_) => (PortState::Filtered, None),
+    };
+    PortCheck {
+        port,
+        state,
+        duration_ms: started.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+// The identification line an SSH server sends first, e.g. "SSH-2.0-OpenSSH_9.6"
+pub async fn read_ssh_banner(address: SocketAddr) -> Option<String> {
+    let read = async {
+        let mut stream = TcpStream::connect(address).await.ok()?;
+        let mut buffer = vec![0u8; 256];
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let read = stream.read(&mut buffer[filled..]).await.ok()?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+            // Servers may send other lines before the identification (RFC 4253 4.2)
+            if let Some(line) = String::from_utf8_lossy(&buffer[..filled]).lines().find(|line| line.starts_with("SSH-")) {
+                return Some(line.trim().to_string());
+            }
+        }
+        None
+    };
+    tokio::time::timeout(BANNER_TIMEOUT, read).await.ok().flatten()
+}
+
+pub async fn ping(host: &str) -> AppResult<PingResult> {
+    validate_host(host)?;
+
+    let count = PING_COUNT.to_string();
+    let mut command = Command::new("ping");
+    if cfg!(windows) {
+        command.args(["-n", &count, "-w", "2000", host]);
+    } else {
+        command.args(["-c", &count, host]);
+    }
+
+    let output = match run_tool(command, PING_TIMEOUT).await {
+        Ok(output) => output,
+        Err(e) => return Ok(PingResult { error: Some(e.to_string()), ..PingResult::default() }),
+    };
+    Ok(parse_ping(&output))
+}
+
+pub async fn traceroute(host: &str) -> AppResult<Vec<TracerouteHop>> {
+    validate_host(host)?;
+
+    let max_hops = TRACEROUTE_MAX_HOPS.to_string();
+    let command = if cfg!(windows) {
+        let mut command = Command::new("tracert");
+        command.args(["-d", "-w", "2000", "-h", &max_hops, host]);
+        command
+    } else {
+        let mut command = Command::new("traceroute");
+        command.args(["-n", "-q", "1", "-w", "2", "-m", &max_hops, host]);
+        command
+    };
+
+    let output = run_tool(command, TRACEROUTE_TIMEOUT).await?;
+    Ok(parse_traceroute(&output))
+}
+
+// Output of a system tool, whatever its exit status (ping exits non-zero on loss)
+async fn run_tool(mut command: Command, timeout: Duration) -> AppResult<String> {
+    command.kill_on_drop(true);
+    let output = tokio::time::timeout(timeout, command.output())
+        .await
+        .map_err(|_| AppError::TimeoutError("Network tool did not finish in time".to_string()))?
+        .map_err(|e| AppError::OperationFailed(format!("Failed to run network tool: {}", e)))?;
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// ping summaries from Linux (iputils, busybox), macOS/BSD and Windows
+pub fn parse_ping(output: &str) -> PingResult {
+    static COUNTS: OnceLock<Regex> = OnceLock::new();
+    static WINDOWS_COUNTS: OnceLock<Regex> = OnceLock::new();
+    static RTT: OnceLock<Regex> = OnceLock::new();
+    static WINDOWS_RTT: OnceLock<Regex> = OnceLock::new();
+    let counts = COUNTS.get_or_init(|| Regex::new(r"(\d+) packets transmitted, (\d+) (?:packets )?received").unwrap());
+    let windows_counts = WINDOWS_COUNTS.get_or_init(|| Regex::new(r"Sent = (\d+), Received = (\d+)").unwrap());
+    let rtt = RTT.get_or_init(|| Regex::new(r"= ([\d.]+)/([\d.]+)/([\d.]+)").unwrap());
+    let windows_rtt = WINDOWS_RTT.get_or_init(|| {
+        Regex::new(r"Minimum = (\d+)ms, Maximum = (\d+)ms, Average = (\d+)ms").unwrap()
+    });
+
+    let mut result = PingResult::default();
+    if let Some(captures) = counts.captures(output).or_else(|| windows_counts.captures(output)) {
+        result.transmitted = captures[1].parse().unwrap_or(0);
+        result.received = captures[2].parse().unwrap_or(0);
+    }
+    if result.transmitted > 0 {
+        result.loss_percent = f64::from(result.transmitted - result.received.min(result.transmitted)) * 100.0
+            / f64::from(result.transmitted);
+    } else {
+        result.loss_percent = 100.0;
+    }
+
+    if let Some(captures) = rtt.captures(output) {
+        result.min_ms = captures[1].parse().ok();
+        result.avg_ms = captures[2].parse().ok();
+        result.max_ms = captures[3].parse().ok();
+    } else if let Some(captures) = windows_rtt.captures(output) {
+        result.min_ms = captures[1].parse().ok();
+        result.max_ms = captures[2].parse().ok();
+        result.avg_ms = captures[3].parse().ok();
+    }
+    result
+}
+
+// Hop lines from traceroute -n and tracert -d
+pub fn parse_traceroute(output: &str) -> Vec<TracerouteHop> {
+    static RTT: OnceLock<Regex> = OnceLock::new();
+    let rtt = RTT.get_or_init(|| Regex::new(r"<?([\d.]+) ?ms").unwrap());
+
+    output.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let hop: u32 = fields.next()?.parse().ok()?;
+            let fields: Vec<&str> = fields.collect();
+            // tracert puts the address last, traceroute first
+            let address = fields.iter()
+                .rev()
+                .chain(fields.iter())
+                .find(|field| field.parse::<std::net::IpAddr>().is_ok())
+                .map(|field| field.to_string());
+            let rtt_ms = rtt.captures(line).and_then(|captures| captures[1].parse().ok());
+            Some(TracerouteHop { hop, address, rtt_ms })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanOrigin {
+    Local,
+    // From the remote end of a connected session
+    Session,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortScanResult {
+    pub host: String,
+    pub origin: ScanOrigin,
+    pub ports: Vec<PortCheck>,
+}
+
+// "22,80,8000-8010" into a sorted list without duplicates
+pub fn parse_port_list(spec: &str) -> AppResult<Vec<u16>> {
+    let invalid = |item: &str| AppError::ValidationError(format!("Invalid port or range: {}", item));
+    let mut ports = Vec::new();
+    for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let (start, end) = match item.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => (item, item),
+        };
+        let start: u16 = start.parse().map_err(|_| invalid(item))?;
+        let end: u16 = end.parse().map_err(|_| invalid(item))?;
+        if start == 0 || end < start {
+            return Err(invalid(item));
+        }
+        if ports.len() + usize::from(end - start) >= MAX_SCAN_PORTS {
+            return Err(AppError::ValidationError(format!("Cannot scan more than {} ports at once", MAX_SCAN_PORTS)));
+        }
+        ports.extend(start..=end);
+    }
+    ports.sort_unstable();
+    ports.dedup();
+    if ports.is_empty() {
+        return Err(AppError::ValidationError("No ports to scan".to_string()));
+    }
+    Ok(ports)
+}
+
+pub async fn scan_ports(host: &str, ports: &[u16], timeout: Duration) -> AppResult<PortScanResult> {
+    validate_host(host)?;
+    let checks = stream::iter(ports.iter().copied())
+        .map(|port| check_port(host, port, timeout))
+        .buffered(SCAN_CONCURRENCY)
+        .collect()
+        .await;
+    Ok(PortScanResult { host: host.to_string(), origin: ScanOrigin::Local, ports: checks })
+}
+
+// Check the ports from the server's side of the session, e.g. to see what its firewall
+// lets through to a database next to it. Needs bash and coreutils timeout on the server.
+pub async fn scan_ports_remote(
+    manager: &SSHManager,
+    session_id: &str,
+    host: &str,
+    ports: &[u16],
+    timeout: Duration,
+) -> AppResult<PortScanResult> {
+    validate_host(host)?;
+    let started = Instant::now();
+    let result = manager.exec_command(session_id, &remote_scan_command(host, ports, timeout)).await?;
+    if result.exit_code != 0 {
+        return Err(AppError::OperationFailed(format!(
+            "Remote port scan failed: {}", result.stderr.trim()
+        )));
+    }
+
+    let mut checks = parse_remote_scan(&result.stdout, ports);
+    // Only the total is known for a remote scan
+    let duration_ms = started.elapsed().as_millis() as u64;
+    for check in &mut checks {
+        check.duration_ms = duration_ms;
+    }
+    Ok(PortScanResult { host: host.to_string(), origin: ScanOrigin::Session, ports: checks })
+}
+
+// One "<port> <exit status>" line per port. The host is validated and passed as an
+// argument, never spliced into the inner script.
+fn remote_scan_command(host: &str, ports: &[u16], timeout: Duration) -> String {
+    let ports: Vec<String> = ports.iter().map(u16::to_string).collect();
+    format!(
+        "for port in {}; do timeout {} bash -c 'exec 3<>\"/dev/tcp/$0/$1\"' {} \"$port\" 2>/dev/null; echo \"$port $?\"; done",
+        ports.join(" "),
+        timeout.as_secs().max(1),
+        host
+    )
+}
+
+fn parse_remote_scan(output: &str, ports: &[u16]) -> Vec<PortCheck> {
+    ports.iter()
+        .map(|&port| {
+            let status = output.lines()
+                .filter_map(|line| line.split_once(' '))
+                .find(|(reported, _)| reported.trim().parse() == Ok(port))
+                .map(|(_, status)| status.trim());
+            let (state, error) = match status {
+                Some("0") => (PortState::Open, None),
+                Some(TIMEOUT_EXIT_STATUS) => (PortState::Filtered, None),
+                Some("127") => (PortState::Unreachable, Some("bash or timeout is missing on the server".to_string())),
+                Some(_) => (PortState::Closed, None),
+                None => (PortState::Unreachable, Some("No result from the server".to_string())),
+            };
+            PortCheck { port, state, duration_ms: 0, error }
+        })
+        .collect()
+}
+
+// Work out why SSH on host:port does or doesn't work
+pub async fn diagnose(hostname: &str, port: u16) -> AppResult<DiagnosticReport> {
+    validate_host(hostname)?;
+
+    let dns = resolve(hostname, port).await;
+    let mut report = DiagnosticReport {
+        hostname: hostname.to_string(),
+        port,
+        dns,
+        ping: None,
+        tcp: None,
+        ssh_banner: None,
+        verdict: Verdict::DnsFailed,
+    };
+    if report.dns.addresses.is_empty() {
+        return Ok(report);
+    }
+
+    let (ping, tcp) = tokio::join!(ping(hostname), check_port(hostname, port, DEFAULT_CONNECT_TIMEOUT));
+    let host_answers_ping = ping.as_ref().is_ok_and(|ping| ping.received > 0);
+    report.ping = ping.ok();
+
+    report.verdict = match tcp.state {
+        PortState::Open => {
+            let address = report.dns.addresses[0].parse().map(|ip| SocketAddr::new(ip, port));
+            report.ssh_banner = match address {
+                Ok(address) => read_ssh_banner(address).await,
+                Err(_) => None,
+            };
+            if report.ssh_banner.is_some() { Verdict::Reachable } else { Verdict::NotSsh }
+        }
+        // A refusal proves the host is up even when ICMP is blocked
+        PortState::Closed => Verdict::SshBlocked,
+        PortState::Filtered | PortState::Unreachable if host_answers_ping => Verdict::SshBlocked,
+        PortState::Filtered | PortState::Unreachable => Verdict::HostDown,
+    };
+    report.tcp = Some(tcp);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ping() {
+        let linux = "4 packets transmitted, 3 received, 25% packet loss, time 3004ms\n\
+                     rtt min/avg/max/mdev = 10.100/12.500/15.900/2.100 ms\n";
+        let result = parse_ping(linux);
+        assert_eq!((result.transmitted, result.received), (4, 3));
+        assert_eq!(result.loss_percent, 25.0);
+        assert_eq!((result.min_ms, result.avg_ms, result.max_ms), (Some(10.1), Some(12.5), Some(15.9)));
+
+        let windows = "Packets: Sent = 4, Received = 4, Lost = 0 (0% loss),\n\
+                       Minimum = 1ms, Maximum = 9ms, Average = 3ms\n";
+        let result = parse_ping(windows);
+        assert_eq!((result.received, result.loss_percent, result.avg_ms), (4, 0.0, Some(3.0)));
+
+        let down = "4 packets transmitted, 0 packets received, 100.0% packet loss\n";
+        assert_eq!(parse_ping(down).loss_percent, 100.0);
+    }
+
+    #[test]
+    fn test_parse_traceroute() {
+        let unix = "traceroute to 10.0.0.9 (10.0.0.9), 30 hops max, 60 byte packets\n 1  192.168.1.1  0.512 ms\n 2  *\n 3  10.0.0.9  4.210 ms\n";
+        assert_eq!(parse_traceroute(unix), vec![
+            TracerouteHop { hop: 1, address: Some("192.168.1.1".to_string()), rtt_ms: Some(0.512) },
+            TracerouteHop { hop: 2, address: None, rtt_ms: None },
+            TracerouteHop { hop: 3, address: Some("10.0.0.9".to_string()), rtt_ms: Some(4.21) },
+        ]);
+
+        let windows = "  1    <1 ms    <1 ms    <1 ms  192.168.1.1\n  2     *        *        *     Request timed out.\n";
+        let hops = parse_traceroute(windows);
+        assert_eq!(hops[0].address.as_deref(), Some("192.168.1.1"));
+        assert_eq!(hops[0].rtt_ms, Some(1.0));
+        assert_eq!(hops[1].address, None);
+    }
+
+    #[test]
+    fn test_port_list_and_remote_scan() {
+        assert_eq!(parse_port_list("443, 22,8000-8002,22").unwrap(), vec![22, 443, 8000, 8001, 8002]);
+        assert!(parse_port_list("0").is_err());
+        assert!(parse_port_list("90-80").is_err());
+        assert!(parse_port_list("1-65535").is_err());
+        assert!(parse_port_list(" , ").is_err());
+
+        let command = remote_scan_command("db.internal", &[5432, 6379], Duration::from_secs(2));
+        assert!(command.starts_with("for port in 5432 6379; do timeout 2 bash -c"));
+
+        let checks = parse_remote_scan("5432 0\n6379 124\n", &[5432, 6379, 9200]);
+        let states: Vec<PortState> = checks.iter().map(|check| check.state).collect();
+        assert_eq!(states, vec![PortState::Open, PortState::Filtered, PortState::Unreachable]);
+    }
+
+    #[tokio::test]
+    async fn test_check_port_and_banner() {
+        assert!(validate_host("-oProxyCommand=x").is_err());
+        assert!(validate_host("db-1.internal").is_ok());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            drop(listener);
+            let _ = tokio::io::AsyncWriteExt::write_all(&mut stream, b"SSH-2.0-OpenSSH_9.6\r\n").await;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        });
+
+        assert_eq!(read_ssh_banner(address).await.as_deref(), Some("SSH-2.0-OpenSSH_9.6"));
+        // The listener closed after its one connection
+        let check = check_port("127.0.0.1", address.port(), DEFAULT_CONNECT_TIMEOUT).await;
+        assert_eq!(check.state, PortState::Closed);
+    }
+}
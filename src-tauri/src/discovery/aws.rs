@@ -0,0 +1,387 @@
+use super::{CloudProvider, DiscoveredInstance};
+use crate::types::{AppError, AppResult};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+const EC2_API_VERSION: &str = "2016-11-15";
+const SERVICE: &str = "ec2";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+const PAGE_SIZE: usize = 1000;
+// A runaway NextToken loop stops here
+const MAX_PAGES: usize = 50;
+
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl std::fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AwsCredentials {
+    pub fn from_env() -> Option<Self> {
+        let access_key_id = std::env::var("AWS_ACCESS_KEY_ID").ok().filter(|value| !value.is_empty())?;
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY").ok().filter(|value| !value.is_empty())?;
+        Some(Self {
+            access_key_id,
+            secret_access_key,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok().filter(|value| !value.is_empty()),
+        })
+    }
+
+    // A [profile] section of ~/.aws/credentials
+    pub fn from_shared_file(content: &str, profile: &str) -> Option<Self> {
+        let mut section = None;
+        let mut values = HashMap::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = Some(name.trim().to_string());
+                continue;
+            }
+            if section.as_deref() != Some(profile) {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                values.insert(key.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+
+        Some(Self {
+            access_key_id: values.remove("aws_access_key_id")?,
+            secret_access_key: values.remove("aws_secret_access_key")?,
+            session_token: values.remove("aws_session_token"),
+        })
+    }
+
+    // Same order as the AWS CLI: environment variables, then the shared credentials file.
+    // Naming a profile skips the environment.
+    pub async fn resolve(home: &Path, profile: Option<&str>) -> AppResult<Self> {
+        if profile.is_none() {
+            if let Some(credentials) = Self::from_env() {
+                return Ok(credentials);
+            }
+        }
+
+        let profile = profile.map(str::to_string)
+            .or_else(|| std::env::var("AWS_PROFILE").ok())
+            .unwrap_or_else(|| "default".to_string());
+        let path = std::env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+            .map(Into::into)
+            .unwrap_or_else(|| home.join(".aws").join("credentials"));
+
+        let content = if tokio::fs::try_exists(&path).await? {
+            tokio::fs::read_to_string(&path).await?
+        } else {
+            String::new()
+        };
+        Self::from_shared_file(&content, &profile).ok_or_else(|| {
+            AppError::InvalidConfiguration(format!("No AWS credentials found for profile {}", profile))
+        })
+    }
+}
+
+// RFC 3986 encoding as SigV4 wants it: everything but unreserved characters
+fn uri_encode(value: &str) -> String {
+    value.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn canonical_query(params: &[(String, String)]) -> String {
+    let mut encoded: Vec<(String, String)> = params.iter()
+        .map(|(key, value)| (uri_encode(key), uri_encode(value)))
+        .collect();
+    encoded.sort();
+    encoded.iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Signature Version 4 headers for a GET with an empty body
+pub fn sign_v4(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    host: &str,
+    query: &str,
+    now: DateTime<Utc>,
+) -> Vec<(&'static str, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+
+    let mut headers = vec![("host", host.to_string()), ("x-amz-date", amz_date.clone())];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+
+    let canonical_request = format!(
+        "GET\n/\n{}\n{}\n{}\n{}",
+        query, canonical_headers, signed_headers, hex(&Sha256::digest(b""))
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date, scope, hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = [date.as_str(), region, service, "aws4_request"].iter()
+        .fold(format!("AWS4{}", credentials.secret_access_key).into_bytes(), |key, part| hmac_sha256(&key, part));
+    let signature = hex(&hmac_sha256(&key, &string_to_sign));
+
+    let mut signed = vec![
+        ("X-Amz-Date", amz_date),
+        ("Authorization", format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        )),
+    ];
+    if let Some(token) = &credentials.session_token {
+        signed.push(("X-Amz-Security-Token", token.clone()));
+    }
+    signed
+}
+
+// DescribeInstances parameters for running instances carrying every given tag.
+// An empty or "*" value only requires the tag key to be present.
+fn describe_params(tags: &HashMap<String, String>, next_token: Option<&str>) -> Vec<(String, String)> {
+    let mut params = vec![
+        ("Action".to_string(), "DescribeInstances".to_string()),
+        ("Version".to_string(), EC2_API_VERSION.to_string()),
+        ("MaxResults".to_string(), PAGE_SIZE.to_string()),
+        ("Filter.1.Name".to_string(), "instance-state-name".to_string()),
+        ("Filter.1.Value.1".to_string(), "running".to_string()),
+    ];
+
+    let mut tags: Vec<_> = tags.iter().collect();
+    tags.sort();
+    for (index, (key, value)) in tags.into_iter().enumerate() {
+        let filter = index + 2;
+        let (name, value) = if value.is_empty() || value == "*" {
+            ("tag-key".to_string(), key.clone())
+        } else {
+            (format!("tag:{}", key), value.clone())
+        };
+        params.push((format!("Filter.{}.Name", filter), name));
+        params.push((format!("Filter.{}.Value.1", filter), value));
+    }
+
+    if let Some(token) = next_token {
+        params.push(("NextToken".to_string(), token.to_string()));
+    }
+    params
+}
+
+pub async fn describe_instances(
+    credentials: &AwsCredentials,
+    region: &str,
+    tags: &HashMap<String, String>,
+) -> AppResult<Vec<DiscoveredInstance>> {
+    if region.is_empty() || !region.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(AppError::ValidationError(format!("Invalid AWS region: {}", region)));
+    }
+
+    let host = format!("ec2.{}.amazonaws.com", region);
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| AppError::OperationFailed(format!("Failed to create HTTP client: {}", e)))?;
+
+    let mut instances = Vec::new();
+    let mut next_token = None;
+    for _ in 0..MAX_PAGES {
+        let query = canonical_query(&describe_params(tags, next_token.as_deref()));
+        let mut request = client.get(format!("https://{}/?{}", host, query));
+        for (name, value) in sign_v4(credentials, region, SERVICE, &host, &query, Utc::now()) {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await
+            .map_err(|e| AppError::IOError(std::io::Error::other(format!("EC2 request failed: {}", e))))?;
+        let status = response.status();
+        let body = response.text().await
+            .map_err(|e| AppError::IOError(std::io::Error::other(format!("EC2 response failed: {}", e))))?;
+        if !status.is_success() {
+            return Err(api_error(status, &body));
+        }
+
+        let (page, token) = parse_describe_instances(&body, region)?;
+        instances.extend(page);
+        match token {
+            Some(token) => next_token = Some(token),
+            None => return Ok(instances),
+        }
+    }
+
+    log::warn!("Stopped listing EC2 instances in {} after {} pages", region, MAX_PAGES);
+    Ok(instances)
+}
+
+fn api_error(status: reqwest::StatusCode, body: &str) -> AppError {
+    let (code, message) = roxmltree::Document::parse(body)
+        .ok()
+        .and_then(|doc| {
+            let error = doc.descendants().find(|node| node.has_tag_name("Error"))?;
+            Some((child_text(error, "Code")?, child_text(error, "Message").unwrap_or_default()))
+        })
+        .unwrap_or_else(|| (status.to_string(), String::new()));
+
+    let message = format!("EC2 {}: {}", code, message);
+    match code.as_str() {
+        "AuthFailure" | "UnauthorizedOperation" | "InvalidClientTokenId" | "SignatureDoesNotMatch" => {
+            AppError::PermissionDenied(message)
+        }
+        _ if status.is_server_error() || code == "RequestLimitExceeded" => AppError::IOError(std::io::Error::other(message)),
+        _ => AppError::OperationFailed(message),
+    }
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn child_text(node: roxmltree::Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+// Instances and the token for the next page from a DescribeInstances response
+pub fn parse_describe_instances(xml: &str, region: &str) -> AppResult<(Vec<DiscoveredInstance>, Option<String>)> {
+    let doc = roxmltree::Document::parse(xml)
+        .map_err(|e| AppError::OperationFailed(format!("Unreadable EC2 response: {}", e)))?;
+    let root = doc.root_element();
+
+    let instances = root.descendants()
+        .filter(|node| node.has_tag_name("instancesSet"))
+        .flat_map(|set| set.children().filter(|node| node.has_tag_name("item")))
+        .filter_map(|item| {
+            let tags: HashMap<String, String> = child(item, "tagSet")
+                .into_iter()
+                .flat_map(|set| set.children().filter(|node| node.has_tag_name("item")))
+                .filter_map(|tag| Some((child_text(tag, "key")?, child_text(tag, "value").unwrap_or_default())))
+                .collect();
+            Some(DiscoveredInstance {
+                provider: CloudProvider::Aws,
+                instance_id: child_text(item, "instanceId")?,
+                name: tags.get("Name").cloned(),
+                region: region.to_string(),
+                public_ip: child_text(item, "ipAddress"),
+                private_ip: child_text(item, "privateIpAddress"),
+                tags,
+                profile_id: None,
+            })
+        })
+        .collect();
+
+    Ok((instances, child_text(root, "nextToken")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn example_credentials() -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        }
+    }
+
+    #[test]
+    fn test_sign_v4_reference_vector() {
+        // "get-vanilla" from the AWS Signature Version 4 test suite
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = sign_v4(&example_credentials(), "us-east-1", "service", "example.amazonaws.com", "", now);
+        assert_eq!(headers[0], ("X-Amz-Date", "20150830T123600Z".to_string()));
+        assert_eq!(
+            headers[1].1,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn test_query_and_credentials_file() {
+        let tags = HashMap::from([("Env".to_string(), "prod web".to_string()), ("Team".to_string(), "*".to_string())]);
+        let query = canonical_query(&describe_params(&tags, None));
+        assert!(query.starts_with("Action=DescribeInstances&Filter.1.Name=instance-state-name"));
+        assert!(query.contains("Filter.2.Name=tag%3AEnv&Filter.2.Value.1=prod%20web"));
+        assert!(query.contains("Filter.3.Name=tag-key&Filter.3.Value.1=Team"));
+
+        let file = "[default]\naws_access_key_id = AKIA1\naws_secret_access_key = s1\n\n[ops]\naws_access_key_id=AKIA2\naws_secret_access_key=s2\naws_session_token=t2\n";
+        let ops = AwsCredentials::from_shared_file(file, "ops").unwrap();
+        assert_eq!((ops.access_key_id.as_str(), ops.session_token.as_deref()), ("AKIA2", Some("t2")));
+        assert!(AwsCredentials::from_shared_file(file, "missing").is_none());
+    }
+
+    #[test]
+    fn test_parse_describe_instances() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<DescribeInstancesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+  <reservationSet>
+    <item>
+      <instancesSet>
+        <item>
+          <instanceId>i-0abc</instanceId>
+          <privateIpAddress>10.0.1.5</privateIpAddress>
+          <ipAddress>54.10.20.30</ipAddress>
+          <networkInterfaceSet><item><privateIpAddress>10.0.9.9</privateIpAddress></item></networkInterfaceSet>
+          <tagSet>
+            <item><key>Name</key><value>web-1</value></item>
+            <item><key>Env</key><value>prod</value></item>
+          </tagSet>
+        </item>
+        <item>
+          <instanceId>i-0def</instanceId>
+          <privateIpAddress>10.0.1.6</privateIpAddress>
+        </item>
+      </instancesSet>
+    </item>
+  </reservationSet>
+  <nextToken>page-2</nextToken>
+</DescribeInstancesResponse>"#;
+
+        let (instances, next_token) = parse_describe_instances(xml, "eu-west-1").unwrap();
+        assert_eq!(next_token.as_deref(), Some("page-2"));
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[0].name.as_deref(), Some("web-1"));
+        assert_eq!(instances[0].public_ip.as_deref(), Some("54.10.20.30"));
+        assert_eq!(instances[0].private_ip.as_deref(), Some("10.0.1.5"));
+        assert_eq!(instances[0].tags.get("Env").map(String::as_str), Some("prod"));
+        assert_eq!((instances[1].instance_id.as_str(), instances[1].public_ip.as_deref()), ("i-0def", None));
+    }
+}
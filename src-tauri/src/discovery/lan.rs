@@ -0,0 +1,385 @@
+use crate::diagnostics::read_ssh_banner;
+use crate::profiles::ConnectionProfile;
+use crate::types::{AppError, AppResult};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{TcpStream, UdpSocket};
+
+const MDNS_GROUP: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+const SSH_SERVICE: &str = "_ssh._tcp.local";
+const DEFAULT_MDNS_WINDOW: Duration = Duration::from_secs(2);
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+// A /22 at most, so a typo can't start sweeping a /8
+pub const MAX_SCAN_HOSTS: usize = 1024;
+const SCAN_CONCURRENCY: usize = 128;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+// Ask responders to answer us directly instead of on the multicast group (RFC 6762 5.4)
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LanHostSource {
+    Mdns,
+    Scan,
+}
+
+// A host on the local network answering SSH, offered for quick connect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanHost {
+    pub address: String,
+    pub port: u16,
+    // The mDNS service target, e.g. "nas.local"
+    pub hostname: Option<String>,
+    // The advertised service instance name
+    #[serde(rename = "serviceName")]
+    pub service_name: Option<String>,
+    pub banner: Option<String>,
+    pub sources: Vec<LanHostSource>,
+    // An existing profile already pointing at this host
+    #[serde(rename = "profileId")]
+    pub profile_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanDiscoveryRequest {
+    #[serde(default = "default_true")]
+    pub mdns: bool,
+    // IPv4 CIDR to probe, e.g. "192.168.1.0/24"; no probing when unset
+    pub subnet: Option<String>,
+    pub port: Option<u16>,
+    #[serde(rename = "timeoutMs")]
+    pub timeout_ms: Option<u64>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub async fn discover(request: &LanDiscoveryRequest) -> AppResult<Vec<LanHost>> {
+    let port = request.port.unwrap_or(22);
+    let targets = match &request.subnet {
+        Some(subnet) => parse_subnet(subnet)?,
+        None => Vec::new(),
+    };
+
+    let mdns = async {
+        if !request.mdns {
+            return Vec::new();
+        }
+        let window = request.timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_MDNS_WINDOW);
+        browse_mdns(window).await.unwrap_or_else(|e| {
+            log::warn!("mDNS browse failed: {}", e);
+            Vec::new()
+        })
+    };
+    let probe_timeout = request.timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_PROBE_TIMEOUT);
+    let scan = scan_subnet(&targets, port, probe_timeout);
+    let (advertised, scanned) = tokio::join!(mdns, scan);
+
+    let mut hosts: BTreeMap<(IpAddr, u16), LanHost> = BTreeMap::new();
+    for host in advertised.into_iter().chain(scanned) {
+        let Ok(address) = host.address.parse::<IpAddr>() else {
+            continue;
+        };
+        match hosts.get_mut(&(address, host.port)) {
+            Some(existing) => {
+                existing.hostname = existing.hostname.take().or(host.hostname);
+                existing.service_name = existing.service_name.take().or(host.service_name);
+                existing.banner = existing.banner.take().or(host.banner);
+                for source in host.sources {
+                    if !existing.sources.contains(&source) {
+                        existing.sources.push(source);
+                    }
+                }
+            }
+            None => {
+                hosts.insert((address, host.port), host);
+            }
+        }
+    }
+
+    // Advertised hosts haven't been checked for SSH yet
+    let hosts: Vec<LanHost> = stream::iter(hosts.into_values())
+        .map(|mut host| async move {
+            if host.banner.is_none() {
+                if let Ok(address) = host.address.parse::<IpAddr>() {
+                    host.banner = read_ssh_banner(SocketAddr::new(address, host.port)).await;
+                }
+            }
+            host
+        })
+        .buffered(SCAN_CONCURRENCY)
+        .collect()
+        .await;
+    log::info!("LAN discovery found {} SSH hosts", hosts.len());
+    Ok(hosts)
+}
+
+// Point hosts at the profiles that already connect to them
+pub fn match_profiles(hosts: &mut [LanHost], profiles: &[ConnectionProfile]) {
+    for host in hosts {
+        host.profile_id = profiles.iter()
+            .find(|profile| {
+                profile.port == host.port
+                    && (profile.hostname == host.address
+                        || host.hostname.as_deref().is_some_and(|name| profile.hostname.eq_ignore_ascii_case(name)))
+            })
+            .map(|profile| profile.id.clone());
+    }
+}
+
+pub fn parse_subnet(subnet: &str) -> AppResult<Vec<Ipv4Addr>> {
+    let invalid = || AppError::ValidationError(format!("Invalid subnet: {}", subnet));
+    let (network, prefix) = subnet.trim().split_once('/').ok_or_else(invalid)?;
+    let network: Ipv4Addr = network.parse().map_err(|_| invalid())?;
+    let prefix: u32 = prefix.parse().ok().filter(|prefix| *prefix <= 32).ok_or_else(invalid)?;
+
+    let size = 1u64 << (32 - prefix);
+    if size > MAX_SCAN_HOSTS as u64 {
+        return Err(AppError::ValidationError(format!(
+            "Subnet {} is too large; at most {} addresses can be scanned",
+            subnet, MAX_SCAN_HOSTS
+        )));
+    }
+    let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+    let first = u32::from(network) & mask;
+    let last = first + (size as u32 - 1);
+    // Skip the network and broadcast addresses except on point-to-point sized subnets
+    let range = if prefix >= 31 { first..=last } else { first + 1..=last - 1 };
+    Ok(range.map(Ipv4Addr::from).collect())
+}
+
+async fn scan_subnet(targets: &[Ipv4Addr], port: u16, timeout: Duration) -> Vec<LanHost> {
+    stream::iter(targets.iter().copied())
+        .map(|address| async move {
+            let address = SocketAddr::new(IpAddr::V4(address), port);
+            let connected = tokio::time::timeout(timeout, TcpStream::connect(address)).await;
+            if !matches!(connected, Ok(Ok(_))) {
+                return None;
+            }
+            // An open port that never sends an SSH identification is something else
+            let banner = read_ssh_banner(address).await?;
+            Some(LanHost {
+                address: address.ip().to_string(),
+                port,
+                hostname: None,
+                service_name: None,
+                banner: Some(banner),
+                sources: vec![LanHostSource::Scan],
+                profile_id: None,
+            })
+        })
+        .buffer_unordered(SCAN_CONCURRENCY)
+        .filter_map(|host| async move { host })
+        .collect()
+        .await
+}
+
+async fn browse_mdns(window: Duration) -> AppResult<Vec<LanHost>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.send_to(&ptr_query(SSH_SERVICE), MDNS_GROUP).await?;
+
+    let mut hosts = Vec::new();
+    let mut buffer = vec![0u8; 9000];
+    let deadline = tokio::time::Instant::now() + window;
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+        let (len, from) = received?;
+        match parse_response(&buffer[..len]) {
+            Some(records) => hosts.extend(records.into_hosts(from.ip())),
+            None => log::debug!("Ignoring malformed mDNS response from {}", from),
+        }
+    }
+    Ok(hosts)
+}
+
+fn ptr_query(name: &str) -> Vec<u8> {
+    // ID 0, standard query, one question
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    packet.extend_from_slice(&(CLASS_IN | UNICAST_RESPONSE).to_be_bytes());
+    packet
+}
+
+#[derive(Debug, Default)]
+struct MdnsRecords {
+    // Service type -> instance names
+    pointers: Vec<String>,
+    // Instance name -> (target host, port)
+    services: HashMap<String, (String, u16)>,
+    // Host name -> address
+    addresses: HashMap<String, Ipv4Addr>,
+}
+
+impl MdnsRecords {
+    fn into_hosts(self, from: IpAddr) -> Vec<LanHost> {
+        let mut instances = self.pointers;
+        // Some responders only send the SRV record
+        for instance in self.services.keys() {
+            if !instances.contains(instance) {
+                instances.push(instance.clone());
+            }
+        }
+
+        instances.into_iter()
+            .filter_map(|instance| {
+                let (target, port) = self.services.get(&instance)?;
+                let address = self.addresses.get(&target.to_lowercase()).map(|address| IpAddr::V4(*address)).unwrap_or(from);
+                let service_name = instance.strip_suffix(&format!(".{}", SSH_SERVICE)).unwrap_or(&instance).to_string();
+                Some(LanHost {
+                    address: address.to_string(),
+                    port: *port,
+                    hostname: Some(target.clone()),
+                    service_name: Some(service_name),
+                    banner: None,
+                    sources: vec![LanHostSource::Mdns],
+                    profile_id: None,
+                })
+            })
+            .collect()
+    }
+}
+
+fn parse_response(packet: &[u8]) -> Option<MdnsRecords> {
+    let read_u16 = |offset: usize| packet.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+    // Only responses
+    if read_u16(2)? & 0x8000 == 0 {
+        return None;
+    }
+    let questions = read_u16(4)?;
+    let records = read_u16(6)? as usize + read_u16(8)? as usize + read_u16(10)? as usize;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(packet, offset)?.1 + 4;
+    }
+
+    let mut parsed = MdnsRecords::default();
+    for _ in 0..records {
+        let (name, next) = read_name(packet, offset)?;
+        let record_type = read_u16(next)?;
+        let length = read_u16(next + 8)? as usize;
+        let data = next + 10;
+        let data_end = data + length;
+        if data_end > packet.len() {
+            return None;
+        }
+
+        match record_type {
+            TYPE_PTR if name.eq_ignore_ascii_case(SSH_SERVICE) => {
+                parsed.pointers.push(read_name(packet, data)?.0);
+            }
+            TYPE_SRV if length >= 7 => {
+                let port = read_u16(data + 4)?;
+                let target = read_name(packet, data + 6)?.0;
+                if name.to_lowercase().ends_with(SSH_SERVICE) {
+                    parsed.services.insert(name, (target, port));
+                }
+            }
+            TYPE_A if length == 4 => {
+                let address = Ipv4Addr::new(packet[data], packet[data + 1], packet[data + 2], packet[data + 3]);
+                parsed.addresses.insert(name.to_lowercase(), address);
+            }
+            _ => {}
+        }
+        offset = data_end;
+    }
+    Some(parsed)
+}
+
+// A possibly compressed name and the offset just past it
+fn read_name(packet: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds pointer loops in hostile packets
+    for _ in 0..128 {
+        let len = *packet.get(offset)? as usize;
+        if len == 0 {
+            let end = end.unwrap_or(offset + 1);
+            return Some((labels.join("."), end));
+        }
+        if len & 0xC0 == 0xC0 {
+            let pointer = ((len & 0x3F) << 8) | *packet.get(offset + 1)? as usize;
+            end.get_or_insert(offset + 2);
+            offset = pointer;
+            continue;
+        }
+        let label = packet.get(offset + 1..offset + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        offset += 1 + len;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_name(packet: &mut Vec<u8>, name: &str) {
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+    }
+
+    fn push_record(packet: &mut Vec<u8>, record_type: u16, data: &[u8]) {
+        packet.extend_from_slice(&record_type.to_be_bytes());
+        packet.extend_from_slice(&[0x80, 0x01, 0, 0, 0x11, 0x94]);
+        packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(data);
+    }
+
+    #[test]
+    fn test_parse_mdns_response() {
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0, 0, 3, 0, 0, 0, 0];
+        // PTR _ssh._tcp.local -> nas._ssh._tcp.local, the service type compressed later on
+        let service_offset = packet.len();
+        push_name(&mut packet, SSH_SERVICE);
+        let mut instance = vec![3];
+        instance.extend_from_slice(b"nas");
+        instance.extend_from_slice(&[0xC0, service_offset as u8]);
+        push_record(&mut packet, TYPE_PTR, &instance);
+
+        // SRV nas._ssh._tcp.local -> nas.local:2222
+        packet.extend_from_slice(&[3, b'n', b'a', b's', 0xC0, service_offset as u8]);
+        let mut srv = vec![0, 0, 0, 0, 0x08, 0xAE];
+        push_name(&mut srv, "nas.local");
+        push_record(&mut packet, TYPE_SRV, &srv);
+
+        push_name(&mut packet, "nas.local");
+        push_record(&mut packet, TYPE_A, &[192, 168, 1, 20]);
+
+        let hosts = parse_response(&packet).unwrap().into_hosts(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 99)));
+        assert_eq!(hosts.len(), 1);
+        assert_eq!((hosts[0].address.as_str(), hosts[0].port), ("192.168.1.20", 2222));
+        assert_eq!(hosts[0].hostname.as_deref(), Some("nas.local"));
+        assert_eq!(hosts[0].service_name.as_deref(), Some("nas"));
+
+        // Queries aren't responses
+        assert!(parse_response(&ptr_query(SSH_SERVICE)).is_none());
+    }
+
+    #[test]
+    fn test_parse_subnet() {
+        let hosts = parse_subnet("192.168.1.77/24").unwrap();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!((hosts[0], hosts[253]), (Ipv4Addr::new(192, 168, 1, 1), Ipv4Addr::new(192, 168, 1, 254)));
+        assert_eq!(parse_subnet("10.0.0.5/32").unwrap(), [Ipv4Addr::new(10, 0, 0, 5)]);
+        assert_eq!(parse_subnet("10.0.0.0/22").unwrap().len(), 1022);
+        assert!(parse_subnet("10.0.0.0/16").is_err());
+        assert!(parse_subnet("10.0.0.0").is_err());
+        assert!(parse_subnet("example.com/24").is_err());
+    }
+}
@@ -0,0 +1,372 @@
+pub mod aws;
+pub mod lan;
+
+use crate::profiles::{ConnectionProfile, ProfileAuthMethod, ProfileRequest, SharedProfileStore};
+use crate::types::{AppError, AppResult, SessionDefaults, SessionTags};
+use aws::AwsCredentials;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub type SharedDiscoveryStore = Arc<DiscoveryStore>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CloudProvider {
+    Aws,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveredInstance {
+    pub provider: CloudProvider,
+    #[serde(rename = "instanceId")]
+    pub instance_id: String,
+    pub name: Option<String>,
+    pub region: String,
+    #[serde(rename = "publicIp")]
+    pub public_ip: Option<String>,
+    #[serde(rename = "privateIp")]
+    pub private_ip: Option<String>,
+    pub tags: HashMap<String, String>,
+    // The profile created from this instance, once imported
+    #[serde(rename = "profileId")]
+    pub profile_id: Option<String>,
+}
+
+impl DiscoveredInstance {
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.instance_id)
+    }
+
+    pub fn address(&self, prefer_private: bool) -> Option<&str> {
+        let (first, second) = if prefer_private {
+            (&self.private_ip, &self.public_ip)
+        } else {
+            (&self.public_ip, &self.private_ip)
+        };
+        first.as_deref().or(second.as_deref())
+    }
+
+    // Every filter tag must be present; an empty or "*" value matches any value
+    pub fn matches_tags(&self, filters: &HashMap<String, String>) -> bool {
+        filters.iter().all(|(key, value)| match self.tags.get(key) {
+            Some(actual) => value.is_empty() || value == "*" || actual == value,
+            None => false,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveryRequest {
+    pub provider: CloudProvider,
+    pub region: String,
+    // Section of ~/.aws/credentials; environment credentials are tried first when unset
+    #[serde(rename = "credentialsProfile")]
+    pub credentials_profile: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+// Connection settings shared by every profile created in one import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportTemplate {
+    pub username: String,
+    pub port: Option<u16>,
+    #[serde(rename = "authMethod")]
+    pub auth_method: ProfileAuthMethod,
+    pub password: Option<String>,
+    #[serde(rename = "privateKey")]
+    pub private_key: Option<String>,
+    pub passphrase: Option<String>,
+    // Connect over the private address, e.g. through a VPN
+    #[serde(rename = "usePrivateIp", default)]
+    pub use_private_ip: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ImportedInstance {
+    #[serde(rename = "profileId")]
+    profile_id: String,
+    #[serde(rename = "usePrivateIp")]
+    use_private_ip: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct StoredDiscovery {
+    instances: Vec<DiscoveredInstance>,
+    imported: HashMap<String, ImportedInstance>,
+    #[serde(rename = "refreshedAt")]
+    refreshed_at: Option<DateTime<Utc>>,
+}
+
+pub struct DiscoveryStore {
+    // Last discovery result, by instance ID
+    instances: DashMap<String, DiscoveredInstance>,
+    imported: DashMap<String, ImportedInstance>,
+    refreshed_at: parking_lot::RwLock<Option<DateTime<Utc>>>,
+    profiles: SharedProfileStore,
+    storage_path: Option<PathBuf>,
+}
+
+impl DiscoveryStore {
+    pub fn new(profiles: SharedProfileStore) -> Self {
+        Self {
+            instances: DashMap::new(),
+            imported: DashMap::new(),
+            refreshed_at: parking_lot::RwLock::new(None),
+            profiles,
+            storage_path: None,
+        }
+    }
+
+    pub async fn load(storage_path: PathBuf, profiles: SharedProfileStore) -> AppResult<Self> {
+        let mut store = Self::new(profiles);
+
+        if tokio::fs::try_exists(&storage_path).await? {
+            let content = tokio::fs::read_to_string(&storage_path).await?;
+            let stored: StoredDiscovery = serde_json::from_str(&content)?;
+            for instance in stored.instances {
+                store.instances.insert(instance.instance_id.clone(), instance);
+            }
+            for (instance_id, imported) in stored.imported {
+                store.imported.insert(instance_id, imported);
+            }
+            *store.refreshed_at.get_mut() = stored.refreshed_at;
+        }
+
+        store.storage_path = Some(storage_path);
+        Ok(store)
+    }
+
+    // Ask the provider for running instances, replacing the previous result for that
+    // provider and region. Imported profiles follow their instance's new address.
+    pub async fn refresh(&self, home: &Path, request: &DiscoveryRequest) -> AppResult<Vec<DiscoveredInstance>> {
+        let found = match request.provider {
+            CloudProvider::Aws => {
+                let credentials = AwsCredentials::resolve(home, request.credentials_profile.as_deref()).await?;
+                aws::describe_instances(&credentials, &request.region, &request.tags).await?
+            }
+        };
+        log::info!("Discovered {} {:?} instances in {}", found.len(), request.provider, request.region);
+
+        self.instances.retain(|_, instance| {
+            instance.provider != request.provider || instance.region != request.region || !instance.matches_tags(&request.tags)
+        });
+        for instance in found {
+            self.instances.insert(instance.instance_id.clone(), instance);
+        }
+        *self.refreshed_at.write() = Some(Utc::now());
+
+        self.forget_deleted_profiles();
+        self.sync_imported_profiles().await;
+        self.save().await?;
+        Ok(self.list(&request.tags))
+    }
+
+    // Cached instances carrying the given tags, by name
+    pub fn list(&self, tags: &HashMap<String, String>) -> Vec<DiscoveredInstance> {
+        let mut instances: Vec<DiscoveredInstance> = self.instances.iter()
+            .filter(|entry| entry.matches_tags(tags))
+            .map(|entry| {
+                let mut instance = entry.value().clone();
+                instance.profile_id = self.imported.get(&instance.instance_id).map(|imported| imported.profile_id.clone());
+                instance
+            })
+            .collect();
+        instances.sort_by_key(|instance| instance.display_name().to_lowercase());
+        instances
+    }
+
+    pub fn refreshed_at(&self) -> Option<DateTime<Utc>> {
+        *self.refreshed_at.read()
+    }
+
+    // Create a profile for each instance that doesn't have one yet
+    pub async fn import(&self, instance_ids: &[String], template: &ImportTemplate) -> AppResult<Vec<ConnectionProfile>> {
+        if template.username.trim().is_empty() {
+            return Err(AppError::ValidationError("Username cannot be empty".to_string()));
+        }
+        self.forget_deleted_profiles();
+
+        let mut created = Vec::new();
+        let result = self.import_each(instance_ids, template, &mut created).await;
+        // Profiles created before a failure still need their mapping saved
+        self.save().await?;
+        result.map(|_| created)
+    }
+
+    async fn import_each(
+        &self,
+        instance_ids: &[String],
+        template: &ImportTemplate,
+        created: &mut Vec<ConnectionProfile>,
+    ) -> AppResult<()> {
+        for instance_id in instance_ids {
+            if self.imported.contains_key(instance_id) {
+                continue;
+            }
+            let instance = self.instances.get(instance_id)
+                .map(|entry| entry.value().clone())
+                .ok_or_else(|| AppError::NotFound(format!("Instance {}", instance_id)))?;
+            let hostname = instance.address(template.use_private_ip)
+                .ok_or_else(|| AppError::ValidationError(format!("Instance {} has no IP address", instance_id)))?;
+
+            let profile = self.profiles.create(ProfileRequest {
+                name: instance.display_name().to_string(),
+                folder: None,
+                hostname: hostname.to_string(),
+                port: template.port,
+                username: template.username.trim().to_string(),
+                auth_method: template.auth_method,
+                password: template.password.clone(),
+                private_key: template.private_key.clone(),
+                passphrase: template.passphrase.clone(),
+                keep_alive: None,
+                ready_timeout: None,
+                fallback_addresses: Vec::new(),
+                race_addresses: false,
+                charset: None,
+                tags: SessionTags::default(),
+                protection: None,
+                totp_secret: None,
+                defaults: SessionDefaults::default(),
+            }).await?;
+
+            self.imported.insert(instance_id.clone(), ImportedInstance {
+                profile_id: profile.id.clone(),
+                use_private_ip: template.use_private_ip,
+            });
+            created.push(profile);
+        }
+        Ok(())
+    }
+
+    fn forget_deleted_profiles(&self) {
+        self.imported.retain(|_, imported| self.profiles.get(&imported.profile_id).is_some());
+    }
+
+    // Instances get new public IPs when they restart, so keep imported profiles pointing at them
+    async fn sync_imported_profiles(&self) {
+        let imported: Vec<(String, ImportedInstance)> = self.imported.iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+
+        for (instance_id, imported) in imported {
+            let Some(address) = self.instances.get(&instance_id)
+                .and_then(|instance| instance.address(imported.use_private_ip).map(str::to_string))
+            else {
+                continue;
+            };
+            let Some(profile) = self.profiles.get(&imported.profile_id) else {
+                continue;
+            };
+            if profile.hostname == address {
+                continue;
+            }
+
+            log::info!("Profile {} follows instance {} to {}", profile.name, instance_id, address);
+            let request = ProfileRequest {
+                name: profile.name,
+                folder: profile.folder,
+                hostname: address,
+                port: Some(profile.port),
+                username: profile.username,
+                auth_method: profile.auth_method,
+                password: None,
+                private_key: None,
+                passphrase: None,
+                keep_alive: profile.keep_alive,
+                ready_timeout: profile.ready_timeout,
+                fallback_addresses: profile.fallback_addresses,
+                race_addresses: profile.race_addresses,
+                charset: profile.charset,
+                tags: profile.tags,
+                protection: profile.protection,
+                totp_secret: None,
+                defaults: profile.defaults,
+            };
+            if let Err(e) = self.profiles.update(&imported.profile_id, request).await {
+                log::warn!("Failed to update profile for instance {}: {}", instance_id, e);
+            }
+        }
+    }
+
+    async fn save(&self) -> AppResult<()> {
+        let Some(path) = &self.storage_path else {
+            return Ok(());
+        };
+
+        let stored = StoredDiscovery {
+            instances: self.instances.iter().map(|entry| entry.value().clone()).collect(),
+            imported: self.imported.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect(),
+            refreshed_at: self.refreshed_at(),
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, serde_json::to_string_pretty(&stored)?).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::CredentialStore;
+    use crate::profiles::ProfileStore;
+
+    fn instance(instance_id: &str, public_ip: Option<&str>, env: &str) -> DiscoveredInstance {
+        DiscoveredInstance {
+            provider: CloudProvider::Aws,
+            instance_id: instance_id.to_string(),
+            name: None,
+            region: "eu-west-1".to_string(),
+            public_ip: public_ip.map(str::to_string),
+            private_ip: Some("10.0.0.5".to_string()),
+            tags: HashMap::from([("Env".to_string(), env.to_string())]),
+            profile_id: None,
+        }
+    }
+
+    #[test]
+    fn test_tag_filter_and_address() {
+        let web = instance("i-1", Some("54.1.2.3"), "prod");
+        assert!(web.matches_tags(&HashMap::from([("Env".to_string(), "prod".to_string())])));
+        assert!(web.matches_tags(&HashMap::from([("Env".to_string(), "*".to_string())])));
+        assert!(!web.matches_tags(&HashMap::from([("Env".to_string(), "staging".to_string())])));
+        assert!(!web.matches_tags(&HashMap::from([("Team".to_string(), String::new())])));
+
+        assert_eq!(web.address(false), Some("54.1.2.3"));
+        assert_eq!(web.address(true), Some("10.0.0.5"));
+        assert_eq!(instance("i-2", None, "prod").address(false), Some("10.0.0.5"));
+    }
+
+    #[tokio::test]
+    async fn test_import_and_follow_address() {
+        let profiles = Arc::new(ProfileStore::new(Arc::new(CredentialStore::new())));
+        let store = DiscoveryStore::new(profiles.clone());
+        store.instances.insert("i-1".to_string(), instance("i-1", Some("54.1.2.3"), "prod"));
+
+        let template = ImportTemplate {
+            username: "ec2-user".to_string(),
+            port: None,
+            auth_method: ProfileAuthMethod::PrivateKey,
+            password: None,
+            private_key: None,
+            passphrase: None,
+            use_private_ip: false,
+        };
+        let created = store.import(&["i-1".to_string()], &template).await.unwrap();
+        assert_eq!((created[0].name.as_str(), created[0].hostname.as_str()), ("i-1", "54.1.2.3"));
+        // Importing again doesn't duplicate the profile
+        assert!(store.import(&["i-1".to_string()], &template).await.unwrap().is_empty());
+        assert_eq!(store.list(&HashMap::new())[0].profile_id.as_deref(), Some(created[0].id.as_str()));
+
+        store.instances.insert("i-1".to_string(), instance("i-1", Some("54.9.9.9"), "prod"));
+        store.sync_imported_profiles().await;
+        assert_eq!(profiles.get(&created[0].id).unwrap().hostname, "54.9.9.9");
+    }
+}
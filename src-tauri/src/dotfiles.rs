@@ -0,0 +1,413 @@
+use crate::jobs::{self, JobHandle};
+use crate::ssh::{shell_quote, SSHManager};
+use crate::types::{AppError, AppResult, JobKind};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub type SharedDotfilesStore = Arc<DotfilesStore>;
+
+// Largest single dotfile we'll push; anything bigger is probably not a dotfile
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+// Files under the local home directory, pushed to the same place under the remote home
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DotfileSet {
+    pub id: String,
+    pub name: String,
+    pub files: Vec<String>,
+    // The profiles (or group of profiles) the set is pushed to
+    #[serde(rename = "profileIds", default)]
+    pub profile_ids: Vec<String>,
+    #[serde(rename = "allProfiles", default)]
+    pub all_profiles: bool,
+    pub enabled: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DotfileSet {
+    fn applies_to(&self, profile_id: &str) -> bool {
+        self.enabled && (self.all_profiles || self.profile_ids.iter().any(|id| id == profile_id))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DotfileSetRequest {
+    pub name: String,
+    pub files: Vec<String>,
+    #[serde(rename = "profileIds", default)]
+    pub profile_ids: Vec<String>,
+    #[serde(rename = "allProfiles", default)]
+    pub all_profiles: bool,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DotfileSyncFile {
+    pub path: String,
+    pub uploaded: bool,
+    // Where the file it replaced was copied to, if there was one
+    pub backup: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DotfileSyncResult {
+    #[serde(rename = "setId")]
+    pub set_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub host: String,
+    pub files: Vec<DotfileSyncFile>,
+    #[serde(rename = "syncedAt")]
+    pub synced_at: DateTime<Utc>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct StoredDotfiles {
+    sets: Vec<DotfileSet>,
+    // Last successful push, by "<set id> <user@host:port>"
+    #[serde(default)]
+    synced: HashMap<String, DateTime<Utc>>,
+}
+
+pub struct DotfilesStore {
+    sets: DashMap<String, DotfileSet>,
+    synced: DashMap<String, DateTime<Utc>>,
+    storage_path: Option<PathBuf>,
+}
+
+impl DotfilesStore {
+    pub fn new() -> Self {
+        Self {
+            sets: DashMap::new(),
+            synced: DashMap::new(),
+            storage_path: None,
+        }
+    }
+
+    pub async fn load(storage_path: PathBuf) -> AppResult<Self> {
+        let mut store = Self::new();
+
+        if tokio::fs::try_exists(&storage_path).await? {
+            let content = tokio::fs::read_to_string(&storage_path).await?;
+            let stored: StoredDotfiles = serde_json::from_str(&content)?;
+            for set in stored.sets {
+                store.sets.insert(set.id.clone(), set);
+            }
+            for (key, synced_at) in stored.synced {
+                store.synced.insert(key, synced_at);
+            }
+        }
+
+        log::info!("Loaded {} dotfile sets from {:?}", store.sets.len(), storage_path);
+        store.storage_path = Some(storage_path);
+        Ok(store)
+    }
+
+    pub async fn create(&self, request: DotfileSetRequest) -> AppResult<DotfileSet> {
+        let files = validate_request(&request)?;
+        let now = Utc::now();
+        let set = DotfileSet {
+            id: Uuid::new_v4().to_string(),
+            name: request.name,
+            files,
+            profile_ids: request.profile_ids,
+            all_profiles: request.all_profiles,
+            enabled: request.enabled.unwrap_or(true),
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.sets.insert(set.id.clone(), set.clone());
+        self.save().await?;
+        Ok(set)
+    }
+
+    // A changed set counts as never pushed, so hosts pick it up on their next connect
+    pub async fn update(&self, set_id: &str, request: DotfileSetRequest) -> AppResult<DotfileSet> {
+        let files = validate_request(&request)?;
+        let mut set = self.get(set_id)
+            .ok_or_else(|| AppError::NotFound(format!("Dotfile set {}", set_id)))?;
+
+        set.name = request.name;
+        set.files = files;
+        set.profile_ids = request.profile_ids;
+        set.all_profiles = request.all_profiles;
+        set.enabled = request.enabled.unwrap_or(set.enabled);
+        set.updated_at = Utc::now();
+
+        self.sets.insert(set.id.clone(), set.clone());
+        self.save().await?;
+        Ok(set)
+    }
+
+    pub async fn delete(&self, set_id: &str) -> AppResult<bool> {
+        let removed = self.sets.remove(set_id).is_some();
+        if removed {
+            let prefix = format!("{} ", set_id);
+            self.synced.retain(|key, _| !key.starts_with(&prefix));
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    pub fn get(&self, set_id: &str) -> Option<DotfileSet> {
+        self.sets.get(set_id).map(|entry| entry.value().clone())
+    }
+
+    pub fn list(&self) -> Vec<DotfileSet> {
+        let mut sets: Vec<DotfileSet> = self.sets.iter().map(|entry| entry.value().clone()).collect();
+        sets.sort_by(|a, b| a.name.cmp(&b.name));
+        sets
+    }
+
+    // Sets for the profile that this host hasn't received since they last changed
+    pub fn pending(&self, profile_id: &str, host: &str) -> Vec<DotfileSet> {
+        self.sets.iter()
+            .filter(|entry| entry.applies_to(profile_id))
+            .filter(|entry| match self.synced.get(&sync_key(&entry.id, host)) {
+                Some(synced_at) => *synced_at < entry.updated_at,
+                None => true,
+            })
+            .map(|entry| entry.value().clone())
+            .collect()
+    }
+
+    // Push every pending set for the profile over an already connected session
+    pub async fn sync_pending(&self, ssh_manager: &SSHManager, home: &Path, profile_id: &str, session_id: &str) -> Vec<DotfileSyncResult> {
+        let host = match session_host(ssh_manager, session_id).await {
+            Ok(host) => host,
+            Err(e) => {
+                log::warn!("Skipping dotfile sync for session {}: {}", session_id, e);
+                return Vec::new();
+            }
+        };
+
+        let mut results = Vec::new();
+        for set in self.pending(profile_id, &host) {
+            match self.sync(ssh_manager, home, session_id, &set).await {
+                Ok(result) => results.push(result),
+                Err(e) => log::warn!("Dotfile set {} could not be pushed to {}: {}", set.name, host, e),
+            }
+        }
+        results
+    }
+
+    // Back up whatever the set would overwrite, then upload it. The host is only marked
+    // as synced when every file made it, so failures are retried on the next connect.
+    pub async fn sync(&self, ssh_manager: &SSHManager, home: &Path, session_id: &str, set: &DotfileSet) -> AppResult<DotfileSyncResult> {
+        let title = format!("Sync dotfiles {}", set.name);
+        jobs::run(JobKind::DotfileSync, title, Some(session_id.to_string()), |job| {
+            self.sync_files(ssh_manager, home, session_id, set, job)
+        }).await
+    }
+
+    // A cancel stops before the next upload; the files left out count as failed
+    async fn sync_files(&self, ssh_manager: &SSHManager, home: &Path, session_id: &str, set: &DotfileSet, job: JobHandle) -> AppResult<DotfileSyncResult> {
+        let host = session_host(ssh_manager, session_id).await?;
+        let synced_at = Utc::now();
+
+        let mut files = Vec::new();
+        let mut contents = Vec::new();
+        for path in &set.files {
+            match read_local(home, path).await {
+                Ok(data) => contents.push((path.clone(), data)),
+                Err(e) => files.push(DotfileSyncFile {
+                    path: path.clone(),
+                    uploaded: false,
+                    backup: None,
+                    error: Some(e.to_string()),
+                }),
+            }
+        }
+
+        let suffix = synced_at.format(".bak-%Y%m%d%H%M%S").to_string();
+        let paths: Vec<&str> = contents.iter().map(|(path, _)| path.as_str()).collect();
+        let backups = if paths.is_empty() {
+            Vec::new()
+        } else {
+            let result = ssh_manager.exec_command(session_id, &prepare_command(&paths, &suffix)).await?;
+            if result.exit_code != 0 {
+                return Err(AppError::OperationFailed(format!("Failed to back up dotfiles: {}", result.stderr.trim())));
+            }
+            result.stdout.lines().map(str::to_string).collect()
+        };
+
+        let total = set.files.len() as u64;
+        job.progress(files.len() as u64, Some(total));
+        for (path, data) in contents {
+            let backup = backups.contains(&path).then(|| format!("{}{}", path, suffix));
+            let error = match job.check() {
+                Ok(()) => ssh_manager.upload_file(session_id, &path, &data).await.err(),
+                Err(e) => Some(e),
+            }.map(|e| e.to_string());
+            files.push(DotfileSyncFile {
+                uploaded: error.is_none(),
+                path,
+                backup,
+                error,
+            });
+            job.progress(files.len() as u64, Some(total));
+        }
+
+        if files.iter().all(|file| file.uploaded) {
+            self.synced.insert(sync_key(&set.id, &host), synced_at);
+            self.save().await?;
+        }
+        log::info!("Pushed dotfile set {} to {}", set.name, host);
+
+        Ok(DotfileSyncResult {
+            set_id: set.id.clone(),
+            session_id: session_id.to_string(),
+            host,
+            files,
+            synced_at,
+        })
+    }
+
+    async fn save(&self) -> AppResult<()> {
+        let Some(path) = &self.storage_path else {
+            return Ok(());
+        };
+
+        let stored = StoredDotfiles {
+            sets: self.list(),
+            synced: self.synced.iter().map(|entry| (entry.key().clone(), *entry.value())).collect(),
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, serde_json::to_string_pretty(&stored)?).await?;
+        Ok(())
+    }
+}
+
+impl Default for DotfilesStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn sync_key(set_id: &str, host: &str) -> String {
+    format!("{} {}", set_id, host)
+}
+
+async fn session_host(ssh_manager: &SSHManager, session_id: &str) -> AppResult<String> {
+    let config = ssh_manager.get_session(session_id).await?.config;
+    Ok(format!("{}@{}:{}", config.username, config.hostname, config.port))
+}
+
+async fn read_local(home: &Path, path: &str) -> AppResult<Vec<u8>> {
+    let local = home.join(path);
+    let metadata = tokio::fs::metadata(&local).await
+        .map_err(|e| AppError::FileOperationFailed(format!("{}: {}", local.display(), e)))?;
+    if metadata.len() > MAX_FILE_BYTES {
+        return Err(AppError::ValidationError(format!("{} is larger than 1 MB", path)));
+    }
+    Ok(tokio::fs::read(&local).await?)
+}
+
+// Copy existing files aside (printing each one that was) and create missing parent
+// directories. Exec runs in the remote home, so relative paths line up.
+fn prepare_command(paths: &[&str], suffix: &str) -> String {
+    let mut script = String::new();
+    for path in paths {
+        let quoted = shell_quote(path);
+        script.push_str(&format!(
+            "if [ -f {path} ]; then cp -p {path} {backup} && echo {path} || exit 1; fi; ",
+            path = quoted,
+            backup = shell_quote(&format!("{}{}", path, suffix)),
+        ));
+        if let Some(parent) = Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            script.push_str(&format!("mkdir -p {} || exit 1; ", shell_quote(&parent.to_string_lossy())));
+        }
+    }
+    script.push_str("true");
+    script
+}
+
+// Paths must stay inside the home directory on both ends. "~/" is accepted and dropped.
+fn normalize_path(path: &str) -> AppResult<String> {
+    let path = path.trim();
+    let path = path.strip_prefix("~/").unwrap_or(path);
+    let valid = !path.is_empty()
+        && !path.starts_with('~')
+        && Path::new(path).components().all(|component| matches!(component, Component::Normal(_)));
+    if !valid {
+        return Err(AppError::ValidationError(format!("Dotfile paths must be relative to the home directory: {}", path)));
+    }
+    Ok(path.replace('\\', "/"))
+}
+
+fn validate_request(request: &DotfileSetRequest) -> AppResult<Vec<String>> {
+    if request.name.trim().is_empty() {
+        return Err(AppError::ValidationError("Dotfile set name cannot be empty".to_string()));
+    }
+    if request.files.is_empty() {
+        return Err(AppError::ValidationError("Dotfile set has no files".to_string()));
+    }
+    let mut files = Vec::new();
+    for file in &request.files {
+        let file = normalize_path(file)?;
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(files: &[&str]) -> DotfileSetRequest {
+        DotfileSetRequest {
+            name: "shell".to_string(),
+            files: files.iter().map(|file| file.to_string()).collect(),
+            profile_ids: vec!["web".to_string()],
+            all_profiles: false,
+            enabled: None,
+        }
+    }
+
+    #[test]
+    fn test_paths_stay_in_home() {
+        assert_eq!(normalize_path("~/.bashrc").unwrap(), ".bashrc");
+        assert_eq!(normalize_path(".config/nvim/init.vim").unwrap(), ".config/nvim/init.vim");
+        assert!(normalize_path("/etc/passwd").is_err());
+        assert!(normalize_path("../.bashrc").is_err());
+        assert!(normalize_path("~").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pending_until_synced_and_again_after_update() {
+        let store = DotfilesStore::new();
+        let set = store.create(request(&[".bashrc", "~/.bashrc", ".vimrc"])).await.unwrap();
+        assert_eq!(set.files, [".bashrc", ".vimrc"]);
+
+        let host = "ops@web-1:22";
+        assert_eq!(store.pending("web", host).len(), 1);
+        assert!(store.pending("db", host).is_empty());
+
+        store.synced.insert(sync_key(&set.id, host), Utc::now());
+        assert!(store.pending("web", host).is_empty());
+
+        store.update(&set.id, request(&[".bashrc", ".tmux.conf"])).await.unwrap();
+        assert_eq!(store.pending("web", host).len(), 1);
+    }
+
+    #[test]
+    fn test_prepare_command() {
+        let command = prepare_command(&[".bashrc", ".config/nvim/init.vim"], ".bak-20240506");
+        assert!(command.starts_with("if [ -f '.bashrc' ]; then cp -p '.bashrc' '.bashrc.bak-20240506' && echo '.bashrc'"));
+        assert!(command.contains("mkdir -p '.config/nvim'"));
+    }
+}
@@ -0,0 +1,51 @@
+use crate::recording::{RecordingConfig, RecordingManager};
+use crate::ssh::SSHManager;
+use crate::transfer::{SharedTransferManager, TransferManager};
+use crate::types::AppResult;
+use crate::SharedSSHManager;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+// The session engine on its own: SSH sessions, SFTP transfers and recordings, without the
+// Tauri app or the HTTP server. Build with default-features = false to embed just this.
+#[derive(Clone)]
+pub struct Engine {
+    pub ssh: SharedSSHManager,
+    pub transfers: SharedTransferManager,
+    pub recordings: Arc<RecordingManager>,
+}
+
+impl Engine {
+    pub async fn new(recording_config: RecordingConfig) -> AppResult<Self> {
+        let ssh: SharedSSHManager = Arc::new(RwLock::new(SSHManager::new()));
+        let transfers = Arc::new(RwLock::new(TransferManager::new(ssh.clone())));
+        let recordings = Arc::new(RecordingManager::new(recording_config).await?);
+        Ok(Self { ssh, transfers, recordings })
+    }
+
+    // Disconnect every session, closing its forwards and follows, cancel running transfers
+    // and stop the background cleanup tasks
+    pub async fn shutdown(&self) -> AppResult<()> {
+        self.ssh.read().await.graceful_shutdown().await?;
+        self.transfers.write().await.graceful_shutdown().await?;
+        self.recordings.stop_background_tasks();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_engine_without_app() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RecordingConfig {
+            storage_path: dir.path().join("recordings"),
+            ..RecordingConfig::default()
+        };
+        let engine = Engine::new(config).await.unwrap();
+        assert!(engine.ssh.read().await.list_sessions().await.is_empty());
+        engine.shutdown().await.unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+use crate::jobs;
+use crate::optimization::TaskManager;
+use crate::profiles::SharedProfileStore;
+use crate::types::{AppError, AppResult, CommandExecResult, JobKind};
+use crate::SharedSSHManager;
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+pub const DEFAULT_PARALLELISM: usize = 8;
+pub const MAX_PARALLELISM: usize = 32;
+pub const MAX_HOSTS: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupExecRequest {
+    // Stored profiles to run on; duplicates run once
+    #[serde(rename = "profileIds")]
+    pub profile_ids: Vec<String>,
+    pub command: String,
+    // Hosts connected at the same time, DEFAULT_PARALLELISM when unset
+    pub parallelism: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostExecResult {
+    #[serde(rename = "profileId")]
+    pub profile_id: String,
+    pub name: Option<String>,
+    pub hostname: Option<String>,
+    pub success: bool,
+    #[serde(rename = "exitCode")]
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    // Set when the host couldn't be reached or the command couldn't be started
+    pub error: Option<String>,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupExecResult {
+    pub command: String,
+    #[serde(rename = "startedAt")]
+    pub started_at: DateTime<Utc>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: DateTime<Utc>,
+    pub succeeded: usize,
+    pub failed: usize,
+    // In the order the profiles were requested
+    pub hosts: Vec<HostExecResult>,
+}
+
+impl GroupExecResult {
+    fn new(command: String, started_at: DateTime<Utc>, hosts: Vec<HostExecResult>) -> Self {
+        let succeeded = hosts.iter().filter(|host| host.success).count();
+        Self {
+            command,
+            started_at,
+            finished_at: Utc::now(),
+            succeeded,
+            failed: hosts.len() - succeeded,
+            hosts,
+        }
+    }
+}
+
+fn validate(request: &GroupExecRequest) -> AppResult<Vec<String>> {
+    if request.command.trim().is_empty() {
+        return Err(AppError::ValidationError("Command cannot be empty".to_string()));
+    }
+
+    let mut seen = HashSet::new();
+    let profile_ids: Vec<String> = request.profile_ids.iter()
+        .filter(|id| seen.insert(id.as_str()))
+        .cloned()
+        .collect();
+    if profile_ids.is_empty() {
+        return Err(AppError::ValidationError("Select at least one host".to_string()));
+    }
+    if profile_ids.len() > MAX_HOSTS {
+        return Err(AppError::ValidationError(format!("Cannot run on more than {} hosts at once", MAX_HOSTS)));
+    }
+    Ok(profile_ids)
+}
+
+// Run the command on every profile, at most `parallelism` hosts at a time. One host
+// failing never stops the others; each gets its own entry in the result. Cancelling the
+// job skips the hosts that haven't started yet.
+pub async fn run_on_group(
+    ssh_manager: &SharedSSHManager,
+    profiles: &SharedProfileStore,
+    request: GroupExecRequest,
+) -> AppResult<GroupExecResult> {
+    let profile_ids = validate(&request)?;
+    let parallelism = request.parallelism.unwrap_or(DEFAULT_PARALLELISM).clamp(1, MAX_PARALLELISM);
+    let title = format!("{} on {} hosts", request.command, profile_ids.len());
+    jobs::run(JobKind::GroupExec, title, None, |job| async move {
+        let tasks = TaskManager::new(parallelism);
+        let started_at = Utc::now();
+        let total = profile_ids.len() as u64;
+        let done = AtomicU64::new(0);
+        job.progress(0, Some(total));
+        log::info!("Running command on {} hosts, {} at a time", total, parallelism);
+
+        let runs = profile_ids.into_iter().map(|profile_id| {
+            let ssh_manager = ssh_manager.clone();
+            let profiles = profiles.clone();
+            let command = request.command.clone();
+            let task_id = format!("group-exec-{}", profile_id);
+            let failed_id = profile_id.clone();
+            let skip = job.clone();
+            let (job, done) = (&job, &done);
+
+            let run = tasks.spawn_task(task_id, "group_exec".to_string(), async move {
+                if skip.is_cancelled() {
+                    return unreached(profile_id, "Cancelled before it started".to_string());
+                }
+                run_on_host(&ssh_manager, &profiles, profile_id, &command).await
+            });
+            async move {
+                let host = run.await.unwrap_or_else(|e| unreached(failed_id, e));
+                job.progress(done.fetch_add(1, Ordering::Relaxed) + 1, Some(total));
+                host
+            }
+        });
+        let hosts = join_all(runs).await;
+
+        let result = GroupExecResult::new(request.command, started_at, hosts);
+        log::info!("Group command finished: {} succeeded, {} failed", result.succeeded, result.failed);
+        Ok(result)
+    }).await
+}
+
+fn unreached(profile_id: String, error: String) -> HostExecResult {
+    HostExecResult {
+        profile_id,
+        name: None,
+        hostname: None,
+        success: false,
+        exit_code: None,
+        stdout: String::new(),
+        stderr: String::new(),
+        error: Some(error),
+        duration_ms: 0,
+    }
+}
+
+async fn run_on_host(
+    ssh_manager: &SharedSSHManager,
+    profiles: &SharedProfileStore,
+    profile_id: String,
+    command: &str,
+) -> HostExecResult {
+    let started = Instant::now();
+    let profile = profiles.get(&profile_id);
+    let outcome: AppResult<CommandExecResult> = async {
+        let config = profiles.connect_config(&profile_id).await?;
+        let (_, result) = ssh_manager.read().await.exec_once(config, command).await?;
+        Ok(result)
+    }.await;
+
+    let mut host = HostExecResult {
+        profile_id,
+        name: profile.as_ref().map(|profile| profile.name.clone()),
+        hostname: profile.map(|profile| profile.hostname),
+        success: false,
+        exit_code: None,
+        stdout: String::new(),
+        stderr: String::new(),
+        error: None,
+        duration_ms: 0,
+    };
+    match outcome {
+        Ok(result) => {
+            host.success = result.exit_code == 0;
+            host.exit_code = Some(result.exit_code);
+            host.stdout = result.stdout;
+            host.stderr = result.stderr;
+        }
+        Err(e) => {
+            log::warn!("Group command on {} failed: {}", host.hostname.as_deref().unwrap_or(&host.profile_id), e);
+            host.error = Some(e.to_string());
+        }
+    }
+    host.duration_ms = started.elapsed().as_millis() as u64;
+    host
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::CredentialStore;
+    use crate::profiles::ProfileStore;
+    use crate::ssh::SSHManager;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn request(profile_ids: &[&str], command: &str) -> GroupExecRequest {
+        GroupExecRequest {
+            profile_ids: profile_ids.iter().map(|id| id.to_string()).collect(),
+            command: command.to_string(),
+            parallelism: Some(2),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(validate(&request(&["a", "b", "a"], "uptime")).unwrap(), vec!["a", "b"]);
+        assert!(validate(&request(&[], "uptime")).is_err());
+        assert!(validate(&request(&["a"], "  ")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_hosts_are_reported_per_host() {
+        let ssh_manager = Arc::new(RwLock::new(SSHManager::new()));
+        let profiles = Arc::new(ProfileStore::new(Arc::new(CredentialStore::new())));
+
+        let result = run_on_group(&ssh_manager, &profiles, request(&["missing-1", "missing-2", "missing-3"], "uptime"))
+            .await
+            .unwrap();
+        assert_eq!((result.succeeded, result.failed), (0, 3));
+        let ids: Vec<&str> = result.hosts.iter().map(|host| host.profile_id.as_str()).collect();
+        assert_eq!(ids, vec!["missing-1", "missing-2", "missing-3"]);
+        assert!(result.hosts.iter().all(|host| host.error.as_deref().is_some_and(|e| e.contains("missing"))));
+    }
+}
@@ -0,0 +1,175 @@
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    En,
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+}
+
+impl Language {
+    // BCP 47 tags ("zh-CN", "en_US") and POSIX locales ("zh_CN.UTF-8") alike
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.trim()
+            .split(['-', '_', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        match primary.as_str() {
+            "en" | "c" | "posix" => Some(Self::En),
+            "zh" => Some(Self::ZhCn),
+            _ => None,
+        }
+    }
+
+    // Pick the best supported language from an Accept-Language header
+    pub fn negotiate(accept_language: &str) -> Self {
+        let mut candidates: Vec<(f32, Self)> = accept_language.split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let language = Self::parse(parts.next()?)?;
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (quality > 0.0).then_some((quality, language))
+            })
+            .collect();
+        // Stable sort keeps header order for equal weights
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        candidates.first().map_or(Self::default(), |(_, language)| *language)
+    }
+
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::parse(&value))
+            .unwrap_or_default()
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::ZhCn => "zh-CN",
+        }
+    }
+}
+
+// Language of the current HTTP request, when the server negotiated one
+tokio::task_local! {
+    static REQUEST_LANGUAGE: Language;
+}
+
+// App-wide choice, used by Tauri commands and anything outside a request
+static PREFERRED: RwLock<Option<Language>> = RwLock::new(None);
+
+pub fn preferred() -> Language {
+    PREFERRED.read().unwrap_or_else(Language::from_env)
+}
+
+pub fn set_preferred(language: Language) {
+    *PREFERRED.write() = Some(language);
+}
+
+pub fn current() -> Language {
+    REQUEST_LANGUAGE.try_with(|language| *language).unwrap_or_else(|_| preferred())
+}
+
+pub async fn scope<F: Future>(language: Language, future: F) -> F::Output {
+    REQUEST_LANGUAGE.scope(language, future).await
+}
+
+// (key, English, Simplified Chinese). Error titles are keyed by AppError::error_code.
+const CATALOG: &[(&str, &str, &str)] = &[
+    ("CONNECTION_FAILED", "SSH connection failed", "SSH 连接失败"),
+    ("AUTH_FAILED", "SSH authentication failed", "SSH 认证失败"),
+    ("SESSION_NOT_FOUND", "Session not found", "会话不存在"),
+    ("HOST_KEY_UNKNOWN", "Unknown host key", "未知的主机密钥"),
+    ("HOST_KEY_MISMATCH", "Host key mismatch", "主机密钥不匹配"),
+    ("INVALID_CONFIG", "Invalid configuration", "配置无效"),
+    ("FILE_OPERATION_FAILED", "File operation failed", "文件操作失败"),
+    ("WEBSOCKET_ERROR", "WebSocket error", "WebSocket 错误"),
+    ("TRANSFER_ERROR", "Transfer error", "传输错误"),
+    ("PERMISSION_DENIED", "Permission denied", "权限不足"),
+    ("RESOURCE_EXHAUSTED", "Resource exhausted", "资源已耗尽"),
+    ("TIMEOUT_ERROR", "Timeout error", "操作超时"),
+    ("VALIDATION_ERROR", "Validation error", "参数校验失败"),
+    ("INTERNAL_ERROR", "Internal server error", "内部服务器错误"),
+    ("OPERATION_FAILED", "Operation failed", "操作失败"),
+    ("NOT_FOUND", "Not found", "未找到"),
+    ("IO_ERROR", "IO error", "IO 错误"),
+    ("SSH2_ERROR", "SSH2 error", "SSH2 错误"),
+    ("SERIALIZATION_ERROR", "Serialization error", "序列化错误"),
+
+    ("mobile.compact_layout", "Consider using compact terminal layout for small screens", "小屏幕建议使用紧凑的终端布局"),
+    ("mobile.tablet", "Tablet detected: enabling split-screen optimizations", "检测到平板设备：已启用分屏优化"),
+    ("mobile.platform", "Mobile platform detected: enabling battery optimizations", "检测到移动平台：已启用省电优化"),
+    ("mobile.high_dpi", "High DPI display detected: optimizing for crisp text rendering", "检测到高 DPI 屏幕：已优化文字渲染清晰度"),
+    ("mobile.keyboard", "Non-touch device: optimizing for keyboard navigation", "非触屏设备：已优化键盘导航"),
+    ("mobile.touch", "Touch device: enabling gesture controls", "触屏设备：已启用手势控制"),
+    ("mobile.data_saver", "Metered connection: send mobile_optimize with dataSaver to cut terminal traffic", "按流量计费的网络：发送带 dataSaver 的 mobile_optimize 以减少终端流量"),
+    ("mobile.small_screen", "Small screen detected: reducing animations for better performance", "检测到小屏幕：已减少动画以提升性能"),
+
+    ("performance.connections", "High connection count detected. Consider implementing connection pooling.", "连接数过高，建议使用连接池。"),
+    ("performance.tasks", "High task count detected. Consider task queuing or rate limiting.", "任务数过高，建议使用任务队列或限流。"),
+    ("performance.memory", "High memory usage detected. Consider implementing memory cleanup.", "内存占用过高，建议进行内存清理。"),
+    ("performance.optimal", "System performance is optimal.", "系统性能良好。"),
+
+    ("security.critical_events", "Critical security events detected in the last 24 hours. Review security logs immediately.", "过去 24 小时内检测到严重安全事件，请立即检查安全日志。"),
+    ("security.locked_accounts", "{count} accounts are currently locked due to failed login attempts.", "有 {count} 个账户因登录失败次数过多被锁定。"),
+    ("security.rate_limits", "High number of rate-limited IPs detected. Consider reviewing access patterns.", "被限流的 IP 数量较多，建议检查访问模式。"),
+    ("security.connections", "High number of active connections. Monitor for potential DDoS activity.", "活动连接数过高，请留意可能的 DDoS 攻击。"),
+    ("security.event_volume", "High security event volume in the last hour. Review for suspicious activity.", "过去一小时安全事件数量较多，请检查可疑活动。"),
+    ("security.normal", "Security status is normal. No immediate action required.", "安全状态正常，无需立即处理。"),
+];
+
+// Catalog entry for the key, falling back to English and then to the key itself
+pub fn text(key: &str, language: Language) -> &str {
+    CATALOG.iter()
+        .find(|(entry, _, _)| *entry == key)
+        .map_or(key, |(_, en, zh)| match language {
+            Language::En => en,
+            Language::ZhCn => zh,
+        })
+}
+
+// Catalog entry with `{name}` placeholders filled in
+pub fn format(key: &str, language: Language, args: &[(&str, String)]) -> String {
+    args.iter().fold(text(key, language).to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Language::negotiate("zh-CN,zh;q=0.9,en;q=0.8"), Language::ZhCn);
+        assert_eq!(Language::negotiate("fr-FR, en;q=0.5, zh;q=0.4"), Language::En);
+        assert_eq!(Language::negotiate("de, fr"), Language::En);
+        assert_eq!(Language::negotiate("en;q=0, zh-TW"), Language::ZhCn);
+        assert_eq!(Language::parse("zh_CN.UTF-8"), Some(Language::ZhCn));
+    }
+
+    #[test]
+    fn test_catalog_lookup() {
+        assert_eq!(text("AUTH_FAILED", Language::ZhCn), "SSH 认证失败");
+        assert_eq!(text("missing.key", Language::ZhCn), "missing.key");
+        assert_eq!(
+            format("security.locked_accounts", Language::En, &[("count", "3".to_string())]),
+            "3 accounts are currently locked due to failed login attempts."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_language_overrides_preference() {
+        assert_eq!(scope(Language::ZhCn, async { current() }).await, Language::ZhCn);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::recording::{RecordingManager, RecordingMetadata};
+use crate::types::{AppError, AppResult, FileTransfer, Job, JobKind, JobProgress, JobStatus, TransferDirection, TransferStatus};
+use chrono::{Duration, Utc};
+use dashmap::DashMap;
+use std::cmp::Reverse;
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+// Long-running work of every subsystem, listed in one place. Transfers and recordings keep
+// their own records and are converted on the way out; everything else runs through run()
+// and is kept here while it runs and for a while after.
+
+// Finished jobs stay listed this long, so a client polling now and then still sees how they ended
+const FINISHED_RETENTION_MINUTES: i64 = 30;
+
+struct RegisteredJob {
+    job: Job,
+    cancel: CancellationToken,
+}
+
+static JOBS: OnceLock<DashMap<String, RegisteredJob>> = OnceLock::new();
+
+fn jobs() -> &'static DashMap<String, RegisteredJob> {
+    JOBS.get_or_init(DashMap::new)
+}
+
+// Given to the work of a job to report progress and notice a cancel. Cancelling is
+// cooperative: the work stops at its next check, so nothing is left half cleaned up.
+#[derive(Clone)]
+pub struct JobHandle {
+    id: String,
+    cancel: CancellationToken,
+}
+
+impl JobHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn progress(&self, done: u64, total: Option<u64>) {
+        if let Some(mut entry) = jobs().get_mut(&self.id) {
+            entry.job.progress = Some(JobProgress { done, total });
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    // For progress callbacks whose error ends the work
+    pub fn check(&self) -> AppResult<()> {
+        if self.is_cancelled() {
+            Err(AppError::OperationFailed("Job cancelled".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Marks a job whose future was dropped before it finished
+struct Registration(String);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Some(mut entry) = jobs().get_mut(&self.0) {
+            if entry.job.finished_at.is_none() {
+                entry.job.status = JobStatus::Cancelled;
+                entry.job.finished_at = Some(Utc::now());
+            }
+        }
+    }
+}
+
+// Scheduled commands run as a single remote exec with nothing to stop between steps
+fn cancellable(kind: JobKind) -> bool {
+    !matches!(kind, JobKind::ScheduledCommand)
+}
+
+// Run work as a listed job. It ends up cancelled when a cancel came in while it ran, even if
+// it returned what it had done so far.
+pub async fn run<T, F, Fut>(kind: JobKind, title: String, session_id: Option<String>, work: F) -> AppResult<T>
+where
+    F: FnOnce(JobHandle) -> Fut,
+    Fut: Future<Output = AppResult<T>>,
+{
+    prune();
+    let handle = JobHandle {
+        id: Uuid::new_v4().to_string(),
+        cancel: CancellationToken::new(),
+    };
+    jobs().insert(handle.id.clone(), RegisteredJob {
+        job: Job {
+            id: handle.id.clone(),
+            kind,
+            title,
+            status: JobStatus::Running,
+            progress: None,
+            session_id,
+            started_at: Utc::now(),
+            finished_at: None,
+            error: None,
+            cancellable: cancellable(kind),
+        },
+        cancel: handle.cancel.clone(),
+    });
+    let registration = Registration(handle.id.clone());
+
+    let result = work(handle.clone()).await;
+
+    if let Some(mut entry) = jobs().get_mut(&registration.0) {
+        entry.job.finished_at = Some(Utc::now());
+        entry.job.status = if handle.is_cancelled() {
+            JobStatus::Cancelled
+        } else if let Err(e) = &result {
+            entry.job.error = Some(e.to_string());
+            JobStatus::Failed
+        } else {
+            JobStatus::Completed
+        };
+    }
+    result
+}
+
+fn prune() {
+    let cutoff = Utc::now() - Duration::minutes(FINISHED_RETENTION_MINUTES);
+    jobs().retain(|_, entry| entry.job.finished_at.map_or(true, |finished| finished > cutoff));
+}
+
+// Jobs run through run(), newest first
+pub fn registered() -> Vec<Job> {
+    prune();
+    let mut listed: Vec<Job> = jobs().iter().map(|entry| entry.job.clone()).collect();
+    listed.sort_by_key(|job| Reverse(job.started_at));
+    listed
+}
+
+// Every job the given subsystems know of, newest first
+pub fn list(transfers: &[FileTransfer], recordings: &RecordingManager) -> Vec<Job> {
+    let mut listed = registered();
+    listed.extend(transfers.iter().map(from_transfer));
+    listed.extend(recordings.active().iter().map(from_recording));
+    listed.sort_by_key(|job| Reverse(job.started_at));
+    listed
+}
+
+// Ask a job run through run() to stop. None when there's no such job.
+pub fn cancel(job_id: &str) -> AppResult<Option<Job>> {
+    let Some(entry) = jobs().get(job_id) else {
+        return Ok(None);
+    };
+    if !entry.job.cancellable {
+        return Err(AppError::ValidationError(format!("Job {} cannot be cancelled", entry.job.title)));
+    }
+    if entry.job.finished_at.is_none() {
+        entry.cancel.cancel();
+    }
+    Ok(Some(entry.job.clone()))
+}
+
+// Stop a job run through run() or an active recording, which keeps what it has recorded.
+// Transfers are cancelled through their manager.
+pub async fn cancel_or_stop(job_id: &str, recordings: &RecordingManager) -> AppResult<Job> {
+    if let Some(job) = cancel(job_id)? {
+        return Ok(job);
+    }
+    let recording = recordings.active().into_iter()
+        .find(|recording| recording.recording_id == job_id)
+        .ok_or_else(|| AppError::NotFound(format!("Job {}", job_id)))?;
+    let stopped = recordings.stop_recording(&recording.session_id).await?.unwrap_or(recording);
+    Ok(from_recording(&stopped))
+}
+
+pub fn from_transfer(transfer: &FileTransfer) -> Job {
+    let status = match transfer.status {
+        TransferStatus::Pending => JobStatus::Queued,
+        TransferStatus::InProgress => JobStatus::Running,
+        TransferStatus::Paused => JobStatus::Paused,
+        TransferStatus::Completed => JobStatus::Completed,
+        TransferStatus::Failed => JobStatus::Failed,
+        TransferStatus::Cancelled => JobStatus::Cancelled,
+    };
+    let verb = match transfer.direction {
+        TransferDirection::Upload => "Upload",
+        TransferDirection::Download => "Download",
+    };
+    Job {
+        id: transfer.id.clone(),
+        kind: JobKind::Transfer,
+        title: format!("{} {}", verb, transfer.name),
+        cancellable: matches!(status, JobStatus::Queued | JobStatus::Running | JobStatus::Paused),
+        status,
+        progress: Some(JobProgress {
+            done: transfer.transferred,
+            total: (transfer.size > 0).then_some(transfer.size),
+        }),
+        session_id: Some(transfer.session_id.clone()),
+        started_at: transfer.start_time,
+        finished_at: transfer.end_time,
+        error: transfer.error.clone(),
+    }
+}
+
+pub fn from_recording(recording: &RecordingMetadata) -> Job {
+    let running = recording.end_time.is_none();
+    Job {
+        id: recording.recording_id.clone(),
+        kind: JobKind::Recording,
+        title: format!("Recording {}", recording.hostname),
+        status: if running { JobStatus::Running } else { JobStatus::Completed },
+        progress: None,
+        session_id: Some(recording.session_id.clone()),
+        started_at: recording.start_time,
+        finished_at: recording.end_time,
+        error: None,
+        cancellable: running,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed(id: &str) -> Job {
+        registered().into_iter().find(|job| job.id == id).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cancel_is_seen_by_the_work() {
+        let (started, ready) = tokio::sync::oneshot::channel();
+        let (cancelled, wait) = tokio::sync::oneshot::channel::<()>();
+        let running = tokio::spawn(run(JobKind::GroupExec, "uptime on 3 hosts".to_string(), None, |job| async move {
+            job.progress(1, Some(3));
+            started.send(job.id().to_string()).unwrap();
+            let _ = wait.await;
+            job.check()?;
+            Ok(())
+        }));
+
+        let id = ready.await.unwrap();
+        let job = listed(&id);
+        assert_eq!(job.status, JobStatus::Running);
+        assert_eq!(job.progress.map(|progress| progress.done), Some(1));
+
+        cancel(&id).unwrap().unwrap();
+        cancelled.send(()).unwrap();
+        assert!(running.await.unwrap().is_err());
+        let job = listed(&id);
+        assert_eq!(job.status, JobStatus::Cancelled);
+        assert!(job.finished_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_commands_are_not_cancellable() {
+        let (started, ready) = tokio::sync::oneshot::channel();
+        let (done, wait) = tokio::sync::oneshot::channel::<()>();
+        let running = tokio::spawn(run(JobKind::ScheduledCommand, "Scheduled job backup".to_string(), None, |job| async move {
+            started.send(job.id().to_string()).unwrap();
+            let _ = wait.await;
+            Err::<(), _>(AppError::OperationFailed("exit 1".to_string()))
+        }));
+
+        let id = ready.await.unwrap();
+        assert!(cancel(&id).is_err());
+        assert!(cancel("no-such-job").unwrap().is_none());
+
+        done.send(()).unwrap();
+        assert!(running.await.unwrap().is_err());
+        let job = listed(&id);
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(job.error.as_deref(), Some("Operation failed: exit 1"));
+    }
+}
@@ -1,55 +1,50 @@
 pub mod types;
+pub mod config;
+pub mod group_exec;
+pub mod i18n;
 pub mod ssh;
+#[cfg(feature = "server")]
 pub mod websocket;
+#[cfg(feature = "server")]
 pub mod server;
 pub mod transfer;
 pub mod performance;
+pub mod playback;
 pub mod logging;
 pub mod optimization;
 pub mod security;
 pub mod recording;
+pub mod reporting;
+pub mod clipboard;
+pub mod credentials;
+pub mod diagnostics;
+pub mod discovery;
+pub mod dotfiles;
+pub mod profiles;
+pub mod notifications;
+pub mod output_stream;
+pub mod plugins;
+pub mod proxy;
+pub mod retry;
+pub mod background;
+pub mod jobs;
+pub mod scheduler;
+pub mod scripting;
+pub mod webhooks;
+pub mod workspaces;
+pub mod engine;
+#[cfg(feature = "desktop")]
 pub mod commands;
+#[cfg(feature = "desktop")]
+mod app;
 
 use ssh::SSHManager;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+#[cfg(feature = "desktop")]
+pub use app::run;
+pub use engine::Engine;
+
 // Global state for SSH manager
 pub type SharedSSHManager = Arc<RwLock<SSHManager>>;
-
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-  // Initialize SSH manager
-  let ssh_manager: SharedSSHManager = Arc::new(RwLock::new(SSHManager::new()));
-
-  tauri::Builder::default()
-    .manage(ssh_manager)
-    .setup(|app| {
-      if cfg!(debug_assertions) {
-        app.handle().plugin(
-          tauri_plugin_log::Builder::default()
-            .level(log::LevelFilter::Info)
-            .build(),
-        )?;
-      }
-
-      log::info!("WebTerminal Pro starting up...");
-      Ok(())
-    })
-    .invoke_handler(tauri::generate_handler![
-      commands::ssh_create_session,
-      commands::ssh_connect,
-      commands::ssh_disconnect,
-      commands::ssh_create_shell,
-      commands::ssh_write_to_shell,
-      commands::ssh_resize_shell,
-      commands::ssh_list_sessions,
-      commands::sftp_create_session,
-      commands::sftp_list_directory,
-      commands::sftp_download_file,
-      commands::sftp_upload_file,
-      commands::get_autocomplete_suggestions,
-    ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
-}
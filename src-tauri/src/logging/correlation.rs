@@ -0,0 +1,79 @@
+use std::future::Future;
+use uuid::Uuid;
+
+// Header used to pass an ID in from HTTP clients and to return it on responses
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+pub fn new_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+// Accept a caller-supplied ID only if it is short and printable, so it can't forge log lines
+pub fn sanitize(id: &str) -> Option<String> {
+    let id = id.trim();
+    let valid = !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    valid.then(|| id.to_string())
+}
+
+// The ID of the user action the current task is working on
+pub fn current() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
+
+// Run a future with every log entry it writes tagged with the given ID
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    CORRELATION_ID.scope(id, future).await
+}
+
+// Task-locals don't follow tokio::spawn or spawn_blocking, so spawned work captures the ID first
+pub fn propagate<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let id = current();
+    async move {
+        match id {
+            Some(id) => scope(id, future).await,
+            None => future.await,
+        }
+    }
+}
+
+pub fn propagate_blocking<R>(f: impl FnOnce() -> R) -> impl FnOnce() -> R {
+    let id = current();
+    move || match id {
+        Some(id) => CORRELATION_ID.sync_scope(id, f),
+        None => f(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_id_follows_spawned_work() {
+        assert_eq!(current(), None);
+
+        let (in_task, in_blocking) = scope("req-1".to_string(), async {
+            let task = tokio::spawn(propagate(async { current() }));
+            let blocking = tokio::task::spawn_blocking(propagate_blocking(current));
+            (task.await.unwrap(), blocking.await.unwrap())
+        }).await;
+
+        assert_eq!(in_task.as_deref(), Some("req-1"));
+        assert_eq!(in_blocking.as_deref(), Some("req-1"));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize(" abc-123 ").as_deref(), Some("abc-123"));
+        assert_eq!(sanitize("line\nbreak"), None);
+        assert_eq!(sanitize(&"a".repeat(65)), None);
+        assert_eq!(sanitize(""), None);
+    }
+}
@@ -0,0 +1,386 @@
+use super::syslog::{LogForwarder, LogForwarding};
+use chrono::{DateTime, Local, Utc};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+
+const DEFAULT_FILE_NAME: &str = "webterminal.log";
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 10;
+
+static LOGGER: OnceLock<FileLogger> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RotationPeriod {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl RotationPeriod {
+    // Files written in the same period share this key
+    fn period_key(self, time: DateTime<Local>) -> String {
+        match self {
+            RotationPeriod::Never => String::new(),
+            RotationPeriod::Hourly => time.format("%Y%m%d%H").to_string(),
+            RotationPeriod::Daily => time.format("%Y%m%d").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileLogConfig {
+    pub directory: PathBuf,
+    pub file_name: String,
+    pub max_file_size: u64,
+    pub rotation: RotationPeriod,
+    // Rotated files kept besides the active one
+    pub max_files: usize,
+    pub max_age_days: Option<u32>,
+    pub level: log::LevelFilter,
+    // Also write plain lines to stderr, for running the server in a terminal
+    pub echo_stderr: bool,
+    // Copy every entry to syslog or journald, for installs managed by systemd
+    pub forward: Option<LogForwarding>,
+}
+
+impl FileLogConfig {
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            file_name: DEFAULT_FILE_NAME.to_string(),
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            rotation: RotationPeriod::Daily,
+            max_files: DEFAULT_MAX_FILES,
+            max_age_days: Some(30),
+            level: log::LevelFilter::Info,
+            echo_stderr: false,
+            forward: None,
+        }
+    }
+
+    // Overrides from WEBTERMINAL_LOG_* environment variables, used by the standalone server
+    pub fn from_env(default_directory: PathBuf) -> Self {
+        let mut config = Self::new(
+            std::env::var_os("WEBTERMINAL_LOG_DIR").map(PathBuf::from).unwrap_or(default_directory),
+        );
+        if let Some(size) = env_number("WEBTERMINAL_LOG_MAX_SIZE_MB") {
+            config.max_file_size = size * 1024 * 1024;
+        }
+        if let Some(files) = env_number("WEBTERMINAL_LOG_MAX_FILES") {
+            config.max_files = files as usize;
+        }
+        if let Ok(forward) = std::env::var("WEBTERMINAL_LOG_FORWARD") {
+            config.forward = LogForwarding::parse(&forward);
+            if config.forward.is_none() {
+                eprintln!("Ignoring unknown WEBTERMINAL_LOG_FORWARD value: {}", forward);
+            }
+        }
+        if let Some(level) = std::env::var("WEBTERMINAL_LOG_LEVEL").ok().and_then(|level| level.parse().ok()) {
+            config.level = level;
+        }
+        config
+    }
+
+    fn stem(&self) -> &str {
+        self.file_name.strip_suffix(".log").unwrap_or(&self.file_name)
+    }
+}
+
+fn env_number(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+struct ActiveFile {
+    file: File,
+    size: u64,
+    period: String,
+}
+
+// Appends JSON lines to a file and rotates it by size and time
+pub struct RotatingFileWriter {
+    config: FileLogConfig,
+    active: Mutex<Option<ActiveFile>>,
+}
+
+impl RotatingFileWriter {
+    pub fn new(config: FileLogConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        let writer = Self {
+            config,
+            active: Mutex::new(None),
+        };
+        writer.prune()?;
+        Ok(writer)
+    }
+
+    pub fn active_path(&self) -> PathBuf {
+        self.config.directory.join(&self.config.file_name)
+    }
+
+    pub fn write_line(&self, line: &str) -> io::Result<()> {
+        let now = Local::now();
+        let period = self.config.rotation.period_key(now);
+        let mut active = self.active.lock();
+
+        let needs_rotation = active.as_ref().is_some_and(|file| {
+            file.period != period || (file.size > 0 && file.size + line.len() as u64 + 1 > self.config.max_file_size)
+        });
+        if needs_rotation {
+            *active = None;
+            self.rotate(now)?;
+        }
+
+        if active.is_none() {
+            *active = Some(self.open(period)?);
+        }
+        let Some(file) = active.as_mut() else {
+            return Ok(());
+        };
+
+        file.file.write_all(line.as_bytes())?;
+        file.file.write_all(b"\n")?;
+        file.size += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        match self.active.lock().as_mut() {
+            Some(file) => file.file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    // Rotated files, oldest first
+    pub fn rotated_files(&self) -> io::Result<Vec<PathBuf>> {
+        let prefix = format!("{}-", self.config.stem());
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.config.directory)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(".log"))
+            })
+            .collect();
+        // Names embed the rotation timestamp, so lexical order is chronological
+        files.sort();
+        Ok(files)
+    }
+
+    fn open(&self, period: String) -> io::Result<ActiveFile> {
+        let path = self.active_path();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+
+        // A file left over from an earlier period is rotated before writing to it
+        let modified_period = metadata.modified().ok()
+            .map(|modified| self.config.rotation.period_key(DateTime::<Local>::from(modified)));
+        if metadata.len() > 0 && modified_period.is_some_and(|modified| modified != period) {
+            drop(file);
+            self.rotate(Local::now())?;
+            return self.open(period);
+        }
+
+        Ok(ActiveFile {
+            file,
+            size: metadata.len(),
+            period,
+        })
+    }
+
+    fn rotate(&self, now: DateTime<Local>) -> io::Result<()> {
+        let path = self.active_path();
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let timestamp = now.format("%Y%m%d-%H%M%S");
+        let mut target = self.config.directory.join(format!("{}-{}.log", self.config.stem(), timestamp));
+        let mut suffix = 1;
+        while target.exists() {
+            target = self.config.directory.join(format!("{}-{}-{}.log", self.config.stem(), timestamp, suffix));
+            suffix += 1;
+        }
+
+        fs::rename(&path, &target)?;
+        self.prune()
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let mut files = self.rotated_files()?;
+
+        if let Some(days) = self.config.max_age_days {
+            let cutoff = std::time::SystemTime::now() - std::time::Duration::from_secs(u64::from(days) * 86_400);
+            files.retain(|path| {
+                let expired = fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .is_ok_and(|modified| modified < cutoff);
+                if expired {
+                    let _ = fs::remove_file(path);
+                }
+                !expired
+            });
+        }
+
+        let excess = files.len().saturating_sub(self.config.max_files);
+        for path in &files[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+// log::Log implementation that writes every record as a JSON line.
+// StructuredLogger output is already JSON and is merged into the line instead of nested.
+pub struct FileLogger {
+    writer: RotatingFileWriter,
+    echo_stderr: bool,
+    forwarder: Option<LogForwarder>,
+    // Every written line, for live tailing
+    lines: broadcast::Sender<String>,
+}
+
+impl FileLogger {
+    pub fn writer(&self) -> &RotatingFileWriter {
+        &self.writer
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.lines.subscribe()
+    }
+}
+
+impl log::Log for FileLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        super::filter::enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let entry = record_entry(record.level(), record.target(), &record.args().to_string(), Utc::now());
+        if let Some(forwarder) = &self.forwarder {
+            // Reporting a failed forward through the logger would recurse
+            let _ = forwarder.forward(record.level(), &entry);
+        }
+
+        let line = entry.to_string();
+        if let Err(e) = self.writer.write_line(&line) {
+            eprintln!("Failed to write log file: {}", e);
+        }
+        let _ = self.lines.send(line);
+        if self.echo_stderr {
+            eprintln!("[{} {} {}] {}", Utc::now().to_rfc3339(), record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.writer.flush();
+    }
+}
+
+pub fn format_record(level: log::Level, target: &str, message: &str, timestamp: DateTime<Utc>) -> String {
+    record_entry(level, target, message, timestamp).to_string()
+}
+
+fn record_entry(level: log::Level, target: &str, message: &str, timestamp: DateTime<Utc>) -> Value {
+    let mut line = match serde_json::from_str::<Value>(message) {
+        Ok(Value::Object(fields)) => Value::Object(fields),
+        _ => json!({ "message": message }),
+    };
+    super::redact::redact_value(&mut line);
+    line["level"] = json!(level.as_str().to_lowercase());
+    line["target"] = json!(target);
+    if line.get("timestamp").is_none() {
+        line["timestamp"] = json!(timestamp.to_rfc3339());
+    }
+    if line.get("correlation_id").is_none() {
+        if let Some(id) = super::correlation::current() {
+            line["correlation_id"] = json!(id);
+        }
+    }
+    line
+}
+
+// The installed file logger, if file logging is enabled
+pub fn logger() -> Option<&'static FileLogger> {
+    LOGGER.get()
+}
+
+// Install the file logger as the global logger. Fails if another logger is already set.
+pub fn init(config: FileLogConfig) -> io::Result<&'static FileLogger> {
+    let level = config.level;
+    let echo_stderr = config.echo_stderr;
+    let forwarder = config.forward.clone().and_then(|forward| match LogForwarder::new(forward) {
+        Ok(forwarder) => Some(forwarder),
+        Err(e) => {
+            eprintln!("Failed to set up log forwarding: {}", e);
+            None
+        }
+    });
+    let writer = RotatingFileWriter::new(config)?;
+
+    let logger = LOGGER.get_or_init(|| FileLogger {
+        writer,
+        echo_stderr,
+        forwarder,
+        lines: broadcast::channel(1024).0,
+    });
+    log::set_logger(logger)
+        .map_err(|e| io::Error::new(io::ErrorKind::AlreadyExists, e.to_string()))?;
+    super::filter::set_default_level(level);
+    Ok(logger)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn config(dir: &Path) -> FileLogConfig {
+        FileLogConfig {
+            max_file_size: 64,
+            max_files: 2,
+            max_age_days: None,
+            rotation: RotationPeriod::Never,
+            ..FileLogConfig::new(dir.to_path_buf())
+        }
+    }
+
+    #[test]
+    fn test_rotates_by_size_and_keeps_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = RotatingFileWriter::new(config(dir.path())).unwrap();
+
+        for i in 0..20 {
+            writer.write_line(&format!("{{\"line\":{},\"padding\":\"xxxxxxxx\"}}", i)).unwrap();
+        }
+        writer.flush().unwrap();
+
+        assert!(writer.active_path().exists());
+        assert!(fs::metadata(writer.active_path()).unwrap().len() <= 64);
+        assert_eq!(writer.rotated_files().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_format_record_merges_structured_messages() {
+        let timestamp = Utc::now();
+        let structured = format_record(log::Level::Info, "ssh", r#"{"event_type":"connection","session_id":"s1"}"#, timestamp);
+        let value: Value = serde_json::from_str(&structured).unwrap();
+        assert_eq!(value["event_type"], "connection");
+        assert_eq!(value["level"], "info");
+        assert_eq!(value["target"], "ssh");
+
+        let plain = format_record(log::Level::Warn, "app", "plain text", timestamp);
+        let value: Value = serde_json::from_str(&plain).unwrap();
+        assert_eq!(value["message"], "plain text");
+        assert_eq!(value["timestamp"], timestamp.to_rfc3339());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::types::{AppError, AppResult};
+use log::LevelFilter;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+// Targets of this crate are matched with or without the crate prefix, so "ssh" works as well
+const CRATE_PREFIX: &str = "webterminal_pro_lib::";
+
+static FILTERS: RwLock<LogFilters> = RwLock::new(LogFilters::new(LevelFilter::Info));
+
+// Level per module path, checked before each record is written
+#[derive(Debug, Clone)]
+pub struct LogFilters {
+    default: LevelFilter,
+    modules: BTreeMap<String, LevelFilter>,
+}
+
+impl LogFilters {
+    pub const fn new(default: LevelFilter) -> Self {
+        Self {
+            default,
+            modules: BTreeMap::new(),
+        }
+    }
+
+    // The most specific module filter wins
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        let target = target.strip_prefix(CRATE_PREFIX).unwrap_or(target);
+        self.modules.iter()
+            .filter(|(module, _)| {
+                target == module.as_str()
+                    || target.strip_prefix(module.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules.values().copied().fold(self.default, std::cmp::max)
+    }
+}
+
+// Serializable view of the filters for the command and HTTP API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLevelConfig {
+    pub level: String,
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+}
+
+impl LogLevelConfig {
+    fn parse(&self) -> AppResult<LogFilters> {
+        let mut filters = LogFilters::new(parse_level(&self.level)?);
+        for (module, level) in &self.modules {
+            let module = module.trim();
+            if module.is_empty() {
+                return Err(AppError::ValidationError("Module name cannot be empty".to_string()));
+            }
+            let module = module.strip_prefix(CRATE_PREFIX).unwrap_or(module);
+            filters.modules.insert(module.to_string(), parse_level(level)?);
+        }
+        Ok(filters)
+    }
+}
+
+fn parse_level(level: &str) -> AppResult<LevelFilter> {
+    LevelFilter::from_str(level.trim())
+        .map_err(|_| AppError::ValidationError(format!("Unknown log level: {}", level)))
+}
+
+pub fn enabled(metadata: &log::Metadata) -> bool {
+    metadata.level() <= FILTERS.read().level_for(metadata.target())
+}
+
+pub fn set_default_level(level: LevelFilter) {
+    let mut filters = FILTERS.write();
+    filters.default = level;
+    log::set_max_level(filters.max_level());
+}
+
+// Replace the active filters. Takes effect for the next record, no restart needed.
+pub fn configure(config: &LogLevelConfig) -> AppResult<LogLevelConfig> {
+    let parsed = config.parse()?;
+    let mut filters = FILTERS.write();
+    *filters = parsed;
+    log::set_max_level(filters.max_level());
+    drop(filters);
+
+    log::info!("Log level changed to {} with {} module filters", config.level, config.modules.len());
+    Ok(current())
+}
+
+pub fn current() -> LogLevelConfig {
+    let filters = FILTERS.read();
+    LogLevelConfig {
+        level: filters.default.as_str().to_lowercase(),
+        modules: filters.modules.iter()
+            .map(|(module, level)| (module.clone(), level.as_str().to_lowercase()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_most_specific_module_wins() {
+        let config = LogLevelConfig {
+            level: "warn".to_string(),
+            modules: BTreeMap::from([
+                ("ssh".to_string(), "debug".to_string()),
+                ("webterminal_pro_lib::ssh::forward".to_string(), "trace".to_string()),
+            ]),
+        };
+        let filters = config.parse().unwrap();
+
+        assert_eq!(filters.level_for("webterminal_pro_lib::server"), LevelFilter::Warn);
+        assert_eq!(filters.level_for("webterminal_pro_lib::ssh"), LevelFilter::Debug);
+        assert_eq!(filters.level_for("webterminal_pro_lib::ssh::known_hosts"), LevelFilter::Debug);
+        assert_eq!(filters.level_for("webterminal_pro_lib::ssh::forward"), LevelFilter::Trace);
+        assert_eq!(filters.level_for("webterminal_pro_lib::sshd"), LevelFilter::Warn);
+        assert_eq!(filters.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_rejects_unknown_levels() {
+        let config = LogLevelConfig {
+            level: "verbose".to_string(),
+            modules: BTreeMap::new(),
+        };
+        assert!(config.parse().is_err());
+    }
+}
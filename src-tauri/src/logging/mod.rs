@@ -1,9 +1,25 @@
+pub mod correlation;
+pub mod file;
+pub mod filter;
+pub mod query;
+pub mod redact;
+pub mod syslog;
+
 use crate::types::{AppError, ErrorSeverity};
-use serde_json::json;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 
 pub struct StructuredLogger;
 
+// Scrub secrets from an entry and tag it with the ID of the user action it belongs to
+fn finish_entry(mut log_data: Value) -> Value {
+    redact::redact_value(&mut log_data);
+    if let Some(id) = correlation::current() {
+        log_data["correlation_id"] = json!(id);
+    }
+    log_data
+}
+
 impl StructuredLogger {
     pub fn log_error(error: &AppError, context: Option<&str>, metadata: Option<HashMap<String, String>>) {
         let severity = error.severity();
@@ -23,10 +39,15 @@ impl StructuredLogger {
             log_data["context"] = json!(ctx);
         }
         
-        if let Some(meta) = metadata {
+        if let Some(meta) = &metadata {
             log_data["metadata"] = json!(meta);
         }
         
+        if severity == ErrorSeverity::Critical {
+            crate::reporting::capture_error(error, context, metadata.as_ref());
+        }
+
+        let log_data = finish_entry(log_data);
         match severity {
             ErrorSeverity::Critical => log::error!("{}", log_data),
             ErrorSeverity::High => log::error!("{}", log_data),
@@ -48,7 +69,7 @@ impl StructuredLogger {
             log_data["details"] = json!(details);
         }
         
-        log::info!("{}", log_data);
+        log::info!("{}", finish_entry(log_data));
     }
     
     pub fn log_performance_metric(metric_name: &str, value: f64, unit: &str, tags: Option<HashMap<String, String>>) {
@@ -65,7 +86,7 @@ impl StructuredLogger {
             log_data["tags"] = json!(tags);
         }
         
-        log::info!("{}", log_data);
+        log::info!("{}", finish_entry(log_data));
     }
     
     pub fn log_security_event(event_type: &str, severity: &str, details: HashMap<String, String>) {
@@ -78,7 +99,7 @@ impl StructuredLogger {
             "timestamp": chrono::Utc::now().to_rfc3339(),
         });
         
-        log::warn!("{}", log_data);
+        log::warn!("{}", finish_entry(log_data));
     }
     
     pub fn log_transfer_event(transfer_id: &str, event_type: &str, details: Option<HashMap<String, String>>) {
@@ -94,7 +115,7 @@ impl StructuredLogger {
             log_data["details"] = json!(details);
         }
         
-        log::info!("{}", log_data);
+        log::info!("{}", finish_entry(log_data));
     }
     
     pub fn log_websocket_event(client_id: &str, event_type: &str, details: Option<HashMap<String, String>>) {
@@ -110,7 +131,7 @@ impl StructuredLogger {
             log_data["details"] = json!(details);
         }
         
-        log::info!("{}", log_data);
+        log::info!("{}", finish_entry(log_data));
     }
 }
 
@@ -0,0 +1,188 @@
+use super::file::RotatingFileWriter;
+use crate::types::{AppError, AppResult};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+
+const DEFAULT_LIMIT: usize = 200;
+const MAX_LIMIT: usize = 5000;
+
+// Filters shared by the query and tail endpoints
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogQuery {
+    // Minimum level, e.g. "warn" also returns errors
+    pub level: Option<String>,
+    pub subsystem: Option<String>,
+    pub session_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    pub fn validate(&self) -> AppResult<()> {
+        if let Some(level) = &self.level {
+            log::Level::from_str(level)
+                .map_err(|_| AppError::ValidationError(format!("Unknown log level: {}", level)))?;
+        }
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since > until {
+                return Err(AppError::ValidationError("since must be before until".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    pub fn matches(&self, entry: &Value) -> bool {
+        if let Some(level) = self.level.as_deref().and_then(|level| log::Level::from_str(level).ok()) {
+            let entry_level = entry["level"].as_str().and_then(|level| log::Level::from_str(level).ok());
+            if entry_level.map_or(true, |entry_level| entry_level > level) {
+                return false;
+            }
+        }
+        if let Some(subsystem) = &self.subsystem {
+            if !subsystem_of(entry).is_some_and(|s| s.eq_ignore_ascii_case(subsystem)) {
+                return false;
+            }
+        }
+        if let Some(session_id) = &self.session_id {
+            if session_id_of(entry) != Some(session_id.as_str()) {
+                return false;
+            }
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let Some(timestamp) = timestamp_of(entry) else {
+                return false;
+            };
+            if self.since.is_some_and(|since| timestamp < since) || self.until.is_some_and(|until| timestamp > until) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Structured events carry an event_type; anything else is grouped by its top-level module
+pub fn subsystem_of(entry: &Value) -> Option<&str> {
+    if let Some(event_type) = entry["event_type"].as_str() {
+        return Some(event_type);
+    }
+    let target = entry["target"].as_str()?;
+    let target = target.strip_prefix("webterminal_pro_lib::").unwrap_or(target);
+    target.split("::").next()
+}
+
+fn session_id_of(entry: &Value) -> Option<&str> {
+    entry["session_id"].as_str()
+        .or_else(|| entry["details"]["session_id"].as_str())
+        .or_else(|| entry["metadata"]["session_id"].as_str())
+}
+
+fn timestamp_of(entry: &Value) -> Option<DateTime<Utc>> {
+    let timestamp = entry["timestamp"].as_str()?;
+    DateTime::parse_from_rfc3339(timestamp).ok().map(|timestamp| timestamp.with_timezone(&Utc))
+}
+
+// Newest matching entries first, read from the active file and then older rotated files
+pub fn query_logs(writer: &RotatingFileWriter, query: &LogQuery) -> AppResult<Vec<Value>> {
+    query.validate()?;
+    writer.flush()?;
+
+    let mut files = writer.rotated_files()?;
+    files.push(writer.active_path());
+
+    let limit = query.limit();
+    let mut entries = Vec::new();
+
+    for path in files.iter().rev() {
+        // Files last written before the range starts can't contain matches
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+        let Ok(modified) = modified else {
+            continue;
+        };
+        if query.since.is_some_and(|since| DateTime::<Utc>::from(modified) < since) {
+            break;
+        }
+
+        let file = std::fs::File::open(path)?;
+        let mut matching: Vec<Value> = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<Value>(&line).ok())
+            .filter(|entry| query.matches(entry))
+            .collect();
+
+        matching.reverse();
+        let remaining = limit - entries.len();
+        entries.extend(matching.into_iter().take(remaining));
+        if entries.len() >= limit {
+            break;
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(level: &str, target: &str, session_id: Option<&str>, timestamp: &str) -> Value {
+        let mut entry = json!({ "level": level, "target": target, "timestamp": timestamp, "message": "m" });
+        if let Some(session_id) = session_id {
+            entry["details"] = json!({ "session_id": session_id });
+        }
+        entry
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let warn = entry("warn", "webterminal_pro_lib::ssh::forward", Some("s1"), "2026-01-01T10:00:00Z");
+        let info = entry("info", "webterminal_pro_lib::server", None, "2026-01-01T12:00:00Z");
+
+        let query = LogQuery { level: Some("warn".to_string()), ..LogQuery::default() };
+        assert!(query.matches(&warn));
+        assert!(!query.matches(&info));
+
+        let query = LogQuery { subsystem: Some("ssh".to_string()), session_id: Some("s1".to_string()), ..LogQuery::default() };
+        assert!(query.matches(&warn));
+        assert!(!query.matches(&info));
+
+        let query = LogQuery {
+            since: Some("2026-01-01T11:00:00Z".parse().unwrap()),
+            ..LogQuery::default()
+        };
+        assert!(!query.matches(&warn));
+        assert!(query.matches(&info));
+
+        let structured = json!({ "level": "info", "event_type": "connection", "target": "webterminal_pro_lib::ssh" });
+        assert_eq!(subsystem_of(&structured), Some("connection"));
+    }
+
+    #[test]
+    fn test_query_validation() {
+        assert!(LogQuery { level: Some("loud".to_string()), ..LogQuery::default() }.validate().is_err());
+        assert_eq!(LogQuery { limit: Some(1_000_000), ..LogQuery::default() }.limit(), MAX_LIMIT);
+    }
+
+    #[test]
+    fn test_query_logs_reads_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = RotatingFileWriter::new(super::super::file::FileLogConfig::new(dir.path().to_path_buf())).unwrap();
+        for i in 0..5 {
+            writer.write_line(&json!({ "level": "info", "target": "app", "n": i }).to_string()).unwrap();
+        }
+
+        let entries = query_logs(&writer, &LogQuery { limit: Some(2), ..LogQuery::default() }).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["n"], 4);
+        assert_eq!(entries[1]["n"], 3);
+    }
+}
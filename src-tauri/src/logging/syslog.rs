@@ -0,0 +1,252 @@
+use chrono::Utc;
+use serde_json::Value;
+use std::io;
+use std::net::UdpSocket;
+
+const APP_NAME: &str = "webterminal-pro";
+// SD-ID for our structured data element; 32473 is the enterprise number reserved for examples
+const SD_ID: &str = "webterminal@32473";
+// Facility "daemon"
+const FACILITY: u8 = 3;
+const DEFAULT_SYSLOG_SOCKET: &str = "/dev/log";
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogForwarding {
+    // RFC 5424 over the local syslog socket or UDP to host:port
+    Syslog { address: Option<String> },
+    Journald,
+}
+
+impl LogForwarding {
+    // "syslog", "syslog://host:514" or "journald"
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        match value {
+            "journald" => Some(Self::Journald),
+            "syslog" => Some(Self::Syslog { address: None }),
+            _ => value.strip_prefix("syslog://")
+                .filter(|address| !address.is_empty())
+                .map(|address| Self::Syslog { address: Some(address.to_string()) }),
+        }
+    }
+}
+
+enum Transport {
+    Udp(UdpSocket, String),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram, std::path::PathBuf),
+}
+
+impl Transport {
+    fn send(&self, payload: &[u8]) -> io::Result<()> {
+        match self {
+            Transport::Udp(socket, address) => socket.send_to(payload, address).map(|_| ()),
+            #[cfg(unix)]
+            Transport::Unix(socket, path) => socket.send_to(payload, path).map(|_| ()),
+        }
+    }
+}
+
+// Sends every log entry to syslog or journald in addition to the log file
+pub struct LogForwarder {
+    kind: LogForwarding,
+    transport: Transport,
+    hostname: String,
+    pid: u32,
+}
+
+impl LogForwarder {
+    pub fn new(kind: LogForwarding) -> io::Result<Self> {
+        let transport = match &kind {
+            LogForwarding::Syslog { address: Some(address) } => {
+                Transport::Udp(UdpSocket::bind("0.0.0.0:0")?, address.clone())
+            }
+            LogForwarding::Syslog { address: None } => unix_transport(DEFAULT_SYSLOG_SOCKET)?,
+            LogForwarding::Journald => unix_transport(JOURNALD_SOCKET)?,
+        };
+
+        Ok(Self {
+            kind,
+            transport,
+            hostname: hostname(),
+            pid: std::process::id(),
+        })
+    }
+
+    pub fn forward(&self, level: log::Level, entry: &Value) -> io::Result<()> {
+        let payload = match self.kind {
+            LogForwarding::Syslog { .. } => format_rfc5424(level, &self.hostname, self.pid, entry).into_bytes(),
+            LogForwarding::Journald => format_journald(level, entry),
+        };
+        self.transport.send(&payload)
+    }
+}
+
+#[cfg(unix)]
+fn unix_transport(path: &str) -> io::Result<Transport> {
+    Ok(Transport::Unix(std::os::unix::net::UnixDatagram::unbound()?, std::path::PathBuf::from(path)))
+}
+
+#[cfg(not(unix))]
+fn unix_transport(path: &str) -> io::Result<Transport> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} is only available on Unix", path)))
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME").ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn severity(level: log::Level) -> u8 {
+    match level {
+        log::Level::Error => 3,
+        log::Level::Warn => 4,
+        log::Level::Info => 6,
+        log::Level::Debug | log::Level::Trace => 7,
+    }
+}
+
+fn message_of(entry: &Value) -> String {
+    entry["message"].as_str()
+        .or_else(|| entry["error_message"].as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| entry.to_string())
+}
+
+// Scalars are kept as they are, nested objects as compact JSON
+fn field_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        other => other.to_string(),
+    }
+}
+
+// <PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD-ID key="value" ...] MSG
+pub fn format_rfc5424(level: log::Level, hostname: &str, pid: u32, entry: &Value) -> String {
+    let pri = FACILITY * 8 + severity(level);
+    let msg_id = entry["event_type"].as_str().unwrap_or("-");
+    let timestamp = entry["timestamp"].as_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+
+    let mut structured = String::new();
+    if let Value::Object(fields) = entry {
+        for (key, value) in fields {
+            if key == "message" || key == "timestamp" {
+                continue;
+            }
+            // PARAM-NAME is at most 32 printable characters without '=', ' ', ']' or '"'
+            let name: String = key.chars()
+                .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+                .take(32)
+                .collect();
+            if name.is_empty() {
+                continue;
+            }
+            let escaped = field_value(value)
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace(']', "\\]");
+            structured.push_str(&format!(" {}=\"{}\"", name, escaped));
+        }
+    }
+    let structured_data = if structured.is_empty() {
+        "-".to_string()
+    } else {
+        format!("[{}{}]", SD_ID, structured)
+    };
+
+    format!(
+        "<{}>1 {} {} {} {} {} {} {}",
+        pri, timestamp, hostname, APP_NAME, pid, msg_id, structured_data, message_of(entry)
+    )
+}
+
+// Native journal protocol: KEY=value lines, with a length-prefixed form for multi-line values
+pub fn format_journald(level: log::Level, entry: &Value) -> Vec<u8> {
+    let mut payload = Vec::new();
+    let mut push = |key: &str, value: &str| {
+        if value.contains('\n') {
+            payload.extend_from_slice(key.as_bytes());
+            payload.push(b'\n');
+            payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            payload.extend_from_slice(value.as_bytes());
+            payload.push(b'\n');
+        } else {
+            payload.extend_from_slice(format!("{}={}\n", key, value).as_bytes());
+        }
+    };
+
+    push("MESSAGE", &message_of(entry));
+    push("PRIORITY", &severity(level).to_string());
+    push("SYSLOG_IDENTIFIER", APP_NAME);
+
+    if let Value::Object(fields) = entry {
+        for (key, value) in fields {
+            if key == "message" {
+                continue;
+            }
+            // Journal field names are uppercase letters, digits and underscores, not starting with '_'
+            let name: String = key.chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+                .collect();
+            let name = name.trim_start_matches(|c: char| c == '_' || c.is_ascii_digit());
+            if name.is_empty() || matches!(name, "MESSAGE" | "PRIORITY" | "SYSLOG_IDENTIFIER") {
+                continue;
+            }
+            push(name, &field_value(value));
+        }
+    }
+
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_rfc5424_keeps_structured_fields() {
+        let entry = json!({
+            "timestamp": "2026-01-01T00:00:00+00:00",
+            "event_type": "connection",
+            "session_id": "s1",
+            "details": { "host": "a\"b]" },
+            "message": "connected",
+        });
+        let line = format_rfc5424(log::Level::Warn, "web-1", 42, &entry);
+
+        assert!(line.starts_with("<28>1 2026-01-01T00:00:00+00:00 web-1 webterminal-pro 42 connection [webterminal@32473 "));
+        assert!(line.contains(r#"session_id="s1""#));
+        assert!(line.contains(r#"details="{\"host\":\"a\\\"b\]\"}""#));
+        assert!(line.ends_with("] connected"));
+    }
+
+    #[test]
+    fn test_journald_fields() {
+        let entry = json!({ "message": "line one\nline two", "session_id": "s1", "level": "error" });
+        let payload = format_journald(log::Level::Error, &entry);
+        let text = String::from_utf8_lossy(&payload);
+
+        assert!(text.starts_with("MESSAGE\n"));
+        assert!(text.contains("PRIORITY=3\n"));
+        assert!(text.contains("SESSION_ID=s1\n"));
+        assert!(text.contains("LEVEL=error\n"));
+    }
+
+    #[test]
+    fn test_parse_forwarding() {
+        assert_eq!(LogForwarding::parse("journald"), Some(LogForwarding::Journald));
+        assert_eq!(LogForwarding::parse("syslog"), Some(LogForwarding::Syslog { address: None }));
+        assert_eq!(
+            LogForwarding::parse("syslog://logs.internal:514"),
+            Some(LogForwarding::Syslog { address: Some("logs.internal:514".to_string()) })
+        );
+        assert_eq!(LogForwarding::parse("kafka"), None);
+    }
+}
@@ -0,0 +1,608 @@
+use crate::types::{AppError, AppResult};
+use crate::webhooks::{self, WebhookEventKind};
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, NaiveTime, Utc, Weekday};
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+// A watch that keeps matching (e.g. a pattern in a log tail) fires at most this often
+const WATCH_COOLDOWN: Duration = Duration::from_secs(10);
+const MAX_PATTERN_LENGTH: usize = 512;
+const MAX_RULES: usize = 64;
+
+pub type SharedNotificationCenter = Arc<NotificationCenter>;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationKind {
+    SessionDropped,
+    TransferCompleted,
+    TransferFailed,
+    OutputPatternMatched,
+    // Raised by a user script through notify()
+    Script,
+    JobFailed,
+}
+
+impl NotificationKind {
+    pub fn severity(self) -> NotificationSeverity {
+        match self {
+            NotificationKind::SessionDropped | NotificationKind::JobFailed => NotificationSeverity::Critical,
+            NotificationKind::TransferFailed => NotificationSeverity::Warning,
+            NotificationKind::TransferCompleted
+            | NotificationKind::OutputPatternMatched
+            | NotificationKind::Script => NotificationSeverity::Info,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationEvent {
+    pub id: String,
+    pub kind: NotificationKind,
+    pub severity: NotificationSeverity,
+    #[serde(rename = "sessionId")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    pub title: String,
+    pub body: String,
+    // Whether the routing asked for a native OS notification
+    pub native: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Where a notification goes; with none it is only logged
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationTarget {
+    // The in-app notification list
+    App,
+    // A native OS notification, also listed in the app
+    Desktop,
+    // The configured webhooks subscribed to "notification"
+    Webhook,
+}
+
+// Local time of day, wrapping past midnight when end is before start, e.g. 22:00-07:00
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    // Every day when empty; for windows past midnight, the day they start on
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+}
+
+impl TimeWindow {
+    fn contains(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        let (inside, day) = if self.start <= self.end {
+            (time >= self.start && time < self.end, now.weekday())
+        } else if time >= self.start {
+            (true, now.weekday())
+        } else {
+            (time < self.end, now.weekday().pred())
+        };
+        inside && (self.days.is_empty() || self.days.contains(&day))
+    }
+}
+
+// Routes the notifications it matches; rules are tried in order and the first match wins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationRule {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    // Each criterion left empty matches everything
+    #[serde(default)]
+    pub kinds: Vec<NotificationKind>,
+    // Hostnames, with * as a wildcard, e.g. "*.prod.example.com"
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(rename = "minSeverity", default)]
+    pub min_severity: Option<NotificationSeverity>,
+    #[serde(default)]
+    pub window: Option<TimeWindow>,
+    pub targets: Vec<NotificationTarget>,
+}
+
+impl NotificationRule {
+    fn matches(&self, kind: NotificationKind, host: Option<&str>, now: NaiveDateTime) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&kind))
+            && (self.hosts.is_empty() || host.is_some_and(|host| self.hosts.iter().any(|pattern| wildcard_match(pattern, host))))
+            && self.min_severity.map_or(true, |min| kind.severity() >= min)
+            && self.window.as_ref().map_or(true, |window| window.contains(now))
+    }
+}
+
+// Case-insensitive, * matching any run of characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let text = text.to_ascii_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NotificationPreference {
+    pub enabled: bool,
+    pub native: bool,
+}
+
+impl NotificationPreference {
+    const fn new(enabled: bool, native: bool) -> Self {
+        Self { enabled, native }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationPreferences {
+    #[serde(rename = "sessionDropped")]
+    pub session_dropped: NotificationPreference,
+    #[serde(rename = "transferCompleted")]
+    pub transfer_completed: NotificationPreference,
+    #[serde(rename = "transferFailed")]
+    pub transfer_failed: NotificationPreference,
+    #[serde(rename = "outputPatternMatched")]
+    pub output_pattern_matched: NotificationPreference,
+    #[serde(default = "default_script_preference")]
+    pub script: NotificationPreference,
+    #[serde(rename = "jobFailed", default = "default_job_failed_preference")]
+    pub job_failed: NotificationPreference,
+}
+
+// Preferences saved before scripts existed have no entry for them
+fn default_script_preference() -> NotificationPreference {
+    NotificationPreference::new(true, true)
+}
+
+fn default_job_failed_preference() -> NotificationPreference {
+    NotificationPreference::new(true, true)
+}
+
+impl NotificationPreferences {
+    pub fn for_kind(&self, kind: NotificationKind) -> NotificationPreference {
+        match kind {
+            NotificationKind::SessionDropped => self.session_dropped,
+            NotificationKind::TransferCompleted => self.transfer_completed,
+            NotificationKind::TransferFailed => self.transfer_failed,
+            NotificationKind::OutputPatternMatched => self.output_pattern_matched,
+            NotificationKind::Script => self.script,
+            NotificationKind::JobFailed => self.job_failed,
+        }
+    }
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        Self {
+            session_dropped: NotificationPreference::new(true, true),
+            transfer_completed: NotificationPreference::new(true, false),
+            transfer_failed: NotificationPreference::new(true, true),
+            output_pattern_matched: NotificationPreference::new(true, true),
+            script: default_script_preference(),
+            job_failed: default_job_failed_preference(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputWatch {
+    pub id: String,
+    // Applies to every session when unset
+    #[serde(rename = "sessionId")]
+    pub session_id: Option<String>,
+    pub pattern: String,
+    pub label: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputWatchRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: Option<String>,
+    pub pattern: String,
+    pub label: Option<String>,
+}
+
+struct WatchEntry {
+    watch: OutputWatch,
+    regex: Regex,
+    last_fired: Option<Instant>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct StoredSettings {
+    preferences: NotificationPreferences,
+    watches: Vec<OutputWatch>,
+    // Saved before routing rules existed
+    #[serde(default)]
+    rules: Vec<NotificationRule>,
+    #[serde(rename = "doNotDisturbUntil", default)]
+    do_not_disturb_until: Option<DateTime<Utc>>,
+}
+
+pub struct NotificationCenter {
+    preferences: RwLock<NotificationPreferences>,
+    rules: RwLock<Vec<NotificationRule>>,
+    // Holds back desktop notifications short of critical ones until then
+    do_not_disturb_until: RwLock<Option<DateTime<Utc>>>,
+    watches: DashMap<String, WatchEntry>,
+    events: broadcast::Sender<NotificationEvent>,
+    storage_path: Option<PathBuf>,
+}
+
+impl NotificationCenter {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            preferences: RwLock::new(NotificationPreferences::default()),
+            rules: RwLock::new(Vec::new()),
+            do_not_disturb_until: RwLock::new(None),
+            watches: DashMap::new(),
+            events,
+            storage_path: None,
+        }
+    }
+
+    pub async fn load(storage_path: PathBuf) -> AppResult<Self> {
+        let mut center = Self::new();
+
+        if tokio::fs::try_exists(&storage_path).await? {
+            let content = tokio::fs::read_to_string(&storage_path).await?;
+            let settings: StoredSettings = serde_json::from_str(&content)?;
+            *center.preferences.get_mut() = settings.preferences;
+            *center.rules.get_mut() = settings.rules;
+            *center.do_not_disturb_until.get_mut() = settings.do_not_disturb_until;
+            for watch in settings.watches {
+                match Regex::new(&watch.pattern) {
+                    Ok(regex) => {
+                        center.watches.insert(watch.id.clone(), WatchEntry { watch, regex, last_fired: None });
+                    }
+                    Err(e) => log::warn!("Skipping output watch {} with invalid pattern: {}", watch.id, e),
+                }
+            }
+        }
+
+        center.storage_path = Some(storage_path);
+        Ok(center)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<NotificationEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn preferences(&self) -> NotificationPreferences {
+        self.preferences.read().clone()
+    }
+
+    pub async fn set_preferences(&self, preferences: NotificationPreferences) -> AppResult<()> {
+        *self.preferences.write() = preferences;
+        self.save().await
+    }
+
+    pub fn rules(&self) -> Vec<NotificationRule> {
+        self.rules.read().clone()
+    }
+
+    // Replaces the whole list, which is matched in the order given
+    pub async fn set_rules(&self, mut rules: Vec<NotificationRule>) -> AppResult<Vec<NotificationRule>> {
+        if rules.len() > MAX_RULES {
+            return Err(AppError::ValidationError(format!("At most {} notification rules are allowed", MAX_RULES)));
+        }
+        for rule in &mut rules {
+            if rule.name.trim().is_empty() {
+                return Err(AppError::ValidationError("Rule name cannot be empty".to_string()));
+            }
+            if rule.hosts.iter().any(|host| host.trim().is_empty()) {
+                return Err(AppError::ValidationError(format!("Rule {} has an empty host pattern", rule.name)));
+            }
+            if rule.id.is_empty() {
+                rule.id = Uuid::new_v4().to_string();
+            }
+        }
+        *self.rules.write() = rules.clone();
+        self.save().await?;
+        Ok(rules)
+    }
+
+    pub fn do_not_disturb_until(&self) -> Option<DateTime<Utc>> {
+        self.do_not_disturb_until.read().filter(|until| *until > Utc::now())
+    }
+
+    // None turns do-not-disturb off
+    pub async fn set_do_not_disturb(&self, until: Option<DateTime<Utc>>) -> AppResult<()> {
+        *self.do_not_disturb_until.write() = until;
+        self.save().await
+    }
+
+    // Where a notification goes: nowhere when the user turned its kind off, else the first
+    // matching rule's targets, or the kind's preference when no rule matches
+    pub fn route(&self, kind: NotificationKind, host: Option<&str>, now: DateTime<Utc>) -> Vec<NotificationTarget> {
+        let preference = self.preferences().for_kind(kind);
+        if !preference.enabled {
+            return Vec::new();
+        }
+
+        let local = now.with_timezone(&Local).naive_local();
+        let rule = self.rules.read().iter().find(|rule| rule.matches(kind, host, local)).cloned();
+        let mut targets = match rule {
+            Some(rule) => rule.targets,
+            None if preference.native => vec![NotificationTarget::Desktop],
+            None => vec![NotificationTarget::App],
+        };
+
+        let quiet = self.do_not_disturb_until.read().is_some_and(|until| until > now);
+        if quiet && kind.severity() < NotificationSeverity::Critical {
+            for target in targets.iter_mut().filter(|target| **target == NotificationTarget::Desktop) {
+                *target = NotificationTarget::App;
+            }
+        }
+        targets
+    }
+
+    pub fn notify(&self, kind: NotificationKind, session_id: Option<&str>, title: impl Into<String>, body: impl Into<String>) {
+        self.notify_host(kind, session_id, None, title, body);
+    }
+
+    // Publish a notification wherever the routing sends it; host is what rules match against
+    pub fn notify_host(
+        &self,
+        kind: NotificationKind,
+        session_id: Option<&str>,
+        host: Option<&str>,
+        title: impl Into<String>,
+        body: impl Into<String>,
+    ) {
+        let timestamp = Utc::now();
+        let targets = self.route(kind, host, timestamp);
+        let event = NotificationEvent {
+            id: Uuid::new_v4().to_string(),
+            kind,
+            severity: kind.severity(),
+            session_id: session_id.map(str::to_string),
+            host: host.map(str::to_string),
+            title: title.into(),
+            body: body.into(),
+            native: targets.contains(&NotificationTarget::Desktop),
+            timestamp,
+        };
+
+        if targets.is_empty() {
+            log::info!("Notification silenced: {} - {}", event.title, event.body);
+            return;
+        }
+        if targets.contains(&NotificationTarget::Webhook) {
+            webhooks::emit(WebhookEventKind::Notification, session_id, serde_json::to_value(&event).unwrap_or_default());
+        }
+        if event.native || targets.contains(&NotificationTarget::App) {
+            // No receivers just means nothing is listening yet
+            let _ = self.events.send(event);
+        }
+    }
+
+    pub async fn add_watch(&self, request: OutputWatchRequest) -> AppResult<OutputWatch> {
+        if request.pattern.is_empty() || request.pattern.len() > MAX_PATTERN_LENGTH {
+            return Err(AppError::ValidationError(format!(
+                "Pattern must be between 1 and {} characters", MAX_PATTERN_LENGTH
+            )));
+        }
+        let regex = Regex::new(&request.pattern)
+            .map_err(|e| AppError::ValidationError(format!("Invalid pattern: {}", e)))?;
+
+        let watch = OutputWatch {
+            id: Uuid::new_v4().to_string(),
+            session_id: request.session_id,
+            label: request.label.unwrap_or_else(|| request.pattern.clone()),
+            pattern: request.pattern,
+            created_at: Utc::now(),
+        };
+        self.watches.insert(watch.id.clone(), WatchEntry { watch: watch.clone(), regex, last_fired: None });
+        self.save().await?;
+        Ok(watch)
+    }
+
+    pub async fn remove_watch(&self, watch_id: &str) -> AppResult<bool> {
+        if self.watches.remove(watch_id).is_none() {
+            return Ok(false);
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    pub fn list_watches(&self) -> Vec<OutputWatch> {
+        let mut watches: Vec<OutputWatch> = self.watches.iter()
+            .map(|entry| entry.watch.clone())
+            .collect();
+        watches.sort_by_key(|watch| watch.created_at);
+        watches
+    }
+
+    // Run terminal output through the watches that apply to the session
+    pub fn check_output(&self, session_id: &str, output: &str) {
+        let now = Instant::now();
+        let mut matched = Vec::new();
+
+        for mut entry in self.watches.iter_mut() {
+            if entry.watch.session_id.as_deref().is_some_and(|id| id != session_id) {
+                continue;
+            }
+            if entry.last_fired.is_some_and(|fired| now.duration_since(fired) < WATCH_COOLDOWN) {
+                continue;
+            }
+            if let Some(found) = entry.regex.find(output) {
+                entry.last_fired = Some(now);
+                matched.push((entry.watch.label.clone(), found.as_str().to_string()));
+            }
+        }
+
+        for (label, text) in matched {
+            self.notify(NotificationKind::OutputPatternMatched, Some(session_id), label, text);
+        }
+    }
+
+    async fn save(&self) -> AppResult<()> {
+        let Some(path) = &self.storage_path else {
+            return Ok(());
+        };
+
+        let settings = StoredSettings {
+            preferences: self.preferences(),
+            watches: self.list_watches(),
+            rules: self.rules(),
+            do_not_disturb_until: *self.do_not_disturb_until.read(),
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, serde_json::to_string_pretty(&settings)?).await?;
+        Ok(())
+    }
+}
+
+impl Default for NotificationCenter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_preferences_filter_notifications() {
+        let center = NotificationCenter::new();
+        let mut events = center.subscribe();
+
+        let mut preferences = NotificationPreferences::default();
+        preferences.transfer_completed.enabled = false;
+        center.set_preferences(preferences).await.unwrap();
+
+        center.notify(NotificationKind::TransferCompleted, None, "Upload finished", "a.txt");
+        center.notify(NotificationKind::SessionDropped, Some("s1"), "Session dropped", "host");
+
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.kind, NotificationKind::SessionDropped);
+        assert!(event.native);
+        assert!(events.try_recv().is_err());
+    }
+
+    fn rule(name: &str, hosts: &[&str], min_severity: Option<NotificationSeverity>, targets: Vec<NotificationTarget>) -> NotificationRule {
+        NotificationRule {
+            id: String::new(),
+            name: name.to_string(),
+            kinds: Vec::new(),
+            hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            min_severity,
+            window: None,
+            targets,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rules_route_notifications() {
+        let center = NotificationCenter::new();
+        let now = Utc::now();
+        center.set_rules(vec![
+            rule("Prod pages", &["*.prod.example.com"], Some(NotificationSeverity::Warning), vec![NotificationTarget::Desktop, NotificationTarget::Webhook]),
+            rule("Quiet prod", &["*.PROD.example.com"], None, Vec::new()),
+        ]).await.unwrap();
+        assert!(center.rules().iter().all(|rule| !rule.id.is_empty()));
+
+        let prod = Some("db1.prod.example.com");
+        assert_eq!(center.route(NotificationKind::SessionDropped, prod, now), [NotificationTarget::Desktop, NotificationTarget::Webhook]);
+        assert!(center.route(NotificationKind::TransferCompleted, prod, now).is_empty());
+        // No rule matches, so the kind's preference decides
+        assert_eq!(center.route(NotificationKind::TransferCompleted, Some("staging"), now), [NotificationTarget::App]);
+
+        center.set_do_not_disturb(Some(now + chrono::Duration::hours(1))).await.unwrap();
+        assert_eq!(center.route(NotificationKind::TransferFailed, prod, now), [NotificationTarget::App, NotificationTarget::Webhook]);
+        assert_eq!(center.route(NotificationKind::SessionDropped, prod, now)[0], NotificationTarget::Desktop);
+
+        assert!(center.set_rules(vec![rule(" ", &[], None, Vec::new())]).await.is_err());
+    }
+
+    #[test]
+    fn test_time_window_and_wildcards() {
+        let at = |day: u32, time: &str| {
+            chrono::NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+                .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+        };
+        // 2024-01-01 is a Monday
+        let night = TimeWindow {
+            start: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+            days: vec![Weekday::Mon],
+        };
+        assert!(night.contains(at(1, "23:30")));
+        assert!(night.contains(at(2, "06:59")));
+        assert!(!night.contains(at(2, "07:00")));
+        assert!(!night.contains(at(2, "23:30")));
+
+        assert!(wildcard_match("*.prod.*", "web.prod.example.com"));
+        assert!(wildcard_match("bastion", "BASTION"));
+        assert!(!wildcard_match("*.prod.example.com", "prod.example.com"));
+        assert!(!wildcard_match("web*", "db-web"));
+    }
+
+    #[tokio::test]
+    async fn test_output_watch_matches_with_cooldown() {
+        let center = NotificationCenter::new();
+        let mut events = center.subscribe();
+
+        let watch = center.add_watch(OutputWatchRequest {
+            session_id: Some("s1".to_string()),
+            pattern: r"BUILD (SUCCESS|FAILED)".to_string(),
+            label: Some("Build finished".to_string()),
+        }).await.unwrap();
+
+        center.check_output("s2", "BUILD SUCCESS");
+        assert!(events.try_recv().is_err());
+
+        center.check_output("s1", "[INFO] BUILD FAILED in 3s");
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.title, "Build finished");
+        assert_eq!(event.body, "BUILD FAILED");
+
+        center.check_output("s1", "BUILD SUCCESS");
+        assert!(events.try_recv().is_err());
+
+        assert!(center.remove_watch(&watch.id).await.unwrap());
+        assert!(center.add_watch(OutputWatchRequest {
+            session_id: None,
+            pattern: "(".to_string(),
+            label: None,
+        }).await.is_err());
+    }
+}
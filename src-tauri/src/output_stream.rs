@@ -0,0 +1,198 @@
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
+
+// Bytes the frontend may have outstanding before output reading pauses
+const DEFAULT_WINDOW_BYTES: usize = 256 * 1024;
+// A frontend that stops acknowledging must not freeze the terminal forever
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub type SharedOutputStreams = Arc<OutputStreamRegistry>;
+
+// Per-session credit window for pushed terminal output
+pub struct FlowControl {
+    unacked: AtomicUsize,
+    window: usize,
+    notify: Notify,
+}
+
+impl FlowControl {
+    fn new(window: usize) -> Self {
+        Self {
+            unacked: AtomicUsize::new(0),
+            window,
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn record_sent(&self, bytes: usize) {
+        self.unacked.fetch_add(bytes, Ordering::AcqRel);
+    }
+
+    pub fn acknowledge(&self, bytes: usize) {
+        let _ = self.unacked.fetch_update(Ordering::AcqRel, Ordering::Acquire, |unacked| {
+            Some(unacked.saturating_sub(bytes))
+        });
+        self.notify.notify_waiters();
+    }
+
+    pub fn unacknowledged(&self) -> usize {
+        self.unacked.load(Ordering::Acquire)
+    }
+
+    // Wait until the frontend has caught up. Returns false if it timed out and the window was reset.
+    pub async fn wait_for_capacity(&self) -> bool {
+        let wait = async {
+            loop {
+                let notified = self.notify.notified();
+                if self.unacknowledged() < self.window {
+                    return;
+                }
+                notified.await;
+            }
+        };
+
+        if timeout(ACK_TIMEOUT, wait).await.is_err() {
+            self.unacked.store(0, Ordering::Release);
+            return false;
+        }
+        true
+    }
+}
+
+pub struct OutputStream {
+    pub id: u64,
+    pub cancel: CancellationToken,
+    pub flow: Arc<FlowControl>,
+}
+
+struct StreamHandle {
+    id: u64,
+    cancel: CancellationToken,
+    flow: Arc<FlowControl>,
+}
+
+// Tracks the output task of every open shell so it can be stopped explicitly
+pub struct OutputStreamRegistry {
+    streams: DashMap<String, StreamHandle>,
+    next_id: AtomicU64,
+    window: usize,
+}
+
+impl OutputStreamRegistry {
+    pub fn new() -> Self {
+        Self::with_window(DEFAULT_WINDOW_BYTES)
+    }
+
+    pub fn with_window(window: usize) -> Self {
+        Self {
+            streams: DashMap::new(),
+            next_id: AtomicU64::new(1),
+            window,
+        }
+    }
+
+    // Register a new stream for the session, stopping any stream it replaces
+    pub fn register(&self, session_id: &str) -> OutputStream {
+        let handle = StreamHandle {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            cancel: CancellationToken::new(),
+            flow: Arc::new(FlowControl::new(self.window)),
+        };
+        let stream = OutputStream {
+            id: handle.id,
+            cancel: handle.cancel.clone(),
+            flow: handle.flow.clone(),
+        };
+
+        if let Some(previous) = self.streams.insert(session_id.to_string(), handle) {
+            previous.cancel.cancel();
+        }
+        stream
+    }
+
+    // Called by the output task when it exits; ignores streams that were already replaced
+    pub fn unregister(&self, session_id: &str, stream_id: u64) {
+        self.streams.remove_if(session_id, |_, handle| handle.id == stream_id);
+    }
+
+    pub fn acknowledge(&self, session_id: &str, bytes: usize) -> bool {
+        match self.streams.get(session_id) {
+            Some(handle) => {
+                handle.flow.acknowledge(bytes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn stop(&self, session_id: &str) -> bool {
+        match self.streams.remove(session_id) {
+            Some((_, handle)) => {
+                handle.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn stop_all(&self) {
+        for entry in self.streams.iter() {
+            entry.cancel.cancel();
+        }
+        self.streams.clear();
+    }
+
+    pub fn is_active(&self, session_id: &str) -> bool {
+        self.streams.contains_key(session_id)
+    }
+}
+
+impl Default for OutputStreamRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_register_replaces_previous_stream() {
+        let registry = OutputStreamRegistry::new();
+
+        let first = registry.register("session");
+        let second = registry.register("session");
+        assert!(first.cancel.is_cancelled());
+        assert!(!second.cancel.is_cancelled());
+
+        // The replaced task exiting must not remove the new registration
+        registry.unregister("session", first.id);
+        assert!(registry.is_active("session"));
+
+        assert!(registry.stop("session"));
+        assert!(second.cancel.is_cancelled());
+        assert!(!registry.is_active("session"));
+    }
+
+    #[tokio::test]
+    async fn test_flow_control_waits_for_ack() {
+        let registry = OutputStreamRegistry::with_window(100);
+        let stream = registry.register("session");
+
+        stream.flow.record_sent(150);
+        let flow = stream.flow.clone();
+        let waiter = tokio::spawn(async move { flow.wait_for_capacity().await });
+
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        assert!(registry.acknowledge("session", 100));
+        assert!(waiter.await.unwrap());
+        assert_eq!(stream.flow.unacknowledged(), 50);
+    }
+}
@@ -94,7 +94,7 @@ impl PerformanceMonitor {
         let transfers = manager.list_transfers();
         
         let active_transfers = transfers.iter()
-            .filter(|t| matches!(t.status, crate::types::TransferStatus::InProgress | crate::types::TransferStatus::Pending))
+            .filter(|t| matches!(t.status, crate::types::TransferStatus::InProgress | crate::types::TransferStatus::Pending | crate::types::TransferStatus::Paused))
             .count() as u32;
 
         // Calculate error rate (simplified)
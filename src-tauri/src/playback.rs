@@ -0,0 +1,196 @@
+use crate::recording::{read_events, TerminalEvent, TerminalEventType};
+use crate::types::{AppError, AppResult};
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+// Same identifier as tauri.conf.json, so the CLI finds what the desktop app recorded
+const APP_IDENTIFIER: &str = "com.webterminal.pro";
+pub const MIN_SPEED: f64 = 0.1;
+pub const MAX_SPEED: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlaybackOptions {
+    pub speed: f64,
+    // Pauses longer than this are cut short, so idle stretches don't stall the replay
+    pub idle_limit: Option<Duration>,
+}
+
+impl Default for PlaybackOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            idle_limit: Some(Duration::from_secs(2)),
+        }
+    }
+}
+
+impl PlaybackOptions {
+    pub fn validate(&self) -> AppResult<()> {
+        if !(MIN_SPEED..=MAX_SPEED).contains(&self.speed) {
+            return Err(AppError::ValidationError(format!(
+                "Speed must be between {} and {}", MIN_SPEED, MAX_SPEED
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Where the desktop app keeps recordings: WEBTERMINAL_RECORDINGS, else the recordings
+// directory under the platform's app data directory
+pub fn default_recordings_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("WEBTERMINAL_RECORDINGS") {
+        return Some(PathBuf::from(dir));
+    }
+    let data_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    data_dir.map(|dir| dir.join(APP_IDENTIFIER).join("recordings"))
+}
+
+// A recording file given directly, or a recording ID looked up in the recordings directory
+pub fn resolve_recording(target: &str, recordings_dir: Option<&Path>) -> AppResult<PathBuf> {
+    let path = Path::new(target);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+    let is_id = !target.is_empty() && target.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if is_id {
+        if let Some(dir) = recordings_dir {
+            let file = dir.join(format!("{}.jsonl", target));
+            if file.is_file() {
+                return Ok(file);
+            }
+        }
+    }
+    Err(AppError::NotFound(format!("Recording {}", target)))
+}
+
+// Output chunks with the delay before each, scaled and capped
+pub fn frames<'a>(events: &'a [TerminalEvent], options: &PlaybackOptions) -> Vec<(Duration, &'a str)> {
+    let mut frames = Vec::new();
+    let mut previous: Option<DateTime<Utc>> = None;
+    for event in events.iter().filter(|event| event.event_type == TerminalEventType::Output) {
+        let gap = previous
+            .map(|previous| (event.timestamp - previous).to_std().unwrap_or_default())
+            .unwrap_or_default();
+        let gap = match options.idle_limit {
+            Some(limit) => gap.min(limit),
+            None => gap,
+        };
+        frames.push((gap.div_f64(options.speed), event.data.as_str()));
+        previous = Some(event.timestamp);
+    }
+    frames
+}
+
+// Bookmarks dropped while recording, in order
+pub fn bookmarks(events: &[TerminalEvent]) -> Vec<&TerminalEvent> {
+    events.iter().filter(|event| event.event_type == TerminalEventType::Bookmark).collect()
+}
+
+// Frames to play from the named bookmark on. Output before it is drawn at once, so the
+// screen looks as it did when the bookmark was dropped.
+pub fn frames_from<'a>(events: &'a [TerminalEvent], options: &PlaybackOptions, bookmark: &str) -> AppResult<Vec<(Duration, &'a str)>> {
+    let at = events.iter()
+        .position(|event| event.event_type == TerminalEventType::Bookmark && event.data == bookmark)
+        .ok_or_else(|| AppError::NotFound(format!("Bookmark {}", bookmark)))?;
+    let mut scheduled: Vec<(Duration, &str)> = frames(&events[..at], options).into_iter().map(|(_, data)| (Duration::ZERO, data)).collect();
+    scheduled.extend(frames(&events[at..], options));
+    Ok(scheduled)
+}
+
+pub async fn play<W: AsyncWrite + Unpin>(path: &Path, options: &PlaybackOptions, bookmark: Option<&str>, out: &mut W) -> AppResult<()> {
+    options.validate()?;
+    let events = read_events(path).await?;
+    let frames = match bookmark {
+        Some(bookmark) => frames_from(&events, options, bookmark)?,
+        None => frames(&events, options),
+    };
+    for (delay, data) in frames {
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        out.write_all(data.as_bytes()).await?;
+        out.flush().await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn event(seconds: i64, event_type: TerminalEventType, data: &str) -> TerminalEvent {
+        TerminalEvent {
+            timestamp: Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap(),
+            event_type,
+            data: data.to_string(),
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_frames() {
+        let events = [
+            event(0, TerminalEventType::Output, "$ "),
+            event(1, TerminalEventType::Input, "ls\r"),
+            event(2, TerminalEventType::Output, "file\r\n"),
+            event(60, TerminalEventType::Output, "$ "),
+        ];
+        let options = PlaybackOptions { speed: 2.0, idle_limit: Some(Duration::from_secs(5)) };
+        let scheduled = frames(&events, &options);
+        let delays: Vec<u64> = scheduled.iter().map(|(delay, _)| delay.as_millis() as u64).collect();
+        assert_eq!(delays, [0, 1000, 2500]);
+        assert_eq!(scheduled[1].1, "file\r\n");
+
+        let unlimited = PlaybackOptions { speed: 1.0, idle_limit: None };
+        assert_eq!(frames(&events, &unlimited)[2].0, Duration::from_secs(58));
+        assert!(PlaybackOptions { speed: 0.0, idle_limit: None }.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_play_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc-123.jsonl");
+        let lines: Vec<String> = [event(0, TerminalEventType::Output, "hello "), event(0, TerminalEventType::Output, "world")]
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap())
+            .collect();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        assert_eq!(resolve_recording("abc-123", Some(dir.path())).unwrap(), path);
+        assert!(resolve_recording("../abc-123", Some(dir.path())).is_err());
+
+        let mut out = Vec::new();
+        play(&path, &PlaybackOptions::default(), None, &mut out).await.unwrap();
+        assert_eq!(out, b"hello world");
+    }
+
+    #[test]
+    fn test_frames_from_bookmark() {
+        let events = [
+            event(0, TerminalEventType::Output, "$ "),
+            event(3, TerminalEventType::Output, "make\r\n"),
+            event(4, TerminalEventType::Bookmark, "build"),
+            event(5, TerminalEventType::Output, "cc main.c\r\n"),
+            event(6, TerminalEventType::Output, "$ "),
+        ];
+        assert_eq!(bookmarks(&events).len(), 1);
+
+        let options = PlaybackOptions { speed: 1.0, idle_limit: None };
+        let scheduled = frames_from(&events, &options, "build").unwrap();
+        let delays: Vec<u64> = scheduled.iter().map(|(delay, _)| delay.as_secs()).collect();
+        assert_eq!(delays, [0, 0, 0, 1]);
+        assert_eq!(scheduled[2].1, "cc main.c\r\n");
+        assert!(frames_from(&events, &options, "deploy").is_err());
+    }
+}
@@ -0,0 +1,436 @@
+use crate::types::{AppError, AppResult, AutocompleteSuggestion, SuggestionType};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use wasmi::{Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+pub type SharedPluginHost = Arc<PluginHost>;
+
+pub const MANIFEST_FILE_NAME: &str = "plugin.json";
+
+// Upper bounds for a single hook call, so a broken plugin can't hang or bloat the terminal
+const CALL_FUEL: u64 = 10_000_000;
+const MAX_MEMORY_BYTES: usize = 16 * 1024 * 1024;
+
+// What a plugin may do. A plugin asks for capabilities in its manifest and gets the ones the user grants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginCapability {
+    // Rewrite terminal output before it is displayed
+    OutputFilter,
+    // Add autocomplete suggestions
+    Autocomplete,
+    // Provide commands the user can run from the UI
+    Commands,
+    // Write to the application log through host_log
+    Log,
+}
+
+// plugin.json, next to the module in the plugin's own directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    #[serde(default = "default_module")]
+    pub module: String,
+    #[serde(default)]
+    pub capabilities: Vec<PluginCapability>,
+    #[serde(default)]
+    pub commands: Vec<String>,
+}
+
+fn default_module() -> String {
+    "plugin.wasm".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginInfo {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub requested: Vec<PluginCapability>,
+    pub granted: Vec<PluginCapability>,
+    pub commands: Vec<String>,
+}
+
+struct PluginState {
+    name: String,
+    granted: Vec<PluginCapability>,
+    limits: StoreLimits,
+}
+
+// The module ABI: it exports `memory` and `alloc(len) -> ptr`, and any of the hooks
+// `filter_output`, `autocomplete` and `run_command`. A hook takes (ptr, len) of a UTF-8
+// input and returns (ptr << 32 | len) of its UTF-8 result, or a negative value for "nothing".
+struct PluginInstance {
+    store: Store<PluginState>,
+    instance: Instance,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+}
+
+impl PluginInstance {
+    fn has_hook(&self, hook: &str) -> bool {
+        self.instance.get_export(&self.store, hook).is_some()
+    }
+
+    fn call_hook(&mut self, hook: &str, input: &[u8]) -> AppResult<Option<String>> {
+        let name = self.store.data().name.clone();
+        let failed = |e: &dyn std::fmt::Display| AppError::OperationFailed(format!("Plugin {} {} failed: {}", name, hook, e));
+
+        let func = self.instance.get_typed_func::<(i32, i32), i64>(&self.store, hook)
+            .map_err(|e| failed(&e))?;
+
+        // Top up to the same budget for every call, and drop what's left afterwards
+        let consumed_before = self.store.fuel_consumed().unwrap_or_default();
+        self.store.add_fuel(CALL_FUEL).map_err(|e| failed(&e))?;
+        let result = self.call_with_input(&func, input);
+        let used = self.store.fuel_consumed().unwrap_or_default() - consumed_before;
+        let _ = self.store.consume_fuel(CALL_FUEL.saturating_sub(used));
+
+        result.map_err(|e| failed(&e))
+    }
+
+    fn call_with_input(&mut self, func: &TypedFunc<(i32, i32), i64>, input: &[u8]) -> Result<Option<String>, String> {
+        let len = i32::try_from(input.len()).map_err(|_| "input too large".to_string())?;
+        let ptr = self.alloc.call(&mut self.store, len).map_err(|e| e.to_string())?;
+        self.memory.write(&mut self.store, ptr as u32 as usize, input).map_err(|e| e.to_string())?;
+
+        let packed = func.call(&mut self.store, (ptr, len)).map_err(|e| e.to_string())?;
+        if packed < 0 {
+            return Ok(None);
+        }
+        let (ptr, len) = ((packed >> 32) as u32 as usize, packed as u32 as usize);
+        let mut output = vec![0; len];
+        self.memory.read(&self.store, ptr, &mut output).map_err(|e| e.to_string())?;
+        String::from_utf8(output).map(Some).map_err(|_| "result is not UTF-8".to_string())
+    }
+}
+
+struct Plugin {
+    manifest: PluginManifest,
+    instance: Mutex<PluginInstance>,
+}
+
+impl Plugin {
+    fn granted(&self) -> Vec<PluginCapability> {
+        self.instance.lock().store.data().granted.clone()
+    }
+
+    fn allows(&self, capability: PluginCapability) -> bool {
+        self.instance.lock().store.data().granted.contains(&capability)
+    }
+
+    fn info(&self) -> PluginInfo {
+        PluginInfo {
+            name: self.manifest.name.clone(),
+            version: self.manifest.version.clone(),
+            description: self.manifest.description.clone(),
+            requested: self.manifest.capabilities.clone(),
+            granted: self.granted(),
+            commands: self.manifest.commands.clone(),
+        }
+    }
+}
+
+pub struct PluginHost {
+    engine: Engine,
+    plugins: DashMap<String, Arc<Plugin>>,
+    grants: DashMap<String, Vec<PluginCapability>>,
+    grants_path: Option<PathBuf>,
+}
+
+impl PluginHost {
+    pub fn new() -> Self {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Self {
+            engine: Engine::new(&config),
+            plugins: DashMap::new(),
+            grants: DashMap::new(),
+            grants_path: None,
+        }
+    }
+
+    // Load every plugin under plugin_dir. One broken plugin is logged and skipped.
+    pub async fn load(plugin_dir: PathBuf, grants_path: PathBuf) -> AppResult<Self> {
+        let mut host = Self::new();
+
+        if tokio::fs::try_exists(&grants_path).await? {
+            let content = tokio::fs::read_to_string(&grants_path).await?;
+            let grants: HashMap<String, Vec<PluginCapability>> = serde_json::from_str(&content)?;
+            host.grants.extend(grants);
+        }
+        host.grants_path = Some(grants_path);
+
+        if tokio::fs::try_exists(&plugin_dir).await? {
+            let mut entries = tokio::fs::read_dir(&plugin_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if !entry.file_type().await?.is_dir() {
+                    continue;
+                }
+                match host.load_plugin(&entry.path()).await {
+                    Ok(name) => log::info!("Loaded plugin {}", name),
+                    Err(e) => log::warn!("Skipping plugin in {}: {}", entry.path().display(), e),
+                }
+            }
+        }
+
+        Ok(host)
+    }
+
+    async fn load_plugin(&self, dir: &Path) -> AppResult<String> {
+        let content = tokio::fs::read_to_string(dir.join(MANIFEST_FILE_NAME)).await?;
+        let manifest: PluginManifest = serde_json::from_str(&content)?;
+        // The module has to stay inside the plugin's directory
+        if Path::new(&manifest.module).components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+            return Err(AppError::ValidationError(format!("Invalid module path: {}", manifest.module)));
+        }
+        let wasm = tokio::fs::read(dir.join(&manifest.module)).await?;
+        let name = manifest.name.clone();
+        self.add_plugin(manifest, &wasm)?;
+        Ok(name)
+    }
+
+    pub fn add_plugin(&self, manifest: PluginManifest, wasm: &[u8]) -> AppResult<()> {
+        if self.plugins.contains_key(&manifest.name) {
+            return Err(AppError::ValidationError(format!("Plugin {} is already loaded", manifest.name)));
+        }
+        let invalid = |e: wasmi::Error| AppError::ValidationError(format!("Invalid plugin module {}: {}", manifest.name, e));
+
+        let module = Module::new(&self.engine, wasm).map_err(invalid)?;
+        let granted = self.effective_grants(&manifest);
+        let state = PluginState {
+            name: manifest.name.clone(),
+            granted,
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).build(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.add_fuel(CALL_FUEL).map_err(|e| AppError::InternalError(e.to_string()))?;
+
+        let mut linker = Linker::<PluginState>::new(&self.engine);
+        linker.func_wrap("env", "host_log", host_log)
+            .map_err(|e| AppError::InternalError(e.to_string()))?;
+        let instance = linker.instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(invalid)?;
+
+        let memory = instance.get_memory(&store, "memory")
+            .ok_or_else(|| AppError::ValidationError(format!("Plugin {} does not export memory", manifest.name)))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc").map_err(invalid)?;
+
+        let name = manifest.name.clone();
+        let plugin = Plugin {
+            manifest,
+            instance: Mutex::new(PluginInstance { store, instance, memory, alloc }),
+        };
+        self.plugins.insert(name, Arc::new(plugin));
+        Ok(())
+    }
+
+    // Only what the manifest asks for and the user has allowed
+    fn effective_grants(&self, manifest: &PluginManifest) -> Vec<PluginCapability> {
+        self.grants.get(&manifest.name)
+            .map(|granted| {
+                manifest.capabilities.iter()
+                    .copied()
+                    .filter(|capability| granted.contains(capability))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn list(&self) -> Vec<PluginInfo> {
+        let mut plugins: Vec<PluginInfo> = self.plugins.iter().map(|plugin| plugin.info()).collect();
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        plugins
+    }
+
+    // Replace the plugin's granted capabilities. Anything its manifest doesn't ask for is refused.
+    pub async fn grant(&self, name: &str, capabilities: Vec<PluginCapability>) -> AppResult<PluginInfo> {
+        let plugin = self.get(name)?;
+        if let Some(extra) = capabilities.iter().find(|capability| !plugin.manifest.capabilities.contains(capability)) {
+            return Err(AppError::PermissionDenied(format!("Plugin {} did not request {:?}", name, extra)));
+        }
+
+        self.grants.insert(name.to_string(), capabilities);
+        plugin.instance.lock().store.data_mut().granted = self.effective_grants(&plugin.manifest);
+        self.persist().await?;
+
+        log::info!("Plugin {} granted {:?}", name, plugin.granted());
+        Ok(plugin.info())
+    }
+
+    // Pass output through every plugin allowed to filter it, in name order
+    pub fn filter_output(&self, session_id: &str, output: String) -> String {
+        let input = |data: &str| serde_json::json!({ "sessionId": session_id, "data": data }).to_string();
+
+        self.plugins_with(PluginCapability::OutputFilter, "filter_output")
+            .into_iter()
+            .fold(output, |output, plugin| {
+                match plugin.instance.lock().call_hook("filter_output", input(&output).as_bytes()) {
+                    Ok(Some(filtered)) => filtered,
+                    Ok(None) => output,
+                    Err(e) => {
+                        log::warn!("{}", e);
+                        output
+                    }
+                }
+            })
+    }
+
+    pub fn autocomplete(&self, input: &str, cursor_position: usize) -> Vec<AutocompleteSuggestion> {
+        let request = serde_json::json!({ "input": input, "cursorPosition": cursor_position }).to_string();
+
+        self.plugins_with(PluginCapability::Autocomplete, "autocomplete")
+            .into_iter()
+            .flat_map(|plugin| {
+                let result = plugin.instance.lock().call_hook("autocomplete", request.as_bytes());
+                let suggestions = result
+                    .and_then(|output| Ok(serde_json::from_str::<Vec<String>>(output.as_deref().unwrap_or("[]"))?))
+                    .unwrap_or_else(|e| {
+                        log::warn!("{}", e);
+                        Vec::new()
+                    });
+                let source = plugin.manifest.name.clone();
+                suggestions.into_iter().map(move |text| AutocompleteSuggestion {
+                    text,
+                    description: Some(source.clone()),
+                    suggestion_type: SuggestionType::Command,
+                })
+            })
+            .collect()
+    }
+
+    pub fn run_command(&self, name: &str, command: &str, args: &[String]) -> AppResult<String> {
+        let plugin = self.get(name)?;
+        if !plugin.allows(PluginCapability::Commands) {
+            return Err(AppError::PermissionDenied(format!("Plugin {} is not allowed to run commands", name)));
+        }
+        if !plugin.manifest.commands.iter().any(|declared| declared == command) {
+            return Err(AppError::NotFound(format!("Plugin {} has no command {}", name, command)));
+        }
+
+        let request = serde_json::json!({ "command": command, "args": args }).to_string();
+        let output = plugin.instance.lock().call_hook("run_command", request.as_bytes())?;
+        Ok(output.unwrap_or_default())
+    }
+
+    fn get(&self, name: &str) -> AppResult<Arc<Plugin>> {
+        self.plugins.get(name)
+            .map(|plugin| plugin.clone())
+            .ok_or_else(|| AppError::NotFound(format!("Plugin {} not found", name)))
+    }
+
+    fn plugins_with(&self, capability: PluginCapability, hook: &str) -> Vec<Arc<Plugin>> {
+        let mut plugins: Vec<Arc<Plugin>> = self.plugins.iter()
+            .filter(|plugin| plugin.allows(capability) && plugin.instance.lock().has_hook(hook))
+            .map(|plugin| plugin.clone())
+            .collect();
+        plugins.sort_by(|a, b| a.manifest.name.cmp(&b.manifest.name));
+        plugins
+    }
+
+    async fn persist(&self) -> AppResult<()> {
+        if let Some(path) = &self.grants_path {
+            let grants: HashMap<String, Vec<PluginCapability>> = self.grants.iter()
+                .map(|entry| (entry.key().clone(), entry.value().clone()))
+                .collect();
+            let content = serde_json::to_string_pretty(&grants)?;
+            tokio::fs::write(path, content).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for PluginHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// env.host_log(level, ptr, len): 0 error, 1 warn, 2 info, anything else debug
+fn host_log(caller: Caller<'_, PluginState>, level: i32, ptr: i32, len: i32) -> Result<(), wasmi::core::Trap> {
+    let state = caller.data();
+    if !state.granted.contains(&PluginCapability::Log) {
+        return Err(wasmi::core::Trap::new(format!("plugin {} is not allowed to log", state.name)));
+    }
+    let memory = caller.get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmi::core::Trap::new("plugin does not export memory"))?;
+
+    let mut message = vec![0; len.max(0) as usize];
+    memory.read(&caller, ptr as u32 as usize, &mut message)
+        .map_err(|e| wasmi::core::Trap::new(e.to_string()))?;
+    let message = String::from_utf8_lossy(&message);
+
+    match level {
+        0 => log::error!("[plugin {}] {}", state.name, message),
+        1 => log::warn!("[plugin {}] {}", state.name, message),
+        2 => log::info!("[plugin {}] {}", state.name, message),
+        _ => log::debug!("[plugin {}] {}", state.name, message),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bump allocator; filter_output always answers "[filtered]", run_command logs first
+    const TEST_PLUGIN: &str = r#"
+        (module
+          (import "env" "host_log" (func $log (param i32 i32 i32)))
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (data (i32.const 16) "[filtered]")
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "filter_output") (param i32 i32) (result i64)
+            (i64.or (i64.shl (i64.const 16) (i64.const 32)) (i64.const 10)))
+          (func (export "run_command") (param $ptr i32) (param $len i32) (result i64)
+            (call $log (i32.const 2) (local.get $ptr) (local.get $len))
+            (i64.const -1)))
+    "#;
+
+    fn manifest() -> PluginManifest {
+        PluginManifest {
+            name: "test".to_string(),
+            version: "1.0.0".to_string(),
+            description: None,
+            module: default_module(),
+            capabilities: vec![PluginCapability::OutputFilter, PluginCapability::Commands],
+            commands: vec!["hello".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks_need_granted_capabilities() {
+        let host = PluginHost::new();
+        host.add_plugin(manifest(), &wat::parse_str(TEST_PLUGIN).unwrap()).unwrap();
+
+        assert_eq!(host.filter_output("s1", "ls".to_string()), "ls");
+        assert!(matches!(host.run_command("test", "hello", &[]), Err(AppError::PermissionDenied(_))));
+
+        host.grant("test", vec![PluginCapability::OutputFilter, PluginCapability::Commands]).await.unwrap();
+        assert_eq!(host.filter_output("s1", "ls".to_string()), "[filtered]");
+        assert!(matches!(host.run_command("test", "nope", &[]), Err(AppError::NotFound(_))));
+        // host_log traps: Log was never requested, so it can't be granted
+        assert!(host.run_command("test", "hello", &[]).is_err());
+        assert!(host.grant("test", vec![PluginCapability::Log]).await.is_err());
+    }
+
+    #[test]
+    fn test_rejects_invalid_module() {
+        let host = PluginHost::new();
+        assert!(matches!(host.add_plugin(manifest(), b"not wasm"), Err(AppError::ValidationError(_))));
+    }
+}
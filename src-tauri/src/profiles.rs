@@ -0,0 +1,950 @@
+use crate::credentials::{CredentialStore, StoredCredential};
+use crate::diagnostics::{self, PortState};
+use crate::ssh::{approval, charset, tags, validate_defaults, SSHManager};
+use crate::types::{AppError, AppResult, ApprovalMethod, ConnectApproval, CredentialSource, FileTransferProtocol, SSHConnectionConfig, SSHSession, SessionDefaults, SessionTags};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const DEFAULT_SSH_PORT: u16 = 22;
+
+pub type SharedProfileStore = Arc<ProfileStore>;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProfileAuthMethod {
+    Password,
+    PrivateKey,
+}
+
+// Saved connection settings. Secrets are never written here, only to the OS keyring.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionProfile {
+    pub id: String,
+    pub name: String,
+    // Where the profile is filed, e.g. "Work/Production"
+    #[serde(default)]
+    pub folder: Option<String>,
+    pub hostname: String,
+    pub port: u16,
+    pub username: String,
+    #[serde(rename = "authMethod")]
+    pub auth_method: ProfileAuthMethod,
+    #[serde(rename = "keepAlive")]
+    pub keep_alive: Option<bool>,
+    #[serde(rename = "readyTimeout")]
+    pub ready_timeout: Option<u32>,
+    // Other ways to reach the same host, e.g. a VPN IP, tried after the hostname
+    #[serde(rename = "fallbackAddresses", default)]
+    pub fallback_addresses: Vec<String>,
+    // Probe every address at once and take the first that answers
+    #[serde(rename = "raceAddresses", default)]
+    pub race_addresses: bool,
+    // Terminal charset for hosts that aren't UTF-8, e.g. "gbk"
+    #[serde(default)]
+    pub charset: Option<String>,
+    // The address that answered last time, tried first
+    #[serde(rename = "lastAddress", default)]
+    pub last_address: Option<String>,
+    // Remote directories to jump to in the SFTP browser
+    #[serde(default)]
+    pub bookmarks: Vec<DirectoryBookmark>,
+    // Labels, colour and notes, copied onto sessions opened from the profile
+    #[serde(flatten)]
+    pub tags: SessionTags,
+    // Connecting needs a second step first, a TOTP code or an admin's approval
+    #[serde(default)]
+    pub protection: Option<ApprovalMethod>,
+    // Terminal and transfer settings every session to the host starts with
+    #[serde(default)]
+    pub defaults: SessionDefaults,
+    #[serde(rename = "hasStoredCredential")]
+    pub has_stored_credential: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileRequest {
+    pub name: String,
+    #[serde(default)]
+    pub folder: Option<String>,
+    pub hostname: String,
+    pub port: Option<u16>,
+    pub username: String,
+    #[serde(rename = "authMethod")]
+    pub auth_method: ProfileAuthMethod,
+    // Secrets are moved into the keyring; omit them on update to keep the stored ones
+    pub password: Option<String>,
+    #[serde(rename = "privateKey")]
+    pub private_key: Option<String>,
+    pub passphrase: Option<String>,
+    #[serde(rename = "keepAlive")]
+    pub keep_alive: Option<bool>,
+    #[serde(rename = "readyTimeout")]
+    pub ready_timeout: Option<u32>,
+    #[serde(rename = "fallbackAddresses", default)]
+    pub fallback_addresses: Vec<String>,
+    #[serde(rename = "raceAddresses", default)]
+    pub race_addresses: bool,
+    #[serde(default)]
+    pub charset: Option<String>,
+    #[serde(flatten)]
+    pub tags: SessionTags,
+    #[serde(default)]
+    pub protection: Option<ApprovalMethod>,
+    // Base32, as authenticator apps show it. Kept in the keyring; omit it on update to keep the stored one.
+    #[serde(rename = "totpSecret", default)]
+    pub totp_secret: Option<String>,
+    #[serde(default)]
+    pub defaults: SessionDefaults,
+}
+
+// Settings for a single connect that differ from the profile's. Nothing here is saved.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileOverrides {
+    pub username: Option<String>,
+    pub port: Option<u16>,
+    // Used instead of the stored secrets when given
+    pub password: Option<String>,
+    #[serde(rename = "privateKey")]
+    pub private_key: Option<String>,
+    pub passphrase: Option<String>,
+    #[serde(rename = "keepAlive")]
+    pub keep_alive: Option<bool>,
+    #[serde(rename = "readyTimeout")]
+    pub ready_timeout: Option<u32>,
+    pub charset: Option<String>,
+    // Only the settings given here replace the profile's
+    #[serde(default)]
+    pub defaults: SessionDefaults,
+}
+
+impl ProfileOverrides {
+    fn has_credential(&self) -> bool {
+        self.password.is_some() || self.private_key.is_some()
+    }
+}
+
+impl ConnectionProfile {
+    // Whether the profile is filed in the folder or one below it
+    pub fn in_folder(&self, folder: &str) -> bool {
+        let Some(folder) = normalize_folder(Some(folder)) else {
+            return true;
+        };
+        self.folder.as_deref().is_some_and(|filed| {
+            filed == folder || filed.strip_prefix(folder.as_str()).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    // Every address to try, the one that worked last time first
+    pub fn addresses(&self) -> Vec<String> {
+        let configured: Vec<&String> = std::iter::once(&self.hostname).chain(&self.fallback_addresses).collect();
+        // A remembered address that was since removed from the profile no longer counts
+        let last = self.last_address.as_ref()
+            .filter(|last| configured.iter().any(|address| address.eq_ignore_ascii_case(last)));
+
+        let mut addresses: Vec<String> = Vec::new();
+        for address in last.into_iter().chain(configured) {
+            if !addresses.iter().any(|known| known.eq_ignore_ascii_case(address)) {
+                addresses.push(address.clone());
+            }
+        }
+        addresses
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryBookmark {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookmarkRequest {
+    // Defaults to the last part of the path
+    pub name: Option<String>,
+    pub path: String,
+}
+
+impl ProfileRequest {
+    fn credential(&self) -> StoredCredential {
+        StoredCredential {
+            password: self.password.clone(),
+            private_key: self.private_key.clone(),
+            passphrase: self.passphrase.clone(),
+        }
+    }
+
+    // Without the spaces apps group it with
+    fn totp_secret(&self) -> Option<String> {
+        self.totp_secret.as_deref()
+            .map(|secret| secret.split_whitespace().collect::<String>().to_uppercase())
+            .filter(|secret| !secret.is_empty())
+    }
+}
+
+pub struct ProfileStore {
+    profiles: Arc<DashMap<String, ConnectionProfile>>,
+    credentials: Arc<CredentialStore>,
+    storage_path: Option<PathBuf>,
+}
+
+impl ProfileStore {
+    pub fn new(credentials: Arc<CredentialStore>) -> Self {
+        Self {
+            profiles: Arc::new(DashMap::new()),
+            credentials,
+            storage_path: None,
+        }
+    }
+
+    pub async fn load(storage_path: PathBuf, credentials: Arc<CredentialStore>) -> AppResult<Self> {
+        let mut store = Self::new(credentials);
+
+        if tokio::fs::try_exists(&storage_path).await? {
+            let content = tokio::fs::read_to_string(&storage_path).await?;
+            let profiles: Vec<ConnectionProfile> = serde_json::from_str(&content)?;
+            for profile in profiles {
+                store.profiles.insert(profile.id.clone(), profile);
+            }
+        }
+
+        log::info!("Loaded {} connection profiles from {:?}", store.profiles.len(), storage_path);
+        store.storage_path = Some(storage_path);
+        Ok(store)
+    }
+
+    pub async fn create(&self, request: ProfileRequest) -> AppResult<ConnectionProfile> {
+        validate_request(&request)?;
+
+        let now = Utc::now();
+        let mut profile = ConnectionProfile {
+            id: Uuid::new_v4().to_string(),
+            name: request.name.clone(),
+            folder: normalize_folder(request.folder.as_deref()),
+            hostname: request.hostname.clone(),
+            port: request.port.unwrap_or(DEFAULT_SSH_PORT),
+            username: request.username.clone(),
+            auth_method: request.auth_method,
+            keep_alive: request.keep_alive,
+            ready_timeout: request.ready_timeout,
+            fallback_addresses: fallback_addresses(&request),
+            race_addresses: request.race_addresses,
+            charset: request.charset.clone(),
+            last_address: None,
+            bookmarks: Vec::new(),
+            tags: tags::normalize(request.tags.clone())?,
+            protection: request.protection,
+            defaults: request.defaults.clone(),
+            has_stored_credential: false,
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.save_protection(&profile, &request, None).await?;
+        let credential = request.credential();
+        if !credential.is_empty() {
+            self.credentials.save(&credential_key(&profile.id), &profile.name, &credential).await?;
+            profile.has_stored_credential = true;
+        }
+
+        self.profiles.insert(profile.id.clone(), profile.clone());
+        self.save().await?;
+
+        log::info!("Connection profile created: {} ({})", profile.name, profile.id);
+        Ok(profile)
+    }
+
+    pub async fn update(&self, profile_id: &str, request: ProfileRequest) -> AppResult<ConnectionProfile> {
+        validate_request(&request)?;
+
+        let mut profile = self.get(profile_id)
+            .ok_or_else(|| AppError::NotFound(format!("Profile {}", profile_id)))?;
+
+        profile.name = request.name.clone();
+        profile.folder = normalize_folder(request.folder.as_deref());
+        profile.hostname = request.hostname.clone();
+        profile.port = request.port.unwrap_or(DEFAULT_SSH_PORT);
+        profile.username = request.username.clone();
+        profile.auth_method = request.auth_method;
+        profile.keep_alive = request.keep_alive;
+        profile.ready_timeout = request.ready_timeout;
+        profile.fallback_addresses = fallback_addresses(&request);
+        profile.race_addresses = request.race_addresses;
+        profile.charset = request.charset.clone();
+        profile.tags = tags::normalize(request.tags.clone())?;
+        profile.defaults = request.defaults.clone();
+        let previous_protection = std::mem::replace(&mut profile.protection, request.protection);
+        profile.updated_at = Utc::now();
+
+        self.save_protection(&profile, &request, previous_protection).await?;
+        let credential = request.credential();
+        if !credential.is_empty() {
+            self.credentials.save(&credential_key(profile_id), &profile.name, &credential).await?;
+            profile.has_stored_credential = true;
+        }
+
+        self.profiles.insert(profile.id.clone(), profile.clone());
+        self.save().await?;
+
+        log::info!("Connection profile updated: {} ({})", profile.name, profile.id);
+        Ok(profile)
+    }
+
+    pub async fn delete(&self, profile_id: &str) -> AppResult<bool> {
+        let Some((_, profile)) = self.profiles.remove(profile_id) else {
+            return Ok(false);
+        };
+
+        if profile.has_stored_credential {
+            self.credentials.delete(&credential_key(profile_id)).await?;
+        }
+        if profile.protection == Some(ApprovalMethod::Totp) {
+            self.credentials.delete(&totp_key(profile_id)).await?;
+        }
+        self.save().await?;
+
+        log::info!("Connection profile deleted: {} ({})", profile.name, profile.id);
+        Ok(true)
+    }
+
+    pub fn get(&self, profile_id: &str) -> Option<ConnectionProfile> {
+        self.profiles.get(profile_id).map(|entry| entry.value().clone())
+    }
+
+    pub fn list(&self) -> Vec<ConnectionProfile> {
+        let mut profiles: Vec<ConnectionProfile> = self.profiles.iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        profiles.sort_by_key(|profile| profile.name.to_lowercase());
+        profiles
+    }
+
+    pub fn list_folder(&self, folder: &str) -> Vec<ConnectionProfile> {
+        self.list().into_iter().filter(|profile| profile.in_folder(folder)).collect()
+    }
+
+    // Every folder profiles are filed in, with the folders above them, in name order
+    pub fn folders(&self) -> Vec<String> {
+        let mut folders: Vec<String> = Vec::new();
+        for entry in self.profiles.iter() {
+            let Some(folder) = entry.folder.as_deref() else {
+                continue;
+            };
+            let parents = folder.match_indices('/').map(|(end, _)| &folder[..end]);
+            for path in parents.chain(std::iter::once(folder)) {
+                if !folders.iter().any(|known| known == path) {
+                    folders.push(path.to_string());
+                }
+            }
+        }
+        folders.sort_by_key(|folder| folder.to_lowercase());
+        folders
+    }
+
+    // A profile with stored secrets for the given host, used to reconnect sessions that were saved without them
+    pub fn find_with_credentials(&self, hostname: &str, port: u16, username: &str) -> Option<ConnectionProfile> {
+        self.profiles.iter()
+            .find(|entry| {
+                entry.has_stored_credential
+                    && entry.hostname.eq_ignore_ascii_case(hostname)
+                    && entry.port == port
+                    && entry.username == username
+            })
+            .map(|entry| entry.value().clone())
+    }
+
+    // Build a session config for the profile, pulling its secrets from the keyring
+    pub async fn build_config(&self, profile_id: &str) -> AppResult<SSHConnectionConfig> {
+        self.build_config_with(profile_id, &ProfileOverrides::default()).await
+    }
+
+    // Like build_config, with some of the profile's settings replaced for this connect only
+    pub async fn build_config_with(&self, profile_id: &str, overrides: &ProfileOverrides) -> AppResult<SSHConnectionConfig> {
+        let profile = self.get(profile_id)
+            .ok_or_else(|| AppError::NotFound(format!("Profile {}", profile_id)))?;
+        validate_overrides(overrides)?;
+
+        let (password, private_key, passphrase) = if overrides.has_credential() {
+            (overrides.password.clone(), overrides.private_key.clone(), overrides.passphrase.clone())
+        } else {
+            let credential = if profile.has_stored_credential {
+                self.credentials.get(&credential_key(profile_id)).await?.unwrap_or_default()
+            } else {
+                StoredCredential::default()
+            };
+            match profile.auth_method {
+                ProfileAuthMethod::Password => (credential.password, None, credential.passphrase),
+                ProfileAuthMethod::PrivateKey => (None, credential.private_key, credential.passphrase),
+            }
+        };
+        if password.is_none() && private_key.is_none() {
+            return Err(AppError::InvalidConfiguration(format!(
+                "No stored credentials for profile {}", profile.name
+            )));
+        }
+
+        let approval = match profile.protection {
+            Some(method) => Some(Box::new(ConnectApproval {
+                profile_id: profile_id.to_string(),
+                profile_name: profile.name.clone(),
+                method,
+                totp_secret: match method {
+                    ApprovalMethod::Totp => self.credentials.get(&totp_key(profile_id)).await?.and_then(|secret| secret.password),
+                    ApprovalMethod::Admin => None,
+                },
+            })),
+            None => None,
+        };
+
+        let defaults = merge_defaults(profile.defaults, &overrides.defaults);
+        validate_defaults(&defaults)?;
+
+        Ok(SSHConnectionConfig {
+            id: Uuid::new_v4().to_string(),
+            hostname: profile.hostname,
+            port: overrides.port.unwrap_or(profile.port),
+            username: overrides.username.as_deref().map(str::trim).map(str::to_string).unwrap_or(profile.username),
+            password,
+            private_key,
+            private_key_path: None,
+            passphrase,
+            keep_alive: overrides.keep_alive.or(profile.keep_alive),
+            ready_timeout: overrides.ready_timeout.or(profile.ready_timeout),
+            websocket_url: None,
+            collect_host_info: false,
+            charset: overrides.charset.clone().or(profile.charset),
+            terminal: None,
+            jump_hosts: Vec::new(),
+            keyboard_interactive: false,
+            file_transfer: FileTransferProtocol::Auto,
+            defaults,
+            // Secrets given for this connect aren't the stored ones, so their use isn't recorded against them
+            credential: (profile.has_stored_credential && !overrides.has_credential()).then(|| CredentialSource {
+                key: credential_key(profile_id),
+                profile_id: Some(profile_id.to_string()),
+            }),
+            approval,
+        })
+    }
+
+    // Like build_config, but connects to whichever of the profile's addresses answers
+    pub async fn connect_config(&self, profile_id: &str) -> AppResult<SSHConnectionConfig> {
+        self.connect_config_with(profile_id, &ProfileOverrides::default()).await
+    }
+
+    pub async fn connect_config_with(&self, profile_id: &str, overrides: &ProfileOverrides) -> AppResult<SSHConnectionConfig> {
+        let mut config = self.build_config_with(profile_id, overrides).await?;
+        let Some(profile) = self.get(profile_id) else {
+            return Ok(config);
+        };
+        let addresses = profile.addresses();
+        if addresses.len() < 2 {
+            return Ok(config);
+        }
+
+        // Nothing answering leaves the first address, so the connect reports its own error
+        let Some(address) = select_address(&addresses, config.port, profile.race_addresses).await else {
+            log::warn!("No address of profile {} answered on port {}", profile.name, config.port);
+            config.hostname = addresses[0].clone();
+            return Ok(config);
+        };
+        log::info!("Profile {} connecting via {}", profile.name, address);
+        if profile.last_address.as_deref() != Some(address.as_str()) {
+            self.record_address(profile_id, &address).await?;
+        }
+        config.hostname = address;
+        Ok(config)
+    }
+
+    // Connect a new session with the profile's settings and this connect's overrides. The session
+    // carries the profile's labels, so lists can tell prod from staging.
+    pub async fn connect_with_profile(&self, ssh_manager: &SSHManager, profile_id: &str, overrides: &ProfileOverrides) -> AppResult<SSHSession> {
+        let config = self.connect_config_with(profile_id, overrides).await?;
+        let tags = self.get(profile_id).map(|profile| profile.tags).unwrap_or_default();
+        let session = ssh_manager.create_session(config).await?;
+        let session = ssh_manager.set_tags(&session.id, tags).await?;
+        ssh_manager.connect(&session.id).await?;
+        Ok(session)
+    }
+
+    pub fn bookmarks(&self, profile_id: &str) -> AppResult<Vec<DirectoryBookmark>> {
+        self.get(profile_id)
+            .map(|profile| profile.bookmarks)
+            .ok_or_else(|| AppError::NotFound(format!("Profile {}", profile_id)))
+    }
+
+    pub async fn add_bookmark(&self, profile_id: &str, request: BookmarkRequest) -> AppResult<DirectoryBookmark> {
+        let (name, path) = validate_bookmark(&request)?;
+        let bookmark = {
+            let mut profile = self.profiles.get_mut(profile_id)
+                .ok_or_else(|| AppError::NotFound(format!("Profile {}", profile_id)))?;
+            if profile.bookmarks.iter().any(|bookmark| bookmark.path == path) {
+                return Err(AppError::ValidationError(format!("{} is already bookmarked", path)));
+            }
+            let bookmark = DirectoryBookmark {
+                id: Uuid::new_v4().to_string(),
+                name,
+                path,
+                created_at: Utc::now(),
+            };
+            profile.bookmarks.push(bookmark.clone());
+            bookmark
+        };
+        self.save().await?;
+        Ok(bookmark)
+    }
+
+    pub async fn update_bookmark(&self, profile_id: &str, bookmark_id: &str, request: BookmarkRequest) -> AppResult<DirectoryBookmark> {
+        let (name, path) = validate_bookmark(&request)?;
+        let bookmark = {
+            let mut profile = self.profiles.get_mut(profile_id)
+                .ok_or_else(|| AppError::NotFound(format!("Profile {}", profile_id)))?;
+            if profile.bookmarks.iter().any(|bookmark| bookmark.path == path && bookmark.id != bookmark_id) {
+                return Err(AppError::ValidationError(format!("{} is already bookmarked", path)));
+            }
+            let bookmark = profile.bookmarks.iter_mut()
+                .find(|bookmark| bookmark.id == bookmark_id)
+                .ok_or_else(|| AppError::NotFound(format!("Bookmark {}", bookmark_id)))?;
+            bookmark.name = name;
+            bookmark.path = path;
+            bookmark.clone()
+        };
+        self.save().await?;
+        Ok(bookmark)
+    }
+
+    pub async fn delete_bookmark(&self, profile_id: &str, bookmark_id: &str) -> AppResult<bool> {
+        let removed = {
+            let mut profile = self.profiles.get_mut(profile_id)
+                .ok_or_else(|| AppError::NotFound(format!("Profile {}", profile_id)))?;
+            let before = profile.bookmarks.len();
+            profile.bookmarks.retain(|bookmark| bookmark.id != bookmark_id);
+            profile.bookmarks.len() != before
+        };
+        if removed {
+            self.save().await?;
+        }
+        Ok(removed)
+    }
+
+    // Store a new TOTP secret, or drop the stored one once TOTP no longer protects the profile
+    async fn save_protection(&self, profile: &ConnectionProfile, request: &ProfileRequest, previous: Option<ApprovalMethod>) -> AppResult<()> {
+        let had_secret = previous == Some(ApprovalMethod::Totp);
+        if profile.protection != Some(ApprovalMethod::Totp) {
+            if had_secret {
+                self.credentials.delete(&totp_key(&profile.id)).await?;
+            }
+            return Ok(());
+        }
+
+        match request.totp_secret() {
+            Some(secret) => {
+                let credential = StoredCredential {
+                    password: Some(secret),
+                    ..StoredCredential::default()
+                };
+                self.credentials.save(&totp_key(&profile.id), &format!("{} (TOTP)", profile.name), &credential).await
+            }
+            None if had_secret => Ok(()),
+            None => Err(AppError::ValidationError("A TOTP secret is needed to protect a profile with TOTP".to_string())),
+        }
+    }
+
+    async fn record_address(&self, profile_id: &str, address: &str) -> AppResult<()> {
+        if let Some(mut profile) = self.profiles.get_mut(profile_id) {
+            profile.last_address = Some(address.to_string());
+        }
+        self.save().await
+    }
+
+    async fn save(&self) -> AppResult<()> {
+        let Some(path) = &self.storage_path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let content = serde_json::to_string_pretty(&self.list())?;
+        tokio::fs::write(path, content).await?;
+        Ok(())
+    }
+}
+
+fn credential_key(profile_id: &str) -> String {
+    format!("profile:{}", profile_id)
+}
+
+fn totp_key(profile_id: &str) -> String {
+    format!("profile-totp:{}", profile_id)
+}
+
+// Trimmed, without blanks, repeats or the hostname itself
+fn fallback_addresses(request: &ProfileRequest) -> Vec<String> {
+    let mut addresses: Vec<String> = Vec::new();
+    for address in request.fallback_addresses.iter().map(|address| address.trim()) {
+        if address.is_empty()
+            || address.eq_ignore_ascii_case(request.hostname.trim())
+            || addresses.iter().any(|known| known.eq_ignore_ascii_case(address))
+        {
+            continue;
+        }
+        addresses.push(address.to_string());
+    }
+    addresses
+}
+
+// The first address with the SSH port open, in order or whichever answers first
+async fn select_address(addresses: &[String], port: u16, race: bool) -> Option<String> {
+    let probe = |address: String| async move {
+        let check = diagnostics::check_port(&address, port, diagnostics::DEFAULT_CONNECT_TIMEOUT).await;
+        (check.state == PortState::Open).then_some(address)
+    };
+
+    if race {
+        let mut probes: FuturesUnordered<_> = addresses.iter().cloned().map(probe).collect();
+        while let Some(result) = probes.next().await {
+            if result.is_some() {
+                return result;
+            }
+        }
+        return None;
+    }
+
+    for address in addresses {
+        if let Some(address) = probe(address.clone()).await {
+            return Some(address);
+        }
+    }
+    None
+}
+
+// The bookmark's name and path, with the trailing slash dropped
+fn validate_bookmark(request: &BookmarkRequest) -> AppResult<(String, String)> {
+    let path = request.path.trim();
+    let path = if path.len() > 1 { path.trim_end_matches('/') } else { path };
+    if path.is_empty() {
+        return Err(AppError::ValidationError("Bookmark path cannot be empty".to_string()));
+    }
+    let name = match request.name.as_deref().map(str::trim).filter(|name| !name.is_empty()) {
+        Some(name) => name.to_string(),
+        None => path.rsplit('/').find(|part| !part.is_empty()).unwrap_or(path).to_string(),
+    };
+    Ok((name, path.to_string()))
+}
+
+// Trimmed parts joined by single slashes, so " Work / Production/" files with "Work/Production"
+fn normalize_folder(folder: Option<&str>) -> Option<String> {
+    let parts: Vec<&str> = folder?.split('/')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+// Set settings replace the profile's; auto-record can only be turned on for one connect
+fn merge_defaults(profile: SessionDefaults, overrides: &SessionDefaults) -> SessionDefaults {
+    SessionDefaults {
+        cols: overrides.cols.or(profile.cols),
+        rows: overrides.rows.or(profile.rows),
+        term: overrides.term.clone().or(profile.term),
+        scrollback_bytes: overrides.scrollback_bytes.or(profile.scrollback_bytes),
+        auto_record: overrides.auto_record || profile.auto_record,
+        max_bytes_per_second: overrides.max_bytes_per_second.or(profile.max_bytes_per_second),
+        max_concurrent_transfers: overrides.max_concurrent_transfers.or(profile.max_concurrent_transfers),
+    }
+}
+
+fn validate_overrides(overrides: &ProfileOverrides) -> AppResult<()> {
+    if overrides.username.as_deref().is_some_and(|username| username.trim().is_empty()) {
+        return Err(AppError::ValidationError("Username cannot be empty".to_string()));
+    }
+    if overrides.port == Some(0) {
+        return Err(AppError::ValidationError("Port number cannot be 0".to_string()));
+    }
+    charset::validate(overrides.charset.as_deref())
+}
+
+fn validate_request(request: &ProfileRequest) -> AppResult<()> {
+    if request.name.trim().is_empty() {
+        return Err(AppError::ValidationError("Profile name cannot be empty".to_string()));
+    }
+    if request.hostname.trim().is_empty() {
+        return Err(AppError::ValidationError("Hostname cannot be empty".to_string()));
+    }
+    if request.username.trim().is_empty() {
+        return Err(AppError::ValidationError("Username cannot be empty".to_string()));
+    }
+    if request.port == Some(0) {
+        return Err(AppError::ValidationError("Port number cannot be 0".to_string()));
+    }
+    if let Some(secret) = request.totp_secret() {
+        approval::decode_secret(&secret)?;
+    }
+    validate_defaults(&request.defaults)?;
+    charset::validate(request.charset.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: &str) -> ProfileRequest {
+        ProfileRequest {
+            name: name.to_string(),
+            folder: None,
+            hostname: "example.com".to_string(),
+            port: None,
+            username: "deploy".to_string(),
+            auth_method: ProfileAuthMethod::Password,
+            password: None,
+            private_key: None,
+            passphrase: None,
+            keep_alive: None,
+            ready_timeout: None,
+            fallback_addresses: Vec::new(),
+            race_addresses: false,
+            charset: None,
+            tags: SessionTags::default(),
+            protection: None,
+            totp_secret: None,
+            defaults: SessionDefaults::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_profile_crud() {
+        let store = ProfileStore::new(Arc::new(CredentialStore::new()));
+
+        let profile = store.create(request("staging")).await.unwrap();
+        assert_eq!(profile.port, 22);
+        assert!(!profile.has_stored_credential);
+
+        let mut update = request("production");
+        update.port = Some(2222);
+        update.tags.labels = vec!["prod".to_string()];
+        update.tags.color = Some("#D32F2F".to_string());
+        let updated = store.update(&profile.id, update).await.unwrap();
+        assert_eq!(updated.name, "production");
+        assert_eq!(updated.port, 2222);
+        assert_eq!(updated.created_at, profile.created_at);
+        assert_eq!(updated.tags.color.as_deref(), Some("#d32f2f"));
+
+        // Stored alongside the other fields
+        let json = serde_json::to_value(&updated).unwrap();
+        assert_eq!(json["labels"], serde_json::json!(["prod"]));
+        let restored: ConnectionProfile = serde_json::from_value(json).unwrap();
+        assert_eq!(restored.tags, updated.tags);
+
+        assert_eq!(store.list().len(), 1);
+        assert!(store.delete(&profile.id).await.unwrap());
+        assert!(!store.delete(&profile.id).await.unwrap());
+        assert!(store.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_profile_validation() {
+        let store = ProfileStore::new(Arc::new(CredentialStore::new()));
+
+        assert!(store.create(request(" ")).await.is_err());
+
+        let mut invalid_port = request("staging");
+        invalid_port.port = Some(0);
+        assert!(store.create(invalid_port).await.is_err());
+
+        let mut invalid_color = request("staging");
+        invalid_color.tags.color = Some("red".to_string());
+        assert!(store.create(invalid_color).await.is_err());
+
+        // TOTP protection needs a base32 secret to check codes against
+        let mut missing_secret = request("production");
+        missing_secret.protection = Some(ApprovalMethod::Totp);
+        assert!(store.create(missing_secret).await.is_err());
+        let mut invalid_secret = request("production");
+        invalid_secret.protection = Some(ApprovalMethod::Totp);
+        invalid_secret.totp_secret = Some("not base32!".to_string());
+        assert!(store.create(invalid_secret).await.is_err());
+
+        let mut invalid_term = request("staging");
+        invalid_term.defaults.term = Some("xterm; reboot".to_string());
+        assert!(store.create(invalid_term).await.is_err());
+        let mut no_transfers = request("staging");
+        no_transfers.defaults.max_concurrent_transfers = Some(0);
+        assert!(store.create(no_transfers).await.is_err());
+        assert!(store.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_session_defaults() {
+        let store = ProfileStore::new(Arc::new(CredentialStore::new()));
+        let mut create = request("build");
+        create.defaults = SessionDefaults {
+            cols: Some(200),
+            rows: Some(50),
+            term: Some("screen-256color".to_string()),
+            auto_record: true,
+            max_concurrent_transfers: Some(1),
+            ..Default::default()
+        };
+        let profile = store.create(create.clone()).await.unwrap();
+        assert_eq!(profile.defaults, create.defaults);
+
+        let json = serde_json::to_value(&profile).unwrap();
+        assert_eq!(json["defaults"]["autoRecord"], true);
+        assert_eq!(json["defaults"]["maxConcurrentTransfers"], 1);
+
+        // Profiles saved before there were defaults have none
+        let mut legacy = json;
+        legacy.as_object_mut().unwrap().remove("defaults");
+        let restored: ConnectionProfile = serde_json::from_value(legacy).unwrap();
+        assert!(restored.defaults.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bookmarks() {
+        let store = ProfileStore::new(Arc::new(CredentialStore::new()));
+        let profile = store.create(request("web")).await.unwrap();
+        let bookmark = |name: Option<&str>, path: &str| BookmarkRequest { name: name.map(str::to_string), path: path.to_string() };
+
+        let logs = store.add_bookmark(&profile.id, bookmark(None, " /var/log/nginx/ ")).await.unwrap();
+        assert_eq!((logs.name.as_str(), logs.path.as_str()), ("nginx", "/var/log/nginx"));
+        assert!(store.add_bookmark(&profile.id, bookmark(Some("again"), "/var/log/nginx")).await.is_err());
+        assert!(store.add_bookmark(&profile.id, bookmark(None, "  ")).await.is_err());
+        assert_eq!(store.add_bookmark(&profile.id, bookmark(None, "/")).await.unwrap().name, "/");
+
+        let renamed = store.update_bookmark(&profile.id, &logs.id, bookmark(Some("Web logs"), "/var/log/nginx")).await.unwrap();
+        assert_eq!(renamed.name, "Web logs");
+        assert_eq!(store.bookmarks(&profile.id).unwrap().len(), 2);
+
+        assert!(store.delete_bookmark(&profile.id, &logs.id).await.unwrap());
+        assert!(!store.delete_bookmark(&profile.id, &logs.id).await.unwrap());
+        assert!(matches!(store.bookmarks("missing"), Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_addresses() {
+        let store = ProfileStore::new(Arc::new(CredentialStore::new()));
+        let mut create = request("nas");
+        create.fallback_addresses = vec![" 10.8.0.2 ".to_string(), "".to_string(), "EXAMPLE.com".to_string(), "10.8.0.2".to_string()];
+        let mut profile = store.create(create).await.unwrap();
+        assert_eq!(profile.fallback_addresses, ["10.8.0.2"]);
+        assert_eq!(profile.addresses(), ["example.com", "10.8.0.2"]);
+
+        profile.last_address = Some("10.8.0.2".to_string());
+        assert_eq!(profile.addresses(), ["10.8.0.2", "example.com"]);
+        profile.last_address = Some("192.168.1.5".to_string());
+        assert_eq!(profile.addresses(), ["example.com", "10.8.0.2"]);
+    }
+
+    #[tokio::test]
+    async fn test_select_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Also loopback, but nothing listens there
+        let addresses = ["127.0.0.2".to_string(), "127.0.0.1".to_string()];
+
+        assert_eq!(select_address(&addresses, port, false).await.as_deref(), Some("127.0.0.1"));
+        assert_eq!(select_address(&addresses, port, true).await.as_deref(), Some("127.0.0.1"));
+        assert_eq!(select_address(&addresses[..1], port, false).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_build_config_requires_credentials() {
+        let store = ProfileStore::new(Arc::new(CredentialStore::new()));
+        let profile = store.create(request("staging")).await.unwrap();
+
+        assert!(store.build_config(&profile.id).await.is_err());
+        assert!(store.build_config("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_folders() {
+        let store = ProfileStore::new(Arc::new(CredentialStore::new()));
+        let mut web = request("web");
+        web.folder = Some(" Work / Production/ ".to_string());
+        let web = store.create(web).await.unwrap();
+        assert_eq!(web.folder.as_deref(), Some("Work/Production"));
+        let mut staging = request("staging");
+        staging.folder = Some("Work".to_string());
+        store.create(staging).await.unwrap();
+        let mut blank = request("home");
+        blank.folder = Some(" / ".to_string());
+        assert_eq!(store.create(blank).await.unwrap().folder, None);
+        let mut lookalike = request("workshop");
+        lookalike.folder = Some("Workshop".to_string());
+        store.create(lookalike).await.unwrap();
+
+        assert_eq!(store.folders(), ["Work", "Work/Production", "Workshop"]);
+        let names = |folder: &str| store.list_folder(folder).into_iter().map(|profile| profile.name).collect::<Vec<_>>();
+        assert_eq!(names("Work"), ["staging", "web"]);
+        assert_eq!(names("Work/Production/"), ["web"]);
+        assert_eq!(names(""), ["home", "staging", "web", "workshop"]);
+
+        let mut moved = request("web");
+        moved.folder = None;
+        assert_eq!(store.update(&web.id, moved).await.unwrap().folder, None);
+        assert_eq!(store.folders(), ["Work", "Workshop"]);
+    }
+
+    #[tokio::test]
+    async fn test_connect_overrides() {
+        let store = ProfileStore::new(Arc::new(CredentialStore::new()));
+        let mut create = request("build");
+        create.defaults.cols = Some(200);
+        create.defaults.max_concurrent_transfers = Some(2);
+        let profile = store.create(create).await.unwrap();
+
+        // Secrets given for the connect stand in for stored ones
+        let overrides = ProfileOverrides {
+            username: Some(" root ".to_string()),
+            port: Some(2222),
+            password: Some("hunter2".to_string()),
+            defaults: SessionDefaults { rows: Some(60), max_concurrent_transfers: Some(1), ..Default::default() },
+            ..Default::default()
+        };
+        let config = store.build_config_with(&profile.id, &overrides).await.unwrap();
+        assert_eq!((config.hostname.as_str(), config.port, config.username.as_str()), ("example.com", 2222, "root"));
+        assert_eq!(config.password.as_deref(), Some("hunter2"));
+        assert!(config.credential.is_none());
+        assert_eq!((config.defaults.cols, config.defaults.rows), (Some(200), Some(60)));
+        assert_eq!(config.defaults.max_concurrent_transfers, Some(1));
+        // Nothing of it is kept
+        assert_eq!(store.get(&profile.id).unwrap().username, "deploy");
+
+        let invalid = ProfileOverrides { port: Some(0), ..overrides.clone() };
+        assert!(store.build_config_with(&profile.id, &invalid).await.is_err());
+        let mut invalid = overrides;
+        invalid.defaults.term = Some("xterm; reboot".to_string());
+        assert!(store.build_config_with(&profile.id, &invalid).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_admin_protection() {
+        let store = ProfileStore::new(Arc::new(CredentialStore::new()));
+        let mut create = request("production");
+        create.protection = Some(ApprovalMethod::Admin);
+        let profile = store.create(create).await.unwrap();
+        assert_eq!(profile.protection, Some(ApprovalMethod::Admin));
+
+        let json = serde_json::to_value(&profile).unwrap();
+        assert_eq!(json["protection"], "admin");
+        // Profiles saved before protection existed aren't protected
+        let mut legacy = json.clone();
+        legacy.as_object_mut().unwrap().remove("protection");
+        assert_eq!(serde_json::from_value::<ConnectionProfile>(legacy).unwrap().protection, None);
+
+        let updated = store.update(&profile.id, request("production")).await.unwrap();
+        assert_eq!(updated.protection, None);
+    }
+}
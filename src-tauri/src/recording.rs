@@ -1,5 +1,6 @@
-use crate::types::AppResult;
+use crate::types::{AppResult, CommandExecResult, OutputBookmark};
 use crate::logging::StructuredLogger;
+use crate::background::BackgroundTasks;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -11,6 +12,8 @@ use tokio::fs;
 use tokio::io::{AsyncWriteExt, AsyncReadExt};
 use uuid::Uuid;
 
+pub type SharedRecordingManager = Arc<RecordingManager>;
+
 // Recording configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecordingConfig {
@@ -55,6 +58,8 @@ pub enum TerminalEventType {
     Disconnect,
     Command,
     Error,
+    // A named marker; data is its name
+    Bookmark,
 }
 
 // Recording session metadata
@@ -201,6 +206,7 @@ pub struct RecordingManager {
     config: RecordingConfig,
     active_recordings: Arc<DashMap<String, ActiveRecording>>,
     metadata_cache: Arc<RwLock<HashMap<String, RecordingMetadata>>>,
+    background: BackgroundTasks,
 }
 
 impl RecordingManager {
@@ -214,6 +220,7 @@ impl RecordingManager {
             config,
             active_recordings: Arc::new(DashMap::new()),
             metadata_cache: Arc::new(RwLock::new(HashMap::new())),
+            background: BackgroundTasks::new(),
         };
         
         // Load existing metadata
@@ -315,6 +322,15 @@ impl RecordingManager {
         }
     }
 
+    pub fn is_recording(&self, session_id: &str) -> bool {
+        self.active_recordings.contains_key(session_id)
+    }
+
+    // Recordings still being written, as they stand so far
+    pub fn active(&self) -> Vec<RecordingMetadata> {
+        self.active_recordings.iter().map(|entry| entry.metadata.clone()).collect()
+    }
+
     // Record a terminal event
     pub async fn record_event(&self, session_id: &str, event: TerminalEvent) -> AppResult<()> {
         if !self.config.enabled {
@@ -336,6 +352,53 @@ impl RecordingManager {
         Ok(())
     }
 
+    // Record an exec channel's command with stdout and stderr as separate events, tagged
+    // with their stream and the exit code, so recordings keep them apart
+    pub async fn record_exec(
+        &self,
+        session_id: &str,
+        command: &str,
+        result: &CommandExecResult,
+        metadata: HashMap<String, String>,
+    ) -> AppResult<()> {
+        let mut metadata = metadata;
+        metadata.insert("exit_code".to_string(), result.exit_code.to_string());
+        let events = [
+            (TerminalEventType::Command, None, command),
+            (TerminalEventType::Output, Some("stdout"), result.stdout.as_str()),
+            (TerminalEventType::Error, Some("stderr"), result.stderr.as_str()),
+        ];
+        for (event_type, stream, data) in events {
+            if data.is_empty() {
+                continue;
+            }
+            let mut metadata = metadata.clone();
+            if let Some(stream) = stream {
+                metadata.insert("stream".to_string(), stream.to_string());
+            }
+            self.record_event(session_id, TerminalEvent {
+                timestamp: Utc::now(),
+                event_type,
+                data: data.to_string(),
+                metadata: Some(metadata),
+            }).await?;
+        }
+        Ok(())
+    }
+
+    // A marker in the session's recording, for playback to jump to
+    pub async fn record_bookmark(&self, session_id: &str, bookmark: &OutputBookmark) -> AppResult<()> {
+        self.record_event(session_id, TerminalEvent {
+            timestamp: bookmark.timestamp,
+            event_type: TerminalEventType::Bookmark,
+            data: bookmark.name.clone(),
+            metadata: Some(HashMap::from([
+                ("bookmark_id".to_string(), bookmark.id.clone()),
+                ("offset".to_string(), bookmark.offset.to_string()),
+            ])),
+        }).await
+    }
+
     // Set terminal size for recording
     pub fn set_terminal_size(&self, session_id: &str, cols: u16, rows: u16) {
         if let Some(mut recording) = self.active_recordings.get_mut(session_id) {
@@ -388,16 +451,7 @@ impl RecordingManager {
             return Err(crate::types::AppError::NotFound(format!("Recording file not found: {}", recording_id)));
         }
         
-        let mut file = fs::File::open(&file_path).await?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents).await?;
-        
-        let mut events = Vec::new();
-        for line in contents.lines() {
-            if let Ok(event) = serde_json::from_str::<TerminalEvent>(line) {
-                events.push(event);
-            }
-        }
+        let mut events = read_events(&file_path).await?;
         
         // Apply playback control filters
         if let Some(control) = control {
@@ -563,16 +617,20 @@ impl RecordingManager {
         let retention_days = self.config.retention_days;
         let metadata_cache = self.metadata_cache.clone();
         
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(3600)); // 1 hour
-            
-            loop {
-                interval.tick().await;
+        // Every hour
+        self.background.spawn_periodic("Recording cleanup", tokio::time::Duration::from_secs(3600), move || {
+            let storage_path = storage_path.clone();
+            let metadata_cache = metadata_cache.clone();
+            async move {
                 Self::cleanup_old_recordings(&storage_path, retention_days, &metadata_cache).await;
             }
         });
     }
 
+    pub fn stop_background_tasks(&self) {
+        self.background.shutdown();
+    }
+
     async fn cleanup_old_recordings(
         storage_path: &Path,
         retention_days: u32,
@@ -609,6 +667,17 @@ impl RecordingManager {
     }
 }
 
+// Events of a recording file, skipping lines that don't parse (e.g. one cut off mid-write)
+pub async fn read_events(path: &Path) -> AppResult<Vec<TerminalEvent>> {
+    let mut file = fs::File::open(path).await?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).await?;
+
+    Ok(contents.lines()
+        .filter_map(|line| serde_json::from_str::<TerminalEvent>(line).ok())
+        .collect())
+}
+
 #[derive(Debug, Serialize)]
 pub struct RecordingStats {
     pub total_recordings: usize,
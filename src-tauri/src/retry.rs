@@ -0,0 +1,194 @@
+use crate::types::{AppError, AppResult};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// How often and how long to retry an operation that failed with a retryable AppError
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    // Stop retrying once this much time has passed since the first attempt
+    pub deadline: Option<Duration>,
+    // Which errors are worth another attempt
+    pub retry_if: fn(&AppError) -> bool,
+}
+
+impl RetryPolicy {
+    pub const fn new(max_attempts: u32, initial_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            initial_delay,
+            max_delay,
+            deadline: None,
+            retry_if: AppError::is_retryable,
+        }
+    }
+
+    pub const fn with_deadline(self, deadline: Duration) -> Self {
+        Self { deadline: Some(deadline), ..self }
+    }
+
+    pub const fn retrying_if(self, retry_if: fn(&AppError) -> bool) -> Self {
+        Self { retry_if, ..self }
+    }
+
+    // TCP connect to the SSH server
+    pub const fn connect() -> Self {
+        Self::new(3, Duration::from_millis(250), Duration::from_secs(2)).with_deadline(Duration::from_secs(30))
+    }
+
+    // A single SFTP request, mostly to ride out a failed channel open
+    pub const fn sftp() -> Self {
+        Self::new(3, Duration::from_millis(100), Duration::from_secs(1))
+            .with_deadline(Duration::from_secs(15))
+            .retrying_if(retryable_file_error)
+    }
+
+    // A whole background transfer, retried with longer pauses than the SFTP calls inside it
+    pub const fn transfer() -> Self {
+        Self::new(3, Duration::from_secs(1), Duration::from_secs(10))
+            .with_deadline(Duration::from_secs(120))
+            .retrying_if(retryable_file_error)
+    }
+
+    // A webhook delivery; receivers that are briefly down get a few chances
+    pub const fn webhook() -> Self {
+        Self::new(4, Duration::from_secs(1), Duration::from_secs(30)).with_deadline(Duration::from_secs(120))
+    }
+
+    // Exponential backoff with jitter, so clients that failed together don't retry together
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let exponential = self.initial_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exponential.min(self.max_delay);
+        // Somewhere between half and the full delay
+        let jitter = (Uuid::new_v4().as_u128() % 1000) as u32;
+        capped / 2 + capped / 2 * jitter / 1000
+    }
+}
+
+// A full remote disk stays full, so file operations don't retry on running out of space
+fn retryable_file_error(error: &AppError) -> bool {
+    error.is_retryable() && !matches!(error, AppError::ResourceExhausted(_))
+}
+
+// Run `operation` until it succeeds, fails with a non-retryable error, or the policy runs out
+pub async fn retry<T, F, Fut>(policy: &RetryPolicy, name: &str, mut operation: F) -> AppResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = AppResult<T>>,
+{
+    let started = Instant::now();
+    let mut attempt = 1;
+
+    loop {
+        let error = match operation().await {
+            Ok(value) => {
+                if attempt > 1 {
+                    log::info!("{} succeeded on attempt {}", name, attempt);
+                }
+                return Ok(value);
+            }
+            Err(error) => error,
+        };
+
+        if !(policy.retry_if)(&error) || attempt >= policy.max_attempts {
+            return Err(error);
+        }
+        let delay = policy.delay_for(attempt);
+        if policy.deadline.is_some_and(|deadline| started.elapsed() + delay > deadline) {
+            log::warn!("{} failed and the retry deadline has passed: {}", name, error);
+            return Err(error);
+        }
+
+        log::warn!(
+            "{} failed (attempt {}/{}), retrying in {}ms: {}",
+            name, attempt, policy.max_attempts, delay.as_millis(), error
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(max_attempts, Duration::from_millis(1), Duration::from_millis(2))
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let calls = AtomicU32::new(0);
+        let result = retry(&fast_policy(3), "test", || async {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(AppError::TimeoutError("slow".to_string()))
+            } else {
+                Ok(42)
+            }
+        }).await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stops_on_permanent_error_or_exhaustion() {
+        let calls = AtomicU32::new(0);
+        let result: AppResult<()> = retry(&fast_policy(5), "test", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(AppError::ValidationError("bad input".to_string()))
+        }).await;
+        assert!(matches!(result, Err(AppError::ValidationError(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let calls = AtomicU32::new(0);
+        let result: AppResult<()> = retry(&fast_policy(2), "test", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(AppError::SSHConnectionFailed("refused".to_string()))
+        }).await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_file_operations_stop_on_full_disk() {
+        let calls = AtomicU32::new(0);
+        let policy = fast_policy(3).retrying_if(retryable_file_error);
+        let result: AppResult<()> = retry(&policy, "test", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(AppError::ResourceExhausted("disk full".to_string()))
+        }).await;
+        assert!(matches!(result, Err(AppError::ResourceExhausted(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(!(RetryPolicy::transfer().retry_if)(&AppError::ResourceExhausted("disk full".to_string())));
+        assert!((RetryPolicy::transfer().retry_if)(&AppError::TimeoutError("slow".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_deadline_ends_retries() {
+        let policy = RetryPolicy::new(10, Duration::from_millis(50), Duration::from_millis(50))
+            .with_deadline(Duration::from_millis(10));
+        let calls = AtomicU32::new(0);
+        let result: AppResult<()> = retry(&policy, "test", || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(AppError::TimeoutError("slow".to_string()))
+        }).await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_backoff_is_capped_and_jittered() {
+        let policy = RetryPolicy::new(10, Duration::from_millis(100), Duration::from_millis(400));
+        for attempt in 1..10 {
+            let delay = policy.delay_for(attempt);
+            let full = Duration::from_millis(100 * 2u64.pow(attempt - 1)).min(Duration::from_millis(400));
+            assert!(delay >= full / 2 && delay <= full, "attempt {} delay {:?}", attempt, delay);
+        }
+    }
+}
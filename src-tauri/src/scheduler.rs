@@ -0,0 +1,453 @@
+use crate::jobs;
+use crate::notifications::{NotificationKind, SharedNotificationCenter};
+use crate::profiles::SharedProfileStore;
+use crate::recording::SharedRecordingManager;
+use crate::types::{AppError, AppResult, CommandExecResult, JobKind};
+use crate::SharedSSHManager;
+use chrono::{DateTime, Local, Utc};
+use croner::Cron;
+use dashmap::{DashMap, DashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+pub type SharedJobScheduler = Arc<JobScheduler>;
+
+pub const RECORDING_TAG: &str = "scheduled-job";
+// The loop re-checks at least this often, so clock changes are picked up
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+// Tail of the output kept on the job itself; the full output is in the recording
+const MAX_SUMMARY_CHARS: usize = 4096;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRun {
+    #[serde(rename = "startedAt")]
+    pub started_at: DateTime<Utc>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: DateTime<Utc>,
+    pub success: bool,
+    #[serde(rename = "exitCode")]
+    pub exit_code: Option<i32>,
+    pub output: String,
+    pub error: Option<String>,
+    #[serde(rename = "recordingId")]
+    pub recording_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "profileId")]
+    pub profile_id: String,
+    pub command: String,
+    // Cron expression in local time, e.g. "0 3 * * *"
+    pub schedule: String,
+    pub enabled: bool,
+    #[serde(rename = "nextRun")]
+    pub next_run: Option<DateTime<Utc>>,
+    #[serde(rename = "lastRun")]
+    pub last_run: Option<JobRun>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRequest {
+    pub name: String,
+    #[serde(rename = "profileId")]
+    pub profile_id: String,
+    pub command: String,
+    pub schedule: String,
+    pub enabled: Option<bool>,
+}
+
+// First time the cron expression fires after `after`
+pub fn next_occurrence(schedule: &str, after: DateTime<Utc>) -> AppResult<DateTime<Utc>> {
+    let cron = parse_schedule(schedule)?;
+    cron.find_next_occurrence(&after.with_timezone(&Local), false)
+        .map(|next| next.with_timezone(&Utc))
+        .map_err(|e| AppError::ValidationError(format!("Schedule \"{}\" never fires: {}", schedule, e)))
+}
+
+fn parse_schedule(schedule: &str) -> AppResult<Cron> {
+    Cron::new(schedule.trim())
+        .parse()
+        .map_err(|e| AppError::ValidationError(format!("Invalid schedule \"{}\": {}", schedule, e)))
+}
+
+// Services a job needs to connect, record and report
+#[derive(Clone)]
+pub struct JobContext {
+    pub ssh_manager: SharedSSHManager,
+    pub profiles: SharedProfileStore,
+    pub recordings: SharedRecordingManager,
+    pub notifications: SharedNotificationCenter,
+}
+
+pub struct JobScheduler {
+    jobs: DashMap<String, ScheduledJob>,
+    // Jobs with a run in flight; a job never overlaps with itself
+    running: DashSet<String>,
+    // Wakes the loop when the schedule changes
+    changed: Notify,
+    storage_path: Option<PathBuf>,
+}
+
+impl JobScheduler {
+    pub fn new() -> Self {
+        Self {
+            jobs: DashMap::new(),
+            running: DashSet::new(),
+            changed: Notify::new(),
+            storage_path: None,
+        }
+    }
+
+    pub async fn load(storage_path: PathBuf) -> AppResult<Self> {
+        let mut store = Self::new();
+
+        if tokio::fs::try_exists(&storage_path).await? {
+            let content = tokio::fs::read_to_string(&storage_path).await?;
+            let jobs: Vec<ScheduledJob> = serde_json::from_str(&content)?;
+            // Runs missed while the app was closed are skipped, not caught up
+            let now = Utc::now();
+            for mut job in jobs {
+                job.next_run = match next_occurrence(&job.schedule, now) {
+                    Ok(next) => Some(next),
+                    Err(e) => {
+                        log::warn!("Job {} will not run: {}", job.name, e);
+                        None
+                    }
+                };
+                store.jobs.insert(job.id.clone(), job);
+            }
+        }
+
+        store.storage_path = Some(storage_path);
+        Ok(store)
+    }
+
+    pub async fn create(&self, request: JobRequest) -> AppResult<ScheduledJob> {
+        validate(&request)?;
+
+        let now = Utc::now();
+        let job = ScheduledJob {
+            id: Uuid::new_v4().to_string(),
+            name: request.name.trim().to_string(),
+            profile_id: request.profile_id,
+            command: request.command,
+            next_run: Some(next_occurrence(&request.schedule, now)?),
+            schedule: request.schedule.trim().to_string(),
+            enabled: request.enabled.unwrap_or(true),
+            last_run: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.jobs.insert(job.id.clone(), job.clone());
+        self.save().await?;
+        self.changed.notify_one();
+        Ok(job)
+    }
+
+    pub async fn update(&self, job_id: &str, request: JobRequest) -> AppResult<ScheduledJob> {
+        validate(&request)?;
+
+        let now = Utc::now();
+        let job = {
+            let mut job = self.jobs.get_mut(job_id)
+                .ok_or_else(|| AppError::NotFound(format!("Job {}", job_id)))?;
+            job.next_run = Some(next_occurrence(&request.schedule, now)?);
+            job.name = request.name.trim().to_string();
+            job.profile_id = request.profile_id;
+            job.command = request.command;
+            job.schedule = request.schedule.trim().to_string();
+            job.enabled = request.enabled.unwrap_or(job.enabled);
+            job.updated_at = now;
+            job.clone()
+        };
+
+        self.save().await?;
+        self.changed.notify_one();
+        Ok(job)
+    }
+
+    pub async fn delete(&self, job_id: &str) -> AppResult<bool> {
+        if self.jobs.remove(job_id).is_none() {
+            return Ok(false);
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<ScheduledJob> {
+        self.jobs.get(job_id).map(|entry| entry.value().clone())
+    }
+
+    pub fn list(&self) -> Vec<ScheduledJob> {
+        let mut jobs: Vec<ScheduledJob> = self.jobs.iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        jobs.sort_by_key(|job| job.name.to_lowercase());
+        jobs
+    }
+
+    // Claim every enabled job due at `now` and move its next run forward
+    fn take_due(&self, now: DateTime<Utc>) -> Vec<ScheduledJob> {
+        let mut due = Vec::new();
+        for mut job in self.jobs.iter_mut() {
+            if !job.enabled || job.next_run.map_or(true, |next| next > now) {
+                continue;
+            }
+            job.next_run = next_occurrence(&job.schedule, now).ok();
+            if self.running.insert(job.id.clone()) {
+                due.push(job.clone());
+            } else {
+                log::warn!("Job {} is still running, skipping this run", job.name);
+            }
+        }
+        due
+    }
+
+    fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.jobs.iter()
+            .filter(|job| job.enabled)
+            .filter_map(|job| job.next_run)
+            .min()
+    }
+
+    async fn finish(&self, job_id: &str, run: JobRun) -> AppResult<()> {
+        self.running.remove(job_id);
+        if let Some(mut job) = self.jobs.get_mut(job_id) {
+            job.last_run = Some(run);
+        }
+        self.save().await
+    }
+
+    async fn save(&self) -> AppResult<()> {
+        let Some(path) = &self.storage_path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let content = serde_json::to_string_pretty(&self.list())?;
+        tokio::fs::write(path, content).await?;
+        Ok(())
+    }
+}
+
+impl Default for JobScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn validate(request: &JobRequest) -> AppResult<()> {
+    if request.name.trim().is_empty() {
+        return Err(AppError::ValidationError("Job name cannot be empty".to_string()));
+    }
+    if request.command.trim().is_empty() {
+        return Err(AppError::ValidationError("Command cannot be empty".to_string()));
+    }
+    parse_schedule(&request.schedule).map(|_| ())
+}
+
+// Run due jobs in the background for the lifetime of the app
+pub fn start(scheduler: SharedJobScheduler, context: JobContext) {
+    tokio::spawn(async move {
+        loop {
+            let now = Utc::now();
+            for job in scheduler.take_due(now) {
+                let scheduler = scheduler.clone();
+                let context = context.clone();
+                tokio::spawn(async move {
+                    complete(&scheduler, &context, &job).await;
+                });
+            }
+
+            let sleep = scheduler.next_due()
+                .and_then(|next| (next - now).to_std().ok())
+                .map_or(MAX_SLEEP, |until| until.min(MAX_SLEEP));
+            tokio::select! {
+                _ = tokio::time::sleep(sleep) => {}
+                _ = scheduler.changed.notified() => {}
+            }
+        }
+    });
+}
+
+// Run a job right away, outside its schedule
+pub async fn run_now(scheduler: &JobScheduler, context: &JobContext, job_id: &str) -> AppResult<JobRun> {
+    let job = scheduler.get(job_id)
+        .ok_or_else(|| AppError::NotFound(format!("Job {}", job_id)))?;
+    if !scheduler.running.insert(job.id.clone()) {
+        return Err(AppError::OperationFailed(format!("Job {} is already running", job.name)));
+    }
+    Ok(complete(scheduler, context, &job).await)
+}
+
+async fn complete(scheduler: &JobScheduler, context: &JobContext, job: &ScheduledJob) -> JobRun {
+    let run = execute(context, job).await;
+
+    if run.success {
+        log::info!("Job {} finished in {}s", job.name, (run.finished_at - run.started_at).num_seconds());
+    } else {
+        let reason = run.error.clone()
+            .unwrap_or_else(|| format!("exited with status {}", run.exit_code.unwrap_or(-1)));
+        log::warn!("Job {} failed: {}", job.name, reason);
+        context.notifications.notify(
+            NotificationKind::JobFailed,
+            None,
+            format!("Scheduled job failed: {}", job.name),
+            reason,
+        );
+    }
+
+    if let Err(e) = scheduler.finish(&job.id, run.clone()).await {
+        log::warn!("Failed to save result of job {}: {}", job.name, e);
+    }
+    run
+}
+
+async fn execute(context: &JobContext, job: &ScheduledJob) -> JobRun {
+    let started_at = Utc::now();
+    let mut run = JobRun {
+        started_at,
+        finished_at: started_at,
+        success: false,
+        exit_code: None,
+        output: String::new(),
+        error: None,
+        recording_id: None,
+    };
+
+    match exec_on_profile(context, job).await {
+        Ok((result, recording_id)) => {
+            run.success = result.exit_code == 0;
+            run.exit_code = Some(result.exit_code);
+            run.output = summarize(&result);
+            run.recording_id = recording_id;
+        }
+        Err(e) => run.error = Some(e.to_string()),
+    }
+    run.finished_at = Utc::now();
+    run
+}
+
+// Connect with the profile's stored credentials, run the command once and hang up
+async fn exec_on_profile(context: &JobContext, job: &ScheduledJob) -> AppResult<(CommandExecResult, Option<String>)> {
+    let config = context.profiles.connect_config(&job.profile_id).await?;
+    let hostname = config.hostname.clone();
+
+    let title = format!("Scheduled job {}", job.name);
+    let (session_id, result) = jobs::run(JobKind::ScheduledCommand, title, None, |_| async move {
+        context.ssh_manager.read().await.exec_once(config, &job.command).await
+    }).await?;
+    let recording_id = match record(&context.recordings, &session_id, &hostname, job, &result).await {
+        Ok(recording_id) => Some(recording_id),
+        Err(e) => {
+            log::warn!("Output of job {} was not recorded: {}", job.name, e);
+            None
+        }
+    };
+    Ok((result, recording_id))
+}
+
+// Keep the run as a tagged recording so it shows up next to interactive sessions
+async fn record(
+    recordings: &SharedRecordingManager,
+    session_id: &str,
+    hostname: &str,
+    job: &ScheduledJob,
+    result: &CommandExecResult,
+) -> AppResult<String> {
+    let recording_id = recordings.start_recording(session_id.to_string(), hostname.to_string(), None).await?;
+    recordings.add_recording_tag(session_id, RECORDING_TAG.to_string());
+    recordings.set_recording_description(
+        session_id,
+        format!("Scheduled job {} exited with status {}", job.name, result.exit_code),
+    );
+
+    let metadata = HashMap::from([("job_id".to_string(), job.id.clone())]);
+    let recorded = recordings.record_exec(session_id, &job.command, result, metadata).await;
+
+    // Always close the recording, even when an event failed to write
+    recordings.stop_recording(session_id).await?;
+    recorded.map(|_| recording_id)
+}
+
+// Last MAX_SUMMARY_CHARS of stdout followed by stderr
+fn summarize(result: &CommandExecResult) -> String {
+    let output = match (result.stdout.is_empty(), result.stderr.is_empty()) {
+        (_, true) => result.stdout.clone(),
+        (true, false) => result.stderr.clone(),
+        (false, false) => format!("{}\n{}", result.stdout.trim_end(), result.stderr),
+    };
+    let skip = output.chars().count().saturating_sub(MAX_SUMMARY_CHARS);
+    output.chars().skip(skip).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Timelike};
+
+    fn request(schedule: &str) -> JobRequest {
+        JobRequest {
+            name: "backup".to_string(),
+            profile_id: "p1".to_string(),
+            command: "tar czf /tmp/etc.tgz /etc".to_string(),
+            schedule: schedule.to_string(),
+            enabled: None,
+        }
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        let after = Utc.with_ymd_and_hms(2024, 5, 1, 10, 7, 30).unwrap();
+        let next = next_occurrence("*/15 * * * *", after).unwrap();
+        assert_eq!((next.minute(), next.second()), (15, 0));
+        assert!(next > after && next - after <= chrono::Duration::minutes(15));
+
+        assert!(next_occurrence("61 * * * *", after).is_err());
+        assert!(next_occurrence("not a schedule", after).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_take_due_claims_each_run_once() {
+        let scheduler = JobScheduler::new();
+        assert!(scheduler.create(request("every minute")).await.is_err());
+        let job = scheduler.create(request("* * * * *")).await.unwrap();
+
+        let at = job.next_run.unwrap();
+        assert!(scheduler.take_due(at - chrono::Duration::seconds(1)).is_empty());
+        assert_eq!(scheduler.take_due(at).len(), 1);
+        // Still running at the next tick, so it isn't started twice
+        assert!(scheduler.take_due(at + chrono::Duration::minutes(1)).is_empty());
+        assert!(scheduler.get(&job.id).unwrap().next_run.unwrap() > at);
+
+        scheduler.running.remove(&job.id);
+        assert_eq!(scheduler.take_due(at + chrono::Duration::minutes(2)).len(), 1);
+    }
+
+    #[test]
+    fn test_summarize_keeps_the_tail() {
+        let result = CommandExecResult {
+            stdout: "x".repeat(MAX_SUMMARY_CHARS) + "done\n",
+            stderr: "warning: slow disk\n".to_string(),
+            exit_code: 0,
+        };
+        let summary = summarize(&result);
+        assert_eq!(summary.chars().count(), MAX_SUMMARY_CHARS);
+        assert!(summary.ends_with("done\nwarning: slow disk\n"));
+    }
+}
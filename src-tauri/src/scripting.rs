@@ -0,0 +1,312 @@
+use crate::types::{AppError, AppResult};
+use parking_lot::{Mutex, RwLock};
+use rhai::{Dynamic, Engine, FuncArgs, Scope, AST};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub type SharedScriptHost = Arc<ScriptHost>;
+
+pub const SCRIPT_EXTENSION: &str = "rhai";
+
+// Sandbox limits for a single hook call
+const MAX_OPERATIONS: u64 = 200_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 1024 * 1024;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+// Further send_input/notify calls in the same hook are dropped
+const MAX_ACTIONS: usize = 100;
+
+// Events a script can handle by defining a function with the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptHook {
+    // on_connect(session_id, host)
+    OnConnect,
+    // on_output(session_id, data)
+    OnOutput,
+    // on_disconnect(session_id)
+    OnDisconnect,
+    // before_transfer(session_id, direction, remote_path); returning false cancels the transfer
+    BeforeTransfer,
+}
+
+impl ScriptHook {
+    pub const ALL: [ScriptHook; 4] = [Self::OnConnect, Self::OnOutput, Self::OnDisconnect, Self::BeforeTransfer];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OnConnect => "on_connect",
+            Self::OnOutput => "on_output",
+            Self::OnDisconnect => "on_disconnect",
+            Self::BeforeTransfer => "before_transfer",
+        }
+    }
+}
+
+// Something a script asked the host to do. Scripts can't touch sessions directly,
+// the host carries these out after the hook returns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptAction {
+    SendInput { session_id: String, input: String },
+    Notify { title: String, body: String },
+}
+
+#[derive(Debug, Default)]
+pub struct ScriptOutcome {
+    pub actions: Vec<ScriptAction>,
+    // Name of the first script whose hook returned false
+    pub cancelled_by: Option<String>,
+}
+
+impl ScriptOutcome {
+    pub fn check_transfer(&self, remote_path: &str) -> AppResult<()> {
+        match &self.cancelled_by {
+            Some(script) => Err(AppError::PermissionDenied(format!("Transfer of {} cancelled by script {}", remote_path, script))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptInfo {
+    pub name: String,
+    pub hooks: Vec<String>,
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+}
+
+impl Script {
+    fn defines(&self, hook: ScriptHook) -> bool {
+        self.ast.iter_functions().any(|function| function.name == hook.name())
+    }
+}
+
+pub struct ScriptHost {
+    engine: Engine,
+    scripts: RwLock<Vec<Script>>,
+    // Filled by the API functions during a call. Calls are serialized by `running`.
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+    running: Mutex<()>,
+    script_dir: Option<PathBuf>,
+}
+
+impl ScriptHost {
+    pub fn new() -> Self {
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::new();
+
+        engine.set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_COLLECTION_SIZE)
+            .set_max_map_size(MAX_COLLECTION_SIZE)
+            .disable_symbol("eval");
+        engine.on_print(|text| log::info!("[script] {}", text));
+        engine.on_debug(|text, source, _| log::debug!("[script {}] {}", source.unwrap_or("?"), text));
+
+        let queue = actions.clone();
+        engine.register_fn("send_input", move |session_id: &str, input: &str| {
+            queue_action(&queue, ScriptAction::SendInput {
+                session_id: session_id.to_string(),
+                input: input.to_string(),
+            });
+        });
+        let queue = actions.clone();
+        engine.register_fn("notify", move |title: &str, body: &str| {
+            queue_action(&queue, ScriptAction::Notify {
+                title: title.to_string(),
+                body: body.to_string(),
+            });
+        });
+
+        Self {
+            engine,
+            scripts: RwLock::new(Vec::new()),
+            actions,
+            running: Mutex::new(()),
+            script_dir: None,
+        }
+    }
+
+    pub async fn load(script_dir: PathBuf) -> AppResult<Self> {
+        let mut host = Self::new();
+        host.script_dir = Some(script_dir);
+        host.reload().await?;
+        Ok(host)
+    }
+
+    // Recompile every script in the directory. A script that fails to compile is logged and skipped.
+    pub async fn reload(&self) -> AppResult<Vec<ScriptInfo>> {
+        let Some(dir) = &self.script_dir else {
+            return Ok(self.list());
+        };
+
+        let mut sources = Vec::new();
+        if tokio::fs::try_exists(dir).await? {
+            let mut entries = tokio::fs::read_dir(dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some(SCRIPT_EXTENSION) {
+                    continue;
+                }
+                let name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+                sources.push((name, tokio::fs::read_to_string(&path).await?));
+            }
+        }
+        sources.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let scripts = sources.into_iter()
+            .filter_map(|(name, source)| match self.compile(&name, &source) {
+                Ok(script) => Some(script),
+                Err(e) => {
+                    log::warn!("{}", e);
+                    None
+                }
+            })
+            .collect();
+        *self.scripts.write() = scripts;
+
+        let loaded = self.list();
+        log::info!("Loaded {} scripts", loaded.len());
+        Ok(loaded)
+    }
+
+    fn compile(&self, name: &str, source: &str) -> AppResult<Script> {
+        let mut ast = self.engine.compile(source)
+            .map_err(|e| AppError::ValidationError(format!("Script {} failed to compile: {}", name, e)))?;
+        ast.set_source(name);
+        Ok(Script { name: name.to_string(), ast })
+    }
+
+    pub fn add_script(&self, name: &str, source: &str) -> AppResult<()> {
+        let script = self.compile(name, source)?;
+        self.scripts.write().push(script);
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<ScriptInfo> {
+        self.scripts.read().iter()
+            .map(|script| ScriptInfo {
+                name: script.name.clone(),
+                hooks: ScriptHook::ALL.iter()
+                    .filter(|hook| script.defines(**hook))
+                    .map(|hook| hook.name().to_string())
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub fn handles(&self, hook: ScriptHook) -> bool {
+        self.scripts.read().iter().any(|script| script.defines(hook))
+    }
+
+    // Run the hook in every script that defines it. A failing script is logged and doesn't stop the others.
+    pub fn run(&self, hook: ScriptHook, args: impl FuncArgs + Clone) -> ScriptOutcome {
+        let mut outcome = ScriptOutcome::default();
+        let scripts = self.scripts.read();
+        let _running = self.running.lock();
+
+        for script in scripts.iter().filter(|script| script.defines(hook)) {
+            // Each script gets a fresh scope, so one can't see another's variables
+            let result = self.engine.call_fn::<Dynamic>(&mut Scope::new(), &script.ast, hook.name(), args.clone());
+            match result {
+                Ok(value) => {
+                    if value.as_bool() == Ok(false) && outcome.cancelled_by.is_none() {
+                        outcome.cancelled_by = Some(script.name.clone());
+                    }
+                }
+                Err(e) => log::warn!("Script {} {} failed: {}", script.name, hook.name(), e),
+            }
+        }
+
+        outcome.actions = std::mem::take(&mut *self.actions.lock());
+        outcome
+    }
+
+    pub fn on_connect(&self, session_id: &str, host: &str) -> ScriptOutcome {
+        self.run(ScriptHook::OnConnect, (session_id.to_string(), host.to_string()))
+    }
+
+    pub fn on_output(&self, session_id: &str, data: &str) -> ScriptOutcome {
+        if !self.handles(ScriptHook::OnOutput) {
+            return ScriptOutcome::default();
+        }
+        self.run(ScriptHook::OnOutput, (session_id.to_string(), data.to_string()))
+    }
+
+    pub fn on_disconnect(&self, session_id: &str) -> ScriptOutcome {
+        self.run(ScriptHook::OnDisconnect, (session_id.to_string(),))
+    }
+
+    // direction is "upload" or "download"
+    pub fn before_transfer(&self, session_id: &str, direction: &str, remote_path: &str) -> ScriptOutcome {
+        self.run(
+            ScriptHook::BeforeTransfer,
+            (session_id.to_string(), direction.to_string(), remote_path.to_string()),
+        )
+    }
+}
+
+fn queue_action(queue: &Mutex<Vec<ScriptAction>>, action: ScriptAction) {
+    let mut queue = queue.lock();
+    if queue.len() < MAX_ACTIONS {
+        queue.push(action);
+    }
+}
+
+impl Default for ScriptHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hooks_queue_actions() {
+        let host = ScriptHost::new();
+        host.add_script("greet", r#"
+            fn on_connect(session_id, host) {
+                if host == "prod" { notify("Connected", "Careful, this is production"); }
+                send_input(session_id, "uptime\n");
+            }
+        "#).unwrap();
+
+        let outcome = host.on_connect("s1", "prod");
+        assert_eq!(outcome.actions, vec![
+            ScriptAction::Notify { title: "Connected".to_string(), body: "Careful, this is production".to_string() },
+            ScriptAction::SendInput { session_id: "s1".to_string(), input: "uptime\n".to_string() },
+        ]);
+        assert!(host.on_disconnect("s1").actions.is_empty());
+        assert_eq!(host.list()[0].hooks, vec!["on_connect"]);
+    }
+
+    #[test]
+    fn test_before_transfer_can_cancel() {
+        let host = ScriptHost::new();
+        host.add_script("guard", r#"
+            fn before_transfer(session_id, direction, path) { !path.starts_with("/etc/") }
+        "#).unwrap();
+
+        assert!(host.before_transfer("s1", "download", "/home/me/a.txt").check_transfer("/home/me/a.txt").is_ok());
+        let outcome = host.before_transfer("s1", "upload", "/etc/passwd");
+        assert_eq!(outcome.cancelled_by.as_deref(), Some("guard"));
+        assert!(matches!(outcome.check_transfer("/etc/passwd"), Err(AppError::PermissionDenied(_))));
+    }
+
+    #[test]
+    fn test_sandbox_limits() {
+        let host = ScriptHost::new();
+        assert!(host.add_script("evil", r#"fn on_connect(s, h) { eval("1") }"#).is_err());
+
+        host.add_script("spin", r#"fn on_output(s, d) { loop { send_input(s, d); } }"#).unwrap();
+        // Runs out of operations instead of hanging
+        let outcome = host.on_output("s1", "x");
+        assert_eq!(outcome.actions.len(), MAX_ACTIONS);
+    }
+}
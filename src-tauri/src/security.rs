@@ -1,14 +1,32 @@
-use crate::types::AppResult;
+use crate::types::{AppResult, ApprovalAttempt, ApprovalOutcome};
 use crate::logging::StructuredLogger;
+use crate::background::BackgroundTasks;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use dashmap::DashMap;
 use chrono::{DateTime, Utc, Duration};
 use serde::{Serialize, Deserialize};
 use std::net::IpAddr;
 use sha2::{Sha256, Digest};
 
+pub type SharedSecurityManager = Arc<SecurityManager>;
+
+// Log every step of the second confirmation for protected hosts as a security event
+pub fn record_approval_attempts(security_manager: SharedSecurityManager, mut receiver: broadcast::Receiver<ApprovalAttempt>) {
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(attempt) => security_manager.record_connect_approval(&attempt).await,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Dropped {} approval attempts from the security log", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
 // Security configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityConfig {
@@ -21,6 +39,10 @@ pub struct SecurityConfig {
     pub audit_log_retention_days: u32,
     pub enable_ddos_protection: bool,
     pub max_concurrent_connections_per_ip: u32,
+    // Inbound WebSocket messages per client per minute. Every keystroke is a message.
+    pub websocket_messages_per_minute: u32,
+    // How long a client over that limit has its messages dropped
+    pub websocket_throttle_seconds: i64,
 }
 
 impl Default for SecurityConfig {
@@ -41,6 +63,8 @@ impl Default for SecurityConfig {
             audit_log_retention_days: 90,
             enable_ddos_protection: true,
             max_concurrent_connections_per_ip: 10,
+            websocket_messages_per_minute: 3000,
+            websocket_throttle_seconds: 5,
         }
     }
 }
@@ -70,6 +94,8 @@ pub enum SecurityEventType {
     EncryptionViolation,
     SessionTimeout,
     DdosDetected,
+    // A step of the second confirmation for a protected host
+    ConnectApproval,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,6 +113,13 @@ struct RateLimitEntry {
     blocked_until: Option<DateTime<Utc>>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitDecision {
+    Allowed,
+    // `first` is set on the message that tripped the limit, later ones until `until` just get dropped
+    Throttled { until: DateTime<Utc>, first: bool },
+}
+
 // Account lockout tracking
 #[derive(Debug, Clone)]
 struct AccountSecurity {
@@ -107,10 +140,13 @@ pub struct SshKeyFingerprint {
 pub struct SecurityManager {
     config: SecurityConfig,
     rate_limits: Arc<DashMap<IpAddr, RateLimitEntry>>,
+    // By WebSocket client ID, dropped when the client disconnects
+    client_rate_limits: DashMap<String, RateLimitEntry>,
     account_security: Arc<DashMap<String, AccountSecurity>>,
     security_events: Arc<RwLock<Vec<SecurityEvent>>>,
     connection_counts: Arc<DashMap<IpAddr, u32>>,
     trusted_fingerprints: Arc<DashMap<String, Vec<SshKeyFingerprint>>>,
+    background: BackgroundTasks,
 }
 
 impl SecurityManager {
@@ -118,10 +154,12 @@ impl SecurityManager {
         let manager = Self {
             config,
             rate_limits: Arc::new(DashMap::new()),
+            client_rate_limits: DashMap::new(),
             account_security: Arc::new(DashMap::new()),
             security_events: Arc::new(RwLock::new(Vec::new())),
             connection_counts: Arc::new(DashMap::new()),
             trusted_fingerprints: Arc::new(DashMap::new()),
+            background: BackgroundTasks::new(),
         };
         
         // Start cleanup tasks
@@ -189,6 +227,121 @@ impl SecurityManager {
         Ok(true)
     }
 
+    // Flood protection for inbound WebSocket messages, per client
+    pub async fn check_client_rate_limit(&self, client_id: &str) -> RateLimitDecision {
+        let now = Utc::now();
+        let mut entry = self.client_rate_limits.entry(client_id.to_string()).or_insert_with(|| RateLimitEntry {
+            requests: Vec::new(),
+            blocked_until: None,
+        });
+
+        if let Some(until) = entry.blocked_until {
+            if now < until {
+                return RateLimitDecision::Throttled { until, first: false };
+            }
+            entry.blocked_until = None;
+            entry.requests.clear();
+        }
+
+        let cutoff = now - Duration::minutes(1);
+        entry.requests.retain(|&timestamp| timestamp > cutoff);
+        if entry.requests.len() < self.config.websocket_messages_per_minute as usize {
+            entry.requests.push(now);
+            return RateLimitDecision::Allowed;
+        }
+
+        let until = now + Duration::seconds(self.config.websocket_throttle_seconds);
+        entry.blocked_until = Some(until);
+        drop(entry);
+
+        self.log_security_event(SecurityEvent {
+            event_type: SecurityEventType::RateLimitExceeded,
+            timestamp: now,
+            source_ip: None,
+            user_id: None,
+            session_id: None,
+            details: {
+                let mut details = HashMap::new();
+                details.insert("client_id".to_string(), client_id.to_string());
+                details.insert("limit".to_string(), self.config.websocket_messages_per_minute.to_string());
+                details
+            },
+            severity: SecuritySeverity::Medium,
+        }).await;
+        RateLimitDecision::Throttled { until, first: true }
+    }
+
+    // A refused WebSocket upgrade: bad or missing token, or a foreign Origin
+    pub async fn record_rejected_upgrade(&self, ip: Option<IpAddr>, reason: &str, origin: Option<&str>) {
+        self.log_security_event(SecurityEvent {
+            event_type: SecurityEventType::UnauthorizedAccess,
+            timestamp: Utc::now(),
+            source_ip: ip,
+            user_id: None,
+            session_id: None,
+            details: {
+                let mut details = HashMap::new();
+                details.insert("reason".to_string(), reason.to_string());
+                if let Some(origin) = origin {
+                    details.insert("origin".to_string(), origin.to_string());
+                }
+                details
+            },
+            severity: SecuritySeverity::High,
+        }).await;
+    }
+
+    pub async fn record_rejected_request(&self, ip: Option<IpAddr>, path: &str, reason: &str) {
+        self.log_security_event(SecurityEvent {
+            event_type: SecurityEventType::UnauthorizedAccess,
+            timestamp: Utc::now(),
+            source_ip: ip,
+            user_id: None,
+            session_id: None,
+            details: {
+                let mut details = HashMap::new();
+                details.insert("path".to_string(), path.to_string());
+                details.insert("reason".to_string(), reason.to_string());
+                details
+            },
+            severity: SecuritySeverity::High,
+        }).await;
+    }
+
+    // Requests, codes and decisions for connections to protected hosts
+    pub async fn record_connect_approval(&self, attempt: &ApprovalAttempt) {
+        let severity = match attempt.outcome {
+            ApprovalOutcome::Requested | ApprovalOutcome::Approved | ApprovalOutcome::Cancelled => SecuritySeverity::Low,
+            ApprovalOutcome::InvalidCode | ApprovalOutcome::Expired => SecuritySeverity::Medium,
+            ApprovalOutcome::Denied => SecuritySeverity::High,
+        };
+        let request = &attempt.request;
+        self.log_security_event(SecurityEvent {
+            event_type: SecurityEventType::ConnectApproval,
+            timestamp: attempt.at,
+            source_ip: None,
+            user_id: Some(request.username.clone()),
+            session_id: Some(request.session_id.clone()),
+            details: {
+                let mut details = HashMap::new();
+                details.insert("outcome".to_string(), format!("{:?}", attempt.outcome));
+                details.insert("request_id".to_string(), request.request_id.clone());
+                details.insert("profile_id".to_string(), request.profile_id.clone());
+                details.insert("host".to_string(), format!("{}:{}", request.hostname, request.port));
+                details.insert("method".to_string(), format!("{:?}", request.method));
+                if let Some(approver) = &attempt.approver {
+                    details.insert("approver".to_string(), approver.clone());
+                }
+                details
+            },
+            severity,
+        }).await;
+    }
+
+    pub fn release_client(&self, client_id: &str) {
+        self.client_rate_limits.remove(client_id);
+    }
+
     // Account lockout management
     pub async fn check_account_lockout(&self, username: &str) -> AppResult<bool> {
         let now = Utc::now();
@@ -330,6 +483,28 @@ impl SecurityManager {
             .push(fingerprint);
     }
 
+    pub fn remove_trusted_fingerprint(&self, username: &str, fingerprint: &str) -> bool {
+        let mut removed = false;
+
+        if let Some(mut trusted) = self.trusted_fingerprints.get_mut(username) {
+            let before = trusted.len();
+            trusted.retain(|trusted| trusted.fingerprint != fingerprint);
+            removed = trusted.len() != before;
+        }
+
+        // Drop users left without any trusted keys
+        self.trusted_fingerprints.remove_if(username, |_, trusted| trusted.is_empty());
+        removed
+    }
+
+    pub fn list_trusted_fingerprints(&self, username: Option<&str>) -> HashMap<String, Vec<SshKeyFingerprint>> {
+        self.trusted_fingerprints
+            .iter()
+            .filter(|entry| username.map_or(true, |username| entry.key() == username))
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
     // Connection tracking for DDoS protection
     pub async fn track_connection(&self, ip: IpAddr) -> AppResult<bool> {
         if !self.config.enable_ddos_protection {
@@ -409,6 +584,12 @@ impl SecurityManager {
         );
     }
 
+    // Get the most recent security events, newest first
+    pub async fn get_recent_events(&self, limit: usize) -> Vec<SecurityEvent> {
+        let events = self.security_events.read().await;
+        events.iter().rev().take(limit).cloned().collect()
+    }
+
     // Get security statistics
     pub async fn get_security_stats(&self) -> SecurityStats {
         let events = self.security_events.read().await;
@@ -448,16 +629,21 @@ impl SecurityManager {
         let security_events = self.security_events.clone();
         let retention_days = self.config.audit_log_retention_days;
         
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(300)); // 5 minutes
-            
-            loop {
-                interval.tick().await;
+        // Every 5 minutes
+        self.background.spawn_periodic("Security cleanup", tokio::time::Duration::from_secs(300), move || {
+            let rate_limits = rate_limits.clone();
+            let account_security = account_security.clone();
+            let security_events = security_events.clone();
+            async move {
                 Self::cleanup_expired_data(&rate_limits, &account_security, &security_events, retention_days).await;
             }
         });
     }
 
+    pub fn stop_background_tasks(&self) {
+        self.background.shutdown();
+    }
+
     async fn cleanup_expired_data(
         rate_limits: &Arc<DashMap<IpAddr, RateLimitEntry>>,
         account_security: &Arc<DashMap<String, AccountSecurity>>,
@@ -504,3 +690,58 @@ pub struct SecurityStats {
     pub active_connections: u32,
     pub critical_events_last_day: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_client_rate_limit() {
+        let manager = SecurityManager::new(SecurityConfig {
+            websocket_messages_per_minute: 3,
+            ..SecurityConfig::default()
+        });
+        for _ in 0..3 {
+            assert_eq!(manager.check_client_rate_limit("client-1").await, RateLimitDecision::Allowed);
+        }
+        assert!(matches!(manager.check_client_rate_limit("client-1").await, RateLimitDecision::Throttled { first: true, .. }));
+        assert!(matches!(manager.check_client_rate_limit("client-1").await, RateLimitDecision::Throttled { first: false, .. }));
+        // Limits are per client
+        assert_eq!(manager.check_client_rate_limit("client-2").await, RateLimitDecision::Allowed);
+
+        manager.release_client("client-1");
+        assert_eq!(manager.check_client_rate_limit("client-1").await, RateLimitDecision::Allowed);
+    }
+
+    #[tokio::test]
+    async fn test_connect_approval_events() {
+        use crate::types::{ApprovalMethod, ApprovalRequest};
+
+        let manager = SecurityManager::new(SecurityConfig::default());
+        let request = ApprovalRequest {
+            request_id: "r1".to_string(),
+            session_id: "s1".to_string(),
+            profile_id: "p1".to_string(),
+            profile_name: "Production DB".to_string(),
+            hostname: "db1.internal".to_string(),
+            port: 22,
+            username: "deploy".to_string(),
+            method: ApprovalMethod::Admin,
+            requested_at: Utc::now(),
+            expires_at: Utc::now(),
+        };
+        manager.record_connect_approval(&ApprovalAttempt {
+            request,
+            outcome: ApprovalOutcome::Denied,
+            approver: Some("alice".to_string()),
+            at: Utc::now(),
+        }).await;
+
+        let events = manager.get_recent_events(10).await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0].event_type, SecurityEventType::ConnectApproval));
+        assert!(matches!(events[0].severity, SecuritySeverity::High));
+        assert_eq!(events[0].details["approver"], "alice");
+        assert_eq!(events[0].details["outcome"], "Denied");
+    }
+}
@@ -1,20 +1,37 @@
-use crate::ssh::SSHManager;
-use crate::websocket::{websocket_handler, SharedSSHManager};
-use crate::transfer::{TransferManager, SharedTransferManager};
+use crate::i18n::{self, Language};
+use crate::logging::{correlation, StructuredLogger};
+use crate::logging::file as log_file;
+use crate::logging::filter::{self as log_filter, LogLevelConfig};
+use crate::logging::query::{self as log_query, LogQuery};
+use crate::engine::Engine;
+use crate::background;
+use crate::jobs;
+use crate::websocket::{self, websocket_handler, SharedSSHManager};
+use crate::transfer::{self, SharedTransferManager};
 use crate::performance::PerformanceMonitor;
 use crate::optimization::PerformanceOptimizer;
-use crate::security::{SecurityManager, SecurityConfig};
+use crate::security::{self, SecurityManager, SecurityConfig};
 use crate::recording::{RecordingManager, RecordingConfig};
-use crate::types::{AppError, AppResult, SSHSession, FileListRequest, FileListResponse, FileInfo, FileDownloadRequest, FileUploadRequest, TransferUploadRequest, TransferDownloadRequest, AutocompleteRequest, AutocompleteResponse, MobileSessionRequest, MobileSessionResponse, SystemPerformanceMetrics};
+use crate::credentials::CredentialStore;
+use crate::profiles::{ConnectionProfile, ProfileOverrides, ProfileRequest, ProfileStore, SharedProfileStore};
+use crate::ssh::history::HistoryEntry;
+use crate::ssh::parse_mode;
+use crate::types::{AppError, AppResult, ApprovalRequest, CommandExecResult, PortForward, HostInfo, RemoteEnvironment, TerminalCharset, WebSocketStats, BackgroundTaskStatus, Job, ProcessSignal, RemoteProcess, SignalResult, TerminalSignal, ServiceAction, ServiceStatus, ServiceUnit, SudoOptions, ContainerAction, DockerContainer, DockerImage, KubeContext, KubeNamespace, KubePod, KubeScope, MultiplexerKind, MultiplexerSession, SSHSession, SessionTags, ShellVariables, RemoteAccounts, NetworkInfo, OutputBookmark, TimelineEvent, FileListRequest, FileListResponse, SftpPathRequest, SftpRenameRequest, SftpDeleteRequest, SftpModeRequest, SftpSymlinkRequest, SftpStat, FileInfo, FileDownloadRequest, FileUploadRequest, FileWriteAtRequest, FileTransfer, TransferLimit, TransferUploadRequest, TransferDownloadRequest, DirectoryTransferRequest, TransferManifestRequest, ManifestFormat, AutocompleteRequest, AutocompleteResponse, MobileSessionRequest, MobileSessionResponse, SystemPerformanceMetrics};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post, put},
     Router,
 };
+use futures_util::Stream;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tower::ServiceBuilder;
@@ -29,6 +46,7 @@ pub struct AppState {
     pub performance_optimizer: Arc<PerformanceOptimizer>,
     pub security_manager: Arc<SecurityManager>,
     pub recording_manager: Arc<RecordingManager>,
+    pub profile_store: SharedProfileStore,
 }
 
 pub struct AppServer {
@@ -38,25 +56,30 @@ pub struct AppServer {
     performance_optimizer: Arc<PerformanceOptimizer>,
     security_manager: Arc<SecurityManager>,
     recording_manager: Arc<RecordingManager>,
+    profile_store: SharedProfileStore,
     port: u16,
 }
 
 impl AppServer {
     pub async fn new(port: u16) -> AppResult<Self> {
-        let ssh_manager = Arc::new(RwLock::new(SSHManager::new()));
-        let transfer_manager = Arc::new(RwLock::new(TransferManager::new(ssh_manager.clone())));
+        let engine = Engine::new(RecordingConfig::default()).await?;
         let performance_monitor = Arc::new(RwLock::new(PerformanceMonitor::new()));
         let performance_optimizer = Arc::new(PerformanceOptimizer::new());
         let security_manager = Arc::new(SecurityManager::new(SecurityConfig::default()));
-        let recording_manager = Arc::new(RecordingManager::new(RecordingConfig::default()).await?);
+        let approval_attempts = engine.ssh.read().await.approvals().attempts();
+        security::record_approval_attempts(security_manager.clone(), approval_attempts);
+        // Next to the recordings, like them relative to where the server runs
+        let credentials = Arc::new(CredentialStore::load(PathBuf::from("./credentials.json")).await?);
+        let profile_store = Arc::new(ProfileStore::load(PathBuf::from("./profiles.json"), credentials).await?);
 
         Ok(Self {
-            ssh_manager,
-            transfer_manager,
+            ssh_manager: engine.ssh,
+            transfer_manager: engine.transfers,
+            recording_manager: engine.recordings,
             performance_monitor,
             performance_optimizer,
             security_manager,
-            recording_manager,
+            profile_store,
             port,
         })
     }
@@ -70,32 +93,108 @@ impl AppServer {
         let listener = tokio::net::TcpListener::bind(addr).await
             .map_err(AppError::IOError)?;
             
-        axum::serve(listener, app).await
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
             .map_err(|e| AppError::IOError(std::io::Error::other(e)))?;
         
         Ok(())
     }
 
     fn create_router(&self) -> Router {
+        let state = AppState {
+            ssh_manager: self.ssh_manager.clone(),
+            transfer_manager: self.transfer_manager.clone(),
+            performance_monitor: self.performance_monitor.clone(),
+            performance_optimizer: self.performance_optimizer.clone(),
+            security_manager: self.security_manager.clone(),
+            recording_manager: self.recording_manager.clone(),
+            profile_store: self.profile_store.clone(),
+        };
+
+        // Answering a protected connect's second step takes the auth token, and has no CORS,
+        // so a web page open in a local browser can't approve one
+        let approvals = Router::new()
+            .route("/api/ssh/approvals", get(pending_approvals))
+            .route("/api/ssh/approvals/:request_id", post(respond_to_approval))
+            .route_layer(middleware::from_fn_with_state(state.clone(), require_auth_token));
+
         Router::new()
             // WebSocket endpoint
             .route("/socket.io/", get(websocket_handler_wrapper))
             .route("/ws", get(websocket_handler_wrapper))
+            .route("/api/websocket/stats", get(websocket_stats))
+            .route("/api/health/tasks", get(background_tasks))
+            .route("/api/jobs", get(list_jobs))
+            .route("/api/jobs/:job_id/cancel", post(cancel_job))
+
+            // Connection profiles
+            .route("/api/profiles", get(list_profiles).post(create_profile))
+            .route("/api/profiles/folders", get(profile_folders))
+            .route("/api/profiles/:profile_id", get(get_profile).put(update_profile).delete(delete_profile))
+            .route("/api/profiles/:profile_id/connect", post(connect_profile))
             
             // SSH API endpoints
             .route("/api/ssh/sessions", get(list_sessions))
             .route("/api/ssh/connect", post(connect_ssh))
             .route("/api/ssh/disconnect/:session_id", post(disconnect_ssh))
+            .route("/api/ssh/exec", post(exec_in_session))
+            .route("/api/ssh/:session_id/hostinfo", get(get_host_info))
+            .route("/api/ssh/:session_id/environment", get(get_environment))
+            .route("/api/ssh/:session_id/variables", get(get_variables))
+            .route("/api/ssh/:session_id/accounts", get(get_accounts))
+            .route("/api/ssh/:session_id/charset", get(get_charset).post(set_charset))
+            .route("/api/ssh/:session_id/tags", put(set_session_tags))
+            .route("/api/ssh/:session_id/timeline", get(get_timeline))
+            .route("/api/ssh/:session_id/exec", post(exec_command))
+            .route("/api/ssh/:session_id/signal", post(send_signal))
+            .route("/api/ssh/:session_id/recent-paths", get(recent_paths))
+            .route("/api/ssh/:session_id/history", get(command_history).delete(clear_command_history))
+            .route("/api/ssh/:session_id/bookmarks", get(list_bookmarks).post(add_bookmark))
+            .route("/api/ssh/:session_id/bookmarks/:bookmark_id", delete(remove_bookmark))
+            .route("/api/ssh/:session_id/bookmarks/:bookmark_id/output", get(output_since_bookmark))
+            .route("/api/ssh/:session_id/forwards", get(list_forwards))
+            .route("/api/ssh/:session_id/forwards/local", post(create_local_forward))
+            .route("/api/ssh/:session_id/forwards/remote", post(create_remote_forward))
+            .route("/api/forwards/:forward_id", delete(close_forward))
+            .route("/api/ssh/:session_id/processes", get(list_processes))
+            .route("/api/ssh/:session_id/processes/signal", post(signal_processes))
+            .route("/api/ssh/:session_id/network", get(inspect_network))
+            .route("/api/ssh/:session_id/services", get(list_services))
+            .route("/api/ssh/:session_id/services/:unit", get(service_status).post(service_action))
+            .route("/api/ssh/:session_id/docker/containers", get(list_containers))
+            .route("/api/ssh/:session_id/docker/containers/:container", post(container_action))
+            .route("/api/ssh/:session_id/docker/images", get(list_images))
+            .route("/api/ssh/:session_id/k8s/contexts", get(list_kube_contexts))
+            .route("/api/ssh/:session_id/k8s/namespaces", get(list_kube_namespaces))
+            .route("/api/ssh/:session_id/k8s/pods", get(list_kube_pods))
+            .route("/api/ssh/:session_id/multiplexers", get(list_multiplexer_sessions))
+            .route("/api/ssh/:session_id/multiplexers/attach", post(attach_multiplexer))
             
             // SFTP API endpoints
             .route("/api/sftp/list", post(list_files))
             .route("/api/sftp/upload", post(upload_file))
+            .route("/api/sftp/append", post(append_file))
+            .route("/api/sftp/write", post(write_file_at))
             .route("/api/sftp/download", post(download_file))
+            .route("/api/sftp/rename", post(rename_path))
+            .route("/api/sftp/delete", post(delete_path))
+            .route("/api/sftp/mkdir", post(make_directory))
+            .route("/api/sftp/chmod", post(chmod))
+            .route("/api/sftp/stat", post(stat_path))
+            .route("/api/sftp/symlink", post(create_symlink))
+            .route("/api/sftp/readlink", post(read_link))
             
             // File transfer endpoints
             .route("/api/file-transfer/list", get(list_transfers))
             .route("/api/file-transfer/upload", post(upload_file_transfer))
             .route("/api/file-transfer/download", post(download_file_transfer))
+            .route("/api/file-transfer/upload-directory", post(upload_directory_transfer))
+            .route("/api/file-transfer/download-directory", post(download_directory_transfer))
+            .route("/api/file-transfer/manifest", post(export_transfer_manifest))
+            .route("/api/file-transfer/limit", get(get_global_transfer_limit).put(set_global_transfer_limit))
+            .route("/api/file-transfer/:transfer_id/limit", post(set_transfer_limit))
+            .route("/api/file-transfer/:transfer_id/pause", post(pause_transfer))
+            .route("/api/file-transfer/:transfer_id/resume", post(resume_transfer))
+            .route("/api/file-transfer/:transfer_id/cancel", post(cancel_transfer))
             
             // Terminal endpoints
             .route("/api/terminal/autocomplete", post(terminal_autocomplete))
@@ -116,24 +215,25 @@ impl AppServer {
             .route("/api/recording/:id/metadata", get(get_recording_metadata))
             .route("/api/recording/:id/events", get(get_recording_events))
             
+            // Logging
+            .route("/api/logs", get(query_logs))
+            .route("/api/logs/tail", get(tail_logs))
+            .route("/api/logs/level", get(get_log_level).merge(put(set_log_level)))
+
             // Health check
             .route("/health", get(health_check))
             
+            .layer(CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods(Any)
+                .allow_headers(Any))
+            .merge(approvals)
             .layer(
                 ServiceBuilder::new()
-                    .layer(CorsLayer::new()
-                        .allow_origin(Any)
-                        .allow_methods(Any)
-                        .allow_headers(Any))
+                    .layer(middleware::from_fn(correlation_middleware))
+                    .layer(middleware::from_fn(language_middleware))
             )
-            .with_state(AppState {
-                ssh_manager: self.ssh_manager.clone(),
-                transfer_manager: self.transfer_manager.clone(),
-                performance_monitor: self.performance_monitor.clone(),
-                performance_optimizer: self.performance_optimizer.clone(),
-                security_manager: self.security_manager.clone(),
-                recording_manager: self.recording_manager.clone(),
-            })
+            .with_state(state)
     }
 
     #[allow(dead_code)]
@@ -160,18 +260,189 @@ impl AppServer {
             }
         }
 
+        self.security_manager.stop_background_tasks();
+        self.recording_manager.stop_background_tasks();
+
         log::info!("Application server shutdown complete");
         Ok(())
     }
 }
 
+// Error body for every API endpoint, derived from AppError so clients can decide whether to retry.
+// `error` repeats the message for clients that only read that field, `localized_message` is in the request's language.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    success: bool,
+    code: &'static str,
+    message: String,
+    error: String,
+    localized_message: String,
+    severity: String,
+    retryable: bool,
+    correlation_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+impl From<AppError> for ApiError {
+    fn from(error: AppError) -> Self {
+        StructuredLogger::log_error(&error, Some("http_api"), None);
+
+        let message = error.to_string();
+        Self {
+            status: status_for(&error),
+            success: false,
+            code: error.error_code(),
+            error: message.clone(),
+            message,
+            localized_message: error.localized(i18n::current()),
+            severity: format!("{:?}", error.severity()),
+            retryable: error.is_retryable(),
+            correlation_id: correlation::current(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+pub fn status_for(error: &AppError) -> StatusCode {
+    match error {
+        AppError::ValidationError(_) | AppError::InvalidConfiguration(_) | AppError::SerializationError(_) => StatusCode::BAD_REQUEST,
+        AppError::SSHAuthenticationFailed(_) => StatusCode::UNAUTHORIZED,
+        AppError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+        AppError::SessionNotFound(_) | AppError::NotFound(_) => StatusCode::NOT_FOUND,
+        AppError::HostKeyUnknown(_) | AppError::HostKeyMismatch(_) => StatusCode::CONFLICT,
+        AppError::ResourceExhausted(_) => StatusCode::TOO_MANY_REQUESTS,
+        AppError::SSHConnectionFailed(_) => StatusCode::BAD_GATEWAY,
+        AppError::TimeoutError(_) => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn logging_disabled() -> ApiError {
+    ApiError::from(AppError::OperationFailed("File logging is not enabled".to_string()))
+        .with_status(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+fn decode_content(content: &str) -> Result<Vec<u8>, AppError> {
+    general_purpose::STANDARD.decode(content)
+        .map_err(|e| AppError::ValidationError(format!("Invalid base64 content: {}", e)))
+}
+
 // API Handlers
 
+#[derive(Debug, Deserialize)]
+struct WebSocketAuthQuery {
+    token: Option<String>,
+}
+
 async fn websocket_handler_wrapper(
     ws: axum::extract::WebSocketUpgrade,
+    Query(query): Query<WebSocketAuthQuery>,
+    headers: axum::http::HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     State(state): State<AppState>,
 ) -> axum::response::Response {
-    websocket_handler(ws, State(state.ssh_manager)).await
+    if let Err(rejection) = websocket::authorize_upgrade(&headers, query.token.as_deref()) {
+        let ip = connect_info.map(|ConnectInfo(address)| address.ip());
+        let origin = headers.get(header::ORIGIN).and_then(|value| value.to_str().ok());
+        log::warn!("Rejected WebSocket upgrade from {:?} (origin {:?}): {}", ip, origin, rejection.reason());
+        state.security_manager.record_rejected_upgrade(ip, rejection.reason(), origin).await;
+        return (rejection.status(), rejection.reason()).into_response();
+    }
+    let transfer_progress = state.transfer_manager.read().await.subscribe_progress();
+    websocket_handler(ws, state.ssh_manager, state.security_manager, transfer_progress)
+}
+
+// Connected WebSocket clients and when each last answered a ping
+async fn websocket_stats() -> Json<WebSocketStats> {
+    Json(websocket::stats())
+}
+
+// The managers' periodic cleanup tasks, each reported unhealthy when it's stuck
+async fn background_tasks() -> Json<Vec<BackgroundTaskStatus>> {
+    Json(background::statuses())
+}
+
+// Transfers, recordings and any other long-running work, newest first
+async fn list_jobs(State(state): State<AppState>) -> Json<Vec<Job>> {
+    let transfers = state.transfer_manager.read().await.list_transfers();
+    Json(jobs::list(&transfers, &state.recording_manager))
+}
+
+async fn cancel_job(
+    Path(job_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Job>, ApiError> {
+    let mut manager = state.transfer_manager.write().await;
+    if manager.get_transfer(&job_id).is_some() {
+        manager.cancel_transfer(&job_id)?;
+        let transfer = manager.get_transfer(&job_id)
+            .ok_or_else(|| AppError::NotFound(format!("Job {}", job_id)))?;
+        return Ok(Json(jobs::from_transfer(&transfer)));
+    }
+    drop(manager);
+    Ok(Json(jobs::cancel_or_stop(&job_id, &state.recording_manager).await?))
+}
+
+// Run each request under a correlation ID, reusing the client's X-Request-Id when it sends a usable one
+async fn correlation_middleware(request: Request, next: Next) -> Response {
+    let request_id = request.headers()
+        .get(correlation::REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(correlation::sanitize)
+        .unwrap_or_else(correlation::new_id);
+
+    let mut response = correlation::scope(request_id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(correlation::REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+// The same token check as WebSocket upgrades, for endpoints that act on a user's behalf
+async fn require_auth_token(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Err(rejection) = websocket::authorize_token(request.headers(), None) {
+        let ip = connect_info.map(|ConnectInfo(address)| address.ip());
+        let path = request.uri().path().to_string();
+        log::warn!("Rejected {} from {:?}: {}", path, ip, rejection.reason());
+        state.security_manager.record_rejected_request(ip, &path, rejection.reason()).await;
+        return (rejection.status(), rejection.reason()).into_response();
+    }
+    next.run(request).await
+}
+
+// Negotiate the language for localized messages from `?lang=` or Accept-Language
+async fn language_middleware(request: Request, next: Next) -> Response {
+    let from_query = request.uri().query()
+        .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("lang=")))
+        .and_then(Language::parse);
+    let language = from_query.unwrap_or_else(|| {
+        request.headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map_or_else(i18n::preferred, Language::negotiate)
+    });
+
+    let mut response = i18n::scope(language, next.run(request)).await;
+    response.headers_mut().insert(header::CONTENT_LANGUAGE, HeaderValue::from_static(language.code()));
+    response
 }
 
 async fn health_check() -> Json<serde_json::Value> {
@@ -197,165 +468,782 @@ struct ConnectRequest {
 #[derive(Serialize)]
 struct ConnectResponse {
     success: bool,
-    session_id: Option<String>,
-    error: Option<String>,
+    session_id: String,
 }
 
 async fn connect_ssh(
     State(state): State<AppState>,
     Json(request): Json<ConnectRequest>,
-) -> Json<ConnectResponse> {
-    let manager = state.ssh_manager.read().await;
-    
-    match manager.create_session(request.config).await {
-        Ok(session) => {
-            match manager.connect(&session.id).await {
-                Ok(_) => Json(ConnectResponse {
-                    success: true,
-                    session_id: Some(session.id),
-                    error: None,
-                }),
-                Err(e) => Json(ConnectResponse {
-                    success: false,
-                    session_id: None,
-                    error: Some(e.to_string()),
-                }),
-            }
-        }
-        Err(e) => Json(ConnectResponse {
-            success: false,
-            session_id: None,
-            error: Some(e.to_string()),
-        }),
+) -> Result<Json<ConnectResponse>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+
+    let session = manager.create_session(request.config).await?;
+    manager.connect(&session.id).await?;
+
+    Ok(Json(ConnectResponse {
+        success: true,
+        session_id: session.id,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileListQuery {
+    folder: Option<String>,
+}
+
+// All profiles, or those filed in a folder and the folders below it
+async fn list_profiles(
+    Query(query): Query<ProfileListQuery>,
+    State(state): State<AppState>,
+) -> Json<Vec<ConnectionProfile>> {
+    Json(match query.folder {
+        Some(folder) => state.profile_store.list_folder(&folder),
+        None => state.profile_store.list(),
+    })
+}
+
+async fn profile_folders(State(state): State<AppState>) -> Json<Vec<String>> {
+    Json(state.profile_store.folders())
+}
+
+async fn get_profile(
+    Path(profile_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<ConnectionProfile>, ApiError> {
+    let profile = state.profile_store.get(&profile_id)
+        .ok_or_else(|| AppError::NotFound(format!("Profile {}", profile_id)))?;
+    Ok(Json(profile))
+}
+
+async fn create_profile(
+    State(state): State<AppState>,
+    Json(request): Json<ProfileRequest>,
+) -> Result<(StatusCode, Json<ConnectionProfile>), ApiError> {
+    let profile = state.profile_store.create(request).await?;
+    Ok((StatusCode::CREATED, Json(profile)))
+}
+
+async fn update_profile(
+    Path(profile_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<ProfileRequest>,
+) -> Result<Json<ConnectionProfile>, ApiError> {
+    Ok(Json(state.profile_store.update(&profile_id, request).await?))
+}
+
+async fn delete_profile(
+    Path(profile_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, ApiError> {
+    if !state.profile_store.delete(&profile_id).await? {
+        return Err(AppError::NotFound(format!("Profile {}", profile_id)).into());
     }
+    Ok(StatusCode::NO_CONTENT)
 }
 
-#[derive(Serialize)]
-struct DisconnectResponse {
-    success: bool,
-    error: Option<String>,
+// Connect with a profile's stored settings and secrets, any overrides in the body applying to this connect only
+async fn connect_profile(
+    Path(profile_id): Path<String>,
+    State(state): State<AppState>,
+    body: axum::body::Bytes,
+) -> Result<Json<ConnectResponse>, ApiError> {
+    // An empty body connects as the profile is; a malformed one is refused rather than ignored
+    let overrides: ProfileOverrides = if body.is_empty() {
+        ProfileOverrides::default()
+    } else {
+        serde_json::from_slice(&body).map_err(AppError::from)?
+    };
+    let manager = state.ssh_manager.read().await;
+    let session = state.profile_store.connect_with_profile(&manager, &profile_id, &overrides).await?;
+
+    Ok(Json(ConnectResponse {
+        success: true,
+        session_id: session.id,
+    }))
+}
+
+// Connections to protected hosts waiting for their second step. A profile connect holds its
+// request open until it's answered here.
+async fn pending_approvals(State(state): State<AppState>) -> Json<Vec<ApprovalRequest>> {
+    Json(state.ssh_manager.read().await.approvals().pending())
+}
+
+#[derive(Debug, Deserialize)]
+struct ApprovalAnswer {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    approve: bool,
+}
+
+// A TOTP code, or an admin's decision
+async fn respond_to_approval(
+    Path(request_id): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    Json(answer): Json<ApprovalAnswer>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let approvals = state.ssh_manager.read().await.approvals();
+    // The token is shared, so where the holder called from is all that tells them apart
+    let approver = format!("auth token holder at {}", addr.ip());
+    match &answer.code {
+        Some(code) => approvals.submit_code(&request_id, code)?,
+        None if answer.approve => approvals.approve(&request_id, &approver)?,
+        None => approvals.deny(&request_id, &approver)?,
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true
+    })))
 }
 
 async fn disconnect_ssh(
     Path(session_id): Path<String>,
     State(state): State<AppState>,
-) -> Json<DisconnectResponse> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     let manager = state.ssh_manager.read().await;
-    
-    match manager.disconnect(&session_id).await {
-        Ok(_) => Json(DisconnectResponse {
-            success: true,
-            error: None,
-        }),
-        Err(e) => Json(DisconnectResponse {
-            success: false,
-            error: Some(e.to_string()),
-        }),
+    manager.disconnect(&session_id).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshQuery {
+    #[serde(default)]
+    refresh: bool,
+}
+
+// Host info gathered on connect, collected now if there is none yet or a refresh is asked for
+async fn get_host_info(
+    Path(session_id): Path<String>,
+    Query(query): Query<RefreshQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<HostInfo>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    let info = match manager.host_info(&session_id).await? {
+        Some(info) if !query.refresh => info,
+        _ => manager.collect_host_info(&session_id).await?,
+    };
+    Ok(Json(info))
+}
+
+// Shell, OS and locale detected on connect, detected again on refresh
+async fn get_environment(
+    Path(session_id): Path<String>,
+    Query(query): Query<RefreshQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<RemoteEnvironment>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    let environment = match manager.environment(&session_id).await? {
+        Some(environment) if !query.refresh => environment,
+        _ => manager.detect_environment(&session_id).await?,
+    };
+    Ok(Json(environment))
+}
+
+// Exported variables and aliases, collected on first request and again on refresh
+async fn get_variables(
+    Path(session_id): Path<String>,
+    Query(query): Query<RefreshQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<ShellVariables>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    let variables = match manager.variables(&session_id)? {
+        Some(variables) if !query.refresh => variables,
+        _ => manager.collect_variables(&session_id).await?,
+    };
+    Ok(Json(variables))
+}
+
+// Users and groups for naming file owners and choosing new ones, read on first request and again on refresh
+async fn get_accounts(
+    Path(session_id): Path<String>,
+    Query(query): Query<RefreshQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<RemoteAccounts>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    let accounts = match manager.accounts(&session_id)? {
+        Some(accounts) if !query.refresh => accounts,
+        _ => manager.collect_accounts(&session_id).await?,
+    };
+    Ok(Json(accounts))
+}
+
+async fn get_charset(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<TerminalCharset>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.charset(&session_id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct CharsetRequest {
+    // Omit, or send "auto", to go back to detection
+    #[serde(default)]
+    charset: Option<String>,
+}
+
+async fn set_charset(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<CharsetRequest>,
+) -> Result<Json<TerminalCharset>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.set_charset(&session_id, request.charset).await?))
+}
+
+// Replaces the session's labels, colour and notes
+async fn set_session_tags(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+    Json(tags): Json<SessionTags>,
+) -> Result<Json<SSHSession>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.set_tags(&session_id, tags).await?))
+}
+
+// DNS, TCP, handshake, auth and shell timings of each connect, plus later disconnects and errors
+async fn get_timeline(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TimelineEvent>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.timeline(&session_id)?))
+}
+
+#[derive(Debug, Deserialize)]
+struct ExecRequest {
+    command: String,
+}
+
+// Runs on an exec channel; stdout and stderr come back (and are recorded) separately
+async fn exec_command(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<ExecRequest>,
+) -> Result<Json<CommandExecResult>, ApiError> {
+    run_exec(&state, &session_id, &request.command).await.map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionExecRequest {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    command: String,
+}
+
+// Same as /api/ssh/:session_id/exec, for clients that address the session in the body
+// like /api/ssh/connect
+async fn exec_in_session(
+    State(state): State<AppState>,
+    Json(request): Json<SessionExecRequest>,
+) -> Result<Json<CommandExecResult>, ApiError> {
+    run_exec(&state, &request.session_id, &request.command).await.map(Json)
+}
+
+async fn run_exec(state: &AppState, session_id: &str, command: &str) -> Result<CommandExecResult, ApiError> {
+    let result = state.ssh_manager.read().await.exec_command(session_id, command).await?;
+    if let Err(e) = state.recording_manager.record_exec(session_id, command, &result, HashMap::new()).await {
+        log::warn!("Failed to record command for session {}: {}", session_id, e);
     }
+    Ok(result)
+}
+
+#[derive(Debug, Deserialize)]
+struct SendSignalRequest {
+    signal: TerminalSignal,
+}
+
+async fn send_signal(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<SendSignalRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    manager.signal_shell(&session_id, request.signal).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true
+    })))
+}
+
+async fn recent_paths(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.recent_paths(&session_id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    #[serde(default)]
+    prefix: String,
+    limit: Option<usize>,
+}
+
+// Commands entered on the session's host, best ranked first
+async fn command_history(
+    Path(session_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<HistoryEntry>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.command_history(&session_id, &query.prefix, query.limit.unwrap_or(100)).await?))
+}
+
+async fn clear_command_history(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    manager.clear_command_history(&session_id).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct BookmarkRequest {
+    name: String,
+}
+
+// Also goes into the session's recording, if one is running
+async fn add_bookmark(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<BookmarkRequest>,
+) -> Result<Json<OutputBookmark>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    let bookmark = manager.add_bookmark(&session_id, &request.name).await?;
+    if let Err(e) = state.recording_manager.record_bookmark(&session_id, &bookmark).await {
+        log::warn!("Failed to record bookmark for session {}: {}", session_id, e);
+    }
+    Ok(Json(bookmark))
+}
+
+async fn list_bookmarks(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<OutputBookmark>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.bookmarks(&session_id).await?))
+}
+
+async fn remove_bookmark(
+    Path((session_id, bookmark_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    manager.remove_bookmark(&session_id, &bookmark_id).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true
+    })))
+}
+
+async fn output_since_bookmark(
+    Path((session_id, bookmark_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<String, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(manager.output_since_bookmark(&session_id, &bookmark_id).await?)
+}
+
+async fn list_forwards(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<PortForward>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.list_port_forwards(Some(&session_id))))
+}
+
+#[derive(Debug, Deserialize)]
+struct LocalForwardRequest {
+    #[serde(rename = "localPort")]
+    local_port: u16,
+    #[serde(rename = "remoteHost")]
+    remote_host: String,
+    #[serde(rename = "remotePort")]
+    remote_port: u16,
+}
+
+async fn create_local_forward(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<LocalForwardRequest>,
+) -> Result<Json<PortForward>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.create_local_forward(&session_id, request.local_port, &request.remote_host, request.remote_port).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoteForwardRequest {
+    #[serde(rename = "remotePort")]
+    remote_port: u16,
+    #[serde(rename = "localHost")]
+    local_host: String,
+    #[serde(rename = "localPort")]
+    local_port: u16,
+}
+
+async fn create_remote_forward(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<RemoteForwardRequest>,
+) -> Result<Json<PortForward>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.create_remote_forward(&session_id, request.remote_port, &request.local_host, request.local_port).await?))
+}
+
+async fn close_forward(
+    Path(forward_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    manager.close_port_forward(&forward_id)?;
+
+    Ok(Json(serde_json::json!({
+        "success": true
+    })))
+}
+
+async fn list_processes(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<RemoteProcess>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.list_processes(&session_id).await?))
+}
+
+async fn inspect_network(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<NetworkInfo>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.inspect_network(&session_id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct SignalProcessesRequest {
+    pids: Vec<u32>,
+    signal: ProcessSignal,
+}
+
+async fn signal_processes(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<SignalProcessesRequest>,
+) -> Result<Json<Vec<SignalResult>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.signal_processes(&session_id, &request.pids, request.signal).await?))
+}
+
+async fn list_services(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ServiceUnit>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.list_services(&session_id).await?))
+}
+
+async fn service_status(
+    Path((session_id, unit)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<Json<ServiceStatus>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.service_status(&session_id, &unit).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceActionRequest {
+    action: ServiceAction,
+    #[serde(default)]
+    sudo: SudoOptions,
+}
+
+async fn service_action(
+    Path((session_id, unit)): Path<(String, String)>,
+    State(state): State<AppState>,
+    Json(request): Json<ServiceActionRequest>,
+) -> Result<Json<ServiceStatus>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.service_action(&session_id, &unit, request.action, &request.sudo).await?))
+}
+
+// Only passwordless sudo over GET, a password belongs in a request body
+#[derive(Debug, Deserialize)]
+struct DockerQuery {
+    #[serde(default)]
+    sudo: bool,
+}
+
+impl DockerQuery {
+    fn sudo(&self) -> SudoOptions {
+        SudoOptions { enabled: self.sudo, password: None }
+    }
+}
+
+async fn list_containers(
+    Path(session_id): Path<String>,
+    Query(query): Query<DockerQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DockerContainer>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.list_containers(&session_id, &query.sudo()).await?))
+}
+
+async fn list_images(
+    Path(session_id): Path<String>,
+    Query(query): Query<DockerQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DockerImage>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.list_images(&session_id, &query.sudo()).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct ContainerActionRequest {
+    action: ContainerAction,
+    #[serde(default)]
+    sudo: SudoOptions,
+}
+
+async fn container_action(
+    Path((session_id, container)): Path<(String, String)>,
+    State(state): State<AppState>,
+    Json(request): Json<ContainerActionRequest>,
+) -> Result<StatusCode, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    manager.container_action(&session_id, &container, request.action, &request.sudo).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_kube_contexts(
+    Path(session_id): Path<String>,
+    Query(scope): Query<KubeScope>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<KubeContext>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.kube_contexts(&session_id, &scope).await?))
+}
+
+async fn list_kube_namespaces(
+    Path(session_id): Path<String>,
+    Query(scope): Query<KubeScope>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<KubeNamespace>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.kube_namespaces(&session_id, &scope).await?))
+}
+
+async fn list_kube_pods(
+    Path(session_id): Path<String>,
+    Query(scope): Query<KubeScope>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<KubePod>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.kube_pods(&session_id, &scope).await?))
+}
+
+async fn list_multiplexer_sessions(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<MultiplexerSession>>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.list_multiplexer_sessions(&session_id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct MultiplexerAttachRequest {
+    kind: MultiplexerKind,
+    id: String,
+    #[serde(default, rename = "detachOthers")]
+    detach_others: bool,
+}
+
+async fn attach_multiplexer(
+    Path(session_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<MultiplexerAttachRequest>,
+) -> Result<StatusCode, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    manager.attach_multiplexer(&session_id, request.kind, &request.id, request.detach_others).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
 async fn list_files(
     State(state): State<AppState>,
     Json(request): Json<FileListRequest>,
-) -> Json<FileListResponse> {
+) -> Result<Json<FileListResponse>, ApiError> {
     log::info!("File listing requested for session: {}, path: {}", request.session_id, request.path);
 
     let manager = state.ssh_manager.read().await;
-
-    match manager.list_directory(&request.session_id, &request.path).await {
-        Ok(sftp_files) => {
-            // Convert SftpFileInfo to FileInfo
-            let files: Vec<FileInfo> = sftp_files.into_iter().map(|sftp_file| {
-                FileInfo {
-                    name: sftp_file.name,
-                    size: sftp_file.size,
-                    is_directory: sftp_file.is_directory,
-                    permissions: sftp_file.permissions.unwrap_or_else(|| "unknown".to_string()),
-                    last_modified: sftp_file.modified
-                        .and_then(|timestamp| chrono::DateTime::from_timestamp(timestamp, 0))
-                        .unwrap_or_else(chrono::Utc::now),
-                }
-            }).collect();
-
-            Json(FileListResponse {
-                files,
-                path: request.path,
-            })
-        }
-        Err(e) => {
-            log::error!("Failed to list files: {}", e);
-            Json(FileListResponse {
-                files: vec![],
-                path: request.path,
-            })
+    let sftp_files = manager.list_directory(&request.session_id, &request.path).await?;
+
+    // Convert SftpFileInfo to FileInfo
+    let files: Vec<FileInfo> = sftp_files.into_iter().map(|sftp_file| {
+        FileInfo {
+            name: sftp_file.name,
+            size: sftp_file.size,
+            is_directory: sftp_file.is_directory,
+            permissions: sftp_file.permissions.unwrap_or_else(|| "unknown".to_string()),
+            last_modified: sftp_file.modified
+                .and_then(|timestamp| chrono::DateTime::from_timestamp(timestamp, 0))
+                .unwrap_or_else(chrono::Utc::now),
+            // The bare id when the host's accounts couldn't be read
+            owner: sftp_file.owner.or_else(|| sftp_file.uid.map(|uid| uid.to_string())),
+            group: sftp_file.group.or_else(|| sftp_file.gid.map(|gid| gid.to_string())),
         }
-    }
+    }).collect();
+
+    Ok(Json(FileListResponse {
+        files,
+        path: request.path,
+    }))
 }
 
 async fn upload_file(
     State(state): State<AppState>,
     Json(request): Json<FileUploadRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     log::info!("File upload requested for session: {}, path: {}", request.session_id, request.remote_path);
 
-    // Decode base64 content
-    let contents = match general_purpose::STANDARD.decode(&request.content) {
-        Ok(data) => data,
-        Err(e) => {
-            log::error!("Failed to decode base64 content: {}", e);
-            return Ok(Json(serde_json::json!({
-                "success": false,
-                "error": "Invalid base64 content"
-            })));
-        }
-    };
+    let contents = decode_content(&request.content)?;
+    let manager = state.ssh_manager.read().await;
+    if request.atomic {
+        manager.upload_file_atomic(&request.session_id, &request.remote_path, &contents).await?;
+    } else {
+        manager.upload_file(&request.session_id, &request.remote_path, &contents).await?;
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "size": contents.len()
+    })))
+}
+
+async fn append_file(
+    State(state): State<AppState>,
+    Json(request): Json<FileUploadRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let contents = decode_content(&request.content)?;
+    let manager = state.ssh_manager.read().await;
+    let size = manager.append_file(&request.session_id, &request.remote_path, &contents).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "size": size
+    })))
+}
 
+async fn write_file_at(
+    State(state): State<AppState>,
+    Json(request): Json<FileWriteAtRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let contents = decode_content(&request.content)?;
     let manager = state.ssh_manager.read().await;
+    let size = manager.write_file_at(&request.session_id, &request.remote_path, request.offset, &contents).await?;
 
-    match manager.upload_file(&request.session_id, &request.remote_path, &contents).await {
-        Ok(_) => {
-            Ok(Json(serde_json::json!({
-                "success": true,
-                "size": contents.len()
-            })))
-        }
-        Err(e) => {
-            log::error!("Failed to upload file: {}", e);
-            Ok(Json(serde_json::json!({
-                "success": false,
-                "error": format!("Upload failed: {}", e)
-            })))
-        }
-    }
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "size": size
+    })))
 }
 
 async fn download_file(
     State(state): State<AppState>,
     Json(request): Json<FileDownloadRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     log::info!("File download requested for session: {}, path: {}", request.session_id, request.remote_path);
 
     let manager = state.ssh_manager.read().await;
+    let contents = manager.download_file(&request.session_id, &request.remote_path).await?;
 
-    match manager.download_file(&request.session_id, &request.remote_path).await {
-        Ok(contents) => {
-            // Encode file contents as base64
-            let encoded_content = general_purpose::STANDARD.encode(&contents);
+    // Encode file contents as base64
+    let encoded_content = general_purpose::STANDARD.encode(&contents);
 
-            Ok(Json(serde_json::json!({
-                "success": true,
-                "content": encoded_content,
-                "size": contents.len()
-            })))
-        }
-        Err(e) => {
-            log::error!("Failed to download file: {}", e);
-            Ok(Json(serde_json::json!({
-                "success": false,
-                "error": format!("Download failed: {}", e)
-            })))
-        }
-    }
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "content": encoded_content,
+        "size": contents.len()
+    })))
+}
+
+async fn rename_path(
+    State(state): State<AppState>,
+    Json(request): Json<SftpRenameRequest>,
+) -> Result<StatusCode, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    manager.rename_path(&request.session_id, &request.from, &request.to, request.overwrite).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_path(
+    State(state): State<AppState>,
+    Json(request): Json<SftpDeleteRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    log::info!("Delete requested for session: {}, path: {}, recursive: {}", request.session_id, request.path, request.recursive);
+
+    let manager = state.ssh_manager.read().await;
+    let removed = manager.delete_path(&request.session_id, &request.path, request.recursive).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "removed": removed
+    })))
+}
+
+async fn make_directory(
+    State(state): State<AppState>,
+    Json(request): Json<SftpModeRequest>,
+) -> Result<StatusCode, ApiError> {
+    let mode = request.mode.as_deref().map(parse_mode).transpose()?;
+    let manager = state.ssh_manager.read().await;
+    manager.make_directory(&request.session_id, &request.path, mode).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn chmod(
+    State(state): State<AppState>,
+    Json(request): Json<SftpModeRequest>,
+) -> Result<StatusCode, ApiError> {
+    let mode = request.mode.as_deref()
+        .ok_or_else(|| AppError::ValidationError("A mode is required".to_string()))
+        .and_then(parse_mode)?;
+    let manager = state.ssh_manager.read().await;
+    manager.chmod(&request.session_id, &request.path, mode).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn stat_path(
+    State(state): State<AppState>,
+    Json(request): Json<SftpPathRequest>,
+) -> Result<Json<SftpStat>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    Ok(Json(manager.stat_path(&request.session_id, &request.path).await?))
+}
+
+async fn create_symlink(
+    State(state): State<AppState>,
+    Json(request): Json<SftpSymlinkRequest>,
+) -> Result<StatusCode, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    manager.create_symlink(&request.session_id, &request.target, &request.link_path).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn read_link(
+    State(state): State<AppState>,
+    Json(request): Json<SftpPathRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let manager = state.ssh_manager.read().await;
+    let target = manager.read_link(&request.session_id, &request.path).await?;
+    Ok(Json(serde_json::json!({ "target": target })))
 }
 
 async fn list_transfers(
@@ -368,113 +1256,167 @@ async fn list_transfers(
     }))
 }
 
+async fn export_transfer_manifest(
+    State(state): State<AppState>,
+    Json(request): Json<TransferManifestRequest>,
+) -> Result<Response, ApiError> {
+    let manager = state.transfer_manager.read().await;
+    let manifest = manager.export_manifest(&request.transfer_ids, request.format)?;
+    let content_type = match request.format {
+        ManifestFormat::Json => "application/json",
+        ManifestFormat::Csv => "text/csv; charset=utf-8",
+    };
+    Ok(([(header::CONTENT_TYPE, content_type)], manifest).into_response())
+}
+
+// The cap shared by all transfers
+async fn get_global_transfer_limit() -> Json<TransferLimit> {
+    Json(TransferLimit {
+        max_bytes_per_second: transfer::global_limit().get(),
+    })
+}
+
+async fn set_global_transfer_limit(Json(request): Json<TransferLimit>) -> Result<Json<TransferLimit>, ApiError> {
+    transfer::global_limit().set(request.max_bytes_per_second)?;
+    Ok(Json(request))
+}
+
+async fn set_transfer_limit(
+    Path(transfer_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<TransferLimit>,
+) -> Result<Json<FileTransfer>, ApiError> {
+    Ok(Json(state.transfer_manager.read().await.set_transfer_limit(&transfer_id, request.max_bytes_per_second)?))
+}
+
+async fn pause_transfer(
+    Path(transfer_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<FileTransfer>, ApiError> {
+    Ok(Json(state.transfer_manager.read().await.pause_transfer(&transfer_id)?))
+}
+
+async fn resume_transfer(
+    Path(transfer_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<FileTransfer>, ApiError> {
+    Ok(Json(state.transfer_manager.read().await.resume_transfer(&transfer_id)?))
+}
+
+async fn cancel_transfer(
+    Path(transfer_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<FileTransfer>, ApiError> {
+    let mut manager = state.transfer_manager.write().await;
+    manager.cancel_transfer(&transfer_id)?;
+    let transfer = manager.get_transfer(&transfer_id)
+        .ok_or_else(|| AppError::NotFound(format!("Transfer {}", transfer_id)))?;
+    Ok(Json(transfer))
+}
+
 async fn upload_file_transfer(
     State(state): State<AppState>,
     Json(request): Json<TransferUploadRequest>,
-) -> Json<serde_json::Value> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     log::info!("File transfer upload requested for session: {}, path: {}", request.session_id, request.remote_path);
 
-    // Decode base64 content
-    let contents = match general_purpose::STANDARD.decode(&request.content) {
-        Ok(data) => data,
-        Err(e) => {
-            log::error!("Failed to decode base64 content: {}", e);
-            return Json(serde_json::json!({
-                "success": false,
-                "error": "Invalid base64 content"
-            }));
-        }
-    };
-
+    let contents = decode_content(&request.content)?;
     let mut manager = state.transfer_manager.write().await;
-
-    match manager.start_upload(
+    let transfer_id = manager.start_upload(
         request.session_id,
         request.remote_path,
         request.name,
         contents,
-    ).await {
-        Ok(transfer_id) => {
-            Json(serde_json::json!({
-                "success": true,
-                "transferId": transfer_id
-            }))
-        }
-        Err(e) => {
-            log::error!("Failed to start upload: {}", e);
-            Json(serde_json::json!({
-                "success": false,
-                "error": format!("Upload failed: {}", e)
-            }))
-        }
-    }
+    ).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "transferId": transfer_id
+    })))
 }
 
 async fn download_file_transfer(
     State(state): State<AppState>,
     Json(request): Json<TransferDownloadRequest>,
-) -> Json<serde_json::Value> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     log::info!("File transfer download requested for session: {}, path: {}", request.session_id, request.remote_path);
 
     let mut manager = state.transfer_manager.write().await;
-
-    match manager.start_download(
+    let transfer_id = manager.start_download(
         request.session_id,
         request.remote_path,
         request.name,
-    ).await {
-        Ok(transfer_id) => {
-            Json(serde_json::json!({
-                "success": true,
-                "transferId": transfer_id
-            }))
-        }
-        Err(e) => {
-            log::error!("Failed to start download: {}", e);
-            Json(serde_json::json!({
-                "success": false,
-                "error": format!("Download failed: {}", e)
-            }))
-        }
-    }
+    ).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "transferId": transfer_id
+    })))
+}
+
+async fn upload_directory_transfer(
+    State(state): State<AppState>,
+    Json(request): Json<DirectoryTransferRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    log::info!("Directory upload requested for session: {}, {} to {}", request.session_id, request.local_path, request.remote_path);
+
+    let mut manager = state.transfer_manager.write().await;
+    let transfer_id = manager.start_directory_upload(
+        request.session_id,
+        request.local_path,
+        request.remote_path,
+    ).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "transferId": transfer_id
+    })))
+}
+
+async fn download_directory_transfer(
+    State(state): State<AppState>,
+    Json(request): Json<DirectoryTransferRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    log::info!("Directory download requested for session: {}, {} to {}", request.session_id, request.remote_path, request.local_path);
+
+    let mut manager = state.transfer_manager.write().await;
+    let transfer_id = manager.start_directory_download(
+        request.session_id,
+        request.remote_path,
+        request.local_path,
+    ).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "transferId": transfer_id
+    })))
 }
 
 async fn terminal_autocomplete(
     State(state): State<AppState>,
     Json(request): Json<AutocompleteRequest>,
-) -> Json<AutocompleteResponse> {
+) -> Result<Json<AutocompleteResponse>, ApiError> {
     log::info!("Terminal autocomplete requested for session: {}, input: '{}'", request.session_id, request.input);
 
     let manager = state.ssh_manager.read().await;
+    let suggestions = manager.get_autocomplete_suggestions(&request.session_id, &request.input, request.cursor_position).await?;
 
-    match manager.get_autocomplete_suggestions(&request.session_id, &request.input, request.cursor_position).await {
-        Ok(suggestions) => {
-            // Extract the prefix for the current word
-            let chars: Vec<char> = request.input.chars().collect();
-            let cursor_pos = request.cursor_position.min(chars.len());
+    // Extract the prefix for the current word
+    let chars: Vec<char> = request.input.chars().collect();
+    let cursor_pos = request.cursor_position.min(chars.len());
 
-            let mut start = cursor_pos;
-            while start > 0 && !chars[start - 1].is_whitespace() {
-                start -= 1;
-            }
+    let mut start = cursor_pos;
+    while start > 0 && !chars[start - 1].is_whitespace() {
+        start -= 1;
+    }
 
-            let prefix: String = chars[start..cursor_pos].iter().collect();
+    let prefix: String = chars[start..cursor_pos].iter().collect();
 
-            Json(AutocompleteResponse {
-                suggestions,
-                prefix,
-                cursor_position: request.cursor_position,
-            })
-        }
-        Err(e) => {
-            log::error!("Failed to get autocomplete suggestions: {}", e);
-            Json(AutocompleteResponse {
-                suggestions: vec![],
-                prefix: String::new(),
-                cursor_position: request.cursor_position,
-            })
-        }
-    }
+    Ok(Json(AutocompleteResponse {
+        suggestions,
+        prefix,
+        cursor_position: request.cursor_position,
+    }))
 }
 
 async fn mobile_session(
@@ -487,41 +1429,47 @@ async fn mobile_session(
                request.device_info.screen_height);
 
     // Generate recommendations based on device info
+    let language = i18n::current();
     let mut recommendations = Vec::new();
     let mut applied_optimizations = request.optimizations.clone();
 
     // Analyze device characteristics and provide recommendations
     if request.device_info.screen_width < 768 {
-        recommendations.push("Consider using compact terminal layout for small screens".to_string());
+        recommendations.push(i18n::text("mobile.compact_layout", language).to_string());
         applied_optimizations.increase_touch_targets = true;
     }
 
     if request.device_info.is_tablet {
-        recommendations.push("Tablet detected: enabling split-screen optimizations".to_string());
+        recommendations.push(i18n::text("mobile.tablet", language).to_string());
         applied_optimizations.optimize_scrolling = true;
     }
 
     if request.device_info.platform == "ios" || request.device_info.platform == "android" {
-        recommendations.push("Mobile platform detected: enabling battery optimizations".to_string());
+        recommendations.push(i18n::text("mobile.platform", language).to_string());
         applied_optimizations.battery_optimization = true;
         applied_optimizations.reduce_network_usage = true;
     }
 
     if request.device_info.pixel_ratio > 2.0 {
-        recommendations.push("High DPI display detected: optimizing for crisp text rendering".to_string());
+        recommendations.push(i18n::text("mobile.high_dpi", language).to_string());
     }
 
     if !request.device_info.supports_touch {
-        recommendations.push("Non-touch device: optimizing for keyboard navigation".to_string());
+        recommendations.push(i18n::text("mobile.keyboard", language).to_string());
     } else {
-        recommendations.push("Touch device: enabling gesture controls".to_string());
+        recommendations.push(i18n::text("mobile.touch", language).to_string());
         applied_optimizations.increase_touch_targets = true;
     }
 
     // Apply performance optimizations based on device capabilities
     if request.device_info.screen_width < 480 || request.device_info.screen_height < 800 {
         applied_optimizations.reduce_animations = true;
-        recommendations.push("Small screen detected: reducing animations for better performance".to_string());
+        recommendations.push(i18n::text("mobile.small_screen", language).to_string());
+    }
+
+    let data_usage = request.session_id.as_deref().and_then(websocket::session_data_usage);
+    if applied_optimizations.reduce_network_usage && !data_usage.as_ref().is_some_and(|usage| usage.data_saver) {
+        recommendations.push(i18n::text("mobile.data_saver", language).to_string());
     }
 
     Json(MobileSessionResponse {
@@ -529,6 +1477,7 @@ async fn mobile_session(
         session_id: request.session_id,
         applied_optimizations,
         recommendations,
+        data_usage,
         error: None,
     })
 }
@@ -564,28 +1513,90 @@ async fn performance_optimization(
 }
 
 fn generate_performance_recommendations(summary: &crate::optimization::PerformanceSummary) -> Vec<String> {
+    let language = i18n::current();
     let mut recommendations = Vec::new();
 
     if summary.active_connections > 40 {
-        recommendations.push("High connection count detected. Consider implementing connection pooling.".to_string());
+        recommendations.push(i18n::text("performance.connections", language).to_string());
     }
 
     if summary.active_tasks > 15 {
-        recommendations.push("High task count detected. Consider task queuing or rate limiting.".to_string());
+        recommendations.push(i18n::text("performance.tasks", language).to_string());
     }
 
     let memory_mb = summary.memory_usage_bytes / (1024 * 1024);
     if memory_mb > 400 {
-        recommendations.push("High memory usage detected. Consider implementing memory cleanup.".to_string());
+        recommendations.push(i18n::text("performance.memory", language).to_string());
     }
 
     if recommendations.is_empty() {
-        recommendations.push("System performance is optimal.".to_string());
+        recommendations.push(i18n::text("performance.optimal", language).to_string());
     }
 
     recommendations
 }
 
+async fn query_logs(
+    Query(query): Query<LogQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let logger = log_file::logger()
+        .ok_or_else(logging_disabled)?;
+
+    let entries = tokio::task::spawn_blocking(move || log_query::query_logs(logger.writer(), &query))
+        .await
+        .map_err(|e| AppError::InternalError(format!("Log query failed: {}", e)))??;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "entries": entries
+    })))
+}
+
+// Server-sent events with every new log entry that matches the filters
+async fn tail_logs(
+    Query(query): Query<LogQuery>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, ApiError> {
+    query.validate()?;
+    let logger = log_file::logger()
+        .ok_or_else(logging_disabled)?;
+
+    let stream = futures_util::stream::unfold((logger.subscribe(), query), |(mut lines, query)| async move {
+        loop {
+            match lines.recv().await {
+                Ok(line) => {
+                    let matches = serde_json::from_str::<serde_json::Value>(&line)
+                        .is_ok_and(|entry| query.matches(&entry));
+                    if matches {
+                        return Some((Ok(SseEvent::default().event("log").data(line)), (lines, query)));
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    let event = SseEvent::default().event("lagged").data(skipped.to_string());
+                    return Some((Ok(event), (lines, query)));
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+async fn get_log_level() -> Json<LogLevelConfig> {
+    Json(log_filter::current())
+}
+
+async fn set_log_level(
+    Json(config): Json<LogLevelConfig>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let config = log_filter::configure(&config)?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "config": config
+    })))
+}
+
 async fn security_stats(
     State(state): State<AppState>,
 ) -> Json<serde_json::Value> {
@@ -607,30 +1618,31 @@ async fn security_stats(
 }
 
 fn generate_security_recommendations(stats: &crate::security::SecurityStats) -> Vec<String> {
+    let language = i18n::current();
     let mut recommendations = Vec::new();
 
     if stats.critical_events_last_day > 0 {
-        recommendations.push("Critical security events detected in the last 24 hours. Review security logs immediately.".to_string());
+        recommendations.push(i18n::text("security.critical_events", language).to_string());
     }
 
     if stats.locked_accounts > 0 {
-        recommendations.push(format!("{} accounts are currently locked due to failed login attempts.", stats.locked_accounts));
+        recommendations.push(i18n::format("security.locked_accounts", language, &[("count", stats.locked_accounts.to_string())]));
     }
 
     if stats.active_rate_limits > 10 {
-        recommendations.push("High number of rate-limited IPs detected. Consider reviewing access patterns.".to_string());
+        recommendations.push(i18n::text("security.rate_limits", language).to_string());
     }
 
     if stats.active_connections > 50 {
-        recommendations.push("High number of active connections. Monitor for potential DDoS activity.".to_string());
+        recommendations.push(i18n::text("security.connections", language).to_string());
     }
 
     if stats.events_last_hour > 100 {
-        recommendations.push("High security event volume in the last hour. Review for suspicious activity.".to_string());
+        recommendations.push(i18n::text("security.event_volume", language).to_string());
     }
 
     if recommendations.is_empty() {
-        recommendations.push("Security status is normal. No immediate action required.".to_string());
+        recommendations.push(i18n::text("security.normal", language).to_string());
     }
 
     recommendations
@@ -663,59 +1675,69 @@ async fn recording_stats(
 async fn search_recordings(
     State(state): State<AppState>,
     Json(criteria): Json<crate::recording::RecordingSearchCriteria>,
-) -> Json<serde_json::Value> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     log::info!("Recording search requested with criteria: {:?}", criteria);
 
-    match state.recording_manager.search_recordings(criteria).await {
-        Ok(recordings) => Json(serde_json::json!({
-            "success": true,
-            "recordings": recordings,
-            "count": recordings.len()
-        })),
-        Err(error) => Json(serde_json::json!({
-            "success": false,
-            "error": error.to_string()
-        }))
-    }
+    let recordings = state.recording_manager.search_recordings(criteria).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "recordings": recordings,
+        "count": recordings.len()
+    })))
 }
 
 async fn get_recording_metadata(
     State(state): State<AppState>,
     Path(recording_id): Path<String>,
-) -> Json<serde_json::Value> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     log::info!("Recording metadata requested for: {}", recording_id);
 
-    match state.recording_manager.get_recording_metadata(&recording_id).await {
-        Ok(Some(metadata)) => Json(serde_json::json!({
-            "success": true,
-            "metadata": metadata
-        })),
-        Ok(None) => Json(serde_json::json!({
-            "success": false,
-            "error": "Recording not found"
-        })),
-        Err(error) => Json(serde_json::json!({
-            "success": false,
-            "error": error.to_string()
-        }))
-    }
+    let metadata = state.recording_manager.get_recording_metadata(&recording_id).await?
+        .ok_or_else(|| AppError::NotFound(format!("Recording not found: {}", recording_id)))?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "metadata": metadata
+    })))
 }
 
 async fn get_recording_events(
     State(state): State<AppState>,
     Path(recording_id): Path<String>,
-) -> Json<serde_json::Value> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     log::info!("Recording events requested for: {}", recording_id);
 
-    match state.recording_manager.load_recording_events(&recording_id, None).await {
-        Ok(events) => Json(serde_json::json!({
-            "success": true,
-            "events": events,
-            "count": events.len()
-        })),
-        Err(error) => Json(serde_json::json!({
-            "success": false,
-            "error": error.to_string()
-        }))
+    let events = state.recording_manager.load_recording_events(&recording_id, None).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "events": events,
+        "count": events.len()
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_api_error_body() {
+        let error = correlation::scope("req-7".to_string(), async {
+            ApiError::from(AppError::SSHConnectionFailed("Connection refused".to_string()))
+        }).await;
+        assert_eq!(error.status, StatusCode::BAD_GATEWAY);
+
+        let body = serde_json::to_value(&error).unwrap();
+        assert_eq!(body["success"], false);
+        assert_eq!(body["code"], "CONNECTION_FAILED");
+        assert_eq!(body["retryable"], true);
+        assert_eq!(body["correlation_id"], "req-7");
+        assert!(body.get("status").is_none());
+
+        let error = i18n::scope(Language::ZhCn, async {
+            ApiError::from(AppError::SessionNotFound("s1".to_string()))
+        }).await;
+        assert_eq!(error.localized_message, "会话不存在: s1");
+
+        assert_eq!(status_for(&AppError::SessionNotFound("s1".to_string())), StatusCode::NOT_FOUND);
+        assert_eq!(logging_disabled().status, StatusCode::SERVICE_UNAVAILABLE);
     }
 }
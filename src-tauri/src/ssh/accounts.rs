@@ -0,0 +1,113 @@
+use crate::types::{RemoteAccounts, RemoteGroup, RemoteUser};
+use chrono::Utc;
+use std::time::Duration;
+
+// getent also lists accounts from LDAP or NIS; minimal systems without it only have the files.
+// The script itself must stay free of single quotes.
+pub const COLLECT_COMMAND: &str = "sh -c '\
+echo ==passwd; getent passwd 2>/dev/null || cat /etc/passwd; \
+echo ==group; getent group 2>/dev/null || cat /etc/group; \
+true'";
+
+// A directory listing waits this long for the accounts before showing bare ids
+pub const LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub fn parse(stdout: &str) -> RemoteAccounts {
+    let (passwd, group) = match stdout.split_once("==group\n") {
+        Some((passwd, group)) => (passwd, group),
+        None => (stdout, ""),
+    };
+    let passwd = passwd.strip_prefix("==passwd\n").unwrap_or(passwd);
+
+    // A getent that fails partway is followed by the whole file, so the first entry of an id wins
+    let mut users: Vec<RemoteUser> = Vec::new();
+    for user in passwd.lines().filter_map(parse_user) {
+        if !users.iter().any(|known| known.uid == user.uid) {
+            users.push(user);
+        }
+    }
+    users.sort_by_key(|user| user.uid);
+    let mut groups: Vec<RemoteGroup> = Vec::new();
+    for group in group.lines().filter_map(parse_group) {
+        if !groups.iter().any(|known| known.gid == group.gid) {
+            groups.push(group);
+        }
+    }
+    groups.sort_by_key(|group| group.gid);
+
+    RemoteAccounts {
+        users,
+        groups,
+        collected_at: Utc::now(),
+    }
+}
+
+// name:password:uid:gid:gecos:home:shell
+fn parse_user(line: &str) -> Option<RemoteUser> {
+    let fields: Vec<&str> = line.trim_end_matches('\r').split(':').collect();
+    if fields.len() < 7 || fields[0].is_empty() {
+        return None;
+    }
+    Some(RemoteUser {
+        uid: fields[2].parse().ok()?,
+        gid: fields[3].parse().ok()?,
+        name: fields[0].to_string(),
+        home: fields[5].to_string(),
+        shell: fields[6].to_string(),
+    })
+}
+
+// name:password:gid:member,member
+fn parse_group(line: &str) -> Option<RemoteGroup> {
+    let fields: Vec<&str> = line.trim_end_matches('\r').split(':').collect();
+    if fields.len() < 4 || fields[0].is_empty() {
+        return None;
+    }
+    Some(RemoteGroup {
+        gid: fields[2].parse().ok()?,
+        name: fields[0].to_string(),
+        members: fields[3].split(',')
+            .filter(|member| !member.is_empty())
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_accounts() {
+        let stdout = "==passwd\n\
+root:x:0:0:root:/root:/bin/bash\n\
+alice:x:1000:1000:Alice,,,:/home/alice:/bin/zsh\n\
++::::::\n\
+broken:x:abc:0::/:/bin/sh\n\
+root:x:0:0:root:/root:/bin/bash\n\
+==group\n\
+root:x:0:\n\
+sudo:x:27:alice,bob\n\
+alice:x:1000:\n";
+        let accounts = parse(stdout);
+
+        let names: Vec<&str> = accounts.users.iter().map(|user| user.name.as_str()).collect();
+        assert_eq!(names, vec!["root", "alice"]);
+        assert_eq!(accounts.users[1].home, "/home/alice");
+        assert_eq!(accounts.users[1].shell, "/bin/zsh");
+        assert_eq!(accounts.groups.len(), 3);
+        assert_eq!(accounts.groups[1].members, vec!["alice", "bob"]);
+        assert!(accounts.groups[0].members.is_empty());
+
+        assert_eq!(accounts.user_name(1000).as_deref(), Some("alice"));
+        assert_eq!(accounts.group_name(27).as_deref(), Some("sudo"));
+        assert_eq!(accounts.user_name(4242), None);
+    }
+
+    #[test]
+    fn test_parse_without_groups() {
+        let accounts = parse("==passwd\nnobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin\n");
+        assert_eq!(accounts.users.len(), 1);
+        assert!(accounts.groups.is_empty());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::types::{
+    AppError, AppResult, ApprovalAttempt, ApprovalMethod, ApprovalOutcome, ApprovalRequest, ConnectApproval,
+    SSHConnectionConfig,
+};
+use crate::webhooks::{self, WebhookEventKind};
+use chrono::Utc;
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot};
+use uuid::Uuid;
+
+// Long enough to reach an admin, short enough not to leave connects hanging
+const APPROVAL_TIMEOUT: Duration = Duration::from_secs(300);
+// Wrong codes allowed before the request is denied
+const MAX_CODE_ATTEMPTS: u32 = 3;
+const TOTP_STEP_SECS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+// Codes from the step before and after still count, for clocks that drift
+const TOTP_SKEW_STEPS: i64 = 1;
+
+struct Decision {
+    outcome: ApprovalOutcome,
+    approver: Option<String>,
+}
+
+struct PendingApproval {
+    request: ApprovalRequest,
+    secret: Option<Vec<u8>>,
+    failed_codes: u32,
+    sender: oneshot::Sender<Decision>,
+}
+
+// Holds connections to protected hosts until someone enters a TOTP code or approves them.
+// Every step goes out as an attempt, for the security log.
+pub struct ConnectApprovals {
+    pending: DashMap<String, PendingApproval>,
+    requests: broadcast::Sender<ApprovalRequest>,
+    attempts: broadcast::Sender<ApprovalAttempt>,
+}
+
+impl ConnectApprovals {
+    pub fn new() -> Self {
+        Self {
+            pending: DashMap::new(),
+            requests: broadcast::channel(16).0,
+            attempts: broadcast::channel(64).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ApprovalRequest> {
+        self.requests.subscribe()
+    }
+
+    pub fn attempts(&self) -> broadcast::Receiver<ApprovalAttempt> {
+        self.attempts.subscribe()
+    }
+
+    // Oldest first
+    pub fn pending(&self) -> Vec<ApprovalRequest> {
+        let mut requests: Vec<ApprovalRequest> = self.pending.iter()
+            .map(|entry| entry.request.clone())
+            .collect();
+        requests.sort_by_key(|request| request.requested_at);
+        requests
+    }
+
+    // Wait for the second step, failing the connect unless it's approved in time
+    pub async fn require(&self, session_id: &str, config: &SSHConnectionConfig, approval: &ConnectApproval) -> AppResult<()> {
+        let secret = match approval.method {
+            ApprovalMethod::Totp => {
+                let secret = approval.totp_secret.as_deref().ok_or_else(|| AppError::InvalidConfiguration(format!(
+                    "Profile {} is protected by TOTP but has no secret", approval.profile_name
+                )))?;
+                Some(decode_secret(secret)?)
+            }
+            ApprovalMethod::Admin => None,
+        };
+
+        let now = Utc::now();
+        let request = ApprovalRequest {
+            request_id: Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            profile_id: approval.profile_id.clone(),
+            profile_name: approval.profile_name.clone(),
+            hostname: config.hostname.clone(),
+            port: config.port,
+            username: config.username.clone(),
+            method: approval.method,
+            requested_at: now,
+            expires_at: now + chrono::Duration::from_std(APPROVAL_TIMEOUT).unwrap_or_default(),
+        };
+        let request_id = request.request_id.clone();
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending.insert(request_id.clone(), PendingApproval {
+            request: request.clone(),
+            secret,
+            failed_codes: 0,
+            sender,
+        });
+        self.record(&request, ApprovalOutcome::Requested, None);
+        if approval.method == ApprovalMethod::Admin {
+            webhooks::emit(WebhookEventKind::ApprovalRequested, Some(session_id), serde_json::to_value(&request).unwrap_or_default());
+        }
+        let _ = self.requests.send(request.clone());
+
+        let decision = match tokio::time::timeout(APPROVAL_TIMEOUT, receiver).await {
+            Ok(Ok(decision)) => decision,
+            Ok(Err(_)) => Decision { outcome: ApprovalOutcome::Cancelled, approver: None },
+            Err(_) => Decision { outcome: ApprovalOutcome::Expired, approver: None },
+        };
+        self.pending.remove(&request_id);
+        self.record(&request, decision.outcome, decision.approver);
+
+        match decision.outcome {
+            ApprovalOutcome::Approved => Ok(()),
+            outcome => Err(AppError::PermissionDenied(format!(
+                "Connection to protected host {} was not approved ({})", approval.profile_name, outcome_label(outcome)
+            ))),
+        }
+    }
+
+    // Check a code for a TOTP request. Too many wrong ones deny the request.
+    pub fn submit_code(&self, request_id: &str, code: &str) -> AppResult<()> {
+        let mut pending = self.pending.get_mut(request_id)
+            .ok_or_else(|| AppError::NotFound(format!("Approval request {}", request_id)))?;
+        let Some(secret) = &pending.secret else {
+            return Err(AppError::ValidationError("This request needs an approval, not a code".to_string()));
+        };
+
+        if verify_code(secret, code.trim(), Utc::now().timestamp()) {
+            drop(pending);
+            return self.decide(request_id, ApprovalOutcome::Approved, None);
+        }
+
+        pending.failed_codes += 1;
+        let denied = pending.failed_codes >= MAX_CODE_ATTEMPTS;
+        let request = pending.request.clone();
+        drop(pending);
+        self.record(&request, ApprovalOutcome::InvalidCode, None);
+        if denied {
+            self.decide(request_id, ApprovalOutcome::Denied, None)?;
+        }
+        Err(AppError::PermissionDenied("Invalid verification code".to_string()))
+    }
+
+    // Approve an admin request; TOTP requests only take a code
+    pub fn approve(&self, request_id: &str, approver: &str) -> AppResult<()> {
+        let needs_code = self.pending.get(request_id)
+            .ok_or_else(|| AppError::NotFound(format!("Approval request {}", request_id)))?
+            .secret.is_some();
+        if needs_code {
+            return Err(AppError::ValidationError("This request needs a verification code".to_string()));
+        }
+        self.decide(request_id, ApprovalOutcome::Approved, Some(approver.to_string()))
+    }
+
+    pub fn deny(&self, request_id: &str, approver: &str) -> AppResult<()> {
+        self.decide(request_id, ApprovalOutcome::Denied, Some(approver.to_string()))
+    }
+
+    // Dropping the senders wakes the connects up, which then fail as cancelled
+    pub fn cancel_session(&self, session_id: &str) {
+        self.pending.retain(|_, pending| pending.request.session_id != session_id);
+    }
+
+    fn decide(&self, request_id: &str, outcome: ApprovalOutcome, approver: Option<String>) -> AppResult<()> {
+        let (_, pending) = self.pending.remove(request_id)
+            .ok_or_else(|| AppError::NotFound(format!("Approval request {}", request_id)))?;
+        pending.sender.send(Decision { outcome, approver })
+            .map_err(|_| AppError::OperationFailed(format!("Approval request {} is no longer waiting", request_id)))
+    }
+
+    fn record(&self, request: &ApprovalRequest, outcome: ApprovalOutcome, approver: Option<String>) {
+        let _ = self.attempts.send(ApprovalAttempt {
+            request: request.clone(),
+            outcome,
+            approver,
+            at: Utc::now(),
+        });
+    }
+}
+
+impl Default for ConnectApprovals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn outcome_label(outcome: ApprovalOutcome) -> &'static str {
+    match outcome {
+        ApprovalOutcome::Requested => "pending",
+        ApprovalOutcome::Approved => "approved",
+        ApprovalOutcome::Denied => "denied",
+        ApprovalOutcome::InvalidCode => "invalid code",
+        ApprovalOutcome::Expired => "timed out",
+        ApprovalOutcome::Cancelled => "cancelled",
+    }
+}
+
+// RFC 4648 base32 as authenticator apps show it: any case, spaces and padding ignored
+pub fn decode_secret(secret: &str) -> AppResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut buffer: u64 = 0;
+    let mut bits = 0;
+    for c in secret.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => return Err(AppError::ValidationError("TOTP secret must be base32".to_string())),
+        };
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bytes.is_empty() {
+        return Err(AppError::ValidationError("TOTP secret is empty".to_string()));
+    }
+    Ok(bytes)
+}
+
+// RFC 6238 with the defaults every authenticator app uses: HMAC-SHA1, 30 second steps, 6 digits
+pub fn totp(secret: &[u8], timestamp: i64) -> String {
+    let counter = timestamp.div_euclid(TOTP_STEP_SECS) as u64;
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([digest[offset], digest[offset + 1], digest[offset + 2], digest[offset + 3]]) & 0x7fff_ffff;
+    format!("{:0width$}", value % 10u32.pow(TOTP_DIGITS), width = TOTP_DIGITS as usize)
+}
+
+fn verify_code(secret: &[u8], code: &str, timestamp: i64) -> bool {
+    code.len() == TOTP_DIGITS as usize
+        && (-TOTP_SKEW_STEPS..=TOTP_SKEW_STEPS).any(|step| totp(secret, timestamp + step * TOTP_STEP_SECS) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    // The RFC 6238 SHA1 test key, "12345678901234567890"
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn config() -> SSHConnectionConfig {
+        serde_json::from_value(serde_json::json!({
+            "id": "s1",
+            "hostname": "db1.internal",
+            "port": 22,
+            "username": "deploy",
+            "password": null,
+            "privateKey": null,
+            "passphrase": null,
+            "keepAlive": null,
+            "readyTimeout": null,
+        }))
+        .unwrap()
+    }
+
+    fn approval(method: ApprovalMethod) -> ConnectApproval {
+        ConnectApproval {
+            profile_id: "p1".to_string(),
+            profile_name: "Production DB".to_string(),
+            method,
+            totp_secret: (method == ApprovalMethod::Totp).then(|| SECRET.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_totp() {
+        let secret = decode_secret(SECRET).unwrap();
+        assert_eq!(secret, b"12345678901234567890");
+        assert_eq!(decode_secret("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap(), secret);
+        assert!(decode_secret("not base32!").is_err());
+
+        // The last six digits of the RFC's eight digit values
+        assert_eq!(totp(&secret, 59), "287082");
+        assert_eq!(totp(&secret, 1111111109), "081804");
+        assert_eq!(totp(&secret, 2000000000), "279037");
+
+        assert!(verify_code(&secret, "081804", 1111111109 + 30));
+        assert!(!verify_code(&secret, "081804", 1111111109 + 90));
+        assert!(!verify_code(&secret, "81804", 1111111109));
+    }
+
+    #[tokio::test]
+    async fn test_totp_approval() {
+        let approvals = Arc::new(ConnectApprovals::new());
+        let mut requests = approvals.subscribe();
+        let mut attempts = approvals.attempts();
+
+        let waiting = approvals.clone();
+        let connect = tokio::spawn(async move { waiting.require("s1", &config(), &approval(ApprovalMethod::Totp)).await });
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(approvals.pending().len(), 1);
+        assert!(approvals.approve(&request.request_id, "admin").is_err());
+        assert!(approvals.submit_code(&request.request_id, "000000").is_err());
+
+        let code = totp(&decode_secret(SECRET).unwrap(), Utc::now().timestamp());
+        approvals.submit_code(&request.request_id, &code).unwrap();
+        connect.await.unwrap().unwrap();
+        assert!(approvals.pending().is_empty());
+
+        let outcomes: Vec<ApprovalOutcome> = std::iter::from_fn(|| attempts.try_recv().ok()).map(|attempt| attempt.outcome).collect();
+        assert_eq!(outcomes, [ApprovalOutcome::Requested, ApprovalOutcome::InvalidCode, ApprovalOutcome::Approved]);
+    }
+
+    #[tokio::test]
+    async fn test_admin_approval() {
+        let approvals = Arc::new(ConnectApprovals::new());
+        let mut requests = approvals.subscribe();
+        let mut attempts = approvals.attempts();
+
+        let waiting = approvals.clone();
+        let connect = tokio::spawn(async move { waiting.require("s1", &config(), &approval(ApprovalMethod::Admin)).await });
+        let request = requests.recv().await.unwrap();
+        assert!(approvals.submit_code(&request.request_id, "123456").is_err());
+        approvals.deny(&request.request_id, "alice").unwrap();
+        assert!(matches!(connect.await.unwrap(), Err(AppError::PermissionDenied(_))));
+
+        let denied = std::iter::from_fn(|| attempts.try_recv().ok()).last().unwrap();
+        assert_eq!(denied.outcome, ApprovalOutcome::Denied);
+        assert_eq!(denied.approver.as_deref(), Some("alice"));
+
+        // Disconnecting while waiting cancels the request
+        let waiting = approvals.clone();
+        let connect = tokio::spawn(async move { waiting.require("s1", &config(), &approval(ApprovalMethod::Admin)).await });
+        requests.recv().await.unwrap();
+        approvals.cancel_session("s1");
+        assert!(connect.await.unwrap().is_err());
+        assert_eq!(attempts.recv().await.unwrap().outcome, ApprovalOutcome::Requested);
+        assert_eq!(attempts.recv().await.unwrap().outcome, ApprovalOutcome::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_too_many_wrong_codes() {
+        let approvals = Arc::new(ConnectApprovals::new());
+        let mut requests = approvals.subscribe();
+
+        let waiting = approvals.clone();
+        let connect = tokio::spawn(async move { waiting.require("s1", &config(), &approval(ApprovalMethod::Totp)).await });
+        let request = requests.recv().await.unwrap();
+        for _ in 0..MAX_CODE_ATTEMPTS {
+            assert!(approvals.submit_code(&request.request_id, "abcdef").is_err());
+        }
+        assert!(connect.await.unwrap().is_err());
+        assert!(matches!(approvals.submit_code(&request.request_id, "123456"), Err(AppError::NotFound(_))));
+    }
+}
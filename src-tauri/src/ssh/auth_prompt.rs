@@ -0,0 +1,228 @@
+use crate::log_security;
+use crate::types::{AppError, AppResult, AuthPromptEvent, AuthPromptField};
+use chrono::Utc;
+use dashmap::DashMap;
+use ssh2::{KeyboardInteractivePrompt, Prompt, Session};
+use std::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+// How long a prompt waits for the user before authentication gives up
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(120);
+
+struct PendingPrompt {
+    session_id: String,
+    fields: usize,
+    sender: mpsc::Sender<Vec<String>>,
+}
+
+// Relays keyboard-interactive prompts (PAM, OTP) to whoever is connecting and hands the
+// answers back to the blocked authentication
+pub struct AuthPrompts {
+    pending: DashMap<String, PendingPrompt>,
+    events: broadcast::Sender<AuthPromptEvent>,
+}
+
+impl AuthPrompts {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(16);
+        Self {
+            pending: DashMap::new(),
+            events,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AuthPromptEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn respond(&self, prompt_id: &str, responses: Vec<String>) -> AppResult<()> {
+        let (_, pending) = self.pending.remove(prompt_id)
+            .ok_or_else(|| AppError::NotFound(format!("Auth prompt {}", prompt_id)))?;
+        if responses.len() != pending.fields {
+            let expected = pending.fields;
+            self.pending.insert(prompt_id.to_string(), pending);
+            return Err(AppError::ValidationError(format!(
+                "Expected {} responses, got {}", expected, responses.len()
+            )));
+        }
+        pending.sender.send(responses)
+            .map_err(|_| AppError::OperationFailed(format!("Auth prompt {} is no longer waiting", prompt_id)))
+    }
+
+    // Dropping the sender wakes the authentication up, which then fails
+    pub fn cancel(&self, prompt_id: &str) -> AppResult<()> {
+        self.pending.remove(prompt_id)
+            .map(|_| ())
+            .ok_or_else(|| AppError::NotFound(format!("Auth prompt {}", prompt_id)))
+    }
+
+    pub fn cancel_session(&self, session_id: &str) {
+        self.pending.retain(|_, pending| pending.session_id != session_id);
+    }
+
+    // Keyboard-interactive authentication, blocking until the server stops asking. A configured
+    // password answers the first password prompt, for servers that only do PAM.
+    pub fn authenticate(
+        &self,
+        session_id: &str,
+        hostname: &str,
+        session: &Session,
+        username: &str,
+        password: Option<&str>,
+    ) -> AppResult<()> {
+        let mut prompter = Prompter {
+            prompts: self,
+            session_id,
+            hostname,
+            password,
+            error: None,
+        };
+        let result = block_in_place(|| session.userauth_keyboard_interactive(username, &mut prompter));
+        if let Err(e) = result {
+            let reason = prompter.error.unwrap_or_else(|| e.to_string());
+            return Err(AppError::SSHAuthenticationFailed(format!("Keyboard-interactive authentication failed: {}", reason)));
+        }
+
+        log_security!("keyboard_interactive_auth_success", "info", {
+            let mut details = std::collections::HashMap::new();
+            details.insert("username".to_string(), username.to_string());
+            details.insert("host".to_string(), hostname.to_string());
+            details
+        });
+        Ok(())
+    }
+
+    fn ask(&self, event: AuthPromptEvent) -> Result<Vec<String>, String> {
+        let (sender, receiver) = mpsc::channel();
+        let prompt_id = event.prompt_id.clone();
+        self.pending.insert(prompt_id.clone(), PendingPrompt {
+            session_id: event.session_id.clone(),
+            fields: event.prompts.len(),
+            sender,
+        });
+
+        if self.events.send(event).is_err() {
+            self.pending.remove(&prompt_id);
+            return Err("nobody is listening for prompts".to_string());
+        }
+        let result = receiver.recv_timeout(RESPONSE_TIMEOUT);
+        self.pending.remove(&prompt_id);
+        result.map_err(|e| match e {
+            mpsc::RecvTimeoutError::Timeout => "timed out waiting for a response".to_string(),
+            mpsc::RecvTimeoutError::Disconnected => "cancelled".to_string(),
+        })
+    }
+}
+
+impl Default for AuthPrompts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Waiting for the user must not stall the runtime's other tasks, which is where the
+// answer comes from. Only multi-threaded runtimes can hand the worker's tasks off.
+fn block_in_place<R>(f: impl FnOnce() -> R) -> R {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+struct Prompter<'a> {
+    prompts: &'a AuthPrompts,
+    session_id: &'a str,
+    hostname: &'a str,
+    password: Option<&'a str>,
+    // Why the last round went unanswered
+    error: Option<String>,
+}
+
+impl KeyboardInteractivePrompt for Prompter<'_> {
+    fn prompt<'b>(&mut self, username: &str, instructions: &str, prompts: &[Prompt<'b>]) -> Vec<String> {
+        // Some servers send a round with only instructions
+        if prompts.is_empty() {
+            return Vec::new();
+        }
+        if let [prompt] = prompts {
+            if is_password_prompt(prompt) {
+                if let Some(password) = self.password.take() {
+                    return vec![password.to_string()];
+                }
+            }
+        }
+
+        let event = AuthPromptEvent {
+            prompt_id: Uuid::new_v4().to_string(),
+            session_id: self.session_id.to_string(),
+            hostname: self.hostname.to_string(),
+            username: username.to_string(),
+            instructions: instructions.to_string(),
+            prompts: prompts.iter()
+                .map(|prompt| AuthPromptField { text: prompt.text.to_string(), echo: prompt.echo })
+                .collect(),
+            expires_at: Utc::now() + chrono::Duration::from_std(RESPONSE_TIMEOUT).unwrap_or_default(),
+        };
+        match self.prompts.ask(event) {
+            Ok(responses) => responses,
+            Err(e) => {
+                self.error = Some(e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+fn is_password_prompt(prompt: &Prompt<'_>) -> bool {
+    !prompt.echo && prompt.text.to_lowercase().contains("password")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(prompt_id: &str, text: &str, echo: bool) -> AuthPromptEvent {
+        AuthPromptEvent {
+            prompt_id: prompt_id.to_string(),
+            session_id: "s1".to_string(),
+            hostname: "bastion".to_string(),
+            username: "deploy".to_string(),
+            instructions: String::new(),
+            prompts: vec![AuthPromptField { text: text.to_string(), echo }],
+            expires_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_round_trip() {
+        let prompts = std::sync::Arc::new(AuthPrompts::new());
+        let mut events = prompts.subscribe();
+
+        let asking = prompts.clone();
+        let answer = std::thread::spawn(move || asking.ask(event("p1", "Verification code: ", true)));
+
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.prompts[0].text, "Verification code: ");
+        assert!(prompts.respond("p1", Vec::new()).is_err());
+        prompts.respond("p1", vec!["123456".to_string()]).unwrap();
+        assert_eq!(answer.join().unwrap().unwrap(), ["123456"]);
+        assert!(prompts.respond("p1", vec!["again".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_prompt() {
+        let prompts = std::sync::Arc::new(AuthPrompts::new());
+        let mut events = prompts.subscribe();
+
+        let asking = prompts.clone();
+        let answer = std::thread::spawn(move || asking.ask(event("p2", "Password: ", false)));
+
+        events.recv().await.unwrap();
+        prompts.cancel_session("s1");
+        assert_eq!(answer.join().unwrap().unwrap_err(), "cancelled");
+    }
+}
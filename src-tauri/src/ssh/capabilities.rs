@@ -0,0 +1,223 @@
+use crate::types::{AppError, AppResult, TerminalCapabilities};
+
+// What shells get when the client doesn't say otherwise
+pub const DEFAULT_TERM: &str = "xterm-256color";
+// An escape sequence cut off at the end of a read is held back until the next one, up to this
+const MAX_PENDING_BYTES: usize = 64;
+
+pub fn validate(capabilities: Option<&TerminalCapabilities>) -> AppResult<()> {
+    match capabilities.and_then(|capabilities| capabilities.term.as_deref()) {
+        Some(term) => validate_term(term),
+        None => Ok(()),
+    }
+}
+
+pub fn validate_term(term: &str) -> AppResult<()> {
+    let valid = !term.is_empty()
+        && term.len() <= 64
+        && term.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.' | '_'));
+    if !valid {
+        return Err(AppError::ValidationError(format!("Invalid TERM: {}", term)));
+    }
+    Ok(())
+}
+
+// The TERM the shell's pty is requested with: the client's, else the profile's default
+pub fn term<'a>(capabilities: Option<&'a TerminalCapabilities>, default: Option<&'a str>) -> &'a str {
+    capabilities.and_then(|capabilities| capabilities.term.as_deref())
+        .or(default)
+        .unwrap_or(DEFAULT_TERM)
+}
+
+// Environment for the shell, set where the server's AcceptEnv allows
+pub fn environment(capabilities: Option<&TerminalCapabilities>) -> Vec<(&'static str, &'static str)> {
+    match capabilities {
+        Some(capabilities) if capabilities.truecolor => vec![("COLORTERM", "truecolor")],
+        _ => Vec::new(),
+    }
+}
+
+// Rewrites shell output the client declared it can't handle: 24-bit colours become the
+// nearest of the 256, and kitty keyboard protocol requests are dropped
+pub struct OutputFilter {
+    truecolor: bool,
+    kitty_keyboard: bool,
+    pending: String,
+}
+
+impl OutputFilter {
+    // None when the client handles everything, or didn't say what it handles
+    pub fn new(capabilities: Option<&TerminalCapabilities>) -> Option<Self> {
+        let capabilities = capabilities?;
+        if capabilities.truecolor && capabilities.kitty_keyboard {
+            return None;
+        }
+        Some(Self {
+            truecolor: capabilities.truecolor,
+            kitty_keyboard: capabilities.kitty_keyboard,
+            pending: String::new(),
+        })
+    }
+
+    pub fn apply(&mut self, output: &str) -> String {
+        let mut input = std::mem::take(&mut self.pending);
+        input.push_str(output);
+
+        let mut filtered = String::with_capacity(input.len());
+        let mut rest = input.as_str();
+        while let Some(start) = rest.find("\x1b[") {
+            filtered.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match csi_len(rest) {
+                Some(len) => {
+                    self.push_csi(&rest[..len], &mut filtered);
+                    rest = &rest[len..];
+                }
+                None if rest.len() < MAX_PENDING_BYTES => {
+                    self.pending = rest.to_string();
+                    return filtered;
+                }
+                // Too long to be anything we rewrite
+                None => {
+                    filtered.push_str("\x1b[");
+                    rest = &rest[2..];
+                }
+            }
+        }
+
+        // A lone ESC may be the start of the next sequence
+        match rest.strip_suffix('\x1b') {
+            Some(head) => {
+                filtered.push_str(head);
+                self.pending.push('\x1b');
+            }
+            None => filtered.push_str(rest),
+        }
+        filtered
+    }
+
+    fn push_csi(&self, sequence: &str, filtered: &mut String) {
+        let params = &sequence[2..sequence.len() - 1];
+        match sequence.as_bytes()[sequence.len() - 1] {
+            b'm' if !self.truecolor && (params.contains(";2;") || params.contains(":2:")) => {
+                filtered.push_str("\x1b[");
+                filtered.push_str(&downgrade_sgr(params));
+                filtered.push('m');
+            }
+            // Push, pop and query of the keyboard flags; a bare CSI u is restore cursor
+            b'u' if !self.kitty_keyboard && params.starts_with(['>', '<', '?', '=']) => {}
+            _ => filtered.push_str(sequence),
+        }
+    }
+}
+
+// Length of the CSI sequence at the start of `text`, None if it's cut off
+fn csi_len(text: &str) -> Option<usize> {
+    text.bytes()
+        .enumerate()
+        .skip(2)
+        .find(|(_, byte)| (0x40..=0x7e).contains(byte))
+        .map(|(i, _)| i + 1)
+}
+
+// SGR parameters with 38;2;r;g;b / 48;2;r;g;b (and the colon forms) as 38;5;n / 48;5;n
+fn downgrade_sgr(params: &str) -> String {
+    let parts: Vec<&str> = params.split(';').collect();
+    let mut downgraded = Vec::with_capacity(parts.len());
+    let mut i = 0;
+    while i < parts.len() {
+        let part = parts[i];
+        if (part == "38" || part == "48") && parts.get(i + 1) == Some(&"2") && i + 4 < parts.len() {
+            if let Some(index) = rgb(&parts[i + 2..i + 5]).map(to_256) {
+                downgraded.push(format!("{};5;{}", part, index));
+                i += 5;
+                continue;
+            }
+        }
+        if part.starts_with("38:2:") || part.starts_with("48:2:") {
+            // 38:2:<colour space>:r:g:b, with the colour space often left out
+            let fields: Vec<&str> = part.split(':').collect();
+            if fields.len() >= 5 {
+                if let Some(index) = rgb(&fields[fields.len() - 3..]).map(to_256) {
+                    downgraded.push(format!("{}:5:{}", fields[0], index));
+                    i += 1;
+                    continue;
+                }
+            }
+        }
+        downgraded.push(part.to_string());
+        i += 1;
+    }
+    downgraded.join(";")
+}
+
+fn rgb(fields: &[&str]) -> Option<(u8, u8, u8)> {
+    Some((fields[0].parse().ok()?, fields[1].parse().ok()?, fields[2].parse().ok()?))
+}
+
+// The nearest colour in the xterm 6x6x6 cube or its grey ramp
+fn to_256((r, g, b): (u8, u8, u8)) -> u8 {
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            grey => 232 + ((grey as u16 - 8) * 24 / 247) as u8,
+        };
+    }
+    let level = |value: u8| (value as u16 * 5 + 127) / 255;
+    (16 + 36 * level(r) + 6 * level(g) + level(b)) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(truecolor: bool, kitty_keyboard: bool) -> TerminalCapabilities {
+        TerminalCapabilities { truecolor, kitty_keyboard, ..Default::default() }
+    }
+
+    #[test]
+    fn test_term_and_validation() {
+        assert_eq!(term(None, None), DEFAULT_TERM);
+        let custom = TerminalCapabilities { term: Some("xterm-kitty".to_string()), ..Default::default() };
+        assert_eq!(term(Some(&custom), None), "xterm-kitty");
+        // The client's TERM wins over the profile's
+        assert_eq!(term(Some(&custom), Some("screen-256color")), "xterm-kitty");
+        assert_eq!(term(Some(&TerminalCapabilities::default()), Some("screen-256color")), "screen-256color");
+        assert!(validate(Some(&custom)).is_ok());
+
+        let invalid = TerminalCapabilities { term: Some("xterm; rm -rf".to_string()), ..Default::default() };
+        assert!(validate(Some(&invalid)).is_err());
+        assert_eq!(environment(Some(&capabilities(true, false))), [("COLORTERM", "truecolor")]);
+    }
+
+    #[test]
+    fn test_filter_only_when_needed() {
+        assert!(OutputFilter::new(None).is_none());
+        assert!(OutputFilter::new(Some(&capabilities(true, true))).is_none());
+        assert!(OutputFilter::new(Some(&capabilities(false, true))).is_some());
+    }
+
+    #[test]
+    fn test_downgrades_truecolor() {
+        let mut filter = OutputFilter::new(Some(&capabilities(false, true))).unwrap();
+        assert_eq!(filter.apply("\x1b[1;38;2;255;0;0mred\x1b[0m"), "\x1b[1;38;5;196mred\x1b[0m");
+        assert_eq!(filter.apply("\x1b[48:2::0:0:255m"), "\x1b[48:5:21m");
+        assert_eq!(filter.apply("\x1b[38;2;128;128;128m"), "\x1b[38;5;243m");
+
+        // Split across reads
+        assert_eq!(filter.apply("a\x1b[38;2;0;25"), "a");
+        assert_eq!(filter.apply("5;0mb"), "\x1b[38;5;46mb");
+        assert_eq!(filter.apply("c\x1b"), "c");
+        assert_eq!(filter.apply("[31md"), "\x1b[31md");
+    }
+
+    #[test]
+    fn test_drops_kitty_keyboard_requests() {
+        let mut filter = OutputFilter::new(Some(&capabilities(true, false))).unwrap();
+        assert_eq!(filter.apply("\x1b[>1u\x1b[?u$ \x1b[<u"), "$ ");
+        // Restore cursor stays
+        assert_eq!(filter.apply("\x1b[u"), "\x1b[u");
+        assert_eq!(filter.apply("\x1b[38;2;1;2;3m"), "\x1b[38;2;1;2;3m");
+    }
+}
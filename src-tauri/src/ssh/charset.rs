@@ -0,0 +1,183 @@
+use crate::types::{AppError, AppResult};
+use chardetng::EncodingDetector;
+use encoding_rs::{Decoder, EncoderResult, Encoding, UTF_8};
+
+// Setting a session's charset to this (or leaving it unset) follows the remote locale
+pub const AUTO: &str = "auto";
+// Output sniffed before an auto session with nothing but valid UTF-8 settles on it
+const MAX_SNIFF_BYTES: usize = 1024 * 1024;
+
+// A charset by name, e.g. "gbk", "shift_jis" or "latin1"
+pub fn lookup(label: &str) -> AppResult<&'static Encoding> {
+    let label = label.trim().to_ascii_lowercase();
+    // Spellings glibc locales use that the WHATWG label list doesn't
+    let label = match label.as_str() {
+        "eucjp" => "euc-jp",
+        "euckr" => "euc-kr",
+        "sjis" => "shift_jis",
+        "big5hkscs" => "big5-hkscs",
+        other => other,
+    };
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| AppError::ValidationError(format!("Unknown charset: {}", label)))
+}
+
+// The charset a locale names, e.g. GBK for zh_CN.GBK; None for C/POSIX or unknown ones
+pub fn from_locale(locale: &str) -> Option<&'static Encoding> {
+    let (_, charset) = locale.split_once('.')?;
+    let charset = charset.split('@').next().unwrap_or(charset);
+    lookup(charset).ok()
+}
+
+pub fn validate(charset: Option<&str>) -> AppResult<()> {
+    match configured(charset) {
+        Some(label) => lookup(label).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+fn configured(charset: Option<&str>) -> Option<&str> {
+    charset.map(str::trim).filter(|charset| !charset.is_empty() && !charset.eq_ignore_ascii_case(AUTO))
+}
+
+struct Sniffer {
+    seen: usize,
+    // Start of a UTF-8 sequence cut off at the end of the last read
+    pending: Vec<u8>,
+}
+
+// Decodes a shell's output and encodes its input in the remote charset
+pub struct TerminalCodec {
+    encoding: &'static Encoding,
+    decoder: Decoder,
+    // Set while an auto session still looks like UTF-8
+    sniffer: Option<Sniffer>,
+}
+
+impl TerminalCodec {
+    // The configured charset, else the one the remote locale names, else UTF-8 until
+    // the output proves otherwise
+    pub fn new(charset: Option<&str>, locale: Option<&str>) -> AppResult<Self> {
+        if let Some(label) = configured(charset) {
+            return Ok(Self::fixed(lookup(label)?));
+        }
+        Ok(match locale.and_then(from_locale) {
+            Some(encoding) => Self::fixed(encoding),
+            None => Self {
+                sniffer: Some(Sniffer { seen: 0, pending: Vec::new() }),
+                ..Self::fixed(UTF_8)
+            },
+        })
+    }
+
+    fn fixed(encoding: &'static Encoding) -> Self {
+        Self { encoding, decoder: encoding.new_decoder_without_bom_handling(), sniffer: None }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    // Multibyte characters split across reads come out whole on the next call
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        let Some(sniffer) = self.sniffer.as_mut() else {
+            return self.decode_stream(bytes);
+        };
+
+        sniffer.seen += bytes.len();
+        let mut data = std::mem::take(&mut sniffer.pending);
+        data.extend_from_slice(bytes);
+
+        match std::str::from_utf8(&data) {
+            Ok(text) => {
+                let text = text.to_string();
+                self.settle_if_sniffed_enough();
+                text
+            }
+            Err(e) if e.error_len().is_none() => {
+                let valid = e.valid_up_to();
+                let text = String::from_utf8_lossy(&data[..valid]).into_owned();
+                sniffer.pending = data[valid..].to_vec();
+                self.settle_if_sniffed_enough();
+                text
+            }
+            Err(_) => {
+                // Guessed from this read alone; earlier UTF-8-looking output only adds noise
+                let mut detector = EncodingDetector::new();
+                detector.feed(&data, true);
+                let encoding = detector.guess(None, false);
+                log::info!("Terminal output is not UTF-8, switching to {}", encoding.name());
+                *self = Self::fixed(encoding);
+                self.decode_stream(&data)
+            }
+        }
+    }
+
+    fn settle_if_sniffed_enough(&mut self) {
+        if self.sniffer.as_ref().is_some_and(|sniffer| sniffer.seen >= MAX_SNIFF_BYTES) {
+            let pending = self.sniffer.take().map(|sniffer| sniffer.pending).unwrap_or_default();
+            self.decode_stream(&pending);
+        }
+    }
+
+    fn decode_stream(&mut self, bytes: &[u8]) -> String {
+        let capacity = self.decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3 + 16);
+        let mut text = String::with_capacity(capacity);
+        let _ = self.decoder.decode_to_string(bytes, &mut text, false);
+        text
+    }
+
+    // Characters the remote charset can't represent are sent as '?'
+    pub fn encode(&self, input: &str) -> Vec<u8> {
+        if self.encoding == UTF_8 {
+            return input.as_bytes().to_vec();
+        }
+        let mut encoder = self.encoding.new_encoder();
+        let mut output = Vec::with_capacity(input.len() * 2 + 16);
+        let mut remaining = input;
+        loop {
+            let (result, read) = encoder.encode_from_utf8_to_vec_without_replacement(remaining, &mut output, true);
+            remaining = &remaining[read..];
+            match result {
+                EncoderResult::InputEmpty => return output,
+                EncoderResult::OutputFull => output.reserve(remaining.len() * 2 + 16),
+                EncoderResult::Unmappable(_) => output.push(b'?'),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configured_and_locale_charsets() {
+        let mut gbk = TerminalCodec::new(Some("GBK"), Some("en_US.UTF-8")).unwrap();
+        let (bytes, _, _) = encoding_rs::GBK.encode("磁盘已满");
+        // Split in the middle of a character
+        assert_eq!(gbk.decode(&bytes[..3]), "磁");
+        assert_eq!(gbk.decode(&bytes[3..]), "盘已满");
+        assert_eq!(gbk.encode("ls 目录 ✓"), b"ls \xc4\xbf\xc2\xbc ?");
+
+        assert_eq!(TerminalCodec::new(None, Some("ja_JP.eucJP")).unwrap().name(), "EUC-JP");
+        assert_eq!(TerminalCodec::new(Some("auto"), Some("ru_RU.KOI8-R@cyrillic")).unwrap().name(), "KOI8-R");
+        assert!(TerminalCodec::new(Some("klingon"), None).is_err());
+    }
+
+    #[test]
+    fn test_auto_switches_on_invalid_utf8() {
+        let mut codec = TerminalCodec::new(None, Some("C")).unwrap();
+        assert_eq!(codec.decode("héllo ".as_bytes()), "héllo ");
+        // A UTF-8 character split across reads stays UTF-8
+        let split = "€".as_bytes();
+        assert_eq!(codec.decode(&split[..1]), "");
+        assert_eq!(codec.decode(&split[1..]), "€");
+        assert_eq!(codec.name(), "UTF-8");
+
+        let text = "系统将于今晚十点重启，请及时保存您的工作并退出登录。";
+        let (bytes, _, _) = encoding_rs::GBK.encode(text);
+        assert_eq!(codec.decode(&bytes), text);
+        assert_eq!(codec.name(), "GBK");
+    }
+}
@@ -0,0 +1,196 @@
+use crate::types::{AutocompleteSuggestion, SuggestionType};
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+
+// Long enough to cover the keystrokes of one path, short enough to notice new files
+const CACHE_TTL: Duration = Duration::from_secs(5);
+// Past this, suggestions are left out rather than holding up the prompt, e.g. behind a transfer
+pub const LIST_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_SUGGESTIONS: usize = 100;
+// Shells that integrate with the terminal report their directory as OSC 7 file://host/path
+const OSC7_PREFIX: &str = "\x1b]7;file://";
+
+#[derive(Debug, Clone)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub is_directory: bool,
+}
+
+// Remote directories listed for completion, per session
+#[derive(Default)]
+pub struct DirectoryCache {
+    listings: DashMap<String, (Instant, Vec<DirectoryEntry>)>,
+    // Where SFTP starts, which is where the login shell starts too
+    home: parking_lot::Mutex<Option<String>>,
+}
+
+impl DirectoryCache {
+    pub fn get(&self, directory: &str) -> Option<Vec<DirectoryEntry>> {
+        let listing = self.listings.get(directory)?;
+        let (listed_at, entries) = listing.value();
+        (listed_at.elapsed() < CACHE_TTL).then(|| entries.clone())
+    }
+
+    pub fn insert(&self, directory: &str, entries: Vec<DirectoryEntry>) {
+        self.listings.retain(|_, (listed_at, _)| listed_at.elapsed() < CACHE_TTL);
+        self.listings.insert(directory.to_string(), (Instant::now(), entries));
+    }
+
+    pub fn home(&self) -> Option<String> {
+        self.home.lock().clone()
+    }
+
+    pub fn set_home(&self, home: &str) {
+        *self.home.lock() = Some(home.to_string());
+    }
+}
+
+// The typed directory part, kept as typed, and the start of the name being completed:
+// "/var/lo" is ("/var/", "lo"), "lo" is ("", "lo")
+pub fn split_path(prefix: &str) -> (&str, &str) {
+    match prefix.rfind('/') {
+        Some(index) => prefix.split_at(index + 1),
+        None => ("", prefix),
+    }
+}
+
+// The remote directory to list for a typed directory part
+pub fn resolve(directory: &str, cwd: &str, home: &str) -> String {
+    let joined = if directory.starts_with('/') {
+        directory.to_string()
+    } else if directory == "~/" || directory.starts_with("~/") {
+        format!("{}/{}", home.trim_end_matches('/'), &directory[2..])
+    } else {
+        format!("{}/{}", cwd.trim_end_matches('/'), directory)
+    };
+    match joined.trim_end_matches('/') {
+        "" => "/".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+// Entries starting with `name`, completed onto what was typed; hidden ones only once a
+// dot is typed, like shells do
+pub fn suggestions(directory: &str, name: &str, entries: &[DirectoryEntry]) -> Vec<AutocompleteSuggestion> {
+    let mut matches: Vec<&DirectoryEntry> = entries.iter()
+        .filter(|entry| entry.name != "." && entry.name != "..")
+        .filter(|entry| entry.name.starts_with(name))
+        .filter(|entry| name.starts_with('.') || !entry.name.starts_with('.'))
+        .collect();
+    matches.sort_by(|a, b| b.is_directory.cmp(&a.is_directory).then_with(|| a.name.cmp(&b.name)));
+
+    matches.into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|entry| {
+            let (suffix, suggestion_type) = if entry.is_directory {
+                ("/", SuggestionType::Directory)
+            } else {
+                ("", SuggestionType::File)
+            };
+            AutocompleteSuggestion {
+                text: format!("{}{}{}", directory, entry.name, suffix),
+                description: None,
+                suggestion_type,
+            }
+        })
+        .collect()
+}
+
+// The last directory the shell reported in this output, if it reported one
+pub fn reported_directory(output: &str) -> Option<String> {
+    let start = output.rfind(OSC7_PREFIX)? + OSC7_PREFIX.len();
+    let rest = &output[start..];
+    let end = rest.find(['\x07', '\x1b'])?;
+    // The host comes first and may be empty, as in file:///home/user
+    let path = &rest[rest[..end].find('/')?..end];
+    Some(percent_decode(path))
+}
+
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes.get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, is_directory: bool) -> DirectoryEntry {
+        DirectoryEntry {
+            name: name.to_string(),
+            is_directory,
+        }
+    }
+
+    #[test]
+    fn test_resolve_typed_path() {
+        assert_eq!(split_path("/var/lo"), ("/var/", "lo"));
+        assert_eq!(split_path("lo"), ("", "lo"));
+        assert_eq!(split_path("~/"), ("~/", ""));
+
+        assert_eq!(resolve("/var/", "/srv", "/home/deploy"), "/var");
+        assert_eq!(resolve("/", "/srv", "/home/deploy"), "/");
+        assert_eq!(resolve("", "/srv", "/home/deploy"), "/srv");
+        assert_eq!(resolve("app/", "/srv", "/home/deploy"), "/srv/app");
+        assert_eq!(resolve("../", "/", "/home/deploy"), "/..");
+        assert_eq!(resolve("~/", "/srv", "/home/deploy"), "/home/deploy");
+        assert_eq!(resolve("~/logs/", "/srv", "/home/deploy/"), "/home/deploy/logs");
+    }
+
+    #[test]
+    fn test_path_suggestions() {
+        let entries = [entry("log", true), entry("local", true), entry("lock", false), entry(".lost", true), entry("lib", true)];
+
+        let found = suggestions("/var/", "lo", &entries);
+        let texts: Vec<&str> = found.iter().map(|suggestion| suggestion.text.as_str()).collect();
+        assert_eq!(texts, ["/var/local/", "/var/log/", "/var/lock"]);
+        assert_eq!(found[0].suggestion_type, SuggestionType::Directory);
+        assert_eq!(found[2].suggestion_type, SuggestionType::File);
+
+        let hidden = suggestions("", ".", &entries);
+        assert_eq!(hidden.len(), 1);
+        assert_eq!(hidden[0].text, ".lost/");
+    }
+
+    #[test]
+    fn test_reported_directory() {
+        assert_eq!(reported_directory("\x1b]7;file://web1/var/log\x07$ ").as_deref(), Some("/var/log"));
+        assert_eq!(reported_directory("\x1b]7;file:///srv/my%20app\x1b\\").as_deref(), Some("/srv/my app"));
+        assert_eq!(
+            reported_directory("\x1b]7;file://web1/tmp\x07ls\r\n\x1b]7;file://web1/etc\x07").as_deref(),
+            Some("/etc")
+        );
+        // Cut off before its terminator
+        assert_eq!(reported_directory("\x1b]7;file://web1/va"), None);
+        assert_eq!(reported_directory("plain output"), None);
+    }
+
+    #[test]
+    fn test_directory_cache() {
+        let cache = DirectoryCache::default();
+        assert!(cache.get("/var").is_none());
+        cache.insert("/var", vec![entry("log", true)]);
+        assert_eq!(cache.get("/var").map(|entries| entries.len()), Some(1));
+
+        assert!(cache.home().is_none());
+        cache.set_home("/home/deploy");
+        assert_eq!(cache.home().as_deref(), Some("/home/deploy"));
+    }
+}
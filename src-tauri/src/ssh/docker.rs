@@ -0,0 +1,143 @@
+use crate::types::{AppError, AppResult, CommandExecResult, ContainerAction, DockerContainer, DockerImage, SudoOptions};
+use serde::Deserialize;
+
+// One JSON object per line, field names as docker prints them
+pub const LIST_CONTAINERS: &str = "docker ps -a --no-trunc --format '{{json .}}'";
+pub const LIST_IMAGES: &str = "docker images --format '{{json .}}'";
+
+const DEFAULT_LOG_LINES: u32 = 100;
+const MAX_LOG_LINES: u32 = 5000;
+const EXIT_NOT_FOUND: i32 = 127;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PsLine {
+    #[serde(rename = "ID")]
+    id: String,
+    names: String,
+    image: String,
+    #[serde(default)]
+    command: String,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    ports: String,
+    #[serde(default)]
+    created_at: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ImageLine {
+    #[serde(rename = "ID")]
+    id: String,
+    repository: String,
+    tag: String,
+    #[serde(default)]
+    size: String,
+    #[serde(default)]
+    created_at: String,
+}
+
+// Container IDs and names go into the command unquoted
+pub fn validate_container(container: &str) -> AppResult<()> {
+    let valid = !container.is_empty()
+        && container.len() <= 128
+        && !container.starts_with('-')
+        && container.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if !valid {
+        return Err(AppError::ValidationError(format!("Invalid container: {}", container)));
+    }
+    Ok(())
+}
+
+pub fn action_command(container: &str, action: ContainerAction, sudo: &SudoOptions) -> AppResult<(String, Option<String>)> {
+    validate_container(container)?;
+    Ok(sudo.wrap(&format!("docker {} {}", action.verb(), container)))
+}
+
+// Container stderr goes to the same stream, it's usually where the logs are
+pub fn logs_command(container: &str, lines: Option<u32>, sudo: &SudoOptions) -> AppResult<(String, Option<String>)> {
+    validate_container(container)?;
+    let lines = lines.unwrap_or(DEFAULT_LOG_LINES).min(MAX_LOG_LINES);
+    Ok(sudo.wrap(&format!("docker logs -f --tail {} {} 2>&1", lines, container)))
+}
+
+pub fn command_error(result: &CommandExecResult) -> AppError {
+    let stderr = result.stderr.trim();
+    let lower = stderr.to_lowercase();
+    if result.exit_code == EXIT_NOT_FOUND {
+        AppError::OperationFailed("docker is not installed on this host".to_string())
+    } else if lower.contains("permission denied") || lower.contains("password is required") || lower.contains("incorrect password") {
+        AppError::PermissionDenied(stderr.to_string())
+    } else if lower.contains("no such container") || lower.contains("no such object") {
+        AppError::NotFound(stderr.to_string())
+    } else {
+        AppError::OperationFailed(format!("docker exited with {}: {}", result.exit_code, stderr))
+    }
+}
+
+pub fn parse_containers(output: &str) -> Vec<DockerContainer> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<PsLine>(line).ok())
+        .map(|line| DockerContainer {
+            id: line.id,
+            name: line.names,
+            image: line.image,
+            // --no-trunc keeps the quotes docker puts around the command
+            command: line.command.trim_matches('"').to_string(),
+            state: line.state,
+            status: line.status,
+            ports: line.ports,
+            created_at: line.created_at,
+        })
+        .collect()
+}
+
+pub fn parse_images(output: &str) -> Vec<DockerImage> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<ImageLine>(line).ok())
+        .map(|line| DockerImage {
+            id: line.id,
+            repository: line.repository,
+            tag: line.tag,
+            size: line.size,
+            created_at: line.created_at,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_containers_and_images() {
+        let output = r#"{"Command":"\"nginx -g 'daemon off;'\"","CreatedAt":"2024-05-06 10:00:00 +0000 UTC","ID":"3f4e","Image":"nginx:1.25","Labels":"","Names":"web","Ports":"0.0.0.0:80->80/tcp","State":"running","Status":"Up 3 hours"}
+{"ID":"9a1b","Image":"redis","Names":"cache","State":"exited","Status":"Exited (0) 2 days ago"}
+not json"#;
+        let containers = parse_containers(output);
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[0].command, "nginx -g 'daemon off;'");
+        assert_eq!((containers[1].name.as_str(), containers[1].state.as_str()), ("cache", "exited"));
+
+        let images = parse_images(r#"{"ID":"sha256:ab","Repository":"nginx","Tag":"1.25","Size":"187MB","CreatedAt":"2024-04-01"}"#);
+        assert_eq!((images[0].repository.as_str(), images[0].tag.as_str()), ("nginx", "1.25"));
+    }
+
+    #[test]
+    fn test_commands_reject_injection() {
+        assert!(validate_container("web; rm -rf /").is_err());
+        assert!(validate_container("--help").is_err());
+
+        let (command, input) = action_command("web", ContainerAction::Restart, &SudoOptions::default()).unwrap();
+        assert_eq!((command.as_str(), input), ("docker restart web", None));
+
+        let sudo = SudoOptions { enabled: true, password: None };
+        assert_eq!(logs_command("web", Some(20), &sudo).unwrap().0, "sudo -n docker logs -f --tail 20 web 2>&1");
+    }
+}
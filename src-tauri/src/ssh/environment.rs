@@ -0,0 +1,135 @@
+use crate::types::{CommandExecResult, OsFamily, RemoteEnvironment, ShellKind};
+use chrono::Utc;
+use std::collections::HashMap;
+
+const SECTION_PREFIX: &str = "==";
+
+// Run through sh so the probe parses the same whatever the login shell is (fish, tcsh, ...).
+// The script itself must stay free of single quotes.
+pub const DETECT_COMMAND: &str = "sh -c '\
+echo ==shell; echo \"$SHELL\"; \
+echo ==uname; uname -s; \
+echo ==osrelease; cat /etc/os-release 2>/dev/null; \
+echo ==macos; sw_vers -productVersion 2>/dev/null; \
+echo ==locale; echo \"${LC_ALL:-${LC_CTYPE:-$LANG}}\"; \
+true'";
+
+pub fn parse(result: &CommandExecResult) -> RemoteEnvironment {
+    let mut environment = RemoteEnvironment {
+        detected_at: Some(Utc::now()),
+        ..RemoteEnvironment::default()
+    };
+
+    // Windows OpenSSH runs cmd.exe or PowerShell, which have no sh to run the probe
+    if result.stdout.trim().is_empty() {
+        let stderr = result.stderr.to_lowercase();
+        if stderr.contains("is not recognized") || stderr.contains("commandnotfoundexception") {
+            environment.os_family = OsFamily::Windows;
+        }
+        return environment;
+    }
+
+    let sections = sections(&result.stdout);
+    let first = |name: &str| {
+        sections.get(name)
+            .and_then(|lines| lines.first())
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+    };
+
+    if let Some(path) = first("shell") {
+        environment.shell = shell_kind(path);
+        environment.shell_path = Some(path.to_string());
+    }
+    environment.os_family = match first("uname") {
+        Some("Linux") => OsFamily::Linux,
+        Some("Darwin") => OsFamily::Macos,
+        Some(kernel) if kernel.ends_with("BSD") || kernel == "DragonFly" => OsFamily::Bsd,
+        Some(kernel) if kernel.starts_with("CYGWIN") || kernel.starts_with("MINGW") || kernel.starts_with("MSYS") => OsFamily::Windows,
+        _ => OsFamily::Unknown,
+    };
+
+    let release: HashMap<&str, String> = sections.get("osrelease").into_iter().flatten()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches('"').trim_matches('\'').to_string()))
+        .collect();
+    environment.os_id = release.get("ID").cloned();
+    environment.os_like = release.get("ID_LIKE")
+        .map(|like| like.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    environment.os_version = release.get("VERSION_ID").cloned();
+    if environment.os_family == OsFamily::Macos {
+        environment.os_id = Some("macos".to_string());
+        environment.os_version = first("macos").map(str::to_string);
+    }
+
+    environment.locale = first("locale").map(str::to_string);
+    environment.utf8 = environment.locale.as_deref().is_some_and(|locale| {
+        let locale = locale.to_lowercase();
+        locale.contains("utf-8") || locale.contains("utf8")
+    });
+    environment
+}
+
+fn shell_kind(path: &str) -> ShellKind {
+    match path.rsplit('/').next().unwrap_or(path) {
+        "bash" => ShellKind::Bash,
+        "zsh" => ShellKind::Zsh,
+        "fish" => ShellKind::Fish,
+        "sh" | "dash" | "ash" => ShellKind::Sh,
+        "ksh" | "mksh" | "ksh93" => ShellKind::Ksh,
+        "csh" | "tcsh" => ShellKind::Csh,
+        _ => ShellKind::Unknown,
+    }
+}
+
+fn sections(output: &str) -> HashMap<&str, Vec<&str>> {
+    let mut sections: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut current = None;
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix(SECTION_PREFIX) {
+            current = Some(name.trim());
+            sections.entry(name.trim()).or_default();
+        } else if let Some(name) = current {
+            sections.entry(name).or_default().push(line);
+        }
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(stdout: &str, stderr: &str) -> CommandExecResult {
+        CommandExecResult {
+            stdout: stdout.to_string(),
+            stderr: stderr.to_string(),
+            exit_code: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_linux_and_macos() {
+        let linux = parse(&output(
+            "==shell\n/usr/bin/fish\n==uname\nLinux\n==osrelease\nNAME=\"Rocky Linux\"\nID=\"rocky\"\n\
+             ID_LIKE=\"rhel centos fedora\"\nVERSION_ID=\"9.3\"\n==macos\n==locale\nen_US.UTF-8\n",
+            "",
+        ));
+        assert_eq!((linux.shell, linux.os_family), (ShellKind::Fish, OsFamily::Linux));
+        assert_eq!((linux.os_id.as_deref(), linux.os_version.as_deref()), (Some("rocky"), Some("9.3")));
+        assert_eq!(linux.os_like, ["rhel", "centos", "fedora"]);
+        assert!(linux.utf8);
+
+        let mac = parse(&output("==shell\n/bin/zsh\n==uname\nDarwin\n==osrelease\n==macos\n14.4.1\n==locale\n\n", ""));
+        assert_eq!((mac.shell, mac.os_family), (ShellKind::Zsh, OsFamily::Macos));
+        assert_eq!((mac.os_id.as_deref(), mac.os_version.as_deref()), (Some("macos"), Some("14.4.1")));
+        assert_eq!((mac.locale, mac.utf8), (None, false));
+    }
+
+    #[test]
+    fn test_parse_windows() {
+        let cmd = parse(&output("", "'sh' is not recognized as an internal or external command,\r\n"));
+        assert_eq!((cmd.os_family, cmd.shell), (OsFamily::Windows, ShellKind::Unknown));
+    }
+}
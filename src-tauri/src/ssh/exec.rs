@@ -0,0 +1,215 @@
+use super::forward::would_block;
+use crate::types::{AppError, AppResult, CommandExecResult};
+use ssh2::{Channel, Session};
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+// How long a command may run before its channel is closed on it
+pub const EXEC_TIMEOUT: Duration = Duration::from_secs(300);
+// Time given to the close that ends a command which ran out of time
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+const IDLE_SLEEP: Duration = Duration::from_millis(10);
+const BUFFER_SIZE: usize = 16 * 1024;
+
+// Run a command over an exec channel of the non-blocking `session`. Its stdout and stderr are
+// read as they arrive, so a command that fills the channel window with one of them isn't
+// left waiting while the other is read to the end.
+pub fn run(session: &Session, command: &str, input: Option<&str>, timeout: Duration) -> AppResult<CommandExecResult> {
+    let deadline = Instant::now() + timeout;
+    let mut channel = until_ready(deadline, || session.channel_session(), |e| {
+        AppError::SSHConnectionFailed(format!("Failed to create channel: {}", e))
+    })?;
+
+    let result = run_on(&mut channel, command, input, deadline);
+    if result.is_err() {
+        let _ = until_ready(Instant::now() + CLOSE_TIMEOUT, || channel.close(), AppError::SSH2Error);
+    }
+    result
+}
+
+fn run_on(channel: &mut Channel, command: &str, input: Option<&str>, deadline: Instant) -> AppResult<CommandExecResult> {
+    until_ready(deadline, || channel.exec(command), |e| {
+        AppError::OperationFailed(format!("Failed to execute command: {}", e))
+    })?;
+
+    if let Some(input) = input {
+        write_input(channel, input.as_bytes(), deadline)?;
+        until_ready(deadline, || channel.send_eof(), AppError::SSH2Error)?;
+    }
+
+    let (stdout, stderr) = drain(channel.stream(0), channel.stderr(), deadline)?;
+    until_ready(deadline, || channel.wait_close(), AppError::SSH2Error)?;
+    let exit_code = channel.exit_status()?;
+
+    Ok(CommandExecResult {
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        exit_code,
+    })
+}
+
+fn write_input(channel: &mut Channel, mut input: &[u8], deadline: Instant) -> AppResult<()> {
+    while !input.is_empty() {
+        match channel.write(input) {
+            Ok(written) => input = &input[written..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => wait(deadline)?,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+// Read both streams until each has ended, taking whatever either has on every pass
+fn drain(stdout: impl Read, stderr: impl Read, deadline: Instant) -> AppResult<(Vec<u8>, Vec<u8>)> {
+    let mut stdout = Output::new(stdout);
+    let mut stderr = Output::new(stderr);
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    while !(stdout.ended && stderr.ended) {
+        if Instant::now() >= deadline {
+            return Err(timed_out());
+        }
+        let progressed = stdout.read_some(&mut buffer)? | stderr.read_some(&mut buffer)?;
+        if !progressed {
+            std::thread::sleep(IDLE_SLEEP);
+        }
+    }
+    Ok((stdout.bytes, stderr.bytes))
+}
+
+struct Output<R> {
+    stream: R,
+    bytes: Vec<u8>,
+    ended: bool,
+}
+
+impl<R: Read> Output<R> {
+    fn new(stream: R) -> Self {
+        Self {
+            stream,
+            bytes: Vec::new(),
+            ended: false,
+        }
+    }
+
+    // Whether the stream moved on, with data or its end
+    fn read_some(&mut self, buffer: &mut [u8]) -> io::Result<bool> {
+        if self.ended {
+            return Ok(false);
+        }
+        match self.stream.read(buffer) {
+            Ok(0) => {
+                self.ended = true;
+                Ok(true)
+            }
+            Ok(read) => {
+                self.bytes.extend_from_slice(&buffer[..read]);
+                Ok(true)
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn until_ready<T>(deadline: Instant, mut call: impl FnMut() -> Result<T, ssh2::Error>, fail: impl Fn(ssh2::Error) -> AppError) -> AppResult<T> {
+    loop {
+        match call() {
+            Ok(value) => return Ok(value),
+            Err(e) if would_block(&e) => wait(deadline)?,
+            Err(e) => return Err(fail(e)),
+        }
+    }
+}
+
+fn wait(deadline: Instant) -> AppResult<()> {
+    if Instant::now() >= deadline {
+        return Err(timed_out());
+    }
+    std::thread::sleep(IDLE_SLEEP);
+    Ok(())
+}
+
+fn timed_out() -> AppError {
+    AppError::TimeoutError("Command did not finish in time and was stopped".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // Hands out its data a chunk at a time, with nothing to read in between
+    struct Trickle {
+        data: Vec<u8>,
+        offset: usize,
+        ready: bool,
+        taken: Rc<Cell<usize>>,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.ready = !self.ready;
+            if !self.ready {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let read = buffer.len().min(4096).min(self.data.len() - self.offset);
+            buffer[..read].copy_from_slice(&self.data[self.offset..self.offset + read]);
+            self.offset += read;
+            self.taken.set(self.offset);
+            Ok(read)
+        }
+    }
+
+    // Like a command whose stdout stays quiet until the server could send all of its stderr
+    struct Stalled {
+        stderr_taken: Rc<Cell<usize>>,
+        stderr_len: usize,
+        data: &'static [u8],
+    }
+
+    impl Read for Stalled {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.stderr_taken.get() < self.stderr_len {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            let read = buffer.len().min(self.data.len());
+            buffer[..read].copy_from_slice(&self.data[..read]);
+            self.data = &self.data[read..];
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn test_drain_reads_stderr_while_stdout_waits() {
+        let stderr_len = 100 * 1024;
+        let taken = Rc::new(Cell::new(0));
+        let stderr = Trickle {
+            data: vec![b'e'; stderr_len],
+            offset: 0,
+            ready: false,
+            taken: taken.clone(),
+        };
+        let stdout = Stalled {
+            stderr_taken: taken,
+            stderr_len,
+            data: b"done\n",
+        };
+
+        let (stdout, stderr) = drain(stdout, stderr, Instant::now() + Duration::from_secs(10)).unwrap();
+        assert_eq!(stdout, b"done\n");
+        assert_eq!(stderr.len(), stderr_len);
+    }
+
+    #[test]
+    fn test_drain_gives_up_at_the_deadline() {
+        let stdout = Stalled {
+            stderr_taken: Rc::new(Cell::new(0)),
+            stderr_len: 1,
+            data: b"",
+        };
+        let result = drain(stdout, io::empty(), Instant::now() + Duration::from_millis(50));
+        assert!(matches!(result, Err(AppError::TimeoutError(_))));
+    }
+}
@@ -0,0 +1,783 @@
+use crate::types::{AppError, AppResult, PortForward, PortForwardKind, PortForwardRequest, PortForwardStatus};
+use chrono::Utc;
+use dashmap::DashMap;
+use ssh2::{Channel, ErrorCode, Listener, Session};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const DEFAULT_BIND_ADDRESS: &str = "127.0.0.1";
+const CHANNEL_OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const SOCKS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const IDLE_SLEEP: Duration = Duration::from_millis(5);
+const BUFFER_SIZE: usize = 16 * 1024;
+
+const SOCKS_VERSION: u8 = 0x05;
+const SOCKS_NO_AUTH: u8 = 0x00;
+const SOCKS_NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+const SOCKS_CMD_CONNECT: u8 = 0x01;
+const SOCKS_REPLY_SUCCEEDED: u8 = 0x00;
+const SOCKS_REPLY_HOST_UNREACHABLE: u8 = 0x04;
+const SOCKS_REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const SOCKS_REPLY_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+pub struct PortForwardManager {
+    forwards: Arc<DashMap<String, ForwardEntry>>,
+    events: broadcast::Sender<PortForward>,
+}
+
+struct ForwardEntry {
+    info: PortForward,
+    cancel: CancellationToken,
+}
+
+enum ForwardSource {
+    Local(TcpListener),
+    Remote(Listener),
+}
+
+// A dynamic forward client that got through the SOCKS handshake, and where it asked to go
+struct SocksRequest {
+    stream: TcpStream,
+    peer: SocketAddr,
+    target: (String, u16),
+}
+
+impl PortForwardManager {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(64);
+        Self {
+            forwards: Arc::new(DashMap::new()),
+            events,
+        }
+    }
+
+    // Status updates for every forward, published whenever one starts, closes or fails
+    pub fn subscribe(&self) -> broadcast::Receiver<PortForward> {
+        self.events.subscribe()
+    }
+
+    pub fn validate_request(request: &PortForwardRequest) -> AppResult<()> {
+        if request.kind == PortForwardKind::Remote && request.bind_port == 0 {
+            return Err(AppError::ValidationError("Remote forwards require an explicit bind port".to_string()));
+        }
+        if request.kind != PortForwardKind::Dynamic {
+            match (&request.target_host, request.target_port) {
+                (Some(host), Some(port)) if !host.is_empty() && port != 0 => {}
+                _ => return Err(AppError::ValidationError(
+                    "Local and remote forwards require a target host and port".to_string(),
+                )),
+            }
+        }
+        Ok(())
+    }
+
+    // Start forwarding over a dedicated, already authenticated SSH session.
+    // The session is owned by the forward and disconnected when it closes.
+    pub async fn start(&self, request: PortForwardRequest, session: Session) -> AppResult<PortForward> {
+        Self::validate_request(&request)?;
+
+        let kind = request.kind;
+        let bind_address = request.bind_address.clone()
+            .filter(|address| !address.is_empty())
+            .unwrap_or_else(|| DEFAULT_BIND_ADDRESS.to_string());
+        let bind_port = request.bind_port;
+
+        let (source, bind_port) = {
+            let session = session.clone();
+            let bind_address = bind_address.clone();
+            tokio::task::spawn_blocking(move || open_source(&session, kind, &bind_address, bind_port))
+                .await
+                .map_err(|e| AppError::InternalError(format!("Port forward setup task failed: {}", e)))??
+        };
+
+        let info = PortForward {
+            id: Uuid::new_v4().to_string(),
+            session_id: request.session_id.clone(),
+            kind,
+            bind_address,
+            bind_port,
+            target_host: request.target_host.clone(),
+            target_port: request.target_port,
+            status: PortForwardStatus::Active,
+            active_connections: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            created_at: Utc::now(),
+            error: None,
+        };
+
+        let cancel = CancellationToken::new();
+        self.forwards.insert(info.id.clone(), ForwardEntry {
+            info: info.clone(),
+            cancel: cancel.clone(),
+        });
+        let _ = self.events.send(info.clone());
+
+        let (handshaken, socks_requests) = mpsc::channel();
+        let worker = ForwardWorker {
+            id: info.id.clone(),
+            kind,
+            target: request.target_host.zip(request.target_port),
+            session,
+            source: Some(source),
+            handshaken,
+            socks_requests,
+            tunnels: Vec::new(),
+            forwards: self.forwards.clone(),
+            cancel,
+        };
+        let forwards = self.forwards.clone();
+        let events = self.events.clone();
+        let forward_id = info.id.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let result = worker.run();
+
+            if let Some((_, entry)) = forwards.remove(&forward_id) {
+                let mut info = entry.info;
+                info.active_connections = 0;
+                match result {
+                    Ok(()) => info.status = PortForwardStatus::Closed,
+                    Err(e) => {
+                        log::warn!("Port forward {} failed: {}", forward_id, e);
+                        info.status = PortForwardStatus::Failed;
+                        info.error = Some(e);
+                    }
+                }
+                let _ = events.send(info);
+            }
+        });
+
+        log::info!(
+            "Port forward {} started for session {}: {:?} {}:{}",
+            info.id, info.session_id, info.kind, info.bind_address, info.bind_port
+        );
+        Ok(info)
+    }
+
+    pub fn list(&self, session_id: Option<&str>) -> Vec<PortForward> {
+        let mut forwards: Vec<PortForward> = self.forwards.iter()
+            .filter(|entry| session_id.map_or(true, |id| entry.info.session_id == id))
+            .map(|entry| entry.info.clone())
+            .collect();
+        forwards.sort_by_key(|forward| forward.created_at);
+        forwards
+    }
+
+    pub fn get(&self, forward_id: &str) -> Option<PortForward> {
+        self.forwards.get(forward_id).map(|entry| entry.info.clone())
+    }
+
+    pub fn close(&self, forward_id: &str) -> AppResult<()> {
+        let entry = self.forwards.get(forward_id)
+            .ok_or_else(|| AppError::NotFound(format!("Port forward {}", forward_id)))?;
+        entry.cancel.cancel();
+        log::info!("Port forward {} closing", forward_id);
+        Ok(())
+    }
+
+    pub fn close_session(&self, session_id: &str) {
+        for entry in self.forwards.iter() {
+            if entry.info.session_id == session_id {
+                entry.cancel.cancel();
+            }
+        }
+    }
+
+    pub fn close_all(&self) {
+        for entry in self.forwards.iter() {
+            entry.cancel.cancel();
+        }
+    }
+}
+
+impl Default for PortForwardManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn open_source(
+    session: &Session,
+    kind: PortForwardKind,
+    bind_address: &str,
+    bind_port: u16,
+) -> AppResult<(ForwardSource, u16)> {
+    match kind {
+        PortForwardKind::Local | PortForwardKind::Dynamic => {
+            let listener = TcpListener::bind((bind_address, bind_port))
+                .map_err(|e| AppError::OperationFailed(format!("Failed to bind {}:{}: {}", bind_address, bind_port, e)))?;
+            listener.set_nonblocking(true)?;
+            let port = listener.local_addr()?.port();
+            Ok((ForwardSource::Local(listener), port))
+        }
+        PortForwardKind::Remote => {
+            let (listener, port) = session.channel_forward_listen(bind_port, Some(bind_address), None)
+                .map_err(|e| AppError::OperationFailed(format!(
+                    "Remote listen on {}:{} failed: {}", bind_address, bind_port, e
+                )))?;
+            Ok((ForwardSource::Remote(listener), port))
+        }
+    }
+}
+
+pub(crate) fn would_block(error: &ssh2::Error) -> bool {
+    error.code() == ErrorCode::Session(libssh2_sys::LIBSSH2_ERROR_EAGAIN)
+}
+
+struct ForwardWorker {
+    id: String,
+    kind: PortForwardKind,
+    target: Option<(String, u16)>,
+    session: Session,
+    source: Option<ForwardSource>,
+    // Handed to the handshake threads of dynamic forwards, which send back the clients
+    // that got through
+    handshaken: mpsc::Sender<SocksRequest>,
+    socks_requests: mpsc::Receiver<SocksRequest>,
+    tunnels: Vec<Tunnel>,
+    forwards: Arc<DashMap<String, ForwardEntry>>,
+    cancel: CancellationToken,
+}
+
+impl ForwardWorker {
+    // Runs on a blocking thread, multiplexing every tunnel over the non-blocking session
+    fn run(mut self) -> Result<(), String> {
+        self.session.set_blocking(false);
+
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut result = Ok(());
+
+        while !self.cancel.is_cancelled() {
+            let mut progressed = match self.accept() {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+
+            let mut sent = 0;
+            let mut received = 0;
+            let forward_id = &self.id;
+            self.tunnels.retain_mut(|tunnel| match tunnel.pump(&mut buffer) {
+                Ok(pumped) => {
+                    progressed |= pumped.progressed;
+                    sent += pumped.sent;
+                    received += pumped.received;
+                    if pumped.finished {
+                        tunnel.close();
+                    }
+                    !pumped.finished
+                }
+                Err(e) => {
+                    log::debug!("Port forward {} connection closed: {}", forward_id, e);
+                    tunnel.close();
+                    false
+                }
+            });
+
+            if let Some(mut entry) = self.forwards.get_mut(&self.id) {
+                entry.info.active_connections = self.tunnels.len();
+                entry.info.bytes_sent += sent;
+                entry.info.bytes_received += received;
+            }
+
+            if !progressed {
+                std::thread::sleep(IDLE_SLEEP);
+            }
+        }
+
+        // Tear down in blocking mode so channel closes and the remote listener cancel complete
+        self.session.set_blocking(true);
+        for mut tunnel in self.tunnels.drain(..) {
+            tunnel.close();
+        }
+        drop(self.source.take());
+        let _ = self.session.disconnect(None, "Port forward closed", None);
+
+        result
+    }
+
+    fn accept(&mut self) -> Result<bool, String> {
+        let mut accepted = self.accept_socks_requests();
+        accepted |= match self.source.as_mut() {
+            Some(ForwardSource::Local(listener)) => match listener.accept() {
+                Ok((stream, peer)) if self.kind == PortForwardKind::Dynamic => {
+                    spawn_socks_handshake(&self.id, stream, peer, self.handshaken.clone());
+                    true
+                }
+                Ok((stream, peer)) => {
+                    let tunnel = self.target.as_ref()
+                        .ok_or_else(|| "Missing forward target".to_string())
+                        .and_then(|target| open_local_tunnel(&self.session, target, stream, peer, false));
+                    match tunnel {
+                        Ok(tunnel) => self.tunnels.push(tunnel),
+                        Err(e) => log::debug!("Port forward {} rejected connection from {}: {}", self.id, peer, e),
+                    }
+                    true
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+                Err(e) => return Err(format!("Failed to accept connection: {}", e)),
+            },
+            Some(ForwardSource::Remote(listener)) => match listener.accept() {
+                Ok(channel) => {
+                    match open_remote_tunnel(self.target.as_ref(), channel) {
+                        Ok(tunnel) => self.tunnels.push(tunnel),
+                        Err(e) => log::debug!("Port forward {} failed to reach target: {}", self.id, e),
+                    }
+                    true
+                }
+                Err(e) if would_block(&e) => false,
+                Err(e) => return Err(format!("Failed to accept remote connection: {}", e)),
+            },
+            None => false,
+        };
+        Ok(accepted)
+    }
+
+    // Open a channel for each dynamic forward client whose handshake has finished
+    fn accept_socks_requests(&mut self) -> bool {
+        let mut accepted = false;
+        while let Ok(request) = self.socks_requests.try_recv() {
+            accepted = true;
+            match open_local_tunnel(&self.session, &request.target, request.stream, request.peer, true) {
+                Ok(tunnel) => self.tunnels.push(tunnel),
+                Err(e) => log::debug!("Port forward {} rejected connection from {}: {}", self.id, request.peer, e),
+            }
+        }
+        accepted
+    }
+}
+
+// The SOCKS handshake waits on the client, so it runs on a thread of its own and only
+// clients that got through reach the worker
+fn spawn_socks_handshake(forward_id: &str, mut stream: TcpStream, peer: SocketAddr, handshaken: mpsc::Sender<SocksRequest>) {
+    let id = forward_id.to_string();
+    let spawned = std::thread::Builder::new()
+        .name(format!("socks-handshake-{}", peer))
+        .spawn(move || {
+            // Accepted sockets inherit the listener's non-blocking flag on some platforms
+            let target = stream.set_nonblocking(false)
+                .and_then(|()| stream.set_read_timeout(Some(SOCKS_HANDSHAKE_TIMEOUT)))
+                .map_err(|e| e.to_string())
+                .and_then(|()| socks5_handshake(&mut stream));
+            match target {
+                // The forward may have closed meanwhile, which drops the client
+                Ok(target) => drop(handshaken.send(SocksRequest { stream, peer, target })),
+                Err(e) => log::debug!("Port forward {} rejected connection from {}: {}", id, peer, e),
+            }
+        });
+    if let Err(e) = spawned {
+        log::warn!("Port forward {} could not start a SOCKS handshake: {}", forward_id, e);
+    }
+}
+
+// Tunnel a local client to `target`. SOCKS clients, already past the handshake, are told
+// whether the channel opened.
+fn open_local_tunnel(
+    session: &Session,
+    target: &(String, u16),
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    socks: bool,
+) -> Result<Tunnel, String> {
+    let (host, port) = target;
+    let channel = match open_direct_channel(session, host, *port, peer) {
+        Ok(channel) => channel,
+        Err(e) => {
+            if socks {
+                let _ = stream.write_all(&socks5_reply(SOCKS_REPLY_HOST_UNREACHABLE));
+            }
+            return Err(e);
+        }
+    };
+
+    if socks {
+        stream.write_all(&socks5_reply(SOCKS_REPLY_SUCCEEDED)).map_err(|e| e.to_string())?;
+        stream.set_read_timeout(None).map_err(|e| e.to_string())?;
+    }
+    stream.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+    Ok(Tunnel::new(stream, channel))
+}
+
+fn open_remote_tunnel(target: Option<&(String, u16)>, mut channel: Channel) -> Result<Tunnel, String> {
+    let connect = || -> Result<TcpStream, String> {
+        let (host, port) = target.ok_or_else(|| "Missing forward target".to_string())?;
+        let address = (host.as_str(), *port).to_socket_addrs()
+            .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+            .next()
+            .ok_or_else(|| format!("No addresses found for {}", host))?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+            .map_err(|e| format!("Failed to connect to {}:{}: {}", host, port, e))?;
+        stream.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Ok(stream)
+    };
+
+    match connect() {
+        Ok(stream) => Ok(Tunnel::new(stream, channel)),
+        Err(e) => {
+            let _ = channel.close();
+            Err(e)
+        }
+    }
+}
+
+// The session is non-blocking here, so retry until the server answers or we time out
+fn open_direct_channel(session: &Session, host: &str, port: u16, peer: SocketAddr) -> Result<Channel, String> {
+    let source = peer.ip().to_string();
+    let deadline = Instant::now() + CHANNEL_OPEN_TIMEOUT;
+
+    loop {
+        match session.channel_direct_tcpip(host, port, Some((source.as_str(), peer.port()))) {
+            Ok(channel) => return Ok(channel),
+            Err(e) if would_block(&e) && Instant::now() < deadline => std::thread::sleep(IDLE_SLEEP),
+            Err(e) => return Err(format!("Failed to open channel to {}:{}: {}", host, port, e)),
+        }
+    }
+}
+
+// Carry a connection to host:port through a jump host's session, for the next SSH session
+// in the chain to run over. ssh2 needs a real socket, so like the WebSocket transport this
+// returns a loopback one, pumped to a direct-tcpip channel on a thread of its own. The jump
+// host is disconnected once either end closes, so dropping the returned stream tears it down.
+pub fn open_jump_tunnel(session: Session, host: &str, port: u16) -> AppResult<TcpStream> {
+    let channel = session.channel_direct_tcpip(host, port, None).map_err(|e| {
+        let _ = session.disconnect(None, "Jump failed", None);
+        AppError::SSHConnectionFailed(format!("Jump host could not reach {}:{}: {}", host, port, e))
+    })?;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let client = TcpStream::connect(listener.local_addr()?)?;
+    let (bridge, _) = listener.accept()?;
+    client.set_nodelay(true)?;
+    bridge.set_nodelay(true)?;
+    bridge.set_nonblocking(true)?;
+
+    let target = format!("{}:{}", host, port);
+    std::thread::Builder::new()
+        .name(format!("jump-{}", target))
+        .spawn(move || {
+            session.set_blocking(false);
+            let mut tunnel = Tunnel::new(bridge, channel);
+            let mut buffer = vec![0u8; BUFFER_SIZE];
+            loop {
+                match tunnel.pump(&mut buffer) {
+                    Ok(pumped) if pumped.finished => break,
+                    Ok(pumped) if !pumped.progressed => std::thread::sleep(IDLE_SLEEP),
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::debug!("Jump tunnel to {} closed: {}", target, e);
+                        break;
+                    }
+                }
+            }
+
+            session.set_blocking(true);
+            tunnel.close();
+            let _ = session.disconnect(None, "Jump tunnel closed", None);
+        })
+        .map_err(|e| AppError::InternalError(format!("Failed to start jump tunnel: {}", e)))?;
+
+    Ok(client)
+}
+
+// Minimal SOCKS5 server handshake (RFC 1928): no authentication, CONNECT only
+fn socks5_handshake<S: Read + Write>(stream: &mut S) -> Result<(String, u16), String> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).map_err(|e| e.to_string())?;
+    if header[0] != SOCKS_VERSION {
+        return Err(format!("Unsupported SOCKS version: {}", header[0]));
+    }
+
+    let mut methods = vec![0u8; header[1] as usize];
+    stream.read_exact(&mut methods).map_err(|e| e.to_string())?;
+    if !methods.contains(&SOCKS_NO_AUTH) {
+        let _ = stream.write_all(&[SOCKS_VERSION, SOCKS_NO_ACCEPTABLE_METHODS]);
+        return Err("Client does not offer unauthenticated SOCKS access".to_string());
+    }
+    stream.write_all(&[SOCKS_VERSION, SOCKS_NO_AUTH]).map_err(|e| e.to_string())?;
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).map_err(|e| e.to_string())?;
+    if request[0] != SOCKS_VERSION {
+        return Err(format!("Unsupported SOCKS version: {}", request[0]));
+    }
+    if request[1] != SOCKS_CMD_CONNECT {
+        let _ = stream.write_all(&socks5_reply(SOCKS_REPLY_COMMAND_NOT_SUPPORTED));
+        return Err(format!("Unsupported SOCKS command: {}", request[1]));
+    }
+
+    let host = match request[3] {
+        0x01 => {
+            let mut address = [0u8; 4];
+            stream.read_exact(&mut address).map_err(|e| e.to_string())?;
+            Ipv4Addr::from(address).to_string()
+        }
+        0x03 => {
+            let mut length = [0u8; 1];
+            stream.read_exact(&mut length).map_err(|e| e.to_string())?;
+            let mut domain = vec![0u8; length[0] as usize];
+            stream.read_exact(&mut domain).map_err(|e| e.to_string())?;
+            String::from_utf8(domain).map_err(|_| "Invalid SOCKS domain name".to_string())?
+        }
+        0x04 => {
+            let mut address = [0u8; 16];
+            stream.read_exact(&mut address).map_err(|e| e.to_string())?;
+            Ipv6Addr::from(address).to_string()
+        }
+        other => {
+            let _ = stream.write_all(&socks5_reply(SOCKS_REPLY_ADDRESS_NOT_SUPPORTED));
+            return Err(format!("Unsupported SOCKS address type: {}", other));
+        }
+    };
+
+    let mut port = [0u8; 2];
+    stream.read_exact(&mut port).map_err(|e| e.to_string())?;
+
+    Ok((host, u16::from_be_bytes(port)))
+}
+
+fn socks5_reply(code: u8) -> [u8; 10] {
+    [SOCKS_VERSION, code, 0x00, 0x01, 0, 0, 0, 0, 0, 0]
+}
+
+#[derive(Default)]
+struct PumpResult {
+    progressed: bool,
+    finished: bool,
+    sent: u64,
+    received: u64,
+}
+
+// One forwarded connection: a local TCP socket paired with an SSH channel
+struct Tunnel {
+    stream: TcpStream,
+    channel: Channel,
+    upstream: Vec<u8>,
+    downstream: Vec<u8>,
+    local_eof: bool,
+    remote_eof: bool,
+    eof_sent: bool,
+}
+
+impl Tunnel {
+    fn new(stream: TcpStream, channel: Channel) -> Self {
+        Self {
+            stream,
+            channel,
+            upstream: Vec::new(),
+            downstream: Vec::new(),
+            local_eof: false,
+            remote_eof: false,
+            eof_sent: false,
+        }
+    }
+
+    fn pump(&mut self, buffer: &mut [u8]) -> std::io::Result<PumpResult> {
+        let mut result = PumpResult::default();
+
+        // Socket -> channel
+        if self.upstream.is_empty() && !self.local_eof {
+            match self.stream.read(buffer) {
+                Ok(0) => {
+                    self.local_eof = true;
+                    result.progressed = true;
+                }
+                Ok(n) => {
+                    self.upstream.extend_from_slice(&buffer[..n]);
+                    result.progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.upstream.is_empty() {
+            match self.channel.write(&self.upstream) {
+                Ok(n) => {
+                    self.upstream.drain(..n);
+                    result.sent += n as u64;
+                    result.progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if self.local_eof && self.upstream.is_empty() && !self.eof_sent {
+            match self.channel.send_eof() {
+                Ok(()) => self.eof_sent = true,
+                Err(e) if would_block(&e) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // Channel -> socket
+        if self.downstream.is_empty() && !self.remote_eof {
+            match self.channel.read(buffer) {
+                Ok(0) => {
+                    if self.channel.eof() {
+                        self.remote_eof = true;
+                        let _ = self.stream.shutdown(Shutdown::Write);
+                        result.progressed = true;
+                    }
+                }
+                Ok(n) => {
+                    self.downstream.extend_from_slice(&buffer[..n]);
+                    result.progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.downstream.is_empty() {
+            match self.stream.write(&self.downstream) {
+                Ok(n) => {
+                    self.downstream.drain(..n);
+                    result.received += n as u64;
+                    result.progressed = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        result.finished = self.eof_sent && self.remote_eof && self.downstream.is_empty();
+        Ok(result)
+    }
+
+    fn close(&mut self) {
+        let _ = self.channel.close();
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(input: Vec<u8>) -> Self {
+            Self { input: Cursor::new(input), output: Vec::new() }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn request(kind: PortForwardKind, target_host: Option<&str>, target_port: Option<u16>) -> PortForwardRequest {
+        PortForwardRequest {
+            session_id: "session".to_string(),
+            kind,
+            bind_address: None,
+            bind_port: 8080,
+            target_host: target_host.map(|h| h.to_string()),
+            target_port,
+        }
+    }
+
+    #[test]
+    fn test_socks5_handshake_domain() {
+        let mut input = vec![0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x03, 11];
+        input.extend_from_slice(b"example.com");
+        input.extend_from_slice(&443u16.to_be_bytes());
+        let mut stream = MockStream::new(input);
+
+        let (host, port) = socks5_handshake(&mut stream).unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(port, 443);
+        assert_eq!(stream.output, vec![0x05, 0x00]);
+    }
+
+    #[test]
+    fn test_socks5_handshake_ipv4() {
+        let input = vec![0x05, 0x02, 0x02, 0x00, 0x05, 0x01, 0x00, 0x01, 10, 0, 0, 1, 0x00, 0x16];
+        let mut stream = MockStream::new(input);
+
+        let (host, port) = socks5_handshake(&mut stream).unwrap();
+        assert_eq!(host, "10.0.0.1");
+        assert_eq!(port, 22);
+    }
+
+    #[test]
+    fn test_socks5_handshake_rejects_auth_only_clients() {
+        let mut stream = MockStream::new(vec![0x05, 0x01, 0x02]);
+
+        assert!(socks5_handshake(&mut stream).is_err());
+        assert_eq!(stream.output, vec![0x05, 0xFF]);
+    }
+
+    #[test]
+    fn test_socks5_handshake_rejects_bind() {
+        let input = vec![0x05, 0x01, 0x00, 0x05, 0x02, 0x00, 0x01, 127, 0, 0, 1, 0x00, 0x50];
+        let mut stream = MockStream::new(input);
+
+        assert!(socks5_handshake(&mut stream).is_err());
+        assert_eq!(stream.output[2..4], [0x05, SOCKS_REPLY_COMMAND_NOT_SUPPORTED]);
+    }
+
+    #[test]
+    fn test_socks_handshakes_run_apart() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let (handshaken, requests) = mpsc::channel();
+
+        // A client that connects and never speaks doesn't keep the next one waiting
+        let _silent = TcpStream::connect(address).unwrap();
+        let (stream, peer) = listener.accept().unwrap();
+        spawn_socks_handshake("forward", stream, peer, handshaken.clone());
+
+        let mut client = TcpStream::connect(address).unwrap();
+        let (stream, peer) = listener.accept().unwrap();
+        spawn_socks_handshake("forward", stream, peer, handshaken);
+        client.write_all(&[0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x01, 10, 0, 0, 1, 0x00, 0x16]).unwrap();
+
+        let request = requests.recv_timeout(SOCKS_HANDSHAKE_TIMEOUT / 2).unwrap();
+        assert_eq!(request.target, ("10.0.0.1".to_string(), 22));
+        assert_eq!(request.peer, peer);
+    }
+
+    #[test]
+    fn test_validate_request() {
+        assert!(PortForwardManager::validate_request(&request(PortForwardKind::Local, Some("db"), Some(5432))).is_ok());
+        assert!(PortForwardManager::validate_request(&request(PortForwardKind::Local, None, Some(5432))).is_err());
+        assert!(PortForwardManager::validate_request(&request(PortForwardKind::Remote, Some("localhost"), None)).is_err());
+        assert!(PortForwardManager::validate_request(&request(PortForwardKind::Dynamic, None, None)).is_ok());
+
+        let mut remote = request(PortForwardKind::Remote, Some("localhost"), Some(3000));
+        remote.bind_port = 0;
+        assert!(PortForwardManager::validate_request(&remote).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_close_unknown_forward() {
+        let manager = PortForwardManager::new();
+        assert!(manager.close("missing").is_err());
+        assert!(manager.list(None).is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::types::AppResult;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// The least used commands go first past this
+const MAX_COMMANDS_PER_HOST: usize = 1000;
+// A command's weight halves for every week it goes unused
+const HALF_LIFE_DAYS: f64 = 7.0;
+// Longer lines are pastes rather than commands worth suggesting
+const MAX_LINE_CHARS: usize = 4096;
+// A line typed after a prompt like these is a secret, not a command
+const SECRET_PROMPTS: &[&str] = &["password", "passphrase", "passcode", "verification code", "one-time"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub count: u32,
+    #[serde(rename = "lastUsed")]
+    pub last_used: DateTime<Utc>,
+}
+
+impl HistoryEntry {
+    // Frequency, discounted by how long ago the command was last used
+    fn score(&self, now: DateTime<Utc>) -> f64 {
+        let age_days = (now - self.last_used).num_seconds().max(0) as f64 / 86_400.0;
+        self.count as f64 * 0.5_f64.powf(age_days / HALF_LIFE_DAYS)
+    }
+}
+
+// Commands entered in shells, per user@host:port so every session to a host shares them
+#[derive(Default)]
+pub struct CommandHistory {
+    hosts: DashMap<String, Vec<HistoryEntry>>,
+    storage_path: Option<PathBuf>,
+}
+
+impl CommandHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Load a persisted history, starting empty if the file does not exist yet
+    pub async fn load(storage_path: PathBuf) -> AppResult<Self> {
+        let mut history = Self::new();
+
+        if tokio::fs::try_exists(&storage_path).await? {
+            let content = tokio::fs::read_to_string(&storage_path).await?;
+            let hosts: HashMap<String, Vec<HistoryEntry>> = serde_json::from_str(&content)?;
+            history.hosts.extend(hosts);
+        }
+
+        history.storage_path = Some(storage_path);
+        Ok(history)
+    }
+
+    pub async fn record(&self, host: &str, command: &str) -> AppResult<()> {
+        let now = Utc::now();
+        {
+            let mut entries = self.hosts.entry(host.to_string()).or_default();
+            match entries.iter_mut().find(|entry| entry.command == command) {
+                Some(entry) => {
+                    entry.count += 1;
+                    entry.last_used = now;
+                }
+                None => entries.push(HistoryEntry {
+                    command: command.to_string(),
+                    count: 1,
+                    last_used: now,
+                }),
+            }
+            if entries.len() > MAX_COMMANDS_PER_HOST {
+                entries.sort_by(|a, b| b.score(now).total_cmp(&a.score(now)));
+                entries.truncate(MAX_COMMANDS_PER_HOST);
+            }
+        }
+        self.save().await
+    }
+
+    // Best ranked first; an empty prefix lists everything
+    pub fn search(&self, host: &str, prefix: &str, limit: usize) -> Vec<HistoryEntry> {
+        let Some(entries) = self.hosts.get(host) else {
+            return Vec::new();
+        };
+        let now = Utc::now();
+        let mut matches: Vec<HistoryEntry> = entries.iter()
+            .filter(|entry| entry.command.starts_with(prefix))
+            .cloned()
+            .collect();
+        matches.sort_by(|a, b| b.score(now).total_cmp(&a.score(now)).then_with(|| b.last_used.cmp(&a.last_used)));
+        matches.truncate(limit);
+        matches
+    }
+
+    pub async fn clear(&self, host: &str) -> AppResult<()> {
+        if self.hosts.remove(host).is_some() {
+            self.save().await?;
+        }
+        Ok(())
+    }
+
+    async fn save(&self) -> AppResult<()> {
+        let Some(path) = &self.storage_path else {
+            return Ok(());
+        };
+
+        let hosts: HashMap<String, Vec<HistoryEntry>> = self.hosts.iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, serde_json::to_string(&hosts)?).await?;
+        Ok(())
+    }
+}
+
+// The line being typed into a shell, rebuilt from its input. Once the shell edits the line
+// itself (tab completion, history recall, Ctrl keys) what it runs is no longer known, and the
+// line is left out.
+#[derive(Default)]
+pub struct InputLine {
+    text: String,
+    edited: bool,
+}
+
+impl InputLine {
+    // Lines entered with this input, in order
+    pub fn feed(&mut self, input: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        for c in input.chars() {
+            match c {
+                '\r' | '\n' => {
+                    let line = std::mem::take(&mut self.text);
+                    let line = line.trim();
+                    if !std::mem::take(&mut self.edited) && !line.is_empty() {
+                        lines.push(line.to_string());
+                    }
+                }
+                '\x7f' | '\x08' => {
+                    self.text.pop();
+                }
+                // Ctrl+C and Ctrl+U throw the line away
+                '\x03' | '\x15' => {
+                    self.text.clear();
+                    self.edited = false;
+                }
+                c if c.is_control() => self.edited = true,
+                c if self.text.len() < MAX_LINE_CHARS => self.text.push(c),
+                _ => self.edited = true,
+            }
+        }
+        lines
+    }
+}
+
+// Whether the shell's last line of output asks for a secret, like sudo's password prompt
+pub fn is_secret_prompt(line: &str) -> bool {
+    let line = line.trim().to_lowercase();
+    line.ends_with(':') && SECRET_PROMPTS.iter().any(|prompt| line.contains(prompt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_input_line() {
+        let mut line = InputLine::default();
+        assert!(line.feed("git sta").is_empty());
+        assert_eq!(line.feed("tus\r"), ["git status"]);
+
+        // Typos fixed with backspace, pasted lines, blank lines
+        assert_eq!(line.feed("lss\x7f -la\r\rpwd\nuptime\n"), ["ls -la", "pwd", "uptime"]);
+
+        // Tab completion and recalled history leave the line unknown
+        assert!(line.feed("cd /va\t\r").is_empty());
+        assert!(line.feed("\x1b[A\r").is_empty());
+        assert_eq!(line.feed("echo ok\r"), ["echo ok"]);
+
+        // Ctrl+C starts over
+        assert_eq!(line.feed("rm -rf\x03df -h\r"), ["df -h"]);
+    }
+
+    #[test]
+    fn test_secret_prompt() {
+        assert!(is_secret_prompt("[sudo] password for deploy: "));
+        assert!(is_secret_prompt("Enter passphrase for key '/home/deploy/.ssh/id_ed25519':"));
+        assert!(!is_secret_prompt("deploy@web1:~$ "));
+        assert!(!is_secret_prompt("Password changed"));
+    }
+
+    #[tokio::test]
+    async fn test_history_ranking_and_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.json");
+        let history = CommandHistory::load(path.clone()).await.unwrap();
+
+        history.record("deploy@web1:22", "git status").await.unwrap();
+        history.record("deploy@web1:22", "git pull").await.unwrap();
+        history.record("deploy@web1:22", "git pull").await.unwrap();
+        history.record("deploy@db1:22", "git log").await.unwrap();
+
+        let commands = |entries: Vec<HistoryEntry>| entries.into_iter().map(|entry| entry.command).collect::<Vec<_>>();
+        assert_eq!(commands(history.search("deploy@web1:22", "git", 10)), ["git pull", "git status"]);
+        assert_eq!(commands(history.search("deploy@web1:22", "git s", 10)), ["git status"]);
+
+        // Used often long ago ranks below used once today
+        history.hosts.get_mut("deploy@web1:22").unwrap()[1].last_used = Utc::now() - Duration::days(60);
+        assert_eq!(commands(history.search("deploy@web1:22", "git", 10)), ["git status", "git pull"]);
+
+        let reloaded = CommandHistory::load(path.clone()).await.unwrap();
+        assert_eq!(reloaded.search("deploy@web1:22", "", 10).len(), 2);
+
+        history.clear("deploy@web1:22").await.unwrap();
+        let reloaded = CommandHistory::load(path).await.unwrap();
+        assert!(reloaded.search("deploy@web1:22", "", 10).is_empty());
+        assert_eq!(commands(reloaded.search("deploy@db1:22", "", 10)), ["git log"]);
+    }
+}
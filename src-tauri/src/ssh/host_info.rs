@@ -0,0 +1,128 @@
+use crate::types::{DiskUsage, HostInfo};
+use chrono::Utc;
+
+const SECTION_PREFIX: &str = "==";
+
+// A single exec that prints each fact under a "==name" marker. Every probe tolerates
+// a missing tool or file, so one gap doesn't lose the rest.
+pub const COLLECT_COMMAND: &str = "\
+echo '==os'; (. /etc/os-release 2>/dev/null && echo \"$PRETTY_NAME\") || sw_vers -productName 2>/dev/null || uname -s; \
+echo '==kernel'; uname -sr; \
+echo '==hostname'; hostname; \
+echo '==uptime'; cat /proc/uptime 2>/dev/null; \
+echo '==cpus'; nproc 2>/dev/null || getconf _NPROCESSORS_ONLN 2>/dev/null; \
+echo '==load'; cat /proc/loadavg 2>/dev/null; \
+echo '==mem'; grep -E '^(MemTotal|MemAvailable):' /proc/meminfo 2>/dev/null; \
+echo '==disk'; df -Pk 2>/dev/null; \
+true";
+
+// Pseudo filesystems that only clutter a disk usage panel
+const IGNORED_FILESYSTEMS: &[&str] = &["tmpfs", "devtmpfs", "overlay", "squashfs", "udev", "none", "shm"];
+
+pub fn parse(output: &str) -> HostInfo {
+    let mut info = HostInfo {
+        collected_at: Some(Utc::now()),
+        ..HostInfo::default()
+    };
+
+    for (section, lines) in sections(output) {
+        let first = lines.first().map(|line| line.trim()).filter(|line| !line.is_empty());
+        match section {
+            "os" => info.os = first.map(str::to_string),
+            "kernel" => info.kernel = first.map(str::to_string),
+            "hostname" => info.hostname = first.map(str::to_string),
+            // /proc/uptime: "<seconds up> <seconds idle>"
+            "uptime" => {
+                info.uptime_seconds = first
+                    .and_then(|line| line.split_whitespace().next())
+                    .and_then(|seconds| seconds.parse::<f64>().ok())
+                    .map(|seconds| seconds as u64);
+            }
+            "cpus" => info.cpu_count = first.and_then(|line| line.parse().ok()),
+            "load" => {
+                let load: Vec<f64> = first
+                    .map(|line| line.split_whitespace().take(3).filter_map(|value| value.parse().ok()).collect())
+                    .unwrap_or_default();
+                info.load_average = <[f64; 3]>::try_from(load).ok();
+            }
+            "mem" => {
+                for line in &lines {
+                    let Some((name, value)) = line.split_once(':') else {
+                        continue;
+                    };
+                    let kb = value.split_whitespace().next().and_then(|kb| kb.parse().ok());
+                    match name {
+                        "MemTotal" => info.memory_total_kb = kb,
+                        "MemAvailable" => info.memory_available_kb = kb,
+                        _ => {}
+                    }
+                }
+            }
+            "disk" => info.disks = lines.iter().skip(1).filter_map(|line| parse_df_line(line)).collect(),
+            _ => {}
+        }
+    }
+    info
+}
+
+pub(crate) fn sections(output: &str) -> Vec<(&str, Vec<&str>)> {
+    let mut sections: Vec<(&str, Vec<&str>)> = Vec::new();
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix(SECTION_PREFIX) {
+            sections.push((name.trim(), Vec::new()));
+        } else if let Some((_, lines)) = sections.last_mut() {
+            lines.push(line);
+        }
+    }
+    sections
+}
+
+// POSIX df -Pk: filesystem, 1024-blocks, used, available, capacity, mounted on
+fn parse_df_line(line: &str) -> Option<DiskUsage> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 6 || IGNORED_FILESYSTEMS.contains(&fields[0]) {
+        return None;
+    }
+    Some(DiskUsage {
+        filesystem: fields[0].to_string(),
+        total_kb: fields[1].parse().ok()?,
+        used_kb: fields[2].parse().ok()?,
+        available_kb: fields[3].parse().ok()?,
+        // Mount points may contain spaces
+        mount_point: fields[5..].join(" "),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_linux_host() {
+        let output = "==os\nUbuntu 22.04.4 LTS\n==kernel\nLinux 5.15.0-105-generic\n==hostname\nweb-1\n\
+            ==uptime\n86461.52 170000.10\n==cpus\n4\n==load\n0.15 0.10 0.05 1/234 5678\n\
+            ==mem\nMemTotal:        8048576 kB\nMemAvailable:    6021344 kB\n\
+            ==disk\nFilesystem     1024-blocks    Used Available Capacity Mounted on\n\
+            /dev/sda1         41152736 9876543  31276193      25% /\ntmpfs              4024288       0   4024288       0% /dev/shm\n\
+            /dev/sdb1         10000000 1000000   9000000      10% /mnt/backup disk\n";
+
+        let info = parse(output);
+        assert_eq!(info.os.as_deref(), Some("Ubuntu 22.04.4 LTS"));
+        assert_eq!(info.hostname.as_deref(), Some("web-1"));
+        assert_eq!(info.uptime_seconds, Some(86461));
+        assert_eq!(info.cpu_count, Some(4));
+        assert_eq!(info.load_average, Some([0.15, 0.10, 0.05]));
+        assert_eq!((info.memory_total_kb, info.memory_available_kb), (Some(8048576), Some(6021344)));
+        assert_eq!(info.disks.len(), 2);
+        assert_eq!(info.disks[1].mount_point, "/mnt/backup disk");
+    }
+
+    #[test]
+    fn test_parse_tolerates_missing_sections() {
+        // A BSD box without /proc
+        let info = parse("==os\nFreeBSD\n==kernel\nFreeBSD 14.0-RELEASE\n==hostname\nfw\n==uptime\n==cpus\n2\n==load\n==mem\n==disk\n");
+        assert_eq!(info.kernel.as_deref(), Some("FreeBSD 14.0-RELEASE"));
+        assert_eq!((info.uptime_seconds, info.load_average, info.memory_total_kb), (None, None, None));
+        assert!(info.disks.is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::log_security;
+use crate::types::{AppError, AppResult};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ssh2::{HostKeyType, Session};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+// How to treat a host key that is not in the store yet. Changed keys are always rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostKeyPolicy {
+    // Trust on first use and remember the key
+    AcceptNew,
+    // Reject the connection and publish an event so the user can decide
+    Ask,
+    // Only connect to hosts that were trusted beforehand
+    Strict,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownHost {
+    pub hostname: String,
+    pub port: u16,
+    #[serde(rename = "keyType")]
+    pub key_type: String,
+    pub fingerprint: String,
+    pub key: String, // Base64 encoded public key
+    #[serde(rename = "addedAt")]
+    pub added_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnknownHostKeyEvent {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub hostname: String,
+    pub port: u16,
+    #[serde(rename = "keyType")]
+    pub key_type: String,
+    pub fingerprint: String,
+}
+
+pub struct KnownHostsStore {
+    hosts: Arc<DashMap<String, KnownHost>>,
+    // Keys seen during a rejected connection, waiting for the user to trust them
+    pending: Arc<DashMap<String, KnownHost>>,
+    storage_path: Option<PathBuf>,
+    policy: HostKeyPolicy,
+    events: broadcast::Sender<UnknownHostKeyEvent>,
+}
+
+impl KnownHostsStore {
+    pub fn new(policy: HostKeyPolicy) -> Self {
+        let (events, _) = broadcast::channel(16);
+        Self {
+            hosts: Arc::new(DashMap::new()),
+            pending: Arc::new(DashMap::new()),
+            storage_path: None,
+            policy,
+            events,
+        }
+    }
+
+    // Load a persisted store, starting empty if the file does not exist yet
+    pub async fn load(storage_path: PathBuf, policy: HostKeyPolicy) -> AppResult<Self> {
+        let mut store = Self::new(policy);
+
+        if tokio::fs::try_exists(&storage_path).await? {
+            let content = tokio::fs::read_to_string(&storage_path).await?;
+            let hosts: Vec<KnownHost> = serde_json::from_str(&content)?;
+            for host in hosts {
+                store.hosts.insert(host_id(&host.hostname, host.port), host);
+            }
+        }
+
+        log::info!("Loaded {} known hosts from {:?}", store.hosts.len(), storage_path);
+        store.storage_path = Some(storage_path);
+        Ok(store)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<UnknownHostKeyEvent> {
+        self.events.subscribe()
+    }
+
+    // Check the server key of a freshly handshaken session before any credentials are sent
+    pub async fn verify(&self, session_id: &str, hostname: &str, port: u16, session: &Session) -> AppResult<()> {
+        let (key, key_type) = session.host_key()
+            .ok_or_else(|| AppError::SSHConnectionFailed("Server did not provide a host key".to_string()))?;
+        let candidate = KnownHost {
+            hostname: hostname.to_string(),
+            port,
+            key_type: key_type_name(key_type).to_string(),
+            fingerprint: fingerprint(key),
+            key: general_purpose::STANDARD.encode(key),
+            added_at: Utc::now(),
+        };
+        let id = host_id(hostname, port);
+
+        if let Some(known) = self.hosts.get(&id) {
+            if known.key == candidate.key {
+                return Ok(());
+            }
+
+            log_security!("host_key_mismatch", "critical", {
+                let mut details = std::collections::HashMap::new();
+                details.insert("host".to_string(), id.clone());
+                details.insert("expected_fingerprint".to_string(), known.fingerprint.clone());
+                details.insert("received_fingerprint".to_string(), candidate.fingerprint.clone());
+                details
+            });
+            return Err(AppError::HostKeyMismatch(format!(
+                "{} presented {} but {} is trusted",
+                id, candidate.fingerprint, known.fingerprint
+            )));
+        }
+
+        match self.policy {
+            HostKeyPolicy::AcceptNew => {
+                log::info!("Trusting new host key for {}: {}", id, candidate.fingerprint);
+                self.hosts.insert(id, candidate);
+                self.save().await
+            }
+            HostKeyPolicy::Ask => {
+                let event = UnknownHostKeyEvent {
+                    session_id: session_id.to_string(),
+                    hostname: candidate.hostname.clone(),
+                    port,
+                    key_type: candidate.key_type.clone(),
+                    fingerprint: candidate.fingerprint.clone(),
+                };
+                let message = format!("{} ({})", id, candidate.fingerprint);
+                self.pending.insert(id, candidate);
+                let _ = self.events.send(event);
+                Err(AppError::HostKeyUnknown(message))
+            }
+            HostKeyPolicy::Strict => Err(AppError::HostKeyUnknown(format!("{} ({})", id, candidate.fingerprint))),
+        }
+    }
+
+    // Trust a key previously reported as unknown; the fingerprint must match what the server presented
+    pub async fn trust(&self, hostname: &str, port: u16, fingerprint: &str) -> AppResult<KnownHost> {
+        let id = host_id(hostname, port);
+        let (_, mut host) = self.pending.remove_if(&id, |_, pending| pending.fingerprint == fingerprint)
+            .ok_or_else(|| AppError::NotFound(format!("No pending host key {} for {}", fingerprint, id)))?;
+
+        host.added_at = Utc::now();
+        self.hosts.insert(id, host.clone());
+        self.save().await?;
+
+        log_security!("host_key_trusted", "info", {
+            let mut details = std::collections::HashMap::new();
+            details.insert("host".to_string(), format!("{}:{}", host.hostname, host.port));
+            details.insert("fingerprint".to_string(), host.fingerprint.clone());
+            details
+        });
+        Ok(host)
+    }
+
+    pub async fn revoke(&self, hostname: &str, port: u16) -> AppResult<bool> {
+        let removed = self.hosts.remove(&host_id(hostname, port)).is_some();
+        if removed {
+            self.save().await?;
+            log::info!("Revoked host key for {}:{}", hostname, port);
+        }
+        Ok(removed)
+    }
+
+    pub fn list(&self) -> Vec<KnownHost> {
+        let mut hosts: Vec<KnownHost> = self.hosts.iter().map(|entry| entry.value().clone()).collect();
+        hosts.sort_by(|a, b| a.hostname.cmp(&b.hostname).then(a.port.cmp(&b.port)));
+        hosts
+    }
+
+    async fn save(&self) -> AppResult<()> {
+        let Some(path) = &self.storage_path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let content = serde_json::to_string_pretty(&self.list())?;
+        tokio::fs::write(path, content).await?;
+        Ok(())
+    }
+}
+
+impl Default for KnownHostsStore {
+    fn default() -> Self {
+        Self::new(HostKeyPolicy::AcceptNew)
+    }
+}
+
+fn host_id(hostname: &str, port: u16) -> String {
+    format!("{}:{}", hostname.to_lowercase(), port)
+}
+
+// OpenSSH style SHA256 fingerprint
+pub(crate) fn fingerprint(key: &[u8]) -> String {
+    let digest = Sha256::digest(key);
+    format!("SHA256:{}", general_purpose::STANDARD_NO_PAD.encode(digest))
+}
+
+fn key_type_name(key_type: HostKeyType) -> &'static str {
+    match key_type {
+        HostKeyType::Rsa => "ssh-rsa",
+        HostKeyType::Dss => "ssh-dss",
+        HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+        HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+        HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+        HostKeyType::Ed25519 => "ssh-ed25519",
+        HostKeyType::Unknown => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_format() {
+        let fp = fingerprint(b"test-key");
+        assert!(fp.starts_with("SHA256:"));
+        assert!(!fp.ends_with('='));
+    }
+
+    #[test]
+    fn test_host_id_is_case_insensitive() {
+        assert_eq!(host_id("Example.COM", 22), host_id("example.com", 22));
+        assert_ne!(host_id("example.com", 22), host_id("example.com", 2222));
+    }
+
+    #[tokio::test]
+    async fn test_trust_requires_pending_key() {
+        let store = KnownHostsStore::new(HostKeyPolicy::Ask);
+        store.pending.insert(host_id("example.com", 22), KnownHost {
+            hostname: "example.com".to_string(),
+            port: 22,
+            key_type: "ssh-ed25519".to_string(),
+            fingerprint: "SHA256:abc".to_string(),
+            key: "a2V5".to_string(),
+            added_at: Utc::now(),
+        });
+
+        assert!(store.trust("example.com", 22, "SHA256:other").await.is_err());
+        assert!(store.trust("example.com", 22, "SHA256:abc").await.is_ok());
+        assert_eq!(store.list().len(), 1);
+        assert!(store.revoke("example.com", 22).await.unwrap());
+        assert!(store.list().is_empty());
+    }
+}
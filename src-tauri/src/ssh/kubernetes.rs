@@ -0,0 +1,228 @@
+use super::shell_quote;
+use crate::types::{AppError, AppResult, CommandExecResult, KubeContext, KubeNamespace, KubePod, KubeScope};
+use serde_json::Value;
+
+const DEFAULT_LOG_LINES: u32 = 100;
+const MAX_LOG_LINES: u32 = 5000;
+const EXIT_NOT_FOUND: i32 = 127;
+// Used when the caller doesn't pick a command to exec into the pod
+const DEFAULT_EXEC_COMMAND: &str = "command -v bash >/dev/null && exec bash || exec sh";
+
+// kubectl plus the scope's kubeconfig and context. Names like EKS ARNs contain
+// ':' and '/', so everything is quoted.
+fn kubectl(scope: &KubeScope) -> String {
+    let mut command = "kubectl".to_string();
+    if let Some(kubeconfig) = scope.kubeconfig.as_deref().filter(|path| !path.is_empty()) {
+        command.push_str(&format!(" --kubeconfig {}", shell_quote(kubeconfig)));
+    }
+    if let Some(context) = scope.context.as_deref().filter(|context| !context.is_empty()) {
+        command.push_str(&format!(" --context {}", shell_quote(context)));
+    }
+    command
+}
+
+fn namespace_flag(scope: &KubeScope) -> String {
+    match scope.namespace.as_deref().filter(|namespace| !namespace.is_empty()) {
+        Some(namespace) => format!(" -n {}", shell_quote(namespace)),
+        None => String::new(),
+    }
+}
+
+fn container_flag(container: Option<&str>) -> String {
+    match container.filter(|container| !container.is_empty()) {
+        Some(container) => format!(" -c {}", shell_quote(container)),
+        None => String::new(),
+    }
+}
+
+// The merged view, so KUBECONFIG with several files works like it does for kubectl
+pub fn contexts_command(scope: &KubeScope) -> String {
+    let scope = KubeScope {
+        context: None,
+        ..scope.clone()
+    };
+    format!("{} config view -o json", kubectl(&scope))
+}
+
+pub fn namespaces_command(scope: &KubeScope) -> String {
+    format!("{} get namespaces -o json", kubectl(scope))
+}
+
+pub fn pods_command(scope: &KubeScope) -> String {
+    format!("{} get pods{} -o json", kubectl(scope), namespace_flag(scope))
+}
+
+pub fn logs_command(scope: &KubeScope, pod: &str, container: Option<&str>, lines: Option<u32>) -> AppResult<String> {
+    validate_pod(pod)?;
+    let lines = lines.unwrap_or(DEFAULT_LOG_LINES).min(MAX_LOG_LINES);
+    Ok(format!(
+        "{} logs -f --tail {}{}{} {} 2>&1",
+        kubectl(scope), lines, namespace_flag(scope), container_flag(container), shell_quote(pod)
+    ))
+}
+
+// Replaces the login shell, so leaving the pod ends the session
+pub fn exec_command(scope: &KubeScope, pod: &str, container: Option<&str>, command: Option<&str>) -> AppResult<String> {
+    validate_pod(pod)?;
+    let command = command.filter(|command| !command.trim().is_empty()).unwrap_or(DEFAULT_EXEC_COMMAND);
+    Ok(format!(
+        "exec {} exec -it{}{} {} -- sh -c {}",
+        kubectl(scope), namespace_flag(scope), container_flag(container), shell_quote(pod), shell_quote(command)
+    ))
+}
+
+fn validate_pod(pod: &str) -> AppResult<()> {
+    if pod.is_empty() || pod.starts_with('-') {
+        return Err(AppError::ValidationError(format!("Invalid pod name: {}", pod)));
+    }
+    Ok(())
+}
+
+pub fn command_error(result: &CommandExecResult) -> AppError {
+    let stderr = result.stderr.trim();
+    let lower = stderr.to_lowercase();
+    if result.exit_code == EXIT_NOT_FOUND {
+        AppError::OperationFailed("kubectl is not installed on this host".to_string())
+    } else if lower.contains("forbidden") || lower.contains("unauthorized") {
+        AppError::PermissionDenied(stderr.to_string())
+    } else if lower.contains("notfound") || lower.contains("not found") || lower.contains("does not exist") {
+        AppError::NotFound(stderr.to_string())
+    } else {
+        AppError::OperationFailed(format!("kubectl exited with {}: {}", result.exit_code, stderr))
+    }
+}
+
+fn parse_json(output: &str) -> AppResult<Value> {
+    serde_json::from_str(output)
+        .map_err(|e| AppError::OperationFailed(format!("Unexpected kubectl output: {}", e)))
+}
+
+fn string(value: &Value, pointer: &str) -> Option<String> {
+    value.pointer(pointer).and_then(Value::as_str).map(str::to_string)
+}
+
+fn items(value: &Value, key: &str) -> Vec<Value> {
+    value.get(key).and_then(Value::as_array).cloned().unwrap_or_default()
+}
+
+pub fn parse_contexts(output: &str) -> AppResult<Vec<KubeContext>> {
+    let config = parse_json(output)?;
+    let current = string(&config, "/current-context");
+    let clusters = items(&config, "clusters");
+
+    Ok(items(&config, "contexts")
+        .iter()
+        .filter_map(|context| {
+            let name = string(context, "/name")?;
+            let cluster = string(context, "/context/cluster").unwrap_or_default();
+            let server = clusters.iter()
+                .find(|entry| string(entry, "/name").as_deref() == Some(cluster.as_str()))
+                .and_then(|entry| string(entry, "/cluster/server"));
+            Some(KubeContext {
+                current: current.as_deref() == Some(name.as_str()),
+                name,
+                cluster,
+                user: string(context, "/context/user").unwrap_or_default(),
+                namespace: string(context, "/context/namespace"),
+                server,
+            })
+        })
+        .collect())
+}
+
+pub fn parse_namespaces(output: &str) -> AppResult<Vec<KubeNamespace>> {
+    let list = parse_json(output)?;
+    Ok(items(&list, "items")
+        .iter()
+        .filter_map(|namespace| {
+            Some(KubeNamespace {
+                name: string(namespace, "/metadata/name")?,
+                status: string(namespace, "/status/phase").unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+pub fn parse_pods(output: &str) -> AppResult<Vec<KubePod>> {
+    let list = parse_json(output)?;
+    Ok(items(&list, "items")
+        .iter()
+        .filter_map(|pod| {
+            let containers: Vec<String> = pod.pointer("/spec/containers")
+                .and_then(Value::as_array)
+                .map(|containers| containers.iter().filter_map(|container| string(container, "/name")).collect())
+                .unwrap_or_default();
+            let statuses = pod.pointer("/status/containerStatuses").and_then(Value::as_array).cloned().unwrap_or_default();
+            let ready = statuses.iter().filter(|status| status.get("ready").and_then(Value::as_bool) == Some(true)).count();
+            let restarts = statuses.iter()
+                .filter_map(|status| status.get("restartCount").and_then(Value::as_u64))
+                .sum::<u64>();
+
+            Some(KubePod {
+                name: string(pod, "/metadata/name")?,
+                namespace: string(pod, "/metadata/namespace").unwrap_or_default(),
+                phase: string(pod, "/status/phase").unwrap_or_default(),
+                ready: format!("{}/{}", ready, containers.len()),
+                restarts: restarts as u32,
+                node: string(pod, "/spec/nodeName"),
+                containers,
+                created_at: string(pod, "/metadata/creationTimestamp"),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_contexts() {
+        let output = r#"{
+            "current-context": "prod",
+            "clusters": [{"name": "eks-prod", "cluster": {"server": "https://api.prod.example.com"}}],
+            "contexts": [
+                {"name": "prod", "context": {"cluster": "eks-prod", "user": "admin", "namespace": "web"}},
+                {"name": "kind", "context": {"cluster": "kind-kind", "user": "kind-kind"}}
+            ]
+        }"#;
+        let contexts = parse_contexts(output).unwrap();
+        assert!(contexts[0].current && !contexts[1].current);
+        assert_eq!(contexts[0].server.as_deref(), Some("https://api.prod.example.com"));
+        assert_eq!(contexts[1].namespace, None);
+    }
+
+    #[test]
+    fn test_parse_pods() {
+        let output = r#"{"items": [{
+            "metadata": {"name": "web-7d9f", "namespace": "web", "creationTimestamp": "2024-05-06T10:00:00Z"},
+            "spec": {"nodeName": "node-1", "containers": [{"name": "app"}, {"name": "proxy"}]},
+            "status": {"phase": "Running", "containerStatuses": [
+                {"name": "app", "ready": true, "restartCount": 2},
+                {"name": "proxy", "ready": false, "restartCount": 1}
+            ]}
+        }]}"#;
+        let pods = parse_pods(output).unwrap();
+        assert_eq!((pods[0].ready.as_str(), pods[0].restarts), ("1/2", 3));
+        assert_eq!(pods[0].containers, ["app", "proxy"]);
+        assert!(parse_pods("error: not json").is_err());
+    }
+
+    #[test]
+    fn test_commands_are_quoted() {
+        let scope = KubeScope {
+            kubeconfig: Some("/home/ops/.kube/prod config".to_string()),
+            context: Some("arn:aws:eks:us-east-1:123:cluster/prod".to_string()),
+            namespace: Some("web".to_string()),
+        };
+        assert_eq!(
+            pods_command(&scope),
+            "kubectl --kubeconfig '/home/ops/.kube/prod config' --context 'arn:aws:eks:us-east-1:123:cluster/prod' get pods -n 'web' -o json"
+        );
+        assert!(!contexts_command(&scope).contains("--context"));
+
+        let exec = exec_command(&KubeScope::default(), "web-7d9f", Some("app"), None).unwrap();
+        assert!(exec.starts_with("exec kubectl exec -it -c 'app' 'web-7d9f' -- sh -c "));
+        assert!(logs_command(&scope, "--all", None, None).is_err());
+    }
+}
@@ -1,29 +1,188 @@
+pub mod accounts;
+pub mod approval;
+pub mod auth_prompt;
+pub mod capabilities;
+pub mod charset;
+pub mod completion;
+pub mod docker;
+pub mod environment;
+pub mod exec;
+pub mod forward;
+pub mod history;
+pub mod host_info;
+pub mod keys;
+pub mod known_hosts;
+pub mod kubernetes;
+pub mod motd;
+pub mod multiplexer;
+pub mod network;
+pub mod processes;
+pub mod reader;
+pub mod recent_paths;
+pub mod scp;
+pub mod scrollback;
+pub mod services;
 pub mod session;
 pub mod shell;
-
-use crate::types::{AppError, AppResult, SSHConnectionConfig, SSHSession, SftpFileInfo, AutocompleteSuggestion, SuggestionType};
+pub mod tags;
+pub mod tail;
+pub mod timeline;
+pub mod variables;
+pub mod watch;
+pub mod writer;
+pub mod ws_transport;
+
+use crate::types::{AppError, AppResult, HostInfo, ProcessSignal, RemoteProcess, SignalResult, ServiceAction, ServiceStatus, ServiceUnit, SudoOptions, ContainerAction, DockerContainer, DockerImage, KubeContext, KubeNamespace, KubePod, KubeScope, MultiplexerKind, MultiplexerSession, RemoteEnvironment, SessionNotice, SessionTags, ShellExit, TimelineEvent, TimelineStage, TerminalSignal, SSHConnectionConfig, SessionDefaults, FileTransferProtocol, SSHSession, SftpFileInfo, SftpStat, TerminalCharset, AutocompleteSuggestion, SuggestionType, PortForward, PortForwardKind, PortForwardRequest, CommandExecResult, CredentialUsage, RemoteAccounts, ShellVariables, NetworkInfo, OutputBookmark};
 use crate::{log_connection, log_security};
+use crate::background::BackgroundTasks;
+use crate::retry::{retry, RetryPolicy};
+use crate::webhooks::{self, WebhookEventKind};
 use chrono::{Utc, Duration};
 use dashmap::DashMap;
-use ssh2::Session;
-use std::io::{Read, Write};
+use approval::ConnectApprovals;
+use auth_prompt::AuthPrompts;
+use capabilities::OutputFilter;
+use charset::TerminalCodec;
+use completion::{DirectoryCache, DirectoryEntry};
+use forward::PortForwardManager;
+use history::{CommandHistory, HistoryEntry, InputLine};
+use reader::{ShellEvent, ShellReader};
+use recent_paths::RecentPaths;
+use scrollback::{Scrollback, MAX_SCROLLBACK_BYTES};
+use known_hosts::KnownHostsStore;
+use ssh2::{ErrorCode, Session};
+use tail::{FileTailManager, FollowTarget, TailEvent};
+use timeline::{Step, Timeline};
+use watch::{DirectoryWatchManager, WatchEvent};
+use writer::{SharedCodec, ShellWriter};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::net::TcpStream;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
 use tempfile::NamedTempFile;
-use tokio::time::{interval, Duration as TokioDuration};
+use tokio::time::Duration as TokioDuration;
+
+// History matches shown ahead of the other suggestions
+const MAX_HISTORY_SUGGESTIONS: usize = 5;
+// Files are streamed in pieces this big rather than held in memory
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+const MAX_BOOKMARK_NAME_CHARS: usize = 100;
+// Shell size when neither the client nor the profile gives one
+const DEFAULT_COLS: u16 = 80;
+const DEFAULT_ROWS: u16 = 24;
 
 pub struct SSHManager {
-    sessions: Arc<DashMap<String, Arc<RwLock<SSHSessionData>>>>,
+    sessions: Arc<DashMap<String, Arc<SSHSessionData>>>,
     session_timeout: Duration,
     cleanup_interval: TokioDuration,
+    port_forwards: Arc<PortForwardManager>,
+    file_tails: Arc<FileTailManager>,
+    directory_watches: Arc<DirectoryWatchManager>,
+    known_hosts: Arc<KnownHostsStore>,
+    auth_prompts: Arc<AuthPrompts>,
+    approvals: Arc<ConnectApprovals>,
+    recent_paths: Arc<RecentPaths>,
+    command_history: Arc<CommandHistory>,
+    // Kept outside the session lock so input never waits behind reads or SFTP transfers
+    shell_writers: Arc<DashMap<String, ShellWriter>>,
+    // Every time a stored credential is offered to a server, for the credential store to record
+    credential_uses: broadcast::Sender<CredentialUsage>,
+    background: BackgroundTasks,
 }
 
+// Each part of a session has its own lock, so a long SFTP transfer doesn't hold up shell
+// reads, resizes or metadata lookups. Take at most one at a time.
 pub struct SSHSessionData {
-    pub session: SSHSession,
-    pub ssh_session: Option<Session>,
-    pub shell: Option<ssh2::Channel>,
-    pub sftp: Option<ssh2::Sftp>,
+    pub session: RwLock<SSHSession>,
+    // ssh2 locks the connection per call, so clones of it can be used while this is unlocked
+    pub connection: RwLock<Option<Session>>,
+    // A handle on the connection's socket, for the shell reader to wait on
+    pub socket: parking_lot::Mutex<Option<TcpStream>>,
+    // Exec channels run on a second connection, opened on first use and never blocking
+    pub exec: Mutex<Option<Session>>,
+    pub shell: Mutex<ShellState>,
+    pub sftp: Mutex<SftpState>,
+    pub timeline: Timeline,
+    // Directories listed for path autocomplete
+    pub completions: DirectoryCache,
+    pub variables: parking_lot::Mutex<Option<ShellVariables>>,
+    // Users and groups, to name file owners
+    pub accounts: parking_lot::Mutex<Option<RemoteAccounts>>,
+    // What is being typed into the shell, for its command history
+    pub input_line: parking_lot::Mutex<InputLine>,
+}
+
+#[derive(Default)]
+pub struct ShellState {
+    pub channel: Option<ssh2::Channel>,
+    // Charset conversion for the shell, set up with it and shared with its writer
+    pub codec: Option<SharedCodec>,
+    // Degrades output for clients that declared they can't handle all of it
+    pub filter: Option<OutputFilter>,
+    // Recent shell output, for clients that attach to the session later
+    pub scrollback: Scrollback,
+    pub reader: Option<ShellReader>,
+    // Where the reader sends output, see SSHManager::subscribe_shell
+    pub subscribers: Vec<mpsc::Sender<ShellEvent>>,
+    // The shell's working directory, as last reported with OSC 7
+    pub cwd: Option<String>,
+    // Markers in the output, oldest first; offsets count from when this shell opened
+    pub bookmarks: Vec<OutputBookmark>,
+}
+
+impl ShellState {
+    // Stops the reader and ends every subscription, handing back the channel to close
+    fn detach(&mut self) -> Option<ssh2::Channel> {
+        self.reader = None;
+        self.subscribers.clear();
+        self.cwd = None;
+        self.channel.take()
+    }
+}
+
+#[derive(Default)]
+pub struct SftpState {
+    // Handed out to each operation, which runs without holding this lock
+    pub sftp: Option<Arc<ssh2::Sftp>>,
+    // The directory last listed over SFTP
+    pub path: Option<String>,
+    // The server refused an SFTP channel, so uploads and downloads go over SCP
+    pub scp_fallback: bool,
+}
+
+impl SSHSessionData {
+    fn new(session: SSHSession) -> Self {
+        Self {
+            session: RwLock::new(session),
+            connection: RwLock::new(None),
+            socket: parking_lot::Mutex::new(None),
+            exec: Mutex::new(None),
+            shell: Mutex::new(ShellState::default()),
+            sftp: Mutex::new(SftpState::default()),
+            timeline: Timeline::new(),
+            completions: DirectoryCache::default(),
+            variables: parking_lot::Mutex::new(None),
+            accounts: parking_lot::Mutex::new(None),
+            input_line: parking_lot::Mutex::new(InputLine::default()),
+        }
+    }
+
+    async fn connection(&self) -> AppResult<Session> {
+        self.connection.read().await.clone()
+            .ok_or_else(|| AppError::SSHConnectionFailed("No SSH session available".to_string()))
+    }
+
+    async fn touch(&self) {
+        self.session.write().await.last_activity = Utc::now();
+    }
+
+    // Ends the exec connection along with any command still running on it
+    async fn close_exec(&self, reason: &str) {
+        if let Some(session) = self.exec.lock().await.take() {
+            let _ = session.disconnect(None, reason, None);
+        }
+    }
 }
 
 impl SSHManager {
@@ -32,6 +191,17 @@ impl SSHManager {
             sessions: Arc::new(DashMap::new()),
             session_timeout: Duration::minutes(30), // 30 minute timeout
             cleanup_interval: TokioDuration::from_secs(300), // Check every 5 minutes
+            port_forwards: Arc::new(PortForwardManager::new()),
+            file_tails: Arc::new(FileTailManager::new()),
+            directory_watches: Arc::new(DirectoryWatchManager::new()),
+            known_hosts: Arc::new(KnownHostsStore::default()),
+            auth_prompts: Arc::new(AuthPrompts::new()),
+            approvals: Arc::new(ConnectApprovals::new()),
+            recent_paths: Arc::new(RecentPaths::new()),
+            command_history: Arc::new(CommandHistory::new()),
+            shell_writers: Arc::new(DashMap::new()),
+            credential_uses: broadcast::channel(64).0,
+            background: BackgroundTasks::new(),
         };
 
         // Start cleanup task
@@ -43,19 +213,29 @@ impl SSHManager {
         let sessions = self.sessions.clone();
         let timeout = self.session_timeout;
         let cleanup_interval = self.cleanup_interval;
-
-        tokio::spawn(async move {
-            let mut interval = interval(cleanup_interval);
-
-            loop {
-                interval.tick().await;
-                Self::cleanup_expired_sessions(&sessions, timeout).await;
+        let port_forwards = self.port_forwards.clone();
+        let file_tails = self.file_tails.clone();
+        let directory_watches = self.directory_watches.clone();
+        let shell_writers = self.shell_writers.clone();
+
+        self.background.spawn_periodic("SSH session cleanup", cleanup_interval, move || {
+            let sessions = sessions.clone();
+            let port_forwards = port_forwards.clone();
+            let file_tails = file_tails.clone();
+            let directory_watches = directory_watches.clone();
+            let shell_writers = shell_writers.clone();
+            async move {
+                Self::cleanup_expired_sessions(&sessions, &port_forwards, &file_tails, &directory_watches, &shell_writers, timeout).await;
             }
         });
     }
 
     async fn cleanup_expired_sessions(
-        sessions: &Arc<DashMap<String, Arc<RwLock<SSHSessionData>>>>,
+        sessions: &Arc<DashMap<String, Arc<SSHSessionData>>>,
+        port_forwards: &PortForwardManager,
+        file_tails: &FileTailManager,
+        directory_watches: &DirectoryWatchManager,
+        shell_writers: &DashMap<String, ShellWriter>,
         timeout: Duration,
     ) {
         let now = Utc::now();
@@ -63,30 +243,36 @@ impl SSHManager {
 
         // Find expired sessions
         for entry in sessions.iter() {
-            let session_data = entry.value().read().await;
-            if now.signed_duration_since(session_data.session.last_activity) > timeout {
+            let last_activity = entry.value().session.read().await.last_activity;
+            if now.signed_duration_since(last_activity) > timeout {
                 expired_sessions.push(entry.key().clone());
             }
         }
 
         // Remove expired sessions
         for session_id in expired_sessions {
-            if let Some((_, session_data)) = sessions.remove(&session_id) {
-                let mut data = session_data.write().await;
+            if let Some((_, data)) = sessions.remove(&session_id) {
+                port_forwards.close_session(&session_id);
+                file_tails.close_session(&session_id);
+                directory_watches.close_session(&session_id);
 
                 // Close shell if exists
-                if let Some(mut shell) = data.shell.take() {
+                shell_writers.remove(&session_id);
+                if let Some(mut shell) = data.shell.lock().await.detach() {
                     let _ = shell.close();
                 }
 
-                // Close SFTP if exists
-                if let Some(_sftp) = data.sftp.take() {
-                    // SFTP will be dropped automatically
+                // Close SSH session, ending any SFTP transfer still running
+                if let Some(session) = data.connection.write().await.take() {
+                    let _ = session.disconnect(None, "Session timeout", None);
+                    data.timeline.record(TimelineStage::Disconnect, Some("Session timeout".to_string()), None);
                 }
+                data.socket.lock().take();
+                data.close_exec("Session timeout").await;
 
-                // Close SSH session
-                if let Some(session) = data.ssh_session.take() {
-                    let _ = session.disconnect(None, "Session timeout", None);
+                // Close SFTP if exists
+                if let Some(_sftp) = data.sftp.lock().await.sftp.take() {
+                    // SFTP will be dropped automatically
                 }
 
                 log_connection!("session_expired", &session_id, {
@@ -109,18 +295,16 @@ impl SSHManager {
             connected: false,
             last_activity: Utc::now(),
             created_at: Utc::now(),
-        };
-
-        let session_data = SSHSessionData {
-            session: session.clone(),
-            ssh_session: None,
-            shell: None,
-            sftp: None,
+            host_info: None,
+            environment: None,
+            notices: Vec::new(),
+            shell_exit: None,
+            tags: SessionTags::default(),
         };
 
         self.sessions.insert(
             config.id.clone(),
-            Arc::new(RwLock::new(session_data)),
+            Arc::new(SSHSessionData::new(session.clone())),
         );
 
         log::info!("SSH session created: {}", config.id);
@@ -128,74 +312,203 @@ impl SSHManager {
     }
 
     pub async fn connect(&self, session_id: &str) -> AppResult<()> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
+        let data = self.session_data(session_id)?;
+        let config = data.session.read().await.config.clone();
+
+        // Protected profiles wait for a TOTP code or an approval before anything reaches the host
+        if let Some(approval) = &config.approval {
+            self.approvals.require(session_id, &config, approval).await?;
+        }
 
-        let mut data = session_data.write().await;
-        let config = &data.session.config;
+        // Held while connecting, so concurrent connects to the same session run one at a time
+        let mut connection = data.connection.write().await;
 
         log::info!("Attempting SSH connection to {}@{}:{}", 
                    config.username, config.hostname, config.port);
 
-        // Create TCP connection
-        let tcp = TcpStream::connect(format!("{}:{}", config.hostname, config.port))
-            .map_err(|e| AppError::SSHConnectionFailed(format!("TCP connection failed: {}", e)))?;
+        if data.timeline.has(TimelineStage::Connected) {
+            data.timeline.record(TimelineStage::Reconnect, None, None);
+        }
+        let step = Step::start(TimelineStage::Connected, Some(format!("{}@{}:{}", config.username, config.hostname, config.port)));
+        let established = self.establish_session(session_id, &config, Some(&data.timeline)).await;
+        step.finish(Some(&data.timeline), &established);
+
+        let (session, socket) = match established {
+            Ok(established) => established,
+            Err(e) => {
+                if matches!(e, AppError::SSHAuthenticationFailed(_)) {
+                    webhooks::emit(WebhookEventKind::SessionAuthFailed, Some(session_id), serde_json::json!({
+                        "host": config.hostname,
+                        "port": config.port,
+                        "username": config.username,
+                        "error": e.to_string(),
+                    }));
+                }
+                return Err(e);
+            }
+        };
+
+        // Store the session, leaving exec to reconnect alongside it
+        *connection = Some(session);
+        data.close_exec("Reconnected").await;
+        *data.socket.lock() = Some(socket);
+        drop(connection);
+        {
+            let mut session = data.session.write().await;
+            session.connected = true;
+            session.last_activity = Utc::now();
+        }
+
+        log_connection!("ssh_connected", session_id, {
+            let mut details = std::collections::HashMap::new();
+            details.insert("host".to_string(), config.hostname.clone());
+            details.insert("port".to_string(), config.port.to_string());
+            details.insert("username".to_string(), config.username.clone());
+            details
+        });
+        webhooks::emit(WebhookEventKind::SessionConnected, Some(session_id), serde_json::json!({
+            "host": config.hostname,
+            "port": config.port,
+            "username": config.username,
+        }));
+
+        // A failed probe shouldn't fail the connect
+        if let Err(e) = self.detect_environment(session_id).await {
+            log::warn!("Failed to detect the remote environment for session {}: {}", session_id, e);
+        }
+        if config.collect_host_info {
+            if let Err(e) = self.collect_host_info(session_id).await {
+                log::warn!("Failed to collect host info for session {}: {}", session_id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Open a new TCP connection, through any jump hosts, then handshake and authenticate.
+    // Each hop's session is owned by the tunnel to the next, so a failure further down the
+    // chain closes every hop before it.
+    // Each step goes on the timeline, if there is one.
+    async fn establish_session(&self, session_id: &str, config: &SSHConnectionConfig, timeline: Option<&Timeline>) -> AppResult<(Session, TcpStream)> {
+        let Some((first, rest)) = config.jump_hosts.split_first() else {
+            let tcp = Self::open_transport(config, timeline).await?;
+            return self.start_session(session_id, config, tcp, timeline).await;
+        };
+
+        let tcp = Self::open_transport(first, timeline).await?;
+        let mut hop = self.start_session(session_id, first, tcp, timeline).await?;
+        for next in rest.iter().chain(std::iter::once(config)) {
+            log::debug!("Session {} hopping to {}:{}", session_id, next.hostname, next.port);
+            let step = Step::start(TimelineStage::Tcp, Some(format!("{}:{} through the jump host", next.hostname, next.port)));
+            let tcp = forward::open_jump_tunnel(hop.0, &next.hostname, next.port);
+            step.finish(timeline, &tcp);
+            hop = self.start_session(session_id, next, tcp?, timeline).await?;
+        }
+        Ok(hop)
+    }
+
+    // TCP connection to the host (or its WebSocket gateway), retrying refused or reset
+    // connects while the host comes up
+    async fn open_transport(config: &SSHConnectionConfig, timeline: Option<&Timeline>) -> AppResult<TcpStream> {
+        let target = format!("{}:{}", config.hostname, config.port);
+        if let Some(url) = &config.websocket_url {
+            // The gateway resolves the host itself
+            let step = Step::start(TimelineStage::Tcp, Some(format!("{} through the WebSocket gateway", target)));
+            let tcp = retry(&RetryPolicy::connect(), "WebSocket connect", || ws_transport::connect(url)).await;
+            step.finish(timeline, &tcp);
+            return tcp;
+        }
+
+        // Resolved once up front, so the lookup's share of a slow connect shows on its own
+        let step = Step::start(TimelineStage::Dns, Some(target.clone()));
+        let addresses = tokio::net::lookup_host((config.hostname.as_str(), config.port)).await
+            .map(|addresses| addresses.collect::<Vec<_>>())
+            .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to resolve {}: {}", config.hostname, e)));
+        step.finish(timeline, &addresses);
+        let addresses = addresses?;
+
+        let step = Step::start(TimelineStage::Tcp, Some(target));
+        let tcp = retry(&RetryPolicy::connect(), "TCP connect", || async {
+            TcpStream::connect(&addresses[..])
+                .map_err(|e| AppError::SSHConnectionFailed(format!("TCP connection failed: {}", e)))
+        }).await;
+        step.finish(timeline, &tcp);
+        tcp
+    }
+
+    // Handshake, verify the host key and authenticate over an open connection. Returns the
+    // session along with a handle on its socket.
+    async fn start_session(&self, session_id: &str, config: &SSHConnectionConfig, tcp: TcpStream, timeline: Option<&Timeline>) -> AppResult<(Session, TcpStream)> {
+        let target = format!("{}:{}", config.hostname, config.port);
 
         // Create SSH session
         let mut session = Session::new()
             .map_err(|e| AppError::SSHConnectionFailed(format!("SSH session creation failed: {}", e)))?;
 
+        let socket = tcp.try_clone()?;
         session.set_tcp_stream(tcp);
-        session.handshake()
-            .map_err(|e| AppError::SSHConnectionFailed(format!("SSH handshake failed: {}", e)))?;
+        let step = Step::start(TimelineStage::Handshake, Some(target.clone()));
+        let handshake = session.handshake()
+            .map_err(|e| AppError::SSHConnectionFailed(format!("SSH handshake failed: {}", e)));
+        step.finish(timeline, &handshake);
+        handshake?;
+
+        // Verify the host key before sending any credentials
+        let step = Step::start(TimelineStage::HostKey, Some(target.clone()));
+        let verified = self.known_hosts.verify(session_id, &config.hostname, config.port, &session).await;
+        step.finish(timeline, &verified);
+        verified?;
 
         // Authenticate
-        self.authenticate(&mut session, config).await?;
-
-        // Clone config values before mutating data
-        let hostname = config.hostname.clone();
-        let port = config.port;
-        let username = config.username.clone();
-
-        // Store the session
-        data.ssh_session = Some(session);
-        data.session.connected = true;
-        data.session.last_activity = Utc::now();
-
-        log_connection!("ssh_connected", session_id, {
-            let mut details = std::collections::HashMap::new();
-            details.insert("host".to_string(), hostname);
-            details.insert("port".to_string(), port.to_string());
-            details.insert("username".to_string(), username);
-            details
-        });
+        let step = Step::start(TimelineStage::Auth, Some(format!("{}@{}", config.username, target)));
+        let authenticated = self.authenticate(session_id, &mut session, config).await;
+        step.finish(timeline, &authenticated);
+        self.record_credential_use(session_id, config, authenticated.is_ok());
+        authenticated?;
 
-        Ok(())
+        Ok((session, socket))
     }
 
     pub async fn disconnect(&self, session_id: &str) -> AppResult<()> {
-        if let Some(session_data) = self.sessions.get(session_id) {
-            let mut data = session_data.write().await;
+        if let Ok(data) = self.session_data(session_id) {
+            // Close port forwards, file follows and directory watches opened for this session
+            self.port_forwards.close_session(session_id);
+            self.file_tails.close_session(session_id);
+            self.directory_watches.close_session(session_id);
+            self.auth_prompts.cancel_session(session_id);
+            self.approvals.cancel_session(session_id);
 
             // Close shell if exists
-            if let Some(mut shell) = data.shell.take() {
+            self.shell_writers.remove(session_id);
+            if let Some(mut shell) = data.shell.lock().await.detach() {
                 let _ = shell.close();
                 log::debug!("Shell closed for session: {}", session_id);
             }
 
+            // Close SSH session, ending any SFTP transfer still running
+            if let Some(session) = data.connection.write().await.take() {
+                let _ = session.disconnect(None, "Client disconnecting", None);
+                log::debug!("SSH connection closed for session: {}", session_id);
+            }
+            data.socket.lock().take();
+            data.close_exec("Client disconnecting").await;
+
             // Close SFTP if exists
-            if let Some(_sftp) = data.sftp.take() {
+            if let Some(_sftp) = data.sftp.lock().await.sftp.take() {
                 // SFTP will be dropped automatically
                 log::debug!("SFTP session closed for session: {}", session_id);
             }
 
-            // Close SSH session
-            if let Some(session) = data.ssh_session.take() {
-                let _ = session.disconnect(None, "Client disconnecting", None);
-                log::debug!("SSH connection closed for session: {}", session_id);
+            let mut session = data.session.write().await;
+            if session.connected {
+                data.timeline.record(TimelineStage::Disconnect, Some("Client disconnected".to_string()), None);
+                webhooks::emit(WebhookEventKind::SessionDisconnected, Some(session_id), serde_json::json!({
+                    "host": session.config.hostname,
+                    "port": session.config.port,
+                    "username": session.config.username,
+                }));
             }
-
-            data.session.connected = false;
+            session.connected = false;
             log::info!("SSH session disconnected: {}", session_id);
         }
 
@@ -216,7 +529,11 @@ impl SSHManager {
         }
 
         // Clear all sessions
+        self.port_forwards.close_all();
+        self.file_tails.close_all();
+        self.directory_watches.close_all();
         self.sessions.clear();
+        self.background.shutdown();
 
         log::info!("SSH manager shutdown complete");
         Ok(())
@@ -227,363 +544,1619 @@ impl SSHManager {
     }
 
     pub async fn get_session_info(&self, session_id: &str) -> AppResult<(bool, bool, bool)> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
-
-        let data = session_data.read().await;
-        Ok((
-            data.ssh_session.is_some(),
-            data.shell.is_some(),
-            data.sftp.is_some(),
-        ))
+        let data = self.session_data(session_id)?;
+
+        let connected = data.connection.read().await.is_some();
+        // The shell writer goes with the shell, and unlike the shell lock isn't held by reads
+        let has_shell = self.shell_writers.contains_key(session_id);
+        // A busy SFTP lock means a channel is being handed out, so it's there or about to be
+        let has_sftp = data.sftp.try_lock().map_or(true, |sftp| sftp.sftp.is_some());
+        Ok((connected, has_shell, has_sftp))
     }
 
-    pub async fn create_shell(&self, session_id: &str, cols: u16, rows: u16) -> AppResult<()> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
+    // A size left out comes from the profile's defaults
+    pub async fn create_shell(&self, session_id: &str, cols: Option<u16>, rows: Option<u16>) -> AppResult<()> {
+        let data = self.session_data(session_id)?;
+        let (cols, rows) = {
+            let defaults = &data.session.read().await.config.defaults;
+            (cols.or(defaults.cols).unwrap_or(DEFAULT_COLS), rows.or(defaults.rows).unwrap_or(DEFAULT_ROWS))
+        };
+        let step = Step::start(TimelineStage::Shell, Some(format!("{}x{}", cols, rows)));
+        let opened = self.open_shell(session_id, &data, cols, rows).await;
+        step.finish(Some(&data.timeline), &opened);
+        opened
+    }
 
-        let mut data = session_data.write().await;
-        
-        let session = data.ssh_session.as_mut()
-            .ok_or_else(|| AppError::SSHConnectionFailed("No SSH session available".to_string()))?;
+    async fn open_shell(&self, session_id: &str, data: &Arc<SSHSessionData>, cols: u16, rows: u16) -> AppResult<()> {
+        let session = data.connection().await?;
+        let (terminal, defaults) = {
+            let config = &data.session.read().await.config;
+            (config.terminal.clone(), config.defaults.clone())
+        };
 
         let mut channel = session.channel_session()
             .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to create channel: {}", e)))?;
 
-        channel.request_pty("xterm-256color", None, Some((cols as u32, rows as u32, 0, 0)))
+        for (name, value) in capabilities::environment(terminal.as_ref()) {
+            // Most servers only accept LANG and LC_* unless AcceptEnv says otherwise
+            if let Err(e) = channel.setenv(name, value) {
+                log::debug!("Server refused {} for session {}: {}", name, session_id, e);
+            }
+        }
+
+        channel.request_pty(capabilities::term(terminal.as_ref(), defaults.term.as_deref()), None, Some((cols as u32, rows as u32, 0, 0)))
             .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to request PTY: {}", e)))?;
 
         channel.shell()
             .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to start shell: {}", e)))?;
+        // The reader only reads stdout, and only when something is buffered for it
+        channel.handle_extended_data(ssh2::ExtendedData::Merge)
+            .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to merge shell stderr: {}", e)))?;
+        let socket = data.socket.lock().as_ref()
+            .ok_or_else(|| AppError::SSHConnectionFailed("No SSH session available".to_string()))?
+            .try_clone()?;
+
+        let codec = {
+            let session = data.session.read().await;
+            let locale = session.environment.as_ref().and_then(|environment| environment.locale.as_deref());
+            TerminalCodec::new(session.config.charset.as_deref(), locale)?
+        };
+        log::debug!("Shell for session {} uses charset {}", session_id, codec.name());
+        let codec = Arc::new(parking_lot::Mutex::new(codec));
+
+        // Replacing an earlier shell's writer stops it
+        let writer = ShellWriter::spawn(session_id, channel.stream(0), codec.clone())?;
+        self.shell_writers.insert(session_id.to_string(), writer);
 
-        data.shell = Some(channel);
-        data.session.last_activity = Utc::now();
+        {
+            // Subscribers of an earlier shell see it end; the new reader starts once the
+            // shell is set up, as it waits for this lock
+            let mut shell = data.shell.lock().await;
+            shell.detach();
+            shell.reader = Some(ShellReader::spawn(session_id, channel.stream(0), socket, data.clone())?);
+            shell.channel = Some(channel);
+            shell.codec = Some(codec);
+            shell.filter = OutputFilter::new(terminal.as_ref());
+            shell.scrollback.clear();
+            shell.scrollback.set_limit(defaults.scrollback_bytes);
+            shell.bookmarks.clear();
+        }
+        let mut session = data.session.write().await;
+        session.shell_exit = None;
+        session.last_activity = Utc::now();
 
         log::info!("Shell created for session: {}", session_id);
         Ok(())
     }
 
+    // Queues the input for the shell's writer; only the activity time needs a lock, and that
+    // is skipped while the metadata is busy
     pub async fn write_to_shell(&self, session_id: &str, input: &str) -> AppResult<()> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
+        let data = self.session_data(session_id)?;
 
-        let mut data = session_data.write().await;
-        
-        if let Some(shell) = data.shell.as_mut() {
-            shell.write(input.as_bytes())
-                .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to write to shell: {}", e)))?;
-            
-            data.session.last_activity = Utc::now();
-        }
+        // No shell, nothing to write to
+        let Some(writer) = self.shell_writers.get(session_id) else {
+            return Ok(());
+        };
+        writer.send(input)?;
+        drop(writer);
 
+        if let Ok(mut session) = data.session.try_write() {
+            session.last_activity = Utc::now();
+        }
+        self.record_commands(&data, input).await;
         Ok(())
     }
 
-    #[allow(dead_code)]
-    pub async fn read_from_shell(&self, session_id: &str) -> AppResult<Option<String>> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
-
-        let mut data = session_data.write().await;
-        
-        if let Some(shell) = data.shell.as_mut() {
-            let mut buffer = [0; 4096];
-            match shell.read(&mut buffer) {
-                Ok(0) => Ok(None), // EOF
-                Ok(n) => {
-                    data.session.last_activity = Utc::now();
-                    Ok(Some(String::from_utf8_lossy(&buffer[..n]).to_string()))
-                }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
-                Err(e) => Err(AppError::SSHConnectionFailed(format!("Failed to read from shell: {}", e))),
+    // Keep the lines entered, except answers to password prompts, in the host's history
+    async fn record_commands(&self, data: &SSHSessionData, input: &str) {
+        let lines = data.input_line.lock().feed(input);
+        if lines.is_empty() {
+            return;
+        }
+        if history::is_secret_prompt(&data.shell.lock().await.scrollback.last_line()) {
+            return;
+        }
+        let host = recent_paths::host_key(&data.session.read().await.config);
+        for line in lines {
+            if let Err(e) = self.command_history.record(&host, &line).await {
+                log::warn!("Failed to save command history: {}", e);
             }
-        } else {
-            Ok(None)
         }
     }
 
-    pub async fn resize_shell(&self, session_id: &str, cols: u16, rows: u16) -> AppResult<()> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
+    // Commands entered before on the session's host, best ranked first
+    pub async fn command_history(&self, session_id: &str, prefix: &str, limit: usize) -> AppResult<Vec<HistoryEntry>> {
+        let data = self.session_data(session_id)?;
+        let host = recent_paths::host_key(&data.session.read().await.config);
+        Ok(self.command_history.search(&host, prefix, limit))
+    }
 
-        let mut data = session_data.write().await;
-        
-        if let Some(shell) = data.shell.as_mut() {
-            shell.request_pty_size(cols as u32, rows as u32, Some(0), Some(0))
-                .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to resize shell: {}", e)))?;
-            
-            data.session.last_activity = Utc::now();
-        }
+    pub async fn clear_command_history(&self, session_id: &str) -> AppResult<()> {
+        let data = self.session_data(session_id)?;
+        let host = recent_paths::host_key(&data.session.read().await.config);
+        self.command_history.clear(&host).await
+    }
 
-        Ok(())
+    pub fn set_command_history(&mut self, command_history: Arc<CommandHistory>) {
+        self.command_history = command_history;
     }
 
-    #[allow(dead_code)]
-    pub async fn get_session(&self, session_id: &str) -> AppResult<SSHSession> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
+    // What the shell has printed so far, up to the scrollback limit, and its output from
+    // there on. The receiver ends once the shell is closed or replaced.
+    pub async fn subscribe_shell(&self, session_id: &str) -> AppResult<(String, mpsc::Receiver<ShellEvent>)> {
+        let data = self.session_data(session_id)?;
 
-        let data = session_data.read().await;
-        Ok(data.session.clone())
+        let mut shell = data.shell.lock().await;
+        if shell.reader.is_none() {
+            return Err(AppError::SSHConnectionFailed("No shell open for this session".to_string()));
+        }
+        let (sender, receiver) = mpsc::channel(reader::SUBSCRIBER_BUFFER);
+        shell.subscribers.push(sender);
+        Ok((shell.scrollback.contents(), receiver))
     }
 
-    pub async fn list_sessions(&self) -> Vec<SSHSession> {
-        let mut sessions = Vec::new();
-        for entry in self.sessions.iter() {
-            if let Ok(data) = entry.value().try_read() {
-                sessions.push(data.session.clone());
-            }
+    // Interrupt, suspend or quit the shell's foreground job without the client needing Ctrl
+    // keys, or send a break
+    pub async fn signal_shell(&self, session_id: &str, signal: TerminalSignal) -> AppResult<()> {
+        let data = self.session_data(session_id)?;
+
+        // The tty's line discipline signals the foreground process group, unlike the SSH
+        // signal request, which OpenSSH only delivers to the shell itself. Queued behind
+        // earlier input, like the keypress would be.
+        if let Some(byte) = signal.control_char() {
+            let writer = self.shell_writers.get(session_id)
+                .ok_or_else(|| AppError::SSHConnectionFailed("No shell open for this session".to_string()))?;
+            return writer.send(&char::from(byte).to_string());
         }
-        sessions
-    }
 
-    #[allow(dead_code)]
-    pub async fn remove_session(&self, session_id: &str) -> AppResult<()> {
-        self.disconnect(session_id).await?;
-        self.sessions.remove(session_id);
-        log::info!("SSH session removed: {}", session_id);
+        {
+            let mut shell = data.shell.lock().await;
+            let channel = shell.channel.as_mut()
+                .ok_or_else(|| AppError::SSHConnectionFailed("No shell open for this session".to_string()))?;
+            // An empty string is a zero break-length, i.e. the server's default
+            channel.process_startup("break", Some(""))
+                .map_err(|e| AppError::OperationFailed(format!("Server refused the break request: {}", e)))?;
+        }
+
+        data.touch().await;
         Ok(())
     }
 
-    fn validate_config(&self, config: &SSHConnectionConfig) -> AppResult<()> {
-        if config.hostname.is_empty() {
-            return Err(AppError::InvalidConfiguration("Hostname cannot be empty".to_string()));
-        }
-        if config.username.is_empty() {
-            return Err(AppError::InvalidConfiguration("Username cannot be empty".to_string()));
-        }
-        if config.port == 0 {
-            return Err(AppError::InvalidConfiguration("Port number cannot be 0".to_string()));
+    // Once the shell has hit EOF (exit typed, or the channel closed), close it and keep
+    // its exit status on the session. None while the shell is still running.
+    pub async fn close_exited_shell(&self, session_id: &str) -> AppResult<Option<ShellExit>> {
+        let data = self.session_data(session_id)?;
+
+        let mut state = data.shell.lock().await;
+        if !state.channel.as_ref().is_some_and(|shell| shell.eof()) {
+            return Ok(None);
         }
-        if config.password.is_none() && config.private_key.is_none() {
-            return Err(AppError::InvalidConfiguration("Either password or private key must be provided".to_string()));
+        let Some(mut shell) = state.detach() else {
+            return Ok(None);
+        };
+        state.codec = None;
+        drop(state);
+        self.shell_writers.remove(session_id);
+
+        // The exit status arrives before the channel closes
+        if let Err(e) = shell.close().and_then(|_| shell.wait_close()) {
+            log::debug!("Failed to close the shell channel of session {}: {}", session_id, e);
         }
-        Ok(())
+        let exit = ShellExit {
+            exit_status: shell.exit_status().ok(),
+            exit_signal: shell.exit_signal().ok().and_then(|signal| signal.exit_signal),
+            closed_at: Utc::now(),
+        };
+        log::info!(
+            "Shell of session {} exited with status {:?}{}",
+            session_id,
+            exit.exit_status,
+            exit.exit_signal.as_deref().map(|signal| format!(" (signal {})", signal)).unwrap_or_default(),
+        );
+
+        let status = exit.exit_status.map_or("unknown".to_string(), |status| status.to_string());
+        data.timeline.record(TimelineStage::ShellClosed, Some(format!("Exit status {}", status)), None);
+        data.session.write().await.shell_exit = Some(exit.clone());
+        Ok(Some(exit))
     }
 
-    async fn authenticate(&self, session: &mut Session, config: &SSHConnectionConfig) -> AppResult<()> {
-        if let Some(password) = &config.password {
-            session.userauth_password(&config.username, password)
-                .map_err(|e| AppError::SSHAuthenticationFailed(format!("Password authentication failed: {}", e)))?;
-        } else if let Some(private_key) = &config.private_key {
-            self.authenticate_with_private_key(session, &config.username, private_key, config.passphrase.as_deref()).await?;
-        } else {
-            return Err(AppError::SSHAuthenticationFailed("No authentication method provided".to_string()));
-        }
+    pub async fn resize_shell(&self, session_id: &str, cols: u16, rows: u16) -> AppResult<()> {
+        let data = self.session_data(session_id)?;
 
-        if !session.authenticated() {
-            return Err(AppError::SSHAuthenticationFailed("Authentication failed".to_string()));
+        let resized = match data.shell.lock().await.channel.as_mut() {
+            Some(shell) => {
+                shell.request_pty_size(cols as u32, rows as u32, Some(0), Some(0))
+                    .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to resize shell: {}", e)))?;
+                true
+            }
+            None => false,
+        };
+        if resized {
+            data.touch().await;
         }
 
         Ok(())
     }
 
-    async fn authenticate_with_private_key(
-        &self,
-        session: &mut Session,
-        username: &str,
-        private_key: &str,
-        passphrase: Option<&str>,
-    ) -> AppResult<()> {
-        // Create a temporary file for the private key
-        let mut temp_file = NamedTempFile::new()
-            .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to create temporary key file: {}", e)))?;
+    // Run a command on a separate exec channel, leaving the interactive shell untouched
+    pub async fn exec_command(&self, session_id: &str, command: &str) -> AppResult<CommandExecResult> {
+        self.exec_command_with_input(session_id, command, None).await
+    }
 
-        // Write the private key to the temporary file
-        temp_file.write_all(private_key.as_bytes())
-            .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to write private key to temp file: {}", e)))?;
+    // Same as exec_command, writing `input` to the command's stdin first (e.g. a sudo -S password)
+    pub async fn exec_command_with_input(&self, session_id: &str, command: &str, input: Option<&str>) -> AppResult<CommandExecResult> {
+        if command.trim().is_empty() {
+            return Err(AppError::ValidationError("Command cannot be empty".to_string()));
+        }
 
-        // Ensure the file is written to disk
-        temp_file.flush()
-            .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to flush private key file: {}", e)))?;
+        let data = self.session_data(session_id)?;
+        let session = self.exec_connection(session_id, &data).await?;
+        data.touch().await;
 
-        let temp_path = temp_file.path();
+        let command = command.to_string();
+        let input = input.map(str::to_string);
+        let result = tokio::task::spawn_blocking(move || exec::run(&session, &command, input.as_deref(), exec::EXEC_TIMEOUT))
+            .await
+            .map_err(|e| AppError::InternalError(format!("Exec task failed: {}", e)))?;
 
-        // Set restrictive permissions on the temporary file (Unix-like systems)
-        #[cfg(unix)]
-        {
-            use std::fs;
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(temp_path)
-                .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to get file metadata: {}", e)))?
-                .permissions();
-            perms.set_mode(0o600); // Read/write for owner only
-            fs::set_permissions(temp_path, perms)
-                .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to set file permissions: {}", e)))?;
+        // No channel could be opened, so the next command starts over on a fresh connection
+        if matches!(result, Err(AppError::SSHConnectionFailed(_))) {
+            data.close_exec("Exec connection failed").await;
         }
+        result
+    }
 
-        // Attempt authentication with the private key
-        let result = if let Some(passphrase) = passphrase {
-            session.userauth_pubkey_file(username, None, temp_path, Some(passphrase))
-        } else {
-            session.userauth_pubkey_file(username, None, temp_path, None)
-        };
+    // The connection exec channels run on. It stays non-blocking so commands can be read
+    // from and given up on without stalling, which the shell's connection, shared with SFTP
+    // and the shell reader, can't allow.
+    async fn exec_connection(&self, session_id: &str, data: &SSHSessionData) -> AppResult<Session> {
+        data.connection().await?;
+        let mut exec = data.exec.lock().await;
+        if let Some(session) = exec.as_ref() {
+            return Ok(session.clone());
+        }
 
-        // Clean up: the temporary file will be automatically deleted when temp_file goes out of scope
+        let config = data.session.read().await.config.clone();
+        let (session, _) = self.establish_session(session_id, &config, None).await?;
+        session.set_blocking(false);
+        *exec = Some(session.clone());
+        Ok(session)
+    }
 
-        result.map_err(|e| AppError::SSHAuthenticationFailed(format!("Private key authentication failed: {}", e)))?;
+    // Gather OS, load, memory and disk details and keep them on the session
+    pub async fn collect_host_info(&self, session_id: &str) -> AppResult<HostInfo> {
+        let result = self.exec_command(session_id, host_info::COLLECT_COMMAND).await?;
+        let info = host_info::parse(&result.stdout);
 
-        log_security!("private_key_auth_success", "info", {
-            let mut details = std::collections::HashMap::new();
-            details.insert("username".to_string(), username.to_string());
-            details.insert("auth_method".to_string(), "private_key".to_string());
-            details
-        });
+        self.session_data(session_id)?.session.write().await.host_info = Some(info.clone());
+        Ok(info)
+    }
 
-        Ok(())
+    // Host info collected earlier, if any
+    pub async fn host_info(&self, session_id: &str) -> AppResult<Option<HostInfo>> {
+        let host_info = self.session_data(session_id)?.session.read().await.host_info.clone();
+        Ok(host_info)
     }
 
-    // SFTP operations
-    pub async fn create_sftp(&self, session_id: &str) -> AppResult<()> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
+    // Detect the login shell, OS and locale and keep them on the session
+    pub async fn detect_environment(&self, session_id: &str) -> AppResult<RemoteEnvironment> {
+        let result = self.exec_command(session_id, environment::DETECT_COMMAND).await?;
+        let environment = environment::parse(&result);
 
-        let mut data = session_data.write().await;
+        self.session_data(session_id)?.session.write().await.environment = Some(environment.clone());
+        Ok(environment)
+    }
 
-        if let Some(ssh_session) = &data.ssh_session {
-            let sftp = ssh_session.sftp()
-                .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to create SFTP session: {}", e)))?;
+    pub async fn environment(&self, session_id: &str) -> AppResult<Option<RemoteEnvironment>> {
+        let environment = self.session_data(session_id)?.session.read().await.environment.clone();
+        Ok(environment)
+    }
 
-            data.sftp = Some(sftp);
-            log::info!("SFTP session created for: {}", session_id);
-        } else {
-            return Err(AppError::SSHConnectionFailed("No SSH session available for SFTP".to_string()));
-        }
+    // Collect the login shell's exported variables and aliases and keep them for autocomplete
+    pub async fn collect_variables(&self, session_id: &str) -> AppResult<ShellVariables> {
+        let result = self.exec_command(session_id, variables::COLLECT_COMMAND).await?;
+        let variables = variables::parse(&result.stdout);
 
-        Ok(())
+        *self.session_data(session_id)?.variables.lock() = Some(variables.clone());
+        Ok(variables)
     }
 
-    pub async fn list_directory(&self, session_id: &str, path: &str) -> AppResult<Vec<SftpFileInfo>> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
-
-        let mut data = session_data.write().await;
+    pub fn variables(&self, session_id: &str) -> AppResult<Option<ShellVariables>> {
+        Ok(self.session_data(session_id)?.variables.lock().clone())
+    }
 
-        // Create SFTP session if it doesn't exist
-        if data.sftp.is_none() {
-            if let Some(ssh_session) = &data.ssh_session {
-                let sftp = ssh_session.sftp()
-                    .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to create SFTP session: {}", e)))?;
-                data.sftp = Some(sftp);
-            } else {
-                return Err(AppError::SSHConnectionFailed("No SSH session available for SFTP".to_string()));
-            }
-        }
+    // Read the host's users and groups and keep them for naming file owners
+    pub async fn collect_accounts(&self, session_id: &str) -> AppResult<RemoteAccounts> {
+        let result = self.exec_command(session_id, accounts::COLLECT_COMMAND).await?;
+        let accounts = accounts::parse(&result.stdout);
 
-        if let Some(sftp) = &data.sftp {
-            let entries = sftp.readdir(std::path::Path::new(path))
-                .map_err(|e| AppError::FileOperationFailed(format!("Failed to list directory: {}", e)))?;
+        *self.session_data(session_id)?.accounts.lock() = Some(accounts.clone());
+        Ok(accounts)
+    }
 
-            let mut files = Vec::new();
-            for (path, stat) in entries {
-                let file_info = SftpFileInfo {
-                    name: path.file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("unknown")
-                        .to_string(),
-                    path: path.to_string_lossy().to_string(),
-                    size: stat.size.unwrap_or(0),
-                    is_directory: stat.is_dir(),
-                    modified: stat.mtime.map(|t| t as i64),
-                    permissions: stat.perm.map(|p| format!("{:o}", p)),
-                };
-                files.push(file_info);
-            }
+    pub fn accounts(&self, session_id: &str) -> AppResult<Option<RemoteAccounts>> {
+        Ok(self.session_data(session_id)?.accounts.lock().clone())
+    }
 
-            data.session.last_activity = Utc::now();
-            Ok(files)
-        } else {
-            Err(AppError::FileOperationFailed("SFTP session not available".to_string()))
+    // The accounts for naming owners in a listing. A host that can't run commands, like an
+    // SFTP-only one, is remembered as having none, so each listing doesn't try again.
+    async fn owner_names(&self, session_id: &str) -> Option<RemoteAccounts> {
+        if let Ok(Some(accounts)) = self.accounts(session_id) {
+            return Some(accounts);
         }
+        let error = match tokio::time::timeout(accounts::LOOKUP_TIMEOUT, self.collect_accounts(session_id)).await {
+            Ok(Ok(accounts)) => return Some(accounts),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+        log::debug!("Looking up accounts for {} failed: {}", session_id, error);
+        let none = RemoteAccounts { users: Vec::new(), groups: Vec::new(), collected_at: Utc::now() };
+        *self.session_data(session_id).ok()?.accounts.lock() = Some(none.clone());
+        Some(none)
     }
 
-    pub async fn download_file(&self, session_id: &str, remote_path: &str) -> AppResult<Vec<u8>> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
+    // Each step of connecting, and what happened to the session since, oldest first
+    pub fn timeline(&self, session_id: &str) -> AppResult<Vec<TimelineEvent>> {
+        Ok(self.session_data(session_id)?.timeline.events())
+    }
 
-        let mut data = session_data.write().await;
+    // Output read from the shell so far, up to the scrollback limit
+    pub async fn scrollback(&self, session_id: &str) -> AppResult<String> {
+        let data = self.session_data(session_id)?;
+        let contents = data.shell.lock().await.scrollback.contents();
+        Ok(contents)
+    }
 
-        // Create SFTP session if it doesn't exist
-        if data.sftp.is_none() {
-            if let Some(ssh_session) = &data.ssh_session {
-                let sftp = ssh_session.sftp()
-                    .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to create SFTP session: {}", e)))?;
-                data.sftp = Some(sftp);
-            } else {
-                return Err(AppError::SSHConnectionFailed("No SSH session available for SFTP".to_string()));
-            }
+    // Mark where the shell's output has got to, to come back to later
+    pub async fn add_bookmark(&self, session_id: &str, name: &str) -> AppResult<OutputBookmark> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_BOOKMARK_NAME_CHARS {
+            return Err(AppError::ValidationError(format!("Bookmark names need 1 to {} characters", MAX_BOOKMARK_NAME_CHARS)));
+        }
+        let data = self.session_data(session_id)?;
+        let mut shell = data.shell.lock().await;
+        if shell.reader.is_none() {
+            return Err(AppError::SSHConnectionFailed("No shell open for this session".to_string()));
         }
 
-        if let Some(sftp) = &data.sftp {
-            let mut remote_file = sftp.open(std::path::Path::new(remote_path))
-                .map_err(|e| AppError::FileOperationFailed(format!("Failed to open remote file: {}", e)))?;
+        let bookmark = OutputBookmark {
+            id: uuid::Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            name: name.to_string(),
+            offset: shell.scrollback.offset(),
+            timestamp: Utc::now(),
+        };
+        shell.bookmarks.push(bookmark.clone());
+        Ok(bookmark)
+    }
 
-            let mut contents = Vec::new();
-            remote_file.read_to_end(&mut contents)
-                .map_err(|e| AppError::FileOperationFailed(format!("Failed to read file: {}", e)))?;
+    pub async fn bookmarks(&self, session_id: &str) -> AppResult<Vec<OutputBookmark>> {
+        let data = self.session_data(session_id)?;
+        let bookmarks = data.shell.lock().await.bookmarks.clone();
+        Ok(bookmarks)
+    }
 
-            data.session.last_activity = Utc::now();
-            Ok(contents)
-        } else {
-            Err(AppError::FileOperationFailed("SFTP session not available".to_string()))
+    pub async fn remove_bookmark(&self, session_id: &str, bookmark_id: &str) -> AppResult<()> {
+        let data = self.session_data(session_id)?;
+        let mut shell = data.shell.lock().await;
+        let count = shell.bookmarks.len();
+        shell.bookmarks.retain(|bookmark| bookmark.id != bookmark_id);
+        if shell.bookmarks.len() == count {
+            return Err(AppError::NotFound(format!("Bookmark {}", bookmark_id)));
         }
+        Ok(())
     }
 
-    pub async fn upload_file(&self, session_id: &str, remote_path: &str, contents: &[u8]) -> AppResult<()> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
+    // The output printed since a bookmark, while the scrollback still holds all of it
+    pub async fn output_since_bookmark(&self, session_id: &str, bookmark_id: &str) -> AppResult<String> {
+        let data = self.session_data(session_id)?;
+        let shell = data.shell.lock().await;
+        let bookmark = shell.bookmarks.iter()
+            .find(|bookmark| bookmark.id == bookmark_id)
+            .ok_or_else(|| AppError::NotFound(format!("Bookmark {}", bookmark_id)))?;
+        shell.scrollback.since(bookmark.offset)
+            .ok_or_else(|| AppError::NotFound(format!("Output at bookmark {} has scrolled out of the scrollback", bookmark.name)))
+    }
 
-        let mut data = session_data.write().await;
+    pub async fn charset(&self, session_id: &str) -> AppResult<TerminalCharset> {
+        let data = self.session_data(session_id)?;
+        let configured = data.session.read().await.config.charset.clone();
+        let active = data.shell.lock().await.codec.as_ref().map(|codec| codec.lock().name().to_string());
+        Ok(TerminalCharset { configured, active })
+    }
 
-        // Create SFTP session if it doesn't exist
-        if data.sftp.is_none() {
-            if let Some(ssh_session) = &data.ssh_session {
-                let sftp = ssh_session.sftp()
-                    .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to create SFTP session: {}", e)))?;
-                data.sftp = Some(sftp);
-            } else {
-                return Err(AppError::SSHConnectionFailed("No SSH session available for SFTP".to_string()));
+    // Switch the charset of a running session; None or "auto" goes back to detection
+    pub async fn set_charset(&self, session_id: &str, charset: Option<String>) -> AppResult<TerminalCharset> {
+        charset::validate(charset.as_deref())?;
+        {
+            let data = self.session_data(session_id)?;
+            let locale = data.session.read().await.environment.as_ref().and_then(|environment| environment.locale.clone());
+            let mut shell = data.shell.lock().await;
+            if shell.channel.is_some() {
+                let codec = TerminalCodec::new(charset.as_deref(), locale.as_deref())?;
+                // In place, so the shell's writer switches too
+                match &shell.codec {
+                    Some(shared) => *shared.lock() = codec,
+                    None => shell.codec = Some(Arc::new(parking_lot::Mutex::new(codec))),
+                }
             }
+            drop(shell);
+            data.session.write().await.config.charset = charset;
         }
+        self.charset(session_id).await
+    }
 
-        if let Some(sftp) = &data.sftp {
-            let mut remote_file = sftp.create(std::path::Path::new(remote_path))
-                .map_err(|e| AppError::FileOperationFailed(format!("Failed to create remote file: {}", e)))?;
+    pub async fn set_notices(&self, session_id: &str, notices: Vec<SessionNotice>) -> AppResult<()> {
+        self.session_data(session_id)?.session.write().await.notices = notices;
+        Ok(())
+    }
 
-            remote_file.write_all(contents)
-                .map_err(|e| AppError::FileOperationFailed(format!("Failed to write file: {}", e)))?;
+    pub async fn set_tags(&self, session_id: &str, tags: SessionTags) -> AppResult<SSHSession> {
+        let tags = tags::normalize(tags)?;
+        let data = self.session_data(session_id)?;
+        let mut session = data.session.write().await;
+        session.tags = tags;
+        Ok(session.clone())
+    }
 
-            data.session.last_activity = Utc::now();
-            Ok(())
-        } else {
-            Err(AppError::FileOperationFailed("SFTP session not available".to_string()))
+    pub async fn list_processes(&self, session_id: &str) -> AppResult<Vec<RemoteProcess>> {
+        let result = self.exec_command(session_id, processes::LIST_COMMAND).await?;
+        if result.exit_code != 0 && result.stdout.trim().is_empty() {
+            return Err(AppError::OperationFailed(format!("ps failed: {}", result.stderr.trim())));
         }
+        Ok(processes::parse_ps(&result.stdout))
     }
 
-    // Terminal autocomplete functionality
+    // Interface addresses and listening sockets, for picking what to forward
+    pub async fn inspect_network(&self, session_id: &str) -> AppResult<NetworkInfo> {
+        let result = self.exec_command(session_id, network::COLLECT_COMMAND).await?;
+        let info = network::parse(&result.stdout);
+        if info.interfaces.is_empty() && info.sockets.is_empty() {
+            return Err(AppError::OperationFailed("Neither ip nor ss gave any output on this host".to_string()));
+        }
+        Ok(info)
+    }
+
+    // Runs as the session's user, so processes owned by others come back as failures
+    pub async fn signal_processes(&self, session_id: &str, pids: &[u32], signal: ProcessSignal) -> AppResult<Vec<SignalResult>> {
+        let command = processes::signal_command(pids, signal)?;
+        let result = self.exec_command(session_id, &command).await?;
+        Ok(processes::parse_signal_output(&result.stdout))
+    }
+
+    pub async fn list_services(&self, session_id: &str) -> AppResult<Vec<ServiceUnit>> {
+        let result = self.exec_command(session_id, services::LIST_COMMAND).await?;
+        if result.stdout.lines().all(|line| line.starts_with("==")) {
+            return Err(AppError::OperationFailed(format!("systemctl unavailable: {}", result.stderr.trim())));
+        }
+        Ok(services::parse_list(&result.stdout))
+    }
+
+    pub async fn service_status(&self, session_id: &str, unit: &str) -> AppResult<ServiceStatus> {
+        let result = self.exec_command(session_id, &services::status_command(unit)?).await?;
+        services::parse_status(&result.stdout)
+    }
+
+    // Run the action, then report where the unit ended up
+    pub async fn service_action(&self, session_id: &str, unit: &str, action: ServiceAction, sudo: &SudoOptions) -> AppResult<ServiceStatus> {
+        let (command, input) = services::action_command(unit, action, sudo)?;
+        let result = self.exec_command_with_input(session_id, &command, input.as_deref()).await?;
+        if result.exit_code != 0 {
+            return Err(services::action_error(&result));
+        }
+        self.service_status(session_id, unit).await
+    }
+
+    // Run a docker CLI command, elevated if asked, and return its stdout
+    async fn docker_exec(&self, session_id: &str, command: &str, sudo: &SudoOptions) -> AppResult<String> {
+        let (command, input) = sudo.wrap(command);
+        let result = self.exec_command_with_input(session_id, &command, input.as_deref()).await?;
+        if result.exit_code != 0 {
+            return Err(docker::command_error(&result));
+        }
+        Ok(result.stdout)
+    }
+
+    pub async fn list_containers(&self, session_id: &str, sudo: &SudoOptions) -> AppResult<Vec<DockerContainer>> {
+        let output = self.docker_exec(session_id, docker::LIST_CONTAINERS, sudo).await?;
+        Ok(docker::parse_containers(&output))
+    }
+
+    pub async fn list_images(&self, session_id: &str, sudo: &SudoOptions) -> AppResult<Vec<DockerImage>> {
+        let output = self.docker_exec(session_id, docker::LIST_IMAGES, sudo).await?;
+        Ok(docker::parse_images(&output))
+    }
+
+    pub async fn container_action(&self, session_id: &str, container: &str, action: ContainerAction, sudo: &SudoOptions) -> AppResult<()> {
+        let (command, input) = docker::action_command(container, action, sudo)?;
+        let result = self.exec_command_with_input(session_id, &command, input.as_deref()).await?;
+        if result.exit_code != 0 {
+            return Err(docker::command_error(&result));
+        }
+        Ok(())
+    }
+
+    // `docker logs -f` as a follow, streamed like a tailed file
+    pub async fn follow_container_logs(&self, session_id: &str, container: &str, lines: Option<u32>, sudo: &SudoOptions) -> AppResult<(String, mpsc::UnboundedReceiver<TailEvent>)> {
+        let (command, input) = docker::logs_command(container, lines, sudo)?;
+        self.follow(session_id, FollowTarget::Command { command, input }).await
+    }
+
+    pub async fn list_multiplexer_sessions(&self, session_id: &str) -> AppResult<Vec<MultiplexerSession>> {
+        let result = self.exec_command(session_id, multiplexer::DETECT_COMMAND).await?;
+        Ok(multiplexer::parse(&result.stdout))
+    }
+
+    // Resume a detached tmux/screen session in the session's interactive shell
+    pub async fn attach_multiplexer(&self, session_id: &str, kind: MultiplexerKind, id: &str, detach_others: bool) -> AppResult<()> {
+        let (_, has_shell, _) = self.get_session_info(session_id).await?;
+        if !has_shell {
+            return Err(AppError::OperationFailed("Session has no shell to attach in".to_string()));
+        }
+        let command = multiplexer::attach_command(kind, id, detach_others)?;
+        self.write_to_shell(session_id, &format!("{}\n", command)).await
+    }
+
+    async fn kubectl_exec(&self, session_id: &str, command: &str) -> AppResult<String> {
+        let result = self.exec_command(session_id, command).await?;
+        if result.exit_code != 0 {
+            return Err(kubernetes::command_error(&result));
+        }
+        Ok(result.stdout)
+    }
+
+    pub async fn kube_contexts(&self, session_id: &str, scope: &KubeScope) -> AppResult<Vec<KubeContext>> {
+        let output = self.kubectl_exec(session_id, &kubernetes::contexts_command(scope)).await?;
+        kubernetes::parse_contexts(&output)
+    }
+
+    pub async fn kube_namespaces(&self, session_id: &str, scope: &KubeScope) -> AppResult<Vec<KubeNamespace>> {
+        let output = self.kubectl_exec(session_id, &kubernetes::namespaces_command(scope)).await?;
+        kubernetes::parse_namespaces(&output)
+    }
+
+    pub async fn kube_pods(&self, session_id: &str, scope: &KubeScope) -> AppResult<Vec<KubePod>> {
+        let output = self.kubectl_exec(session_id, &kubernetes::pods_command(scope)).await?;
+        kubernetes::parse_pods(&output)
+    }
+
+    pub async fn follow_pod_logs(&self, session_id: &str, scope: &KubeScope, pod: &str, container: Option<&str>, lines: Option<u32>) -> AppResult<(String, mpsc::UnboundedReceiver<TailEvent>)> {
+        let command = kubernetes::logs_command(scope, pod, container, lines)?;
+        self.follow(session_id, FollowTarget::Command { command, input: None }).await
+    }
+
+    // Turn the session's shell into a shell inside the pod. Meant for a fresh session
+    // opened for the purpose, since the login shell is replaced.
+    pub async fn open_pod_exec(&self, session_id: &str, scope: &KubeScope, pod: &str, container: Option<&str>, command: Option<&str>) -> AppResult<()> {
+        let (_, has_shell, _) = self.get_session_info(session_id).await?;
+        if !has_shell {
+            return Err(AppError::OperationFailed("Session has no shell to exec from".to_string()));
+        }
+        let command = kubernetes::exec_command(scope, pod, container, command)?;
+        self.write_to_shell(session_id, &format!("{}\n", command)).await
+    }
+
+    // Connect a throwaway session, run one command and hang up. Returns the session ID
+    // the command ran under, which no longer exists once this returns.
+    pub async fn exec_once(&self, config: SSHConnectionConfig, command: &str) -> AppResult<(String, CommandExecResult)> {
+        let session = self.create_session(config).await?;
+        let result = match self.connect(&session.id).await {
+            Ok(()) => self.exec_command(&session.id, command).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = self.remove_session(&session.id).await {
+            log::warn!("Failed to close session {}: {}", session.id, e);
+        }
+        result.map(|result| (session.id, result))
+    }
+
+    pub fn known_hosts(&self) -> Arc<KnownHostsStore> {
+        self.known_hosts.clone()
+    }
+
+    pub fn set_known_hosts(&mut self, known_hosts: Arc<KnownHostsStore>) {
+        self.known_hosts = known_hosts;
+    }
+
+    pub fn auth_prompts(&self) -> Arc<AuthPrompts> {
+        self.auth_prompts.clone()
+    }
+
+    pub fn approvals(&self) -> Arc<ConnectApprovals> {
+        self.approvals.clone()
+    }
+
+    pub fn credential_uses(&self) -> broadcast::Receiver<CredentialUsage> {
+        self.credential_uses.subscribe()
+    }
+
+    // Failed attempts count too: the secret reached the server either way
+    fn record_credential_use(&self, session_id: &str, config: &SSHConnectionConfig, succeeded: bool) {
+        let Some(source) = &config.credential else {
+            return;
+        };
+        let method = if config.password.is_some() { "password" } else { "publickey" };
+        // No one listening just means nothing is audited, e.g. in the server build
+        let _ = self.credential_uses.send(CredentialUsage {
+            key: source.key.clone(),
+            profile_id: source.profile_id.clone(),
+            session_id: session_id.to_string(),
+            hostname: config.hostname.clone(),
+            port: config.port,
+            username: config.username.clone(),
+            local_user: std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok(),
+            method: method.to_string(),
+            succeeded,
+            at: Utc::now(),
+        });
+    }
+
+    pub fn port_forwards(&self) -> Arc<PortForwardManager> {
+        self.port_forwards.clone()
+    }
+
+    pub async fn create_port_forward(&self, request: PortForwardRequest) -> AppResult<PortForward> {
+        PortForwardManager::validate_request(&request)?;
+
+        let data = self.session_data(&request.session_id)?;
+        data.connection().await?;
+        let config = data.session.read().await.config.clone();
+
+        // Forwards run on their own connection so tunnel traffic never blocks the shell or SFTP
+        let (session, _) = self.establish_session(&request.session_id, &config, None).await?;
+        self.port_forwards.start(request, session).await
+    }
+
+    // Stream lines appended to a remote file, on a connection of its own like port forwards
+    pub async fn follow_file(&self, session_id: &str, path: &str, initial_lines: Option<u32>) -> AppResult<(String, mpsc::UnboundedReceiver<TailEvent>)> {
+        let target = FollowTarget::File {
+            path: path.to_string(),
+            initial_lines,
+        };
+        self.follow(session_id, target).await
+    }
+
+    pub async fn follow(&self, session_id: &str, target: FollowTarget) -> AppResult<(String, mpsc::UnboundedReceiver<TailEvent>)> {
+        let data = self.session_data(session_id)?;
+        data.connection().await?;
+        let config = data.session.read().await.config.clone();
+
+        let (session, _) = self.establish_session(session_id, &config, None).await?;
+        self.file_tails.start(session_id, target, session)
+    }
+
+    pub fn stop_following(&self, follow_id: &str) -> AppResult<()> {
+        self.file_tails.stop(follow_id)
+    }
+
+    // Poll a remote directory every interval_secs (5 by default) for added, removed and changed
+    // entries, on a connection of its own so it never waits behind transfers
+    pub async fn watch_directory(&self, session_id: &str, path: &str, interval_secs: Option<u64>) -> AppResult<(String, mpsc::UnboundedReceiver<WatchEvent>)> {
+        if path.trim().is_empty() {
+            return Err(AppError::ValidationError("Nothing to watch".to_string()));
+        }
+        let interval = DirectoryWatchManager::interval(interval_secs)?;
+
+        let data = self.session_data(session_id)?;
+        data.connection().await?;
+        let config = data.session.read().await.config.clone();
+
+        let (session, _) = self.establish_session(session_id, &config, None).await?;
+        self.directory_watches.start(session_id, path.trim(), interval, session)
+    }
+
+    pub fn unwatch_directory(&self, watch_id: &str) -> AppResult<()> {
+        self.directory_watches.stop(watch_id)
+    }
+
+    // Forward local_port on the loopback interface to remote_host:remote_port, as seen from the server
+    pub async fn create_local_forward(&self, session_id: &str, local_port: u16, remote_host: &str, remote_port: u16) -> AppResult<PortForward> {
+        self.create_port_forward(PortForwardRequest {
+            session_id: session_id.to_string(),
+            kind: PortForwardKind::Local,
+            bind_address: None,
+            bind_port: local_port,
+            target_host: Some(remote_host.to_string()),
+            target_port: Some(remote_port),
+        }).await
+    }
+
+    // Listen on remote_port on the server and tunnel each connection back to local_host:local_port
+    // here. Binds the server's loopback interface, or every interface if its GatewayPorts allows.
+    pub async fn create_remote_forward(&self, session_id: &str, remote_port: u16, local_host: &str, local_port: u16) -> AppResult<PortForward> {
+        self.create_port_forward(PortForwardRequest {
+            session_id: session_id.to_string(),
+            kind: PortForwardKind::Remote,
+            bind_address: None,
+            bind_port: remote_port,
+            target_host: Some(local_host.to_string()),
+            target_port: Some(local_port),
+        }).await
+    }
+
+    pub fn list_port_forwards(&self, session_id: Option<&str>) -> Vec<PortForward> {
+        self.port_forwards.list(session_id)
+    }
+
+    pub fn close_port_forward(&self, forward_id: &str) -> AppResult<()> {
+        self.port_forwards.close(forward_id)
+    }
+
+    #[allow(dead_code)]
+    pub async fn get_session(&self, session_id: &str) -> AppResult<SSHSession> {
+        let session = self.session_data(session_id)?.session.read().await.clone();
+        Ok(session)
+    }
+
+    pub async fn list_sessions(&self) -> Vec<SSHSession> {
+        let mut sessions = Vec::new();
+        for entry in self.sessions.iter() {
+            if let Ok(session) = entry.value().session.try_read() {
+                sessions.push(session.clone());
+            }
+        }
+        sessions
+    }
+
+    pub async fn remove_session(&self, session_id: &str) -> AppResult<()> {
+        self.disconnect(session_id).await?;
+        self.sessions.remove(session_id);
+        log::info!("SSH session removed: {}", session_id);
+        Ok(())
+    }
+
+    // The session's state, cloned out of the map so no map shard stays locked across awaits
+    fn session_data(&self, session_id: &str) -> AppResult<Arc<SSHSessionData>> {
+        self.sessions.get(session_id)
+            .map(|entry| entry.value().clone())
+            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))
+    }
+
+    fn validate_config(&self, config: &SSHConnectionConfig) -> AppResult<()> {
+        if config.hostname.is_empty() {
+            return Err(AppError::InvalidConfiguration("Hostname cannot be empty".to_string()));
+        }
+        if config.username.is_empty() {
+            return Err(AppError::InvalidConfiguration("Username cannot be empty".to_string()));
+        }
+        if config.port == 0 {
+            return Err(AppError::InvalidConfiguration("Port number cannot be 0".to_string()));
+        }
+        if config.password.is_none() && config.private_key.is_none() && config.private_key_path.is_none() && !config.keyboard_interactive {
+            return Err(AppError::InvalidConfiguration("Either password or private key must be provided".to_string()));
+        }
+        if let Some(url) = &config.websocket_url {
+            ws_transport::validate_url(url)?;
+        }
+        for jump_host in &config.jump_hosts {
+            if !jump_host.jump_hosts.is_empty() {
+                return Err(AppError::InvalidConfiguration("Jump hosts can't have jump hosts of their own; list every hop in order".to_string()));
+            }
+            self.validate_config(jump_host)
+                .map_err(|e| AppError::InvalidConfiguration(format!("Jump host {}: {}", jump_host.hostname, e)))?;
+        }
+        capabilities::validate(config.terminal.as_ref())?;
+        validate_defaults(&config.defaults)?;
+        charset::validate(config.charset.as_deref())
+    }
+
+    async fn authenticate(&self, session_id: &str, session: &mut Session, config: &SSHConnectionConfig) -> AppResult<()> {
+        // Asking which methods the server takes tries "none", which some servers accept
+        let methods = session.auth_methods(&config.username).map(str::to_string).unwrap_or_default();
+        if session.authenticated() {
+            return Ok(());
+        }
+        let offers = |method: &str| methods.split(',').any(|offered| offered == method);
+        let interactive = offers("keyboard-interactive");
+
+        let result = if let Some(password) = &config.password {
+            // PAM-only servers ask for the password as a keyboard-interactive prompt instead
+            if interactive && !offers("password") {
+                Ok(())
+            } else {
+                session.userauth_password(&config.username, password)
+                    .map_err(|e| AppError::SSHAuthenticationFailed(format!("Password authentication failed: {}", e)))
+            }
+        } else if let Some(private_key) = &config.private_key {
+            self.authenticate_with_private_key(session, &config.username, private_key, config.passphrase.as_deref()).await
+        } else if let Some(key_path) = &config.private_key_path {
+            session.userauth_pubkey_file(&config.username, None, std::path::Path::new(key_path), config.passphrase.as_deref())
+                .map_err(|e| AppError::SSHAuthenticationFailed(format!("Private key authentication failed: {}", e)))
+        } else if interactive {
+            Ok(())
+        } else {
+            Err(AppError::SSHAuthenticationFailed("No authentication method provided".to_string()))
+        };
+
+        // Servers that want a second factor leave a key or password only partially authenticated
+        if !session.authenticated() && interactive {
+            if let Err(e) = &result {
+                log::debug!("{}, trying keyboard-interactive", e);
+            }
+            self.auth_prompts.authenticate(session_id, &config.hostname, session, &config.username, config.password.as_deref())?;
+        } else {
+            result?;
+        }
+
+        if !session.authenticated() {
+            return Err(AppError::SSHAuthenticationFailed("Authentication failed".to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn authenticate_with_private_key(
+        &self,
+        session: &mut Session,
+        username: &str,
+        private_key: &str,
+        passphrase: Option<&str>,
+    ) -> AppResult<()> {
+        // Create a temporary file for the private key
+        let mut temp_file = NamedTempFile::new()
+            .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to create temporary key file: {}", e)))?;
+
+        // Write the private key to the temporary file
+        temp_file.write_all(private_key.as_bytes())
+            .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to write private key to temp file: {}", e)))?;
+
+        // Ensure the file is written to disk
+        temp_file.flush()
+            .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to flush private key file: {}", e)))?;
+
+        let temp_path = temp_file.path();
+
+        // Set restrictive permissions on the temporary file (Unix-like systems)
+        #[cfg(unix)]
+        {
+            use std::fs;
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = fs::metadata(temp_path)
+                .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to get file metadata: {}", e)))?
+                .permissions();
+            perms.set_mode(0o600); // Read/write for owner only
+            fs::set_permissions(temp_path, perms)
+                .map_err(|e| AppError::SSHAuthenticationFailed(format!("Failed to set file permissions: {}", e)))?;
+        }
+
+        // Attempt authentication with the private key
+        let result = if let Some(passphrase) = passphrase {
+            session.userauth_pubkey_file(username, None, temp_path, Some(passphrase))
+        } else {
+            session.userauth_pubkey_file(username, None, temp_path, None)
+        };
+
+        // Clean up: the temporary file will be automatically deleted when temp_file goes out of scope
+
+        result.map_err(|e| AppError::SSHAuthenticationFailed(format!("Private key authentication failed: {}", e)))?;
+
+        log_security!("private_key_auth_success", "info", {
+            let mut details = std::collections::HashMap::new();
+            details.insert("username".to_string(), username.to_string());
+            details.insert("auth_method".to_string(), "private_key".to_string());
+            details
+        });
+
+        Ok(())
+    }
+
+    // SFTP operations
+    pub async fn create_sftp(&self, session_id: &str) -> AppResult<()> {
+        let data = self.session_data(session_id)?;
+
+        let Some(ssh_session) = data.connection.read().await.clone() else {
+            return Err(AppError::SSHConnectionFailed("No SSH session available for SFTP".to_string()));
+        };
+        let sftp = ssh_session.sftp()
+            .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to create SFTP session: {}", e)))?;
+
+        data.sftp.lock().await.sftp = Some(Arc::new(sftp));
+        log::info!("SFTP session created for: {}", session_id);
+
+        Ok(())
+    }
+
+    pub async fn list_directory(&self, session_id: &str, path: &str) -> AppResult<Vec<SftpFileInfo>> {
+        let mut files: Vec<SftpFileInfo> = self.with_sftp(session_id, "SFTP list directory", |sftp| {
+            let entries = sftp.readdir(std::path::Path::new(path))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to list directory: {}", e)))?;
+
+            let files = entries.into_iter()
+                .map(|(path, stat)| SftpFileInfo {
+                    name: path.file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("unknown")
+                        .to_string(),
+                    path: path.to_string_lossy().to_string(),
+                    size: stat.size.unwrap_or(0),
+                    is_directory: stat.is_dir(),
+                    modified: stat.mtime.map(|t| t as i64),
+                    permissions: stat.perm.map(|p| format!("{:o}", p)),
+                    uid: stat.uid,
+                    gid: stat.gid,
+                    owner: None,
+                    group: None,
+                })
+                .collect();
+            Ok(files)
+        }).await?;
+
+        if let Some(accounts) = self.owner_names(session_id).await {
+            for file in &mut files {
+                file.owner = file.uid.and_then(|uid| accounts.user_name(uid));
+                file.group = file.gid.and_then(|gid| accounts.group_name(gid));
+            }
+        }
+
+        self.visit_directory(session_id, path).await;
+        Ok(files)
+    }
+
+    // Remember the directory as the session's current SFTP path and in its host's recent paths
+    async fn visit_directory(&self, session_id: &str, path: &str) {
+        let Ok(data) = self.session_data(session_id) else {
+            return;
+        };
+        data.sftp.lock().await.path = Some(path.trim().to_string());
+        self.recent_paths.visit(&data.session.read().await.config, path);
+    }
+
+    // The directory last listed over SFTP, e.g. to bookmark it
+    pub async fn sftp_path(&self, session_id: &str) -> AppResult<Option<String>> {
+        let data = self.session_data(session_id)?;
+        let path = data.sftp.lock().await.path.clone();
+        Ok(path)
+    }
+
+    // Directories recently listed on the session's host, by this or earlier sessions
+    pub async fn recent_paths(&self, session_id: &str) -> AppResult<Vec<String>> {
+        let data = self.session_data(session_id)?;
+        let recent = self.recent_paths.list(&data.session.read().await.config);
+        Ok(recent)
+    }
+
+
+    pub async fn download_file(&self, session_id: &str, remote_path: &str) -> AppResult<Vec<u8>> {
+        let remote_path = remote_path.to_string();
+        if self.uses_scp(session_id).await? {
+            return self.with_scp(session_id, "SCP download", move |session| {
+                let mut contents = Vec::new();
+                scp::receive(session, &remote_path, 0, |_| Ok(&mut contents), &|_| Ok(()))?;
+                Ok(contents)
+            }).await;
+        }
+        self.with_sftp_blocking(session_id, "SFTP download", move |sftp| {
+            let mut remote_file = sftp.open(std::path::Path::new(&remote_path))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to open remote file: {}", e)))?;
+
+            let mut contents = Vec::new();
+            remote_file.read_to_end(&mut contents)
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to read file: {}", e)))?;
+            Ok(contents)
+        }).await
+    }
+
+    pub async fn upload_file(&self, session_id: &str, remote_path: &str, contents: &[u8]) -> AppResult<()> {
+        self.upload_file_with_progress(session_id, remote_path, Arc::from(contents), 0, |_| Ok(())).await
+    }
+
+    // Written a chunk at a time from offset on, for carrying on with an interrupted upload.
+    // progress gets the bytes written so far, offset included, and stops the upload by
+    // returning an error. A retried attempt carries on where the last one stopped.
+    pub async fn upload_file_with_progress(
+        &self,
+        session_id: &str,
+        remote_path: &str,
+        contents: Arc<[u8]>,
+        offset: u64,
+        progress: impl Fn(u64) -> AppResult<()> + Send + Sync + 'static,
+    ) -> AppResult<()> {
+        let remote = remote_path.to_string();
+        if self.uses_scp(session_id).await? {
+            // SCP can't write from an offset, so the upload starts over
+            return self.with_scp(session_id, "SCP upload", move |session| {
+                scp::send(session, &remote, contents.len() as u64, &mut &contents[..], &progress).map(drop)
+            }).await;
+        }
+
+        let offset = offset.min(contents.len() as u64);
+        let (required, keeps_existing) = upload_requirement(contents.len() as u64, offset);
+        self.ensure_free_space(session_id, remote_path, required, keeps_existing).await?;
+        let written = AtomicU64::new(offset);
+        let remote_path = remote;
+        self.with_sftp_blocking(session_id, "SFTP upload", move |sftp| {
+            let start = written.load(Ordering::Relaxed);
+            let mut remote_file = if start == 0 {
+                sftp.create(std::path::Path::new(&remote_path))
+            } else {
+                sftp.open_mode(std::path::Path::new(&remote_path), ssh2::OpenFlags::WRITE, 0o644, ssh2::OpenType::File)
+            }.map_err(|e| AppError::FileOperationFailed(format!("Failed to create remote file: {}", e)))?;
+            remote_file.seek(SeekFrom::Start(start))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to write file: {}", e)))?;
+
+            let report = |done: u64| {
+                written.store(start + done, Ordering::Relaxed);
+                progress(start + done)
+            };
+            copy_chunks(&mut &contents[start as usize..], &mut remote_file, &report).map(drop)
+        }).await
+    }
+
+    // Create a remote directory, leaving one that already exists alone
+    pub async fn create_directory(&self, session_id: &str, path: &str) -> AppResult<()> {
+        self.with_sftp(session_id, "SFTP create directory", |sftp| {
+            let path = std::path::Path::new(path);
+            if sftp.stat(path).is_ok_and(|stat| stat.is_dir()) {
+                return Ok(());
+            }
+            sftp.mkdir(path, 0o755)
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to create directory: {}", e)))
+        }).await
+    }
+
+    // Unlike create_directory, fails when the path already exists
+    pub async fn make_directory(&self, session_id: &str, path: &str, mode: Option<u32>) -> AppResult<()> {
+        let mode = mode.unwrap_or(0o755);
+        validate_mode(mode)?;
+        self.with_sftp(session_id, "SFTP mkdir", |sftp| {
+            sftp.mkdir(std::path::Path::new(path), mode as i32)
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to create directory {}: {}", path, e)))
+        }).await
+    }
+
+    // Move or rename a file or directory. An existing target is only replaced with overwrite.
+    pub async fn rename_path(&self, session_id: &str, from: &str, to: &str, overwrite: bool) -> AppResult<()> {
+        if overwrite {
+            return self.rename_over(session_id, from, to).await;
+        }
+        self.with_sftp(session_id, "SFTP rename", |sftp| {
+            if sftp.lstat(std::path::Path::new(to)).is_ok() {
+                return Err(AppError::ValidationError(format!("{} already exists", to)));
+            }
+            sftp.rename(std::path::Path::new(from), std::path::Path::new(to), Some(ssh2::RenameFlags::NATIVE))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to rename {}: {}", from, e)))
+        }).await
+    }
+
+    // Remove a file, symlink or empty directory, or with recursive a directory and everything
+    // below it. Symlinks are removed, never followed. Returns how many entries were removed.
+    pub async fn delete_path(&self, session_id: &str, path: &str, recursive: bool) -> AppResult<u64> {
+        let trimmed = path.trim();
+        if trimmed.is_empty() || trimmed.trim_end_matches('/').is_empty() {
+            return Err(AppError::ValidationError(format!("Refusing to delete \"{}\"", path)));
+        }
+
+        let stat = self.lstat(session_id, trimmed).await?;
+        if !stat.is_dir() {
+            self.remove_entry(session_id, trimmed, false).await?;
+            return Ok(1);
+        }
+
+        let mut removed = 0;
+        if recursive {
+            let (entries, unlisted) = self.walk_directory(session_id, trimmed).await?;
+            // Deleting only part of the tree would be worse than stopping before anything's gone
+            if let Some((directory, e)) = unlisted.into_iter().next() {
+                return Err(AppError::FileOperationFailed(format!("Failed to list {}: {}", directory, e)));
+            }
+            // Walked parents first, so in reverse every directory comes after its contents
+            for entry in entries.iter().rev() {
+                self.remove_entry(session_id, &entry.path, entry.is_directory).await?;
+                removed += 1;
+            }
+        }
+        self.remove_entry(session_id, trimmed, true).await?;
+        Ok(removed + 1)
+    }
+
+    async fn remove_entry(&self, session_id: &str, path: &str, is_directory: bool) -> AppResult<()> {
+        self.with_sftp(session_id, "SFTP delete", |sftp| {
+            let target = std::path::Path::new(path);
+            let removed = if is_directory { sftp.rmdir(target) } else { sftp.unlink(target) };
+            removed.map_err(|e| AppError::FileOperationFailed(format!("Failed to delete {}: {}", path, e)))
+        }).await
+    }
+
+    // Set the permission bits, e.g. 0o644; the file type bits are left to the server
+    pub async fn chmod(&self, session_id: &str, path: &str, mode: u32) -> AppResult<()> {
+        validate_mode(mode)?;
+        self.with_sftp(session_id, "SFTP chmod", |sftp| {
+            let stat = ssh2::FileStat { size: None, uid: None, gid: None, perm: Some(mode), atime: None, mtime: None };
+            sftp.setstat(std::path::Path::new(path), stat)
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to change the mode of {}: {}", path, e)))
+        }).await
+    }
+
+    // A path's attributes without following it when it's a symlink, plus where a symlink points
+    pub async fn stat_path(&self, session_id: &str, path: &str) -> AppResult<SftpStat> {
+        let stat = self.lstat(session_id, path).await?;
+        let is_symlink = stat.file_type().is_symlink();
+        let link_target = if is_symlink {
+            Some(self.read_link(session_id, path).await?)
+        } else {
+            None
+        };
+        let accounts = self.owner_names(session_id).await;
+
+        Ok(SftpStat {
+            path: path.to_string(),
+            size: stat.size.unwrap_or(0),
+            is_directory: stat.is_dir(),
+            is_symlink,
+            link_target,
+            permissions: stat.perm.map(|perm| format!("{:o}", perm & 0o7777)),
+            uid: stat.uid,
+            gid: stat.gid,
+            owner: stat.uid.zip(accounts.as_ref()).and_then(|(uid, accounts)| accounts.user_name(uid)),
+            group: stat.gid.zip(accounts.as_ref()).and_then(|(gid, accounts)| accounts.group_name(gid)),
+            modified: stat.mtime.map(|t| t as i64),
+            accessed: stat.atime.map(|t| t as i64),
+        })
+    }
+
+    async fn lstat(&self, session_id: &str, path: &str) -> AppResult<ssh2::FileStat> {
+        self.with_sftp(session_id, "SFTP stat", |sftp| {
+            sftp.lstat(std::path::Path::new(path))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to stat {}: {}", path, e)))
+        }).await
+    }
+
+    // Create link_path as a symlink pointing at target, which needn't exist
+    pub async fn create_symlink(&self, session_id: &str, target: &str, link_path: &str) -> AppResult<()> {
+        self.with_sftp(session_id, "SFTP symlink", |sftp| {
+            // ssh2 takes the target first, in the argument order OpenSSH's server expects
+            sftp.symlink(std::path::Path::new(target), std::path::Path::new(link_path))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to create symlink {}: {}", link_path, e)))
+        }).await
+    }
+
+    pub async fn read_link(&self, session_id: &str, path: &str) -> AppResult<String> {
+        self.with_sftp(session_id, "SFTP readlink", |sftp| {
+            sftp.readlink(std::path::Path::new(path))
+                .map(|target| target.to_string_lossy().to_string())
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to read symlink {}: {}", path, e)))
+        }).await
+    }
+
+    // Every entry below a remote directory, parents before their children. Subdirectories that
+    // can't be listed are returned with their error instead of failing the whole walk.
+    pub async fn walk_directory(&self, session_id: &str, root: &str) -> AppResult<(Vec<SftpFileInfo>, Vec<(String, AppError)>)> {
+        let mut entries = Vec::new();
+        let mut unlisted = Vec::new();
+        let mut pending = vec![without_trailing_slash(root).to_string()];
+
+        while let Some(directory) = pending.pop() {
+            let listed = self.with_sftp(session_id, "SFTP list directory", |sftp| {
+                sftp.readdir(std::path::Path::new(&directory))
+                    .map_err(|e| AppError::FileOperationFailed(format!("Failed to list directory: {}", e)))
+            }).await;
+            let listing = match listed {
+                Ok(listing) => listing,
+                // Nothing to walk without the top directory itself
+                Err(e) if entries.is_empty() && unlisted.is_empty() => return Err(e),
+                Err(e) => {
+                    unlisted.push((directory, e));
+                    continue;
+                }
+            };
+
+            // Depth-first, in name order
+            let mut listing: Vec<SftpFileInfo> = listing.into_iter()
+                .filter_map(|(path, stat)| {
+                    let name = path.file_name()?.to_str()?.to_string();
+                    (name != "." && name != "..").then(|| SftpFileInfo {
+                        path: join_remote(&directory, &name),
+                        name,
+                        size: stat.size.unwrap_or(0),
+                        is_directory: stat.is_dir(),
+                        modified: stat.mtime.map(|t| t as i64),
+                        permissions: stat.perm.map(|p| format!("{:o}", p)),
+                        uid: stat.uid,
+                        gid: stat.gid,
+                        owner: None,
+                        group: None,
+                    })
+                })
+                .collect();
+            listing.sort_by(|a, b| a.name.cmp(&b.name));
+            pending.extend(listing.iter().rev().filter(|entry| entry.is_directory).map(|entry| entry.path.clone()));
+            entries.extend(listing);
+        }
+        Ok((entries, unlisted))
+    }
+
+    // Like upload_file, but writes to a temporary file next to the target and renames it over
+    // the target once complete, so a failed transfer never leaves a truncated file. Over SFTP,
+    // the target's permissions carry over.
+    pub async fn upload_file_atomic(&self, session_id: &str, remote_path: &str, contents: &[u8]) -> AppResult<()> {
+        self.ensure_free_space(session_id, remote_path, contents.len() as u64, true).await?;
+
+        let target = std::path::Path::new(remote_path);
+        let name = target.file_name().and_then(|name| name.to_str())
+            .ok_or_else(|| AppError::ValidationError(format!("Not a file path: {}", remote_path)))?;
+        let temp_path = target.with_file_name(format!(".{}.{}.part", name, uuid::Uuid::new_v4().simple()))
+            .to_string_lossy()
+            .into_owned();
+
+        let contents: Arc<[u8]> = Arc::from(contents);
+        let (target, temp_file_path) = (target.to_path_buf(), temp_path.clone());
+        let scp = self.uses_scp(session_id).await?;
+        let written = if scp {
+            // SCP can't read the target's mode, so the file gets the default one
+            self.with_scp(session_id, "SCP upload", move |session| {
+                scp::send(session, &temp_file_path, contents.len() as u64, &mut &contents[..], &|_| Ok(())).map(drop)
+            }).await
+        } else {
+            self.with_sftp_blocking(session_id, "SFTP upload", move |sftp| {
+                let mode = sftp.stat(&target).ok().and_then(|stat| stat.perm).map_or(0o644, |perm| perm & 0o7777);
+                let flags = ssh2::OpenFlags::WRITE | ssh2::OpenFlags::CREATE | ssh2::OpenFlags::TRUNCATE;
+                let mut temp_file = sftp.open_mode(std::path::Path::new(&temp_file_path), flags, mode as i32, ssh2::OpenType::File)
+                    .map_err(|e| AppError::FileOperationFailed(format!("Failed to create temporary file: {}", e)))?;
+                temp_file.write_all(&contents)
+                    .map_err(|e| AppError::FileOperationFailed(format!("Failed to write file: {}", e)))?;
+
+                // The server's umask may have narrowed the mode
+                let stat = ssh2::FileStat { size: None, uid: None, gid: None, perm: Some(mode), atime: None, mtime: None };
+                if let Err(e) = temp_file.setstat(stat) {
+                    log::debug!("Failed to set the mode of {}: {}", temp_file_path, e);
+                }
+                Ok(())
+            }).await
+        };
+
+        let result = match written {
+            Ok(()) => self.rename_over(session_id, &temp_path, remote_path).await,
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            let cleanup = if scp {
+                self.exec_command(session_id, &format!("rm -f -- {}", shell_quote(&temp_path))).await.map(drop)
+            } else {
+                self.with_sftp(session_id, "SFTP cleanup", |sftp| {
+                    sftp.unlink(std::path::Path::new(&temp_path))
+                        .map_err(|e| AppError::FileOperationFailed(format!("Failed to remove {}: {}", temp_path, e)))
+                }).await
+            };
+            if let Err(e) = cleanup {
+                log::warn!("Failed to clean up after a failed upload: {}", e);
+            }
+        }
+        result
+    }
+
+    async fn rename_over(&self, session_id: &str, from: &str, to: &str) -> AppResult<()> {
+        let flags = ssh2::RenameFlags::OVERWRITE | ssh2::RenameFlags::ATOMIC | ssh2::RenameFlags::NATIVE;
+        let renamed = !self.uses_scp(session_id).await? && self.with_sftp(session_id, "SFTP rename", |sftp| {
+            Ok(sftp.rename(std::path::Path::new(from), std::path::Path::new(to), Some(flags)).is_ok())
+        }).await?;
+        if renamed {
+            return Ok(());
+        }
+
+        // SFTP v3 servers like OpenSSH's won't rename onto an existing file, and SCP can't rename
+        // at all; mv does, with rename(2)
+        let result = self.exec_command(session_id, &format!("mv -f -- {} {}", shell_quote(from), shell_quote(to))).await?;
+        if result.exit_code != 0 {
+            return Err(AppError::FileOperationFailed(format!("Failed to replace {}: {}", to, result.stderr.trim())));
+        }
+        Ok(())
+    }
+
+    // Add to the end of a remote file, creating it if needed, e.g. to append to a log.
+    // Returns the file's new size.
+    pub async fn append_file(&self, session_id: &str, remote_path: &str, contents: &[u8]) -> AppResult<u64> {
+        self.ensure_free_space(session_id, remote_path, contents.len() as u64, true).await?;
+        self.with_sftp(session_id, "SFTP append", |sftp| {
+            let flags = ssh2::OpenFlags::WRITE | ssh2::OpenFlags::APPEND | ssh2::OpenFlags::CREATE;
+            let mut remote_file = sftp.open_mode(std::path::Path::new(remote_path), flags, 0o644, ssh2::OpenType::File)
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to open remote file: {}", e)))?;
+
+            // Not every server honours the append flag, so write at the current end explicitly
+            let end = remote_file.stat().ok().and_then(|stat| stat.size).unwrap_or(0);
+            write_at(&mut remote_file, end, contents)?;
+            Ok(end + contents.len() as u64)
+        }).await
+    }
+
+    // Overwrite part of a remote file in place, creating it if needed. Writing past the end
+    // extends the file. Returns the file's new size.
+    pub async fn write_file_at(&self, session_id: &str, remote_path: &str, offset: u64, contents: &[u8]) -> AppResult<u64> {
+        self.ensure_free_space(session_id, remote_path, offset.saturating_add(contents.len() as u64), false).await?;
+        self.with_sftp(session_id, "SFTP write", |sftp| {
+            let flags = ssh2::OpenFlags::WRITE | ssh2::OpenFlags::CREATE;
+            let mut remote_file = sftp.open_mode(std::path::Path::new(remote_path), flags, 0o644, ssh2::OpenType::File)
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to open remote file: {}", e)))?;
+
+            let size = remote_file.stat().ok().and_then(|stat| stat.size).unwrap_or(0);
+            write_at(&mut remote_file, offset, contents)?;
+            Ok(size.max(offset + contents.len() as u64))
+        }).await
+    }
+
+    // Stream a local file to the remote host without loading it into memory, calling progress
+    // with the bytes copied so far; an error from it stops the upload
+    pub async fn upload_from_path(
+        &self,
+        session_id: &str,
+        local_path: &std::path::Path,
+        remote_path: &str,
+        progress: impl Fn(u64) -> AppResult<()> + Send + Sync + 'static,
+    ) -> AppResult<u64> {
+        let (local, remote) = (local_path.to_path_buf(), remote_path.to_string());
+        if self.uses_scp(session_id).await? {
+            return self.with_scp(session_id, "SCP upload", move |session| {
+                let mut local_file = std::fs::File::open(&local)
+                    .map_err(|e| AppError::FileOperationFailed(format!("Failed to open local file: {}", e)))?;
+                let size = local_file.metadata()?.len();
+                scp::send(session, &remote, size, &mut local_file, &progress)
+            }).await;
+        }
+        // An unreadable local file fails in the upload itself, with a better error
+        if let Ok(metadata) = std::fs::metadata(local_path) {
+            self.ensure_free_space(session_id, remote_path, metadata.len(), false).await?;
+        }
+        let (local_path, remote_path) = (local, remote);
+        self.with_sftp_blocking(session_id, "SFTP upload", move |sftp| {
+            let mut local_file = std::fs::File::open(&local_path)
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to open local file: {}", e)))?;
+            let mut remote_file = sftp.create(std::path::Path::new(&remote_path))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to create remote file: {}", e)))?;
+            copy_chunks(&mut local_file, &mut remote_file, &progress)
+        }).await
+    }
+
+    // Stream a remote file straight to disk
+    pub async fn download_to_path(
+        &self,
+        session_id: &str,
+        remote_path: &str,
+        local_path: &std::path::Path,
+        progress: impl Fn(u64) -> AppResult<()> + Send + Sync + 'static,
+    ) -> AppResult<u64> {
+        let local_path = local_path.to_path_buf();
+        let create = move |offset: u64| {
+            let mut file = std::fs::OpenOptions::new().write(true).create(true).truncate(false).open(&local_path)?;
+            file.set_len(offset)?;
+            file.seek(SeekFrom::Start(offset))?;
+            Ok(file)
+        };
+        self.download_to(session_id, remote_path, 0, create, progress).await
+    }
+
+    // Stream a remote file from offset on into the writer create gives for that offset, a
+    // chunk at a time, and return the file's size. progress gets the bytes written so far,
+    // offset included, and stops the download by returning an error. A retried attempt asks
+    // for a writer again and carries on where the last one stopped.
+    pub async fn download_to<W: Write>(
+        &self,
+        session_id: &str,
+        remote_path: &str,
+        offset: u64,
+        create: impl Fn(u64) -> AppResult<W> + Send + Sync + 'static,
+        progress: impl Fn(u64) -> AppResult<()> + Send + Sync + 'static,
+    ) -> AppResult<u64> {
+        let written = AtomicU64::new(offset);
+        let remote_path = remote_path.to_string();
+        if self.uses_scp(session_id).await? {
+            return self.with_scp(session_id, "SCP download", move |session| {
+                let report = |done: u64| {
+                    written.store(done, Ordering::Relaxed);
+                    progress(done)
+                };
+                scp::receive(session, &remote_path, written.load(Ordering::Relaxed), &create, &report)
+            }).await;
+        }
+        self.with_sftp_blocking(session_id, "SFTP download", move |sftp| {
+            let start = written.load(Ordering::Relaxed);
+            let mut remote_file = sftp.open(std::path::Path::new(&remote_path))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to open remote file: {}", e)))?;
+            remote_file.seek(SeekFrom::Start(start))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to read file: {}", e)))?;
+            let mut writer = create(start)?;
+
+            let report = |done: u64| {
+                written.store(start + done, Ordering::Relaxed);
+                progress(start + done)
+            };
+            Ok(start + copy_chunks(&mut remote_file, &mut writer, &report)?)
+        }).await
+    }
+
+    // The size the server reports for a remote file, if it does
+    pub async fn file_size(&self, session_id: &str, remote_path: &str) -> AppResult<Option<u64>> {
+        // Over SCP the size is only known once the download starts
+        if self.uses_scp(session_id).await? {
+            return Ok(None);
+        }
+        self.with_sftp(session_id, "SFTP stat", |sftp| {
+            let stat = sftp.stat(std::path::Path::new(remote_path))
+                .map_err(|e| AppError::FileOperationFailed(format!("Failed to stat remote file: {}", e)))?;
+            Ok(stat.size)
+        }).await
+    }
+
+    // Fail before writing anything when the target filesystem can't take `required` more bytes,
+    // counting the file being replaced as freed unless it stays while writing (appends, or
+    // atomic uploads going to a temporary file first). File operation retries leave a
+    // ResourceExhausted error alone, see RetryPolicy::transfer.
+    async fn ensure_free_space(&self, session_id: &str, remote_path: &str, required: u64, keeps_existing: bool) -> AppResult<()> {
+        // The free space comes from an SFTP extension
+        if self.uses_scp(session_id).await? {
+            return Ok(());
+        }
+        let space = self.with_sftp(session_id, "SFTP free space check", |sftp| {
+            Ok(free_space(sftp, std::path::Path::new(remote_path)))
+        }).await?;
+        match space {
+            Some((available, existing)) => {
+                let existing = if keeps_existing { 0 } else { existing };
+                check_free_space(remote_path, required, existing, available)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Run an SFTP operation on the session's SFTP channel. A transient failure drops the
+    // channel, so the next attempt opens a fresh one.
+    async fn with_sftp<T>(
+        &self,
+        session_id: &str,
+        name: &str,
+        operation: impl Fn(&ssh2::Sftp) -> AppResult<T>,
+    ) -> AppResult<T> {
+        let operation = &operation;
+        retry(&RetryPolicy::sftp(), name, || async move {
+            let data = self.session_data(session_id)?;
+            let sftp = Self::sftp_channel(&data).await?;
+            let result = operation(&sftp);
+            Self::after_sftp(&data, &sftp, &result).await;
+            result
+        }).await
+    }
+
+    // Like with_sftp, for copies that take a while. They run on the blocking pool, where a
+    // throttled transfer can sleep between chunks without holding up a runtime worker, and
+    // other operations on the session go ahead alongside them.
+    async fn with_sftp_blocking<T: Send + 'static>(
+        &self,
+        session_id: &str,
+        name: &str,
+        operation: impl Fn(&ssh2::Sftp) -> AppResult<T> + Send + Sync + 'static,
+    ) -> AppResult<T> {
+        let operation = Arc::new(operation);
+        retry(&RetryPolicy::sftp(), name, || {
+            let operation = operation.clone();
+            async move {
+                let data = self.session_data(session_id)?;
+                let sftp = Self::sftp_channel(&data).await?;
+                let channel = sftp.clone();
+                let result = blocking(move || operation(&channel)).await;
+                Self::after_sftp(&data, &sftp, &result).await;
+                result
+            }
+        }).await
+    }
+
+    // The session's SFTP channel, opened if need be. The lock is only held to hand it out;
+    // libssh2 takes turns between the operations sharing the channel.
+    async fn sftp_channel(data: &SSHSessionData) -> AppResult<Arc<ssh2::Sftp>> {
+        let connection = data.connection.read().await.clone();
+        let mut state = data.sftp.lock().await;
+        Self::ensure_sftp(&mut state, connection.as_ref()).cloned()
+    }
+
+    // A channel that failed transiently is dropped, unless another operation has already
+    // replaced it
+    async fn after_sftp<T>(data: &SSHSessionData, sftp: &Arc<ssh2::Sftp>, result: &AppResult<T>) {
+        match result {
+            Ok(_) => data.touch().await,
+            Err(e) if e.is_retryable() => {
+                let mut state = data.sftp.lock().await;
+                if state.sftp.as_ref().is_some_and(|current| Arc::ptr_eq(current, sftp)) {
+                    state.sftp = None;
+                }
+            }
+            Err(_) => {}
+        }
+    }
+
+    // Whether uploads and downloads go over SCP: always when the session's config asks for it,
+    // and in auto mode once the server has refused an SFTP channel
+    async fn uses_scp(&self, session_id: &str) -> AppResult<bool> {
+        let data = self.session_data(session_id)?;
+        let protocol = data.session.read().await.config.file_transfer;
+        match protocol {
+            FileTransferProtocol::Sftp => Ok(false),
+            FileTransferProtocol::Scp => Ok(true),
+            FileTransferProtocol::Auto => {
+                let connection = data.connection.read().await.clone();
+                let mut state = data.sftp.lock().await;
+                if state.scp_fallback || state.sftp.is_some() {
+                    return Ok(state.scp_fallback);
+                }
+                // with_sftp reports the missing connection
+                let Some(connection) = connection else {
+                    return Ok(false);
+                };
+                match connection.sftp() {
+                    Ok(sftp) => {
+                        state.sftp = Some(Arc::new(sftp));
+                        Ok(false)
+                    }
+                    Err(e) if sftp_refused(&e) => {
+                        log::warn!("No SFTP on session {} ({}), transferring files over SCP", session_id, e);
+                        state.scp_fallback = true;
+                        Ok(true)
+                    }
+                    // Anything else may work next time, and with_sftp retries it
+                    Err(e) => {
+                        log::debug!("Opening SFTP on session {} failed: {}", session_id, e);
+                        Ok(false)
+                    }
+                }
+            }
+        }
+    }
+
+    // Like with_sftp_blocking, for a transfer on an SCP channel of its own
+    async fn with_scp<T: Send + 'static>(
+        &self,
+        session_id: &str,
+        name: &str,
+        operation: impl Fn(&Session) -> AppResult<T> + Send + Sync + 'static,
+    ) -> AppResult<T> {
+        let operation = Arc::new(operation);
+        retry(&RetryPolicy::sftp(), name, || {
+            let operation = operation.clone();
+            async move {
+                let data = self.session_data(session_id)?;
+                let connection = data.connection().await?;
+                let result = blocking(move || operation(&connection)).await;
+                if result.is_ok() {
+                    data.touch().await;
+                }
+                result
+            }
+        }).await
+    }
+
+    fn ensure_sftp<'a>(state: &'a mut SftpState, connection: Option<&Session>) -> AppResult<&'a Arc<ssh2::Sftp>> {
+        if state.scp_fallback {
+            return Err(AppError::FileOperationFailed(
+                "The server has no SFTP subsystem; only uploads and downloads work, over SCP".to_string()
+            ));
+        }
+        if state.sftp.is_none() {
+            let ssh_session = connection
+                .ok_or_else(|| AppError::SSHConnectionFailed("No SSH session available for SFTP".to_string()))?;
+            let sftp = ssh_session.sftp()
+                .map_err(|e| AppError::SSHConnectionFailed(format!("Failed to create SFTP session: {}", e)))?;
+            state.sftp = Some(Arc::new(sftp));
+        }
+
+        state.sftp.as_ref()
+            .ok_or_else(|| AppError::FileOperationFailed("SFTP session not available".to_string()))
+    }
+
+    // Terminal autocomplete functionality
     pub async fn get_autocomplete_suggestions(
         &self,
         session_id: &str,
         input: &str,
         cursor_position: usize,
     ) -> AppResult<Vec<AutocompleteSuggestion>> {
-        let session_data = self.sessions.get(session_id)
-            .ok_or_else(|| AppError::SessionNotFound(session_id.to_string()))?;
-
-        let data = session_data.read().await;
-
-        if data.ssh_session.is_none() {
-            return Err(AppError::SSHConnectionFailed("No SSH session available".to_string()));
-        }
+        self.session_data(session_id)?.connection().await?;
 
         // Parse the input to determine what kind of completion is needed
-        let suggestions = self.generate_suggestions(input, cursor_position).await?;
+        let suggestions = self.generate_suggestions(session_id, input, cursor_position).await?;
 
         Ok(suggestions)
     }
 
     async fn generate_suggestions(
         &self,
+        session_id: &str,
         input: &str,
         cursor_position: usize,
     ) -> AppResult<Vec<AutocompleteSuggestion>> {
@@ -592,16 +2165,30 @@ impl SSHManager {
         // Get the word at cursor position
         let (prefix, word_start) = self.get_word_at_cursor(input, cursor_position);
 
+        // Earlier commands starting with the whole line typed so far
+        let line: String = input.chars().take(cursor_position).collect();
+        if !line.trim().is_empty() {
+            suggestions.extend(self.get_history_suggestions(session_id, &line).await);
+        }
+
+        // $VARS come from the shell's exported variables
+        if prefix.starts_with('$') {
+            suggestions.extend(self.get_variable_suggestions(session_id, &prefix).await);
+            return Ok(suggestions);
+        }
+
         // If we're at the beginning or after whitespace, suggest commands
+        let is_argument = input.chars().take(word_start).any(|c| !c.is_whitespace());
         if word_start == 0 || input.chars().nth(word_start.saturating_sub(1)) == Some(' ') {
             suggestions.extend(self.get_command_suggestions(&prefix));
         }
+        if !is_argument {
+            suggestions.extend(self.get_alias_suggestions(session_id, &prefix));
+        }
 
-        // If the prefix looks like a path, suggest files/directories
-        if prefix.contains('/') || prefix.starts_with('.') || prefix.starts_with('~') {
-            // For now, we'll provide basic path suggestions
-            // In a full implementation, this would use SFTP to list directories
-            suggestions.extend(self.get_path_suggestions(&prefix));
+        // Paths, and any argument that isn't an option, complete from the remote directory
+        if (is_argument && !prefix.starts_with('-')) || prefix.contains('/') || prefix.starts_with('.') || prefix.starts_with('~') {
+            suggestions.extend(self.get_path_suggestions(session_id, &prefix).await);
         }
 
         // Add common option suggestions if prefix starts with -
@@ -670,46 +2257,123 @@ impl SSHManager {
             .collect()
     }
 
-    fn get_path_suggestions(&self, prefix: &str) -> Vec<AutocompleteSuggestion> {
-        // Basic path suggestions - in a full implementation, this would
-        // use SFTP to list actual directories
-        let mut suggestions = Vec::new();
-
-        if prefix.is_empty() || prefix == "." {
-            suggestions.push(AutocompleteSuggestion {
-                text: "./".to_string(),
-                description: Some("Current directory".to_string()),
-                suggestion_type: SuggestionType::Directory,
-            });
-            suggestions.push(AutocompleteSuggestion {
-                text: "../".to_string(),
-                description: Some("Parent directory".to_string()),
-                suggestion_type: SuggestionType::Directory,
-            });
-        }
-
-        if prefix.is_empty() || prefix.starts_with('/') {
-            let common_paths = vec![
-                ("/home/", "User home directories"),
-                ("/etc/", "System configuration"),
-                ("/var/", "Variable data"),
-                ("/tmp/", "Temporary files"),
-                ("/usr/", "User programs"),
-                ("/opt/", "Optional software"),
-            ];
-
-            for (path, desc) in common_paths {
-                if path.starts_with(prefix) {
-                    suggestions.push(AutocompleteSuggestion {
-                        text: path.to_string(),
-                        description: Some(desc.to_string()),
-                        suggestion_type: SuggestionType::Directory,
-                    });
+    // Entries of the remote directory being typed, relative to the shell's working
+    // directory. Completion is best effort: a listing that fails or is slow leaves them out.
+    async fn get_path_suggestions(&self, session_id: &str, prefix: &str) -> Vec<AutocompleteSuggestion> {
+        let Ok(data) = self.session_data(session_id) else {
+            return Vec::new();
+        };
+        let (directory, name) = completion::split_path(prefix);
+
+        let listed = tokio::time::timeout(completion::LIST_TIMEOUT, async {
+            let home = match data.completions.home() {
+                Some(home) => home,
+                None => {
+                    let home = self.with_sftp(session_id, "SFTP home directory", |sftp| {
+                        sftp.realpath(std::path::Path::new("."))
+                            .map(|path| path.to_string_lossy().to_string())
+                            .map_err(|e| AppError::FileOperationFailed(format!("Failed to resolve home directory: {}", e)))
+                    }).await?;
+                    data.completions.set_home(&home);
+                    home
                 }
+            };
+            let cwd = data.shell.lock().await.cwd.clone().unwrap_or_else(|| home.clone());
+            let remote_directory = completion::resolve(directory, &cwd, &home);
+
+            if let Some(entries) = data.completions.get(&remote_directory) {
+                return Ok(entries);
+            }
+            let entries = self.with_sftp(session_id, "SFTP list for autocomplete", |sftp| {
+                let entries = sftp.readdir(std::path::Path::new(&remote_directory))
+                    .map_err(|e| AppError::FileOperationFailed(format!("Failed to list directory: {}", e)))?;
+                Ok(entries.into_iter()
+                    .filter_map(|(path, stat)| Some(DirectoryEntry {
+                        name: path.file_name()?.to_str()?.to_string(),
+                        is_directory: stat.is_dir(),
+                    }))
+                    .collect::<Vec<_>>())
+            }).await?;
+            data.completions.insert(&remote_directory, entries.clone());
+            Ok::<_, AppError>(entries)
+        }).await;
+
+        match listed {
+            Ok(Ok(entries)) => completion::suggestions(directory, name, &entries),
+            Ok(Err(e)) => {
+                log::debug!("No path suggestions for {} in session {}: {}", prefix, session_id, e);
+                Vec::new()
+            }
+            Err(_) => {
+                log::debug!("Listing {} for autocomplete in session {} timed out", prefix, session_id);
+                Vec::new()
             }
         }
+    }
+
+    async fn get_history_suggestions(&self, session_id: &str, line: &str) -> Vec<AutocompleteSuggestion> {
+        let Ok(entries) = self.command_history(session_id, line, MAX_HISTORY_SUGGESTIONS).await else {
+            return Vec::new();
+        };
+        entries.into_iter()
+            .filter(|entry| entry.command != line)
+            .map(|entry| AutocompleteSuggestion {
+                text: entry.command,
+                description: Some(match entry.count {
+                    1 => "used once".to_string(),
+                    count => format!("used {} times", count),
+                }),
+                suggestion_type: SuggestionType::History,
+            })
+            .collect()
+    }
+
+    // Collected on first use, since that runs the user's rc files; a shell slow to start
+    // leaves them out until it has been collected
+    async fn get_variable_suggestions(&self, session_id: &str, prefix: &str) -> Vec<AutocompleteSuggestion> {
+        let collected = match self.variables(session_id) {
+            Ok(Some(variables)) => Ok(variables),
+            _ => match tokio::time::timeout(completion::LIST_TIMEOUT, self.collect_variables(session_id)).await {
+                Ok(collected) => collected,
+                Err(_) => Err(AppError::TimeoutError("Collecting shell variables timed out".to_string())),
+            },
+        };
+        let variables = match collected {
+            Ok(variables) => variables,
+            Err(e) => {
+                log::debug!("No variable suggestions in session {}: {}", session_id, e);
+                return Vec::new();
+            }
+        };
 
-        suggestions
+        let (opening, name) = match prefix.strip_prefix("${") {
+            Some(name) => ("${", name),
+            None => ("$", &prefix[1..]),
+        };
+        let closing = if opening == "${" { "}" } else { "" };
+        variables.variables.into_iter()
+            .filter(|variable| variable.name.starts_with(name))
+            .map(|variable| AutocompleteSuggestion {
+                text: format!("{}{}{}", opening, variable.name, closing),
+                description: variable.value.map(|value| value.chars().take(80).collect()),
+                suggestion_type: SuggestionType::Variable,
+            })
+            .collect()
+    }
+
+    // Aliases already collected for the session; completing commands never waits for them
+    fn get_alias_suggestions(&self, session_id: &str, prefix: &str) -> Vec<AutocompleteSuggestion> {
+        let Ok(Some(variables)) = self.variables(session_id) else {
+            return Vec::new();
+        };
+        variables.aliases.into_iter()
+            .filter(|alias| alias.name.starts_with(prefix))
+            .map(|alias| AutocompleteSuggestion {
+                text: alias.name,
+                description: Some(format!("alias for {}", alias.command)),
+                suggestion_type: SuggestionType::Command,
+            })
+            .collect()
     }
 
     fn get_option_suggestions(&self, prefix: &str) -> Vec<AutocompleteSuggestion> {
@@ -738,6 +2402,135 @@ impl SSHManager {
     }
 }
 
+// Permission bits written in octal, e.g. "755" or "0644"
+pub fn parse_mode(mode: &str) -> AppResult<u32> {
+    let mode = u32::from_str_radix(mode.trim(), 8)
+        .map_err(|_| AppError::ValidationError(format!("Not an octal file mode: {}", mode)))?;
+    validate_mode(mode)?;
+    Ok(mode)
+}
+
+fn validate_mode(mode: u32) -> AppResult<()> {
+    if mode > 0o7777 {
+        return Err(AppError::ValidationError(format!("File mode {:o} has more than permission bits", mode)));
+    }
+    Ok(())
+}
+
+pub fn validate_defaults(defaults: &SessionDefaults) -> AppResult<()> {
+    if defaults.cols == Some(0) || defaults.rows == Some(0) {
+        return Err(AppError::ValidationError("Terminal size cannot be 0".to_string()));
+    }
+    if let Some(term) = &defaults.term {
+        capabilities::validate_term(term)?;
+    }
+    if let Some(bytes) = defaults.scrollback_bytes {
+        if bytes == 0 || bytes > MAX_SCROLLBACK_BYTES {
+            return Err(AppError::ValidationError(format!("Scrollback must be between 1 and {} bytes", MAX_SCROLLBACK_BYTES)));
+        }
+    }
+    if defaults.max_bytes_per_second == Some(0) {
+        return Err(AppError::ValidationError("Bandwidth cap cannot be 0".to_string()));
+    }
+    if defaults.max_concurrent_transfers == Some(0) {
+        return Err(AppError::ValidationError("Concurrent transfers cannot be 0".to_string()));
+    }
+    Ok(())
+}
+
+// Trailing slashes dropped, except for the one that is the root itself
+fn without_trailing_slash(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" if path.starts_with('/') => "/",
+        trimmed => trimmed,
+    }
+}
+
+// `name` inside a remote directory, without doubling the slash when the directory is / or ends in one
+pub(crate) fn join_remote(directory: &str, name: &str) -> String {
+    format!("{}/{}", directory.trim_end_matches('/'), name)
+}
+
+// Single-quote a value for a POSIX shell command line
+pub(crate) fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+// The server turned down the request for its SFTP subsystem, as opposed to a channel that
+// couldn't be opened or would have blocked, which may work next time
+fn sftp_refused(error: &ssh2::Error) -> bool {
+    error.code() == ErrorCode::Session(libssh2_sys::LIBSSH2_ERROR_CHANNEL_FAILURE)
+        && error.message().contains("SFTP subsystem")
+}
+
+// Run blocking libssh2 I/O on the blocking pool rather than a runtime worker
+pub(crate) async fn blocking<T: Send + 'static>(operation: impl FnOnce() -> AppResult<T> + Send + 'static) -> AppResult<T> {
+    tokio::task::spawn_blocking(operation)
+        .await
+        .map_err(|e| AppError::InternalError(format!("Blocking task failed: {}", e)))?
+}
+
+// Copy everything from reader to writer, reporting the running total after each chunk.
+// An error from progress stops the copy.
+fn copy_chunks(reader: &mut impl Read, writer: &mut impl Write, progress: &impl Fn(u64) -> AppResult<()>) -> AppResult<u64> {
+    let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+    let mut copied = 0u64;
+    loop {
+        let read = reader.read(&mut buffer)
+            .map_err(|e| AppError::FileOperationFailed(format!("Failed to read file: {}", e)))?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read])
+            .map_err(|e| AppError::FileOperationFailed(format!("Failed to write file: {}", e)))?;
+        copied += read as u64;
+        progress(copied)?;
+    }
+    writer.flush()
+        .map_err(|e| AppError::FileOperationFailed(format!("Failed to write file: {}", e)))?;
+    Ok(copied)
+}
+
+// Bytes available to us on the filesystem holding `remote_path`, and the size of the file
+// it would replace. None when the server doesn't support the statvfs extension.
+fn free_space(sftp: &ssh2::Sftp, remote_path: &std::path::Path) -> Option<(u64, u64)> {
+    let dir = remote_path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."));
+    let stats = match sftp.opendir(dir).and_then(|mut dir| dir.statvfs()) {
+        Ok(stats) => stats,
+        Err(e) => {
+            log::debug!("Skipping the free space check for {}: {}", dir.display(), e);
+            return None;
+        }
+    };
+    let block_size = if stats.f_frsize > 0 { stats.f_frsize } else { stats.f_bsize };
+    let existing = sftp.stat(remote_path).ok().and_then(|stat| stat.size).unwrap_or(0);
+    Some((stats.f_bavail.saturating_mul(block_size), existing))
+}
+
+fn write_at(remote_file: &mut ssh2::File, offset: u64, contents: &[u8]) -> AppResult<()> {
+    remote_file.seek(SeekFrom::Start(offset))
+        .and_then(|_| remote_file.write_all(contents))
+        .map_err(|e| AppError::FileOperationFailed(format!("Failed to write file: {}", e)))
+}
+
+// A resumed upload keeps what's already there and only writes the rest
+fn upload_requirement(size: u64, offset: u64) -> (u64, bool) {
+    (size.saturating_sub(offset), offset > 0)
+}
+
+// Overwriting a file frees its old contents, so only the growth has to fit
+fn check_free_space(remote_path: &str, required: u64, existing: u64, available: u64) -> AppResult<()> {
+    if required.saturating_sub(existing) <= available {
+        return Ok(());
+    }
+    Err(AppError::ResourceExhausted(format!(
+        "Not enough space on the remote filesystem for {}: {} bytes required, {} available",
+        remote_path, required, available
+    )))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -759,9 +2552,20 @@ mod tests {
             username: "testuser".to_string(),
             password: Some("testpass".to_string()),
             private_key: None,
+            private_key_path: None,
             passphrase: None,
             keep_alive: Some(true),
             ready_timeout: Some(5000),
+            websocket_url: None,
+            collect_host_info: false,
+            charset: None,
+            terminal: None,
+            jump_hosts: Vec::new(),
+            keyboard_interactive: false,
+            file_transfer: FileTransferProtocol::Auto,
+            defaults: SessionDefaults::default(),
+            credential: None,
+            approval: None,
         };
 
         let result = manager.create_session(config).await;
@@ -773,6 +2577,181 @@ mod tests {
         assert_eq!(session.config.username, "testuser");
     }
 
+    #[test]
+    fn test_remote_paths_under_root() {
+        assert_eq!(without_trailing_slash("/"), "/");
+        assert_eq!(without_trailing_slash("/var/www//"), "/var/www");
+        assert_eq!(join_remote(without_trailing_slash("/"), "etc"), "/etc");
+        assert_eq!(join_remote("/var/www", "index.html"), "/var/www/index.html");
+    }
+
+    #[tokio::test]
+    async fn test_exec_command_requires_session() {
+        let manager = SSHManager::new();
+
+        let result = manager.exec_command("non-existent", "uptime").await;
+        assert!(matches!(result, Err(AppError::SessionNotFound(_))));
+
+        let result = manager.exec_command("non-existent", "  ").await;
+        assert!(matches!(result, Err(AppError::ValidationError(_))));
+    }
+
+    #[test]
+    fn test_check_free_space() {
+        assert!(check_free_space("/tmp/a", 100, 0, 100).is_ok());
+        // Replacing a 60 byte file only needs 40 more
+        assert!(check_free_space("/tmp/a", 100, 60, 40).is_ok());
+
+        let error = check_free_space("/tmp/a", 100, 0, 99).unwrap_err();
+        assert!(matches!(error, AppError::ResourceExhausted(_)));
+        assert!(error.to_string().contains("100 bytes required, 99 available"));
+    }
+
+    #[test]
+    fn test_resumed_upload_needs_only_the_rest() {
+        assert_eq!(upload_requirement(100, 0), (100, false));
+        assert_eq!(upload_requirement(100, 70), (30, true));
+
+        // 70 of 100 bytes are already there, so 30 free bytes are enough
+        let (required, _) = upload_requirement(100, 70);
+        assert!(check_free_space("/tmp/a", required, 0, 30).is_ok());
+        assert!(check_free_space("/tmp/a", required, 0, 29).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_signal_shell_requires_session() {
+        let manager = SSHManager::new();
+
+        let result = manager.signal_shell("non-existent", TerminalSignal::Int).await;
+        assert!(matches!(result, Err(AppError::SessionNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_jump_host_validation() {
+        let manager = SSHManager::new();
+        let host = |hostname: &str| SSHConnectionConfig {
+            id: hostname.to_string(),
+            hostname: hostname.to_string(),
+            port: 22,
+            username: "testuser".to_string(),
+            password: Some("testpass".to_string()),
+            private_key: None,
+            private_key_path: None,
+            passphrase: None,
+            keep_alive: None,
+            ready_timeout: None,
+            websocket_url: None,
+            collect_host_info: false,
+            charset: None,
+            terminal: None,
+            jump_hosts: Vec::new(),
+            keyboard_interactive: false,
+            file_transfer: FileTransferProtocol::Auto,
+            defaults: SessionDefaults::default(),
+            credential: None,
+            approval: None,
+        };
+
+        let mut config = host("target");
+        config.jump_hosts = vec![host("bastion"), host("inner")];
+        assert!(manager.create_session(config.clone()).await.is_ok());
+
+        config.jump_hosts[1].password = None;
+        let error = manager.create_session(config.clone()).await.unwrap_err();
+        assert!(error.to_string().contains("Jump host inner"));
+        // Unless its prompts are answered interactively
+        config.jump_hosts[1].keyboard_interactive = true;
+        assert!(manager.create_session(config.clone()).await.is_ok());
+
+        config.jump_hosts = vec![SSHConnectionConfig { jump_hosts: vec![host("nested")], ..host("bastion") }];
+        assert!(matches!(manager.create_session(config).await, Err(AppError::InvalidConfiguration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_sftp_lock_does_not_block_metadata() {
+        let manager = SSHManager::new();
+        let config = SSHConnectionConfig {
+            id: "busy".to_string(),
+            hostname: "localhost".to_string(),
+            port: 22,
+            username: "testuser".to_string(),
+            password: Some("testpass".to_string()),
+            private_key: None,
+            private_key_path: None,
+            passphrase: None,
+            keep_alive: None,
+            ready_timeout: None,
+            websocket_url: None,
+            collect_host_info: false,
+            charset: None,
+            terminal: None,
+            jump_hosts: Vec::new(),
+            keyboard_interactive: false,
+            file_transfer: FileTransferProtocol::Auto,
+            defaults: SessionDefaults::default(),
+            credential: None,
+            approval: None,
+        };
+        manager.create_session(config).await.unwrap();
+
+        // As if a long transfer were running
+        let data = manager.session_data("busy").unwrap();
+        let _transfer = data.sftp.lock().await;
+
+        assert_eq!(manager.get_session("busy").await.unwrap().id, "busy");
+        assert_eq!(manager.list_sessions().await.len(), 1);
+        assert_eq!(manager.scrollback("busy").await.unwrap(), "");
+        assert!(manager.subscribe_shell("busy").await.is_err());
+        manager.resize_shell("busy", 80, 24).await.unwrap();
+        assert_eq!(manager.get_session_info("busy").await.unwrap(), (false, false, true));
+    }
+
+    #[tokio::test]
+    async fn test_failed_connect_timeline() {
+        // A server that hangs up before the SSH handshake
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || drop(listener.accept()));
+
+        let manager = SSHManager::new();
+        let config = SSHConnectionConfig {
+            id: "slow".to_string(),
+            hostname: "127.0.0.1".to_string(),
+            port,
+            username: "testuser".to_string(),
+            password: Some("testpass".to_string()),
+            private_key: None,
+            private_key_path: None,
+            passphrase: None,
+            keep_alive: None,
+            ready_timeout: None,
+            websocket_url: None,
+            collect_host_info: false,
+            charset: None,
+            terminal: None,
+            jump_hosts: Vec::new(),
+            keyboard_interactive: false,
+            file_transfer: FileTransferProtocol::Auto,
+            defaults: SessionDefaults::default(),
+            credential: None,
+            approval: None,
+        };
+        manager.create_session(config).await.unwrap();
+        assert!(manager.timeline("slow").unwrap().is_empty());
+        assert!(manager.connect("slow").await.is_err());
+
+        let timeline = manager.timeline("slow").unwrap();
+        let stages: Vec<_> = timeline.iter().map(|event| (event.stage, event.error.is_some())).collect();
+        assert_eq!(stages, [
+            (TimelineStage::Dns, false),
+            (TimelineStage::Tcp, false),
+            (TimelineStage::Handshake, true),
+            (TimelineStage::Connected, true),
+        ]);
+        assert!(timeline.iter().all(|event| event.duration_ms.is_some()));
+        assert!(manager.timeline("missing").is_err());
+    }
+
     #[tokio::test]
     async fn test_session_not_found_error() {
         let manager = SSHManager::new();
@@ -785,6 +2764,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sftp_after_scp_fallback() {
+        let mut state = SftpState { scp_fallback: true, ..SftpState::default() };
+        let Err(error) = SSHManager::ensure_sftp(&mut state, None) else {
+            panic!("SFTP opened after the fallback");
+        };
+        assert!(error.to_string().contains("SCP"));
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("755").unwrap(), 0o755);
+        assert_eq!(parse_mode(" 0644 ").unwrap(), 0o644);
+        assert_eq!(parse_mode("4755").unwrap(), 0o4755);
+        assert!(parse_mode("rwxr-xr-x").is_err());
+        assert!(parse_mode("9").is_err());
+        assert!(parse_mode("100644").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_refuses_root() {
+        let manager = SSHManager::new();
+        for path in ["/", "//", " "] {
+            let error = manager.delete_path("missing", path, true).await.unwrap_err();
+            assert!(matches!(error, AppError::ValidationError(_)));
+        }
+        assert_eq!(manager.delete_path("missing", "/tmp/x", false).await.unwrap_err().error_code(), "SESSION_NOT_FOUND");
+    }
+
     #[tokio::test]
     async fn test_graceful_shutdown() {
         let manager = SSHManager::new();
@@ -0,0 +1,175 @@
+use crate::types::{NoticeKind, NoticeSeverity, SessionNotice};
+use regex::Regex;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// The MOTD and login messages come right after the shell opens
+const CAPTURE_WINDOW: Duration = Duration::from_secs(3);
+const MAX_CAPTURE: usize = 16 * 1024;
+const DISK_WARNING_PERCENT: f64 = 90.0;
+const DISK_CRITICAL_PERCENT: f64 = 95.0;
+
+// Collects the first output of a shell and classifies it once the login has settled
+pub struct LoginNoticeScanner {
+    buffer: String,
+    started: Instant,
+    done: bool,
+}
+
+impl Default for LoginNoticeScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoginNoticeScanner {
+    pub fn new() -> Self {
+        Self {
+            buffer: String::new(),
+            started: Instant::now(),
+            done: false,
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &str) {
+        if self.done || self.buffer.len() >= MAX_CAPTURE {
+            return;
+        }
+        let mut end = chunk.len().min(MAX_CAPTURE - self.buffer.len());
+        while !chunk.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.buffer.push_str(&chunk[..end]);
+    }
+
+    // The notices, once the capture window has passed; None before that and ever after
+    pub fn poll(&mut self) -> Option<Vec<SessionNotice>> {
+        if self.done || (self.started.elapsed() < CAPTURE_WINDOW && self.buffer.len() < MAX_CAPTURE) {
+            return None;
+        }
+        self.done = true;
+        Some(classify(&std::mem::take(&mut self.buffer)))
+    }
+}
+
+pub fn classify(output: &str) -> Vec<SessionNotice> {
+    static ESCAPES: OnceLock<Regex> = OnceLock::new();
+    let escapes = ESCAPES.get_or_init(|| {
+        Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[()][0-9A-Za-z]|\r").unwrap()
+    });
+    let text = escapes.replace_all(output, "");
+
+    let mut notices: Vec<SessionNotice> = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(notice) = classify_line(line) {
+            if !notices.iter().any(|known| known.kind == notice.kind && known.message == notice.message) {
+                notices.push(notice);
+            }
+        }
+    }
+    notices
+}
+
+fn classify_line(line: &str) -> Option<SessionNotice> {
+    static FAILED_LOGINS: OnceLock<Regex> = OnceLock::new();
+    static DISK_USAGE: OnceLock<Regex> = OnceLock::new();
+    static UPDATES: OnceLock<Regex> = OnceLock::new();
+    static SECURITY_UPDATES: OnceLock<Regex> = OnceLock::new();
+    let failed_logins = FAILED_LOGINS.get_or_init(|| Regex::new(r"(?i)there (?:were|was) (\d+) failed login attempts?").unwrap());
+    // Landscape's sysinfo ("Usage of /: 93.1% of 19.56GB") and its warning ("=> / is using 93.1% of 19.56GB")
+    let disk_usage = DISK_USAGE.get_or_init(|| Regex::new(r"(?:Usage of \S+:|=> \S+ is using)\s+([\d.]+)% of").unwrap());
+    let updates = UPDATES.get_or_init(|| Regex::new(r"(?i)(\d+) (?:updates? can be applied|packages? can be updated)").unwrap());
+    let security_updates = SECURITY_UPDATES.get_or_init(|| {
+        Regex::new(r"(?i)(\d+) (?:of these updates (?:is a|are)|updates? (?:is a|are)) (?:standard )?security updates?").unwrap()
+    });
+    let lower = line.to_lowercase();
+    let count = |regex: &Regex| regex.captures(line).and_then(|captures| captures[1].parse::<u64>().ok());
+
+    let (kind, severity) = if lower.contains("system restart required") || lower.contains("reboot required") {
+        (NoticeKind::RebootRequired, NoticeSeverity::Warning)
+    } else if count(failed_logins).is_some_and(|count| count > 0) || lower.starts_with("last failed login:") {
+        (NoticeKind::FailedLogins, NoticeSeverity::Warning)
+    } else if lower.starts_with("last login:") {
+        (NoticeKind::LastLogin, NoticeSeverity::Info)
+    } else if let Some(percent) = disk_usage.captures(line).and_then(|captures| captures[1].parse::<f64>().ok()) {
+        match percent {
+            percent if percent >= DISK_CRITICAL_PERCENT => (NoticeKind::DiskSpace, NoticeSeverity::Critical),
+            percent if percent >= DISK_WARNING_PERCENT => (NoticeKind::DiskSpace, NoticeSeverity::Warning),
+            _ => return None,
+        }
+    } else if let Some(count) = count(security_updates) {
+        if count == 0 {
+            return None;
+        }
+        (NoticeKind::SecurityUpdates, NoticeSeverity::Warning)
+    } else if let Some(count) = count(updates) {
+        if count == 0 {
+            return None;
+        }
+        (NoticeKind::Updates, NoticeSeverity::Info)
+    } else if lower.contains("password has expired") || lower.contains("required to change your password") {
+        (NoticeKind::PasswordExpiry, NoticeSeverity::Critical)
+    } else if lower.contains("password will expire") {
+        (NoticeKind::PasswordExpiry, NoticeSeverity::Warning)
+    } else if lower.starts_with("you have new mail") || lower.starts_with("you have mail") {
+        (NoticeKind::Mail, NoticeSeverity::Info)
+    } else {
+        return None;
+    };
+
+    Some(SessionNotice {
+        kind,
+        severity,
+        message: line.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_ubuntu_motd() {
+        let motd = "Welcome to Ubuntu 22.04.4 LTS (GNU/Linux 5.15.0-105-generic x86_64)\r\n\r\n\
+            \x1b[1m  System information as of Mon May  6 10:00:00 UTC 2024\x1b[0m\r\n\
+            \x20 Usage of /:   96.2% of 19.56GB   Users logged in: 0\r\n\
+            \x20 => /var is using 91.0% of 9.78GB\r\n\
+            \x20 Usage of /home: 40.0% of 100GB\r\n\
+            12 updates can be applied immediately.\r\n\
+            5 of these updates are standard security updates.\r\n\
+            0 additional security updates can be applied with ESM Apps.\r\n\
+            *** System restart required ***\r\n\
+            Last failed login: Mon May  6 09:58:12 UTC 2024 from 203.0.113.9 on ssh:notty\r\n\
+            There were 3 failed login attempts since the last successful login.\r\n\
+            Last login: Sun May  5 18:00:01 2024 from 198.51.100.4\r\n\
+            You have new mail.\r\n\
+            \x1b]0;deploy@web-1: ~\x07deploy@web-1:~$ ";
+        let notices = classify(motd);
+        let kinds: Vec<(NoticeKind, NoticeSeverity)> = notices.iter().map(|notice| (notice.kind, notice.severity)).collect();
+        assert_eq!(kinds, [
+            (NoticeKind::DiskSpace, NoticeSeverity::Critical),
+            (NoticeKind::DiskSpace, NoticeSeverity::Warning),
+            (NoticeKind::Updates, NoticeSeverity::Info),
+            (NoticeKind::SecurityUpdates, NoticeSeverity::Warning),
+            (NoticeKind::RebootRequired, NoticeSeverity::Warning),
+            (NoticeKind::FailedLogins, NoticeSeverity::Warning),
+            (NoticeKind::FailedLogins, NoticeSeverity::Warning),
+            (NoticeKind::LastLogin, NoticeSeverity::Info),
+            (NoticeKind::Mail, NoticeSeverity::Info),
+        ]);
+        assert_eq!(notices[4].message, "*** System restart required ***");
+    }
+
+    #[test]
+    fn test_scanner_caps_capture() {
+        let mut scanner = LoginNoticeScanner::new();
+        scanner.feed("WARNING: Your password has expired.\n");
+        assert!(scanner.poll().is_none());
+
+        scanner.feed(&"x".repeat(MAX_CAPTURE));
+        let notices = scanner.poll().unwrap();
+        assert_eq!(notices[0].severity, NoticeSeverity::Critical);
+        scanner.feed("You have mail.\n");
+        assert!(scanner.poll().is_none());
+    }
+}
@@ -0,0 +1,117 @@
+use super::shell_quote;
+use crate::types::{AppError, AppResult, MultiplexerKind, MultiplexerSession};
+use chrono::{DateTime, Utc};
+
+const SECTION_PREFIX: &str = "==";
+
+// Both listings in one exec; hosts without tmux or screen just print nothing
+pub const DETECT_COMMAND: &str = "\
+echo '==tmux'; tmux list-sessions -F '#{session_name}\t#{session_windows}\t#{session_attached}\t#{session_created}' 2>/dev/null; \
+echo '==screen'; screen -ls 2>/dev/null; \
+true";
+
+pub fn parse(output: &str) -> Vec<MultiplexerSession> {
+    let mut sessions = Vec::new();
+    let mut section = "";
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix(SECTION_PREFIX) {
+            section = name.trim();
+            continue;
+        }
+        let session = match section {
+            "tmux" => parse_tmux_line(line),
+            "screen" => parse_screen_line(line),
+            _ => None,
+        };
+        sessions.extend(session);
+    }
+    sessions
+}
+
+// name \t windows \t attached clients \t created (unix time)
+fn parse_tmux_line(line: &str) -> Option<MultiplexerSession> {
+    let mut fields = line.split('\t');
+    let name = fields.next().filter(|name| !name.is_empty())?.to_string();
+    let windows = fields.next().and_then(|windows| windows.parse().ok());
+    let attached = fields.next().and_then(|clients| clients.parse::<u32>().ok()).unwrap_or(0) > 0;
+    let created_at = fields.next()
+        .and_then(|created| created.parse::<i64>().ok())
+        .and_then(|created| DateTime::<Utc>::from_timestamp(created, 0));
+    Some(MultiplexerSession {
+        kind: MultiplexerKind::Tmux,
+        id: name.clone(),
+        name,
+        windows,
+        attached,
+        created_at,
+    })
+}
+
+// "\t12345.pts-0.web-1\t(05/06/2024 10:00:00 AM)\t(Detached)"; the date column is
+// missing on older screens
+fn parse_screen_line(line: &str) -> Option<MultiplexerSession> {
+    if !line.starts_with('\t') {
+        return None;
+    }
+    let fields: Vec<&str> = line.split('\t').map(str::trim).filter(|field| !field.is_empty()).collect();
+    let id = fields.first()?;
+    let (pid, name) = id.split_once('.')?;
+    if pid.parse::<u32>().is_err() {
+        return None;
+    }
+    let state = fields.last()?.to_lowercase();
+    Some(MultiplexerSession {
+        kind: MultiplexerKind::Screen,
+        id: id.to_string(),
+        name: name.to_string(),
+        windows: None,
+        attached: state.contains("attached") && !state.contains("detached"),
+        created_at: None,
+    })
+}
+
+// Attach in the interactive shell. Taking the session over from other clients is
+// optional, since it kicks them out.
+pub fn attach_command(kind: MultiplexerKind, id: &str, detach_others: bool) -> AppResult<String> {
+    if id.is_empty() || id.starts_with('-') {
+        return Err(AppError::ValidationError(format!("Invalid session: {}", id)));
+    }
+    let id = shell_quote(id);
+    Ok(match (kind, detach_others) {
+        (MultiplexerKind::Tmux, false) => format!("tmux attach-session -t {}", id),
+        (MultiplexerKind::Tmux, true) => format!("tmux attach-session -d -t {}", id),
+        (MultiplexerKind::Screen, false) => format!("screen -x {}", id),
+        (MultiplexerKind::Screen, true) => format!("screen -d -r {}", id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tmux_and_screen() {
+        let output = "==tmux\nwork\t3\t0\t1714989600\ndeploy\t1\t1\t1714989700\n\
+            ==screen\nThere are screens on:\n\t12345.pts-0.web-1\t(05/06/2024 10:00:00 AM)\t(Detached)\n\
+            \t6789.build\t(Attached)\n2 Sockets in /run/screen/S-ops.\n";
+        let sessions = parse(output);
+        assert_eq!(sessions.len(), 4);
+        assert_eq!((sessions[0].name.as_str(), sessions[0].windows, sessions[0].attached), ("work", Some(3), false));
+        assert!(sessions[1].attached);
+        assert_eq!(sessions[0].created_at.unwrap().timestamp(), 1714989600);
+        assert_eq!((sessions[2].id.as_str(), sessions[2].name.as_str()), ("12345.pts-0.web-1", "pts-0.web-1"));
+        assert!(!sessions[2].attached && sessions[3].attached);
+    }
+
+    #[test]
+    fn test_parse_nothing_running() {
+        assert!(parse("==tmux\n==screen\nNo Sockets found in /run/screen/S-ops.\n").is_empty());
+    }
+
+    #[test]
+    fn test_attach_command() {
+        assert_eq!(attach_command(MultiplexerKind::Tmux, "work", false).unwrap(), "tmux attach-session -t 'work'");
+        assert_eq!(attach_command(MultiplexerKind::Screen, "12345.build", true).unwrap(), "screen -d -r '12345.build'");
+        assert!(attach_command(MultiplexerKind::Tmux, "-x", false).is_err());
+    }
+}
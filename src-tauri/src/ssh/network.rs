@@ -0,0 +1,190 @@
+use super::host_info::sections;
+use crate::types::{AddressFamily, InterfaceAddress, ListeningSocket, NetworkInfo, NetworkInterface};
+use chrono::Utc;
+use std::net::IpAddr;
+
+// ip -o prints one line per link or address. ss lists processes only for sockets the
+// session's user may see, and still lists the rest.
+pub const COLLECT_COMMAND: &str = "\
+echo '==link'; ip -o link show 2>/dev/null; \
+echo '==addr'; ip -o addr show 2>/dev/null; \
+echo '==sockets'; ss -tulnp 2>/dev/null; \
+true";
+
+pub fn parse(output: &str) -> NetworkInfo {
+    let mut interfaces: Vec<NetworkInterface> = Vec::new();
+    let mut sockets = Vec::new();
+
+    for (section, lines) in sections(output) {
+        match section {
+            "link" => interfaces.extend(lines.iter().filter_map(|line| parse_link(line))),
+            "addr" => {
+                for (name, address) in lines.iter().filter_map(|line| parse_addr(line)) {
+                    // Addresses of links ip link didn't list still get an interface
+                    match interfaces.iter_mut().find(|interface| interface.name == name) {
+                        Some(interface) => interface.addresses.push(address),
+                        None => interfaces.push(NetworkInterface {
+                            name,
+                            state: None,
+                            mtu: None,
+                            mac: None,
+                            addresses: vec![address],
+                        }),
+                    }
+                }
+            }
+            "sockets" => sockets.extend(lines.iter().filter_map(|line| parse_socket(line))),
+            _ => {}
+        }
+    }
+    sockets.sort_by(|a: &ListeningSocket, b| (a.port, &a.protocol, &a.address).cmp(&(b.port, &b.protocol, &b.address)));
+
+    NetworkInfo {
+        interfaces,
+        sockets,
+        collected_at: Utc::now(),
+    }
+}
+
+// "2: eth0@if5: <BROADCAST,UP,LOWER_UP> mtu 1500 qdisc noqueue state UP ... link/ether 02:42:ac:11:00:02 brd ..."
+fn parse_link(line: &str) -> Option<NetworkInterface> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let name = interface_name(fields.get(1)?.trim_end_matches(':'))?;
+    let after = |key: &str| fields.iter().position(|field| *field == key).and_then(|index| fields.get(index + 1)).copied();
+
+    Some(NetworkInterface {
+        name,
+        state: after("state").map(str::to_string),
+        mtu: after("mtu").and_then(|mtu| mtu.parse().ok()),
+        mac: after("link/ether").map(str::to_string),
+        addresses: Vec::new(),
+    })
+}
+
+// "2: eth0    inet 10.0.0.5/24 brd 10.0.0.255 scope global dynamic eth0\       valid_lft ..."
+fn parse_addr(line: &str) -> Option<(String, InterfaceAddress)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let name = interface_name(fields.get(1)?)?;
+    let family = match *fields.get(2)? {
+        "inet" => AddressFamily::Ipv4,
+        "inet6" => AddressFamily::Ipv6,
+        _ => return None,
+    };
+    let (address, prefix_length) = fields.get(3)?.split_once('/')?;
+    let scope = fields.iter().position(|field| *field == "scope").and_then(|index| fields.get(index + 1));
+
+    Some((name, InterfaceAddress {
+        family,
+        address: address.to_string(),
+        prefix_length: prefix_length.parse().ok()?,
+        scope: scope.map(|scope| scope.to_string()),
+    }))
+}
+
+// VLANs and container veths are named "eth0.100@eth0"; the part before '@' is the interface
+fn interface_name(field: &str) -> Option<String> {
+    let name = field.split('@').next()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+// Netid State Recv-Q Send-Q Local:Port Peer:Port Process, e.g.
+// "tcp LISTEN 0 128 0.0.0.0:22 0.0.0.0:* users:((\"sshd\",pid=812,fd=3))"
+fn parse_socket(line: &str) -> Option<ListeningSocket> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let protocol = *fields.first()?;
+    if !matches!(protocol, "tcp" | "udp") {
+        return None;
+    }
+    let (address, port) = fields.get(4)?.rsplit_once(':')?;
+    // "[::1]" and "127.0.0.53%lo" are an address with brackets or the interface it's bound to
+    let address = address.trim_start_matches('[').trim_end_matches(']');
+    let address = address.split('%').next().unwrap_or(address);
+    let users = fields.get(6..).map(|rest| rest.join(" ")).unwrap_or_default();
+    let (process, pid) = parse_users(&users);
+
+    Some(ListeningSocket {
+        protocol: protocol.to_string(),
+        address: address.to_string(),
+        port: port.parse().ok()?,
+        loopback: address.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()),
+        process,
+        pid,
+    })
+}
+
+// users:(("nginx",pid=1201,fd=6),("nginx",pid=1200,fd=6)); the first one is enough
+fn parse_users(users: &str) -> (Option<String>, Option<u32>) {
+    let Some((_, rest)) = users.split_once("((\"") else {
+        return (None, None);
+    };
+    let name = rest.split('"').next().map(str::to_string);
+    let pid = rest.split_once("pid=")
+        .and_then(|(_, pid)| pid.split([',', ')']).next())
+        .and_then(|pid| pid.parse().ok());
+    (name, pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "==link
+1: lo: <LOOPBACK,UP,LOWER_UP> mtu 65536 qdisc noqueue state UNKNOWN mode DEFAULT group default qlen 1000\\    link/loopback 00:00:00:00:00:00 brd 00:00:00:00:00:00
+2: eth0@if7: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc noqueue state UP mode DEFAULT group default \\    link/ether 02:42:ac:11:00:02 brd ff:ff:ff:ff:ff:ff link-netnsid 0
+==addr
+1: lo    inet 127.0.0.1/8 scope host lo\\       valid_lft forever preferred_lft forever
+1: lo    inet6 ::1/128 scope host \\       valid_lft forever preferred_lft forever
+2: eth0    inet 172.17.0.2/16 brd 172.17.255.255 scope global eth0\\       valid_lft forever preferred_lft forever
+5: wg0    inet 10.8.0.1/24 scope global wg0\\       valid_lft forever preferred_lft forever
+==sockets
+Netid State  Recv-Q Send-Q Local Address:Port  Peer Address:Port Process
+udp   UNCONN 0      0      127.0.0.53%lo:53      0.0.0.0:*
+tcp   LISTEN 0      4096   0.0.0.0:22            0.0.0.0:*     users:((\"sshd\",pid=812,fd=3))
+tcp   LISTEN 0      511    [::1]:5432            [::]:*        users:((\"postgres\",pid=1200,fd=7),(\"postgres\",pid=1201,fd=7))
+tcp   LISTEN 0      511    *:80                  *:*
+";
+
+    #[test]
+    fn test_parse_interfaces() {
+        let info = parse(OUTPUT);
+        let names: Vec<&str> = info.interfaces.iter().map(|interface| interface.name.as_str()).collect();
+        assert_eq!(names, ["lo", "eth0", "wg0"]);
+
+        let lo = &info.interfaces[0];
+        assert_eq!((lo.state.as_deref(), lo.mtu, lo.mac.as_deref()), (Some("UNKNOWN"), Some(65536), None));
+        assert_eq!(lo.addresses.len(), 2);
+        assert_eq!(lo.addresses[1].family, AddressFamily::Ipv6);
+        assert_eq!((lo.addresses[1].address.as_str(), lo.addresses[1].prefix_length), ("::1", 128));
+
+        let eth0 = &info.interfaces[1];
+        assert_eq!(eth0.mac.as_deref(), Some("02:42:ac:11:00:02"));
+        assert_eq!((eth0.addresses[0].address.as_str(), eth0.addresses[0].scope.as_deref()), ("172.17.0.2", Some("global")));
+
+        // Only in the address list
+        assert!(info.interfaces[2].state.is_none());
+        assert_eq!(info.interfaces[2].addresses[0].prefix_length, 24);
+    }
+
+    #[test]
+    fn test_parse_sockets() {
+        let info = parse(OUTPUT);
+        let sockets: Vec<(&str, &str, u16, bool)> = info.sockets.iter()
+            .map(|socket| (socket.protocol.as_str(), socket.address.as_str(), socket.port, socket.loopback))
+            .collect();
+        assert_eq!(sockets, [
+            ("tcp", "0.0.0.0", 22, false),
+            ("udp", "127.0.0.53", 53, true),
+            ("tcp", "*", 80, false),
+            ("tcp", "::1", 5432, true),
+        ]);
+        assert_eq!((info.sockets[0].process.as_deref(), info.sockets[0].pid), (Some("sshd"), Some(812)));
+        assert_eq!(info.sockets[3].pid, Some(1200));
+        assert!(info.sockets[2].process.is_none());
+    }
+
+    #[test]
+    fn test_missing_tools() {
+        let info = parse("==link\n==addr\n==sockets\n");
+        assert!(info.interfaces.is_empty() && info.sockets.is_empty());
+    }
+}
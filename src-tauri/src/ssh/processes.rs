@@ -0,0 +1,121 @@
+use crate::types::{AppError, AppResult, ProcessSignal, RemoteProcess, SignalResult};
+
+// etime instead of etimes and -ax instead of -e keep this working on BSD and macOS ps.
+// The trailing '=' on each column drops the header line.
+pub const LIST_COMMAND: &str = "ps -axo pid=,ppid=,user=,pcpu=,pmem=,rss=,etime=,stat=,args=";
+
+const MAX_SIGNAL_PIDS: usize = 256;
+
+pub fn parse_ps(output: &str) -> Vec<RemoteProcess> {
+    output.lines().filter_map(parse_ps_line).collect()
+}
+
+fn parse_ps_line(line: &str) -> Option<RemoteProcess> {
+    let mut fields = line.split_whitespace();
+    let pid = fields.next()?.parse().ok()?;
+    let ppid = fields.next()?.parse().ok()?;
+    let user = fields.next()?.to_string();
+    let cpu_percent = fields.next()?.parse().ok()?;
+    let mem_percent = fields.next()?.parse().ok()?;
+    let rss_kb = fields.next()?.parse().ok()?;
+    let elapsed_seconds = parse_elapsed(fields.next()?)?;
+    let state = fields.next()?.to_string();
+    // Arguments keep their spaces; kernel threads may have none at all
+    let command = fields.collect::<Vec<_>>().join(" ");
+
+    Some(RemoteProcess {
+        pid,
+        ppid,
+        user,
+        cpu_percent,
+        mem_percent,
+        rss_kb,
+        elapsed_seconds,
+        state,
+        command,
+    })
+}
+
+// ps etime: [[dd-]hh:]mm:ss
+fn parse_elapsed(value: &str) -> Option<u64> {
+    let (days, clock) = match value.split_once('-') {
+        Some((days, clock)) => (days.parse::<u64>().ok()?, clock),
+        None => (0, value),
+    };
+    let mut seconds = 0u64;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(days * 86_400 + seconds)
+}
+
+// One kill per PID so a single failure doesn't hide the others; each prints
+// "<pid> <exit code> <kill's message>"
+pub fn signal_command(pids: &[u32], signal: ProcessSignal) -> AppResult<String> {
+    if pids.is_empty() {
+        return Err(AppError::ValidationError("No process IDs given".to_string()));
+    }
+    if pids.len() > MAX_SIGNAL_PIDS {
+        return Err(AppError::ValidationError(format!("At most {} processes can be signalled at once", MAX_SIGNAL_PIDS)));
+    }
+    // 0 would hit our own process group and 1 is init
+    if let Some(pid) = pids.iter().find(|pid| **pid <= 1) {
+        return Err(AppError::ValidationError(format!("Refusing to signal PID {}", pid)));
+    }
+
+    let pids = pids.iter().map(u32::to_string).collect::<Vec<_>>().join(" ");
+    Ok(format!(
+        "for pid in {}; do out=$(kill -s {} \"$pid\" 2>&1); echo \"$pid $? $out\"; done",
+        pids,
+        signal.name()
+    ))
+}
+
+pub fn parse_signal_output(output: &str) -> Vec<SignalResult> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ' ');
+            let pid = fields.next()?.parse().ok()?;
+            let success = fields.next()? == "0";
+            let message = fields.next().map(str::trim).filter(|message| !message.is_empty());
+            Some(SignalResult {
+                pid,
+                success,
+                error: if success { None } else { Some(message.unwrap_or("kill failed").to_string()) },
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ps() {
+        let output = "    1     0 root      0.0  0.1 11904   12-03:04:05 Ss   /sbin/init splash\n\
+                        812     1 www-data  2.5  1.2 98304       01:02 S    nginx: worker process\n\
+                          2     0 root      0.0  0.0     0    03:00:00 S    \n\
+                      garbage line\n";
+        let processes = parse_ps(output);
+        assert_eq!(processes.len(), 3);
+        assert_eq!(processes[0].elapsed_seconds, 12 * 86_400 + 3 * 3600 + 4 * 60 + 5);
+        assert_eq!(processes[1].command, "nginx: worker process");
+        assert_eq!((processes[1].cpu_percent, processes[1].rss_kb), (2.5, 98304));
+        assert_eq!(processes[2].command, "");
+    }
+
+    #[test]
+    fn test_signal_command_and_output() {
+        assert!(signal_command(&[], ProcessSignal::Term).is_err());
+        assert!(signal_command(&[1, 400], ProcessSignal::Kill).is_err());
+        let command = signal_command(&[400, 401], ProcessSignal::Kill).unwrap();
+        assert!(command.starts_with("for pid in 400 401; do out=$(kill -s KILL"));
+
+        let results = parse_signal_output("400 0 \n401 1 bash: kill: (401) - Operation not permitted\n");
+        assert!(results[0].success && results[0].error.is_none());
+        assert!(!results[1].success);
+        assert_eq!(results[1].error.as_deref(), Some("bash: kill: (401) - Operation not permitted"));
+    }
+}
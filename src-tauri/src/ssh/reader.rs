@@ -0,0 +1,177 @@
+use super::{completion, SSHSessionData};
+use crate::types::{AppError, AppResult, TimelineStage};
+use chrono::Utc;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+// Chunks a subscriber may fall behind by before the reader waits for it, which leaves the
+// rest of the output with the server until it catches up
+pub const SUBSCRIBER_BUFFER: usize = 256;
+// How long to wait for the socket before checking whether the shell is still there
+const WAIT_INTERVAL: Duration = Duration::from_millis(250);
+// Pause while the writer, stalled on a full window, is the one draining the connection
+const WRITER_BACKOFF: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone)]
+pub enum ShellEvent {
+    Output(String),
+    // The shell hit EOF; close_exited_shell has its exit status
+    Closed,
+    Failed(String),
+}
+
+// Output of a shell, read by a thread of its own as it arrives. Waiting for it happens on
+// the socket, outside the session lock, so an idle shell never holds up its writer, SFTP
+// or exec. Stops when dropped, i.e. when the shell is closed or replaced.
+pub struct ShellReader {
+    cancel: CancellationToken,
+}
+
+impl ShellReader {
+    pub fn spawn(session_id: &str, stream: ssh2::Stream, socket: TcpStream, data: Arc<SSHSessionData>) -> AppResult<Self> {
+        let cancel = CancellationToken::new();
+        let stopped = cancel.clone();
+        let session_id = session_id.to_string();
+
+        std::thread::Builder::new()
+            .name(format!("shell-reader-{}", session_id))
+            .spawn(move || {
+                read_shell(&session_id, stream, &socket, &data, &stopped);
+                log::debug!("Shell reader stopped for session {}", session_id);
+            })
+            .map_err(|e| AppError::InternalError(format!("Failed to start shell reader: {}", e)))?;
+
+        Ok(Self { cancel })
+    }
+}
+
+impl Drop for ShellReader {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+fn read_shell(session_id: &str, mut stream: ssh2::Stream, socket: &TcpStream, data: &SSHSessionData, stopped: &CancellationToken) {
+    let mut buffer = [0; 4096];
+    loop {
+        let (available, writable) = {
+            let shell = data.shell.blocking_lock();
+            if stopped.is_cancelled() {
+                return;
+            }
+            let Some(channel) = shell.channel.as_ref() else {
+                return;
+            };
+            if channel.eof() {
+                drop(shell);
+                publish(data, ShellEvent::Closed);
+                return;
+            }
+            (channel.read_window().available, channel.write_window().remaining > 0)
+        };
+
+        // A read with nothing buffered for the shell would wait for it inside the session
+        // lock. Wait on the socket instead, then let libssh2 sort what arrived into its
+        // channels: it drains the transport, without waiting, before every write.
+        if available == 0 {
+            if !wait_readable(socket) {
+                continue;
+            }
+            if !writable {
+                std::thread::sleep(WRITER_BACKOFF);
+                continue;
+            }
+            // Writes nothing, so there is no amount to check
+            match stream.write(&[]).map(drop) {
+                Ok(()) => continue,
+                // The read below reports what happened to the channel
+                Err(e) => log::debug!("Failed to drain the connection of session {}: {}", session_id, e),
+            }
+        }
+
+        match stream.read(&mut buffer) {
+            Ok(0) => {
+                publish(data, ShellEvent::Closed);
+                return;
+            }
+            Ok(n) => deliver(data, &buffer[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => {
+                log::error!("Error reading from shell for session {}: {}", session_id, e);
+                data.timeline.record(TimelineStage::Error, Some("Shell read".to_string()), Some(e.to_string()));
+                publish(data, ShellEvent::Failed(format!("Failed to read from shell: {}", e)));
+                return;
+            }
+        }
+    }
+}
+
+// Decode and filter like every reader of the shell sees it, keep it for scrollback, then
+// hand it to the subscribers
+fn deliver(data: &SSHSessionData, bytes: &[u8]) {
+    let output = {
+        let mut shell = data.shell.blocking_lock();
+        let shell = &mut *shell;
+        let output = match shell.codec.as_ref() {
+            Some(codec) => codec.lock().decode(bytes),
+            None => String::from_utf8_lossy(bytes).to_string(),
+        };
+        let output = match shell.filter.as_mut() {
+            Some(filter) => filter.apply(&output),
+            None => output,
+        };
+        if let Some(cwd) = completion::reported_directory(&output) {
+            shell.cwd = Some(cwd);
+        }
+        shell.scrollback.push(&output);
+        output
+    };
+    if let Ok(mut session) = data.session.try_write() {
+        session.last_activity = Utc::now();
+    }
+    if !output.is_empty() {
+        publish(data, ShellEvent::Output(output));
+    }
+}
+
+// Sent outside the shell lock, since a full subscriber holds the reader up until it has
+// caught up
+fn publish(data: &SSHSessionData, event: ShellEvent) {
+    let subscribers = {
+        let mut shell = data.shell.blocking_lock();
+        shell.subscribers.retain(|subscriber| !subscriber.is_closed());
+        shell.subscribers.clone()
+    };
+    for subscriber in subscribers {
+        let _ = subscriber.blocking_send(event.clone());
+    }
+}
+
+#[cfg(unix)]
+fn wait_readable(socket: &TcpStream) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let mut fd = libc::pollfd {
+        fd: socket.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: a single pollfd for a socket that outlives the call
+    let ready = unsafe { libc::poll(&mut fd, 1, WAIT_INTERVAL.as_millis() as libc::c_int) };
+    ready > 0
+}
+
+// libssh2 keeps the socket non-blocking, so a peek tells whether anything arrived
+#[cfg(not(unix))]
+fn wait_readable(socket: &TcpStream) -> bool {
+    match socket.peek(&mut [0; 1]) {
+        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+            std::thread::sleep(Duration::from_millis(20));
+            false
+        }
+        _ => true,
+    }
+}
@@ -0,0 +1,96 @@
+use crate::types::SSHConnectionConfig;
+use dashmap::DashMap;
+use std::collections::VecDeque;
+
+const MAX_RECENT_PATHS: usize = 20;
+
+// Remote directories browsed over SFTP, most recent first, per user@host:port so
+// every session to the same host shares them
+#[derive(Default)]
+pub struct RecentPaths {
+    hosts: DashMap<String, VecDeque<String>>,
+}
+
+impl RecentPaths {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn visit(&self, config: &SSHConnectionConfig, path: &str) {
+        let path = normalize(path);
+        if path.is_empty() {
+            return;
+        }
+        let mut paths = self.hosts.entry(host_key(config)).or_default();
+        paths.retain(|known| *known != path);
+        paths.push_front(path);
+        paths.truncate(MAX_RECENT_PATHS);
+    }
+
+    pub fn list(&self, config: &SSHConnectionConfig) -> Vec<String> {
+        self.hosts.get(&host_key(config))
+            .map(|paths| paths.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+pub(crate) fn host_key(config: &SSHConnectionConfig) -> String {
+    format!("{}@{}:{}", config.username, config.hostname.to_lowercase(), config.port)
+}
+
+fn normalize(path: &str) -> String {
+    let path = path.trim();
+    if path.len() > 1 {
+        path.trim_end_matches('/').to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FileTransferProtocol, SessionDefaults};
+
+    fn config(hostname: &str) -> SSHConnectionConfig {
+        SSHConnectionConfig {
+            id: "session".to_string(),
+            hostname: hostname.to_string(),
+            port: 22,
+            username: "deploy".to_string(),
+            password: None,
+            private_key: None,
+            private_key_path: None,
+            passphrase: None,
+            keep_alive: None,
+            ready_timeout: None,
+            websocket_url: None,
+            collect_host_info: false,
+            charset: None,
+            terminal: None,
+            jump_hosts: Vec::new(),
+            keyboard_interactive: false,
+            file_transfer: FileTransferProtocol::Auto,
+            defaults: SessionDefaults::default(),
+            credential: None,
+            approval: None,
+        }
+    }
+
+    #[test]
+    fn test_most_recent_first_per_host() {
+        let recent = RecentPaths::new();
+        recent.visit(&config("web"), "/var/log");
+        recent.visit(&config("web"), "/etc/");
+        recent.visit(&config("WEB"), "/var/log/");
+        recent.visit(&config("db"), "/srv");
+
+        assert_eq!(recent.list(&config("web")), ["/var/log", "/etc"]);
+        assert_eq!(recent.list(&config("db")), ["/srv"]);
+
+        for i in 0..MAX_RECENT_PATHS + 5 {
+            recent.visit(&config("web"), &format!("/tmp/{}", i));
+        }
+        assert_eq!(recent.list(&config("web")).len(), MAX_RECENT_PATHS);
+    }
+}
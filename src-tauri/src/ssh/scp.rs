@@ -0,0 +1,65 @@
+use super::copy_chunks;
+use crate::types::{AppError, AppResult};
+use ssh2::{Channel, Session};
+use std::io::{Read, Write};
+use std::path::Path;
+
+// Plain SCP, for hosts whose SSH server has no SFTP subsystem. It can't seek, list or stat,
+// so it only moves whole files, and a resumed download reads past the bytes it already has.
+
+// The mode new files get, as with sftp.create
+const UPLOAD_MODE: i32 = 0o644;
+
+// Send exactly size bytes from reader to remote_path, reporting the running total like
+// copy_chunks. Returns the bytes sent.
+pub fn send(
+    session: &Session,
+    remote_path: &str,
+    size: u64,
+    reader: &mut impl Read,
+    progress: &impl Fn(u64) -> AppResult<()>,
+) -> AppResult<u64> {
+    let mut channel = session.scp_send(Path::new(remote_path), UPLOAD_MODE, size, None)
+        .map_err(|e| AppError::FileOperationFailed(format!("Failed to start SCP upload: {}", e)))?;
+    let sent = copy_chunks(&mut reader.take(size), &mut channel, progress)?;
+    // The server waits for the announced size, so a short read can't be sent as is
+    if sent < size {
+        return Err(AppError::FileOperationFailed(format!("Local file shrank to {} of {} bytes during the upload", sent, size)));
+    }
+    finish(channel)?;
+    Ok(sent)
+}
+
+// Copy remote_path from offset on into the writer create gives for that offset, reporting
+// the bytes written so far, offset included. Returns the file's size.
+pub fn receive<W: Write>(
+    session: &Session,
+    remote_path: &str,
+    offset: u64,
+    create: impl FnOnce(u64) -> AppResult<W>,
+    progress: &impl Fn(u64) -> AppResult<()>,
+) -> AppResult<u64> {
+    let (mut channel, stat) = session.scp_recv(Path::new(remote_path))
+        .map_err(|e| AppError::FileOperationFailed(format!("Failed to start SCP download: {}", e)))?;
+    let size = stat.size();
+    let offset = offset.min(size);
+
+    std::io::copy(&mut (&mut channel).take(offset), &mut std::io::sink())
+        .map_err(|e| AppError::FileOperationFailed(format!("Failed to read file: {}", e)))?;
+    let mut writer = create(offset)?;
+    // Read no further than the file: the protocol's status byte follows it
+    let copied = copy_chunks(&mut (&mut channel).take(size - offset), &mut writer, &|done| progress(offset + done))?;
+    if offset + copied < size {
+        return Err(AppError::FileOperationFailed(format!("SCP download ended after {} of {} bytes", offset + copied, size)));
+    }
+    finish(channel)?;
+    Ok(size)
+}
+
+fn finish(mut channel: Channel) -> AppResult<()> {
+    channel.send_eof()
+        .and_then(|()| channel.wait_eof())
+        .and_then(|()| channel.close())
+        .and_then(|()| channel.wait_close())
+        .map_err(|e| AppError::FileOperationFailed(format!("Failed to finish SCP transfer: {}", e)))
+}
@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+
+// Recent output kept per session, replayed to clients that attach to it later. A profile
+// can ask for more, up to MAX_SCROLLBACK_BYTES.
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 64 * 1024;
+pub const MAX_SCROLLBACK_BYTES: usize = 16 * 1024 * 1024;
+
+// The last `limit` bytes of a shell's decoded output
+pub struct Scrollback {
+    chunks: VecDeque<String>,
+    len: usize,
+    limit: usize,
+    // Bytes pushed since the shell opened, dropped ones included
+    total: u64,
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scrollback {
+    pub fn new() -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            len: 0,
+            limit: DEFAULT_SCROLLBACK_BYTES,
+            total: 0,
+        }
+    }
+
+    // Takes effect as output comes in
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit.unwrap_or(DEFAULT_SCROLLBACK_BYTES).clamp(1, MAX_SCROLLBACK_BYTES);
+    }
+
+    pub fn push(&mut self, output: &str) {
+        if output.is_empty() {
+            return;
+        }
+        self.chunks.push_back(output.to_string());
+        self.len += output.len();
+        self.total += output.len() as u64;
+
+        while self.len > self.limit {
+            let Some(front) = self.chunks.front_mut() else { break };
+            let excess = self.len - self.limit;
+            if excess >= front.len() {
+                self.len -= front.len();
+                self.chunks.pop_front();
+                continue;
+            }
+            // Cut at the next line so the replay doesn't start inside an escape sequence
+            let mut cut = front[excess..].find('\n').map_or(excess, |newline| excess + newline + 1);
+            while !front.is_char_boundary(cut) {
+                cut += 1;
+            }
+            front.drain(..cut);
+            self.len -= cut;
+        }
+    }
+
+    pub fn contents(&self) -> String {
+        let mut contents = String::with_capacity(self.len);
+        for chunk in &self.chunks {
+            contents.push_str(chunk);
+        }
+        contents
+    }
+
+    // Where the next output starts, counted from the shell's first byte
+    pub fn offset(&self) -> u64 {
+        self.total
+    }
+
+    // Output from an earlier offset on, or None once it is no longer kept
+    pub fn since(&self, offset: u64) -> Option<String> {
+        let start = self.total - self.len as u64;
+        if offset < start || offset > self.total {
+            return None;
+        }
+        self.contents().get((offset - start) as usize..).map(str::to_string)
+    }
+
+    // Output since the last newline, e.g. the prompt the shell is waiting at
+    pub fn last_line(&self) -> String {
+        let mut line = String::new();
+        for chunk in self.chunks.iter().rev() {
+            match chunk.rfind('\n') {
+                Some(newline) => {
+                    line.insert_str(0, &chunk[newline + 1..]);
+                    break;
+                }
+                None => line.insert_str(0, chunk),
+            }
+        }
+        line
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+        self.total = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_recent_output_from_a_line_start() {
+        let mut scrollback = Scrollback::new();
+        scrollback.push("$ ls\r\n");
+        assert_eq!(scrollback.contents(), "$ ls\r\n");
+
+        let line = format!("{}\r\n", "é".repeat(99));
+        for _ in 0..DEFAULT_SCROLLBACK_BYTES / line.len() + 10 {
+            scrollback.push(&line);
+        }
+        scrollback.push("$ ");
+        let contents = scrollback.contents();
+        assert!(contents.len() <= DEFAULT_SCROLLBACK_BYTES);
+        assert!(contents.starts_with('é'));
+        assert!(contents.ends_with("\r\n$ "));
+    }
+
+    #[test]
+    fn test_output_since_offset() {
+        let mut scrollback = Scrollback::new();
+        scrollback.push("$ make\r\n");
+        let mark = scrollback.offset();
+        scrollback.push("cc -o app main.c\r\n");
+        assert_eq!(scrollback.since(mark).as_deref(), Some("cc -o app main.c\r\n"));
+        assert_eq!(scrollback.since(scrollback.offset()).as_deref(), Some(""));
+        assert!(scrollback.since(scrollback.offset() + 1).is_none());
+
+        // Once the marked output has scrolled out, it is gone
+        let line = format!("{}\n", "x".repeat(1023));
+        for _ in 0..DEFAULT_SCROLLBACK_BYTES / line.len() + 1 {
+            scrollback.push(&line);
+        }
+        assert!(scrollback.since(mark).is_none());
+        assert_eq!(scrollback.since(scrollback.offset() - line.len() as u64).as_deref(), Some(line.as_str()));
+    }
+
+    #[test]
+    fn test_profile_limit() {
+        let mut scrollback = Scrollback::new();
+        scrollback.set_limit(Some(DEFAULT_SCROLLBACK_BYTES * 4));
+        let line = format!("{}\n", "x".repeat(1023));
+        for _ in 0..DEFAULT_SCROLLBACK_BYTES * 2 / line.len() {
+            scrollback.push(&line);
+        }
+        assert_eq!(scrollback.contents().len(), DEFAULT_SCROLLBACK_BYTES * 2);
+
+        scrollback.set_limit(Some(line.len()));
+        scrollback.push(&line);
+        assert_eq!(scrollback.contents(), line);
+    }
+}
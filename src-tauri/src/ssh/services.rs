@@ -0,0 +1,210 @@
+use crate::types::{AppError, AppResult, CommandExecResult, ServiceAction, ServiceStatus, ServiceUnit, SudoOptions};
+use std::collections::HashMap;
+
+const SECTION_PREFIX: &str = "==";
+const LOG_LINES: usize = 20;
+
+// Loaded units and unit files are listed separately so enabled-but-stopped
+// services show up too
+pub const LIST_COMMAND: &str = "\
+echo '==units'; systemctl list-units --type=service --all --no-legend --no-pager --plain; \
+echo '==files'; systemctl list-unit-files --type=service --no-legend --no-pager; \
+true";
+
+const SHOW_PROPERTIES: &str = "Id,Description,LoadState,ActiveState,SubState,UnitFileState,MainPID,ActiveEnterTimestamp";
+
+// Unit names go into a shell command unquoted, so only allow what systemd allows
+pub fn validate_unit(unit: &str) -> AppResult<()> {
+    let valid = !unit.is_empty()
+        && unit.len() <= 256
+        && !unit.starts_with('-')
+        && unit.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '@' | '.' | '_' | ':' | '-' | '\\'));
+    if !valid {
+        return Err(AppError::ValidationError(format!("Invalid unit name: {}", unit)));
+    }
+    Ok(())
+}
+
+pub fn status_command(unit: &str) -> AppResult<String> {
+    validate_unit(unit)?;
+    Ok(format!(
+        "echo '==show'; systemctl show {unit} --no-pager -p {props}; \
+         echo '==logs'; journalctl -u {unit} -n {lines} --no-pager -o short-iso 2>/dev/null; true",
+        unit = unit,
+        props = SHOW_PROPERTIES,
+        lines = LOG_LINES
+    ))
+}
+
+// Returns the command and what to feed its stdin
+pub fn action_command(unit: &str, action: ServiceAction, sudo: &SudoOptions) -> AppResult<(String, Option<String>)> {
+    validate_unit(unit)?;
+    Ok(sudo.wrap(&format!("systemctl {} {}", action.verb(), unit)))
+}
+
+// Map a failed systemctl/sudo run to an error the panel can act on
+pub fn action_error(result: &CommandExecResult) -> AppError {
+    let stderr = result.stderr.trim();
+    let lower = stderr.to_lowercase();
+    if lower.contains("password is required")
+        || lower.contains("incorrect password")
+        || lower.contains("not in the sudoers")
+        || lower.contains("access denied")
+        || lower.contains("interactive authentication required")
+    {
+        AppError::PermissionDenied(stderr.to_string())
+    } else if lower.contains("not found") || lower.contains("not loaded") {
+        AppError::NotFound(stderr.to_string())
+    } else {
+        AppError::OperationFailed(format!("systemctl exited with {}: {}", result.exit_code, stderr))
+    }
+}
+
+fn sections(output: &str) -> HashMap<&str, Vec<&str>> {
+    let mut sections: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut current = None;
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix(SECTION_PREFIX) {
+            current = Some(name.trim());
+            sections.entry(name.trim()).or_default();
+        } else if let Some(name) = current {
+            sections.entry(name).or_default().push(line);
+        }
+    }
+    sections
+}
+
+pub fn parse_list(output: &str) -> Vec<ServiceUnit> {
+    let sections = sections(output);
+    let mut units: Vec<ServiceUnit> = sections.get("units").into_iter().flatten()
+        .filter_map(|line| parse_unit_line(line))
+        .collect();
+
+    for line in sections.get("files").into_iter().flatten() {
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(state)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        // Templates (foo@.service) can't run on their own
+        if name.ends_with("@.service") {
+            continue;
+        }
+        match units.iter_mut().find(|unit| unit.name == name) {
+            Some(unit) => unit.unit_file_state = Some(state.to_string()),
+            None => units.push(ServiceUnit {
+                name: name.to_string(),
+                load_state: "not-loaded".to_string(),
+                active_state: "inactive".to_string(),
+                sub_state: "dead".to_string(),
+                unit_file_state: Some(state.to_string()),
+                ..ServiceUnit::default()
+            }),
+        }
+    }
+
+    units.sort_by(|a, b| a.name.cmp(&b.name));
+    units
+}
+
+// list-units --plain: UNIT LOAD ACTIVE SUB DESCRIPTION...
+fn parse_unit_line(line: &str) -> Option<ServiceUnit> {
+    // Failed units may still carry a leading marker
+    let line = line.trim_start_matches(|c: char| !c.is_ascii_alphanumeric());
+    let mut fields = line.split_whitespace();
+    let name = fields.next()?.to_string();
+    if !name.ends_with(".service") {
+        return None;
+    }
+    Some(ServiceUnit {
+        name,
+        load_state: fields.next()?.to_string(),
+        active_state: fields.next()?.to_string(),
+        sub_state: fields.next()?.to_string(),
+        description: fields.collect::<Vec<_>>().join(" "),
+        unit_file_state: None,
+    })
+}
+
+pub fn parse_status(output: &str) -> AppResult<ServiceStatus> {
+    let sections = sections(output);
+    let properties: HashMap<&str, &str> = sections.get("show").into_iter().flatten()
+        .filter_map(|line| line.split_once('='))
+        .collect();
+    let property = |name: &str| properties.get(name).map(|value| value.trim()).filter(|value| !value.is_empty());
+
+    let name = property("Id").ok_or_else(|| AppError::OperationFailed("systemctl show returned nothing".to_string()))?;
+    if property("LoadState") == Some("not-found") {
+        return Err(AppError::NotFound(format!("Unit {} not found", name)));
+    }
+
+    Ok(ServiceStatus {
+        unit: ServiceUnit {
+            name: name.to_string(),
+            description: property("Description").unwrap_or_default().to_string(),
+            load_state: property("LoadState").unwrap_or_default().to_string(),
+            active_state: property("ActiveState").unwrap_or_default().to_string(),
+            sub_state: property("SubState").unwrap_or_default().to_string(),
+            unit_file_state: property("UnitFileState").map(str::to_string),
+        },
+        // systemd reports 0 when there is no main process
+        main_pid: property("MainPID").and_then(|pid| pid.parse().ok()).filter(|pid| *pid != 0),
+        active_since: property("ActiveEnterTimestamp").map(str::to_string),
+        logs: sections.get("logs").into_iter().flatten()
+            .filter(|line| !line.starts_with("-- "))
+            .map(|line| line.to_string())
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list_merges_unit_files() {
+        let output = "==units\n\
+            nginx.service     loaded active   running A high performance web server\n\
+            ● cron.service    loaded failed   failed  Regular background program processing daemon\n\
+            ==files\n\
+            nginx.service     enabled  enabled\n\
+            getty@.service    enabled  enabled\n\
+            backup.service    disabled enabled\n";
+        let units = parse_list(output);
+        let names: Vec<&str> = units.iter().map(|unit| unit.name.as_str()).collect();
+        assert_eq!(names, ["backup.service", "cron.service", "nginx.service"]);
+        assert_eq!(units[0].load_state, "not-loaded");
+        assert_eq!(units[1].active_state, "failed");
+        assert_eq!(units[2].unit_file_state.as_deref(), Some("enabled"));
+        assert_eq!(units[2].description, "A high performance web server");
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let output = "==show\nId=nginx.service\nDescription=nginx\nLoadState=loaded\nActiveState=active\n\
+            SubState=running\nUnitFileState=enabled\nMainPID=812\nActiveEnterTimestamp=Mon 2024-05-06 10:00:00 UTC\n\
+            ==logs\n-- No entries --\n2024-05-06T10:00:00+0000 web-1 systemd[1]: Started nginx.\n";
+        let status = parse_status(output).unwrap();
+        assert_eq!(status.main_pid, Some(812));
+        assert_eq!(status.logs.len(), 1);
+
+        let missing = parse_status("==show\nId=nope.service\nLoadState=not-found\nMainPID=0\n==logs\n");
+        assert!(matches!(missing, Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_action_command() {
+        assert!(validate_unit("nginx.service; rm -rf /").is_err());
+        assert!(validate_unit("--all").is_err());
+
+        let (command, input) = action_command("nginx", ServiceAction::Restart, &SudoOptions::default()).unwrap();
+        assert_eq!((command.as_str(), input), ("systemctl restart nginx", None));
+
+        let sudo = SudoOptions { enabled: true, password: Some("hunter2".to_string()) };
+        let (command, input) = action_command("nginx", ServiceAction::Stop, &sudo).unwrap();
+        assert_eq!(command, "sudo -S -p '' systemctl stop nginx");
+        assert_eq!(input.as_deref(), Some("hunter2\n"));
+
+        let sudo = SudoOptions { enabled: true, password: None };
+        assert_eq!(action_command("nginx", ServiceAction::Start, &sudo).unwrap().0, "sudo -n systemctl start nginx");
+    }
+}
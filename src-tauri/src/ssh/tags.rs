@@ -0,0 +1,62 @@
+use crate::types::{AppError, AppResult, SessionTags};
+
+const MAX_LABELS: usize = 16;
+const MAX_LABEL_LEN: usize = 32;
+const MAX_NOTES_LEN: usize = 4096;
+
+// Trimmed, with duplicate and empty labels dropped and the colour lowercased
+pub fn normalize(tags: SessionTags) -> AppResult<SessionTags> {
+    let mut labels: Vec<String> = Vec::new();
+    for label in tags.labels.iter().map(|label| label.trim()).filter(|label| !label.is_empty()) {
+        if label.chars().count() > MAX_LABEL_LEN {
+            return Err(AppError::ValidationError(format!(
+                "Label {} is longer than {} characters", label, MAX_LABEL_LEN
+            )));
+        }
+        if !labels.iter().any(|known| known.eq_ignore_ascii_case(label)) {
+            labels.push(label.to_string());
+        }
+    }
+    if labels.len() > MAX_LABELS {
+        return Err(AppError::ValidationError(format!("At most {} labels are allowed", MAX_LABELS)));
+    }
+
+    let color = match tags.color.as_deref().map(str::trim).filter(|color| !color.is_empty()) {
+        Some(color) if is_hex_color(color) => Some(color.to_ascii_lowercase()),
+        Some(color) => return Err(AppError::ValidationError(format!("Invalid color: {}, expected #rrggbb", color))),
+        None => None,
+    };
+
+    let notes = tags.notes.filter(|notes| !notes.trim().is_empty());
+    if notes.as_ref().is_some_and(|notes| notes.len() > MAX_NOTES_LEN) {
+        return Err(AppError::ValidationError(format!("Notes are longer than {} bytes", MAX_NOTES_LEN)));
+    }
+
+    Ok(SessionTags { labels, color, notes })
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = normalize(SessionTags {
+            labels: vec![" prod ".to_string(), "PROD".to_string(), String::new(), "eu-west".to_string()],
+            color: Some("#D32F2F".to_string()),
+            notes: Some("  ".to_string()),
+        }).unwrap();
+        assert_eq!(tags.labels, ["prod", "eu-west"]);
+        assert_eq!(tags.color.as_deref(), Some("#d32f2f"));
+        assert!(tags.notes.is_none());
+
+        let invalid = SessionTags { color: Some("red".to_string()), ..Default::default() };
+        assert!(normalize(invalid).is_err());
+        let long = SessionTags { labels: vec!["x".repeat(MAX_LABEL_LEN + 1)], ..Default::default() };
+        assert!(normalize(long).is_err());
+    }
+}
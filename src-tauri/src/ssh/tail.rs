@@ -0,0 +1,342 @@
+use super::shell_quote;
+use crate::types::{AppError, AppResult};
+use dashmap::DashMap;
+use ssh2::{Channel, Session};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const IDLE_SLEEP: Duration = Duration::from_millis(50);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BUFFER_SIZE: usize = 16 * 1024;
+// A line this long without a newline is flushed as is
+const MAX_LINE_BYTES: usize = 64 * 1024;
+// How far back the SFTP fallback reads to find the initial lines
+const BACKLOG_BYTES: u64 = 64 * 1024;
+pub const DEFAULT_INITIAL_LINES: u32 = 50;
+const MAX_INITIAL_LINES: u32 = 5000;
+// Shell exit code for "command not found"
+const EXIT_NOT_FOUND: i32 = 127;
+
+// What a follow streams: a remote file, or the output of a long-running command
+// such as `docker logs -f`
+#[derive(Debug, Clone)]
+pub enum FollowTarget {
+    File {
+        path: String,
+        initial_lines: Option<u32>,
+    },
+    Command {
+        command: String,
+        // Written to the command's stdin first, e.g. a sudo -S password
+        input: Option<String>,
+    },
+}
+
+impl FollowTarget {
+    fn describe(&self) -> &str {
+        match self {
+            FollowTarget::File { path, .. } => path,
+            FollowTarget::Command { command, .. } => command,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TailEvent {
+    Lines(Vec<String>),
+    // The follow ended, with the reason if it failed
+    Closed(Option<String>),
+}
+
+struct TailEntry {
+    session_id: String,
+    cancel: CancellationToken,
+}
+
+pub struct FileTailManager {
+    tails: Arc<DashMap<String, TailEntry>>,
+}
+
+impl FileTailManager {
+    pub fn new() -> Self {
+        Self {
+            tails: Arc::new(DashMap::new()),
+        }
+    }
+
+    // Follow `target` over a dedicated, already authenticated SSH session, which is
+    // disconnected when the follow stops. Returns the follow ID and its line stream.
+    pub fn start(
+        &self,
+        session_id: &str,
+        target: FollowTarget,
+        session: Session,
+    ) -> AppResult<(String, mpsc::UnboundedReceiver<TailEvent>)> {
+        if target.describe().trim().is_empty() {
+            return Err(AppError::ValidationError("Nothing to follow".to_string()));
+        }
+
+        let follow_id = Uuid::new_v4().to_string();
+        let cancel = CancellationToken::new();
+        self.tails.insert(follow_id.clone(), TailEntry {
+            session_id: session_id.to_string(),
+            cancel: cancel.clone(),
+        });
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        log::info!("Following {} for session {} as {}", target.describe(), session_id, follow_id);
+        let worker = TailWorker {
+            session,
+            target,
+            sender,
+            cancel,
+        };
+        let tails = self.tails.clone();
+        let id = follow_id.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let sender = worker.sender.clone();
+            let result = worker.run();
+            tails.remove(&id);
+            if let Err(e) = &result {
+                log::warn!("File follow {} failed: {}", id, e);
+            }
+            let _ = sender.send(TailEvent::Closed(result.err()));
+        });
+
+        Ok((follow_id, receiver))
+    }
+
+    pub fn stop(&self, follow_id: &str) -> AppResult<()> {
+        let entry = self.tails.get(follow_id)
+            .ok_or_else(|| AppError::NotFound(format!("File follow {}", follow_id)))?;
+        entry.cancel.cancel();
+        Ok(())
+    }
+
+    pub fn close_session(&self, session_id: &str) {
+        for entry in self.tails.iter() {
+            if entry.session_id == session_id {
+                entry.cancel.cancel();
+            }
+        }
+    }
+
+    pub fn close_all(&self) {
+        for entry in self.tails.iter() {
+            entry.cancel.cancel();
+        }
+    }
+}
+
+impl Default for FileTailManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Splits a byte stream into lines, holding back a trailing partial line
+#[derive(Default)]
+struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, data: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(data);
+        let mut lines = Vec::new();
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            lines.push(decode_line(&line[..end]));
+        }
+        if self.pending.len() > MAX_LINE_BYTES {
+            lines.push(decode_line(&std::mem::take(&mut self.pending)));
+        }
+        lines
+    }
+}
+
+fn decode_line(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_end_matches('\r').to_string()
+}
+
+fn tail_command(path: &str, initial_lines: u32) -> String {
+    format!("tail -n {} -F -- {}", initial_lines, shell_quote(path))
+}
+
+struct TailWorker {
+    session: Session,
+    target: FollowTarget,
+    sender: mpsc::UnboundedSender<TailEvent>,
+    cancel: CancellationToken,
+}
+
+impl TailWorker {
+    // Runs on a blocking thread. Files are followed with `tail -F`, falling back to
+    // polling over SFTP when the host has no tail.
+    fn run(self) -> Result<(), String> {
+        let result = match &self.target {
+            FollowTarget::File { path, initial_lines } => {
+                let initial_lines = initial_lines.unwrap_or(DEFAULT_INITIAL_LINES).min(MAX_INITIAL_LINES);
+                match self.follow_exec(&tail_command(path, initial_lines), None) {
+                    Err(TailError::Unavailable) => {
+                        log::info!("tail is not available, polling {} over SFTP", path);
+                        self.follow_with_sftp(path, initial_lines)
+                    }
+                    Err(TailError::Failed(e)) => Err(e),
+                    Ok(()) => Ok(()),
+                }
+            }
+            FollowTarget::Command { command, input } => match self.follow_exec(command, input.as_deref()) {
+                Err(TailError::Unavailable) => Err(format!("Command not found: {}", command)),
+                Err(TailError::Failed(e)) => Err(e),
+                Ok(()) => Ok(()),
+            },
+        };
+        let _ = self.session.disconnect(None, "File follow closed", None);
+        result
+    }
+
+    fn send(&self, lines: Vec<String>) {
+        if lines.is_empty() {
+            return;
+        }
+        // Nobody is listening any more, stop quietly
+        if self.sender.send(TailEvent::Lines(lines)).is_err() {
+            self.cancel.cancel();
+        }
+    }
+
+    fn follow_exec(&self, command: &str, input: Option<&str>) -> Result<(), TailError> {
+        let mut channel = self.session.channel_session()
+            .map_err(|e| TailError::Failed(format!("Failed to open channel: {}", e)))?;
+        channel.exec(command)
+            .map_err(|e| TailError::Failed(format!("Failed to start {}: {}", command, e)))?;
+        if let Some(input) = input {
+            channel.write_all(input.as_bytes())
+                .and_then(|_| channel.flush())
+                .map_err(|e| TailError::Failed(format!("Failed to write input: {}", e)))?;
+        }
+
+        self.session.set_blocking(false);
+        let result = self.pump_channel(&mut channel);
+        self.session.set_blocking(true);
+
+        if self.cancel.is_cancelled() {
+            let _ = channel.close();
+            return Ok(());
+        }
+        let received_any = result.map_err(TailError::Failed)?;
+
+        // The command exited on its own: missing tool, unreadable file or similar
+        let _ = channel.wait_close();
+        let mut stderr = String::new();
+        let _ = channel.stderr().read_to_string(&mut stderr);
+        match channel.exit_status() {
+            Ok(EXIT_NOT_FOUND) if !received_any => Err(TailError::Unavailable),
+            Ok(0) => Ok(()),
+            Ok(code) => Err(TailError::Failed(format!("Exited with {}: {}", code, stderr.trim()))),
+            Err(e) => Err(TailError::Failed(e.to_string())),
+        }
+    }
+
+    // Forward the command's output until it exits or the follow is cancelled. Returns whether any output arrived.
+    fn pump_channel(&self, channel: &mut Channel) -> Result<bool, String> {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut lines = LineBuffer::default();
+        let mut received_any = false;
+
+        while !self.cancel.is_cancelled() {
+            match channel.read(&mut buffer) {
+                Ok(0) if channel.eof() => break,
+                Ok(0) => std::thread::sleep(IDLE_SLEEP),
+                Ok(read) => {
+                    received_any = true;
+                    self.send(lines.push(&buffer[..read]));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(IDLE_SLEEP),
+                Err(e) => return Err(format!("Failed to read tail output: {}", e)),
+            }
+        }
+        Ok(received_any)
+    }
+
+    // Poll the file size and read whatever was appended. A shrinking file is taken
+    // to be truncated or rotated and is read again from the start.
+    fn follow_with_sftp(&self, path: &str, initial_lines: u32) -> Result<(), String> {
+        let sftp = self.session.sftp().map_err(|e| format!("Failed to open SFTP: {}", e))?;
+        let path = Path::new(path);
+        let size = sftp.stat(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?.size.unwrap_or(0);
+
+        // Start with the last few lines, like tail -n
+        let start = size.saturating_sub(BACKLOG_BYTES);
+        let backlog = self.read_range(&sftp, path, start)?;
+        let mut lines = LineBuffer::default();
+        let mut initial = lines.push(&backlog);
+        // The first line is likely cut off when we started mid-file
+        if start > 0 && !initial.is_empty() {
+            initial.remove(0);
+        }
+        let skip = initial.len().saturating_sub(initial_lines as usize);
+        self.send(initial.split_off(skip));
+        let mut offset = size;
+
+        while !self.cancel.is_cancelled() {
+            std::thread::sleep(POLL_INTERVAL);
+            // Missing while being rotated, try again on the next poll
+            let Ok(stat) = sftp.stat(path) else {
+                continue;
+            };
+            let size = stat.size.unwrap_or(0);
+            if size < offset {
+                offset = 0;
+                lines = LineBuffer::default();
+            }
+            if size > offset {
+                let data = self.read_range(&sftp, path, offset)?;
+                offset += data.len() as u64;
+                self.send(lines.push(&data));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_range(&self, sftp: &ssh2::Sftp, path: &Path, offset: u64) -> Result<Vec<u8>, String> {
+        let mut file = sftp.open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+        file.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).map_err(|e| e.to_string())?;
+        Ok(data)
+    }
+}
+
+enum TailError {
+    Unavailable,
+    Failed(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_buffer_holds_partial_lines() {
+        let mut lines = LineBuffer::default();
+        assert_eq!(lines.push(b"first\r\nsec"), ["first"]);
+        assert!(lines.push(b"ond").is_empty());
+        assert_eq!(lines.push(b"\nthird\n"), ["second", "third"]);
+
+        let long = vec![b'x'; MAX_LINE_BYTES + 1];
+        assert_eq!(lines.push(&long).len(), 1);
+    }
+
+    #[test]
+    fn test_tail_command_quotes_path() {
+        assert_eq!(tail_command("/var/log/app's log", 20), "tail -n 20 -F -- '/var/log/app'\\''s log'");
+    }
+}
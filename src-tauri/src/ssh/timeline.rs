@@ -0,0 +1,112 @@
+use crate::types::{AppResult, TimelineEvent, TimelineStage};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+// Enough for a day of reconnects without growing with the session's age
+const MAX_EVENTS: usize = 256;
+
+// What happened to a session and how long each step took, oldest first
+#[derive(Default)]
+pub struct Timeline {
+    events: Mutex<VecDeque<TimelineEvent>>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Something that happened at once, like a disconnect
+    pub fn record(&self, stage: TimelineStage, detail: Option<String>, error: Option<String>) {
+        self.push(TimelineEvent {
+            stage,
+            at: Utc::now(),
+            duration_ms: None,
+            detail,
+            error,
+        });
+    }
+
+    pub fn events(&self) -> Vec<TimelineEvent> {
+        self.events.lock().iter().cloned().collect()
+    }
+
+    pub fn has(&self, stage: TimelineStage) -> bool {
+        self.events.lock().iter().any(|event| event.stage == stage)
+    }
+
+    fn push(&self, event: TimelineEvent) {
+        let mut events = self.events.lock();
+        if events.len() == MAX_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+}
+
+// A step under way; finishing it records how long it took and whether it failed
+pub struct Step {
+    stage: TimelineStage,
+    detail: Option<String>,
+    at: DateTime<Utc>,
+    started: Instant,
+}
+
+impl Step {
+    pub fn start(stage: TimelineStage, detail: Option<String>) -> Self {
+        Self {
+            stage,
+            detail,
+            at: Utc::now(),
+            started: Instant::now(),
+        }
+    }
+
+    // Dedicated connections (forwards, follows, watches) have no timeline to record into
+    pub fn finish<T>(self, timeline: Option<&Timeline>, result: &AppResult<T>) {
+        let Some(timeline) = timeline else {
+            return;
+        };
+        timeline.push(TimelineEvent {
+            stage: self.stage,
+            at: self.at,
+            duration_ms: Some(self.started.elapsed().as_millis() as u64),
+            detail: self.detail,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AppError;
+
+    #[test]
+    fn test_timeline() {
+        let timeline = Timeline::new();
+        Step::start(TimelineStage::Dns, Some("example.com:22".to_string())).finish(Some(&timeline), &Ok(()));
+        Step::start(TimelineStage::Tcp, None).finish::<()>(Some(&timeline), &Err(AppError::SSHConnectionFailed("refused".to_string())));
+        timeline.record(TimelineStage::Disconnect, Some("client disconnected".to_string()), None);
+
+        let events = timeline.events();
+        assert_eq!(events.iter().map(|event| event.stage).collect::<Vec<_>>(), [TimelineStage::Dns, TimelineStage::Tcp, TimelineStage::Disconnect]);
+        assert!(events[0].duration_ms.is_some() && events[0].error.is_none());
+        assert!(events[1].error.as_deref().is_some_and(|error| error.contains("refused")));
+        assert!(events[2].duration_ms.is_none());
+        assert!(timeline.has(TimelineStage::Tcp) && !timeline.has(TimelineStage::Auth));
+
+        let json = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(json["stage"], "dns");
+        assert!(json.get("durationMs").is_some() && json.get("error").is_none());
+
+        for _ in 0..MAX_EVENTS {
+            timeline.record(TimelineStage::Error, None, Some("read failed".to_string()));
+        }
+        let events = timeline.events();
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert!(events.iter().all(|event| event.stage == TimelineStage::Error));
+    }
+}
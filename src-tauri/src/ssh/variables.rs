@@ -0,0 +1,143 @@
+use crate::types::{ShellAlias, ShellVariable, ShellVariables};
+use chrono::Utc;
+
+// Aliases live in rc files, which only an interactive login shell reads; its stdin is
+// closed so nothing in them waits for input. The script itself must stay free of single
+// quotes.
+pub const COLLECT_COMMAND: &str = "sh -c '\
+echo ==env; env; \
+echo ==alias; \"${SHELL:-sh}\" -ic alias </dev/null 2>/dev/null; \
+true'";
+
+// Values of variables named like these are left out, so secrets don't end up on screen
+const SENSITIVE_NAMES: &[&str] = &["SECRET", "TOKEN", "PASSWORD", "PASSWD", "API_KEY", "ACCESS_KEY", "PRIVATE_KEY", "CREDENTIAL"];
+
+pub fn parse(stdout: &str) -> ShellVariables {
+    let (env, aliases) = match stdout.split_once("==alias\n") {
+        Some((env, aliases)) => (env, aliases),
+        None => (stdout, ""),
+    };
+    let env = env.strip_prefix("==env\n").unwrap_or(env);
+
+    let mut variables = parse_env(env);
+    variables.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut aliases: Vec<ShellAlias> = aliases.lines().filter_map(parse_alias).collect();
+    aliases.sort_by(|a, b| a.name.cmp(&b.name));
+    aliases.dedup_by(|a, b| a.name == b.name);
+
+    ShellVariables {
+        variables,
+        aliases,
+        collected_at: Utc::now(),
+    }
+}
+
+// env prints multi-line values as they are, so a line that doesn't start a new variable
+// continues the last one. Exported bash functions (BASH_FUNC_name%%=() { ... }) are skipped.
+fn parse_env(env: &str) -> Vec<ShellVariable> {
+    let mut variables: Vec<ShellVariable> = Vec::new();
+    let mut continuing = false;
+    for line in env.lines() {
+        match line.split_once('=') {
+            Some((name, value)) if is_identifier(name) => {
+                variables.push(ShellVariable {
+                    name: name.to_string(),
+                    value: Some(value.to_string()),
+                    sensitive: false,
+                });
+                continuing = true;
+            }
+            Some((name, _)) if !name.contains(char::is_whitespace) => continuing = false,
+            _ if continuing => {
+                if let Some(value) = variables.last_mut().and_then(|variable| variable.value.as_mut()) {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for variable in &mut variables {
+        let name = variable.name.to_uppercase();
+        if SENSITIVE_NAMES.iter().any(|sensitive| name.contains(sensitive)) {
+            variable.value = None;
+            variable.sensitive = true;
+        }
+    }
+    variables
+}
+
+// bash: alias ll='ls -l'  zsh: ll='ls -l'  fish: alias ll 'ls -l'  tcsh: ll<TAB>ls -l
+fn parse_alias(line: &str) -> Option<ShellAlias> {
+    let (name, command) = match line.strip_prefix("alias ") {
+        Some(rest) => rest.split_once(['=', ' '])?,
+        None => line.split_once(['=', '\t'])?,
+    };
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(ShellAlias {
+        name: name.to_string(),
+        command: unquote(command.trim()),
+    })
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        value[1..value.len() - 1].replace("'\\''", "'")
+    } else if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        value[1..value.len() - 1].to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_variables() {
+        let parsed = parse(
+            "==env\nPATH=/usr/bin:/bin\nHOME=/home/deploy\nMOTD=line one\nline two\n\
+             BASH_FUNC_greet%%=() {  echo hi\n}\nAWS_SECRET_ACCESS_KEY=abc123\nEMPTY=\n==alias\n",
+        );
+        let names: Vec<&str> = parsed.variables.iter().map(|variable| variable.name.as_str()).collect();
+        assert_eq!(names, ["AWS_SECRET_ACCESS_KEY", "EMPTY", "HOME", "MOTD", "PATH"]);
+
+        let value = |name: &str| parsed.variables.iter().find(|variable| variable.name == name).unwrap();
+        assert_eq!(value("MOTD").value.as_deref(), Some("line one\nline two"));
+        assert_eq!(value("EMPTY").value.as_deref(), Some(""));
+        assert!(value("AWS_SECRET_ACCESS_KEY").sensitive && value("AWS_SECRET_ACCESS_KEY").value.is_none());
+        assert!(!value("PATH").sensitive);
+        assert!(parsed.aliases.is_empty());
+    }
+
+    #[test]
+    fn test_parse_aliases() {
+        let parsed = parse(
+            "==env\nSHELL=/bin/bash\n==alias\nalias ll='ls -alF'\nalias gs='git status'\n\
+             alias say='echo '\\''hi'\\'''\nwelcome from .bashrc\n",
+        );
+        let aliases: Vec<(&str, &str)> = parsed.aliases.iter().map(|alias| (alias.name.as_str(), alias.command.as_str())).collect();
+        assert_eq!(aliases, [("gs", "git status"), ("ll", "ls -alF"), ("say", "echo 'hi'")]);
+
+        let zsh = parse("==env\n==alias\nll='ls -l'\nwhich-command=whence\n");
+        assert_eq!(zsh.aliases[0].name, "ll");
+        assert_eq!(zsh.aliases[1].command, "whence");
+
+        let fish = parse("==env\n==alias\nalias ll 'ls -l'\n");
+        assert_eq!((fish.aliases[0].name.as_str(), fish.aliases[0].command.as_str()), ("ll", "ls -l"));
+
+        let tcsh = parse("==env\n==alias\nll\tls -l\n");
+        assert_eq!((tcsh.aliases[0].name.as_str(), tcsh.aliases[0].command.as_str()), ("ll", "ls -l"));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::types::{AppError, AppResult, DirectoryChange};
+use dashmap::DashMap;
+use ssh2::Session;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+pub const DEFAULT_INTERVAL_SECS: u64 = 5;
+const MIN_INTERVAL_SECS: u64 = 1;
+const MAX_INTERVAL_SECS: u64 = 300;
+// How often a sleeping watcher checks whether it was stopped
+const CANCEL_CHECK: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    Changed(DirectoryChange),
+    // The watch ended, with the reason if it failed
+    Closed(Option<String>),
+}
+
+struct WatchEntry {
+    session_id: String,
+    cancel: CancellationToken,
+}
+
+// Polls remote directories for changes so file browsers can refresh on their own
+pub struct DirectoryWatchManager {
+    watches: Arc<DashMap<String, WatchEntry>>,
+}
+
+impl DirectoryWatchManager {
+    pub fn new() -> Self {
+        Self {
+            watches: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub fn interval(interval_secs: Option<u64>) -> AppResult<Duration> {
+        let secs = interval_secs.unwrap_or(DEFAULT_INTERVAL_SECS);
+        if !(MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS).contains(&secs) {
+            return Err(AppError::ValidationError(format!(
+                "Watch interval must be between {} and {} seconds", MIN_INTERVAL_SECS, MAX_INTERVAL_SECS
+            )));
+        }
+        Ok(Duration::from_secs(secs))
+    }
+
+    // Watch `path` over a dedicated, already authenticated SSH session, which is disconnected
+    // when the watch stops. Returns the watch ID and its change stream.
+    pub fn start(
+        &self,
+        session_id: &str,
+        path: &str,
+        interval: Duration,
+        session: Session,
+    ) -> AppResult<(String, mpsc::UnboundedReceiver<WatchEvent>)> {
+        let watch_id = Uuid::new_v4().to_string();
+        let cancel = CancellationToken::new();
+        self.watches.insert(watch_id.clone(), WatchEntry {
+            session_id: session_id.to_string(),
+            cancel: cancel.clone(),
+        });
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        log::info!("Watching {} for session {} as {}", path, session_id, watch_id);
+        let worker = WatchWorker {
+            session,
+            path: path.to_string(),
+            interval,
+            sender,
+            cancel,
+        };
+        let watches = self.watches.clone();
+        let id = watch_id.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let sender = worker.sender.clone();
+            let result = worker.run();
+            watches.remove(&id);
+            if let Err(e) = &result {
+                log::warn!("Directory watch {} failed: {}", id, e);
+            }
+            let _ = sender.send(WatchEvent::Closed(result.err()));
+        });
+
+        Ok((watch_id, receiver))
+    }
+
+    pub fn stop(&self, watch_id: &str) -> AppResult<()> {
+        let entry = self.watches.get(watch_id)
+            .ok_or_else(|| AppError::NotFound(format!("Directory watch {}", watch_id)))?;
+        entry.cancel.cancel();
+        Ok(())
+    }
+
+    pub fn close_session(&self, session_id: &str) {
+        for entry in self.watches.iter() {
+            if entry.session_id == session_id {
+                entry.cancel.cancel();
+            }
+        }
+    }
+
+    pub fn close_all(&self) {
+        for entry in self.watches.iter() {
+            entry.cancel.cancel();
+        }
+    }
+}
+
+impl Default for DirectoryWatchManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// What a listing looked like: name -> (mtime, size)
+type Snapshot = HashMap<String, (Option<u64>, Option<u64>)>;
+
+fn diff(before: &Snapshot, after: &Snapshot) -> Option<DirectoryChange> {
+    let mut change = DirectoryChange::default();
+    for (name, entry) in after {
+        match before.get(name) {
+            None => change.added.push(name.clone()),
+            Some(previous) if previous != entry => change.modified.push(name.clone()),
+            Some(_) => {}
+        }
+    }
+    change.removed = before.keys().filter(|name| !after.contains_key(*name)).cloned().collect();
+    if change.added.is_empty() && change.removed.is_empty() && change.modified.is_empty() {
+        return None;
+    }
+    change.added.sort();
+    change.removed.sort();
+    change.modified.sort();
+    Some(change)
+}
+
+struct WatchWorker {
+    session: Session,
+    path: String,
+    interval: Duration,
+    sender: mpsc::UnboundedSender<WatchEvent>,
+    cancel: CancellationToken,
+}
+
+impl WatchWorker {
+    // Runs on a blocking thread, listing the directory once per interval
+    fn run(self) -> Result<(), String> {
+        let result = self.poll();
+        let _ = self.session.disconnect(None, "Directory watch closed", None);
+        result
+    }
+
+    fn poll(&self) -> Result<(), String> {
+        let sftp = self.session.sftp().map_err(|e| format!("Failed to open SFTP: {}", e))?;
+        let mut snapshot = self.snapshot(&sftp)?;
+
+        while self.sleep() {
+            let current = self.snapshot(&sftp)?;
+            if let Some(change) = diff(&snapshot, &current) {
+                // Nobody is listening any more, stop quietly
+                if self.sender.send(WatchEvent::Changed(change)).is_err() {
+                    break;
+                }
+            }
+            snapshot = current;
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self, sftp: &ssh2::Sftp) -> Result<Snapshot, String> {
+        let entries = sftp.readdir(Path::new(&self.path))
+            .map_err(|e| format!("Cannot list {}: {}", self.path, e))?;
+        Ok(entries.into_iter()
+            .filter_map(|(path, stat)| {
+                let name = path.file_name()?.to_string_lossy().to_string();
+                Some((name, (stat.mtime, stat.size)))
+            })
+            .collect())
+    }
+
+    // False once the watch is stopped
+    fn sleep(&self) -> bool {
+        let deadline = Instant::now() + self.interval;
+        while Instant::now() < deadline {
+            if self.cancel.is_cancelled() {
+                return false;
+            }
+            std::thread::sleep(CANCEL_CHECK.min(deadline.saturating_duration_since(Instant::now())));
+        }
+        !self.cancel.is_cancelled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_listings() {
+        let before: Snapshot = HashMap::from([
+            ("app.log".to_string(), (Some(100), Some(10))),
+            ("old.txt".to_string(), (Some(50), Some(1))),
+            ("same".to_string(), (Some(1), Some(1))),
+        ]);
+        let after: Snapshot = HashMap::from([
+            ("app.log".to_string(), (Some(160), Some(20))),
+            ("new.txt".to_string(), (Some(170), Some(0))),
+            ("same".to_string(), (Some(1), Some(1))),
+        ]);
+
+        let change = diff(&before, &after).unwrap();
+        assert_eq!(change.added, ["new.txt"]);
+        assert_eq!(change.removed, ["old.txt"]);
+        assert_eq!(change.modified, ["app.log"]);
+        assert!(diff(&after, &after).is_none());
+    }
+
+    #[test]
+    fn test_interval_bounds() {
+        assert_eq!(DirectoryWatchManager::interval(None).unwrap(), Duration::from_secs(DEFAULT_INTERVAL_SECS));
+        assert!(DirectoryWatchManager::interval(Some(0)).is_err());
+        assert!(DirectoryWatchManager::interval(Some(MAX_INTERVAL_SECS + 1)).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+use crate::ssh::charset::TerminalCodec;
+use crate::types::{AppError, AppResult};
+use parking_lot::Mutex;
+use std::io::Write;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+// Shared by a shell's reads (decode) and its writer thread (encode)
+pub type SharedCodec = Arc<Mutex<TerminalCodec>>;
+
+// Input for a shell, written by a thread of its own through the channel's stdin stream, so
+// keystrokes never wait for the session lock that reads and SFTP transfers hold
+pub struct ShellWriter {
+    queue: mpsc::UnboundedSender<String>,
+}
+
+impl ShellWriter {
+    pub fn spawn(session_id: &str, mut stream: ssh2::Stream, codec: SharedCodec) -> AppResult<Self> {
+        let (queue, mut input) = mpsc::unbounded_channel::<String>();
+        let session_id = session_id.to_string();
+
+        std::thread::Builder::new()
+            .name(format!("shell-writer-{}", session_id))
+            .spawn(move || {
+                // Ends when the shell is closed and its writer dropped, or the channel fails
+                while let Some(text) = input.blocking_recv() {
+                    let bytes = codec.lock().encode(&text);
+                    if let Err(e) = stream.write_all(&bytes).and_then(|_| stream.flush()) {
+                        log::warn!("Failed to write to the shell of session {}: {}", session_id, e);
+                        break;
+                    }
+                }
+                log::debug!("Shell writer stopped for session {}", session_id);
+            })
+            .map_err(|e| AppError::InternalError(format!("Failed to start shell writer: {}", e)))?;
+
+        Ok(Self { queue })
+    }
+
+    // Queued in order; fails once the writer has stopped
+    pub fn send(&self, input: &str) -> AppResult<()> {
+        self.queue.send(input.to_string())
+            .map_err(|_| AppError::SSHConnectionFailed("Failed to write to shell: input closed".to_string()))
+    }
+}
@@ -0,0 +1,139 @@
+use crate::types::{AppError, AppResult};
+use futures_util::{SinkExt, StreamExt};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(15);
+const BUFFER_SIZE: usize = 16 * 1024;
+
+type GatewaySocket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+pub fn validate_url(url: &str) -> AppResult<()> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| AppError::InvalidConfiguration(format!("Invalid WebSocket URL: {}", e)))?;
+    if parsed.scheme() != "ws" && parsed.scheme() != "wss" {
+        return Err(AppError::InvalidConfiguration("WebSocket URL must start with ws:// or wss://".to_string()));
+    }
+    if parsed.host_str().is_none() {
+        return Err(AppError::InvalidConfiguration("WebSocket URL has no host".to_string()));
+    }
+    Ok(())
+}
+
+// Open the gateway connection and return a loopback socket whose bytes are carried
+// over it as binary frames. ssh2 needs a real socket, so it gets this one.
+pub async fn connect(url: &str) -> AppResult<TcpStream> {
+    validate_url(url)?;
+
+    let (socket, _) = tokio::time::timeout(HANDSHAKE_TIMEOUT, tokio_tungstenite::connect_async(url))
+        .await
+        .map_err(|_| AppError::TimeoutError("WebSocket gateway handshake timed out".to_string()))?
+        .map_err(gateway_error)?;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let client = TcpStream::connect(listener.local_addr()?)?;
+    let (bridge, _) = listener.accept()?;
+    client.set_nodelay(true)?;
+    bridge.set_nodelay(true)?;
+    bridge.set_nonblocking(true)?;
+    let bridge = tokio::net::TcpStream::from_std(bridge)?;
+
+    tokio::spawn(async move {
+        if let Err(e) = pump(socket, bridge).await {
+            log::debug!("WebSocket tunnel closed: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+fn gateway_error(error: tungstenite::Error) -> AppError {
+    match error {
+        // The gateway turned us away, retrying won't help
+        tungstenite::Error::Http(response) if response.status().is_client_error() => {
+            AppError::PermissionDenied(format!("WebSocket gateway refused the connection: {}", response.status()))
+        }
+        e => AppError::SSHConnectionFailed(format!("WebSocket gateway connection failed: {}", e)),
+    }
+}
+
+fn io_error(error: tungstenite::Error) -> std::io::Error {
+    std::io::Error::other(error.to_string())
+}
+
+// Copy bytes both ways until either side closes
+async fn pump(socket: GatewaySocket, bridge: tokio::net::TcpStream) -> std::io::Result<()> {
+    let (mut gateway_tx, mut gateway_rx) = socket.split();
+    let (mut bridge_rx, mut bridge_tx) = bridge.into_split();
+
+    let upstream = async {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        loop {
+            let read = bridge_rx.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            gateway_tx.send(Message::Binary(buffer[..read].to_vec())).await.map_err(io_error)?;
+        }
+        let _ = gateway_tx.send(Message::Close(None)).await;
+        Ok(())
+    };
+
+    let downstream = async {
+        while let Some(message) = gateway_rx.next().await {
+            match message.map_err(io_error)? {
+                Message::Binary(data) => bridge_tx.write_all(&data).await?,
+                Message::Close(_) => break,
+                // Pings are answered by tungstenite; SSH never travels as text
+                _ => {}
+            }
+        }
+        bridge_tx.shutdown().await
+    };
+
+    tokio::select! {
+        result = upstream => result,
+        result = downstream => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_validate_url() {
+        assert!(validate_url("wss://gateway.example.com/ssh?target=db1").is_ok());
+        assert!(validate_url("ws://127.0.0.1:8080").is_ok());
+        assert!(validate_url("https://gateway.example.com").is_err());
+        assert!(validate_url("not a url").is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_tunnel_carries_bytes_both_ways() {
+        // A gateway that echoes every binary frame back
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(message)) = socket.next().await {
+                if message.is_binary() {
+                    socket.send(message).await.unwrap();
+                }
+            }
+        });
+
+        let mut stream = connect(&format!("ws://127.0.0.1:{}", port)).await.unwrap();
+        let echoed = tokio::task::spawn_blocking(move || {
+            stream.write_all(b"SSH-2.0-test\r\n").unwrap();
+            let mut buffer = [0u8; 14];
+            stream.read_exact(&mut buffer).unwrap();
+            buffer
+        }).await.unwrap();
+        assert_eq!(&echoed, b"SSH-2.0-test\r\n");
+    }
+}
@@ -1,28 +1,387 @@
-use crate::types::{AppError, AppResult, FileTransfer, TransferStatus, TransferDirection};
-use crate::ssh::SSHManager;
+use crate::types::{AppError, AppResult, FileTransfer, SessionDefaults, ManifestEntry, ManifestFormat, TransferEntryError, TransferFileProgress, TransferManifest, TransferStatus, TransferDirection};
+use crate::logging::correlation;
+use crate::log_transfer;
+use crate::background::BackgroundTasks;
+use crate::retry::{retry, RetryPolicy};
+use crate::ssh::{join_remote, SSHManager};
+use crate::webhooks::{self, WebhookEventKind};
 use chrono::Utc;
 use dashmap::DashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use tokio::time::{interval, Duration};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+use tokio::sync::{broadcast, watch, RwLock};
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 pub type SharedTransferManager = Arc<RwLock<TransferManager>>;
+pub type SharedTransferProgress = Arc<TransferProgress>;
+
+// Running transfers report progress at most this often
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+// Live transfer records for progress bars. Updates while a transfer runs are throttled per
+// transfer; the one for a finished transfer always goes out.
+pub struct TransferProgress {
+    events: broadcast::Sender<FileTransfer>,
+    last_sent: DashMap<String, Instant>,
+}
+
+impl Default for TransferProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransferProgress {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(256);
+        Self {
+            events,
+            last_sent: DashMap::new(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<FileTransfer> {
+        self.events.subscribe()
+    }
+
+    // A record for a transfer the manager doesn't run itself, like the desktop's dialog
+    // transfers, to hand to TransferManager::track
+    pub fn external(session_id: &str, remote_path: &str, local_path: &str, direction: TransferDirection) -> FileTransfer {
+        FileTransfer {
+            id: Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            name: remote_path.rsplit('/').next().unwrap_or(remote_path).to_string(),
+            remote_path: remote_path.to_string(),
+            local_path: Some(local_path.to_string()),
+            size: 0,
+            transferred: 0,
+            status: TransferStatus::InProgress,
+            direction,
+            start_time: Utc::now(),
+            end_time: None,
+            error: None,
+            checksum: None,
+            is_directory: false,
+            files_total: 0,
+            files_done: 0,
+            current_file: None,
+            errors: Vec::new(),
+            bytes_per_second: None,
+            max_bytes_per_second: None,
+        }
+    }
+
+    pub fn report(&self, transfer: &FileTransfer) {
+        if transfer.end_time.is_some() {
+            self.last_sent.remove(&transfer.id);
+        } else {
+            let now = Instant::now();
+            if self.last_sent.get(&transfer.id).is_some_and(|last| now.duration_since(*last) < PROGRESS_INTERVAL) {
+                return;
+            }
+            self.last_sent.insert(transfer.id.clone(), now);
+        }
+        // Nobody listening is fine
+        let _ = self.events.send(transfer.clone());
+    }
+
+    fn update(&self, transfers: &DashMap<String, FileTransfer>, transfer_id: &str, change: impl FnOnce(&mut FileTransfer)) {
+        if let Some(mut transfer) = transfers.get_mut(transfer_id) {
+            change(&mut transfer);
+            self.report(&transfer);
+        }
+    }
+}
+
+// A bandwidth cap: every chunk books the time it takes at the capped rate, and the copy
+// waits until the bookings made so far have passed
+pub struct RateLimit {
+    state: parking_lot::Mutex<RateState>,
+}
+
+struct RateState {
+    bytes_per_second: Option<u64>,
+    // When everything booked so far has been sent at the capped rate
+    booked_until: Instant,
+}
+
+impl RateLimit {
+    pub fn new(bytes_per_second: Option<u64>) -> Self {
+        Self {
+            state: parking_lot::Mutex::new(RateState {
+                bytes_per_second,
+                booked_until: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn get(&self) -> Option<u64> {
+        self.state.lock().bytes_per_second
+    }
+
+    // Takes effect from the next chunk, without the time booked under the old cap
+    pub fn set(&self, bytes_per_second: Option<u64>) -> AppResult<()> {
+        if bytes_per_second == Some(0) {
+            return Err(AppError::ValidationError("Bandwidth cap cannot be 0".to_string()));
+        }
+        let mut state = self.state.lock();
+        state.bytes_per_second = bytes_per_second;
+        state.booked_until = Instant::now();
+        Ok(())
+    }
+
+    // How long to wait before sending more, now that bytes went out. Time the link sat
+    // idle isn't saved up for a burst later.
+    fn book(&self, bytes: u64) -> Duration {
+        let mut state = self.state.lock();
+        let Some(rate) = state.bytes_per_second else {
+            return Duration::ZERO;
+        };
+        let now = Instant::now();
+        state.booked_until = state.booked_until.max(now) + Duration::from_secs_f64(bytes as f64 / rate as f64);
+        state.booked_until - now
+    }
+}
+
+// Shared by every transfer in the process, on top of their own caps
+static GLOBAL_LIMIT: OnceLock<RateLimit> = OnceLock::new();
+
+pub fn global_limit() -> &'static RateLimit {
+    GLOBAL_LIMIT.get_or_init(|| RateLimit::new(None))
+}
+
+// A long wait is cut into slices, so a pause or cancel still stops the transfer promptly
+const PACE_SLICE: Duration = Duration::from_millis(100);
+// Throughput is measured over windows at least this long
+const METER_WINDOW: Duration = Duration::from_secs(1);
+
+// Keeps one transfer within its cap and the global one, and measures how fast it goes
+#[derive(Clone)]
+pub struct Throttle {
+    limit: Arc<RateLimit>,
+    meter: Arc<parking_lot::Mutex<Meter>>,
+}
+
+#[derive(Default)]
+struct Meter {
+    // The running total last reported, to tell how much each chunk added
+    last: u64,
+    window: Option<(Instant, u64)>,
+    rate: Option<u64>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_second: Option<u64>) -> Self {
+        Self {
+            limit: Arc::new(RateLimit::new(bytes_per_second)),
+            meter: Arc::default(),
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.meter.lock().rate
+    }
+
+    // Called from a copy's progress callback with its running total, which starts over when
+    // the copy does. Waits as long as the caps need, stopping early with check's error.
+    pub fn pace(&self, done: u64, check: impl Fn() -> AppResult<()>) -> AppResult<()> {
+        let sent = self.meter.lock().add(done);
+        let wait = self.limit.book(sent).max(global_limit().book(sent));
+        let until = Instant::now() + wait;
+        loop {
+            check()?;
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            // Copies run on the blocking pool, where this holds up no runtime worker
+            std::thread::sleep(left.min(PACE_SLICE));
+        }
+    }
+}
+
+impl Meter {
+    fn add(&mut self, done: u64) -> u64 {
+        let sent = done.checked_sub(self.last).unwrap_or(done);
+        self.last = done;
+
+        let now = Instant::now();
+        let (start, bytes) = self.window.get_or_insert((now, 0));
+        *bytes += sent;
+        let elapsed = now.duration_since(*start);
+        if elapsed >= METER_WINDOW {
+            self.rate = Some((*bytes as f64 / elapsed.as_secs_f64()) as u64);
+            self.window = Some((now, 0));
+        }
+        sent
+    }
+}
+
+// Pausing or cancelling a running transfer. Its copy loop checks between chunks, so the
+// network I/O stops within one chunk rather than when the file is done.
+#[derive(Clone)]
+struct TransferControl {
+    cancel: CancellationToken,
+    paused: Arc<watch::Sender<bool>>,
+    throttle: Throttle,
+}
+
+impl TransferControl {
+    fn new(bytes_per_second: Option<u64>) -> Self {
+        Self {
+            cancel: CancellationToken::new(),
+            paused: Arc::new(watch::channel(false).0),
+            throttle: Throttle::new(bytes_per_second),
+        }
+    }
+
+    fn pause(&self, paused: bool) {
+        self.paused.send_replace(paused);
+    }
+
+    fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    fn rate(&self) -> Option<u64> {
+        self.throttle.rate()
+    }
+
+    // Given as the copy's progress callback with its running total, once progress is recorded
+    fn pace(&self, total: u64) -> AppResult<()> {
+        self.throttle.pace(total, || self.check())
+    }
+
+    // Given as the copy's progress callback, whose error ends the copy
+    fn check(&self) -> AppResult<()> {
+        if self.cancel.is_cancelled() {
+            Err(AppError::TransferError("Transfer cancelled".to_string()))
+        } else if *self.paused.borrow() {
+            Err(AppError::TransferError("Transfer paused".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Whether a failed copy was stopped on purpose rather than by an error
+    fn interrupted(&self) -> bool {
+        self.check().is_err()
+    }
+
+    // Waits out a pause; false once the transfer is cancelled
+    async fn proceed(&self) -> bool {
+        let mut paused = self.paused.subscribe();
+        tokio::select! {
+            biased;
+            _ = self.cancel.cancelled() => false,
+            resumed = paused.wait_for(|paused| !paused) => resumed.is_ok(),
+        }
+    }
+}
+
+// A transfer the manager tracks but doesn't run. Whoever runs it reports through this, and
+// copies again after a pause, as directory transfers do.
+#[derive(Clone)]
+pub struct TrackedTransfer {
+    transfer_id: String,
+    transfers: Arc<DashMap<String, FileTransfer>>,
+    controls: Arc<DashMap<String, TransferControl>>,
+    progress: Arc<TransferProgress>,
+    control: TransferControl,
+}
+
+impl TrackedTransfer {
+    pub fn id(&self) -> &str {
+        &self.transfer_id
+    }
+
+    // Given the copy's running total. Keeps to the caps, and stops the copy once the transfer
+    // is paused or cancelled, or when check fails, e.g. for a cancelled job.
+    pub fn report(&self, done: u64, check: impl Fn() -> AppResult<()>) -> AppResult<()> {
+        self.progress.update(&self.transfers, &self.transfer_id, |transfer| {
+            transfer.transferred = done;
+            transfer.bytes_per_second = self.control.rate();
+        });
+        self.control.throttle.pace(done, || {
+            check()?;
+            self.control.check()
+        })
+    }
+
+    // Whether a failed copy was stopped by a pause or cancel
+    pub fn interrupted(&self) -> bool {
+        self.control.interrupted()
+    }
+
+    // Waits out a pause; false once the transfer is cancelled
+    pub async fn proceed(&self) -> bool {
+        self.control.proceed().await
+    }
+
+    // A cancelled transfer stays cancelled whatever its copy returned
+    pub fn finish(&self, result: &AppResult<u64>) {
+        self.controls.remove(&self.transfer_id);
+        let Some(mut transfer) = self.transfers.get_mut(&self.transfer_id) else {
+            return;
+        };
+        if matches!(transfer.status, TransferStatus::Cancelled) {
+            return;
+        }
+
+        transfer.bytes_per_second = None;
+        transfer.end_time = Some(Utc::now());
+        match result {
+            Ok(size) => {
+                transfer.status = TransferStatus::Completed;
+                transfer.size = *size;
+                transfer.transferred = *size;
+                log_transfer!(&self.transfer_id, "completed");
+            }
+            Err(e) => {
+                transfer.status = TransferStatus::Failed;
+                transfer.error = Some(e.to_string());
+                log_transfer!(&self.transfer_id, "failed", HashMap::from([("error".to_string(), e.to_string())]));
+            }
+        }
+        self.progress.report(&transfer);
+    }
+}
 
 pub struct TransferManager {
     transfers: Arc<DashMap<String, FileTransfer>>,
+    controls: Arc<DashMap<String, TransferControl>>,
+    progress: Arc<TransferProgress>,
     ssh_manager: Arc<RwLock<SSHManager>>,
     max_concurrent_transfers: usize,
     active_transfers: usize,
+    background: BackgroundTasks,
 }
 
 impl TransferManager {
     pub fn new(ssh_manager: Arc<RwLock<SSHManager>>) -> Self {
+        Self::with_progress(ssh_manager, Arc::new(TransferProgress::new()))
+    }
+
+    // Reporting to an existing progress feed, like the desktop's
+    pub fn with_progress(ssh_manager: Arc<RwLock<SSHManager>>, progress: SharedTransferProgress) -> Self {
         let manager = Self {
             transfers: Arc::new(DashMap::new()),
+            controls: Arc::new(DashMap::new()),
+            progress,
             ssh_manager,
             max_concurrent_transfers: 3, // Allow up to 3 concurrent transfers
             active_transfers: 0,
+            background: BackgroundTasks::new(),
         };
 
         // Start periodic cleanup task
@@ -33,13 +392,10 @@ impl TransferManager {
     fn start_cleanup_task(&self) {
         let transfers = self.transfers.clone();
 
-        tokio::spawn(async move {
-            let mut interval = interval(Duration::from_secs(600)); // Clean up every 10 minutes
-
-            loop {
-                interval.tick().await;
-                Self::periodic_cleanup(&transfers).await;
-            }
+        // Clean up every 10 minutes
+        self.background.spawn_periodic("Transfer cleanup", Duration::from_secs(600), move || {
+            let transfers = transfers.clone();
+            async move { Self::periodic_cleanup(&transfers).await }
         });
     }
 
@@ -66,6 +422,10 @@ impl TransferManager {
         }
     }
 
+    pub fn subscribe_progress(&self) -> broadcast::Receiver<FileTransfer> {
+        self.progress.subscribe()
+    }
+
     pub fn list_transfers(&self) -> Vec<FileTransfer> {
         self.transfers.iter().map(|entry| entry.value().clone()).collect()
     }
@@ -74,6 +434,28 @@ impl TransferManager {
         self.transfers.get(transfer_id).map(|entry| entry.value().clone())
     }
 
+    // Room for one more transfer, overall and within the session's profile limit. Returns
+    // the session's defaults for the new transfer.
+    async fn check_capacity(&self, session_id: &str) -> AppResult<SessionDefaults> {
+        if self.active_transfers >= self.max_concurrent_transfers {
+            return Err(AppError::FileOperationFailed("Too many concurrent transfers".to_string()));
+        }
+        let defaults = self.ssh_manager.read().await.get_session(session_id).await
+            .map(|session| session.config.defaults)
+            .unwrap_or_default();
+        let Some(limit) = defaults.max_concurrent_transfers else {
+            return Ok(defaults);
+        };
+        let running = self.transfers.iter()
+            .filter(|transfer| transfer.session_id == session_id)
+            .filter(|transfer| matches!(transfer.status, TransferStatus::Pending | TransferStatus::InProgress | TransferStatus::Paused))
+            .count();
+        if running >= limit {
+            return Err(AppError::FileOperationFailed(format!("Session already has {} of its {} concurrent transfers running", running, limit)));
+        }
+        Ok(defaults)
+    }
+
     pub async fn start_upload(
         &mut self,
         session_id: String,
@@ -81,12 +463,12 @@ impl TransferManager {
         name: String,
         content: Vec<u8>,
     ) -> AppResult<String> {
-        if self.active_transfers >= self.max_concurrent_transfers {
-            return Err(AppError::FileOperationFailed("Too many concurrent transfers".to_string()));
-        }
+        let defaults = self.check_capacity(&session_id).await?;
 
         let transfer_id = Uuid::new_v4().to_string();
         let size = content.len() as u64;
+        let checksum = sha256_hex(&content);
+        let content: Arc<[u8]> = Arc::from(content);
 
         let transfer = FileTransfer {
             id: transfer_id.clone(),
@@ -101,42 +483,53 @@ impl TransferManager {
             start_time: Utc::now(),
             end_time: None,
             error: None,
+            checksum: None,
+            is_directory: false,
+            files_total: 0,
+            files_done: 0,
+            current_file: None,
+            errors: Vec::new(),
+            bytes_per_second: None,
+            max_bytes_per_second: defaults.max_bytes_per_second,
         };
 
         self.transfers.insert(transfer_id.clone(), transfer.clone());
         self.active_transfers += 1;
 
+        log_transfer!(&transfer_id, "upload_started");
+
         // Start the upload task
         let transfers = self.transfers.clone();
+        let controls = self.controls.clone();
+        let control = self.add_control(&transfer_id);
+        let progress = self.progress.clone();
         let ssh_manager = self.ssh_manager.clone();
         let transfer_id_clone = transfer_id.clone();
 
-        tokio::spawn(async move {
-            let result = Self::execute_upload(
-                ssh_manager,
-                transfers.clone(),
-                transfer_id_clone.clone(),
-                session_id,
-                remote_path,
-                content,
-            ).await;
-
-            // Update transfer status
-            if let Some(mut transfer) = transfers.get_mut(&transfer_id_clone) {
-                match result {
-                    Ok(_) => {
-                        transfer.status = TransferStatus::Completed;
-                        transfer.transferred = transfer.size;
-                        transfer.end_time = Some(Utc::now());
-                    }
-                    Err(e) => {
-                        transfer.status = TransferStatus::Failed;
-                        transfer.error = Some(e.to_string());
-                        transfer.end_time = Some(Utc::now());
-                    }
+        tokio::spawn(correlation::propagate(async move {
+            // A pause ends a run; the next one carries on from the bytes already written
+            let result = loop {
+                if !control.proceed().await {
+                    break Err(AppError::TransferError("Transfer cancelled".to_string()));
                 }
-            }
-        });
+                let run = retry(&RetryPolicy::transfer(), "Upload transfer", || Self::execute_upload(
+                    &ssh_manager,
+                    &transfers,
+                    &progress,
+                    &control,
+                    &transfer_id_clone,
+                    &session_id,
+                    &remote_path,
+                    &content,
+                )).await;
+                match run {
+                    Err(_) if control.interrupted() => continue,
+                    run => break run.map(|()| (size, checksum)),
+                }
+            };
+            controls.remove(&transfer_id_clone);
+            finish_file_transfer(&transfers, &progress, &transfer_id_clone, result);
+        }));
 
         Ok(transfer_id)
     }
@@ -147,9 +540,7 @@ impl TransferManager {
         remote_path: String,
         name: Option<String>,
     ) -> AppResult<String> {
-        if self.active_transfers >= self.max_concurrent_transfers {
-            return Err(AppError::FileOperationFailed("Too many concurrent transfers".to_string()));
-        }
+        let defaults = self.check_capacity(&session_id).await?;
 
         let transfer_id = Uuid::new_v4().to_string();
         let display_name = name.unwrap_or_else(|| {
@@ -169,93 +560,442 @@ impl TransferManager {
             start_time: Utc::now(),
             end_time: None,
             error: None,
+            checksum: None,
+            is_directory: false,
+            files_total: 0,
+            files_done: 0,
+            current_file: None,
+            errors: Vec::new(),
+            bytes_per_second: None,
+            max_bytes_per_second: defaults.max_bytes_per_second,
         };
 
         self.transfers.insert(transfer_id.clone(), transfer.clone());
         self.active_transfers += 1;
 
+        log_transfer!(&transfer_id, "download_started");
+
         // Start the download task
         let transfers = self.transfers.clone();
+        let controls = self.controls.clone();
+        let control = self.add_control(&transfer_id);
+        let progress = self.progress.clone();
         let ssh_manager = self.ssh_manager.clone();
         let transfer_id_clone = transfer_id.clone();
 
-        tokio::spawn(async move {
-            let result = Self::execute_download(
-                ssh_manager,
-                transfers.clone(),
-                transfer_id_clone.clone(),
-                session_id,
-                remote_path,
-            ).await;
-
-            // Update transfer status
-            if let Some(mut transfer) = transfers.get_mut(&transfer_id_clone) {
+        tokio::spawn(correlation::propagate(async move {
+            // Kept across runs, so a resumed download only hashes the rest of the file
+            let hasher = Arc::new(parking_lot::Mutex::new(Sha256::new()));
+            let result = loop {
+                if !control.proceed().await {
+                    break Err(AppError::TransferError("Transfer cancelled".to_string()));
+                }
+                let run = retry(&RetryPolicy::transfer(), "Download transfer", || Self::execute_download(
+                    &ssh_manager,
+                    &transfers,
+                    &progress,
+                    &control,
+                    &hasher,
+                    &transfer_id_clone,
+                    &session_id,
+                    &remote_path,
+                )).await;
+                match run {
+                    Err(_) if control.interrupted() => continue,
+                    run => break run.map(|size| (size, hex(&hasher.lock().clone().finalize()))),
+                }
+            };
+            controls.remove(&transfer_id_clone);
+            finish_file_transfer(&transfers, &progress, &transfer_id_clone, result);
+        }));
+
+        Ok(transfer_id)
+    }
+
+    // Copy a remote directory tree into local_path, one file at a time
+    pub async fn start_directory_download(
+        &mut self,
+        session_id: String,
+        remote_path: String,
+        local_path: String,
+    ) -> AppResult<String> {
+        let remote_root = validate_directory_paths(&remote_path, &local_path)?;
+        let transfer_id = self.begin_directory_transfer(&session_id, &remote_root, &local_path, TransferDirection::Download).await?;
+
+        let transfers = self.transfers.clone();
+        let controls = self.controls.clone();
+        let control = self.add_control(&transfer_id);
+        let progress = self.progress.clone();
+        let ssh_manager = self.ssh_manager.clone();
+        let id = transfer_id.clone();
+        tokio::spawn(correlation::propagate(async move {
+            let result = Self::execute_directory_download(&ssh_manager, &transfers, &progress, &control, &id, &session_id, &remote_root, Path::new(&local_path)).await;
+            controls.remove(&id);
+            finish_directory_transfer(&transfers, &progress, &id, result);
+        }));
+
+        Ok(transfer_id)
+    }
+
+    // Copy a local directory tree to remote_path, creating the directories it needs
+    pub async fn start_directory_upload(
+        &mut self,
+        session_id: String,
+        local_path: String,
+        remote_path: String,
+    ) -> AppResult<String> {
+        let remote_root = validate_directory_paths(&remote_path, &local_path)?;
+        let transfer_id = self.begin_directory_transfer(&session_id, &remote_root, &local_path, TransferDirection::Upload).await?;
+
+        let transfers = self.transfers.clone();
+        let controls = self.controls.clone();
+        let control = self.add_control(&transfer_id);
+        let progress = self.progress.clone();
+        let ssh_manager = self.ssh_manager.clone();
+        let id = transfer_id.clone();
+        tokio::spawn(correlation::propagate(async move {
+            let result = Self::execute_directory_upload(&ssh_manager, &transfers, &progress, &control, &id, &session_id, Path::new(&local_path), &remote_root).await;
+            controls.remove(&id);
+            finish_directory_transfer(&transfers, &progress, &id, result);
+        }));
+
+        Ok(transfer_id)
+    }
+
+    async fn begin_directory_transfer(&mut self, session_id: &str, remote_path: &str, local_path: &str, direction: TransferDirection) -> AppResult<String> {
+        let defaults = self.check_capacity(session_id).await?;
+
+        let transfer_id = Uuid::new_v4().to_string();
+        let root = match direction {
+            TransferDirection::Download => remote_path,
+            TransferDirection::Upload => local_path,
+        };
+        let name = root.trim_end_matches(['/', '\\']).rsplit(['/', '\\']).next().filter(|name| !name.is_empty()).unwrap_or("/");
+
+        self.transfers.insert(transfer_id.clone(), FileTransfer {
+            id: transfer_id.clone(),
+            session_id: session_id.to_string(),
+            name: name.to_string(),
+            remote_path: remote_path.to_string(),
+            local_path: Some(local_path.to_string()),
+            size: 0,
+            transferred: 0,
+            status: TransferStatus::Pending,
+            direction,
+            start_time: Utc::now(),
+            end_time: None,
+            error: None,
+            checksum: None,
+            is_directory: true,
+            files_total: 0,
+            files_done: 0,
+            current_file: None,
+            errors: Vec::new(),
+            bytes_per_second: None,
+            max_bytes_per_second: defaults.max_bytes_per_second,
+        });
+        self.active_transfers += 1;
+
+        log_transfer!(&transfer_id, "directory_transfer_started");
+        Ok(transfer_id)
+    }
+
+    // The SSH manager is only held for one file at a time, not while the transfer is paused
+    #[allow(clippy::too_many_arguments)]
+    async fn execute_directory_download(
+        ssh_manager: &Arc<RwLock<SSHManager>>,
+        transfers: &Arc<DashMap<String, FileTransfer>>,
+        progress: &Arc<TransferProgress>,
+        control: &TransferControl,
+        transfer_id: &str,
+        session_id: &str,
+        remote_root: &str,
+        local_root: &Path,
+    ) -> AppResult<()> {
+        let (listing, unlisted) = ssh_manager.read().await.walk_directory(session_id, remote_root).await?;
+        let entries: Vec<TreeEntry> = listing.into_iter()
+            .filter_map(|entry| Some(TreeEntry {
+                relative: relative_path(remote_root, &entry.path)?,
+                size: entry.size,
+                is_directory: entry.is_directory,
+            }))
+            .collect();
+        let errors = unlisted.into_iter()
+            .map(|(path, e)| (relative_path(remote_root, &path).unwrap_or(path), e))
+            .collect();
+        plan(transfers, progress, transfer_id, &entries, errors);
+
+        tokio::fs::create_dir_all(local_root).await?;
+        let mut copied = 0;
+        for entry in &entries {
+            if !control.proceed().await {
+                return Ok(());
+            }
+            let target = local_target(local_root, &entry.relative);
+            if entry.is_directory {
+                if let Err(e) = tokio::fs::create_dir_all(&target).await {
+                    record_entry_error(transfers, transfer_id, &entry.relative, &e.into());
+                }
+                continue;
+            }
+
+            let remote = join_remote(remote_root, &entry.relative);
+            // A file interrupted by a pause is copied again from the start once resumed
+            loop {
+                start_file(transfers, transfer_id, entry);
+                // The copy reports from the blocking pool, so the callback owns what it updates
+                let report = {
+                    let (transfers, progress, control, transfer_id) = (transfers.clone(), progress.clone(), control.clone(), transfer_id.to_string());
+                    move |done: u64| {
+                        record_progress(&transfers, &progress, &transfer_id, copied + done, done, control.rate());
+                        control.pace(copied + done)
+                    }
+                };
+                let result = ssh_manager.read().await.download_to_path(session_id, &remote, &target, report).await;
                 match result {
                     Ok(size) => {
-                        transfer.status = TransferStatus::Completed;
-                        transfer.size = size;
-                        transfer.transferred = size;
-                        transfer.end_time = Some(Utc::now());
+                        copied += size;
+                        finish_file(transfers, progress, transfer_id, copied);
                     }
-                    Err(e) => {
-                        transfer.status = TransferStatus::Failed;
-                        transfer.error = Some(e.to_string());
-                        transfer.end_time = Some(Utc::now());
+                    Err(_) if control.interrupted() => {
+                        if control.proceed().await {
+                            continue;
+                        }
+                        return Ok(());
                     }
+                    Err(e) => record_entry_error(transfers, transfer_id, &entry.relative, &e),
                 }
+                break;
             }
-        });
+        }
+        Ok(())
+    }
 
-        Ok(transfer_id)
+    #[allow(clippy::too_many_arguments)]
+    async fn execute_directory_upload(
+        ssh_manager: &Arc<RwLock<SSHManager>>,
+        transfers: &Arc<DashMap<String, FileTransfer>>,
+        progress: &Arc<TransferProgress>,
+        control: &TransferControl,
+        transfer_id: &str,
+        session_id: &str,
+        local_root: &Path,
+        remote_root: &str,
+    ) -> AppResult<()> {
+        let (entries, unlisted) = walk_local(local_root).await?;
+        plan(transfers, progress, transfer_id, &entries, unlisted);
+
+        ssh_manager.read().await.create_directory(session_id, remote_root).await?;
+        let mut copied = 0;
+        for entry in &entries {
+            if !control.proceed().await {
+                return Ok(());
+            }
+            let remote = join_remote(remote_root, &entry.relative);
+            if entry.is_directory {
+                if let Err(e) = ssh_manager.read().await.create_directory(session_id, &remote).await {
+                    record_entry_error(transfers, transfer_id, &entry.relative, &e);
+                }
+                continue;
+            }
+
+            let source = local_target(local_root, &entry.relative);
+            loop {
+                start_file(transfers, transfer_id, entry);
+                let report = {
+                    let (transfers, progress, control, transfer_id) = (transfers.clone(), progress.clone(), control.clone(), transfer_id.to_string());
+                    move |done: u64| {
+                        record_progress(&transfers, &progress, &transfer_id, copied + done, done, control.rate());
+                        control.pace(copied + done)
+                    }
+                };
+                let result = ssh_manager.read().await.upload_from_path(session_id, &source, &remote, report).await;
+                match result {
+                    Ok(size) => {
+                        copied += size;
+                        finish_file(transfers, progress, transfer_id, copied);
+                    }
+                    Err(_) if control.interrupted() => {
+                        if control.proceed().await {
+                            continue;
+                        }
+                        return Ok(());
+                    }
+                    Err(e) => record_entry_error(transfers, transfer_id, &entry.relative, &e),
+                }
+                break;
+            }
+        }
+        Ok(())
     }
 
+    // Called again for each retry attempt and after each pause, so it only borrows the
+    // transfer's inputs, and carries on from the bytes already written
+    #[allow(clippy::too_many_arguments)]
     async fn execute_upload(
-        ssh_manager: Arc<RwLock<SSHManager>>,
-        transfers: Arc<DashMap<String, FileTransfer>>,
-        transfer_id: String,
-        session_id: String,
-        remote_path: String,
-        content: Vec<u8>,
+        ssh_manager: &Arc<RwLock<SSHManager>>,
+        transfers: &Arc<DashMap<String, FileTransfer>>,
+        progress: &Arc<TransferProgress>,
+        control: &TransferControl,
+        transfer_id: &str,
+        session_id: &str,
+        remote_path: &str,
+        content: &Arc<[u8]>,
     ) -> AppResult<()> {
-        // Update status to in progress
-        if let Some(mut transfer) = transfers.get_mut(&transfer_id) {
-            transfer.status = TransferStatus::InProgress;
-        }
+        let offset = start_run(transfers, progress, transfer_id);
 
         let manager = ssh_manager.read().await;
-        manager.upload_file(&session_id, &remote_path, &content).await?;
-
-        Ok(())
+        let report = reporter(transfers, progress, control, transfer_id);
+        manager.upload_file_with_progress(session_id, remote_path, content.clone(), offset, report).await
     }
 
+    // Streamed through the checksum rather than held in memory; the contents themselves
+    // aren't kept by the transfer manager
+    #[allow(clippy::too_many_arguments)]
     async fn execute_download(
-        ssh_manager: Arc<RwLock<SSHManager>>,
-        transfers: Arc<DashMap<String, FileTransfer>>,
-        transfer_id: String,
-        session_id: String,
-        remote_path: String,
+        ssh_manager: &Arc<RwLock<SSHManager>>,
+        transfers: &Arc<DashMap<String, FileTransfer>>,
+        progress: &Arc<TransferProgress>,
+        control: &TransferControl,
+        hasher: &Arc<parking_lot::Mutex<Sha256>>,
+        transfer_id: &str,
+        session_id: &str,
+        remote_path: &str,
     ) -> AppResult<u64> {
-        // Update status to in progress
-        if let Some(mut transfer) = transfers.get_mut(&transfer_id) {
+        let offset = start_run(transfers, progress, transfer_id);
+
+        let manager = ssh_manager.read().await;
+        if offset == 0 {
+            let size = manager.file_size(session_id, remote_path).await?.unwrap_or(0);
+            progress.update(transfers, transfer_id, |transfer| transfer.size = size);
+        }
+        let report = reporter(transfers, progress, control, transfer_id);
+        let hasher = hasher.clone();
+        manager.download_to(session_id, remote_path, offset, move |_| Ok(HashWriter(hasher.clone())), report).await
+    }
+
+    // Take in a transfer that runs outside the manager, like the desktop's dialog transfers,
+    // so it's listed, paused and cancelled like the rest. It doesn't count against the
+    // manager's own concurrency limit.
+    pub fn track(&self, transfer: FileTransfer) -> TrackedTransfer {
+        let transfer_id = transfer.id.clone();
+        self.transfers.insert(transfer_id.clone(), transfer);
+        log_transfer!(&transfer_id, "tracked_transfer_started");
+
+        TrackedTransfer {
+            control: self.add_control(&transfer_id),
+            transfer_id,
+            transfers: self.transfers.clone(),
+            controls: self.controls.clone(),
+            progress: self.progress.clone(),
+        }
+    }
+
+    // Capped at whatever the transfer record was created with
+    fn add_control(&self, transfer_id: &str) -> TransferControl {
+        let limit = self.transfers.get(transfer_id).and_then(|transfer| transfer.max_bytes_per_second);
+        let control = TransferControl::new(limit);
+        self.controls.insert(transfer_id.to_string(), control.clone());
+        control
+    }
+
+    // Stops the transfer's I/O within a chunk; resume_transfer carries on from there
+    pub fn pause_transfer(&self, transfer_id: &str) -> AppResult<FileTransfer> {
+        let control = self.controls.get(transfer_id)
+            .ok_or_else(|| AppError::NotFound(format!("Running transfer {}", transfer_id)))?;
+        let mut transfer = self.transfers.get_mut(transfer_id)
+            .ok_or_else(|| AppError::NotFound(format!("Transfer {}", transfer_id)))?;
+        if matches!(transfer.status, TransferStatus::Pending | TransferStatus::InProgress) {
+            transfer.status = TransferStatus::Paused;
+            transfer.bytes_per_second = None;
+            control.pause(true);
+            log_transfer!(transfer_id, "paused");
+            self.progress.report(&transfer);
+        }
+        Ok(transfer.clone())
+    }
+
+    pub fn resume_transfer(&self, transfer_id: &str) -> AppResult<FileTransfer> {
+        let control = self.controls.get(transfer_id)
+            .ok_or_else(|| AppError::NotFound(format!("Running transfer {}", transfer_id)))?;
+        let mut transfer = self.transfers.get_mut(transfer_id)
+            .ok_or_else(|| AppError::NotFound(format!("Transfer {}", transfer_id)))?;
+        if matches!(transfer.status, TransferStatus::Paused) {
             transfer.status = TransferStatus::InProgress;
+            control.pause(false);
+            log_transfer!(transfer_id, "resumed");
+            self.progress.report(&transfer);
         }
+        Ok(transfer.clone())
+    }
 
-        let manager = ssh_manager.read().await;
-        let content = manager.download_file(&session_id, &remote_path).await?;
-        let size = content.len() as u64;
+    // Cap a running transfer, or lift its cap, from its next chunk on
+    pub fn set_transfer_limit(&self, transfer_id: &str, bytes_per_second: Option<u64>) -> AppResult<FileTransfer> {
+        let control = self.controls.get(transfer_id)
+            .ok_or_else(|| AppError::NotFound(format!("Running transfer {}", transfer_id)))?;
+        let mut transfer = self.transfers.get_mut(transfer_id)
+            .ok_or_else(|| AppError::NotFound(format!("Transfer {}", transfer_id)))?;
+        control.throttle.limit().set(bytes_per_second)?;
+        transfer.max_bytes_per_second = bytes_per_second;
+        self.progress.report(&transfer);
+        Ok(transfer.clone())
+    }
 
-        // For now, we don't actually save the file locally in the Tauri app
-        // The content would be returned to the frontend
-        
-        Ok(size)
+    // Manifest of the given transfers, in the order given. Every one of them has to have
+    // finished, failed ones included, so the record is complete.
+    pub fn manifest(&self, transfer_ids: &[String]) -> AppResult<TransferManifest> {
+        if transfer_ids.is_empty() {
+            return Err(AppError::ValidationError("No transfers given for the manifest".to_string()));
+        }
+
+        let mut entries = Vec::with_capacity(transfer_ids.len());
+        for transfer_id in transfer_ids {
+            let transfer = self.get_transfer(transfer_id)
+                .ok_or_else(|| AppError::NotFound(format!("Transfer {}", transfer_id)))?;
+            let Some(end_time) = transfer.end_time else {
+                return Err(AppError::ValidationError(format!("Transfer {} hasn't finished", transfer_id)));
+            };
+            entries.push(ManifestEntry {
+                transfer_id: transfer.id,
+                name: transfer.name,
+                remote_path: transfer.remote_path,
+                direction: transfer.direction,
+                status: transfer.status,
+                size: transfer.size,
+                checksum: transfer.checksum,
+                start_time: transfer.start_time,
+                duration_ms: end_time.signed_duration_since(transfer.start_time).num_milliseconds(),
+                error: transfer.error,
+            });
+        }
+
+        Ok(TransferManifest {
+            generated_at: Utc::now(),
+            file_count: entries.len(),
+            total_bytes: entries.iter().map(|entry| entry.size).sum(),
+            entries,
+        })
+    }
+
+    pub fn export_manifest(&self, transfer_ids: &[String], format: ManifestFormat) -> AppResult<String> {
+        let manifest = self.manifest(transfer_ids)?;
+        match format {
+            ManifestFormat::Json => Ok(serde_json::to_string_pretty(&manifest)?),
+            ManifestFormat::Csv => Ok(manifest_csv(&manifest)),
+        }
     }
 
+    // Also stops the running copy, so nothing more is read or written
     pub fn cancel_transfer(&mut self, transfer_id: &str) -> AppResult<()> {
+        if let Some(control) = self.controls.get(transfer_id) {
+            control.cancel();
+        }
         if let Some(mut transfer) = self.transfers.get_mut(transfer_id) {
-            if matches!(transfer.status, TransferStatus::Pending | TransferStatus::InProgress) {
+            if matches!(transfer.status, TransferStatus::Pending | TransferStatus::InProgress | TransferStatus::Paused) {
                 transfer.status = TransferStatus::Cancelled;
                 transfer.end_time = Some(Utc::now());
                 self.active_transfers = self.active_transfers.saturating_sub(1);
+                self.progress.report(&transfer);
             }
         }
         Ok(())
@@ -285,7 +1025,7 @@ impl TransferManager {
             .iter()
             .filter(|entry| matches!(
                 entry.value().status,
-                TransferStatus::Pending | TransferStatus::InProgress
+                TransferStatus::Pending | TransferStatus::InProgress | TransferStatus::Paused
             ))
             .count();
     }
@@ -293,12 +1033,12 @@ impl TransferManager {
     pub async fn graceful_shutdown(&mut self) -> AppResult<()> {
         log::info!("Starting graceful shutdown of transfer manager");
 
-        // Cancel all pending and in-progress transfers
+        // Cancel all pending, paused and in-progress transfers
         let active_transfer_ids: Vec<String> = self.transfers
             .iter()
             .filter(|entry| matches!(
                 entry.value().status,
-                TransferStatus::Pending | TransferStatus::InProgress
+                TransferStatus::Pending | TransferStatus::InProgress | TransferStatus::Paused
             ))
             .map(|entry| entry.key().clone())
             .collect();
@@ -312,6 +1052,7 @@ impl TransferManager {
         // Clear all transfers
         self.transfers.clear();
         self.active_transfers = 0;
+        self.background.shutdown();
 
         log::info!("Transfer manager shutdown complete");
         Ok(())
@@ -326,6 +1067,280 @@ impl TransferManager {
     }
 }
 
+// A file or directory inside a directory transfer, by its '/'-separated path below the root
+#[derive(Debug, Clone, PartialEq)]
+struct TreeEntry {
+    relative: String,
+    size: u64,
+    is_directory: bool,
+}
+
+// The remote root without a trailing slash, or an error for paths that can't be transferred
+fn validate_directory_paths(remote_path: &str, local_path: &str) -> AppResult<String> {
+    let remote_path = remote_path.trim();
+    if remote_path.is_empty() || local_path.trim().is_empty() {
+        return Err(AppError::ValidationError("Directory transfers need both a remote and a local path".to_string()));
+    }
+    match remote_path.trim_end_matches('/') {
+        "" => Ok("/".to_string()),
+        root => Ok(root.to_string()),
+    }
+}
+
+fn relative_path(root: &str, path: &str) -> Option<String> {
+    let relative = path.strip_prefix(root.trim_end_matches('/'))?.trim_start_matches('/');
+    (!relative.is_empty()).then(|| relative.to_string())
+}
+
+// Built a component at a time, so the separator is right for this platform
+fn local_target(root: &Path, relative: &str) -> PathBuf {
+    relative.split('/').fold(root.to_path_buf(), |path, part| path.join(part))
+}
+
+// Every entry below a local directory, parents before their children. Symbolic links to
+// directories are skipped, so a link cycle can't make the walk endless.
+async fn walk_local(root: &Path) -> AppResult<(Vec<TreeEntry>, Vec<(String, AppError)>)> {
+    if !tokio::fs::metadata(root).await?.is_dir() {
+        return Err(AppError::ValidationError(format!("{} is not a directory", root.display())));
+    }
+
+    let mut entries = Vec::new();
+    let mut unlisted = Vec::new();
+    let mut pending = vec![String::new()];
+    while let Some(directory) = pending.pop() {
+        let mut listing = Vec::new();
+        let read = async {
+            let mut reader = tokio::fs::read_dir(local_target(root, &directory)).await?;
+            while let Some(entry) = reader.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let file_type = entry.file_type().await?;
+                let metadata = if file_type.is_symlink() { tokio::fs::metadata(entry.path()).await? } else { entry.metadata().await? };
+                if file_type.is_symlink() && metadata.is_dir() {
+                    log::debug!("Skipping symbolic link to a directory: {}", entry.path().display());
+                    continue;
+                }
+                let relative = if directory.is_empty() { name } else { format!("{}/{}", directory, name) };
+                listing.push(TreeEntry { relative, size: metadata.len(), is_directory: metadata.is_dir() });
+            }
+            Ok::<_, AppError>(())
+        };
+        if let Err(e) = read.await {
+            if directory.is_empty() {
+                return Err(e);
+            }
+            unlisted.push((directory, e));
+            continue;
+        }
+
+        listing.sort_by(|a, b| a.relative.cmp(&b.relative));
+        pending.extend(listing.iter().rev().filter(|entry| entry.is_directory).map(|entry| entry.relative.clone()));
+        entries.extend(listing);
+    }
+    Ok((entries, unlisted))
+}
+
+// Record the tree's totals and the directories that couldn't be listed
+fn plan(transfers: &DashMap<String, FileTransfer>, progress: &TransferProgress, transfer_id: &str, entries: &[TreeEntry], unlisted: Vec<(String, AppError)>) {
+    progress.update(transfers, transfer_id, |transfer| {
+        if matches!(transfer.status, TransferStatus::Pending) {
+            transfer.status = TransferStatus::InProgress;
+        }
+        transfer.files_total = entries.iter().filter(|entry| !entry.is_directory).count() as u32;
+        transfer.size = entries.iter().filter(|entry| !entry.is_directory).map(|entry| entry.size).sum();
+        transfer.errors.extend(unlisted.into_iter().map(|(path, e)| TransferEntryError { path, error: e.to_string() }));
+    });
+}
+
+fn start_file(transfers: &DashMap<String, FileTransfer>, transfer_id: &str, entry: &TreeEntry) {
+    if let Some(mut transfer) = transfers.get_mut(transfer_id) {
+        transfer.current_file = Some(TransferFileProgress {
+            path: entry.relative.clone(),
+            size: entry.size,
+            transferred: 0,
+        });
+    }
+}
+
+// Progress callback for a single file's copy, which runs on the blocking pool and so owns
+// what it updates. Records the running total and throughput, then keeps to the caps.
+fn reporter(transfers: &Arc<DashMap<String, FileTransfer>>, progress: &Arc<TransferProgress>, control: &TransferControl, transfer_id: &str) -> impl Fn(u64) -> AppResult<()> + Send + Sync + 'static {
+    let (transfers, progress, control, transfer_id) = (transfers.clone(), progress.clone(), control.clone(), transfer_id.to_string());
+    move |done| {
+        progress.update(&transfers, &transfer_id, |transfer| {
+            transfer.transferred = done;
+            transfer.bytes_per_second = control.rate();
+        });
+        control.pace(done)
+    }
+}
+
+fn record_progress(transfers: &DashMap<String, FileTransfer>, progress: &TransferProgress, transfer_id: &str, total: u64, file: u64, rate: Option<u64>) {
+    progress.update(transfers, transfer_id, |transfer| {
+        transfer.transferred = total;
+        transfer.bytes_per_second = rate;
+        if let Some(current) = transfer.current_file.as_mut() {
+            current.transferred = file;
+        }
+    });
+}
+
+fn finish_file(transfers: &DashMap<String, FileTransfer>, progress: &TransferProgress, transfer_id: &str, total: u64) {
+    progress.update(transfers, transfer_id, |transfer| {
+        transfer.files_done += 1;
+        transfer.transferred = total;
+        transfer.current_file = None;
+    });
+}
+
+fn record_entry_error(transfers: &DashMap<String, FileTransfer>, transfer_id: &str, path: &str, error: &AppError) {
+    log_transfer!(transfer_id, "entry_failed", HashMap::from([
+        ("path".to_string(), path.to_string()),
+        ("error".to_string(), error.to_string()),
+    ]));
+    if let Some(mut transfer) = transfers.get_mut(transfer_id) {
+        transfer.current_file = None;
+        transfer.errors.push(TransferEntryError {
+            path: path.to_string(),
+            error: error.to_string(),
+        });
+    }
+}
+
+// Failed when any entry was left behind, though everything else was still copied
+fn finish_directory_transfer(transfers: &DashMap<String, FileTransfer>, progress: &TransferProgress, transfer_id: &str, result: AppResult<()>) {
+    let Some(mut transfer) = transfers.get_mut(transfer_id) else {
+        return;
+    };
+    if matches!(transfer.status, TransferStatus::Cancelled) {
+        return;
+    }
+
+    transfer.current_file = None;
+    transfer.bytes_per_second = None;
+    transfer.end_time = Some(Utc::now());
+    match result {
+        Ok(()) if transfer.errors.is_empty() => {
+            transfer.status = TransferStatus::Completed;
+            log_transfer!(transfer_id, "completed");
+        }
+        Ok(()) => {
+            transfer.status = TransferStatus::Failed;
+            transfer.error = Some(format!("{} entries could not be transferred", transfer.errors.len()));
+            log_transfer!(transfer_id, "partially_failed", HashMap::from([("errors".to_string(), transfer.errors.len().to_string())]));
+        }
+        Err(e) => {
+            transfer.status = TransferStatus::Failed;
+            transfer.error = Some(e.to_string());
+            log_transfer!(transfer_id, "failed", HashMap::from([("error".to_string(), e.to_string())]));
+        }
+    }
+    progress.report(&transfer);
+    emit_finished(&transfer);
+}
+
+// Marks a single-file transfer as running unless it was paused meanwhile, and returns
+// where its copy carries on from
+fn start_run(transfers: &DashMap<String, FileTransfer>, progress: &TransferProgress, transfer_id: &str) -> u64 {
+    let mut offset = 0;
+    progress.update(transfers, transfer_id, |transfer| {
+        if matches!(transfer.status, TransferStatus::Pending) {
+            transfer.status = TransferStatus::InProgress;
+        }
+        offset = transfer.transferred;
+    });
+    offset
+}
+
+fn finish_file_transfer(transfers: &DashMap<String, FileTransfer>, progress: &TransferProgress, transfer_id: &str, result: AppResult<(u64, String)>) {
+    let Some(mut transfer) = transfers.get_mut(transfer_id) else {
+        return;
+    };
+    if matches!(transfer.status, TransferStatus::Cancelled) {
+        return;
+    }
+
+    transfer.bytes_per_second = None;
+    transfer.end_time = Some(Utc::now());
+    match result {
+        Ok((size, checksum)) => {
+            transfer.status = TransferStatus::Completed;
+            transfer.size = size;
+            transfer.transferred = size;
+            transfer.checksum = Some(checksum);
+            log_transfer!(transfer_id, "completed");
+        }
+        Err(e) => {
+            transfer.status = TransferStatus::Failed;
+            transfer.error = Some(e.to_string());
+            log_transfer!(transfer_id, "failed", HashMap::from([("error".to_string(), e.to_string())]));
+        }
+    }
+    progress.report(&transfer);
+    emit_finished(&transfer);
+}
+
+// Hashes a download as it's written, across the runs a pause splits it into
+struct HashWriter(Arc<parking_lot::Mutex<Sha256>>);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    hex(&Sha256::digest(content))
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn manifest_csv(manifest: &TransferManifest) -> String {
+    let mut csv = String::from("transfer_id,name,remote_path,direction,status,size,sha256,start_time,duration_ms,error\n");
+    for entry in &manifest.entries {
+        let fields = [
+            entry.transfer_id.clone(),
+            entry.name.clone(),
+            entry.remote_path.clone(),
+            serde_json::to_value(&entry.direction).ok().and_then(|value| value.as_str().map(str::to_string)).unwrap_or_default(),
+            serde_json::to_value(&entry.status).ok().and_then(|value| value.as_str().map(str::to_string)).unwrap_or_default(),
+            entry.size.to_string(),
+            entry.checksum.clone().unwrap_or_default(),
+            entry.start_time.to_rfc3339(),
+            entry.duration_ms.to_string(),
+            entry.error.clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+// Quoted when it holds a separator, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// The webhook payload is the finished transfer record
+fn emit_finished(transfer: &FileTransfer) {
+    let kind = match transfer.status {
+        TransferStatus::Completed => WebhookEventKind::TransferCompleted,
+        _ => WebhookEventKind::TransferFailed,
+    };
+    webhooks::emit(kind, Some(&transfer.session_id), serde_json::to_value(transfer).unwrap_or_default());
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -377,6 +1392,57 @@ mod tests {
         assert_eq!(manager.get_total_transfer_count(), 0);
     }
 
+    #[tokio::test]
+    async fn test_export_manifest() {
+        let ssh_manager = Arc::new(RwLock::new(SSHManager::new()));
+        let manager = TransferManager::new(ssh_manager);
+
+        let start_time = Utc::now();
+        let finished = |id: &str, name: &str| FileTransfer {
+            id: id.to_string(),
+            session_id: "session".to_string(),
+            name: name.to_string(),
+            remote_path: format!("/srv/app/{}", name),
+            local_path: None,
+            size: 5,
+            transferred: 5,
+            status: TransferStatus::Completed,
+            direction: TransferDirection::Upload,
+            start_time,
+            end_time: Some(start_time + chrono::Duration::milliseconds(1500)),
+            error: None,
+            checksum: Some(sha256_hex(b"hello")),
+            is_directory: false,
+            files_total: 0,
+            files_done: 0,
+            current_file: None,
+            errors: Vec::new(),
+            bytes_per_second: None,
+            max_bytes_per_second: None,
+        };
+        manager.transfers.insert("a".to_string(), finished("a", "app.conf"));
+        manager.transfers.insert("b".to_string(), finished("b", "notes, v2.txt"));
+
+        let ids = vec!["a".to_string(), "b".to_string()];
+        let manifest = manager.manifest(&ids).unwrap();
+        assert_eq!(manifest.file_count, 2);
+        assert_eq!(manifest.total_bytes, 10);
+        assert_eq!(manifest.entries[0].duration_ms, 1500);
+
+        let csv = manager.export_manifest(&ids, ManifestFormat::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains(",upload,completed,5,2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824,"));
+        assert!(lines[2].contains("\"notes, v2.txt\""));
+
+        let json: serde_json::Value = serde_json::from_str(&manager.export_manifest(&ids, ManifestFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["entries"][1]["remotePath"], "/srv/app/notes, v2.txt");
+
+        manager.transfers.get_mut("b").unwrap().end_time = None;
+        assert!(manager.manifest(&ids).is_err());
+        assert!(matches!(manager.manifest(&["missing".to_string()]), Err(AppError::NotFound(_))));
+    }
+
     #[tokio::test]
     async fn test_cancel_nonexistent_transfer() {
         let ssh_manager = Arc::new(RwLock::new(SSHManager::new()));
@@ -386,4 +1452,242 @@ mod tests {
         let result = manager.cancel_transfer("non-existent-id");
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_progress_is_throttled() {
+        let progress = Arc::new(TransferProgress::new());
+        let mut events = progress.subscribe();
+        let manager = TransferManager::with_progress(Arc::new(RwLock::new(SSHManager::new())), progress.clone());
+        let mut transfer = TransferProgress::external("session", "/srv/app/build.tar", "/tmp/build.tar", TransferDirection::Download);
+        transfer.size = 300;
+        let tracked = manager.track(transfer);
+
+        for transferred in [100, 200, 300] {
+            tracked.report(transferred, || Ok(())).unwrap();
+        }
+        tracked.finish(&Ok(300));
+
+        // The first update and the final one; those in between came too soon
+        assert_eq!(events.try_recv().unwrap().transferred, 100);
+        let finished = events.try_recv().unwrap();
+        assert!(matches!(finished.status, TransferStatus::Completed));
+        assert_eq!((finished.transferred, finished.name.as_str()), (300, "build.tar"));
+        assert!(events.try_recv().is_err());
+        assert!(progress.last_sent.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_walk_local_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("site");
+        std::fs::create_dir_all(root.join("assets/img")).unwrap();
+        std::fs::write(root.join("index.html"), "<html>").unwrap();
+        std::fs::write(root.join("assets/app.js"), "let a;").unwrap();
+        std::fs::write(root.join("assets/img/logo.svg"), "<svg/>").unwrap();
+
+        let (entries, unlisted) = walk_local(&root).await.unwrap();
+        assert!(unlisted.is_empty());
+        let paths: Vec<(&str, bool)> = entries.iter().map(|entry| (entry.relative.as_str(), entry.is_directory)).collect();
+        assert_eq!(paths, [
+            ("assets", true),
+            ("index.html", false),
+            ("assets/app.js", false),
+            ("assets/img", true),
+            ("assets/img/logo.svg", false),
+        ]);
+        assert_eq!(entries[1].size, 6);
+
+        assert!(walk_local(&root.join("index.html")).await.is_err());
+        assert!(walk_local(&root.join("missing")).await.is_err());
+    }
+
+    #[test]
+    fn test_directory_paths() {
+        assert_eq!(validate_directory_paths("/var/www/", "/tmp/www").unwrap(), "/var/www");
+        assert_eq!(validate_directory_paths("/", "/tmp/root").unwrap(), "/");
+        assert!(validate_directory_paths(" ", "/tmp/www").is_err());
+        assert!(validate_directory_paths("/var/www", "").is_err());
+
+        assert_eq!(relative_path("/var/www", "/var/www/assets/app.js").as_deref(), Some("assets/app.js"));
+        assert_eq!(relative_path("/", "/etc").as_deref(), Some("etc"));
+        assert_eq!(relative_path("/var/www", "/var/www"), None);
+        assert_eq!(relative_path("/var/www", "/srv/app"), None);
+
+        // What a download or upload of / names its entries
+        assert_eq!(join_remote("/", "etc/hosts"), "/etc/hosts");
+        assert_eq!(join_remote("/var/www", "assets/app.js"), "/var/www/assets/app.js");
+        assert_eq!(relative_path("/", &join_remote("/", "etc/hosts")).as_deref(), Some("etc/hosts"));
+
+        assert_eq!(local_target(Path::new("/tmp/www"), "assets/app.js"), Path::new("/tmp/www").join("assets").join("app.js"));
+    }
+
+    #[tokio::test]
+    async fn test_pause_resume_and_cancel() {
+        let ssh_manager = Arc::new(RwLock::new(SSHManager::new()));
+        let mut manager = TransferManager::new(ssh_manager);
+        let transfer_id = manager.begin_directory_transfer("session", "/var/www", "/tmp/www", TransferDirection::Download).await.unwrap();
+        let control = manager.add_control(&transfer_id);
+
+        let transfer = manager.pause_transfer(&transfer_id).unwrap();
+        assert!(matches!(transfer.status, TransferStatus::Paused));
+        assert!(control.interrupted());
+
+        // The copy loop waits for the resume
+        let waiting = tokio::spawn({
+            let control = control.clone();
+            async move { control.proceed().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        let transfer = manager.resume_transfer(&transfer_id).unwrap();
+        assert!(matches!(transfer.status, TransferStatus::InProgress));
+        assert!(waiting.await.unwrap());
+        assert!(control.check().is_ok());
+
+        manager.pause_transfer(&transfer_id).unwrap();
+        manager.cancel_transfer(&transfer_id).unwrap();
+        assert!(matches!(manager.get_transfer(&transfer_id).unwrap().status, TransferStatus::Cancelled));
+        assert!(!control.proceed().await);
+        assert_eq!(manager.get_active_transfer_count(), 0);
+
+        assert!(manager.pause_transfer("missing").is_err());
+    }
+
+    #[test]
+    fn test_throttle_paces_to_its_cap() {
+        let throttle = Throttle::new(Some(20_000));
+        let started = Instant::now();
+        for done in [1_000, 2_000, 3_000, 4_000] {
+            throttle.pace(done, || Ok(())).unwrap();
+        }
+        // 4 KB at 20 KB/s
+        assert!(started.elapsed() >= Duration::from_millis(190));
+
+        // A restarted copy counts from zero again, and a lifted cap doesn't wait
+        throttle.limit().set(None).unwrap();
+        let started = Instant::now();
+        throttle.pace(1_000_000, || Ok(())).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert!(throttle.limit().set(Some(0)).is_err());
+
+        // A cancel ends the wait rather than the whole booked time
+        let throttle = Throttle::new(Some(1));
+        let checks = std::cell::Cell::new(0);
+        let cancelled = throttle.pace(1_000, || {
+            checks.set(checks.get() + 1);
+            if checks.get() > 1 {
+                return Err(AppError::TransferError("Transfer cancelled".to_string()));
+            }
+            Ok(())
+        });
+        assert!(cancelled.is_err());
+        assert_eq!(checks.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_capped_copy_leaves_other_operations_running() {
+        let transfers = Arc::new(DashMap::new());
+        let progress = Arc::new(TransferProgress::new());
+        let control = TransferControl::new(Some(20_000));
+        let report = reporter(&transfers, &progress, &control, "capped");
+        // 10 KB at 20 KB/s, reported from the blocking pool as a real copy does
+        let copy = tokio::spawn(crate::ssh::blocking(move || {
+            for done in (1..=10).map(|kb| kb * 1_000) {
+                report(done)?;
+            }
+            Ok(())
+        }));
+
+        // This runtime has a single worker, so these only finish early if the copy isn't on it
+        let started = Instant::now();
+        let manager = SSHManager::new();
+        let (sessions, ()) = tokio::join!(manager.list_sessions(), tokio::time::sleep(Duration::from_millis(20)));
+        assert!(sessions.is_empty());
+        assert!(started.elapsed() < Duration::from_millis(300));
+        assert!(!copy.is_finished());
+
+        copy.await.unwrap().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(450));
+    }
+
+    #[tokio::test]
+    async fn test_tracked_transfer_pause_and_cancel() {
+        let ssh_manager = Arc::new(RwLock::new(SSHManager::new()));
+        let progress = Arc::new(TransferProgress::new());
+        let mut manager = TransferManager::with_progress(ssh_manager, progress.clone());
+        let mut events = progress.subscribe();
+
+        let tracked = manager.track(TransferProgress::external("session", "/tmp/a.log", "/home/me/a.log", TransferDirection::Download));
+        assert!(tracked.report(100, || Ok(())).is_ok());
+        assert_eq!(manager.get_transfer(tracked.id()).unwrap().transferred, 100);
+        assert_eq!(events.try_recv().unwrap().transferred, 100);
+        // The caller's check stops the copy too
+        assert!(tracked.report(200, || Err(AppError::OperationFailed("Job cancelled".to_string()))).is_err());
+        assert!(!tracked.interrupted());
+
+        manager.pause_transfer(tracked.id()).unwrap();
+        assert!(tracked.report(300, || Ok(())).is_err());
+        assert!(tracked.interrupted());
+        manager.resume_transfer(tracked.id()).unwrap();
+        assert!(tracked.proceed().await);
+
+        manager.cancel_transfer(tracked.id()).unwrap();
+        assert!(!tracked.proceed().await);
+        tracked.finish(&Err(AppError::TransferError("Transfer cancelled".to_string())));
+        let transfer = manager.get_transfer(tracked.id()).unwrap();
+        assert!(matches!(transfer.status, TransferStatus::Cancelled));
+        assert!(transfer.error.is_none());
+        assert!(manager.pause_transfer(tracked.id()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_set_transfer_limit() {
+        let ssh_manager = Arc::new(RwLock::new(SSHManager::new()));
+        let mut manager = TransferManager::new(ssh_manager);
+        let transfer_id = manager.begin_directory_transfer("session", "/var/www", "/tmp/www", TransferDirection::Download).await.unwrap();
+        assert!(manager.set_transfer_limit(&transfer_id, Some(1024)).is_err());
+
+        let control = manager.add_control(&transfer_id);
+        let transfer = manager.set_transfer_limit(&transfer_id, Some(1024)).unwrap();
+        assert_eq!(transfer.max_bytes_per_second, Some(1024));
+        assert_eq!(control.throttle.limit().get(), Some(1024));
+        assert!(manager.set_transfer_limit(&transfer_id, Some(0)).is_err());
+        assert_eq!(manager.set_transfer_limit(&transfer_id, None).unwrap().max_bytes_per_second, None);
+    }
+
+    #[tokio::test]
+    async fn test_directory_transfer_progress() {
+        let ssh_manager = Arc::new(RwLock::new(SSHManager::new()));
+        let mut manager = TransferManager::new(ssh_manager);
+        let transfer_id = manager.begin_directory_transfer("session", "/var/www/", "/tmp/www", TransferDirection::Download).await.unwrap();
+        let transfers = manager.transfers.clone();
+        let progress = manager.progress.clone();
+        assert_eq!(manager.get_transfer(&transfer_id).unwrap().name, "www");
+
+        let entries = [
+            TreeEntry { relative: "assets".to_string(), size: 0, is_directory: true },
+            TreeEntry { relative: "assets/app.js".to_string(), size: 100, is_directory: false },
+            TreeEntry { relative: "index.html".to_string(), size: 50, is_directory: false },
+        ];
+        plan(&transfers, &progress, &transfer_id, &entries, vec![("private".to_string(), AppError::PermissionDenied("denied".to_string()))]);
+
+        start_file(&transfers, &transfer_id, &entries[1]);
+        record_progress(&transfers, &progress, &transfer_id, 40, 40, None);
+        let transfer = manager.get_transfer(&transfer_id).unwrap();
+        assert_eq!((transfer.files_total, transfer.size, transfer.transferred), (2, 150, 40));
+        assert_eq!(transfer.current_file.unwrap().transferred, 40);
+
+        finish_file(&transfers, &progress, &transfer_id, 100);
+        start_file(&transfers, &transfer_id, &entries[2]);
+        record_entry_error(&transfers, &transfer_id, "index.html", &AppError::FileOperationFailed("gone".to_string()));
+        finish_directory_transfer(&transfers, &progress, &transfer_id, Ok(()));
+
+        // Everything that could be copied was, the rest is listed
+        let transfer = manager.get_transfer(&transfer_id).unwrap();
+        assert!(matches!(transfer.status, TransferStatus::Failed));
+        assert_eq!((transfer.files_done, transfer.transferred), (1, 100));
+        let failed: Vec<&str> = transfer.errors.iter().map(|error| error.path.as_str()).collect();
+        assert_eq!(failed, ["private", "index.html"]);
+        assert!(transfer.current_file.is_none() && transfer.end_time.is_some());
+    }
 }
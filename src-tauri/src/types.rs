@@ -10,34 +10,964 @@ pub struct SSHConnectionConfig {
     pub password: Option<String>,
     #[serde(rename = "privateKey")]
     pub private_key: Option<String>,
+    // Key file on this machine, e.g. one returned by ssh_discover_keys
+    #[serde(rename = "privateKeyPath", default)]
+    pub private_key_path: Option<String>,
     pub passphrase: Option<String>,
     #[serde(rename = "keepAlive")]
     pub keep_alive: Option<bool>,
     #[serde(rename = "readyTimeout")]
     pub ready_timeout: Option<u32>,
+    // ws:// or wss:// gateway for servers that only expose SSH over WebSocket
+    #[serde(rename = "websocketUrl", default)]
+    pub websocket_url: Option<String>,
+    // Gather OS, uptime, memory and disk details right after connecting
+    #[serde(rename = "collectHostInfo", default)]
+    pub collect_host_info: bool,
+    // Terminal charset, e.g. "gbk" or "shift_jis"; unset or "auto" follows the remote locale
+    #[serde(default)]
+    pub charset: Option<String>,
+    // Declared by the client; unset keeps the defaults and passes output through untouched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub terminal: Option<TerminalCapabilities>,
+    // Bastions to hop through, in order, like ssh -J; each one reaches the next over a
+    // direct-tcpip channel, and the last one reaches this host
+    #[serde(rename = "jumpHosts", default, skip_serializing_if = "Vec::is_empty")]
+    pub jump_hosts: Vec<SSHConnectionConfig>,
+    // Connect without a password or key, answering the server's prompts (PAM, one-time
+    // codes) through auth_prompt. Prompts after a password or key are answered either way.
+    #[serde(rename = "keyboardInteractive", default)]
+    pub keyboard_interactive: bool,
+    // How files are uploaded and downloaded; SCP for hosts without an SFTP subsystem
+    #[serde(rename = "fileTransfer", default)]
+    pub file_transfer: FileTransferProtocol,
+    // From the profile the config was built from; what the client asks for explicitly wins
+    #[serde(default, skip_serializing_if = "SessionDefaults::is_empty")]
+    pub defaults: SessionDefaults,
+    // Set when the secrets came from the credential store, so their use is audited
+    #[serde(skip)]
+    pub credential: Option<CredentialSource>,
+    // Set for protected profiles; connecting waits for the second step. Boxed to keep
+    // configs small in the WebSocket messages that carry them.
+    #[serde(skip)]
+    pub approval: Option<Box<ConnectApproval>>,
 }
 
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileTransferProtocol {
+    // SFTP, switching to SCP for the session if the server won't open an SFTP channel
+    #[default]
+    Auto,
+    Sftp,
+    // Only whole-file uploads and downloads; browsing and file management need SFTP
+    Scp,
+}
+
+// Terminal and transfer settings for one host, configured once on its profile
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionDefaults {
+    // Shell size when the client doesn't send one
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+    // TERM when the client's terminal doesn't name one
+    pub term: Option<String>,
+    // Output kept for clients attaching later, instead of the built-in 64 KiB
+    #[serde(rename = "scrollbackBytes")]
+    pub scrollback_bytes: Option<usize>,
+    // Start a recording as soon as the shell opens
+    #[serde(rename = "autoRecord", default)]
+    pub auto_record: bool,
+    // Bandwidth cap for each transfer on the session
+    #[serde(rename = "maxBytesPerSecond")]
+    pub max_bytes_per_second: Option<u64>,
+    // Transfers on the session allowed to run at once, within the overall limit
+    #[serde(rename = "maxConcurrentTransfers")]
+    pub max_concurrent_transfers: Option<usize>,
+}
+
+impl SessionDefaults {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CredentialSource {
+    pub key: String,
+    pub profile_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ApprovalMethod {
+    // A code from the authenticator app the profile's secret was enrolled in
+    Totp,
+    // Someone else approves the request
+    Admin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectApproval {
+    pub profile_id: String,
+    pub profile_name: String,
+    pub method: ApprovalMethod,
+    // Base32, for ApprovalMethod::Totp
+    pub totp_secret: Option<String>,
+}
+
+// A connection to a protected host waiting for its second step. Answered with a TOTP code
+// or an approval, depending on the method.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    #[serde(rename = "requestId")]
+    pub request_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "profileId")]
+    pub profile_id: String,
+    #[serde(rename = "profileName")]
+    pub profile_name: String,
+    pub hostname: String,
+    pub port: u16,
+    pub username: String,
+    pub method: ApprovalMethod,
+    #[serde(rename = "requestedAt")]
+    pub requested_at: DateTime<Utc>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ApprovalOutcome {
+    Requested,
+    Approved,
+    Denied,
+    InvalidCode,
+    Expired,
+    Cancelled,
+}
+
+// One step of an approval, for the security log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalAttempt {
+    pub request: ApprovalRequest,
+    pub outcome: ApprovalOutcome,
+    // Who approved or denied it, when someone did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approver: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalResponseData {
+    #[serde(rename = "requestId")]
+    pub request_id: String,
+    // For TOTP requests
+    #[serde(default)]
+    pub code: Option<String>,
+    // For admin requests: approve, or deny when false
+    #[serde(default)]
+    pub approve: bool,
+    #[serde(default)]
+    pub approver: Option<String>,
+}
+
+// A stored credential was offered to a server, kept so a leaked key's reach can be traced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialUsage {
+    pub key: String,
+    #[serde(rename = "profileId", default, skip_serializing_if = "Option::is_none")]
+    pub profile_id: Option<String>,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub hostname: String,
+    pub port: u16,
+    pub username: String,
+    // Who was signed in on this machine
+    #[serde(rename = "localUser", default, skip_serializing_if = "Option::is_none")]
+    pub local_user: Option<String>,
+    // "password" or "publickey"
+    pub method: String,
+    pub succeeded: bool,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SSHSession {
+    pub id: String,
+    pub config: SSHConnectionConfig,
+    pub connected: bool,
+    #[serde(rename = "lastActivity")]
+    pub last_activity: DateTime<Utc>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "hostInfo", default, skip_serializing_if = "Option::is_none")]
+    pub host_info: Option<HostInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<RemoteEnvironment>,
+    // Classified from the MOTD and login messages of the first shell
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notices: Vec<SessionNotice>,
+    // Set once the remote shell exits; the SSH connection itself may still be up
+    #[serde(rename = "shellExit", default, skip_serializing_if = "Option::is_none")]
+    pub shell_exit: Option<ShellExit>,
+    #[serde(flatten)]
+    pub tags: SessionTags,
+}
+
+// Set by the user to tell sessions apart, e.g. prod from staging; kept with the profile
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionTags {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    // #rrggbb
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+// How a session's shell ended
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellExit {
+    // None when the server closed the channel without reporting one
+    #[serde(rename = "exitStatus")]
+    pub exit_status: Option<i32>,
+    // e.g. "KILL" when the shell died from a signal
+    #[serde(rename = "exitSignal", default, skip_serializing_if = "Option::is_none")]
+    pub exit_signal: Option<String>,
+    #[serde(rename = "closedAt")]
+    pub closed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineStage {
+    Dns,
+    Tcp,
+    Handshake,
+    HostKey,
+    Auth,
+    // The whole connect, from the first lookup to authenticated
+    Connected,
+    Shell,
+    ShellClosed,
+    Reconnect,
+    Disconnect,
+    // Failures outside any of the steps above, e.g. a shell read
+    Error,
+}
+
+// One step in a session's life, so "why was connecting slow" can be answered afterwards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    pub stage: TimelineStage,
+    // When the step started
+    pub at: DateTime<Utc>,
+    #[serde(rename = "durationMs", default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    // The host of the hop, the reason for a disconnect, ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Sent as the shell-closed Tauri event and the shell_closed WebSocket message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellClosedEvent {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(flatten)]
+    pub exit: ShellExit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoticeKind {
+    DiskSpace,
+    RebootRequired,
+    FailedLogins,
+    LastLogin,
+    Updates,
+    SecurityUpdates,
+    PasswordExpiry,
+    Mail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoticeSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionNotice {
+    pub kind: NoticeKind,
+    pub severity: NoticeSeverity,
+    // The login line it came from, without escape sequences
+    pub message: String,
+}
+
+// What the client's terminal emulator handles, so the shell can be set up to match
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TerminalCapabilities {
+    // 24-bit SGR colours; without it they're mapped to the 256-colour palette
+    #[serde(default)]
+    pub truecolor: bool,
+    // Wide characters laid out per Unicode's East Asian Width tables
+    #[serde(rename = "unicodeWidth", default)]
+    pub unicode_width: bool,
+    // The kitty keyboard protocol; without it, requests to turn it on are dropped
+    #[serde(rename = "kittyKeyboard", default)]
+    pub kitty_keyboard: bool,
+    // TERM for the shell, e.g. "xterm-kitty"; defaults to xterm-256color
+    #[serde(default)]
+    pub term: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalCharset {
+    // As set on the connection; None means auto
+    pub configured: Option<String>,
+    // What the shell is decoded with, once it's open
+    pub active: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionNoticesEvent {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub notices: Vec<SessionNotice>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+    // sh, dash, ash
+    Sh,
+    Ksh,
+    // csh, tcsh
+    Csh,
+    #[default]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OsFamily {
+    Linux,
+    Macos,
+    Bsd,
+    Windows,
+    #[default]
+    Unknown,
+}
+
+// What kind of host the session is on, detected once on connect so completions,
+// snippets and command policies can adapt to it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RemoteEnvironment {
+    // The login shell, which interactive shells run
+    pub shell: ShellKind,
+    #[serde(rename = "shellPath")]
+    pub shell_path: Option<String>,
+    #[serde(rename = "osFamily")]
+    pub os_family: OsFamily,
+    // os-release ID such as "ubuntu" or "rhel", or "macos"
+    #[serde(rename = "osId")]
+    pub os_id: Option<String>,
+    // os-release ID_LIKE, e.g. ["rhel", "fedora"] on Rocky
+    #[serde(rename = "osLike")]
+    pub os_like: Vec<String>,
+    #[serde(rename = "osVersion")]
+    pub os_version: Option<String>,
+    pub locale: Option<String>,
+    pub utf8: bool,
+    #[serde(rename = "detectedAt")]
+    pub detected_at: Option<DateTime<Utc>>,
+}
+
+// Exported variables and aliases of the login shell, for display and completing $VARS
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellVariables {
+    pub variables: Vec<ShellVariable>,
+    pub aliases: Vec<ShellAlias>,
+    #[serde(rename = "collectedAt")]
+    pub collected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellVariable {
+    pub name: String,
+    // None when the name looks like it holds a secret
+    pub value: Option<String>,
+    pub sensitive: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellAlias {
+    pub name: String,
+    pub command: String,
+}
+
+// Users and groups of the remote host, for showing file ownership by name and offering chown choices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteAccounts {
+    pub users: Vec<RemoteUser>,
+    pub groups: Vec<RemoteGroup>,
+    #[serde(rename = "collectedAt")]
+    pub collected_at: DateTime<Utc>,
+}
+
+impl RemoteAccounts {
+    pub fn user_name(&self, uid: u32) -> Option<String> {
+        self.users.iter().find(|user| user.uid == uid).map(|user| user.name.clone())
+    }
+
+    pub fn group_name(&self, gid: u32) -> Option<String> {
+        self.groups.iter().find(|group| group.gid == gid).map(|group| group.name.clone())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteUser {
+    pub uid: u32,
+    // The user's primary group
+    pub gid: u32,
+    pub name: String,
+    pub home: String,
+    pub shell: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteGroup {
+    pub gid: u32,
+    pub name: String,
+    // Users with this as a supplementary group; those with it as their primary group aren't listed
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskUsage {
+    pub filesystem: String,
+    #[serde(rename = "mountPoint")]
+    pub mount_point: String,
+    #[serde(rename = "totalKb")]
+    pub total_kb: u64,
+    #[serde(rename = "usedKb")]
+    pub used_kb: u64,
+    #[serde(rename = "availableKb")]
+    pub available_kb: u64,
+}
+
+// Snapshot of the remote machine for the host dashboard. Fields the server
+// couldn't report (no /proc on BSD, no df, ...) are left empty.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub os: Option<String>,
+    pub kernel: Option<String>,
+    pub hostname: Option<String>,
+    #[serde(rename = "uptimeSeconds")]
+    pub uptime_seconds: Option<u64>,
+    #[serde(rename = "cpuCount")]
+    pub cpu_count: Option<u32>,
+    #[serde(rename = "loadAverage")]
+    pub load_average: Option<[f64; 3]>,
+    #[serde(rename = "memoryTotalKb")]
+    pub memory_total_kb: Option<u64>,
+    #[serde(rename = "memoryAvailableKb")]
+    pub memory_available_kb: Option<u64>,
+    pub disks: Vec<DiskUsage>,
+    #[serde(rename = "collectedAt")]
+    pub collected_at: Option<DateTime<Utc>>,
+}
+
+// A named marker in a shell's live output, to jump back to in scrollback or a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputBookmark {
+    pub id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub name: String,
+    // Bytes of output the shell had printed when the marker was dropped
+    pub offset: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// Interfaces and listening sockets of the remote host, parsed from ip and ss
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkInfo {
+    pub interfaces: Vec<NetworkInterface>,
+    pub sockets: Vec<ListeningSocket>,
+    #[serde(rename = "collectedAt")]
+    pub collected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    pub name: String,
+    // UP, DOWN or UNKNOWN as ip reports it; loopback is usually UNKNOWN
+    pub state: Option<String>,
+    pub mtu: Option<u32>,
+    pub mac: Option<String>,
+    pub addresses: Vec<InterfaceAddress>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterfaceAddress {
+    pub family: AddressFamily,
+    pub address: String,
+    #[serde(rename = "prefixLength")]
+    pub prefix_length: u8,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListeningSocket {
+    // tcp or udp
+    pub protocol: String,
+    // As bound: 0.0.0.0, ::, a specific address or *
+    pub address: String,
+    pub port: u16,
+    // Only reachable from the host itself, so a local forward is the way in
+    pub loopback: bool,
+    // Sockets of other users' processes show none unless the session has root
+    pub process: Option<String>,
+    pub pid: Option<u32>,
+}
+
+// One row of the remote task manager, parsed from ps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteProcess {
+    pub pid: u32,
+    pub ppid: u32,
+    pub user: String,
+    #[serde(rename = "cpuPercent")]
+    pub cpu_percent: f64,
+    #[serde(rename = "memPercent")]
+    pub mem_percent: f64,
+    #[serde(rename = "rssKb")]
+    pub rss_kb: u64,
+    #[serde(rename = "elapsedSeconds")]
+    pub elapsed_seconds: u64,
+    pub state: String,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ProcessSignal {
+    Term,
+    Kill,
+    Int,
+    Hup,
+    Quit,
+    Stop,
+    Cont,
+    Usr1,
+    Usr2,
+}
+
+impl ProcessSignal {
+    // Name as understood by `kill -s`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcessSignal::Term => "TERM",
+            ProcessSignal::Kill => "KILL",
+            ProcessSignal::Int => "INT",
+            ProcessSignal::Hup => "HUP",
+            ProcessSignal::Quit => "QUIT",
+            ProcessSignal::Stop => "STOP",
+            ProcessSignal::Cont => "CONT",
+            ProcessSignal::Usr1 => "USR1",
+            ProcessSignal::Usr2 => "USR2",
+        }
+    }
+}
+
+// Sent to whatever runs in the foreground of a session's shell, as the matching Ctrl key would
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TerminalSignal {
+    Int,
+    Tstp,
+    Quit,
+    // The SSH break request (RFC 4335), e.g. for serial consoles behind the server
+    Break,
+}
+
+impl TerminalSignal {
+    // The character the remote tty turns into this signal; None for Break
+    pub fn control_char(&self) -> Option<u8> {
+        match self {
+            TerminalSignal::Int => Some(0x03),
+            TerminalSignal::Tstp => Some(0x1a),
+            TerminalSignal::Quit => Some(0x1c),
+            TerminalSignal::Break => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalSignalData {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub signal: TerminalSignal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalResult {
+    pub pid: u32,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+// A systemd service as shown in the services panel
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServiceUnit {
+    pub name: String,
+    pub description: String,
+    #[serde(rename = "loadState")]
+    pub load_state: String,
+    #[serde(rename = "activeState")]
+    pub active_state: String,
+    #[serde(rename = "subState")]
+    pub sub_state: String,
+    // enabled, disabled, static, masked, ...
+    #[serde(rename = "unitFileState")]
+    pub unit_file_state: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServiceStatus {
+    #[serde(flatten)]
+    pub unit: ServiceUnit,
+    #[serde(rename = "mainPid")]
+    pub main_pid: Option<u32>,
+    #[serde(rename = "activeSince")]
+    pub active_since: Option<String>,
+    // Latest journal lines, empty when the user can't read the journal
+    pub logs: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceAction {
+    Start,
+    Stop,
+    Restart,
+    Reload,
+    Enable,
+    Disable,
+}
+
+impl ServiceAction {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            ServiceAction::Start => "start",
+            ServiceAction::Stop => "stop",
+            ServiceAction::Restart => "restart",
+            ServiceAction::Reload => "reload",
+            ServiceAction::Enable => "enable",
+            ServiceAction::Disable => "disable",
+        }
+    }
+}
+
+// How to elevate a command. Without a password sudo runs non-interactively and
+// fails instead of hanging on a prompt.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SudoOptions {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl SudoOptions {
+    // The command to run and what to feed its stdin
+    pub fn wrap(&self, command: &str) -> (String, Option<String>) {
+        match (self.enabled, &self.password) {
+            (false, _) => (command.to_string(), None),
+            // -p '' keeps the prompt out of stderr
+            (true, Some(password)) => (format!("sudo -S -p '' {}", command), Some(format!("{}\n", password))),
+            (true, None) => (format!("sudo -n {}", command), None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DockerContainer {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub command: String,
+    // created, running, paused, exited, ...
+    pub state: String,
+    // Human readable, e.g. "Up 3 hours"
+    pub status: String,
+    pub ports: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DockerImage {
+    pub id: String,
+    pub repository: String,
+    pub tag: String,
+    pub size: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+impl ContainerAction {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            ContainerAction::Start => "start",
+            ContainerAction::Stop => "stop",
+            ContainerAction::Restart => "restart",
+        }
+    }
+}
+
+// Which kubeconfig, context and namespace a kubectl call runs against.
+// Anything left out falls back to kubectl's own defaults on the host.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KubeScope {
+    #[serde(default)]
+    pub kubeconfig: Option<String>,
+    #[serde(default)]
+    pub context: Option<String>,
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KubeContext {
+    pub name: String,
+    pub cluster: String,
+    pub user: String,
+    pub namespace: Option<String>,
+    // API server URL of the context's cluster
+    pub server: Option<String>,
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KubeNamespace {
+    pub name: String,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KubePod {
+    pub name: String,
+    pub namespace: String,
+    pub phase: String,
+    // Ready containers over total, as kubectl shows it
+    pub ready: String,
+    pub restarts: u32,
+    pub node: Option<String>,
+    pub containers: Vec<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiplexerKind {
+    Tmux,
+    Screen,
+}
+
+// A tmux or screen session left running on the host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiplexerSession {
+    pub kind: MultiplexerKind,
+    // What attach takes: the tmux session name, or screen's pid.name
+    pub id: String,
+    pub name: String,
+    pub windows: Option<u32>,
+    pub attached: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiplexerSessionsEvent {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub sessions: Vec<MultiplexerSession>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalSize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+// WebSocket message types
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SSHConnectData {
+    pub config: SSHConnectionConfig,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSocketClientStats {
+    pub id: String,
+    #[serde(rename = "connectedAt")]
+    pub connected_at: DateTime<Utc>,
+    // Last ping or pong from the client
+    #[serde(rename = "lastPing")]
+    pub last_ping: Option<DateTime<Utc>>,
+    #[serde(rename = "protocolVersion")]
+    pub protocol_version: u32,
+    pub sessions: Vec<String>,
+    #[serde(rename = "messageCount")]
+    pub message_count: u64,
+    #[serde(rename = "errorCount")]
+    pub error_count: u64,
+    #[serde(rename = "dataUsage")]
+    pub data_usage: DataUsage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSocketStats {
+    #[serde(rename = "connectedClients")]
+    pub connected_clients: usize,
+    #[serde(rename = "detachedSessions")]
+    pub detached_sessions: usize,
+    #[serde(rename = "heartbeatIntervalSeconds")]
+    pub heartbeat_interval_seconds: u64,
+    #[serde(rename = "heartbeatTimeoutSeconds")]
+    pub heartbeat_timeout_seconds: u64,
+    pub clients: Vec<WebSocketClientStats>,
+}
+
+// A manager's periodic upkeep task, e.g. the SSH manager's expired session cleanup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundTaskStatus {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "intervalSeconds")]
+    pub interval_seconds: u64,
+    #[serde(rename = "startedAt")]
+    pub started_at: DateTime<Utc>,
+    #[serde(rename = "lastRun")]
+    pub last_run: Option<DateTime<Utc>>,
+    pub runs: u64,
+    // In the middle of a run
+    pub running: bool,
+    pub healthy: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum JobKind {
+    Transfer,
+    Recording,
+    DotfileSync,
+    ScheduledCommand,
+    GroupExec,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+// Units depend on the kind: bytes for transfers, files for dotfile syncs, hosts for group runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobProgress {
+    pub done: u64,
+    pub total: Option<u64>,
+}
+
+// Any long-running piece of backend work, whichever subsystem runs it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub kind: JobKind,
+    pub title: String,
+    pub status: JobStatus,
+    pub progress: Option<JobProgress>,
+    #[serde(rename = "sessionId")]
+    pub session_id: Option<String>,
+    #[serde(rename = "startedAt")]
+    pub started_at: DateTime<Utc>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    pub cancellable: bool,
+}
+
+// Move a session attached to another client over to this one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SSHTakeoverData {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+    // Proof the client may have the session: its current resume token, or the server's auth token
+    #[serde(rename = "resumeToken")]
+    pub resume_token: Option<String>,
+    #[serde(rename = "authToken")]
+    pub auth_token: Option<String>,
+}
+
+// Ends the scrollback replayed to a client attaching to a session; live output follows
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SSHSession {
-    pub id: String,
-    pub config: SSHConnectionConfig,
-    pub connected: bool,
-    #[serde(rename = "lastActivity")]
-    pub last_activity: DateTime<Utc>,
-    #[serde(rename = "createdAt")]
-    pub created_at: DateTime<Utc>,
+pub struct TerminalReplayedResponse {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub bytes: usize,
 }
 
+// Sent to the client that lost a session to ssh_takeover
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TerminalSize {
-    pub cols: u16,
-    pub rows: u16,
+pub struct SessionTakenOverResponse {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub message: String,
 }
 
-// WebSocket message types
+// Re-attach to a session whose WebSocket dropped, within the resume grace period
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SSHConnectData {
-    pub config: SSHConnectionConfig,
+pub struct SSHResumeData {
+    #[serde(rename = "resumeToken")]
+    pub resume_token: String,
     pub cols: Option<u16>,
     pub rows: Option<u16>,
 }
@@ -54,6 +984,8 @@ pub struct TerminalOutputEvent {
     #[serde(rename = "sessionId")]
     pub session_id: String,
     pub data: String,
+    // UTF-8 length of data, returned by the frontend in terminal_output_ack
+    pub bytes: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -71,6 +1003,191 @@ pub struct TerminalDataResponse {
     pub data: String,
     pub timestamp: Option<i64>,
     pub batched: Option<bool>,
+    // "deflate" when data is raw DEFLATE, base64-encoded; see mobile_optimize
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    // Counts this session's terminal_data messages, so gaps and reordering show
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    // Set on each part of an output too large for one message; true on the last part
+    #[serde(rename = "final", default, skip_serializing_if = "Option::is_none")]
+    pub is_final: Option<bool>,
+}
+
+// Follow a remote file, streamed back as file_tail events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileFollowData {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub path: String,
+    // Existing lines to send first, like tail -n
+    pub lines: Option<u32>,
+}
+
+// Follow a container's logs; lines arrive as file_tail events and file_unfollow stops it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerLogsData {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "containerId")]
+    pub container_id: String,
+    pub lines: Option<u32>,
+    #[serde(default)]
+    pub sudo: SudoOptions,
+}
+
+// Follow a pod's logs, streamed the same way as container logs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PodLogsData {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(default)]
+    pub scope: KubeScope,
+    pub pod: String,
+    pub container: Option<String>,
+    pub lines: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PodLogsStartedResponse {
+    #[serde(rename = "followId")]
+    pub follow_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub pod: String,
+    pub container: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerLogsStartedResponse {
+    #[serde(rename = "followId")]
+    pub follow_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "containerId")]
+    pub container_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileUnfollowData {
+    #[serde(rename = "followId")]
+    pub follow_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileFollowStartedResponse {
+    #[serde(rename = "followId")]
+    pub follow_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileTailResponse {
+    #[serde(rename = "followId")]
+    pub follow_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub lines: Vec<String>,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileFollowClosedResponse {
+    #[serde(rename = "followId")]
+    pub follow_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub error: Option<String>,
+}
+
+// Poll a remote directory and send directory_changed whenever its listing changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryWatchData {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub path: String,
+    #[serde(rename = "intervalSecs", default)]
+    pub interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryUnwatchData {
+    #[serde(rename = "watchId")]
+    pub watch_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryWatchStartedResponse {
+    #[serde(rename = "watchId")]
+    pub watch_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub path: String,
+}
+
+// Entry names, relative to the watched directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirectoryChange {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryChangedEvent {
+    #[serde(rename = "watchId")]
+    pub watch_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub path: String,
+    #[serde(flatten)]
+    pub change: DirectoryChange,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryWatchClosedResponse {
+    #[serde(rename = "watchId")]
+    pub watch_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub error: Option<String>,
+}
+
+// A round of keyboard-interactive authentication, e.g. a PAM password or a one-time code.
+// Answered with auth_response, one response per prompt, in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthPromptEvent {
+    #[serde(rename = "promptId")]
+    pub prompt_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub hostname: String,
+    pub username: String,
+    pub instructions: String,
+    pub prompts: Vec<AuthPromptField>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthPromptField {
+    pub text: String,
+    // False for secrets the input should mask
+    pub echo: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthResponseData {
+    #[serde(rename = "promptId")]
+    pub prompt_id: String,
+    #[serde(default)]
+    pub responses: Vec<String>,
+    // Gives up on the connection instead of answering
+    #[serde(default)]
+    pub cancel: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,6 +1195,9 @@ pub struct SSHConnectedResponse {
     #[serde(rename = "sessionId")]
     pub session_id: String,
     pub status: String,
+    // Send back in ssh_resume to re-attach after the connection drops; None when resuming is off
+    #[serde(rename = "resumeToken", default, skip_serializing_if = "Option::is_none")]
+    pub resume_token: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -95,6 +1215,14 @@ pub struct SSHErrorResponse {
     pub details: Option<String>,
 }
 
+// Messages from this client are being dropped until retryAfterMs has passed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThrottledResponse {
+    pub message: String,
+    #[serde(rename = "retryAfterMs")]
+    pub retry_after_ms: i64,
+}
+
 // File transfer types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileInfo {
@@ -105,6 +1233,8 @@ pub struct FileInfo {
     pub permissions: String,
     #[serde(rename = "lastModified")]
     pub last_modified: DateTime<Utc>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -124,6 +1254,51 @@ pub struct FileListRequest {
     pub path: String,
 }
 
+// Body of the /api/sftp file management routes that only need a path
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpPathRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpRenameRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpDeleteRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub path: String,
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+// Modes are octal strings, e.g. "755"; a new directory gets 755 without one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpModeRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub path: String,
+    pub mode: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpSymlinkRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub target: String,
+    #[serde(rename = "linkPath")]
+    pub link_path: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileListResponse {
     pub files: Vec<FileInfo>,
@@ -139,6 +1314,35 @@ pub struct MobileOptimizationData {
     pub batch_updates: Option<bool>,
     #[serde(rename = "compressionEnabled")]
     pub compression_enabled: Option<bool>,
+    // Spend as few bytes as possible: long batches, harder compression and no notices
+    // the terminal can do without
+    #[serde(rename = "dataSaver", default, skip_serializing_if = "Option::is_none")]
+    pub data_saver: Option<bool>,
+}
+
+// What a WebSocket client was sent, and what batching, compression and data saver spared
+// it, since it connected or last reset the count
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DataUsage {
+    #[serde(rename = "dataSaver")]
+    pub data_saver: bool,
+    // Every frame, terminal output or not
+    #[serde(rename = "bytesSent")]
+    pub bytes_sent: u64,
+    // Terminal output as read from the shells, and as it went out after compression
+    #[serde(rename = "outputBytes")]
+    pub output_bytes: u64,
+    #[serde(rename = "outputPayloadBytes")]
+    pub output_payload_bytes: u64,
+    #[serde(rename = "savedBytes")]
+    pub saved_bytes: u64,
+    // Reads from the shells, each of which would be a message without batching
+    #[serde(rename = "outputChunks")]
+    pub output_chunks: u64,
+    #[serde(rename = "outputMessages")]
+    pub output_messages: u64,
+    #[serde(rename = "suppressedEvents")]
+    pub suppressed_events: u64,
 }
 
 // Performance monitoring types
@@ -154,27 +1358,99 @@ pub struct PerformanceMetrics {
     pub timestamp: DateTime<Utc>,
 }
 
+// Optional WebSocket protocol features, agreed on in the hello handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolFeature {
+    BinaryFrames,
+    Compression,
+    MultiSession,
+}
+
+impl ProtocolFeature {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "binary_frames" => Some(Self::BinaryFrames),
+            "compression" => Some(Self::Compression),
+            "multi_session" => Some(Self::MultiSession),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelloData {
+    #[serde(rename = "protocolVersion")]
+    pub protocol_version: u32,
+    // Plain names, so a newer client's unknown features are skipped instead of failing the parse
+    #[serde(default)]
+    pub features: Vec<String>,
+    pub client: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelloResponse {
+    #[serde(rename = "protocolVersion")]
+    pub protocol_version: u32,
+    #[serde(rename = "minProtocolVersion")]
+    pub min_protocol_version: u32,
+    // Features enabled for this connection: the ones both sides support
+    pub features: Vec<ProtocolFeature>,
+    pub server: String,
+}
+
 // WebSocket event types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum WebSocketEvent {
+    #[serde(rename = "hello")]
+    Hello(HelloData),
+    // Boxed, as a connection config is far bigger than any other event
     #[serde(rename = "ssh_connect")]
-    SSHConnect(SSHConnectData),
+    SSHConnect(Box<SSHConnectData>),
+    #[serde(rename = "ssh_resume")]
+    SSHResume(SSHResumeData),
+    #[serde(rename = "ssh_takeover")]
+    SSHTakeover(SSHTakeoverData),
     #[serde(rename = "terminal_input")]
     TerminalInput(TerminalInputData),
     #[serde(rename = "terminal_resize")]
     TerminalResize(TerminalResizeData),
+    #[serde(rename = "terminal_signal")]
+    TerminalSignal(TerminalSignalData),
     #[serde(rename = "ssh_disconnect")]
     SSHDisconnect { session_id: String },
     #[serde(rename = "mobile_optimize")]
     MobileOptimize(MobileOptimizationData),
     #[serde(rename = "performance_metrics")]
     PerformanceMetrics(PerformanceMetrics),
+    #[serde(rename = "file_follow")]
+    FileFollow(FileFollowData),
+    #[serde(rename = "file_unfollow")]
+    FileUnfollow(FileUnfollowData),
+    #[serde(rename = "container_logs")]
+    ContainerLogs(ContainerLogsData),
+    #[serde(rename = "pod_logs")]
+    PodLogs(PodLogsData),
+    #[serde(rename = "directory_watch")]
+    DirectoryWatch(DirectoryWatchData),
+    #[serde(rename = "directory_unwatch")]
+    DirectoryUnwatch(DirectoryUnwatchData),
+    #[serde(rename = "auth_response")]
+    AuthResponse(AuthResponseData),
+    // Answered with data_usage; reset starts the count over
+    #[serde(rename = "data_usage")]
+    DataUsage {
+        #[serde(default)]
+        reset: bool,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum WebSocketResponse {
+    #[serde(rename = "hello")]
+    Hello(HelloResponse),
     #[serde(rename = "terminal_data")]
     TerminalData(TerminalDataResponse),
     #[serde(rename = "ssh_connected")]
@@ -183,11 +1459,44 @@ pub enum WebSocketResponse {
     SSHDisconnected(SSHDisconnectedResponse),
     #[serde(rename = "ssh_error")]
     SSHError(SSHErrorResponse),
+    #[serde(rename = "throttled")]
+    Throttled(ThrottledResponse),
+    #[serde(rename = "session_taken_over")]
+    SessionTakenOver(SessionTakenOverResponse),
+    #[serde(rename = "terminal_replayed")]
+    TerminalReplayed(TerminalReplayedResponse),
+    #[serde(rename = "shell_closed")]
+    ShellClosed(ShellClosedEvent),
+    #[serde(rename = "file_follow_started")]
+    FileFollowStarted(FileFollowStartedResponse),
+    #[serde(rename = "container_logs_started")]
+    ContainerLogsStarted(ContainerLogsStartedResponse),
+    #[serde(rename = "pod_logs_started")]
+    PodLogsStarted(PodLogsStartedResponse),
+    #[serde(rename = "file_tail")]
+    FileTail(FileTailResponse),
+    #[serde(rename = "file_follow_closed")]
+    FileFollowClosed(FileFollowClosedResponse),
+    #[serde(rename = "directory_watch_started")]
+    DirectoryWatchStarted(DirectoryWatchStartedResponse),
+    #[serde(rename = "directory_changed")]
+    DirectoryChanged(DirectoryChangedEvent),
+    #[serde(rename = "directory_watch_closed")]
+    DirectoryWatchClosed(DirectoryWatchClosedResponse),
+    #[serde(rename = "session_notices")]
+    SessionNotices(SessionNoticesEvent),
+    #[serde(rename = "auth_prompt")]
+    AuthPrompt(AuthPromptEvent),
     #[serde(rename = "mobile_optimized")]
     MobileOptimized {
         applied: MobileOptimizationData,
         timestamp: i64,
     },
+    #[serde(rename = "data_usage")]
+    DataUsage(DataUsage),
+    // The transfer record as it stands, sent while a transfer runs and once when it ends
+    #[serde(rename = "transfer_progress")]
+    TransferProgress(Box<FileTransfer>),
 }
 
 // Enhanced error types with better categorization
@@ -199,6 +1508,10 @@ pub enum AppError {
     SSHAuthenticationFailed(String),
     #[error("Session not found: {0}")]
     SessionNotFound(String),
+    #[error("Unknown host key: {0}")]
+    HostKeyUnknown(String),
+    #[error("Host key mismatch: {0}")]
+    HostKeyMismatch(String),
     #[error("Invalid configuration: {0}")]
     InvalidConfiguration(String),
     #[error("File operation failed: {0}")]
@@ -230,11 +1543,43 @@ pub enum AppError {
 }
 
 impl AppError {
+    // The message without the variant's English prefix
+    pub fn detail(&self) -> String {
+        match self {
+            AppError::SSHConnectionFailed(detail)
+            | AppError::SSHAuthenticationFailed(detail)
+            | AppError::SessionNotFound(detail)
+            | AppError::HostKeyUnknown(detail)
+            | AppError::HostKeyMismatch(detail)
+            | AppError::InvalidConfiguration(detail)
+            | AppError::FileOperationFailed(detail)
+            | AppError::WebSocketError(detail)
+            | AppError::TransferError(detail)
+            | AppError::PermissionDenied(detail)
+            | AppError::ResourceExhausted(detail)
+            | AppError::TimeoutError(detail)
+            | AppError::ValidationError(detail)
+            | AppError::InternalError(detail)
+            | AppError::OperationFailed(detail)
+            | AppError::NotFound(detail) => detail.clone(),
+            AppError::IOError(e) => e.to_string(),
+            AppError::SSH2Error(e) => e.to_string(),
+            AppError::SerializationError(e) => e.to_string(),
+        }
+    }
+
+    // User-facing message with the title from the i18n catalog; the detail stays as reported
+    pub fn localized(&self, language: crate::i18n::Language) -> String {
+        format!("{}: {}", crate::i18n::text(self.error_code(), language), self.detail())
+    }
+
     pub fn error_code(&self) -> &'static str {
         match self {
             AppError::SSHConnectionFailed(_) => "CONNECTION_FAILED",
             AppError::SSHAuthenticationFailed(_) => "AUTH_FAILED",
             AppError::SessionNotFound(_) => "SESSION_NOT_FOUND",
+            AppError::HostKeyUnknown(_) => "HOST_KEY_UNKNOWN",
+            AppError::HostKeyMismatch(_) => "HOST_KEY_MISMATCH",
             AppError::InvalidConfiguration(_) => "INVALID_CONFIG",
             AppError::FileOperationFailed(_) => "FILE_OPERATION_FAILED",
             AppError::WebSocketError(_) => "WEBSOCKET_ERROR",
@@ -256,6 +1601,8 @@ impl AppError {
         match self {
             AppError::SSHConnectionFailed(_) | AppError::SSHAuthenticationFailed(_) => ErrorSeverity::High,
             AppError::SessionNotFound(_) | AppError::InvalidConfiguration(_) => ErrorSeverity::Medium,
+            AppError::HostKeyUnknown(_) => ErrorSeverity::Medium,
+            AppError::HostKeyMismatch(_) => ErrorSeverity::Critical,
             AppError::FileOperationFailed(_) | AppError::TransferError(_) => ErrorSeverity::Medium,
             AppError::WebSocketError(_) => ErrorSeverity::High,
             AppError::PermissionDenied(_) => ErrorSeverity::High,
@@ -298,6 +1645,41 @@ pub struct SftpFileInfo {
     pub is_directory: bool,
     pub modified: Option<i64>,
     pub permissions: Option<String>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    // Names of uid and gid, when the host's accounts could be read
+    pub owner: Option<String>,
+    pub group: Option<String>,
+}
+
+// One remote path as lstat sees it, so a symlink is described rather than what it points at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpStat {
+    pub path: String,
+    pub size: u64,
+    #[serde(rename = "isDirectory")]
+    pub is_directory: bool,
+    #[serde(rename = "isSymlink")]
+    pub is_symlink: bool,
+    #[serde(rename = "linkTarget")]
+    pub link_target: Option<String>,
+    // Octal permission bits, e.g. "644"
+    pub permissions: Option<String>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub modified: Option<i64>,
+    pub accessed: Option<i64>,
+}
+
+// Result of a one-shot command run on its own exec channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandExecResult {
+    pub stdout: String,
+    pub stderr: String,
+    #[serde(rename = "exitCode")]
+    pub exit_code: i32,
 }
 
 // File download request
@@ -317,6 +1699,20 @@ pub struct FileUploadRequest {
     #[serde(rename = "remotePath")]
     pub remote_path: String,
     pub content: String, // Base64 encoded content
+    // Write to a temporary file and rename it over remotePath once complete
+    #[serde(default)]
+    pub atomic: bool,
+}
+
+// Patch-style write into an existing file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileWriteAtRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "remotePath")]
+    pub remote_path: String,
+    pub offset: u64,
+    pub content: String, // Base64 encoded content
 }
 
 // File transfer types
@@ -339,6 +1735,94 @@ pub struct FileTransfer {
     #[serde(rename = "endTime")]
     pub end_time: Option<DateTime<Utc>>,
     pub error: Option<String>,
+    // Hex SHA-256 of the contents, once transferred
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    // Directory transfers copy a whole tree; size and transferred then add up all its files
+    #[serde(rename = "isDirectory", default)]
+    pub is_directory: bool,
+    #[serde(rename = "filesTotal", default)]
+    pub files_total: u32,
+    #[serde(rename = "filesDone", default)]
+    pub files_done: u32,
+    #[serde(rename = "currentFile", default, skip_serializing_if = "Option::is_none")]
+    pub current_file: Option<TransferFileProgress>,
+    // Entries of a directory transfer that couldn't be copied; the rest carries on without them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<TransferEntryError>,
+    // Measured over the last second or so while the transfer runs
+    #[serde(rename = "bytesPerSecond", default, skip_serializing_if = "Option::is_none")]
+    pub bytes_per_second: Option<u64>,
+    // The transfer's own bandwidth cap; the global one applies on top
+    #[serde(rename = "maxBytesPerSecond", default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes_per_second: Option<u64>,
+}
+
+// A bandwidth cap, for one transfer or for all of them; None lifts it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferLimit {
+    #[serde(rename = "maxBytesPerSecond", default)]
+    pub max_bytes_per_second: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferFileProgress {
+    // Relative to the directory being transferred
+    pub path: String,
+    pub size: u64,
+    pub transferred: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferEntryError {
+    pub path: String,
+    pub error: String,
+}
+
+// A record of finished transfers, e.g. as change-control evidence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferManifest {
+    #[serde(rename = "generatedAt")]
+    pub generated_at: DateTime<Utc>,
+    #[serde(rename = "fileCount")]
+    pub file_count: usize,
+    #[serde(rename = "totalBytes")]
+    pub total_bytes: u64,
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    #[serde(rename = "transferId")]
+    pub transfer_id: String,
+    pub name: String,
+    #[serde(rename = "remotePath")]
+    pub remote_path: String,
+    pub direction: TransferDirection,
+    pub status: TransferStatus,
+    pub size: u64,
+    pub checksum: Option<String>,
+    #[serde(rename = "startTime")]
+    pub start_time: DateTime<Utc>,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: i64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferManifestRequest {
+    #[serde(rename = "transferIds")]
+    pub transfer_ids: Vec<String>,
+    #[serde(default)]
+    pub format: ManifestFormat,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -346,6 +1830,8 @@ pub struct FileTransfer {
 pub enum TransferStatus {
     Pending,
     InProgress,
+    // Stopped between chunks until resumed; what was copied so far is kept
+    Paused,
     Completed,
     Failed,
     Cancelled,
@@ -369,6 +1855,17 @@ pub struct TransferUploadRequest {
     pub name: String,
 }
 
+// A remote directory and the local one it's copied to or from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryTransferRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "remotePath")]
+    pub remote_path: String,
+    #[serde(rename = "localPath")]
+    pub local_path: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransferDownloadRequest {
     #[serde(rename = "sessionId")]
@@ -378,6 +1875,66 @@ pub struct TransferDownloadRequest {
     pub name: Option<String>,
 }
 
+// Port forwarding types
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PortForwardKind {
+    Local,
+    Remote,
+    Dynamic,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PortForwardStatus {
+    Starting,
+    Active,
+    Closed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortForwardRequest {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub kind: PortForwardKind,
+    #[serde(rename = "bindAddress")]
+    pub bind_address: Option<String>,
+    #[serde(rename = "bindPort")]
+    pub bind_port: u16,
+    // Required for local and remote forwards, ignored for dynamic (SOCKS) forwards
+    #[serde(rename = "targetHost")]
+    pub target_host: Option<String>,
+    #[serde(rename = "targetPort")]
+    pub target_port: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortForward {
+    pub id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub kind: PortForwardKind,
+    #[serde(rename = "bindAddress")]
+    pub bind_address: String,
+    #[serde(rename = "bindPort")]
+    pub bind_port: u16,
+    #[serde(rename = "targetHost")]
+    pub target_host: Option<String>,
+    #[serde(rename = "targetPort")]
+    pub target_port: Option<u16>,
+    pub status: PortForwardStatus,
+    #[serde(rename = "activeConnections")]
+    pub active_connections: usize,
+    #[serde(rename = "bytesSent")]
+    pub bytes_sent: u64,
+    #[serde(rename = "bytesReceived")]
+    pub bytes_received: u64,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    pub error: Option<String>,
+}
+
 // Terminal autocomplete types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AutocompleteRequest {
@@ -403,6 +1960,8 @@ pub enum SuggestionType {
     Directory,
     Option,
     Variable,
+    // A command entered before on the same host
+    History,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -447,6 +2006,9 @@ pub struct MobileSessionResponse {
     pub session_id: Option<String>,
     pub applied_optimizations: MobileOptimizations,
     pub recommendations: Vec<String>,
+    // What the session's WebSocket clients were sent and spared so far
+    #[serde(rename = "dataUsage", default, skip_serializing_if = "Option::is_none")]
+    pub data_usage: Option<DataUsage>,
     pub error: Option<String>,
 }
 
@@ -0,0 +1,191 @@
+use crate::logging::correlation;
+use crate::retry::{retry, RetryPolicy};
+use crate::types::{AppError, AppResult};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+use uuid::Uuid;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+
+static WEBHOOKS: OnceLock<Vec<WebhookConfig>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEventKind {
+    #[serde(rename = "session.connected")]
+    SessionConnected,
+    #[serde(rename = "session.disconnected")]
+    SessionDisconnected,
+    #[serde(rename = "session.auth_failed")]
+    SessionAuthFailed,
+    #[serde(rename = "transfer.completed")]
+    TransferCompleted,
+    #[serde(rename = "transfer.failed")]
+    TransferFailed,
+    // A connection to a protected host waiting for an admin
+    #[serde(rename = "approval.requested")]
+    ApprovalRequested,
+    // A notification routed to webhooks by a notification rule
+    #[serde(rename = "notification")]
+    Notification,
+}
+
+// One entry of the "webhooks" list in the config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Every event when empty
+    #[serde(default)]
+    pub events: Vec<WebhookEventKind>,
+    // Signs each delivery with HMAC-SHA256 when set
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+impl WebhookConfig {
+    fn wants(&self, kind: WebhookEventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+
+    // The URL itself often carries a token (Slack, Teams), so logs only get the host
+    fn host(&self) -> String {
+        reqwest::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| "invalid url".to_string())
+    }
+}
+
+// Hex HMAC-SHA256 over "{timestamp}.{body}", sent as "sha256=<hex>". Including the
+// timestamp lets receivers reject replays.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    let digest: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", digest)
+}
+
+pub fn payload(kind: WebhookEventKind, session_id: Option<&str>, data: Value) -> Value {
+    json!({
+        "id": Uuid::new_v4().to_string(),
+        "event": kind,
+        "timestamp": Utc::now().to_rfc3339(),
+        "sessionId": session_id,
+        "correlationId": correlation::current(),
+        "data": data,
+    })
+}
+
+// Install the configured webhooks. Returns how many are active.
+pub fn init(webhooks: &[WebhookConfig]) -> AppResult<usize> {
+    for webhook in webhooks {
+        let url = reqwest::Url::parse(&webhook.url)
+            .map_err(|e| AppError::InvalidConfiguration(format!("Invalid webhook URL for {}: {}", webhook.host(), e)))?;
+        if url.scheme() != "https" && url.scheme() != "http" {
+            return Err(AppError::InvalidConfiguration(format!("Webhook URL for {} must be http or https", webhook.host())));
+        }
+    }
+    if !webhooks.is_empty() && WEBHOOKS.set(webhooks.to_vec()).is_ok() {
+        log::info!("{} webhooks configured", webhooks.len());
+    }
+    Ok(WEBHOOKS.get().map_or(0, Vec::len))
+}
+
+// Fire the event at every webhook subscribed to it, in the background
+pub fn emit(kind: WebhookEventKind, session_id: Option<&str>, data: Value) {
+    let Some(webhooks) = WEBHOOKS.get() else {
+        return;
+    };
+    let targets: Vec<&'static WebhookConfig> = webhooks.iter().filter(|webhook| webhook.wants(kind)).collect();
+    if targets.is_empty() {
+        return;
+    }
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        log::warn!("No runtime to deliver webhook event {:?}", kind);
+        return;
+    };
+
+    let body = payload(kind, session_id, data).to_string();
+    for webhook in targets {
+        let body = body.clone();
+        runtime.spawn(correlation::propagate(async move {
+            if let Err(e) = deliver(webhook, kind, &body).await {
+                log::warn!("Webhook delivery to {} failed: {}", webhook.host(), e);
+            }
+        }));
+    }
+}
+
+async fn deliver(webhook: &WebhookConfig, kind: WebhookEventKind, body: &str) -> AppResult<()> {
+    let client = reqwest::Client::builder()
+        .timeout(SEND_TIMEOUT)
+        .build()
+        .map_err(|e| AppError::OperationFailed(format!("Failed to create HTTP client: {}", e)))?;
+    let event = serde_json::to_value(kind)?;
+    let event = event.as_str().unwrap_or_default();
+
+    retry(&RetryPolicy::webhook(), "Webhook delivery", || async {
+        // Signed per attempt, so the timestamp is fresh on retries
+        let timestamp = Utc::now().timestamp();
+        let mut request = client.post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Event", event)
+            .header(TIMESTAMP_HEADER, timestamp.to_string());
+        for (name, value) in &webhook.headers {
+            request = request.header(name, value);
+        }
+        if let Some(secret) = &webhook.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, timestamp, body));
+        }
+
+        // Network errors, throttling and server errors are worth another try, other rejections aren't
+        let response = request.body(body.to_string()).send().await
+            .map_err(|e| AppError::IOError(std::io::Error::other(e.to_string())))?;
+        let status = response.status();
+        if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(AppError::IOError(std::io::Error::other(format!("webhook answered {}", status))));
+        }
+        if !status.is_success() {
+            return Err(AppError::OperationFailed(format!("Webhook rejected the event with status {}", status)));
+        }
+        Ok(())
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // Reference value from `printf '1700000000.{"a":1}' | openssl dgst -sha256 -hmac s3cret`
+        assert_eq!(
+            sign("s3cret", 1_700_000_000, r#"{"a":1}"#),
+            "sha256=1698a50bc74d1ff1db85c4e0a5297c2ad9fdba245d5737cdb789e4cc6e098940"
+        );
+    }
+
+    #[test]
+    fn test_config_and_payload() {
+        let config: WebhookConfig = serde_json::from_str(
+            r#"{ "url": "https://hooks.example.com/T000/B000/XXXX", "events": ["session.auth_failed"] }"#,
+        ).unwrap();
+        assert!(config.wants(WebhookEventKind::SessionAuthFailed));
+        assert!(!config.wants(WebhookEventKind::SessionConnected));
+        assert_eq!(config.host(), "hooks.example.com");
+
+        let payload = payload(WebhookEventKind::TransferCompleted, Some("s1"), json!({ "bytes": 42 }));
+        assert_eq!(payload["event"], "transfer.completed");
+        assert_eq!(payload["sessionId"], "s1");
+        assert_eq!(payload["data"]["bytes"], 42);
+    }
+}
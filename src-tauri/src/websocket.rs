@@ -3,37 +3,376 @@ use crate::types::{
     AppError, AppResult, WebSocketEvent, WebSocketResponse,
     SSHConnectData, TerminalInputData, TerminalResizeData,
     SSHConnectedResponse, SSHDisconnectedResponse, SSHErrorResponse,
-    TerminalDataResponse
+    TerminalDataResponse, HelloData, HelloResponse, ProtocolFeature,
+    FileFollowData, FileUnfollowData, FileFollowStartedResponse, FileTailResponse, FileFollowClosedResponse,
+    ContainerLogsData, ContainerLogsStartedResponse, PodLogsData, PodLogsStartedResponse, SessionNoticesEvent,
+    MobileOptimizationData, SSHResumeData, WebSocketClientStats, WebSocketStats, ThrottledResponse,
+    SSHTakeoverData, SessionTakenOverResponse, TerminalReplayedResponse,
+    DirectoryWatchData, DirectoryUnwatchData, DirectoryWatchStartedResponse, DirectoryChangedEvent, DirectoryWatchClosedResponse,
+    ShellClosedEvent, TerminalSignalData, AuthPromptEvent, AuthResponseData, DataUsage, FileTransfer
 };
+use crate::security::{RateLimitDecision, SharedSecurityManager};
+use crate::config::WebSocketConfig;
+use crate::ssh::auth_prompt::AuthPrompts;
+use crate::ssh::motd::LoginNoticeScanner;
+use crate::ssh::reader::ShellEvent;
+use crate::ssh::tail::TailEvent;
+use crate::ssh::watch::WatchEvent;
 use crate::log_websocket;
+use crate::logging::correlation;
 use axum::{
-    extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
-    },
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    http::{header, HeaderMap, StatusCode},
     response::Response,
 };
+use base64::{engine::general_purpose, Engine as _};
+use dashmap::DashMap;
+use flate2::{write::DeflateEncoder, Compression};
 use futures_util::{sink::SinkExt, stream::StreamExt};
 use serde_json;
-use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
-use tokio::time::{interval, Duration};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{broadcast, RwLock, mpsc, watch};
+use tokio::time::{interval, interval_at, Duration, Instant, Interval};
 use uuid::Uuid;
 use chrono;
 
 pub type SharedSSHManager = Arc<RwLock<SSHManager>>;
 
+// Version 1 is the original JSON-only protocol, spoken by clients that never send hello
+pub const PROTOCOL_VERSION: u32 = 2;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+// axum's WebSocket has no permessage-deflate, so compression is never offered
+const SERVER_FEATURES: &[ProtocolFeature] = &[ProtocolFeature::BinaryFrames, ProtocolFeature::MultiSession];
+// Smaller terminal_data payloads aren't worth deflating
+const COMPRESS_MIN_BYTES: usize = 512;
+// Unless every byte counts
+const DATA_SAVER_COMPRESS_MIN_BYTES: usize = 128;
+const MAX_BATCH_BYTES: usize = 64 * 1024;
+// Floor for the configured message size, so a typo can't turn output into a flood of tiny messages
+const MIN_MESSAGE_BYTES: usize = 1024;
+
+static CONFIG: OnceLock<WebSocketConfig> = OnceLock::new();
+// SSH sessions of dropped clients waiting to be resumed, session ID by resume token
+static DETACHED_SESSIONS: OnceLock<DashMap<String, String>> = OnceLock::new();
+// Which client each attached session streams to, by session ID
+static ATTACHMENTS: OnceLock<DashMap<String, Attachment>> = OnceLock::new();
+// Connected clients as of their last message or heartbeat, by client ID
+static CLIENT_STATS: OnceLock<DashMap<String, WebSocketClientStats>> = OnceLock::new();
+
+// Apply the websocket section of config.json; until then the defaults apply
+pub fn configure(config: &WebSocketConfig) {
+    if CONFIG.set(config.clone()).is_ok() {
+        log::info!(
+            "WebSocket resume grace {}s, heartbeat every {}s with a {}s timeout",
+            config.resume_grace_seconds, config.heartbeat_interval_seconds, config.heartbeat_timeout_seconds
+        );
+        if config.auth_token.as_deref().map_or(true, str::is_empty) {
+            log::warn!("WebSocket upgrades are refused until websocket.authToken is set in config.json");
+        }
+    }
+}
+
+fn config() -> &'static WebSocketConfig {
+    CONFIG.get_or_init(WebSocketConfig::default)
+}
+
+fn resume_grace() -> Duration {
+    Duration::from_secs(config().resume_grace_seconds)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpgradeRejection {
+    MissingToken,
+    InvalidToken,
+    OriginNotAllowed,
+    TokenNotConfigured,
+}
+
+impl UpgradeRejection {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::MissingToken => "missing token",
+            Self::InvalidToken => "invalid token",
+            Self::OriginNotAllowed => "origin not allowed",
+            Self::TokenNotConfigured => "no auth token configured",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::MissingToken | Self::InvalidToken => StatusCode::UNAUTHORIZED,
+            Self::OriginNotAllowed | Self::TokenNotConfigured => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+// Origin and token checks for an upgrade request. Clients that send no Origin (not browsers)
+// skip the first, but never the token, so with none configured every upgrade is refused.
+pub fn authorize_upgrade(headers: &HeaderMap, query_token: Option<&str>) -> Result<(), UpgradeRejection> {
+    let config = config();
+    if let Some(origin) = headers.get(header::ORIGIN) {
+        let allowed = origin.to_str().is_ok_and(|origin| origin_allowed(origin, &config.allowed_origins));
+        if !allowed {
+            return Err(UpgradeRejection::OriginNotAllowed);
+        }
+    }
+    authorize_token(headers, query_token)
+}
+
+// The configured auth token, as ?token= or an Authorization: Bearer header. Without one
+// configured nothing gets through.
+pub fn authorize_token(headers: &HeaderMap, query_token: Option<&str>) -> Result<(), UpgradeRejection> {
+    let expected = config().auth_token.as_deref()
+        .filter(|token| !token.is_empty())
+        .ok_or(UpgradeRejection::TokenNotConfigured)?;
+    let bearer = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let token = query_token.or(bearer).ok_or(UpgradeRejection::MissingToken)?;
+    if !constant_time_eq(token.trim().as_bytes(), expected.as_bytes()) {
+        return Err(UpgradeRejection::InvalidToken);
+    }
+    Ok(())
+}
+
+fn origin_allowed(origin: &str, allowed: &[String]) -> bool {
+    if allowed.iter().any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin)) {
+        return true;
+    }
+    // Pages served from this machine, and the desktop app's webview
+    let Some((_, authority)) = origin.split_once("://") else {
+        return false;
+    };
+    let host = match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or(""),
+        None => authority.split(':').next().unwrap_or(""),
+    };
+    matches!(host.to_ascii_lowercase().as_str(), "localhost" | "127.0.0.1" | "::1" | "tauri.localhost")
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn client_stats() -> &'static DashMap<String, WebSocketClientStats> {
+    CLIENT_STATS.get_or_init(DashMap::new)
+}
+
+pub fn stats() -> WebSocketStats {
+    let mut clients: Vec<WebSocketClientStats> = client_stats().iter().map(|entry| entry.value().clone()).collect();
+    clients.sort_by_key(|client| client.connected_at);
+    WebSocketStats {
+        connected_clients: clients.len(),
+        detached_sessions: detached_sessions().len(),
+        heartbeat_interval_seconds: config().heartbeat_interval_seconds,
+        heartbeat_timeout_seconds: config().heartbeat_timeout_seconds,
+        clients,
+    }
+}
+
+fn detached_sessions() -> &'static DashMap<String, String> {
+    DETACHED_SESSIONS.get_or_init(DashMap::new)
+}
+
+struct Attachment {
+    client_id: String,
+    control: mpsc::UnboundedSender<ClientControl>,
+    // What another client must show to take the session over
+    resume_token: Option<String>,
+}
+
+// Sent to a client's connection loop by other clients
+#[derive(Debug)]
+enum ClientControl {
+    SessionTakenOver { session_id: String },
+}
+
+fn attachments() -> &'static DashMap<String, Attachment> {
+    ATTACHMENTS.get_or_init(DashMap::new)
+}
+
+fn owns_session(session_id: &str, client_id: &str) -> bool {
+    attachments().get(session_id).is_some_and(|attachment| attachment.client_id == client_id)
+}
+
+// Sessions are only driven by the client they're attached to; another client takes them over first
+fn require_attached(session_id: &str, client_id: &str) -> AppResult<()> {
+    if owns_session(session_id, client_id) {
+        return Ok(());
+    }
+    Err(AppError::WebSocketError(format!("Session {} is not attached to this client", session_id)))
+}
+
+fn release_attachment(session_id: &str, client_id: &str) {
+    attachments().remove_if(session_id, |_, attachment| attachment.client_id == client_id);
+}
+
+// Never resolves when heartbeats are off
+async fn next_heartbeat(heartbeat: &mut Option<Interval>) {
+    match heartbeat {
+        Some(heartbeat) => {
+            heartbeat.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// How a client's terminal output is paced, changed by mobile_optimize
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OutputMode {
+    poll_interval: Duration,
+    // Output is collected and sent as one message at most this often; zero sends every read
+    batch_window: Duration,
+    // Deflate larger JSON terminal_data payloads
+    compress: bool,
+    // Compress harder and leave out notices the terminal can do without
+    data_saver: bool,
+}
+
+impl Default for OutputMode {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(50),
+            batch_window: Duration::ZERO,
+            compress: false,
+            data_saver: false,
+        }
+    }
+}
+
+impl OutputMode {
+    // Low bandwidth implies batching and compression unless the client turns them off
+    fn for_mobile(data: &MobileOptimizationData, binary: bool) -> Self {
+        // Binary frames are raw bytes with no room to flag a compressed payload
+        if data.data_saver.unwrap_or(false) {
+            return Self {
+                poll_interval: Duration::from_millis(500),
+                batch_window: Duration::from_millis(1000),
+                compress: !binary,
+                data_saver: true,
+            };
+        }
+
+        let low_bandwidth = data.low_bandwidth.unwrap_or(false);
+        let batch = data.batch_updates.unwrap_or(low_bandwidth);
+        let compress = !binary && data.compression_enabled.unwrap_or(low_bandwidth);
+        let (poll_ms, batch_ms) = match (low_bandwidth, batch) {
+            (true, true) => (200, 400),
+            (true, false) => (200, 0),
+            (false, true) => (100, 150),
+            (false, false) => (50, 0),
+        };
+        Self {
+            poll_interval: Duration::from_millis(poll_ms),
+            batch_window: Duration::from_millis(batch_ms),
+            compress,
+            data_saver: false,
+        }
+    }
+}
+
+// Running totals behind a client's DataUsage, shared with its outgoing and output tasks
+#[derive(Debug, Default)]
+struct DataCounters {
+    bytes_sent: AtomicU64,
+    output_bytes: AtomicU64,
+    output_payload_bytes: AtomicU64,
+    output_chunks: AtomicU64,
+    output_messages: AtomicU64,
+    suppressed_events: AtomicU64,
+}
+
+impl DataCounters {
+    fn add(counter: &AtomicU64, amount: usize) {
+        counter.fetch_add(amount as u64, Ordering::Relaxed);
+    }
+
+    fn report(&self, data_saver: bool) -> DataUsage {
+        let output_bytes = self.output_bytes.load(Ordering::Relaxed);
+        let output_payload_bytes = self.output_payload_bytes.load(Ordering::Relaxed);
+        DataUsage {
+            data_saver,
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            output_bytes,
+            output_payload_bytes,
+            saved_bytes: output_bytes.saturating_sub(output_payload_bytes),
+            output_chunks: self.output_chunks.load(Ordering::Relaxed),
+            output_messages: self.output_messages.load(Ordering::Relaxed),
+            suppressed_events: self.suppressed_events.load(Ordering::Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        for counter in [
+            &self.bytes_sent,
+            &self.output_bytes,
+            &self.output_payload_bytes,
+            &self.output_chunks,
+            &self.output_messages,
+            &self.suppressed_events,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
 // Structure to manage WebSocket client sessions
 #[derive(Debug)]
 struct WebSocketClient {
-    #[allow(dead_code)] // Reserved for future client identification features
     id: String,
-    session_id: Option<String>,
+    // Connected sessions, most recent last. More than one only with multi_session.
+    sessions: Vec<String>,
+    // File follows started by this client, stopped when it goes away
+    follows: Vec<String>,
+    // Directory watches started by this client, likewise
+    watches: Vec<String>,
+    // Resume token of each connected session, by session ID
+    resume_tokens: HashMap<String, String>,
+    protocol_version: u32,
+    features: Vec<ProtocolFeature>,
     sender: mpsc::UnboundedSender<Message>,
+    // Handed to other clients through ATTACHMENTS, e.g. to say they took a session over
+    control: mpsc::UnboundedSender<ClientControl>,
+    // Shared with the output tasks of this client's sessions
+    output_mode: watch::Sender<OutputMode>,
+    usage: Arc<DataCounters>,
     connected_at: chrono::DateTime<chrono::Utc>,
     last_ping: Option<chrono::DateTime<chrono::Utc>>,
     message_count: u64,
     error_count: u64,
+    // Times this client has been throttled for flooding
+    throttle_count: u64,
+}
+
+impl WebSocketClient {
+    fn has_feature(&self, feature: ProtocolFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    fn current_session(&self) -> Option<String> {
+        self.sessions.last().cloned()
+    }
+
+    fn publish_stats(&self) {
+        client_stats().insert(self.id.clone(), WebSocketClientStats {
+            id: self.id.clone(),
+            connected_at: self.connected_at,
+            last_ping: self.last_ping,
+            protocol_version: self.protocol_version,
+            sessions: self.sessions.clone(),
+            message_count: self.message_count,
+            error_count: self.error_count,
+            data_usage: self.usage.report(self.output_mode.borrow().data_saver),
+        });
+    }
+
+    // Silent for longer than the heartbeat timeout, counting from the connect until the first pong
+    fn missed_heartbeats(&self, timeout: chrono::Duration) -> bool {
+        chrono::Utc::now() - self.last_ping.unwrap_or(self.connected_at) > timeout
+    }
 }
 
 #[allow(dead_code)] // Reserved for future connection state management
@@ -45,14 +384,21 @@ enum ConnectionState {
     Error(String),
 }
 
-pub async fn websocket_handler(
+pub fn websocket_handler(
     ws: WebSocketUpgrade,
-    State(ssh_manager): State<SharedSSHManager>,
+    ssh_manager: SharedSSHManager,
+    security_manager: SharedSecurityManager,
+    transfer_progress: broadcast::Receiver<FileTransfer>,
 ) -> Response {
-    ws.on_upgrade(|socket| handle_websocket(socket, ssh_manager))
+    ws.on_upgrade(|socket| handle_websocket(socket, ssh_manager, security_manager, transfer_progress))
 }
 
-async fn handle_websocket(socket: WebSocket, ssh_manager: SharedSSHManager) {
+async fn handle_websocket(
+    socket: WebSocket,
+    ssh_manager: SharedSSHManager,
+    security_manager: SharedSecurityManager,
+    mut transfer_progress: broadcast::Receiver<FileTransfer>,
+) {
     let (ws_sender, mut ws_receiver) = socket.split();
     let client_id = Uuid::new_v4().to_string();
 
@@ -60,31 +406,120 @@ async fn handle_websocket(socket: WebSocket, ssh_manager: SharedSSHManager) {
 
     // Create a channel for sending messages to the WebSocket
     let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+    let (control_tx, mut control_rx) = mpsc::unbounded_channel::<ClientControl>();
 
     // Create client structure
     let mut client = WebSocketClient {
         id: client_id.clone(),
-        session_id: None,
+        sessions: Vec::new(),
+        follows: Vec::new(),
+        watches: Vec::new(),
+        resume_tokens: HashMap::new(),
+        protocol_version: MIN_PROTOCOL_VERSION,
+        features: Vec::new(),
         sender: tx,
+        control: control_tx,
+        output_mode: watch::channel(OutputMode::default()).0,
+        usage: Arc::default(),
         connected_at: chrono::Utc::now(),
         last_ping: None,
         message_count: 0,
         error_count: 0,
+        throttle_count: 0,
     };
 
     // Spawn task to handle outgoing messages
     let mut ws_sender = ws_sender;
+    let usage = client.usage.clone();
     let outgoing_task = tokio::spawn(async move {
         while let Some(message) = rx.recv().await {
+            match &message {
+                Message::Text(text) => DataCounters::add(&usage.bytes_sent, text.len()),
+                Message::Binary(bytes) => DataCounters::add(&usage.bytes_sent, bytes.len()),
+                _ => {}
+            }
             if ws_sender.send(message).await.is_err() {
                 break;
             }
         }
     });
 
-    // Handle incoming messages
-    while let Some(msg) = ws_receiver.next().await {
+    // Read in a task of its own, so answers to auth prompts get through while the ssh_connect
+    // waiting for them holds up the loop below
+    let auth_prompts = ssh_manager.read().await.auth_prompts();
+    let replies = client.sender.clone();
+    let (incoming_tx, mut incoming) = mpsc::unbounded_channel();
+    let incoming_task = tokio::spawn(async move {
+        while let Some(msg) = ws_receiver.next().await {
+            if let Ok(Message::Text(text)) = &msg {
+                if let Some(response) = auth_response(text) {
+                    if let Err(e) = answer_auth_prompt(&auth_prompts, response) {
+                        let error_response = WebSocketResponse::SSHError(SSHErrorResponse {
+                            session_id: None,
+                            message: e.to_string(),
+                            code: Some(e.error_code().to_string()),
+                            details: None,
+                        });
+                        if let Ok(response_text) = serde_json::to_string(&error_response) {
+                            let _ = replies.send(Message::Text(response_text));
+                        }
+                    }
+                    continue;
+                }
+            }
+            if incoming_tx.send(msg).is_err() {
+                break;
+            }
+        }
+    });
+
+    let heartbeat_period = Duration::from_secs(config().heartbeat_interval_seconds);
+    let heartbeat_timeout = chrono::Duration::seconds(config().heartbeat_timeout_seconds as i64);
+    let mut heartbeat = (!heartbeat_period.is_zero())
+        .then(|| interval_at(Instant::now() + heartbeat_period, heartbeat_period));
+
+    // Handle incoming messages, pinging the client between them
+    loop {
+        client.publish_stats();
+
+        let msg = tokio::select! {
+            msg = incoming.recv() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = next_heartbeat(&mut heartbeat) => {
+                if client.missed_heartbeats(heartbeat_timeout) {
+                    log::warn!("WebSocket client {} stopped answering pings, evicting it", client_id);
+                    break;
+                }
+                if client.sender.send(Message::Ping(Vec::new())).is_err() {
+                    break;
+                }
+                continue;
+            }
+            Some(control) = control_rx.recv() => {
+                handle_client_control(control, &mut client);
+                continue;
+            }
+            // Only transfers on this client's own sessions
+            Ok(transfer) = transfer_progress.recv() => {
+                if client.sessions.contains(&transfer.session_id) {
+                    if let Ok(response_text) = serde_json::to_string(&WebSocketResponse::TransferProgress(Box::new(transfer))) {
+                        let _ = client.sender.send(Message::Text(response_text));
+                    }
+                }
+                continue;
+            }
+        };
+
         match msg {
+            Ok(Message::Text(_) | Message::Binary(_)) if !allow_message(&security_manager, &mut client).await => {
+                // Throttling again and again is an error, and enough of those end the connection
+                if client.error_count > 10 {
+                    log::warn!("Client {} kept flooding after being throttled, disconnecting", client_id);
+                    break;
+                }
+            }
             Ok(Message::Text(text)) => {
                 client.message_count += 1;
 
@@ -94,7 +529,7 @@ async fn handle_websocket(socket: WebSocket, ssh_manager: SharedSSHManager) {
                     client.error_count += 1;
 
                     let error_response = WebSocketResponse::SSHError(SSHErrorResponse {
-                        session_id: client.session_id.clone(),
+                        session_id: client.current_session(),
                         message: "Message too large".to_string(),
                         code: Some("MESSAGE_TOO_LARGE".to_string()),
                         details: Some(format!("Message size: {} bytes, limit: 1MB", text.len())),
@@ -106,19 +541,26 @@ async fn handle_websocket(socket: WebSocket, ssh_manager: SharedSSHManager) {
                     continue;
                 }
 
-                match handle_websocket_message(&text, &ssh_manager, &mut client).await {
+                // Each message is one user action, traced under its own ID
+                let request_id = correlation::new_id();
+                let result = correlation::scope(
+                    request_id.clone(),
+                    handle_websocket_message(&text, &ssh_manager, &mut client),
+                ).await;
+
+                match result {
                     Ok(_) => {
                         log::debug!("Successfully handled message from client {}", client_id);
                     }
                     Err(e) => {
                         client.error_count += 1;
-                        log::error!("Error handling WebSocket message from client {}: {}", client_id, e);
+                        log::error!("Error handling WebSocket message {} from client {}: {}", request_id, client_id, e);
 
                         let error_response = WebSocketResponse::SSHError(SSHErrorResponse {
-                            session_id: client.session_id.clone(),
+                            session_id: client.current_session(),
                             message: e.to_string(),
                             code: Some(e.error_code().to_string()),
-                            details: Some(format!("Client: {}, Message count: {}", client_id, client.message_count)),
+                            details: Some(format!("Client: {}, Message count: {}, Request: {}", client_id, client.message_count, request_id)),
                         });
 
                         if let Ok(response_text) = serde_json::to_string(&error_response) {
@@ -157,8 +599,15 @@ async fn handle_websocket(socket: WebSocket, ssh_manager: SharedSSHManager) {
                 client.last_ping = Some(chrono::Utc::now());
             }
             Ok(Message::Binary(data)) => {
-                log::warn!("Received unexpected binary message from client {}: {} bytes", client_id, data.len());
-                // Ignore binary messages for now
+                if !client.has_feature(ProtocolFeature::BinaryFrames) {
+                    log::warn!("Received unexpected binary message from client {}: {} bytes", client_id, data.len());
+                    continue;
+                }
+                client.message_count += 1;
+                if let Err(e) = handle_binary_input(&data, &ssh_manager, &client_id).await {
+                    client.error_count += 1;
+                    log::error!("Error handling binary input from client {}: {}", client_id, e);
+                }
             }
             Err(e) => {
                 client.error_count += 1;
@@ -180,8 +629,11 @@ async fn handle_websocket(socket: WebSocket, ssh_manager: SharedSSHManager) {
         }
     }
 
-    // Cleanup: stop the outgoing task
+    // Cleanup: stop the reader and outgoing tasks
+    incoming_task.abort();
     outgoing_task.abort();
+    client_stats().remove(&client_id);
+    security_manager.release_client(&client_id);
 
     // Log connection statistics
     let connection_duration = chrono::Utc::now().signed_duration_since(client.connected_at);
@@ -191,8 +643,28 @@ async fn handle_websocket(socket: WebSocket, ssh_manager: SharedSSHManager) {
                client.message_count,
                client.error_count);
 
-    // Cleanup: disconnect SSH session if connected
-    if let Some(session_id) = &client.session_id {
+    // Cleanup: stop file follows, which would otherwise run on until their session closes
+    if !client.follows.is_empty() {
+        let manager = ssh_manager.read().await;
+        for follow_id in &client.follows {
+            let _ = manager.stop_following(follow_id);
+        }
+    }
+    if !client.watches.is_empty() {
+        let manager = ssh_manager.read().await;
+        for watch_id in &client.watches {
+            let _ = manager.unwatch_directory(watch_id);
+        }
+    }
+
+    // Cleanup: keep SSH sessions around for a resume, or disconnect those still connected
+    let grace = resume_grace();
+    for session_id in &client.sessions {
+        release_attachment(session_id, &client_id);
+        if let Some(token) = client.resume_tokens.get(session_id).filter(|_| !grace.is_zero()) {
+            detach_session(token.clone(), session_id.clone(), ssh_manager.clone(), grace);
+            continue;
+        }
         log::info!("Cleaning up SSH session {} for disconnected WebSocket client {}", session_id, client_id);
         let manager = ssh_manager.read().await;
         if let Err(e) = manager.disconnect(session_id).await {
@@ -205,13 +677,52 @@ async fn handle_websocket(socket: WebSocket, ssh_manager: SharedSSHManager) {
     log::info!("WebSocket connection cleanup complete for client: {}", client_id);
 }
 
-async fn handle_websocket_message(
-    text: &str,
-    ssh_manager: &SharedSSHManager,
-    client: &mut WebSocketClient,
-) -> AppResult<()> {
-    // Parse the message - try both direct event format and Socket.IO format
-    let event = if let Ok(event) = serde_json::from_str::<WebSocketEvent>(text) {
+fn handle_client_control(control: ClientControl, client: &mut WebSocketClient) {
+    match control {
+        ClientControl::SessionTakenOver { session_id } => {
+            // The session lives on with its new client, so this one must not end or detach it
+            client.sessions.retain(|id| *id != session_id);
+            client.resume_tokens.remove(&session_id);
+            log::info!("Session {} was taken over from WebSocket client {}", session_id, client.id);
+
+            let response = WebSocketResponse::SessionTakenOver(SessionTakenOverResponse {
+                session_id,
+                message: "The session was taken over by another client".to_string(),
+            });
+            if let Ok(response_text) = serde_json::to_string(&response) {
+                let _ = client.sender.send(Message::Text(response_text));
+            }
+        }
+    }
+}
+
+// Drop messages from a client over its rate limit, telling it once per throttle period
+async fn allow_message(security_manager: &SharedSecurityManager, client: &mut WebSocketClient) -> bool {
+    let RateLimitDecision::Throttled { until, first } = security_manager.check_client_rate_limit(&client.id).await else {
+        return true;
+    };
+    if first {
+        client.throttle_count += 1;
+        // The first throttle is a warning, repeat offenders head towards the error limit
+        if client.throttle_count > 1 {
+            client.error_count += 1;
+        }
+        log::warn!("Throttling WebSocket client {} until {}", client.id, until);
+
+        let response = WebSocketResponse::Throttled(ThrottledResponse {
+            message: "Too many messages, slow down".to_string(),
+            retry_after_ms: (until - chrono::Utc::now()).num_milliseconds().max(0),
+        });
+        if let Ok(response_text) = serde_json::to_string(&response) {
+            let _ = client.sender.send(Message::Text(response_text));
+        }
+    }
+    false
+}
+
+// Parse the message - try both direct event format and Socket.IO format
+fn parse_event(text: &str) -> AppResult<WebSocketEvent> {
+    Ok(if let Ok(event) = serde_json::from_str::<WebSocketEvent>(text) {
         event
     } else {
         // Try to parse Socket.IO format: ["event_name", data]
@@ -222,18 +733,62 @@ async fn handle_websocket_message(
                     let data = &array[1];
                     
                     match event_name {
+                        "hello" => {
+                            let hello_data: HelloData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::Hello(hello_data)
+                        }
                         "ssh_connect" => {
                             let connect_data: SSHConnectData = serde_json::from_value(data.clone())?;
-                            WebSocketEvent::SSHConnect(connect_data)
+                            WebSocketEvent::SSHConnect(Box::new(connect_data))
+                        }
+                        "ssh_resume" => {
+                            let resume_data: SSHResumeData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::SSHResume(resume_data)
+                        }
+                        "ssh_takeover" => {
+                            let takeover_data: SSHTakeoverData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::SSHTakeover(takeover_data)
                         }
                         "terminal_input" => {
                             let input_data: TerminalInputData = serde_json::from_value(data.clone())?;
                             WebSocketEvent::TerminalInput(input_data)
                         }
+                        "terminal_signal" => {
+                            let signal_data: TerminalSignalData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::TerminalSignal(signal_data)
+                        }
                         "terminal_resize" => {
                             let resize_data: TerminalResizeData = serde_json::from_value(data.clone())?;
                             WebSocketEvent::TerminalResize(resize_data)
                         }
+                        "file_follow" => {
+                            let follow_data: FileFollowData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::FileFollow(follow_data)
+                        }
+                        "file_unfollow" => {
+                            let unfollow_data: FileUnfollowData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::FileUnfollow(unfollow_data)
+                        }
+                        "directory_watch" => {
+                            let watch_data: DirectoryWatchData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::DirectoryWatch(watch_data)
+                        }
+                        "directory_unwatch" => {
+                            let unwatch_data: DirectoryUnwatchData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::DirectoryUnwatch(unwatch_data)
+                        }
+                        "auth_response" => {
+                            let response_data: AuthResponseData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::AuthResponse(response_data)
+                        }
+                        "container_logs" => {
+                            let logs_data: ContainerLogsData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::ContainerLogs(logs_data)
+                        }
+                        "pod_logs" => {
+                            let logs_data: PodLogsData = serde_json::from_value(data.clone())?;
+                            WebSocketEvent::PodLogs(logs_data)
+                        }
                         "ssh_disconnect" => {
                             let session_id = data.get("sessionId")
                                 .and_then(|v| v.as_str())
@@ -254,152 +809,797 @@ async fn handle_websocket_message(
         } else {
             return Err(AppError::WebSocketError("Failed to parse WebSocket message".to_string()));
         }
-    };
+    })
+}
+
+async fn handle_websocket_message(
+    text: &str,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    let event = parse_event(text)?;
 
     // Handle the event
     match event {
+        WebSocketEvent::Hello(data) => {
+            handle_hello(data, client)?;
+        }
         WebSocketEvent::SSHConnect(data) => {
-            handle_ssh_connect(data, ssh_manager, client).await?;
+            handle_ssh_connect(*data, ssh_manager, client).await?;
+        }
+        WebSocketEvent::SSHResume(data) => {
+            handle_ssh_resume(data, ssh_manager, client).await?;
+        }
+        WebSocketEvent::SSHTakeover(data) => {
+            handle_ssh_takeover(data, ssh_manager, client).await?;
         }
         WebSocketEvent::TerminalInput(data) => {
-            handle_terminal_input(data, ssh_manager).await?;
+            handle_terminal_input(data, ssh_manager, client).await?;
         }
         WebSocketEvent::TerminalResize(data) => {
-            handle_terminal_resize(data, ssh_manager).await?;
+            handle_terminal_resize(data, ssh_manager, client).await?;
+        }
+        WebSocketEvent::TerminalSignal(data) => {
+            handle_terminal_signal(data, ssh_manager, client).await?;
         }
         WebSocketEvent::SSHDisconnect { session_id } => {
             handle_ssh_disconnect(&session_id, ssh_manager, client).await?;
         }
         WebSocketEvent::MobileOptimize(data) => {
-            handle_mobile_optimization(serde_json::to_value(data)?, ssh_manager.clone(), client).await?;
+            handle_mobile_optimize(data, client)?;
+        }
+        WebSocketEvent::PerformanceMetrics(data) => {
+            handle_performance_metrics(serde_json::to_value(data)?, ssh_manager.clone(), client).await?;
+        }
+        WebSocketEvent::FileFollow(data) => {
+            handle_file_follow(data, ssh_manager, client).await?;
+        }
+        WebSocketEvent::FileUnfollow(data) => {
+            handle_file_unfollow(data, ssh_manager, client).await?;
+        }
+        WebSocketEvent::DirectoryWatch(data) => {
+            handle_directory_watch(data, ssh_manager, client).await?;
+        }
+        WebSocketEvent::DirectoryUnwatch(data) => {
+            handle_directory_unwatch(data, ssh_manager, client).await?;
+        }
+        WebSocketEvent::ContainerLogs(data) => {
+            handle_container_logs(data, ssh_manager, client).await?;
+        }
+        WebSocketEvent::PodLogs(data) => {
+            handle_pod_logs(data, ssh_manager, client).await?;
+        }
+        WebSocketEvent::AuthResponse(data) => {
+            answer_auth_prompt(&ssh_manager.read().await.auth_prompts(), data)?;
+        }
+        WebSocketEvent::DataUsage { reset } => {
+            handle_data_usage(reset, client)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_ssh_connect(
+    data: SSHConnectData,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    let manager = ssh_manager.read().await;
+
+    // Create session
+    let session = manager.create_session(data.config.clone()).await?;
+
+    // Connect, passing on any keyboard-interactive prompts
+    let prompts = relay_auth_prompts(session.id.clone(), manager.auth_prompts().subscribe(), client.sender.clone());
+    let connected = manager.connect(&session.id).await;
+    prompts.abort();
+
+    // Create shell
+    let opened = match connected {
+        Ok(()) => manager.create_shell(&session.id, data.cols, data.rows).await,
+        Err(e) => Err(e),
+    };
+    // Nobody could attach to a session that never got going, so it doesn't wait to expire
+    if let Err(e) = opened {
+        if let Err(cleanup) = manager.remove_session(&session.id).await {
+            log::debug!("Failed to remove session {} after a failed connect: {}", session.id, cleanup);
+        }
+        return Err(e);
+    }
+
+    attach_session(&session.id, "connected", ssh_manager, client).await
+}
+
+fn relay_auth_prompts(
+    session_id: String,
+    mut prompts: tokio::sync::broadcast::Receiver<AuthPromptEvent>,
+    sender: mpsc::UnboundedSender<Message>,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(correlation::propagate(async move {
+        while let Ok(prompt) = prompts.recv().await {
+            if prompt.session_id != session_id {
+                continue;
+            }
+            let Ok(response_text) = serde_json::to_string(&WebSocketResponse::AuthPrompt(prompt)) else {
+                continue;
+            };
+            if sender.send(Message::Text(response_text)).is_err() {
+                break;
+            }
+        }
+    }))
+}
+
+// An auth_response, which the reader hands over directly instead of queueing it
+fn auth_response(text: &str) -> Option<AuthResponseData> {
+    if !text.contains("auth_response") {
+        return None;
+    }
+    match parse_event(text) {
+        Ok(WebSocketEvent::AuthResponse(data)) => Some(data),
+        _ => None,
+    }
+}
+
+fn answer_auth_prompt(prompts: &AuthPrompts, data: AuthResponseData) -> AppResult<()> {
+    if data.cancel {
+        return prompts.cancel(&data.prompt_id);
+    }
+    prompts.respond(&data.prompt_id, data.responses)
+}
+
+async fn handle_ssh_resume(
+    data: SSHResumeData,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    let (_, session_id) = detached_sessions().remove(&data.resume_token)
+        .ok_or_else(|| AppError::SessionNotFound("unknown or expired resume token".to_string()))?;
+
+    {
+        let manager = ssh_manager.read().await;
+        manager.get_session(&session_id).await?;
+        // The new client's terminal may not be the size the old one left behind
+        if let (Some(cols), Some(rows)) = (data.cols, data.rows) {
+            manager.resize_shell(&session_id, cols, rows).await?;
+        }
+    }
+    log::info!("WebSocket client {} resumed SSH session {}", client.id, session_id);
+
+    attach_session(&session_id, "resumed", ssh_manager, client).await
+}
+
+// Take a session from whichever client has it, or from the resume queue if its client dropped
+async fn handle_ssh_takeover(
+    data: SSHTakeoverData,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    let session_id = data.session_id.clone();
+    if owns_session(&session_id, &client.id) {
+        return Err(AppError::ValidationError(format!("Session {} is already attached to this client", session_id)));
+    }
+    authorize_takeover(&data)?;
+
+    {
+        let manager = ssh_manager.read().await;
+        manager.get_session(&session_id).await?;
+        if let (Some(cols), Some(rows)) = (data.cols, data.rows) {
+            manager.resize_shell(&session_id, cols, rows).await?;
+        }
+    }
+
+    // The previous client's output task stops once attach_session records the new owner
+    detached_sessions().retain(|_, detached| *detached != session_id);
+    if let Some((_, previous)) = attachments().remove(&session_id) {
+        log::info!("WebSocket client {} takes session {} over from client {}", client.id, session_id, previous.client_id);
+        let _ = previous.control.send(ClientControl::SessionTakenOver { session_id: session_id.clone() });
+    }
+
+    attach_session(&session_id, "taken_over", ssh_manager, client).await
+}
+
+// Knowing the session ID isn't enough: the client shows the session's resume token, whether
+// its client is attached or dropped, or the auth token when the server has one
+fn authorize_takeover(data: &SSHTakeoverData) -> AppResult<()> {
+    let expected = config().auth_token.as_deref().filter(|token| !token.is_empty());
+    if let (Some(token), Some(expected)) = (data.auth_token.as_deref(), expected) {
+        if constant_time_eq(token.trim().as_bytes(), expected.as_bytes()) {
+            return Ok(());
+        }
+    }
+
+    if let Some(token) = data.resume_token.as_deref() {
+        let attached = attachments().get(&data.session_id)
+            .and_then(|attachment| attachment.resume_token.clone())
+            .is_some_and(|current| constant_time_eq(token.as_bytes(), current.as_bytes()));
+        let detached = detached_sessions().get(token).is_some_and(|entry| *entry.value() == data.session_id);
+        if attached || detached {
+            return Ok(());
+        }
+    }
+
+    log::warn!("Refused to hand session {} over without its resume token or the auth token", data.session_id);
+    Err(AppError::PermissionDenied(format!(
+        "Taking session {} over needs its resume token or the server's auth token", data.session_id
+    )))
+}
+
+// Make the session this client's, confirm it and start streaming its output
+async fn attach_session(
+    session_id: &str,
+    status: &str,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    // Update client with session ID. Without multi_session a new connect replaces the old one.
+    if !client.has_feature(ProtocolFeature::MultiSession) {
+        for replaced in client.sessions.drain(..) {
+            release_attachment(&replaced, &client.id);
+        }
+        client.resume_tokens.clear();
+    }
+    // A fresh token each time, so one that leaked from an earlier connection is useless
+    let resume_token = (!resume_grace().is_zero()).then(|| Uuid::new_v4().simple().to_string());
+    if let Some(token) = &resume_token {
+        client.resume_tokens.insert(session_id.to_string(), token.clone());
+    }
+
+    client.sessions.push(session_id.to_string());
+    attachments().insert(session_id.to_string(), Attachment {
+        client_id: client.id.clone(),
+        control: client.control.clone(),
+        resume_token: resume_token.clone(),
+    });
+
+    // Send success response
+    let response = WebSocketResponse::SSHConnected(SSHConnectedResponse {
+        session_id: session_id.to_string(),
+        status: status.to_string(),
+        resume_token,
+    });
+
+    let response_text = serde_json::to_string(&response)?;
+    client.sender.send(Message::Text(response_text))
+        .map_err(|e| AppError::WebSocketError(format!("Failed to send response: {}", e)))?;
+
+    // Start background task to read from shell and send output
+    let binary = client.has_feature(ProtocolFeature::BinaryFrames);
+    start_terminal_output_task(session_id.to_string(), client.id.clone(), ssh_manager.clone(), client.sender.clone(), binary, client.output_mode.subscribe(), client.usage.clone()).await;
+
+    Ok(())
+}
+
+// Keep a dropped client's session for `grace`, disconnecting it unless ssh_resume claims it first
+fn detach_session(token: String, session_id: String, ssh_manager: SharedSSHManager, grace: Duration) {
+    log::info!("Keeping SSH session {} for {} seconds for the client to resume", session_id, grace.as_secs());
+    detached_sessions().insert(token.clone(), session_id.clone());
+
+    tokio::spawn(async move {
+        tokio::time::sleep(grace).await;
+        if detached_sessions().remove(&token).is_none() {
+            return; // Resumed
+        }
+        log::info!("Resume grace period over, disconnecting SSH session {}", session_id);
+        let manager = ssh_manager.read().await;
+        if let Err(e) = manager.disconnect(&session_id).await {
+            log::error!("Error disconnecting SSH session {} after the resume grace period: {}", session_id, e);
+        }
+    });
+}
+
+// Background task to forward SSH shell output to the WebSocket as the shell's reader delivers it
+async fn start_terminal_output_task(
+    session_id: String,
+    client_id: String,
+    ssh_manager: SharedSSHManager,
+    sender: mpsc::UnboundedSender<Message>,
+    binary: bool,
+    mut output_mode: watch::Receiver<OutputMode>,
+    usage: Arc<DataCounters>,
+) {
+    // Keep the ID of the ssh_connect message, so a dropped shell traces back to it
+    tokio::spawn(correlation::propagate(async move {
+        let mut mode = *output_mode.borrow_and_update();
+        // Output arrives as it is read; the ticks flush batches and look after the session
+        let mut interval = interval(mode.poll_interval);
+        let mut login_notices = LoginNoticeScanner::new();
+        let mut batch = String::new();
+        let mut batch_started: Option<Instant> = None;
+        let mut seq = 0u64;
+
+        // The scrollback comes with the subscription, so nothing falls between the replay and live output
+        let subscription = ssh_manager.read().await.subscribe_shell(&session_id).await;
+        let (replay, mut output) = match subscription {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                log::warn!("No shell output to forward for session {}: {}", session_id, e);
+                return;
+            }
+        };
+        if !replay.is_empty() {
+            send_terminal_output(&sender, &session_id, &replay, binary, &mode, &mut seq, &usage);
+            let response = WebSocketResponse::TerminalReplayed(TerminalReplayedResponse {
+                session_id: session_id.clone(),
+                bytes: replay.len(),
+            });
+            send_notice(&sender, &response, &mode, &usage);
+        }
+
+        loop {
+            let event = tokio::select! {
+                event = output.recv() => match event {
+                    Some(event) => Some(event),
+                    None => {
+                        log::info!("Shell of session {} closed or replaced, stopping output task", session_id);
+                        break;
+                    }
+                },
+                _ = interval.tick() => None,
+            };
+
+            // The client went away or another took the session over; leave the output to whoever has it next
+            if sender.is_closed() || !owns_session(&session_id, &client_id) {
+                break;
+            }
+
+            match event {
+                Some(ShellEvent::Output(data)) => {
+                    DataCounters::add(&usage.output_chunks, 1);
+                    login_notices.feed(&data);
+                    batch.push_str(&data);
+                }
+                Some(ShellEvent::Closed) => {
+                    // Whatever the shell printed last goes out before the notice
+                    if !batch.is_empty() {
+                        send_terminal_output(&sender, &session_id, &std::mem::take(&mut batch), binary, &mode, &mut seq, &usage);
+                    }
+                    match ssh_manager.read().await.close_exited_shell(&session_id).await {
+                        Ok(Some(exit)) => {
+                            let response = WebSocketResponse::ShellClosed(ShellClosedEvent { session_id: session_id.clone(), exit });
+                            if let Ok(response_text) = serde_json::to_string(&response) {
+                                let _ = sender.send(Message::Text(response_text));
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => log::debug!("Failed to close the shell of session {}: {}", session_id, e),
+                    }
+                    break;
+                }
+                Some(ShellEvent::Failed(message)) => {
+                    let e = AppError::SSHConnectionFailed(message);
+                    log::error!("Error reading from shell for session {}: {}", session_id, e);
+
+                    // Send error to client
+                    let error_response = WebSocketResponse::SSHError(SSHErrorResponse {
+                        session_id: Some(session_id.clone()),
+                        message: format!("Shell read error: {}", e),
+                        code: Some(e.error_code().to_string()),
+                        details: None,
+                    });
+
+                    if let Ok(response_text) = serde_json::to_string(&error_response) {
+                        let _ = sender.send(Message::Text(response_text));
+                    }
+                    break;
+                }
+                None => {
+                    if output_mode.has_changed().unwrap_or(false) {
+                        mode = *output_mode.borrow_and_update();
+                        interval = tokio::time::interval(mode.poll_interval);
+                    }
+
+                    // MOTD warnings, once the login output has settled
+                    if let Some(notices) = login_notices.poll().filter(|notices| !notices.is_empty()) {
+                        if let Err(e) = ssh_manager.read().await.set_notices(&session_id, notices.clone()).await {
+                            log::debug!("Failed to keep login notices for session {}: {}", session_id, e);
+                        }
+                        let response = WebSocketResponse::SessionNotices(SessionNoticesEvent { session_id: session_id.clone(), notices });
+                        send_notice(&sender, &response, &mode, &usage);
+                    }
+
+                    if ssh_manager.read().await.get_session(&session_id).await.is_err() {
+                        log::info!("SSH session {} no longer exists, stopping output task", session_id);
+                        break;
+                    }
+                }
+            }
+
+            // Send output to client once the batch window is up
+            if !batch.is_empty() {
+                let started = *batch_started.get_or_insert_with(Instant::now);
+                if started.elapsed() >= mode.batch_window || batch.len() >= MAX_BATCH_BYTES {
+                    batch_started = None;
+                    if !send_terminal_output(&sender, &session_id, &std::mem::take(&mut batch), binary, &mode, &mut seq, &usage) {
+                        log::info!("WebSocket client disconnected, stopping terminal output task for session: {}", session_id);
+                        break;
+                    }
+                }
+            }
+        }
+
+        log::info!("Terminal output task ended for session: {}", session_id);
+    }));
+}
+
+// Informational messages the terminal works without, left out in data saver mode
+fn send_notice(sender: &mpsc::UnboundedSender<Message>, response: &WebSocketResponse, mode: &OutputMode, usage: &DataCounters) {
+    if mode.data_saver {
+        DataCounters::add(&usage.suppressed_events, 1);
+        return;
+    }
+    if let Ok(response_text) = serde_json::to_string(response) {
+        let _ = sender.send(Message::Text(response_text));
+    }
+}
+
+// Sends output in parts of at most maxMessageBytes. False once the client has gone away.
+fn send_terminal_output(
+    sender: &mpsc::UnboundedSender<Message>,
+    session_id: &str,
+    data: &str,
+    binary: bool,
+    mode: &OutputMode,
+    seq: &mut u64,
+    usage: &DataCounters,
+) -> bool {
+    let parts = split_output(data, config().max_message_bytes.max(MIN_MESSAGE_BYTES));
+    DataCounters::add(&usage.output_bytes, data.len());
+    DataCounters::add(&usage.output_messages, parts.len());
+
+    // Binary frames arrive in order and carry raw bytes, so the parts need no numbering
+    if binary {
+        DataCounters::add(&usage.output_payload_bytes, data.len());
+        return parts.iter().all(|part| sender.send(Message::Binary(encode_binary_frame(session_id, part.as_bytes()))).is_ok());
+    }
+
+    let split = parts.len() > 1;
+    let count = parts.len();
+    for (index, part) in parts.into_iter().enumerate() {
+        let (data, encoding) = match mode.compress.then(|| compress_output(part, mode.data_saver)).flatten() {
+            Some(compressed) => (compressed, Some("deflate".to_string())),
+            None => (part.to_string(), None),
+        };
+        DataCounters::add(&usage.output_payload_bytes, data.len());
+        *seq += 1;
+        let terminal_response = WebSocketResponse::TerminalData(TerminalDataResponse {
+            session_id: session_id.to_string(),
+            data,
+            timestamp: Some(chrono::Utc::now().timestamp_millis()),
+            batched: Some(!mode.batch_window.is_zero()),
+            encoding,
+            seq: Some(*seq),
+            is_final: split.then_some(index + 1 == count),
+        });
+        if let Ok(response_text) = serde_json::to_string(&terminal_response) {
+            if sender.send(Message::Text(response_text)).is_err() {
+                return false;
+            }
         }
-        WebSocketEvent::PerformanceMetrics(data) => {
-            handle_performance_metrics(serde_json::to_value(data)?, ssh_manager.clone(), client).await?;
+    }
+    true
+}
+
+// Parts of at most `max` bytes, cut on character boundaries
+fn split_output(data: &str, max: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = data;
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
         }
+        let (part, tail) = rest.split_at(end);
+        parts.push(part);
+        rest = tail;
     }
+    parts.push(rest);
+    parts
+}
 
-    Ok(())
+// Raw DEFLATE, base64-encoded, if that comes out smaller than the text itself
+fn compress_output(data: &str, data_saver: bool) -> Option<String> {
+    let (min_bytes, level) = if data_saver {
+        (DATA_SAVER_COMPRESS_MIN_BYTES, Compression::best())
+    } else {
+        (COMPRESS_MIN_BYTES, Compression::fast())
+    };
+    if data.len() < min_bytes {
+        return None;
+    }
+    let mut encoder = DeflateEncoder::new(Vec::new(), level);
+    encoder.write_all(data.as_bytes()).ok()?;
+    let compressed = general_purpose::STANDARD.encode(encoder.finish().ok()?);
+    (compressed.len() < data.len()).then_some(compressed)
 }
 
-async fn handle_ssh_connect(
-    data: SSHConnectData,
+async fn handle_file_follow(
+    data: FileFollowData,
     ssh_manager: &SharedSSHManager,
     client: &mut WebSocketClient,
 ) -> AppResult<()> {
-    let manager = ssh_manager.read().await;
-
-    // Create session
-    let session = manager.create_session(data.config.clone()).await?;
+    require_attached(&data.session_id, &client.id)?;
+    let (follow_id, events) = {
+        let manager = ssh_manager.read().await;
+        manager.follow_file(&data.session_id, &data.path, data.lines).await?
+    };
+    client.follows.push(follow_id.clone());
 
-    // Connect
-    manager.connect(&session.id).await?;
+    let response = WebSocketResponse::FileFollowStarted(FileFollowStartedResponse {
+        follow_id: follow_id.clone(),
+        session_id: data.session_id.clone(),
+        path: data.path,
+    });
+    let response_text = serde_json::to_string(&response)?;
+    client.sender.send(Message::Text(response_text))
+        .map_err(|e| AppError::WebSocketError(format!("Failed to send response: {}", e)))?;
 
-    // Create shell
-    let cols = data.cols.unwrap_or(80);
-    let rows = data.rows.unwrap_or(24);
-    manager.create_shell(&session.id, cols, rows).await?;
+    relay_follow(follow_id, data.session_id, events, client.sender.clone());
+    Ok(())
+}
 
-    // Update client with session ID
-    client.session_id = Some(session.id.clone());
+async fn handle_container_logs(
+    data: ContainerLogsData,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    require_attached(&data.session_id, &client.id)?;
+    let (follow_id, events) = {
+        let manager = ssh_manager.read().await;
+        manager.follow_container_logs(&data.session_id, &data.container_id, data.lines, &data.sudo).await?
+    };
+    client.follows.push(follow_id.clone());
 
-    // Send success response
-    let response = WebSocketResponse::SSHConnected(SSHConnectedResponse {
-        session_id: session.id.clone(),
-        status: "connected".to_string(),
+    let response = WebSocketResponse::ContainerLogsStarted(ContainerLogsStartedResponse {
+        follow_id: follow_id.clone(),
+        session_id: data.session_id.clone(),
+        container_id: data.container_id,
     });
-
     let response_text = serde_json::to_string(&response)?;
     client.sender.send(Message::Text(response_text))
         .map_err(|e| AppError::WebSocketError(format!("Failed to send response: {}", e)))?;
 
-    // Start background task to read from shell and send output
-    start_terminal_output_task(session.id.clone(), ssh_manager.clone(), client.sender.clone()).await;
+    relay_follow(follow_id, data.session_id, events, client.sender.clone());
+    Ok(())
+}
 
+async fn handle_pod_logs(
+    data: PodLogsData,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    require_attached(&data.session_id, &client.id)?;
+    let (follow_id, events) = {
+        let manager = ssh_manager.read().await;
+        manager.follow_pod_logs(&data.session_id, &data.scope, &data.pod, data.container.as_deref(), data.lines).await?
+    };
+    client.follows.push(follow_id.clone());
+
+    let response = WebSocketResponse::PodLogsStarted(PodLogsStartedResponse {
+        follow_id: follow_id.clone(),
+        session_id: data.session_id.clone(),
+        pod: data.pod,
+        container: data.container,
+    });
+    let response_text = serde_json::to_string(&response)?;
+    client.sender.send(Message::Text(response_text))
+        .map_err(|e| AppError::WebSocketError(format!("Failed to send response: {}", e)))?;
+
+    relay_follow(follow_id, data.session_id, events, client.sender.clone());
     Ok(())
 }
 
-// Background task to continuously read from SSH shell and send output to WebSocket
-async fn start_terminal_output_task(
+// Relay a follow's lines as their own event type, apart from terminal output
+fn relay_follow(
+    follow_id: String,
     session_id: String,
-    ssh_manager: SharedSSHManager,
+    mut events: mpsc::UnboundedReceiver<TailEvent>,
     sender: mpsc::UnboundedSender<Message>,
 ) {
-    tokio::spawn(async move {
-        let mut interval = interval(Duration::from_millis(50)); // Read every 50ms
+    tokio::spawn(correlation::propagate(async move {
+        while let Some(event) = events.recv().await {
+            let (response, closed) = match event {
+                TailEvent::Lines(lines) => (WebSocketResponse::FileTail(FileTailResponse {
+                    follow_id: follow_id.clone(),
+                    session_id: session_id.clone(),
+                    lines,
+                    timestamp: chrono::Utc::now().timestamp_millis(),
+                }), false),
+                TailEvent::Closed(error) => (WebSocketResponse::FileFollowClosed(FileFollowClosedResponse {
+                    follow_id: follow_id.clone(),
+                    session_id: session_id.clone(),
+                    error,
+                }), true),
+            };
+            let Ok(response_text) = serde_json::to_string(&response) else {
+                continue;
+            };
+            // Dropping the receiver stops the follow once the client is gone
+            if sender.send(Message::Text(response_text)).is_err() || closed {
+                break;
+            }
+        }
+    }));
+}
 
-        loop {
-            interval.tick().await;
-
-            // Try to read from shell
-            let output = {
-                let manager = ssh_manager.read().await;
-                match manager.read_from_shell(&session_id).await {
-                    Ok(Some(data)) => Some(data),
-                    Ok(None) => None, // No data available
-                    Err(e) => {
-                        log::error!("Error reading from shell for session {}: {}", session_id, e);
+async fn handle_file_unfollow(
+    data: FileUnfollowData,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    let manager = ssh_manager.read().await;
+    manager.stop_following(&data.follow_id)?;
+    client.follows.retain(|id| *id != data.follow_id);
+    Ok(())
+}
 
-                        // Send error to client
-                        let error_response = WebSocketResponse::SSHError(SSHErrorResponse {
-                            session_id: Some(session_id.clone()),
-                            message: format!("Shell read error: {}", e),
-                            code: Some(e.error_code().to_string()),
-                            details: None,
-                        });
+async fn handle_directory_watch(
+    data: DirectoryWatchData,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    require_attached(&data.session_id, &client.id)?;
+    let (watch_id, events) = {
+        let manager = ssh_manager.read().await;
+        manager.watch_directory(&data.session_id, &data.path, data.interval_secs).await?
+    };
+    client.watches.push(watch_id.clone());
 
-                        if let Ok(response_text) = serde_json::to_string(&error_response) {
-                            let _ = sender.send(Message::Text(response_text));
-                        }
+    let response = WebSocketResponse::DirectoryWatchStarted(DirectoryWatchStartedResponse {
+        watch_id: watch_id.clone(),
+        session_id: data.session_id.clone(),
+        path: data.path.clone(),
+    });
+    let response_text = serde_json::to_string(&response)?;
+    client.sender.send(Message::Text(response_text))
+        .map_err(|e| AppError::WebSocketError(format!("Failed to send response: {}", e)))?;
 
-                        break; // Exit the loop on error
-                    }
-                }
-            };
+    relay_directory_watch(watch_id, data.session_id, data.path, events, client.sender.clone());
+    Ok(())
+}
 
-            // Send output to client if available
-            if let Some(data) = output {
-                let terminal_response = WebSocketResponse::TerminalData(TerminalDataResponse {
+fn relay_directory_watch(
+    watch_id: String,
+    session_id: String,
+    path: String,
+    mut events: mpsc::UnboundedReceiver<WatchEvent>,
+    sender: mpsc::UnboundedSender<Message>,
+) {
+    tokio::spawn(correlation::propagate(async move {
+        while let Some(event) = events.recv().await {
+            let (response, closed) = match event {
+                WatchEvent::Changed(change) => (WebSocketResponse::DirectoryChanged(DirectoryChangedEvent {
+                    watch_id: watch_id.clone(),
                     session_id: session_id.clone(),
-                    data,
-                    timestamp: Some(chrono::Utc::now().timestamp_millis()),
-                    batched: Some(false),
-                });
-
-                if let Ok(response_text) = serde_json::to_string(&terminal_response) {
-                    if sender.send(Message::Text(response_text)).is_err() {
-                        log::info!("WebSocket client disconnected, stopping terminal output task for session: {}", session_id);
-                        break;
-                    }
-                }
-            }
-
-            // Check if session still exists
-            {
-                let manager = ssh_manager.read().await;
-                if manager.get_session(&session_id).await.is_err() {
-                    log::info!("SSH session {} no longer exists, stopping output task", session_id);
-                    break;
-                }
+                    path: path.clone(),
+                    change,
+                    timestamp: chrono::Utc::now().timestamp_millis(),
+                }), false),
+                WatchEvent::Closed(error) => (WebSocketResponse::DirectoryWatchClosed(DirectoryWatchClosedResponse {
+                    watch_id: watch_id.clone(),
+                    session_id: session_id.clone(),
+                    error,
+                }), true),
+            };
+            let Ok(response_text) = serde_json::to_string(&response) else {
+                continue;
+            };
+            // Dropping the receiver stops the watch once the client is gone
+            if sender.send(Message::Text(response_text)).is_err() || closed {
+                break;
             }
         }
+    }));
+}
 
-        log::info!("Terminal output task ended for session: {}", session_id);
+async fn handle_directory_unwatch(
+    data: DirectoryUnwatchData,
+    ssh_manager: &SharedSSHManager,
+    client: &mut WebSocketClient,
+) -> AppResult<()> {
+    let manager = ssh_manager.read().await;
+    manager.unwatch_directory(&data.watch_id)?;
+    client.watches.retain(|id| *id != data.watch_id);
+    Ok(())
+}
+
+// Agree on a protocol version and the features both sides support. Must come before ssh_connect,
+// since running output tasks have already picked their frame format.
+fn handle_hello(data: HelloData, client: &mut WebSocketClient) -> AppResult<()> {
+    if data.protocol_version < MIN_PROTOCOL_VERSION {
+        return Err(AppError::WebSocketError(format!(
+            "Unsupported protocol version {}, this server speaks {} to {}",
+            data.protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        )));
+    }
+    if !client.sessions.is_empty() {
+        return Err(AppError::WebSocketError("hello must be sent before ssh_connect".to_string()));
+    }
+
+    client.protocol_version = data.protocol_version.min(PROTOCOL_VERSION);
+    client.features = negotiate_features(&data.features);
+    log::info!(
+        "Client {} ({}) negotiated protocol version {} with features {:?}",
+        client.id, data.client.as_deref().unwrap_or("unknown"), client.protocol_version, client.features
+    );
+
+    let response = WebSocketResponse::Hello(HelloResponse {
+        protocol_version: client.protocol_version,
+        min_protocol_version: MIN_PROTOCOL_VERSION,
+        features: client.features.clone(),
+        server: concat!("webterminal-pro/", env!("CARGO_PKG_VERSION")).to_string(),
     });
+    let response_text = serde_json::to_string(&response)?;
+    client.sender.send(Message::Text(response_text))
+        .map_err(|e| AppError::WebSocketError(format!("Failed to send response: {}", e)))?;
+
+    Ok(())
+}
+
+fn negotiate_features(requested: &[String]) -> Vec<ProtocolFeature> {
+    SERVER_FEATURES.iter()
+        .copied()
+        .filter(|feature| requested.iter().any(|name| ProtocolFeature::parse(name) == Some(*feature)))
+        .collect()
+}
+
+// Binary frames, in both directions: session ID length (1 byte), session ID, then raw terminal data
+fn encode_binary_frame(session_id: &str, data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(1 + session_id.len() + data.len());
+    frame.push(session_id.len() as u8);
+    frame.extend_from_slice(session_id.as_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+fn decode_binary_frame(frame: &[u8]) -> AppResult<(&str, &[u8])> {
+    let invalid = || AppError::WebSocketError("Invalid binary frame".to_string());
+    let (&id_len, rest) = frame.split_first().ok_or_else(invalid)?;
+    if rest.len() < id_len as usize {
+        return Err(invalid());
+    }
+    let (session_id, data) = rest.split_at(id_len as usize);
+    let session_id = std::str::from_utf8(session_id).map_err(|_| invalid())?;
+    Ok((session_id, data))
+}
+
+async fn handle_binary_input(frame: &[u8], ssh_manager: &SharedSSHManager, client_id: &str) -> AppResult<()> {
+    let (session_id, data) = decode_binary_frame(frame)?;
+    require_attached(session_id, client_id)?;
+    let manager = ssh_manager.read().await;
+    manager.write_to_shell(session_id, &String::from_utf8_lossy(data)).await
 }
 
 async fn handle_terminal_input(
     data: TerminalInputData,
     ssh_manager: &SharedSSHManager,
+    client: &WebSocketClient,
 ) -> AppResult<()> {
+    require_attached(&data.session_id, &client.id)?;
     let manager = ssh_manager.read().await;
     manager.write_to_shell(&data.session_id, &data.input).await?;
     Ok(())
 }
 
+// For mobile clients without Ctrl keys
+async fn handle_terminal_signal(
+    data: TerminalSignalData,
+    ssh_manager: &SharedSSHManager,
+    client: &WebSocketClient,
+) -> AppResult<()> {
+    require_attached(&data.session_id, &client.id)?;
+    let manager = ssh_manager.read().await;
+    manager.signal_shell(&data.session_id, data.signal).await?;
+    Ok(())
+}
+
 async fn handle_terminal_resize(
     data: TerminalResizeData,
     ssh_manager: &SharedSSHManager,
+    client: &WebSocketClient,
 ) -> AppResult<()> {
+    require_attached(&data.session_id, &client.id)?;
     let manager = ssh_manager.read().await;
     manager.resize_shell(&data.session_id, data.cols, data.rows).await?;
     Ok(())
@@ -410,11 +1610,14 @@ async fn handle_ssh_disconnect(
     ssh_manager: &SharedSSHManager,
     client: &mut WebSocketClient,
 ) -> AppResult<()> {
+    require_attached(session_id, &client.id)?;
     let manager = ssh_manager.read().await;
     manager.disconnect(session_id).await?;
 
     // Clear the session ID from client
-    client.session_id = None;
+    client.sessions.retain(|id| id != session_id);
+    client.resume_tokens.remove(session_id);
+    release_attachment(session_id, &client.id);
 
     let response = WebSocketResponse::SSHDisconnected(SSHDisconnectedResponse {
         session_id: session_id.to_string(),
@@ -427,101 +1630,59 @@ async fn handle_ssh_disconnect(
     Ok(())
 }
 
-async fn handle_mobile_optimization(
-    data: serde_json::Value,
-    ssh_manager: Arc<RwLock<SSHManager>>,
-    client: &mut WebSocketClient,
-) -> AppResult<()> {
-    log::info!("Processing mobile optimization request");
-
-    let optimization_type = data.get("type").and_then(|v| v.as_str()).unwrap_or("general");
-    let session_id = data.get("sessionId").and_then(|v| v.as_str());
-
-    let mut optimizations_applied = Vec::new();
-    let mut recommendations = Vec::new();
-
-    match optimization_type {
-        "bandwidth" => {
-            // Implement bandwidth optimization
-            optimizations_applied.push("Enabled data compression".to_string());
-            optimizations_applied.push("Reduced update frequency".to_string());
-            optimizations_applied.push("Enabled output batching".to_string());
-
-            recommendations.push("Consider using low-bandwidth mode for better performance".to_string());
-
-            log::info!("Applied bandwidth optimizations for mobile device");
-        }
-        "battery" => {
-            // Implement battery optimization
-            optimizations_applied.push("Reduced background processing".to_string());
-            optimizations_applied.push("Optimized refresh rates".to_string());
-            optimizations_applied.push("Enabled power-saving mode".to_string());
-
-            recommendations.push("Disable animations to save battery".to_string());
-            recommendations.push("Use dark theme to reduce screen power consumption".to_string());
-
-            log::info!("Applied battery optimizations for mobile device");
-        }
-        "touch" => {
-            // Implement touch interface optimization
-            optimizations_applied.push("Enabled touch-friendly controls".to_string());
-            optimizations_applied.push("Increased tap target sizes".to_string());
-            optimizations_applied.push("Optimized gesture recognition".to_string());
-
-            recommendations.push("Use virtual keyboard for better text input".to_string());
-            recommendations.push("Enable haptic feedback for better touch response".to_string());
-
-            log::info!("Applied touch interface optimizations for mobile device");
-        }
-        "performance" => {
-            // Implement general performance optimization
-            optimizations_applied.push("Optimized rendering pipeline".to_string());
-            optimizations_applied.push("Reduced memory usage".to_string());
-            optimizations_applied.push("Enabled hardware acceleration".to_string());
-
-            recommendations.push("Close unused sessions to free memory".to_string());
-            recommendations.push("Limit concurrent connections".to_string());
-
-            log::info!("Applied general performance optimizations for mobile device");
-        }
-        _ => {
-            // Default optimization
-            optimizations_applied.push("Applied general mobile optimizations".to_string());
-            recommendations.push("Specify optimization type for better results".to_string());
-        }
-    }
+// Trade latency for fewer, larger and optionally compressed messages on this client's sessions
+fn handle_mobile_optimize(data: MobileOptimizationData, client: &mut WebSocketClient) -> AppResult<()> {
+    let mode = OutputMode::for_mobile(&data, client.has_feature(ProtocolFeature::BinaryFrames));
+    client.output_mode.send_replace(mode);
+    log::info!("Client {} switched terminal output to {:?}", client.id, mode);
+
+    let response = WebSocketResponse::MobileOptimized {
+        applied: MobileOptimizationData {
+            low_bandwidth: Some(data.low_bandwidth.unwrap_or(false)),
+            batch_updates: Some(!mode.batch_window.is_zero()),
+            compression_enabled: Some(mode.compress),
+            data_saver: Some(mode.data_saver),
+        },
+        timestamp: chrono::Utc::now().timestamp_millis(),
+    };
+    let response_text = serde_json::to_string(&response)?;
+    client.sender.send(Message::Text(response_text))
+        .map_err(|e| AppError::WebSocketError(format!("Failed to send response: {}", e)))?;
 
-    // If session ID is provided, apply session-specific optimizations
-    if let Some(session_id) = session_id {
-        let manager = ssh_manager.read().await;
-        if let Ok(_session) = manager.get_session(session_id).await {
-            optimizations_applied.push(format!("Optimized session: {}", session_id));
+    Ok(())
+}
 
-            // Apply session-specific mobile optimizations
-            // This could include adjusting terminal settings, buffer sizes, etc.
-            log::info!("Applied session-specific mobile optimizations for session: {}", session_id);
-        }
+fn handle_data_usage(reset: bool, client: &mut WebSocketClient) -> AppResult<()> {
+    let usage = client.usage.report(client.output_mode.borrow().data_saver);
+    if reset {
+        client.usage.reset();
     }
 
-    // Send optimization results back to client
-    let response = serde_json::json!({
-        "type": "mobile_optimization_result",
-        "success": true,
-        "optimizations": {
-            "applied": optimizations_applied,
-            "recommendations": recommendations,
-            "type": optimization_type
-        },
-        "timestamp": chrono::Utc::now().timestamp()
-    });
-
-    let response_text = serde_json::to_string(&response)?;
+    let response_text = serde_json::to_string(&WebSocketResponse::DataUsage(usage))?;
     client.sender.send(Message::Text(response_text))
         .map_err(|e| AppError::WebSocketError(format!("Failed to send response: {}", e)))?;
 
     Ok(())
 }
 
+// Totals over the clients streaming the session, as of their last message or heartbeat
+pub fn session_data_usage(session_id: &str) -> Option<DataUsage> {
+    let mut total: Option<DataUsage> = None;
+    for client in client_stats().iter().filter(|client| client.sessions.iter().any(|session| session == session_id)) {
+        let usage = &client.data_usage;
+        let total = total.get_or_insert_with(DataUsage::default);
+        total.data_saver |= usage.data_saver;
+        total.bytes_sent += usage.bytes_sent;
+        total.output_bytes += usage.output_bytes;
+        total.output_payload_bytes += usage.output_payload_bytes;
+        total.saved_bytes += usage.saved_bytes;
+        total.output_chunks += usage.output_chunks;
+        total.output_messages += usage.output_messages;
+        total.suppressed_events += usage.suppressed_events;
+    }
+    total
+}
+
 async fn handle_performance_metrics(
     data: serde_json::Value,
     ssh_manager: Arc<RwLock<SSHManager>>,
@@ -1061,3 +2222,529 @@ async fn get_system_uptime() -> Result<u64, Box<dyn std::error::Error>> {
 
     Ok(0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mobile_output_mode() {
+        let low_bandwidth = MobileOptimizationData { low_bandwidth: Some(true), batch_updates: None, compression_enabled: None, data_saver: None };
+        let mode = OutputMode::for_mobile(&low_bandwidth, false);
+        assert_eq!((mode.poll_interval, mode.batch_window, mode.compress), (Duration::from_millis(200), Duration::from_millis(400), true));
+        assert!(!OutputMode::for_mobile(&low_bandwidth, true).compress);
+
+        let reset = MobileOptimizationData { low_bandwidth: None, batch_updates: None, compression_enabled: None, data_saver: None };
+        assert_eq!(OutputMode::for_mobile(&reset, false), OutputMode::default());
+
+        let output = "drwxr-xr-x 2 root root 4096 Jan  1 00:00 bin\r\n".repeat(40);
+        let compressed = general_purpose::STANDARD.decode(compress_output(&output, false).unwrap()).unwrap();
+        let mut inflated = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::DeflateDecoder::new(&compressed[..]), &mut inflated).unwrap();
+        assert_eq!(inflated, output);
+        assert!(compress_output("$ ", false).is_none());
+    }
+
+    #[test]
+    fn test_data_saver() {
+        let data_saver = MobileOptimizationData { low_bandwidth: None, batch_updates: None, compression_enabled: None, data_saver: Some(true) };
+        let mode = OutputMode::for_mobile(&data_saver, false);
+        assert!(mode.data_saver && mode.compress);
+        assert_eq!(mode.batch_window, Duration::from_millis(1000));
+        assert!(!OutputMode::for_mobile(&data_saver, true).compress);
+
+        // Output too small to deflate normally is worth it when every byte counts
+        let output = "total 0\r\n".repeat(20);
+        assert!(compress_output(&output, false).is_none());
+        assert!(compress_output(&output, true).is_some());
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let usage = DataCounters::default();
+        let mut seq = 0;
+        DataCounters::add(&usage.output_chunks, 20);
+        assert!(send_terminal_output(&sender, "session-1", &output, false, &mode, &mut seq, &usage));
+        let Ok(Message::Text(text)) = receiver.try_recv() else {
+            panic!("expected terminal_data");
+        };
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&text).unwrap()["encoding"], "deflate");
+
+        let notice = WebSocketResponse::TerminalReplayed(TerminalReplayedResponse { session_id: "session-1".to_string(), bytes: 10 });
+        send_notice(&sender, &notice, &mode, &usage);
+        assert!(receiver.try_recv().is_err());
+        send_notice(&sender, &notice, &OutputMode::default(), &usage);
+        assert!(receiver.try_recv().is_ok());
+
+        let report = usage.report(true);
+        assert_eq!((report.output_bytes, report.output_chunks, report.output_messages), (output.len() as u64, 20, 1));
+        assert!(report.saved_bytes > 0 && report.output_payload_bytes < report.output_bytes);
+        assert_eq!(report.suppressed_events, 1);
+
+        usage.reset();
+        assert_eq!(usage.report(false), DataUsage::default());
+    }
+
+    #[test]
+    fn test_origin_allowed() {
+        let allowed = vec!["https://term.example.com".to_string()];
+        assert!(origin_allowed("http://localhost:3000", &allowed));
+        assert!(origin_allowed("http://[::1]:3000", &allowed));
+        assert!(origin_allowed("tauri://localhost", &allowed));
+        assert!(origin_allowed("https://term.example.com", &allowed));
+        assert!(!origin_allowed("https://evil.example.com", &allowed));
+        assert!(!origin_allowed("http://localhost.evil.com", &allowed));
+        assert!(!origin_allowed("null", &allowed));
+        assert!(origin_allowed("https://evil.example.com", &["*".to_string()]));
+        assert!(constant_time_eq(b"secret", b"secret") && !constant_time_eq(b"secret", b"secreT"));
+    }
+
+    #[test]
+    fn test_token_needs_configuring() {
+        // No test configures a token, so even a well-formed one proves nothing
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer anything".parse().unwrap());
+        assert_eq!(authorize_token(&headers, None), Err(UpgradeRejection::TokenNotConfigured));
+        assert_eq!(authorize_token(&HeaderMap::new(), Some("anything")), Err(UpgradeRejection::TokenNotConfigured));
+        assert_eq!(UpgradeRejection::TokenNotConfigured.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_upgrade_refused_without_configured_token() {
+        assert_eq!(authorize_upgrade(&HeaderMap::new(), None), Err(UpgradeRejection::TokenNotConfigured));
+        assert_eq!(authorize_upgrade(&HeaderMap::new(), Some("anything")), Err(UpgradeRejection::TokenNotConfigured));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ORIGIN, "http://localhost:3000".parse().unwrap());
+        assert_eq!(authorize_upgrade(&headers, None), Err(UpgradeRejection::TokenNotConfigured));
+    }
+
+    #[test]
+    fn test_split_output() {
+        assert_eq!(split_output("", 4), vec![""]);
+        assert_eq!(split_output("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        // "é" is two bytes and must not be cut in half
+        assert_eq!(split_output("abcé€", 4), vec!["abc", "é", "€"]);
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut seq = 0;
+        let output = "x".repeat(MIN_MESSAGE_BYTES * 100);
+        assert!(send_terminal_output(&sender, "session-1", &output, false, &OutputMode::default(), &mut seq, &DataCounters::default()));
+        let mut parts = Vec::new();
+        while let Ok(Message::Text(text)) = receiver.try_recv() {
+            parts.push(serde_json::from_str::<serde_json::Value>(&text).unwrap());
+        }
+        assert_eq!(parts.len() as u64, seq);
+        assert!(parts.len() > 1);
+        assert_eq!(parts[0]["seq"], 1);
+        assert_eq!(parts[0]["final"], false);
+        assert_eq!(parts.last().unwrap()["final"], true);
+        let joined: String = parts.iter().map(|part| part["data"].as_str().unwrap()).collect();
+        assert_eq!(joined, output);
+    }
+
+    #[test]
+    fn test_missed_heartbeats() {
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let mut client = WebSocketClient {
+            id: "client-1".to_string(),
+            sessions: Vec::new(),
+            follows: Vec::new(),
+            watches: Vec::new(),
+            resume_tokens: HashMap::new(),
+            protocol_version: PROTOCOL_VERSION,
+            features: Vec::new(),
+            sender,
+            control: mpsc::unbounded_channel().0,
+            output_mode: watch::channel(OutputMode::default()).0,
+            usage: Arc::default(),
+            connected_at: chrono::Utc::now() - chrono::Duration::seconds(120),
+            last_ping: None,
+            message_count: 0,
+            error_count: 0,
+            throttle_count: 0,
+        };
+        let timeout = chrono::Duration::seconds(90);
+        assert!(client.missed_heartbeats(timeout));
+
+        client.last_ping = Some(chrono::Utc::now() - chrono::Duration::seconds(30));
+        assert!(!client.missed_heartbeats(timeout));
+
+        client.publish_stats();
+        assert_eq!(stats().clients.iter().filter(|stats| stats.id == "client-1").count(), 1);
+        client_stats().remove("client-1");
+    }
+
+    #[test]
+    fn test_session_taken_over() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let (control, _control_receiver) = mpsc::unbounded_channel();
+        let mut client = WebSocketClient {
+            id: "laptop".to_string(),
+            sessions: vec!["session-1".to_string()],
+            follows: Vec::new(),
+            watches: Vec::new(),
+            resume_tokens: HashMap::from([("session-1".to_string(), "token".to_string())]),
+            protocol_version: PROTOCOL_VERSION,
+            features: Vec::new(),
+            sender,
+            control: control.clone(),
+            output_mode: watch::channel(OutputMode::default()).0,
+            usage: Arc::default(),
+            connected_at: chrono::Utc::now(),
+            last_ping: None,
+            message_count: 0,
+            error_count: 0,
+            throttle_count: 0,
+        };
+        attachments().insert("session-1".to_string(), Attachment { client_id: "desktop".to_string(), control, resume_token: None });
+        assert!(owns_session("session-1", "desktop"));
+        // Only the owner's cleanup releases it
+        release_attachment("session-1", "laptop");
+        assert!(!owns_session("session-1", "laptop"));
+        assert!(owns_session("session-1", "desktop"));
+
+        handle_client_control(ClientControl::SessionTakenOver { session_id: "session-1".to_string() }, &mut client);
+        assert!(client.sessions.is_empty());
+        assert!(client.resume_tokens.is_empty());
+        let Ok(Message::Text(text)) = receiver.try_recv() else { panic!("no notification sent") };
+        let response: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(response["type"], "session_taken_over");
+        assert_eq!(response["sessionId"], "session-1");
+
+        release_attachment("session-1", "desktop");
+        assert!(attachments().get("session-1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_takeover_needs_proof() {
+        let ssh_manager: SharedSSHManager = Arc::new(RwLock::new(SSHManager::new()));
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let mut client = WebSocketClient {
+            id: "intruder".to_string(),
+            sessions: Vec::new(),
+            follows: Vec::new(),
+            watches: Vec::new(),
+            resume_tokens: HashMap::new(),
+            protocol_version: PROTOCOL_VERSION,
+            features: Vec::new(),
+            sender,
+            control: mpsc::unbounded_channel().0,
+            output_mode: watch::channel(OutputMode::default()).0,
+            usage: Arc::default(),
+            connected_at: chrono::Utc::now(),
+            last_ping: None,
+            message_count: 0,
+            error_count: 0,
+            throttle_count: 0,
+        };
+        let (control, mut control_receiver) = mpsc::unbounded_channel();
+        attachments().insert("session-2".to_string(), Attachment {
+            client_id: "owner".to_string(),
+            control,
+            resume_token: Some("secret".to_string()),
+        });
+
+        for resume_token in [None, Some("guess".to_string())] {
+            let data = SSHTakeoverData { session_id: "session-2".to_string(), cols: None, rows: None, resume_token, auth_token: None };
+            let result = handle_ssh_takeover(data, &ssh_manager, &mut client).await;
+            assert!(matches!(result, Err(AppError::PermissionDenied(_))));
+        }
+        // No auth token is configured, so presenting one proves nothing
+        let data = SSHTakeoverData { session_id: "session-2".to_string(), cols: None, rows: None, resume_token: None, auth_token: Some(String::new()) };
+        assert!(matches!(authorize_takeover(&data), Err(AppError::PermissionDenied(_))));
+
+        assert!(owns_session("session-2", "owner"));
+        assert!(control_receiver.try_recv().is_err());
+        assert!(client.sessions.is_empty());
+
+        let data = SSHTakeoverData { session_id: "session-2".to_string(), cols: None, rows: None, resume_token: Some("secret".to_string()), auth_token: None };
+        assert!(authorize_takeover(&data).is_ok());
+        release_attachment("session-2", "owner");
+
+        detached_sessions().insert("token-2".to_string(), "session-3".to_string());
+        let data = SSHTakeoverData { session_id: "session-3".to_string(), cols: None, rows: None, resume_token: Some("token-2".to_string()), auth_token: None };
+        assert!(authorize_takeover(&data).is_ok());
+        detached_sessions().remove("token-2");
+    }
+
+    #[tokio::test]
+    async fn test_input_needs_attachment() {
+        let ssh_manager: SharedSSHManager = Arc::new(RwLock::new(SSHManager::new()));
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let mut client = WebSocketClient {
+            id: "second".to_string(),
+            sessions: Vec::new(),
+            follows: Vec::new(),
+            watches: Vec::new(),
+            resume_tokens: HashMap::new(),
+            protocol_version: PROTOCOL_VERSION,
+            features: Vec::new(),
+            sender,
+            control: mpsc::unbounded_channel().0,
+            output_mode: watch::channel(OutputMode::default()).0,
+            usage: Arc::default(),
+            connected_at: chrono::Utc::now(),
+            last_ping: None,
+            message_count: 0,
+            error_count: 0,
+            throttle_count: 0,
+        };
+        attachments().insert("session-4".to_string(), Attachment {
+            client_id: "first".to_string(),
+            control: mpsc::unbounded_channel().0,
+            resume_token: None,
+        });
+
+        for message in [
+            r#"{"type":"terminal_input","sessionId":"session-4","input":"reboot\r"}"#,
+            r#"["terminal_resize",{"sessionId":"session-4","cols":10,"rows":2}]"#,
+            r#"["terminal_signal",{"sessionId":"session-4","signal":"INT"}]"#,
+            r#"["ssh_disconnect",{"sessionId":"session-4"}]"#,
+            r#"["file_follow",{"sessionId":"session-4","path":"/etc/shadow"}]"#,
+        ] {
+            let result = handle_websocket_message(message, &ssh_manager, &mut client).await;
+            assert!(matches!(result, Err(AppError::WebSocketError(_))), "{} was let through: {:?}", message, result);
+        }
+        let frame = encode_binary_frame("session-4", b"reboot\r");
+        assert!(matches!(handle_binary_input(&frame, &ssh_manager, "second").await, Err(AppError::WebSocketError(_))));
+        assert!(owns_session("session-4", "first"));
+
+        // The owner gets past the check, to the manager, which has no such session
+        let result = handle_binary_input(&frame, &ssh_manager, "first").await;
+        assert!(matches!(result, Err(AppError::SessionNotFound(_))));
+        release_attachment("session-4", "first");
+    }
+
+    #[tokio::test]
+    async fn test_detached_session_expires() {
+        let ssh_manager: SharedSSHManager = Arc::new(RwLock::new(SSHManager::new()));
+        detach_session("token-1".to_string(), "session-1".to_string(), ssh_manager, Duration::from_millis(20));
+        assert_eq!(detached_sessions().get("token-1").map(|entry| entry.value().clone()), Some("session-1".to_string()));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(detached_sessions().get("token-1").is_none());
+    }
+
+    #[test]
+    fn test_negotiate_features() {
+        let requested = vec!["compression".to_string(), "binary_frames".to_string(), "from_the_future".to_string()];
+        assert_eq!(negotiate_features(&requested), vec![ProtocolFeature::BinaryFrames]);
+        assert!(negotiate_features(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_binary_frame_round_trip() {
+        let frame = encode_binary_frame("session-1", b"ls -la\r");
+        let (session_id, data) = decode_binary_frame(&frame).unwrap();
+        assert_eq!(session_id, "session-1");
+        assert_eq!(data, b"ls -la\r");
+
+        assert!(decode_binary_frame(&[]).is_err());
+        assert!(decode_binary_frame(&[9, b'a']).is_err());
+    }
+}
+
+// Malformed and edge-case client messages, derived from a fixed seed so a failure replays
+#[cfg(test)]
+mod fuzz {
+    use super::*;
+
+    // The connect config is invalid three ways over, so no single mutation makes it dial out
+    const CONFIG: &str = r#"{"id":"fuzz-1","hostname":"","port":0,"username":"","password":null}"#;
+    const MUTATIONS_PER_SEED: usize = 200;
+
+    // One valid message of each kind a client sends, in both framings, to mutate
+    fn seeds() -> Vec<String> {
+        let mut seeds = vec![
+            r#"{"type":"hello","protocolVersion":2,"features":["binary_frames"],"client":"fuzz"}"#.to_string(),
+            r#"["hello",{"protocolVersion":2}]"#.to_string(),
+            format!(r#"{{"type":"ssh_connect","config":{},"cols":80,"rows":24}}"#, CONFIG),
+            format!(r#"["ssh_connect",{{"config":{}}}]"#, CONFIG),
+        ];
+        seeds.extend([
+            r#"{"type":"ssh_resume","resumeToken":"0000","cols":80,"rows":24}"#,
+            r#"["ssh_takeover",{"sessionId":"fuzz-1"}]"#,
+            r#"{"type":"terminal_input","sessionId":"fuzz-1","input":"ls -la\r"}"#,
+            r#"["terminal_input",{"sessionId":"fuzz-1","input":"\u001b[A"}]"#,
+            r#"{"type":"terminal_resize","sessionId":"fuzz-1","cols":80,"rows":24}"#,
+            r#"["terminal_signal",{"sessionId":"fuzz-1","signal":"INT"}]"#,
+            r#"{"type":"ssh_disconnect","session_id":"fuzz-1"}"#,
+            r#"["ssh_disconnect",{"sessionId":"fuzz-1"}]"#,
+            r#"{"type":"mobile_optimize","lowBandwidth":true}"#,
+            r#"["mobile_optimize",{"dataSaver":true}]"#,
+            r#"{"type":"data_usage","reset":true}"#,
+            r#"["file_follow",{"sessionId":"fuzz-1","path":"/var/log/syslog","lines":10}]"#,
+            r#"["file_unfollow",{"followId":"follow-1"}]"#,
+            r#"["directory_watch",{"sessionId":"fuzz-1","path":"/tmp","intervalSecs":5}]"#,
+            r#"["directory_unwatch",{"watchId":"watch-1"}]"#,
+            r#"["container_logs",{"sessionId":"fuzz-1","containerId":"abc123","lines":10}]"#,
+            r#"["pod_logs",{"sessionId":"fuzz-1","pod":"web-0"}]"#,
+            r#"["auth_response",{"promptId":"prompt-1","responses":["hunter2"]}]"#,
+        ].map(str::to_string));
+        seeds
+    }
+
+    // Shapes the parser has to turn away without help from a mutation
+    fn edge_cases() -> Vec<String> {
+        let mut cases: Vec<String> = [
+            "", " ", "null", "0", "\"ssh_connect\"", "[]", "{}", "\u{feff}{}",
+            r#"["ssh_connect"]"#,
+            r#"["ssh_connect",null]"#,
+            r#"["ssh_connect",{"config":null}]"#,
+            r#"[null,{}]"#,
+            r#"[42,{}]"#,
+            r#"["no_such_event",{}]"#,
+            r#"{"type":"no_such_event"}"#,
+            r#"{"type":null}"#,
+            r#"{"type":"hello","type":"ssh_connect"}"#,
+            r#"{"type":"terminal_input"}"#,
+            r#"{"type":"terminal_resize","sessionId":"s","cols":-1,"rows":1e400}"#,
+            r#"["terminal_resize",{"sessionId":"s","cols":70000,"rows":0}]"#,
+            r#"["terminal_input",{"sessionId":"s","input":"\ud800"}]"#,
+            r#"["terminal_signal",{"sessionId":"s","signal":"KILL"}]"#,
+            r#"["hello",{"protocolVersion":4294967296}]"#,
+            r#"["ssh_disconnect",{"sessionId":7}]"#,
+            r#"["auth_response",{"promptId":"p","responses":[null]}]"#,
+        ].map(str::to_string).to_vec();
+
+        // Deep enough to overflow a naive recursive parser
+        cases.push(format!("{}{}", "[".repeat(10_000), "]".repeat(10_000)));
+        cases.push(format!(r#"["terminal_input",{{"sessionId":"{}","input":"x"}}]"#, "s".repeat(64 * 1024)));
+        cases.push(format!(r#"["terminal_input",{{"sessionId":"s","input":"{}"}}]"#, "\\u0000".repeat(16 * 1024)));
+        cases
+    }
+
+    // xorshift64, enough to spread mutations around without a dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    // Every prefix of the seed, then random replacements, deletions and repeats. Text
+    // frames are UTF-8, so this works on chars.
+    fn mutations(seed: &str, rng: &mut Rng) -> Vec<String> {
+        const ALPHABET: &[char] = &['{', '}', '[', ']', '"', ',', ':', '\\', '0', '-', 'e', ' ', '\u{0}', 'é'];
+        let chars: Vec<char> = seed.chars().collect();
+        let mut mutated: Vec<String> = (0..chars.len()).map(|end| chars[..end].iter().collect()).collect();
+
+        for _ in 0..MUTATIONS_PER_SEED {
+            let mut chars = chars.clone();
+            for _ in 0..=rng.next(3) {
+                let at = rng.next(chars.len());
+                match rng.next(3) {
+                    0 => chars[at] = ALPHABET[rng.next(ALPHABET.len())],
+                    1 => {
+                        chars.remove(at);
+                    }
+                    _ => {
+                        let end = (at + 1 + rng.next(8)).min(chars.len());
+                        let repeated = chars[at..end].to_vec();
+                        chars.splice(at..at, repeated);
+                    }
+                }
+                if chars.is_empty() {
+                    break;
+                }
+            }
+            mutated.push(chars.into_iter().collect());
+        }
+        mutated
+    }
+
+    fn payloads() -> Vec<String> {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let mut payloads = edge_cases();
+        for seed in seeds() {
+            payloads.extend(mutations(&seed, &mut rng));
+            payloads.push(seed);
+        }
+        payloads
+    }
+
+    fn client(id: &str) -> (WebSocketClient, mpsc::UnboundedReceiver<Message>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (control, _) = mpsc::unbounded_channel();
+        let client = WebSocketClient {
+            id: id.to_string(),
+            sessions: Vec::new(),
+            follows: Vec::new(),
+            watches: Vec::new(),
+            resume_tokens: HashMap::new(),
+            protocol_version: PROTOCOL_VERSION,
+            features: Vec::new(),
+            sender,
+            control,
+            output_mode: watch::channel(OutputMode::default()).0,
+            usage: Arc::default(),
+            connected_at: chrono::Utc::now(),
+            last_ping: None,
+            message_count: 0,
+            error_count: 0,
+            throttle_count: 0,
+        };
+        (client, receiver)
+    }
+
+    #[test]
+    fn test_parse_malformed_events() {
+        for payload in payloads() {
+            // Anything that isn't an event is a protocol or serialization error, never a panic
+            match parse_event(&payload) {
+                Ok(_) | Err(AppError::WebSocketError(_)) | Err(AppError::SerializationError(_)) => {}
+                Err(e) => panic!("unexpected error {:?} for {:?}", e, payload),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_malformed_messages() {
+        let ssh_manager: SharedSSHManager = Arc::new(RwLock::new(SSHManager::new()));
+        let (mut client, _outgoing) = client("fuzz-client");
+
+        for payload in payloads() {
+            // Bad input is turned away as such; anything else means it got further than it should
+            match handle_websocket_message(&payload, &ssh_manager, &mut client).await {
+                Ok(_)
+                | Err(AppError::WebSocketError(_))
+                | Err(AppError::SerializationError(_))
+                | Err(AppError::ValidationError(_))
+                | Err(AppError::NotFound(_))
+                | Err(AppError::SessionNotFound(_)) => {}
+                // A takeover without a token
+                Err(AppError::PermissionDenied(_)) if payload.contains("ssh_takeover") => {}
+                // How a malformed ssh_connect config is turned away, the same as over HTTP
+                Err(AppError::InvalidConfiguration(_)) if payload.contains("ssh_connect") => {}
+                Err(e) => panic!("unexpected error {:?} for {:?}", e, payload),
+            }
+        }
+
+        // Nothing was set up, so nothing is left behind
+        assert_eq!(ssh_manager.read().await.get_active_session_count(), 0);
+        assert!(client.sessions.is_empty());
+        assert!(client.follows.is_empty() && client.watches.is_empty());
+        assert!(!attachments().iter().any(|attachment| attachment.client_id == "fuzz-client"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_connect_leaves_no_session() {
+        // A server that hangs up before the SSH handshake
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || drop(listener.accept()));
+
+        let ssh_manager: SharedSSHManager = Arc::new(RwLock::new(SSHManager::new()));
+        let (mut client, _outgoing) = client("fuzz-connect");
+        let message = serde_json::json!(["ssh_connect", {
+            "config": { "id": "fuzz-2", "hostname": "127.0.0.1", "port": port, "username": "fuzz", "password": "fuzz" },
+        }]);
+
+        assert!(handle_websocket_message(&message.to_string(), &ssh_manager, &mut client).await.is_err());
+        assert_eq!(ssh_manager.read().await.get_active_session_count(), 0);
+        assert!(client.sessions.is_empty());
+    }
+}
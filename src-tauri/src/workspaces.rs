@@ -0,0 +1,221 @@
+use crate::types::{AppError, AppResult, SSHConnectionConfig};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub type SharedWorkspaceStore = Arc<WorkspaceStore>;
+
+// One session as it was when the workspace was captured. Secrets are never stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceSession {
+    pub config: SSHConnectionConfig,
+    #[serde(rename = "shellOpen")]
+    pub shell_open: bool,
+    #[serde(rename = "workingDirectory")]
+    pub working_directory: Option<String>,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+    // Opaque to the backend: pane position, tab order, ...
+    pub layout: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    pub sessions: Vec<WorkspaceSession>,
+    pub layout: Option<serde_json::Value>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+// Per-session state only the frontend knows about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceSessionHint {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "workingDirectory")]
+    pub working_directory: Option<String>,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+    pub layout: Option<serde_json::Value>,
+}
+
+pub struct WorkspaceStore {
+    workspaces: DashMap<String, Workspace>,
+    storage_path: Option<PathBuf>,
+}
+
+impl WorkspaceStore {
+    pub fn new() -> Self {
+        Self {
+            workspaces: DashMap::new(),
+            storage_path: None,
+        }
+    }
+
+    pub async fn load(storage_path: PathBuf) -> AppResult<Self> {
+        let mut store = Self::new();
+
+        if tokio::fs::try_exists(&storage_path).await? {
+            let content = tokio::fs::read_to_string(&storage_path).await?;
+            let workspaces: Vec<Workspace> = serde_json::from_str(&content)?;
+            for workspace in workspaces {
+                store.workspaces.insert(workspace.id.clone(), workspace);
+            }
+        }
+
+        store.storage_path = Some(storage_path);
+        Ok(store)
+    }
+
+    // Save under the given name, replacing a workspace that already has it
+    pub async fn save_workspace(
+        &self,
+        name: &str,
+        mut sessions: Vec<WorkspaceSession>,
+        layout: Option<serde_json::Value>,
+    ) -> AppResult<Workspace> {
+        if name.trim().is_empty() {
+            return Err(AppError::ValidationError("Workspace name cannot be empty".to_string()));
+        }
+
+        for session in &mut sessions {
+            session.config.password = None;
+            session.config.private_key = None;
+            session.config.passphrase = None;
+        }
+
+        let now = Utc::now();
+        let existing = self.find_by_name(name);
+        let workspace = Workspace {
+            id: existing.as_ref().map_or_else(|| Uuid::new_v4().to_string(), |w| w.id.clone()),
+            name: name.trim().to_string(),
+            sessions,
+            layout,
+            created_at: existing.as_ref().map_or(now, |w| w.created_at),
+            updated_at: now,
+        };
+
+        self.workspaces.insert(workspace.id.clone(), workspace.clone());
+        self.save().await?;
+        Ok(workspace)
+    }
+
+    pub async fn delete(&self, workspace_id: &str) -> AppResult<bool> {
+        if self.workspaces.remove(workspace_id).is_none() {
+            return Ok(false);
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    pub fn get(&self, workspace_id: &str) -> Option<Workspace> {
+        self.workspaces.get(workspace_id).map(|entry| entry.value().clone())
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<Workspace> {
+        let name = name.trim();
+        self.workspaces.iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+            .map(|entry| entry.value().clone())
+    }
+
+    pub fn list(&self) -> Vec<Workspace> {
+        let mut workspaces: Vec<Workspace> = self.workspaces.iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        workspaces.sort_by_key(|workspace| workspace.name.to_lowercase());
+        workspaces
+    }
+
+    async fn save(&self) -> AppResult<()> {
+        let Some(path) = &self.storage_path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let content = serde_json::to_string_pretty(&self.list())?;
+        tokio::fs::write(path, content).await?;
+        Ok(())
+    }
+}
+
+impl Default for WorkspaceStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Quote a directory for a POSIX shell `cd`
+pub fn cd_command(directory: &str) -> String {
+    format!("cd '{}'\n", directory.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FileTransferProtocol, SessionDefaults};
+
+    fn session(hostname: &str) -> WorkspaceSession {
+        WorkspaceSession {
+            config: SSHConnectionConfig {
+                id: "config".to_string(),
+                hostname: hostname.to_string(),
+                port: 22,
+                username: "deploy".to_string(),
+                password: Some("secret".to_string()),
+                private_key: None,
+                private_key_path: None,
+                passphrase: None,
+                keep_alive: None,
+                ready_timeout: None,
+                websocket_url: None,
+                collect_host_info: false,
+                charset: None,
+                terminal: None,
+                jump_hosts: Vec::new(),
+                keyboard_interactive: false,
+                file_transfer: FileTransferProtocol::Auto,
+                defaults: SessionDefaults::default(),
+                credential: None,
+                approval: None,
+            },
+            shell_open: true,
+            working_directory: Some("/var/log".to_string()),
+            cols: Some(120),
+            rows: Some(40),
+            layout: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_strips_secrets_and_replaces_by_name() {
+        let store = WorkspaceStore::new();
+
+        let first = store.save_workspace("ops", vec![session("web-1")], None).await.unwrap();
+        assert!(first.sessions[0].config.password.is_none());
+
+        let second = store.save_workspace("OPS", vec![session("web-1"), session("web-2")], None).await.unwrap();
+        assert_eq!(second.id, first.id);
+        assert_eq!(second.created_at, first.created_at);
+        assert_eq!(store.list().len(), 1);
+        assert_eq!(store.get(&first.id).unwrap().sessions.len(), 2);
+
+        assert!(store.save_workspace(" ", Vec::new(), None).await.is_err());
+        assert!(store.delete(&first.id).await.unwrap());
+        assert!(store.list().is_empty());
+    }
+
+    #[test]
+    fn test_cd_command_quotes_directory() {
+        assert_eq!(cd_command("/srv/it's here"), "cd '/srv/it'\\''s here'\n");
+    }
+}
@@ -35,6 +35,7 @@ async fn test_ssh_session_lifecycle() {
         username: "testuser".to_string(),
         password: Some("testpass".to_string()),
         private_key: None,
+        private_key_path: None,
         passphrase: None,
     };
     
@@ -156,6 +157,7 @@ async fn test_autocomplete_functionality() {
         username: "testuser".to_string(),
         password: Some("testpass".to_string()),
         private_key: None,
+        private_key_path: None,
         passphrase: None,
     };
     
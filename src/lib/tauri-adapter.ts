@@ -65,6 +65,7 @@ interface AutocompleteRequest {
 interface TerminalOutputEvent {
   sessionId: string;
   data: string;
+  bytes: number;
 }
 
 export class TauriSSHAdapter implements WebSocketAdapter {
@@ -197,11 +198,15 @@ export class TauriSSHAdapter implements WebSocketAdapter {
   private async setupEventListeners() {
     // Listen for terminal output events
     const terminalOutputUnlisten = await listen<TerminalOutputEvent>('terminal-output', (event) => {
-      const { sessionId, data } = event.payload;
+      const { sessionId, data, bytes } = event.payload;
       const listener = this.outputListeners.get(sessionId);
       if (listener) {
         listener(data);
       }
+      // Return flow-control credit so the backend keeps streaming output
+      invoke('terminal_output_ack', { sessionId, bytes }).catch((error) => {
+        console.error('Failed to acknowledge terminal output:', error);
+      });
     });
     this.eventUnlisteners.push(terminalOutputUnlisten);
 
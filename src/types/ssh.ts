@@ -5,6 +5,7 @@ export interface SSHConnectionConfig {
   username: string;
   password?: string;
   privateKey?: string;
+  privateKeyPath?: string;
   passphrase?: string;
   name?: string;
 }